@@ -1,30 +1,70 @@
+use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Mutex;
 
-use golden_thread_core::{diagnostics, open_archive, seed, CoreError};
+use golden_thread_core::{crypto, diagnostics, open_archive, seed, CoreError};
+use golden_thread_core::export;
 use golden_thread_core::importer;
-use golden_thread_core::models::{ArchiveStats, MediaRow, MessageRow, MessageTags, ScrapbookMessage, SearchHit, Tag, ThreadMediaRow, ThreadSummary};
+use golden_thread_core::maintenance;
+use golden_thread_core::models::{ArchiveBundleSummary, ArchiveStats, AttachmentAuditReport, BackupInspection, AttachmentDetail, AttachmentTags, AttachmentVerifyReport, BenchReport, BlurhashBackfillReport, CryptoDiagnostics, DimensionBackfillReport, ExportFormat, GcReport, GlobalSearchResult, ImportIssue, ImportProgress, ImportRecord, ImportStage, LargeAttachment, MediaExportSummary, MediaMonthBucket, MediaRow, Mention, MessageDetail, MessageNote, MessageRow, MessageTags, ResniffReport, ScrapbookContextMessage, ScrapbookOrder, ScrapbookPage, SearchCursor, SearchHit, SearchOptions, SearchPage, StorageUsage, Tag, TagImportSummary, TagStats, ThreadMediaFilters, ThreadMediaRow, ThreadMediaSummary, ThreadSearchGroup, ThreadSummary};
 use golden_thread_core::query::{
+    add_message_tag,
+    add_tag_to_messages,
     archive_stats,
+    attachment_mime,
+    attachment_total_bytes,
     create_tag,
-    delete_tag,
+    delete_message_note,
+    delete_tag_checked,
+    export_tags_json,
+    get_attachment,
+    get_attachment_tags_bulk,
     get_message,
+    get_message_detail,
+    get_message_note,
+    get_message_notes_bulk,
     get_message_tags,
     get_message_tags_bulk,
+    get_or_create_tag,
+    global_search,
+    import_tags_json,
+    largest_attachments,
     list_attachments_for_message,
+    list_document_attachments,
+    list_import_issues,
+    list_imports,
     list_media,
+    list_media_by_sender,
     list_messages,
     list_messages_around,
     list_messages_after,
+    list_mentions_for_messages,
     list_reactions_for_messages,
     list_scrapbook_messages,
+    list_tagged_media,
     list_tags,
     list_thread_media,
     list_threads,
+    merge_tags,
+    remove_message_tag,
+    remove_tag_from_messages,
+    scrapbook_context,
+    search_message_notes,
     search_messages,
+    search_messages_grouped,
+    search_messages_page,
+    set_attachment_tags,
+    set_message_note,
     set_message_tags,
+    suggest_tag_color,
+    tag_stats,
+    thread_media_month_buckets,
+    thread_media_summary,
+    thread_storage_usage,
     update_tag,
+    TAG_DELETE_CONFIRM_USES,
 };
 use tauri::{Emitter, Manager};
 
@@ -94,12 +134,18 @@ struct DbState {
 
 struct MediaState {
     inner: Mutex<Option<std::sync::Arc<media_ops::MediaState>>>,
+    /// The `thumbs/` cap chosen in the UI, kept here so it outlives lock and reset.
+    thumbs_cap_bytes: AtomicU64,
+    /// The preview cache limits chosen in the UI, kept for the same reason.
+    cache_config: Mutex<media_ops::MediaCacheConfig>,
 }
 
 impl Default for MediaState {
     fn default() -> Self {
         Self {
             inner: Mutex::new(None),
+            thumbs_cap_bytes: AtomicU64::new(media_ops::DEFAULT_THUMBS_CAP_BYTES),
+            cache_config: Mutex::new(media_ops::MediaCacheConfig::default()),
         }
     }
 }
@@ -125,7 +171,12 @@ fn get_or_init_media(
             archive.join("attachments"),
             archive.join("thumbs"),
             archive.join("previews").join("session").join("media"),
+            archive.join("logs"),
         );
+        media_state.thumbs_cap_bytes.store(state.thumbs_cap_bytes.load(Ordering::Relaxed), Ordering::Relaxed);
+        if let Ok(config) = state.cache_config.lock() {
+            media_ops::set_cache_config(&media_state, *config);
+        }
         *guard = Some(std::sync::Arc::new(media_state));
     }
     guard.as_ref().ok_or_else(|| "media unavailable".to_string()).cloned()
@@ -260,16 +311,26 @@ fn list_message_reactions_cmd(
     result
 }
 
+#[tauri::command]
+fn list_message_mentions_cmd(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    message_ids: Vec<String>,
+) -> Result<Vec<Mention>, String> {
+    with_db(&app_handle, &state, |db| list_mentions_for_messages(&db.conn, &message_ids)).map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 fn search_messages_cmd(
     app_handle: tauri::AppHandle,
     state: tauri::State<DbState>,
     query: String,
-    thread_id: Option<String>,
+    options: Option<SearchOptions>,
     limit: i64,
     offset: i64,
 ) -> Result<Vec<SearchHit>, String> {
-    let result = with_db(&app_handle, &state, |db| search_messages(&db.conn, &query, thread_id.as_deref(), limit, offset))
+    let options = options.unwrap_or_default();
+    let result = with_db(&app_handle, &state, |db| search_messages(&db.conn, &query, &options, limit, offset))
         .map_err(|e| e.to_string());
     if let Err(ref err) = result {
         if let Ok(log_dir) = diagnostics_dir(&app_handle) {
@@ -279,6 +340,63 @@ fn search_messages_cmd(
     result
 }
 
+#[tauri::command]
+fn search_messages_page_cmd(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    query: String,
+    options: Option<SearchOptions>,
+    cursor: Option<SearchCursor>,
+    limit: i64,
+) -> Result<SearchPage, String> {
+    let options = options.unwrap_or_default();
+    let result = with_db(&app_handle, &state, |db| {
+        search_messages_page(&db.conn, &query, &options, cursor.as_ref(), limit)
+    })
+    .map_err(|e| e.to_string());
+    if let Err(ref err) = result {
+        if let Ok(log_dir) = diagnostics_dir(&app_handle) {
+            let _ = diagnostics::log_event(&log_dir, "query_error", &format!("search_messages_page failed: {}", err));
+        }
+    }
+    result
+}
+
+#[tauri::command]
+fn search_messages_grouped_cmd(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    query: String,
+    limit_per_thread: Option<i64>,
+) -> Result<Vec<ThreadSearchGroup>, String> {
+    let limit_per_thread = limit_per_thread.unwrap_or(3);
+    let result = with_db(&app_handle, &state, |db| search_messages_grouped(&db.conn, &query, limit_per_thread))
+        .map_err(|e| e.to_string());
+    if let Err(ref err) = result {
+        if let Ok(log_dir) = diagnostics_dir(&app_handle) {
+            let _ = diagnostics::log_event(&log_dir, "query_error", &format!("search_messages_grouped failed: {}", err));
+        }
+    }
+    result
+}
+
+#[tauri::command]
+fn global_search_cmd(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    query: String,
+    limit: Option<i64>,
+) -> Result<GlobalSearchResult, String> {
+    let limit = limit.unwrap_or(20);
+    let result = with_db(&app_handle, &state, |db| global_search(&db.conn, &query, limit)).map_err(|e| e.to_string());
+    if let Err(ref err) = result {
+        if let Ok(log_dir) = diagnostics_dir(&app_handle) {
+            let _ = diagnostics::log_event(&log_dir, "query_error", &format!("global_search failed: {}", err));
+        }
+    }
+    result
+}
+
 #[tauri::command]
 fn list_media_cmd(
     app_handle: tauri::AppHandle,
@@ -302,24 +420,13 @@ fn list_thread_media_cmd(
     app_handle: tauri::AppHandle,
     state: tauri::State<DbState>,
     thread_id: String,
-    from_ts: Option<i64>,
-    to_ts: Option<i64>,
-    size_bucket: Option<i64>,
+    filters: ThreadMediaFilters,
     sort: String,
     limit: i64,
     offset: i64,
 ) -> Result<Vec<ThreadMediaRow>, String> {
     let result = with_db(&app_handle, &state, |db| {
-        list_thread_media(
-            &db.conn,
-            &thread_id,
-            from_ts,
-            to_ts,
-            size_bucket,
-            &sort,
-            limit,
-            offset,
-        )
+        list_thread_media(&db.conn, &thread_id, &filters, &sort, limit, offset)
     })
     .map_err(|e| e.to_string());
     if let Err(ref err) = result {
@@ -330,6 +437,30 @@ fn list_thread_media_cmd(
     result
 }
 
+#[tauri::command]
+fn thread_media_month_buckets_cmd(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    thread_id: String,
+    kind: Option<String>,
+) -> Result<Vec<MediaMonthBucket>, String> {
+    with_db(&app_handle, &state, |db| thread_media_month_buckets(&db.conn, &thread_id, kind.as_deref()))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn thread_media_summary_cmd(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    thread_id: String,
+    from_ts: Option<i64>,
+    to_ts: Option<i64>,
+    size_bucket: Option<i64>,
+) -> Result<ThreadMediaSummary, String> {
+    with_db(&app_handle, &state, |db| thread_media_summary(&db.conn, &thread_id, from_ts, to_ts, size_bucket))
+        .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 fn list_message_attachments_cmd(
     app_handle: tauri::AppHandle,
@@ -352,13 +483,14 @@ async fn attachment_data_url_cmd(
     state: tauri::State<'_, MediaState>,
     sha256: String,
     mime: String,
-) -> Result<String, String> {
+) -> Result<media_ops::DataUrlResponse, media_ops::DataUrlError> {
     validate_sha256(&sha256)?;
     if !(mime.starts_with("image/") || mime.starts_with("video/") || mime.starts_with("audio/")) {
-        return Err("unsupported media type".to_string());
+        return Err("unsupported media type".to_string().into());
     }
+    // Large images come back downscaled, so their limit only bounds the decode.
     let max_bytes: u64 = if mime.starts_with("image/") {
-        12 * 1024 * 1024
+        64 * 1024 * 1024
     } else if mime.starts_with("audio/") {
         25 * 1024 * 1024
     } else {
@@ -380,18 +512,95 @@ async fn attachment_path_cmd(
     state: tauri::State<'_, MediaState>,
     sha256: String,
     mime: Option<String>,
+    request_id: Option<String>,
 ) -> Result<String, String> {
     validate_sha256(&sha256)?;
 
     let media = get_or_init_media(&app_handle, &state)?;
+    let request = request_id.map(|id| media_ops::track_request(&media, id));
 
     tauri::async_runtime::spawn_blocking(move || {
-        media_ops::decrypt_to_preview(&media, &sha256, mime.as_deref())
+        let untracked = AtomicBool::new(false);
+        let cancel = request.as_ref().map_or(&untracked, |request| request.cancel_flag());
+        // Small files finish before a percentage is worth showing; large ones report each
+        // whole percent.
+        let last_percent = AtomicU64::new(0);
+        let emit_progress = |done: u64, total: u64| {
+            if total < crypto::PARALLEL_DECRYPT_THRESHOLD {
+                return;
+            }
+            let percent = done * 100 / total;
+            if percent > last_percent.fetch_max(percent, Ordering::Relaxed) {
+                let progress = media_ops::DecryptProgress {
+                    sha256: sha256.clone(),
+                    done,
+                    total,
+                };
+                let _ = app_handle.emit("media_progress", progress);
+            }
+        };
+        media_ops::decrypt_to_preview(&media, &sha256, mime.as_deref(), cancel, emit_progress)
     })
     .await
     .map_err(|e| e.to_string())?
 }
 
+/// Stops an `attachment_path_cmd` call the UI gave up on, before its next chunk. False if
+/// it already finished.
+#[tauri::command]
+fn cancel_media_request_cmd(state: tauri::State<'_, MediaState>, request_id: String) -> bool {
+    // No media state means nothing is in flight, and cancelling shouldn't load the key.
+    let Ok(guard) = state.inner.lock() else {
+        return false;
+    };
+    guard.as_ref().is_some_and(|media| media_ops::cancel_request(media, &request_id))
+}
+
+/// Serves `gtmedia://localhost/<sha256>` by decrypting just the chunks a `Range` request
+/// covers, so `<video>` and `<audio>` can play and seek without a plaintext temp file.
+fn media_protocol_response(
+    app_handle: &tauri::AppHandle,
+    request: &tauri::http::Request<Vec<u8>>,
+) -> tauri::http::Response<Vec<u8>> {
+    let sha256 = request.uri().path().trim_start_matches('/');
+    let result = validate_sha256(sha256).and_then(|_| {
+        let media = get_or_init_media(app_handle, &app_handle.state::<MediaState>())?;
+        let mime = with_db(app_handle, &app_handle.state::<DbState>(), |db| attachment_mime(&db.conn, sha256))
+            .map_err(|e| e.to_string())?;
+        let range = request
+            .headers()
+            .get(tauri::http::header::RANGE)
+            .and_then(|value| value.to_str().ok());
+        Ok((media_ops::read_range(&media, sha256, range)?, mime))
+    });
+    let builder = tauri::http::Response::builder()
+        .header(tauri::http::header::CACHE_CONTROL, "no-store")
+        .header(tauri::http::header::ACCEPT_RANGES, "bytes");
+    let response = match result {
+        Ok((mut range, mime)) => {
+            let mut builder = builder
+                .status(range.status)
+                .header(tauri::http::header::CONTENT_TYPE, mime.as_deref().unwrap_or("application/octet-stream"));
+            if let Some(content_range) = range.content_range() {
+                builder = builder.header(tauri::http::header::CONTENT_RANGE, content_range);
+            }
+            builder.body(std::mem::take(&mut *range.body))
+        }
+        Err(err) => {
+            if let Ok(log_dir) = diagnostics_dir(app_handle) {
+                let _ = diagnostics::log_event(&log_dir, "media_stream_error", &err);
+            }
+            let status = match err.as_str() {
+                "invalid attachment id" => 400,
+                "attachment missing" => 404,
+                _ => 500,
+            };
+            builder.status(status).body(err.into_bytes())
+        }
+    };
+    response.unwrap_or_else(|_| tauri::http::Response::new(Vec::new()))
+}
+
 #[tauri::command]
 async fn attachment_thumbnail_cmd(
     app_handle: tauri::AppHandle,
@@ -401,26 +610,178 @@ async fn attachment_thumbnail_cmd(
     max_size: u32,
 ) -> Result<String, String> {
     validate_sha256(&sha256)?;
+
+    let media = get_or_init_media(&app_handle, &state)?;
+
+    tauri::async_runtime::spawn_blocking(move || {
+        media_ops::generate_any_thumbnail(&media, &sha256, mime.as_deref(), max_size)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Warms the preview cache for the lightbox's neighbours in the background and pins
+/// `current` against eviction meanwhile. Returns before anything is decrypted.
+#[tauri::command]
+fn prefetch_media_cmd(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, MediaState>,
+    current: Option<String>,
+    items: Vec<media_ops::PrefetchRequest>,
+) -> Result<(), String> {
+    if let Some(sha256) = &current {
+        validate_sha256(sha256)?;
+    }
+    let items: Vec<_> = items.into_iter().filter(|item| validate_sha256(&item.sha256).is_ok()).collect();
+    let media = get_or_init_media(&app_handle, &state)?;
+    media_ops::prefetch(&media, current.as_deref(), items);
+    Ok(())
+}
+
+#[tauri::command]
+async fn attachment_thumbnails_cmd(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, MediaState>,
+    requests: Vec<media_ops::ThumbRequest>,
+) -> Result<HashMap<String, media_ops::ThumbResult>, String> {
+    if requests.len() > media_ops::MAX_THUMB_BATCH {
+        return Err(format!("at most {} thumbnails per batch", media_ops::MAX_THUMB_BATCH));
+    }
+    let (valid, invalid): (Vec<_>, Vec<_>) =
+        requests.into_iter().partition(|request| validate_sha256(&request.sha256).is_ok());
+
+    let media = get_or_init_media(&app_handle, &state)?;
+
+    let mut results = tauri::async_runtime::spawn_blocking(move || media_ops::generate_thumbnails(&media, &valid))
+        .await
+        .map_err(|e| e.to_string())?;
+    for request in invalid {
+        let key = format!("{}:{}", request.sha256, request.max_size);
+        results.insert(key, Err("invalid attachment id".to_string()).into());
+    }
+    Ok(results)
+}
+
+#[tauri::command]
+async fn attachment_waveform_cmd(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, MediaState>,
+    sha256: String,
+    mime: Option<String>,
+    buckets: usize,
+) -> Result<Vec<u8>, String> {
+    validate_sha256(&sha256)?;
     if let Some(m) = mime.as_deref() {
-        if !m.starts_with("image/") {
-            return Err("thumbnail only for images".to_string());
+        if !m.starts_with("audio/") {
+            return Err("waveform only for audio".to_string());
         }
     }
 
     let media = get_or_init_media(&app_handle, &state)?;
 
     tauri::async_runtime::spawn_blocking(move || {
-        media_ops::generate_thumbnail(&media, &sha256, max_size)
+        media_ops::generate_waveform(&media, &sha256, mime.as_deref(), buckets).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+async fn export_attachment_cmd(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, MediaState>,
+    sha256: String,
+    dest_path: String,
+    original_filename: Option<String>,
+    overwrite: Option<bool>,
+) -> Result<String, String> {
+    validate_sha256(&sha256)?;
+
+    let media = get_or_init_media(&app_handle, &state)?;
+
+    tauri::async_runtime::spawn_blocking(move || {
+        export::export_attachment(
+            &media.attachments_dir,
+            &sha256,
+            std::path::Path::new(&dest_path),
+            original_filename.as_deref(),
+            &media.key,
+            overwrite.unwrap_or(false),
+        )
+        .map(|path| path.to_string_lossy().to_string())
+        .map_err(|e| e.to_string())
     })
     .await
     .map_err(|e| e.to_string())?
 }
 
+#[tauri::command]
+fn list_document_attachments_cmd(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    thread_id: Option<String>,
+    filename_needle: Option<String>,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<ThreadMediaRow>, String> {
+    with_db(&app_handle, &state, |db| {
+        list_document_attachments(&db.conn, thread_id.as_deref(), filename_needle.as_deref(), limit, offset)
+    })
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn list_media_by_sender_cmd(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    sender_id: String,
+    kind: Option<String>,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<ThreadMediaRow>, String> {
+    with_db(&app_handle, &state, |db| list_media_by_sender(&db.conn, &sender_id, kind.as_deref(), limit, offset))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn largest_attachments_cmd(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    limit: i64,
+    kind: Option<String>,
+) -> Result<Vec<LargeAttachment>, String> {
+    with_db(&app_handle, &state, |db| largest_attachments(&db.conn, limit, kind.as_deref())).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn attachment_total_bytes_cmd(app_handle: tauri::AppHandle, state: tauri::State<DbState>) -> Result<i64, String> {
+    with_db(&app_handle, &state, |db| attachment_total_bytes(&db.conn)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn thread_storage_usage_cmd(app_handle: tauri::AppHandle, state: tauri::State<DbState>) -> Result<StorageUsage, String> {
+    with_db(&app_handle, &state, |db| thread_storage_usage(&db.conn)).map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 fn archive_stats_cmd(app_handle: tauri::AppHandle, state: tauri::State<DbState>) -> Result<ArchiveStats, String> {
     with_db(&app_handle, &state, |db| archive_stats(&db.conn)).map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+fn list_imports_cmd(app_handle: tauri::AppHandle, state: tauri::State<DbState>) -> Result<Vec<ImportRecord>, String> {
+    with_db(&app_handle, &state, |db| list_imports(&db.conn)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn list_import_issues_cmd(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    import_id: String,
+) -> Result<Vec<ImportIssue>, String> {
+    with_db(&app_handle, &state, |db| list_import_issues(&db.conn, &import_id)).map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 fn seed_demo_cmd(
     app_handle: tauri::AppHandle,
@@ -433,17 +794,46 @@ fn seed_demo_cmd(
 }
 
 #[tauri::command]
-async fn import_backup_cmd(app_handle: tauri::AppHandle, path: String, passphrase: String) -> Result<(), String> {
+fn rebuild_fts_cmd(app_handle: tauri::AppHandle, state: tauri::State<DbState>) -> Result<(), String> {
+    let app = app_handle.clone();
+    let emit_status = |progress: ImportProgress| {
+        let _ = app.emit("fts_status", progress.to_string());
+    };
+    let result = with_db(&app_handle, &state, |db| importer::rebuild_fts(&db.conn, &emit_status))
+        .map_err(|e| e.to_string());
+    if let Err(ref err) = result {
+        if let Ok(log_dir) = diagnostics_dir(&app_handle) {
+            let _ = diagnostics::log_event(&log_dir, "fts_rebuild_error", err);
+        }
+    }
+    result
+}
+
+#[tauri::command]
+async fn import_backup_cmd(
+    app_handle: tauri::AppHandle,
+    path: String,
+    passphrase: String,
+    verify_attachments: Option<bool>,
+) -> Result<(), String> {
     let app = app_handle.clone();
     let log_dir = diagnostics_dir(&app_handle).map_err(|e| e.to_string())?;
     let _ = diagnostics::log_event(&log_dir, "import_start", "import requested");
+    let bench_log_dir = log_dir.clone();
     let handle = tauri::async_runtime::spawn_blocking(move || {
-        let emit_status = |msg: &str| {
-            let _ = app.emit("import_status", msg.to_string());
+        let emit_status = |progress: ImportProgress| {
+            let _ = app.emit("import_status", progress);
         };
-        emit_status("Preparing import...");
-        let plan = importer::plan_import_with_progress(std::path::Path::new(&path), &passphrase, emit_status)
+        // Tune the attachment chunk size once per launch, before the first import.
+        if !crypto::has_tuned_chunk_size() {
+            if let Ok(report) = crypto::benchmark(IMPORT_BENCHMARK_SAMPLE_MB, &crypto::BENCHMARK_CHUNK_SIZES) {
+                log_benchmark(&bench_log_dir, &report);
+            }
+        }
+        emit_status(ImportProgress::new(ImportStage::Hashing));
+        let mut plan = importer::plan_import_with_progress(std::path::Path::new(&path), &passphrase, emit_status)
             .map_err(|e| e.to_string())?;
+        plan.verify_attachments = verify_attachments.unwrap_or(false);
         let archive = archive_path(&app).map_err(|e| e.to_string())?;
         importer::import_backup_with_progress(&plan, &archive, emit_status).map_err(|e| e.to_string())
     });
@@ -464,41 +854,268 @@ async fn import_backup_cmd(app_handle: tauri::AppHandle, path: String, passphras
 }
 
 #[tauri::command]
-fn reset_archive_cmd(
-    app_handle: tauri::AppHandle,
-    db_state: tauri::State<DbState>,
-    media_state: tauri::State<MediaState>,
-) -> Result<(), String> {
-    let base = app_handle
-        .path()
-        .app_data_dir()
-        .map_err(|e| e.to_string())?;
-    let archive_dir = archive_dir(&base).map_err(|e| e.to_string())?;
-    let archive_path = archive_dir.join("archive.sqlite");
-    let archive_wal = archive_dir.join("archive.sqlite-wal");
-    let archive_shm = archive_dir.join("archive.sqlite-shm");
-    let attachments_dir = archive_dir.join("attachments");
-    let thumbs_dir = archive_dir.join("thumbs");
-    let previews_dir = archive_dir.join("previews");
+async fn inspect_backup_cmd(app_handle: tauri::AppHandle, path: String, passphrase: String) -> Result<BackupInspection, String> {
+    let app = app_handle.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let emit_status = |progress: ImportProgress| {
+            let _ = app.emit("import_status", progress);
+        };
+        let plan = importer::plan_import_with_progress(std::path::Path::new(&path), &passphrase, emit_status)
+            .map_err(|e| e.to_string())?;
+        importer::inspect_backup(&plan, emit_status).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
 
-    if archive_path.exists() {
-        fs::remove_file(&archive_path).map_err(|e| e.to_string())?;
-    }
-    if archive_wal.exists() {
-        fs::remove_file(&archive_wal).map_err(|e| e.to_string())?;
-    }
-    if archive_shm.exists() {
-        fs::remove_file(&archive_shm).map_err(|e| e.to_string())?;
-    }
-    if attachments_dir.exists() {
-        fs::remove_dir_all(&attachments_dir).map_err(|e| e.to_string())?;
-    }
-    if thumbs_dir.exists() {
-        fs::remove_dir_all(&thumbs_dir).map_err(|e| e.to_string())?;
-    }
-    if previews_dir.exists() {
-        fs::remove_dir_all(&previews_dir).map_err(|e| e.to_string())?;
-    }
+#[tauri::command]
+async fn export_scrapbook_cmd(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, MediaState>,
+    tag_id: String,
+    dest_dir: String,
+    format: ExportFormat,
+) -> Result<String, String> {
+    let media = get_or_init_media(&app_handle, &state)?;
+    let app = app_handle.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let emit_status = |msg: &str| {
+            let _ = app.emit("export_status", msg.to_string());
+        };
+        let db = open_archive(archive_path(&app).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+        export::export_scrapbook(
+            &db.conn,
+            &tag_id,
+            &media.attachments_dir,
+            &media.key,
+            std::path::Path::new(&dest_dir),
+            format,
+            emit_status,
+        )
+        .map(|path| path.to_string_lossy().to_string())
+        .map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+async fn export_thread_media_cmd(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, MediaState>,
+    thread_id: String,
+    dest_dir: String,
+    filters: ThreadMediaFilters,
+) -> Result<MediaExportSummary, String> {
+    let media = get_or_init_media(&app_handle, &state)?;
+    let app = app_handle.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let emit_status = |msg: &str| {
+            let _ = app.emit("export_status", msg.to_string());
+        };
+        let db = open_archive(archive_path(&app).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+        export::export_thread_media(
+            &db.conn,
+            &media.attachments_dir,
+            &media.key,
+            &thread_id,
+            std::path::Path::new(&dest_dir),
+            &filters,
+            emit_status,
+        )
+        .map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+async fn audit_attachments_cmd(app_handle: tauri::AppHandle) -> Result<AttachmentAuditReport, String> {
+    let app = app_handle.clone();
+    let log_dir = diagnostics_dir(&app_handle).map_err(|e| e.to_string())?;
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        let emit_status = |msg: &str| {
+            let _ = app.emit("audit_status", msg.to_string());
+        };
+        let archive = archive_path(&app).map_err(|e| e.to_string())?;
+        let attachments_dir = archive.with_file_name("attachments");
+        let db = open_archive(&archive).map_err(|e| e.to_string())?;
+        maintenance::audit_attachments(&db.conn, &attachments_dir, emit_status).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+    match &result {
+        Ok(report) => {
+            let summary = format!("checked {} attachments, {} missing", report.checked, report.missing.len());
+            let _ = diagnostics::log_event(&log_dir, "attachment_audit", &summary);
+        }
+        Err(err) => {
+            let _ = diagnostics::log_event(&log_dir, "attachment_audit_error", err);
+        }
+    }
+    result
+}
+
+#[tauri::command]
+fn gc_orphaned_attachments_cmd(app_handle: tauri::AppHandle, dry_run: Option<bool>) -> Result<GcReport, String> {
+    let dry_run = dry_run.unwrap_or(true);
+    let archive = archive_path(&app_handle).map_err(|e| e.to_string())?;
+    let db = open_archive(&archive).map_err(|e| e.to_string())?;
+    let report = maintenance::gc_orphaned_attachments(&db.conn, &archive.with_file_name("attachments"), dry_run)
+        .map_err(|e| e.to_string())?;
+    if !dry_run {
+        if let Ok(log_dir) = diagnostics_dir(&app_handle) {
+            let summary = format!("deleted {} orphaned attachments ({} bytes)", report.deleted, report.bytes);
+            let _ = diagnostics::log_event(&log_dir, "attachment_gc", &summary);
+        }
+    }
+    Ok(report)
+}
+
+#[tauri::command]
+async fn resniff_attachments_cmd(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, MediaState>,
+) -> Result<ResniffReport, String> {
+    let media = get_or_init_media(&app_handle, &state)?;
+    let app = app_handle.clone();
+    let report = tauri::async_runtime::spawn_blocking(move || {
+        let db = open_archive(archive_path(&app).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+        maintenance::resniff_attachments(&db.conn, &media.attachments_dir, &media.key).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    if let Ok(log_dir) = diagnostics_dir(&app_handle) {
+        let summary = format!(
+            "resniffed {} attachments: {} rows updated, {} missing, {} failed",
+            report.checked, report.updated, report.missing, report.failed
+        );
+        let _ = diagnostics::log_event(&log_dir, "attachment_resniff", &summary);
+    }
+    Ok(report)
+}
+
+#[tauri::command]
+async fn verify_attachments_cmd(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, MediaState>,
+) -> Result<AttachmentVerifyReport, String> {
+    let media = get_or_init_media(&app_handle, &state)?;
+    let app = app_handle.clone();
+    let report = tauri::async_runtime::spawn_blocking(move || {
+        let emit_status = |msg: &str| {
+            let _ = app.emit("audit_status", msg.to_string());
+        };
+        let db = open_archive(archive_path(&app).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+        maintenance::verify_attachments(&db.conn, &media.attachments_dir, &media.key, emit_status)
+            .map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    if let Ok(log_dir) = diagnostics_dir(&app_handle) {
+        let summary = format!(
+            "verified {} attachments: {} ok, {} missing, {} corrupt, {} in the legacy format",
+            report.checked,
+            report.verified,
+            report.missing,
+            report.corrupt.len(),
+            report.legacy_format
+        );
+        let _ = diagnostics::log_event(&log_dir, "attachment_verify", &summary);
+        for corrupt in &report.corrupt {
+            let detail = format!("{} ({})", corrupt.sha256, corrupt.reason);
+            let _ = diagnostics::log_event(&log_dir, "attachment_corrupt", &detail);
+        }
+    }
+    Ok(report)
+}
+
+#[tauri::command]
+async fn backfill_image_dimensions_cmd(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, MediaState>,
+) -> Result<DimensionBackfillReport, String> {
+    let media = get_or_init_media(&app_handle, &state)?;
+    let app = app_handle.clone();
+    let report = tauri::async_runtime::spawn_blocking(move || {
+        let db = open_archive(archive_path(&app).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+        maintenance::backfill_image_dimensions(&db.conn, &media.attachments_dir, &media.key).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    if let Ok(log_dir) = diagnostics_dir(&app_handle) {
+        let summary = format!(
+            "checked {} images: {} rows given dimensions, {} missing, {} failed",
+            report.checked, report.updated, report.missing, report.failed
+        );
+        let _ = diagnostics::log_event(&log_dir, "image_dimension_backfill", &summary);
+    }
+    Ok(report)
+}
+
+#[tauri::command]
+async fn backfill_blurhash_cmd(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, MediaState>,
+) -> Result<BlurhashBackfillReport, String> {
+    let media = get_or_init_media(&app_handle, &state)?;
+    let app = app_handle.clone();
+    let report = tauri::async_runtime::spawn_blocking(move || {
+        let emit_status = |msg: &str| {
+            let _ = app.emit("audit_status", msg.to_string());
+        };
+        let db = open_archive(archive_path(&app).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+        maintenance::backfill_blurhash(&db.conn, &media.attachments_dir, &media.key, emit_status)
+            .map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    if let Ok(log_dir) = diagnostics_dir(&app_handle) {
+        let summary = format!(
+            "checked {} images: {} placeholders computed, {} missing, {} failed",
+            report.checked, report.updated, report.missing, report.failed
+        );
+        let _ = diagnostics::log_event(&log_dir, "blurhash_backfill", &summary);
+    }
+    Ok(report)
+}
+
+#[tauri::command]
+fn reset_archive_cmd(
+    app_handle: tauri::AppHandle,
+    db_state: tauri::State<DbState>,
+    media_state: tauri::State<MediaState>,
+) -> Result<(), String> {
+    let base = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| e.to_string())?;
+    let archive_dir = archive_dir(&base).map_err(|e| e.to_string())?;
+    let archive_path = archive_dir.join("archive.sqlite");
+    let archive_wal = archive_dir.join("archive.sqlite-wal");
+    let archive_shm = archive_dir.join("archive.sqlite-shm");
+    let attachments_dir = archive_dir.join("attachments");
+    let thumbs_dir = archive_dir.join("thumbs");
+    let previews_dir = archive_dir.join("previews");
+
+    if archive_path.exists() {
+        fs::remove_file(&archive_path).map_err(|e| e.to_string())?;
+    }
+    if archive_wal.exists() {
+        fs::remove_file(&archive_wal).map_err(|e| e.to_string())?;
+    }
+    if archive_shm.exists() {
+        fs::remove_file(&archive_shm).map_err(|e| e.to_string())?;
+    }
+    if attachments_dir.exists() {
+        fs::remove_dir_all(&attachments_dir).map_err(|e| e.to_string())?;
+    }
+    if thumbs_dir.exists() {
+        fs::remove_dir_all(&thumbs_dir).map_err(|e| e.to_string())?;
+    }
+    if previews_dir.exists() {
+        fs::remove_dir_all(&previews_dir).map_err(|e| e.to_string())?;
+    }
 
     // Clear media cache if initialized
     if let Ok(guard) = media_state.inner.lock() {
@@ -520,6 +1137,220 @@ fn reset_archive_cmd(
     Ok(())
 }
 
+/// Replaces the master key, re-encrypting the archive under a new one. Emits
+/// `rotate_key_status` messages; rerunning it after an interruption finishes the rotation.
+#[tauri::command]
+async fn rotate_key_cmd(
+    app_handle: tauri::AppHandle,
+    db_state: tauri::State<'_, DbState>,
+    media_state: tauri::State<'_, MediaState>,
+) -> Result<(), String> {
+    let archive = archive_path(&app_handle).map_err(|e| e.to_string())?;
+    let log_dir = diagnostics_dir(&app_handle).map_err(|e| e.to_string())?;
+    // Nothing may keep using the database or the old key while they are replaced.
+    if let Ok(mut guard) = db_state.db.lock() {
+        *guard = None;
+    }
+    if let Ok(mut guard) = media_state.inner.lock() {
+        if let Some(media) = guard.as_ref() {
+            media_ops::clear_cache(media);
+        }
+        *guard = None;
+    }
+    let app = app_handle.clone();
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        let emit_status = |msg: &str| {
+            let _ = app.emit("rotate_key_status", msg.to_string());
+        };
+        let old_key = crypto::load_or_create_master_key().map_err(|e| e.to_string())?;
+        let db = golden_thread_core::db::open_archive_for_key_rotation(&archive, &old_key).map_err(|e| e.to_string())?;
+        crypto::rotate_master_key(
+            &db.conn,
+            &archive.with_file_name("attachments"),
+            &archive.with_file_name("thumbs"),
+            &old_key,
+            emit_status,
+        )
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+    match &result {
+        Ok(()) => {
+            let _ = diagnostics::log_event(&log_dir, "key_rotation", "master key rotated");
+        }
+        Err(err) => {
+            let _ = diagnostics::log_event(&log_dir, "key_rotation_error", err);
+        }
+    }
+    result
+}
+
+/// Loads the master key, from the passphrase-protected key file when the keychain is
+/// unavailable. A no-op once the key is loaded.
+#[tauri::command]
+async fn unlock_key_file_cmd(app_handle: tauri::AppHandle, passphrase: String) -> Result<(), String> {
+    let archive = archive_path(&app_handle).map_err(|e| e.to_string())?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let key_file = archive.with_file_name(crypto::KEY_FILE_NAME);
+        crypto::load_or_create_master_key_with_fallback(&key_file, || Ok(passphrase))
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+async fn change_key_file_passphrase_cmd(
+    app_handle: tauri::AppHandle,
+    old_passphrase: String,
+    new_passphrase: String,
+) -> Result<(), String> {
+    let archive = archive_path(&app_handle).map_err(|e| e.to_string())?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let key_file = archive.with_file_name(crypto::KEY_FILE_NAME);
+        crypto::change_key_file_passphrase(&key_file, &old_passphrase, &new_passphrase).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Drops the master key from memory: closes the database, tears down media state, removes
+/// decrypted previews and wipes the cached key. Everything that needs the key fails with
+/// "archive is locked" until `unlock_archive_cmd`.
+#[tauri::command]
+fn lock_archive_cmd(
+    app_handle: tauri::AppHandle,
+    db_state: tauri::State<'_, DbState>,
+    media_state: tauri::State<'_, MediaState>,
+) -> Result<(), String> {
+    if let Ok(mut guard) = db_state.db.lock() {
+        *guard = None;
+    }
+    if let Ok(mut guard) = media_state.inner.lock() {
+        if let Some(media) = guard.as_ref() {
+            media_ops::clear_cache(media);
+        }
+        *guard = None;
+    }
+    clear_preview_cache(&app_handle);
+    crypto::lock_master_key();
+    if let Ok(log_dir) = diagnostics_dir(&app_handle) {
+        let _ = diagnostics::log_event(&log_dir, "archive_locked", "master key dropped from memory");
+    }
+    Ok(())
+}
+
+/// Reloads the master key after `lock_archive_cmd`, from the keychain or, when the keychain
+/// is unavailable, from the key file with `passphrase`. Stays locked if loading fails.
+#[tauri::command]
+async fn unlock_archive_cmd(app_handle: tauri::AppHandle, passphrase: Option<String>) -> Result<(), String> {
+    let archive = archive_path(&app_handle).map_err(|e| e.to_string())?;
+    let log_dir = diagnostics_dir(&app_handle).map_err(|e| e.to_string())?;
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        crypto::unlock_master_key();
+        let key_file = archive.with_file_name(crypto::KEY_FILE_NAME);
+        let loaded = crypto::load_or_create_master_key_with_fallback(&key_file, || {
+            passphrase.ok_or_else(|| CoreError::InvalidPassphrase("key file passphrase required".to_string()))
+        });
+        if loaded.is_err() {
+            crypto::lock_master_key();
+        }
+        loaded.map(|_| ()).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+    match &result {
+        Ok(()) => {
+            let _ = diagnostics::log_event(&log_dir, "archive_unlocked", "master key reloaded");
+        }
+        Err(err) => {
+            let _ = diagnostics::log_event(&log_dir, "archive_unlock_error", err);
+        }
+    }
+    result
+}
+
+/// Writes the whole archive to a `.gtarchive` bundle at `dest_path` sealed under
+/// `passphrase`, emitting `archive_bundle_status` messages.
+#[tauri::command]
+async fn export_archive_bundle_cmd(
+    app_handle: tauri::AppHandle,
+    dest_path: String,
+    passphrase: String,
+) -> Result<ArchiveBundleSummary, String> {
+    let archive = archive_path(&app_handle).map_err(|e| e.to_string())?;
+    let log_dir = diagnostics_dir(&app_handle).map_err(|e| e.to_string())?;
+    let app = app_handle.clone();
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        let emit_status = |msg: &str| {
+            let _ = app.emit("archive_bundle_status", msg.to_string());
+        };
+        let archive_dir = archive.parent().unwrap_or_else(|| std::path::Path::new("."));
+        export::export_archive_bundle(archive_dir, std::path::Path::new(&dest_path), &passphrase, emit_status)
+            .map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+    match &result {
+        Ok(summary) => {
+            let message = format!("{} attachments, {} unreadable", summary.attachments, summary.unreadable);
+            let _ = diagnostics::log_event(&log_dir, "archive_bundle_export", &message);
+        }
+        Err(err) => {
+            let _ = diagnostics::log_event(&log_dir, "archive_bundle_export_error", err);
+        }
+    }
+    result
+}
+
+/// Restores a `.gtarchive` bundle into the (empty) archive, re-encrypting its attachments
+/// under this machine's master key and emitting `archive_bundle_status` messages.
+#[tauri::command]
+async fn import_archive_bundle_cmd(
+    app_handle: tauri::AppHandle,
+    db_state: tauri::State<'_, DbState>,
+    media_state: tauri::State<'_, MediaState>,
+    src_path: String,
+    passphrase: String,
+) -> Result<ArchiveBundleSummary, String> {
+    let archive = archive_path(&app_handle).map_err(|e| e.to_string())?;
+    let log_dir = diagnostics_dir(&app_handle).map_err(|e| e.to_string())?;
+    // The restore replaces the database file, so nothing may hold it open.
+    if let Ok(mut guard) = db_state.db.lock() {
+        *guard = None;
+    }
+    if let Ok(mut guard) = media_state.inner.lock() {
+        if let Some(media) = guard.as_ref() {
+            media_ops::clear_cache(media);
+        }
+        *guard = None;
+    }
+    let app = app_handle.clone();
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        let emit_status = |msg: &str| {
+            let _ = app.emit("archive_bundle_status", msg.to_string());
+        };
+        let archive_dir = archive.parent().unwrap_or_else(|| std::path::Path::new("."));
+        export::import_archive_bundle(std::path::Path::new(&src_path), archive_dir, &passphrase, emit_status)
+            .map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+    match &result {
+        Ok(summary) => {
+            let message = format!("restored {} attachments", summary.attachments);
+            let _ = diagnostics::log_event(&log_dir, "archive_bundle_import", &message);
+        }
+        Err(err) => {
+            let _ = diagnostics::log_event(&log_dir, "archive_bundle_import_error", err);
+        }
+    }
+    result
+}
+
 #[tauri::command]
 fn get_diagnostics_cmd(app_handle: tauri::AppHandle) -> Result<String, String> {
     let log_dir = diagnostics_dir(&app_handle).map_err(|e| e.to_string())?;
@@ -530,18 +1361,148 @@ fn get_diagnostics_cmd(app_handle: tauri::AppHandle) -> Result<String, String> {
     fs::read_to_string(path).map_err(|e| e.to_string())
 }
 
+/// Sample size for the benchmark an import runs before it starts; `crypto_benchmark_cmd`
+/// defaults to the larger one.
+const IMPORT_BENCHMARK_SAMPLE_MB: usize = 8;
+const BENCHMARK_SAMPLE_MB: usize = 32;
+
+/// Measures encrypt/decrypt throughput per chunk size and disk write speed, to tell a
+/// crypto-bound import from an IO-bound one. The recommended chunk size is used for
+/// attachments imported afterwards.
+#[tauri::command]
+async fn crypto_benchmark_cmd(app_handle: tauri::AppHandle, sample_mb: Option<usize>) -> Result<BenchReport, String> {
+    let log_dir = diagnostics_dir(&app_handle).map_err(|e| e.to_string())?;
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        crypto::benchmark(sample_mb.unwrap_or(BENCHMARK_SAMPLE_MB), &crypto::BENCHMARK_CHUNK_SIZES)
+            .map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+    match &result {
+        Ok(report) => log_benchmark(&log_dir, report),
+        Err(err) => {
+            let _ = diagnostics::log_event(&log_dir, "crypto_benchmark_error", err);
+        }
+    }
+    result
+}
+
+/// Logs `report` as JSON with speeds rounded to 0.1 MB/s and whitespace between values, so
+/// the log sanitizer's long-number redaction leaves it readable.
+fn log_benchmark(log_dir: &std::path::Path, report: &BenchReport) {
+    let round = |mb_s: f64| (mb_s * 10.0).round() / 10.0;
+    let mut rounded = report.clone();
+    rounded.disk_write_mb_s = round(rounded.disk_write_mb_s);
+    for result in &mut rounded.results {
+        result.encrypt_mb_s = round(result.encrypt_mb_s);
+        result.decrypt_mb_s = round(result.decrypt_mb_s);
+    }
+    if let Ok(json) = serde_json::to_string_pretty(&rounded) {
+        let _ = diagnostics::log_event(log_dir, "crypto_benchmark", &json);
+    }
+}
+
+#[tauri::command]
+fn crypto_diagnostics_cmd(app_handle: tauri::AppHandle) -> Result<CryptoDiagnostics, String> {
+    let diagnostics = CryptoDiagnostics {
+        key_fingerprint: master_key_fingerprint(),
+        self_test: crypto::self_test(),
+    };
+    if let Ok(log_dir) = diagnostics_dir(&app_handle) {
+        let msg = format!(
+            "self_test_passed={} key_fingerprint={}",
+            diagnostics.self_test.passed,
+            logged_fingerprint(diagnostics.key_fingerprint.as_deref())
+        );
+        let _ = diagnostics::log_event(&log_dir, "crypto_diagnostics", &msg);
+    }
+    Ok(diagnostics)
+}
+
+fn master_key_fingerprint() -> Option<String> {
+    crypto::load_or_create_master_key()
+        .ok()
+        .map(|key| crypto::key_fingerprint(&key))
+}
+
+/// The fingerprint in groups of eight, as the log sanitizer redacts any word with ten or
+/// more digits, which most unbroken fingerprints have.
+fn logged_fingerprint(fingerprint: Option<&str>) -> String {
+    match fingerprint {
+        Some(hex) => diagnostics::group_hex(hex),
+        None => "unavailable".to_string(),
+    }
+}
+
+/// Clears decrypted previews, and with `include_thumbs` the encrypted thumbnail cache too.
 #[tauri::command]
 async fn clear_media_cache_cmd(
     app_handle: tauri::AppHandle,
     state: tauri::State<'_, MediaState>,
+    include_thumbs: Option<bool>,
 ) -> Result<(), String> {
     clear_preview_cache(&app_handle);
 
     let media = get_or_init_media(&app_handle, &state)?;
     media_ops::clear_cache(&media);
+    if include_thumbs.unwrap_or(false) {
+        tauri::async_runtime::spawn_blocking(move || media_ops::clear_thumbs(&media))
+            .await
+            .map_err(|e| e.to_string())?;
+    }
     Ok(())
 }
 
+#[tauri::command]
+async fn media_cache_stats_cmd(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, MediaState>,
+) -> Result<media_ops::MediaCacheStats, String> {
+    let media = get_or_init_media(&app_handle, &state)?;
+    tauri::async_runtime::spawn_blocking(move || media_ops::media_cache_stats(&media))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn set_thumbs_cache_cap_cmd(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, MediaState>,
+    cap_mb: u64,
+) -> Result<(), String> {
+    if cap_mb == 0 {
+        return Err("thumbnail cache cap must be at least 1 MB".to_string());
+    }
+    let cap_bytes = cap_mb.saturating_mul(1024 * 1024);
+    state.thumbs_cap_bytes.store(cap_bytes, Ordering::Relaxed);
+    let media = get_or_init_media(&app_handle, &state)?;
+    tauri::async_runtime::spawn_blocking(move || media_ops::set_thumbs_cap(&media, cap_bytes))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+async fn set_media_cache_config_cmd(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, MediaState>,
+    max_files: usize,
+    ttl_secs: u64,
+) -> Result<(), String> {
+    if max_files == 0 || ttl_secs == 0 {
+        return Err("media cache limits must be at least 1".to_string());
+    }
+    let config = media_ops::MediaCacheConfig {
+        max_files,
+        ttl: std::time::Duration::from_secs(ttl_secs),
+    };
+    *state.cache_config.lock().map_err(|_| "media lock poisoned".to_string())? = config;
+    let media = get_or_init_media(&app_handle, &state)?;
+    // Eviction deletes files, so keep it off the async runtime.
+    tauri::async_runtime::spawn_blocking(move || media_ops::set_cache_config(&media, config))
+        .await
+        .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 async fn drain_media_evictions_cmd(
     app_handle: tauri::AppHandle,
@@ -580,8 +1541,30 @@ fn update_tag_cmd(
 }
 
 #[tauri::command]
-fn delete_tag_cmd(app_handle: tauri::AppHandle, state: tauri::State<DbState>, id: String) -> Result<(), String> {
-    with_db(&app_handle, &state, |db| delete_tag(&db.conn, &id)).map_err(|e| e.to_string())
+fn suggest_tag_color_cmd(app_handle: tauri::AppHandle, state: tauri::State<DbState>) -> Result<String, String> {
+    with_db(&app_handle, &state, |db| suggest_tag_color(&db.conn)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn delete_tag_cmd(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    id: String,
+    force: bool,
+) -> Result<usize, String> {
+    with_db(&app_handle, &state, |db| delete_tag_checked(&db.conn, &id, TAG_DELETE_CONFIRM_USES, force))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn merge_tags_cmd(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    source_tag_id: String,
+    target_tag_id: String,
+) -> Result<(), String> {
+    with_db(&app_handle, &state, |db| merge_tags(&db.conn, &source_tag_id, &target_tag_id))
+        .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -615,15 +1598,207 @@ fn set_message_tags_cmd(
 }
 
 #[tauri::command]
+fn add_message_tag_cmd(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    message_id: String,
+    tag_id: String,
+) -> Result<(), String> {
+    with_db(&app_handle, &state, |db| add_message_tag(&db.conn, &message_id, &tag_id)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn remove_message_tag_cmd(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    message_id: String,
+    tag_id: String,
+) -> Result<(), String> {
+    with_db(&app_handle, &state, |db| remove_message_tag(&db.conn, &message_id, &tag_id))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn bulk_tag_cmd(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    tag_id: String,
+    message_ids: Vec<String>,
+    add: bool,
+) -> Result<usize, String> {
+    with_db(&app_handle, &state, |db| {
+        if add {
+            add_tag_to_messages(&db.conn, &tag_id, &message_ids)
+        } else {
+            remove_tag_from_messages(&db.conn, &tag_id, &message_ids)
+        }
+    })
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn tag_stats_cmd(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    tag_id: String,
+) -> Result<TagStats, String> {
+    with_db(&app_handle, &state, |db| tag_stats(&db.conn, &tag_id)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_or_create_tag_cmd(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    name: String,
+    default_color: String,
+) -> Result<Tag, String> {
+    with_db(&app_handle, &state, |db| get_or_create_tag(&db.conn, &name, &default_color)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn export_tags_json_cmd(app_handle: tauri::AppHandle, state: tauri::State<DbState>) -> Result<String, String> {
+    with_db(&app_handle, &state, |db| export_tags_json(&db.conn)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn import_tags_json_cmd(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    json: String,
+) -> Result<TagImportSummary, String> {
+    with_db(&app_handle, &state, |db| import_tags_json(&db.conn, &json)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_attachment_tags_cmd(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    attachment_id: String,
+    tag_ids: Vec<String>,
+) -> Result<(), String> {
+    with_db(&app_handle, &state, |db| set_attachment_tags(&db.conn, &attachment_id, &tag_ids))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_attachment_tags_bulk_cmd(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    attachment_ids: Vec<String>,
+) -> Result<Vec<AttachmentTags>, String> {
+    with_db(&app_handle, &state, |db| get_attachment_tags_bulk(&db.conn, &attachment_ids))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn list_tagged_media_cmd(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    tag_id: String,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<ThreadMediaRow>, String> {
+    with_db(&app_handle, &state, |db| list_tagged_media(&db.conn, &tag_id, limit, offset))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
 fn list_scrapbook_messages_cmd(
     app_handle: tauri::AppHandle,
     state: tauri::State<DbState>,
     tag_id: String,
+    thread_id: Option<String>,
+    order: Option<ScrapbookOrder>,
     before_ts: Option<i64>,
     before_id: Option<String>,
     limit: i64,
-) -> Result<Vec<ScrapbookMessage>, String> {
-    with_db(&app_handle, &state, |db| list_scrapbook_messages(&db.conn, &tag_id, before_ts, before_id.as_deref(), limit))
+) -> Result<ScrapbookPage, String> {
+    let order = order.unwrap_or_default();
+    with_db(&app_handle, &state, |db| {
+        list_scrapbook_messages(&db.conn, &tag_id, thread_id.as_deref(), order, before_ts, before_id.as_deref(), limit)
+    })
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_attachment_cmd(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    attachment_id: String,
+) -> Result<AttachmentDetail, String> {
+    with_db(&app_handle, &state, |db| get_attachment(&db.conn, &attachment_id)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_message_detail_cmd(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    message_id: String,
+) -> Result<MessageDetail, String> {
+    with_db(&app_handle, &state, |db| get_message_detail(&db.conn, &message_id)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_message_note_cmd(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    message_id: String,
+) -> Result<Option<MessageNote>, String> {
+    with_db(&app_handle, &state, |db| get_message_note(&db.conn, &message_id)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_message_notes_bulk_cmd(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    message_ids: Vec<String>,
+) -> Result<Vec<MessageNote>, String> {
+    with_db(&app_handle, &state, |db| get_message_notes_bulk(&db.conn, &message_ids))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_message_note_cmd(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    message_id: String,
+    note_text: String,
+) -> Result<MessageNote, String> {
+    with_db(&app_handle, &state, |db| set_message_note(&db.conn, &message_id, &note_text))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn delete_message_note_cmd(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    message_id: String,
+) -> Result<(), String> {
+    with_db(&app_handle, &state, |db| delete_message_note(&db.conn, &message_id)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn search_message_notes_cmd(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    query: String,
+    limit: i64,
+) -> Result<Vec<MessageNote>, String> {
+    with_db(&app_handle, &state, |db| search_message_notes(&db.conn, &query, limit))
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn scrapbook_context_cmd(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<DbState>,
+    tag_id: String,
+    message_id: String,
+    before: i64,
+    after: i64,
+) -> Result<Vec<ScrapbookContextMessage>, String> {
+    with_db(&app_handle, &state, |db| scrapbook_context(&db.conn, &tag_id, &message_id, before, after))
         .map_err(|e| e.to_string())
 }
 
@@ -633,9 +1808,19 @@ fn main() {
         .manage(MediaState::default())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_clipboard_manager::init())
+        .register_asynchronous_uri_scheme_protocol("gtmedia", |ctx, request, responder| {
+            let app_handle = ctx.app_handle().clone();
+            tauri::async_runtime::spawn_blocking(move || {
+                responder.respond(media_protocol_response(&app_handle, &request));
+            });
+        })
         .setup(|app| {
             if let Ok(log_dir) = diagnostics_dir(&app.handle()) {
-                let _ = diagnostics::log_event(&log_dir, "app_start", "app started");
+                let msg = format!(
+                    "app started key_fingerprint={}",
+                    logged_fingerprint(master_key_fingerprint().as_deref())
+                );
+                let _ = diagnostics::log_event(&log_dir, "app_start", &msg);
             }
             clear_preview_cache(&app.handle());
             Ok(())
@@ -647,28 +1832,88 @@ fn main() {
             get_message_cmd,
             list_messages_around_cmd,
             list_message_reactions_cmd,
+            list_message_mentions_cmd,
             search_messages_cmd,
+            search_messages_page_cmd,
+            search_messages_grouped_cmd,
+            global_search_cmd,
             list_media_cmd,
             list_thread_media_cmd,
+            thread_media_summary_cmd,
+            thread_media_month_buckets_cmd,
             list_message_attachments_cmd,
             attachment_data_url_cmd,
             attachment_path_cmd,
             attachment_thumbnail_cmd,
+            attachment_thumbnails_cmd,
+            prefetch_media_cmd,
+            cancel_media_request_cmd,
+            attachment_waveform_cmd,
+            export_attachment_cmd,
             archive_stats_cmd,
+            list_imports_cmd,
+            list_import_issues_cmd,
+            rotate_key_cmd,
+            unlock_key_file_cmd,
+            change_key_file_passphrase_cmd,
+            lock_archive_cmd,
+            unlock_archive_cmd,
+            export_archive_bundle_cmd,
+            import_archive_bundle_cmd,
+            list_media_by_sender_cmd,
+            list_document_attachments_cmd,
+            largest_attachments_cmd,
+            attachment_total_bytes_cmd,
+            thread_storage_usage_cmd,
             get_diagnostics_cmd,
+            crypto_diagnostics_cmd,
+            crypto_benchmark_cmd,
             clear_media_cache_cmd,
+            media_cache_stats_cmd,
+            set_thumbs_cache_cap_cmd,
+            set_media_cache_config_cmd,
             drain_media_evictions_cmd,
             seed_demo_cmd,
             import_backup_cmd,
+            inspect_backup_cmd,
+            export_scrapbook_cmd,
+            export_thread_media_cmd,
+            audit_attachments_cmd,
+            gc_orphaned_attachments_cmd,
+            resniff_attachments_cmd,
+            verify_attachments_cmd,
+            backfill_image_dimensions_cmd,
+            backfill_blurhash_cmd,
+            rebuild_fts_cmd,
             reset_archive_cmd,
             list_tags_cmd,
             create_tag_cmd,
             update_tag_cmd,
+            suggest_tag_color_cmd,
             delete_tag_cmd,
+            merge_tags_cmd,
             get_message_tags_cmd,
             get_message_tags_bulk_cmd,
             set_message_tags_cmd,
+            add_message_tag_cmd,
+            remove_message_tag_cmd,
+            bulk_tag_cmd,
+            get_or_create_tag_cmd,
+            tag_stats_cmd,
+            export_tags_json_cmd,
+            import_tags_json_cmd,
+            set_attachment_tags_cmd,
+            get_attachment_tags_bulk_cmd,
+            list_tagged_media_cmd,
             list_scrapbook_messages_cmd,
+            scrapbook_context_cmd,
+            get_attachment_cmd,
+            get_message_detail_cmd,
+            get_message_note_cmd,
+            get_message_notes_bulk_cmd,
+            set_message_note_cmd,
+            delete_message_note_cmd,
+            search_message_notes_cmd,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -4,22 +4,62 @@
 //! that run on tokio's blocking thread pool via `spawn_blocking`.
 
 use std::collections::HashMap;
-use std::io::{Seek, SeekFrom};
 use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
 
 use base64::engine::general_purpose::STANDARD as BASE64_STANDARD;
 use base64::Engine;
 use golden_thread_core::crypto::{self, MasterKey};
+use golden_thread_core::{diagnostics, CoreError};
+use image::codecs::jpeg::JpegEncoder;
 use image::codecs::webp::WebPEncoder;
 use image::imageops::FilterType;
 use image::ColorType;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use zeroize::Zeroizing;
 
-const MAX_MEDIA_FILES: usize = 20;
-const MEDIA_TTL: Duration = Duration::from_secs(300);
-const PARALLEL_DECRYPT_THRESHOLD: u64 = 10 * 1024 * 1024;
-const PARALLEL_DECRYPT_WORKERS: usize = 4;
+/// Decrypted previews kept in `media/` unless the user picks another limit.
+pub const DEFAULT_MAX_MEDIA_FILES: usize = 20;
+/// Seconds an unused preview stays in `media/` unless the user picks another limit.
+pub const DEFAULT_MEDIA_TTL_SECS: u64 = 300;
+/// Where ffmpeg is looked for after `PATH`: apps launched from the Finder don't get the
+/// shell's `PATH`, which is where Homebrew adds itself.
+const FFMPEG_DIRS: [&str; 2] = ["/opt/homebrew/bin", "/usr/local/bin"];
+/// Seconds into a video its poster frame is taken from, past any fade-in.
+const POSTER_FRAME_SECS: &str = "1";
+/// Most peaks a waveform can be asked for.
+pub const MAX_WAVEFORM_BUCKETS: usize = 1024;
+/// Audio frames folded into one peak while decoding, before the peaks are bucketed.
+const WAVEFORM_WINDOW_FRAMES: usize = 256;
+/// Most thumbnails one batch request may ask for.
+pub const MAX_THUMB_BATCH: usize = 32;
+/// Threads a thumbnail batch is spread across.
+const THUMB_BATCH_WORKERS: usize = 4;
+/// Most previews one prefetch request may warm.
+pub const MAX_PREFETCH: usize = 8;
+/// `ftyp` brands of HEIF containers, as iPhone HEIC photos use.
+const HEIF_BRANDS: [&[u8; 4]; 7] = [b"heic", b"heix", b"heim", b"heis", b"hevc", b"hevx", b"mif1"];
+/// Quality of the JPEGs that HEIC and downscaled image previews are re-encoded as.
+const PREVIEW_JPEG_QUALITY: u8 = 90;
+/// Longest side of a downscaled image preview.
+pub const PREVIEW_MAX_DIMENSION: u32 = 2048;
+/// Image previews up to this size are sent as they are; larger ones are downscaled if
+/// they're over [`PREVIEW_MAX_DIMENSION`].
+const PREVIEW_DOWNSCALE_BYTES: usize = 2 * 1024 * 1024;
+/// Default cap on `thumbs/`, which otherwise keeps every thumbnail and waveform forever.
+pub const DEFAULT_THUMBS_CAP_BYTES: u64 = 500 * 1024 * 1024;
+/// Most plaintext returned for one streamed range. Players ask for `bytes=0-` and then
+/// follow up, so an open-ended range never decrypts a whole video into memory.
+pub const MAX_STREAM_RANGE: u64 = 4 * 1024 * 1024;
 
 /// Shared state for media operations.
 pub struct MediaState {
@@ -27,7 +67,17 @@ pub struct MediaState {
     pub attachments_dir: PathBuf,
     pub thumbs_dir: PathBuf,
     pub media_dir: PathBuf,
+    /// Where decryption failures are logged.
+    pub log_dir: PathBuf,
     pub cache: Mutex<MediaCache>,
+    /// Bytes `thumbs/` may hold before its least recently used files are evicted.
+    pub thumbs_cap_bytes: AtomicU64,
+    /// Bytes in `thumbs/`, measured on the first write and kept up to date after.
+    thumbs_usage: Mutex<Option<u64>>,
+    /// Bumped by each [`prefetch`], so an unfinished one stops before its next item.
+    prefetch_generation: AtomicU64,
+    /// Cancel flags of the [`track_request`] requests in flight, by request id.
+    requests: Mutex<HashMap<String, Arc<AtomicBool>>>,
 }
 
 impl MediaState {
@@ -36,6 +86,7 @@ impl MediaState {
         attachments_dir: PathBuf,
         thumbs_dir: PathBuf,
         media_dir: PathBuf,
+        log_dir: PathBuf,
     ) -> Self {
         std::fs::create_dir_all(&media_dir).ok();
         std::fs::create_dir_all(&thumbs_dir).ok();
@@ -44,9 +95,25 @@ impl MediaState {
             attachments_dir,
             thumbs_dir,
             media_dir,
-            cache: Mutex::new(MediaCache::new()),
+            log_dir,
+            cache: Mutex::new(MediaCache::new(MediaCacheConfig::default())),
+            thumbs_cap_bytes: AtomicU64::new(DEFAULT_THUMBS_CAP_BYTES),
+            thumbs_usage: Mutex::new(None),
+            prefetch_generation: AtomicU64::new(0),
+            requests: Mutex::new(HashMap::new()),
         }
     }
+
+    /// Logs a failed decryption of `sha256`'s blob at `path` as `decrypt_error`, with the
+    /// blob's format version, and returns the error for the UI. Crypto errors name the
+    /// damaged chunk; the path goes last, since the log sanitizer cuts everything from a path on.
+    fn decrypt_error(&self, sha256: &str, path: &Path, err: CoreError) -> String {
+        let message = format!("{} in {}", err, path.display());
+        let version = crypto::format_version_of(path).map_or_else(|_| "unknown".to_string(), |v| v.to_string());
+        let logged = format!("sha256 {} format {}: {}", diagnostics::group_hex(sha256), version, message);
+        let _ = diagnostics::log_event(&self.log_dir, "decrypt_error", &logged);
+        message
+    }
 }
 
 /// Generate or load a cached thumbnail, returning a data URL.
@@ -67,15 +134,11 @@ fn generate_thumbnail_inner(
     sha256: &str,
     max_size: u32,
 ) -> Result<String, String> {
-    let encrypted_thumb = state
-        .thumbs_dir
-        .join(format!("{}_{}.bin", sha256, max_size));
+    let encrypted_thumb = thumb_path(state, sha256, max_size);
 
     // Check for cached encrypted thumbnail
-    if encrypted_thumb.exists() {
-        let data = decrypt_to_bytes(&encrypted_thumb, &state.key)?;
-        let encoded = BASE64_STANDARD.encode(data);
-        return Ok(format!("data:image/webp;base64,{}", encoded));
+    if let Some(cached) = read_cached(state, sha256, &encrypted_thumb) {
+        return Ok(data_url("image/webp", &cached?));
     }
 
     // Generate from source attachment
@@ -84,54 +147,182 @@ fn generate_thumbnail_inner(
         return Err("attachment missing".to_string());
     }
 
-    let data = decrypt_to_bytes(&attachment_path, &state.key)?;
-    let img = image::load_from_memory(&data).map_err(|e| e.to_string())?;
+    let data = decrypt_to_bytes(&attachment_path, &state.key)
+        .map_err(|e| state.decrypt_error(sha256, &attachment_path, e))?;
+    encode_thumbnail(state, &data, max_size, &encrypted_thumb)
+}
+
+/// Generate or load a cached poster-frame thumbnail for a video, returning a data URL. The
+/// frame is grabbed with ffmpeg, which must be installed; without it this is an error the
+/// UI shows in place of the thumbnail.
+pub fn generate_video_thumbnail(
+    state: &MediaState,
+    sha256: &str,
+    mime: Option<&str>,
+    max_size: u32,
+) -> Result<String, String> {
+    // Wrap in catch_unwind for crash isolation
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        generate_video_thumbnail_inner(state, sha256, mime, max_size)
+    }))
+    .map_err(|_| "thumbnail generation panicked".to_string())?
+}
+
+fn generate_video_thumbnail_inner(
+    state: &MediaState,
+    sha256: &str,
+    mime: Option<&str>,
+    max_size: u32,
+) -> Result<String, String> {
+    let encrypted_thumb = thumb_path(state, sha256, max_size);
+    if let Some(cached) = read_cached(state, sha256, &encrypted_thumb) {
+        return Ok(data_url("image/webp", &cached?));
+    }
+
+    let attachment_path = state.attachments_dir.join(sha256);
+    if !attachment_path.exists() {
+        return Err("attachment missing".to_string());
+    }
+    let ffmpeg = find_ffmpeg().ok_or_else(|| "video thumbnails need ffmpeg, which isn't installed".to_string())?;
+
+    // ffmpeg needs a seekable file, so the video is decrypted next to the previews; the temp
+    // file is deleted when dropped, on every path out of here.
+    std::fs::create_dir_all(&state.media_dir).map_err(|e| e.to_string())?;
+    let ext = mime.and_then(mime_extension).unwrap_or("bin");
+    let video = tempfile::Builder::new()
+        .suffix(&format!(".{}", ext))
+        .tempfile_in(&state.media_dir)
+        .map_err(|e| e.to_string())?;
+    crypto::decrypt_attachment_to_path(&attachment_path, video.path(), &state.key)
+        .map_err(|e| state.decrypt_error(sha256, &attachment_path, e))?;
+    let frame = extract_poster_frame(&ffmpeg, video.path())?;
+    drop(video);
+    encode_thumbnail(state, &frame, max_size, &encrypted_thumb)
+}
+
+/// Downscales `image_bytes` to fit `max_size`, encodes it as WebP, caches it encrypted at
+/// `encrypted_thumb` and returns it as a data URL. Every intermediate buffer is wiped.
+fn encode_thumbnail(
+    state: &MediaState,
+    image_bytes: &[u8],
+    max_size: u32,
+    encrypted_thumb: &Path,
+) -> Result<String, String> {
+    let img = decode_image(image_bytes).map_err(|e| e.to_string())?;
     let resized = img.resize(max_size, max_size, FilterType::Triangle);
+    drop(Zeroizing::new(img.into_bytes()));
     let rgba = resized.to_rgba8();
+    drop(Zeroizing::new(resized.into_bytes()));
     let (w, h) = rgba.dimensions();
 
-    let mut webp_bytes: Vec<u8> = Vec::new();
-    let encoder = WebPEncoder::new_lossless(&mut webp_bytes);
-    encoder
-        .encode(&rgba, w, h, ColorType::Rgba8.into())
-        .map_err(|e| e.to_string())?;
+    let mut webp_bytes = Zeroizing::new(Vec::new());
+    let encoder = WebPEncoder::new_lossless(&mut *webp_bytes);
+    let encoded = encoder.encode(&rgba, w, h, ColorType::Rgba8.into());
+    drop(Zeroizing::new(rgba.into_raw()));
+    encoded.map_err(|e| e.to_string())?;
 
     // Cache the encrypted thumbnail
-    std::fs::create_dir_all(&state.thumbs_dir).map_err(|e| e.to_string())?;
-    let mut reader = std::io::Cursor::new(&webp_bytes);
-    let mut temp =
-        tempfile::NamedTempFile::new_in(&state.thumbs_dir).map_err(|e| e.to_string())?;
-    crypto::encrypt_stream(&mut reader, &mut temp, &state.key).map_err(|e| e.to_string())?;
+    store_encrypted(state, &webp_bytes, encrypted_thumb)?;
+    Ok(data_url("image/webp", &webp_bytes))
+}
 
-    // Atomic rename (handle race condition)
-    match temp.persist(&encrypted_thumb) {
-        Ok(_) => {}
-        Err(e) if e.error.kind() == std::io::ErrorKind::AlreadyExists => {}
-        Err(e) => return Err(e.to_string()),
-    }
+/// One thumbnail in a batch request.
+#[derive(Clone, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ThumbRequest {
+    pub sha256: String,
+    pub mime: Option<String>,
+    pub max_size: u32,
+}
 
-    let encoded = BASE64_STANDARD.encode(webp_bytes);
-    Ok(format!("data:image/webp;base64,{}", encoded))
+/// The outcome of one [`ThumbRequest`]: a data URL, or why there isn't one.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct ThumbResult {
+    pub url: Option<String>,
+    pub error: Option<String>,
 }
 
-/// Decrypt attachment to a preview file, returning the file path.
-pub fn decrypt_to_preview(
+impl From<Result<String, String>> for ThumbResult {
+    fn from(result: Result<String, String>) -> Self {
+        match result {
+            Ok(url) => ThumbResult { url: Some(url), error: None },
+            Err(error) => ThumbResult { url: None, error: Some(error) },
+        }
+    }
+}
+
+/// Thumbnail for an image or a video poster frame, by `mime`. Other kinds are an error.
+pub fn generate_any_thumbnail(
     state: &MediaState,
     sha256: &str,
     mime: Option<&str>,
+    max_size: u32,
 ) -> Result<String, String> {
+    match mime {
+        Some(m) if m.starts_with("video/") => generate_video_thumbnail(state, sha256, mime, max_size),
+        Some(m) if !m.starts_with("image/") => Err("thumbnail only for images and videos".to_string()),
+        _ => generate_thumbnail(state, sha256, max_size),
+    }
+}
+
+/// Generate a batch of thumbnails across a few threads, keyed `"{sha256}:{max_size}"`. A
+/// failed item is reported in its own result and doesn't fail the others.
+pub fn generate_thumbnails(state: &MediaState, requests: &[ThumbRequest]) -> HashMap<String, ThumbResult> {
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(HashMap::with_capacity(requests.len()));
+    std::thread::scope(|scope| {
+        for _ in 0..THUMB_BATCH_WORKERS.min(requests.len()) {
+            scope.spawn(|| {
+                while let Some(request) = requests.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    let result = generate_any_thumbnail(state, &request.sha256, request.mime.as_deref(), request.max_size);
+                    if let Ok(mut results) = results.lock() {
+                        results.insert(format!("{}:{}", request.sha256, request.max_size), result.into());
+                    }
+                }
+            });
+        }
+    });
+    results.into_inner().unwrap_or_default()
+}
+
+/// Progress of a preview decryption, emitted to the frontend as `media_progress`.
+#[derive(Clone, serde::Serialize)]
+pub struct DecryptProgress {
+    pub sha256: String,
+    pub done: u64,
+    pub total: u64,
+}
+
+/// Decrypt attachment to a preview file, returning the file path. `progress` gets
+/// `(bytes_done, bytes_total)` as chunks are decrypted. Setting `cancel` stops the decrypt
+/// before its next chunk, failing with `"cancelled"` and leaving no plaintext behind.
+pub fn decrypt_to_preview<F>(
+    state: &MediaState,
+    sha256: &str,
+    mime: Option<&str>,
+    cancel: &AtomicBool,
+    progress: F,
+) -> Result<String, String>
+where
+    F: Fn(u64, u64) + Sync,
+{
     // Wrap in catch_unwind for crash isolation
     std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
-        decrypt_to_preview_inner(state, sha256, mime)
+        decrypt_to_preview_inner(state, sha256, mime, cancel, progress)
     }))
     .map_err(|_| "media decryption panicked".to_string())?
 }
 
-fn decrypt_to_preview_inner(
+fn decrypt_to_preview_inner<F>(
     state: &MediaState,
     sha256: &str,
     mime: Option<&str>,
-) -> Result<String, String> {
+    cancel: &AtomicBool,
+    progress: F,
+) -> Result<String, String>
+where
+    F: Fn(u64, u64) + Sync,
+{
     let ext = mime.and_then(mime_extension).unwrap_or("bin");
     let cache_key = format!("{}:{}", sha256, ext);
 
@@ -147,38 +338,16 @@ fn decrypt_to_preview_inner(
         return Err("attachment missing".to_string());
     }
 
-    let plaintext_len = crypto::encrypted_plaintext_len(&attachment_path).ok();
     std::fs::create_dir_all(&state.media_dir).map_err(|e| e.to_string())?;
 
     let preview_path = state.media_dir.join(format!("{}.{}", sha256, ext));
-    let mut temp =
+    let temp =
         tempfile::NamedTempFile::new_in(&state.media_dir).map_err(|e| e.to_string())?;
-
-    // Use parallel decryption for large files
-    if let Some(len) = plaintext_len {
-        if len >= PARALLEL_DECRYPT_THRESHOLD {
-            crypto::decrypt_file_parallel(
-                &attachment_path,
-                temp.path(),
-                &state.key,
-                PARALLEL_DECRYPT_WORKERS,
-            )
-            .map_err(|e| e.to_string())?;
-        } else if len > 0 {
-            let mut reader =
-                std::fs::File::open(&attachment_path).map_err(|e| e.to_string())?;
-            let file = temp.as_file_mut();
-            let _ = file.set_len(len);
-            let _ = file.seek(SeekFrom::Start(0));
-            crypto::decrypt_stream(&mut reader, &mut temp, &state.key)
-                .map_err(|e| e.to_string())?;
-        }
-    } else {
-        let mut reader =
-            std::fs::File::open(&attachment_path).map_err(|e| e.to_string())?;
-        crypto::decrypt_stream(&mut reader, &mut temp, &state.key)
-            .map_err(|e| e.to_string())?;
-    }
+    crypto::decrypt_attachment_to_path_cancellable(&attachment_path, temp.path(), &state.key, cancel, progress)
+        .map_err(|e| match e {
+            CoreError::Cancelled => e.to_string(),
+            e => state.decrypt_error(sha256, &attachment_path, e),
+        })?;
 
     // Atomic rename
     match temp.persist(&preview_path) {
@@ -195,26 +364,470 @@ fn decrypt_to_preview_inner(
     Ok(preview_path.to_string_lossy().to_string())
 }
 
-/// Generate a data URL for small attachments.
+/// One attachment to decrypt ahead of time.
+#[derive(Clone, serde::Deserialize)]
+pub struct PrefetchRequest {
+    pub sha256: String,
+    pub mime: Option<String>,
+}
+
+/// Pins `current`'s preview against LRU eviction, then decrypts `items` into the preview
+/// cache one at a time on a background thread, so the lightbox's neighbours open without a
+/// wait. Returns straight away. A later call supersedes this one, which stops before its
+/// next item; failures are ignored, since the item is decrypted again when it's opened.
+pub fn prefetch(state: &Arc<MediaState>, current: Option<&str>, mut items: Vec<PrefetchRequest>) {
+    if let Ok(mut cache) = state.cache.lock() {
+        cache.pin(current.map(str::to_string));
+    }
+    let generation = state.prefetch_generation.fetch_add(1, Ordering::Relaxed) + 1;
+    items.truncate(MAX_PREFETCH);
+    if items.is_empty() {
+        return;
+    }
+    let state = Arc::clone(state);
+    // Best effort like the prefetch itself: if no thread can be spawned, nothing is warmed.
+    let _ = std::thread::Builder::new().name("media-prefetch".to_string()).spawn(move || {
+        for item in items {
+            if state.prefetch_generation.load(Ordering::Relaxed) != generation {
+                return;
+            }
+            let Some(cache_generation) = state.cache.lock().ok().map(|cache| cache.generation()) else {
+                return;
+            };
+            let ext = item.mime.as_deref().and_then(mime_extension).unwrap_or("bin");
+            let warmed = decrypt_to_preview(&state, &item.sha256, item.mime.as_deref(), &AtomicBool::new(false), |_, _| {});
+            // A clear (on lock or reset) while this decrypted mustn't leave plaintext behind.
+            if let Ok(mut cache) = state.cache.lock() {
+                if cache.generation() != cache_generation {
+                    if let Ok(path) = warmed {
+                        cache.remove(&format!("{}:{}", item.sha256, ext));
+                        let _ = std::fs::remove_file(path);
+                    }
+                    return;
+                }
+            }
+        }
+    });
+}
+
+/// A request [`cancel_request`] can stop, registered under its id until dropped.
+pub struct TrackedRequest {
+    state: Arc<MediaState>,
+    id: String,
+    cancel: Arc<AtomicBool>,
+}
+
+impl TrackedRequest {
+    /// Set once the request is cancelled; pass it to [`decrypt_to_preview`].
+    pub fn cancel_flag(&self) -> &AtomicBool {
+        &self.cancel
+    }
+}
+
+impl Drop for TrackedRequest {
+    fn drop(&mut self) {
+        if let Ok(mut requests) = self.state.requests.lock() {
+            requests.remove(&self.id);
+        }
+    }
+}
+
+/// Registers a cancellable request under `request_id`, before its work is queued, so a
+/// cancel that arrives first still finds it.
+pub fn track_request(state: &Arc<MediaState>, request_id: String) -> TrackedRequest {
+    let cancel = Arc::new(AtomicBool::new(false));
+    if let Ok(mut requests) = state.requests.lock() {
+        requests.insert(request_id.clone(), Arc::clone(&cancel));
+    }
+    TrackedRequest {
+        state: Arc::clone(state),
+        id: request_id,
+        cancel,
+    }
+}
+
+/// Cancels the request tracked under `request_id`. False if it already finished.
+pub fn cancel_request(state: &MediaState, request_id: &str) -> bool {
+    let Ok(requests) = state.requests.lock() else {
+        return false;
+    };
+    requests.get(request_id).map(|cancel| cancel.store(true, Ordering::Relaxed)).is_some()
+}
+
+/// Why a waveform couldn't be generated. Audio symphonia can't decode is told apart, so the
+/// UI can fall back to a plain player.
+#[derive(Debug)]
+pub enum WaveformError {
+    /// No demuxer or decoder for the attachment's container or codec.
+    Unsupported(String),
+    Failed(String),
+}
+
+impl std::fmt::Display for WaveformError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WaveformError::Unsupported(msg) => write!(f, "unsupported audio format: {}", msg),
+            WaveformError::Failed(msg) => f.write_str(msg),
+        }
+    }
+}
+
+/// Generate or load cached waveform peaks for an audio attachment: `buckets` values, each
+/// the loudest sample in its stretch of the audio, where 255 is full scale. Cached encrypted
+/// under `thumbs/` like thumbnails.
+pub fn generate_waveform(
+    state: &MediaState,
+    sha256: &str,
+    mime: Option<&str>,
+    buckets: usize,
+) -> Result<Vec<u8>, WaveformError> {
+    // Wrap in catch_unwind for crash isolation
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        generate_waveform_inner(state, sha256, mime, buckets)
+    }))
+    .map_err(|_| WaveformError::Failed("waveform generation panicked".to_string()))?
+}
+
+fn generate_waveform_inner(
+    state: &MediaState,
+    sha256: &str,
+    mime: Option<&str>,
+    buckets: usize,
+) -> Result<Vec<u8>, WaveformError> {
+    if buckets == 0 || buckets > MAX_WAVEFORM_BUCKETS {
+        return Err(WaveformError::Failed(format!("waveform needs 1-{} buckets", MAX_WAVEFORM_BUCKETS)));
+    }
+    let encrypted_wave = state.thumbs_dir.join(format!("{}_wave_{}.bin", sha256, buckets));
+    if let Some(cached) = read_cached(state, sha256, &encrypted_wave) {
+        return Ok(cached.map_err(WaveformError::Failed)?.to_vec());
+    }
+
+    let attachment_path = state.attachments_dir.join(sha256);
+    if !attachment_path.exists() {
+        return Err(WaveformError::Failed("attachment missing".to_string()));
+    }
+    let data = decrypt_to_bytes(&attachment_path, &state.key)
+        .map_err(|e| WaveformError::Failed(state.decrypt_error(sha256, &attachment_path, e)))?;
+    let windows = decode_window_peaks(data, mime)?;
+    if windows.is_empty() {
+        return Err(WaveformError::Failed("no audio could be decoded".to_string()));
+    }
+    let peaks = bucket_peaks(&windows, buckets);
+    store_encrypted(state, &peaks, &encrypted_wave).map_err(WaveformError::Failed)?;
+    Ok(peaks)
+}
+
+/// A data URL preview, with the dimensions of what it shows for images.
+#[derive(Debug, serde::Serialize)]
+pub struct DataUrlResponse {
+    pub url: String,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// Whether the image was shrunk to [`PREVIEW_MAX_DIMENSION`]; the original is still
+    /// available through `decrypt_to_preview`.
+    pub downscaled: bool,
+}
+
+/// Generate a data URL for small attachments. The decrypted bytes are wiped before
+/// returning; the data URL itself is the only plaintext copy left. Large images are
+/// downscaled and HEIC photos converted to JPEG, since the webview can't show them; GIFs
+/// are left alone to keep their animation. `max_bytes` limits the plaintext size.
 pub fn generate_data_url(
     state: &MediaState,
     sha256: &str,
     mime: &str,
     max_bytes: u64,
-) -> Result<String, String> {
+) -> Result<DataUrlResponse, DataUrlError> {
+    // Wrap in catch_unwind for crash isolation
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        generate_data_url_inner(state, sha256, mime, max_bytes)
+    }))
+    .map_err(|_| DataUrlError::from("data URL generation panicked".to_string()))?
+}
+
+fn generate_data_url_inner(
+    state: &MediaState,
+    sha256: &str,
+    mime: &str,
+    max_bytes: u64,
+) -> Result<DataUrlResponse, DataUrlError> {
     let attachment_path = state.attachments_dir.join(sha256);
     if !attachment_path.exists() {
-        return Err("attachment missing".to_string());
+        return Err("attachment missing".to_string().into());
     }
 
-    let meta = std::fs::metadata(&attachment_path).map_err(|e| e.to_string())?;
-    if meta.len() > max_bytes {
-        return Err("media too large to preview".to_string());
+    let size = crypto::encrypted_plaintext_len(&attachment_path)
+        .map_err(|e| state.decrypt_error(sha256, &attachment_path, e))?;
+    if size > max_bytes {
+        return Err(DataUrlError::TooLarge { size, limit: max_bytes });
     }
 
-    let data = decrypt_to_bytes(&attachment_path, &state.key)?;
-    let encoded = BASE64_STANDARD.encode(data);
-    Ok(format!("data:{};base64,{}", mime, encoded))
+    let data = decrypt_to_bytes(&attachment_path, &state.key)
+        .map_err(|e| state.decrypt_error(sha256, &attachment_path, e))?;
+    if mime.starts_with("image/") {
+        return image_data_url(&data, mime).map_err(DataUrlError::from);
+    }
+    Ok(DataUrlResponse {
+        url: data_url(mime, &data),
+        width: None,
+        height: None,
+        downscaled: false,
+    })
+}
+
+fn image_data_url(data: &[u8], mime: &str) -> Result<DataUrlResponse, String> {
+    let heif = is_heif(data);
+    let as_is = |width, height| DataUrlResponse {
+        url: data_url(mime, data),
+        width,
+        height,
+        downscaled: false,
+    };
+    if !heif && (mime == "image/gif" || data.len() <= PREVIEW_DOWNSCALE_BYTES) {
+        let (width, height) = image::ImageReader::new(std::io::Cursor::new(data))
+            .with_guessed_format()
+            .ok()
+            .and_then(|reader| reader.into_dimensions().ok())
+            .unzip();
+        return Ok(as_is(width, height));
+    }
+
+    let img = decode_image(data).map_err(|e| e.to_string())?;
+    let (width, height) = (img.width(), img.height());
+    let downscaled = width.max(height) > PREVIEW_MAX_DIMENSION;
+    if !heif && !downscaled {
+        drop(Zeroizing::new(img.into_bytes()));
+        return Ok(as_is(Some(width), Some(height)));
+    }
+    let img = if downscaled {
+        let resized = img.resize(PREVIEW_MAX_DIMENSION, PREVIEW_MAX_DIMENSION, FilterType::Triangle);
+        drop(Zeroizing::new(img.into_bytes()));
+        resized
+    } else {
+        img
+    };
+    let (width, height) = (img.width(), img.height());
+    let (encoded_mime, encoded) = encode_preview(img)?;
+    Ok(DataUrlResponse {
+        url: data_url(encoded_mime, &encoded),
+        width: Some(width),
+        height: Some(height),
+        downscaled,
+    })
+}
+
+/// Re-encodes a preview as JPEG, or as PNG if it has transparency to keep.
+fn encode_preview(img: image::DynamicImage) -> Result<(&'static str, Zeroizing<Vec<u8>>), String> {
+    let opaque = match &img {
+        image::DynamicImage::ImageRgba8(rgba) => rgba.pixels().all(|pixel| pixel.0[3] == u8::MAX),
+        other => !other.color().has_alpha(),
+    };
+    let mut encoded = Zeroizing::new(Vec::new());
+    let result = if opaque {
+        let rgb = img.to_rgb8();
+        let result = JpegEncoder::new_with_quality(&mut *encoded, PREVIEW_JPEG_QUALITY).encode_image(&rgb);
+        drop(Zeroizing::new(rgb.into_raw()));
+        result.map(|_| "image/jpeg")
+    } else {
+        img.write_to(&mut std::io::Cursor::new(&mut *encoded), image::ImageFormat::Png).map(|_| "image/png")
+    };
+    drop(Zeroizing::new(img.into_bytes()));
+    let encoded_mime = result.map_err(|e| e.to_string())?;
+    Ok((encoded_mime, encoded))
+}
+
+/// Why [`generate_data_url`] has no data URL, serialized for the frontend as
+/// `{ kind: "too_large", size, limit }` or `{ kind: "failed", message }`.
+#[derive(Debug, PartialEq, serde::Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DataUrlError {
+    /// The attachment's plaintext is over the limit; the UI can open it another way.
+    TooLarge { size: u64, limit: u64 },
+    Failed { message: String },
+}
+
+impl From<String> for DataUrlError {
+    fn from(message: String) -> Self {
+        DataUrlError::Failed { message }
+    }
+}
+
+impl std::fmt::Display for DataUrlError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DataUrlError::TooLarge { size, limit } => {
+                write!(f, "media too large to preview ({} bytes, limit {})", size, limit)
+            }
+            DataUrlError::Failed { message } => f.write_str(message),
+        }
+    }
+}
+
+/// Why an image attachment couldn't be decoded. HEIC is told apart, as it depends on how
+/// the app was built.
+#[derive(Debug)]
+pub enum ImageDecodeError {
+    /// A HEIC image in a build without the `heic` feature, or without libheif's HEVC decoder.
+    HeicUnsupported(String),
+    Failed(String),
+}
+
+impl std::fmt::Display for ImageDecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImageDecodeError::HeicUnsupported(msg) => write!(f, "HEIC images are not supported: {}", msg),
+            ImageDecodeError::Failed(msg) => f.write_str(msg),
+        }
+    }
+}
+
+/// Decodes an image attachment, HEIC through libheif and everything else with `image`.
+fn decode_image(data: &[u8]) -> Result<image::DynamicImage, ImageDecodeError> {
+    if is_heif(data) {
+        return decode_heif(data);
+    }
+    image::load_from_memory(data).map_err(|e| ImageDecodeError::Failed(e.to_string()))
+}
+
+/// Whether `data` is a HEIF container, by the brand in its leading `ftyp` box.
+fn is_heif(data: &[u8]) -> bool {
+    data.len() >= 12 && &data[4..8] == b"ftyp" && HEIF_BRANDS.iter().any(|brand| &data[8..12] == *brand)
+}
+
+#[cfg(feature = "heic")]
+fn decode_heif(data: &[u8]) -> Result<image::DynamicImage, ImageDecodeError> {
+    use libheif_rs::{ColorSpace, HeifContext, HeifError, HeifErrorCode, LibHeif, RgbChroma};
+
+    let heif_error = |e: HeifError| match e.code {
+        HeifErrorCode::UnsupportedFeature
+        | HeifErrorCode::UnsupportedFileType
+        | HeifErrorCode::DecoderPluginError
+        | HeifErrorCode::PluginLoadingError => ImageDecodeError::HeicUnsupported(e.message),
+        _ => ImageDecodeError::Failed(e.message),
+    };
+    let lib = LibHeif::new_checked().map_err(heif_error)?;
+    let context = HeifContext::read_from_bytes(data).map_err(heif_error)?;
+    let handle = context.primary_image_handle().map_err(heif_error)?;
+    let decoded = lib.decode(&handle, ColorSpace::Rgb(RgbChroma::Rgba), None).map_err(heif_error)?;
+    let planes = decoded.planes();
+    let plane = planes
+        .interleaved
+        .ok_or_else(|| ImageDecodeError::Failed("HEIC image has no RGBA plane".to_string()))?;
+
+    // Rows can be padded past the image width; copy just the pixels.
+    let row_len = plane.width as usize * 4;
+    let mut pixels = Vec::with_capacity(row_len * plane.height as usize);
+    for row in plane.data.chunks(plane.stride).take(plane.height as usize) {
+        pixels.extend_from_slice(&row[..row_len]);
+    }
+    image::RgbaImage::from_raw(plane.width, plane.height, pixels)
+        .map(image::DynamicImage::ImageRgba8)
+        .ok_or_else(|| ImageDecodeError::Failed("HEIC image is truncated".to_string()))
+}
+
+#[cfg(not(feature = "heic"))]
+fn decode_heif(_data: &[u8]) -> Result<image::DynamicImage, ImageDecodeError> {
+    Err(ImageDecodeError::HeicUnsupported("built without the `heic` feature".to_string()))
+}
+
+
+/// One response to a streamed media request: `status` is 200 for the whole attachment, 206
+/// for part of it, and 416 (with an empty body) for a range that doesn't fit.
+pub struct MediaRange {
+    pub status: u16,
+    pub start: u64,
+    pub total: u64,
+    pub body: Zeroizing<Vec<u8>>,
+}
+
+impl MediaRange {
+    /// The `Content-Range` header value for this response, if it needs one.
+    pub fn content_range(&self) -> Option<String> {
+        match self.status {
+            206 if self.body.is_empty() => Some(format!("bytes */{}", self.total)),
+            206 => Some(format!("bytes {}-{}/{}", self.start, self.start + self.body.len() as u64 - 1, self.total)),
+            416 => Some(format!("bytes */{}", self.total)),
+            _ => None,
+        }
+    }
+}
+
+/// Decrypt the plaintext bytes an HTTP `Range` header asks for, verifying only the chunks
+/// that cover them. Without a header, small attachments are returned whole and larger ones
+/// as a first range. Ranges are capped at [`MAX_STREAM_RANGE`].
+pub fn read_range(state: &MediaState, sha256: &str, range: Option<&str>) -> Result<MediaRange, String> {
+    // Wrap in catch_unwind for crash isolation
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| read_range_inner(state, sha256, range)))
+        .map_err(|_| "media decryption panicked".to_string())?
+}
+
+fn read_range_inner(state: &MediaState, sha256: &str, range: Option<&str>) -> Result<MediaRange, String> {
+    let attachment_path = state.attachments_dir.join(sha256);
+    if !attachment_path.exists() {
+        return Err("attachment missing".to_string());
+    }
+    let total = crypto::encrypted_plaintext_len(&attachment_path)
+        .map_err(|e| state.decrypt_error(sha256, &attachment_path, e))?;
+
+    let (status, start, end) = match range {
+        None if total <= MAX_STREAM_RANGE => (200, 0, total),
+        None => (206, 0, MAX_STREAM_RANGE),
+        Some(header) => match parse_range(header, total) {
+            Some((start, end)) => (206, start, end.min(start + MAX_STREAM_RANGE)),
+            None => {
+                return Ok(MediaRange {
+                    status: 416,
+                    start: 0,
+                    total,
+                    body: Zeroizing::new(Vec::new()),
+                })
+            }
+        },
+    };
+    let body = if end > start {
+        crypto::decrypt_range(&attachment_path, &state.key, start, end - start)
+            .map_err(|e| state.decrypt_error(sha256, &attachment_path, e))?
+    } else {
+        Zeroizing::new(Vec::new())
+    };
+    Ok(MediaRange {
+        status,
+        start,
+        total,
+        body,
+    })
+}
+
+/// The half-open byte span a single-range `bytes=` header selects from `total` bytes, or
+/// `None` if it is malformed or starts past the end. Multiple ranges aren't supported.
+fn parse_range(header: &str, total: u64) -> Option<(u64, u64)> {
+    let spec = header.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None;
+    }
+    let (first, last) = spec.split_once('-')?;
+    let (first, last) = (first.trim(), last.trim());
+    if first.is_empty() {
+        let suffix: u64 = last.parse().ok()?;
+        if suffix == 0 {
+            return None;
+        }
+        return Some((total.saturating_sub(suffix), total));
+    }
+    let start: u64 = first.parse().ok()?;
+    if start >= total {
+        return None;
+    }
+    let end = if last.is_empty() {
+        total
+    } else {
+        let last: u64 = last.parse().ok()?;
+        if last < start {
+            return None;
+        }
+        last.saturating_add(1).min(total)
+    };
+    Some((start, end))
 }
 
 /// Clear all cached preview files.
@@ -224,6 +837,51 @@ pub fn clear_cache(state: &MediaState) {
     }
 }
 
+/// Delete every cached thumbnail and waveform; they are regenerated on demand.
+pub fn clear_thumbs(state: &MediaState) {
+    if let Ok(mut usage) = state.thumbs_usage.lock() {
+        for (path, _, _) in cached_files(&state.thumbs_dir) {
+            let _ = std::fs::remove_file(path);
+        }
+        *usage = Some(dir_usage(&state.thumbs_dir).0);
+    }
+}
+
+/// Sizes of the on-disk media caches, for the options menu.
+#[derive(Clone, serde::Serialize)]
+pub struct MediaCacheStats {
+    pub thumbs_bytes: u64,
+    pub thumbs_files: usize,
+    pub thumbs_cap_bytes: u64,
+    pub preview_bytes: u64,
+    pub preview_files: usize,
+}
+
+pub fn media_cache_stats(state: &MediaState) -> MediaCacheStats {
+    let (thumbs_bytes, thumbs_files) = dir_usage(&state.thumbs_dir);
+    let (preview_bytes, preview_files) = dir_usage(&state.media_dir);
+    MediaCacheStats {
+        thumbs_bytes,
+        thumbs_files,
+        thumbs_cap_bytes: state.thumbs_cap_bytes.load(Ordering::Relaxed),
+        preview_bytes,
+        preview_files,
+    }
+}
+
+/// Change the `thumbs/` cap, evicting straight away if it's now over.
+pub fn set_thumbs_cap(state: &MediaState, cap_bytes: u64) {
+    state.thumbs_cap_bytes.store(cap_bytes, Ordering::Relaxed);
+    note_thumbs_written(state, 0);
+}
+
+/// Change the preview cache limits, evicting straight away down to the new ones.
+pub fn set_cache_config(state: &MediaState, config: MediaCacheConfig) {
+    if let Ok(mut cache) = state.cache.lock() {
+        cache.set_config(config);
+    }
+}
+
 /// Drain list of evicted SHA256s for frontend cache sync.
 pub fn drain_evictions(state: &MediaState) -> Vec<String> {
     if let Ok(mut cache) = state.cache.lock() {
@@ -235,13 +893,217 @@ pub fn drain_evictions(state: &MediaState) -> Vec<String> {
 
 // --- Helper functions ---
 
-fn decrypt_to_bytes(path: &Path, key: &MasterKey) -> Result<Vec<u8>, String> {
-    let mut reader = std::fs::File::open(path).map_err(|e| e.to_string())?;
-    let mut out: Vec<u8> = Vec::new();
-    crypto::decrypt_stream(&mut reader, &mut out, key).map_err(|e| e.to_string())?;
+/// Encrypts `bytes` to `dest` through a temp file in `thumbs/`, renamed into place.
+fn store_encrypted(state: &MediaState, bytes: &[u8], dest: &Path) -> Result<(), String> {
+    std::fs::create_dir_all(&state.thumbs_dir).map_err(|e| e.to_string())?;
+    let mut reader = std::io::Cursor::new(bytes);
+    let mut temp =
+        tempfile::NamedTempFile::new_in(&state.thumbs_dir).map_err(|e| e.to_string())?;
+    crypto::encrypt_stream(&mut reader, &mut temp, &state.key).map_err(|e| e.to_string())?;
+
+    // Atomic rename (handle race condition)
+    let written = temp.as_file().metadata().map(|meta| meta.len()).unwrap_or(0);
+    match temp.persist(dest) {
+        Ok(_) => note_thumbs_written(state, written),
+        Err(e) if e.error.kind() == std::io::ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(e.to_string()),
+    }
+    Ok(())
+}
+
+/// Decrypts a cached file from `thumbs/` if there is one, marking it recently used.
+fn read_cached(state: &MediaState, sha256: &str, path: &Path) -> Option<Result<Zeroizing<Vec<u8>>, String>> {
+    if !path.exists() {
+        return None;
+    }
+    // Eviction goes by mtime, so a hit counts as a use.
+    if let Ok(file) = std::fs::File::options().write(true).open(path) {
+        let _ = file.set_modified(SystemTime::now());
+    }
+    Some(decrypt_to_bytes(path, &state.key).map_err(|e| state.decrypt_error(sha256, path, e)))
+}
+
+/// Adds `bytes` to the `thumbs/` usage and, once it's over the cap, deletes the least
+/// recently used files until it fits again.
+fn note_thumbs_written(state: &MediaState, bytes: u64) {
+    let Ok(mut usage) = state.thumbs_usage.lock() else {
+        return;
+    };
+    let total = match *usage {
+        Some(total) => total + bytes,
+        None => dir_usage(&state.thumbs_dir).0,
+    };
+    let cap = state.thumbs_cap_bytes.load(Ordering::Relaxed);
+    if total <= cap {
+        *usage = Some(total);
+        return;
+    }
+    let mut files = cached_files(&state.thumbs_dir);
+    files.sort_by_key(|(_, _, modified)| *modified);
+    let mut remaining: u64 = files.iter().map(|(_, len, _)| len).sum();
+    for (path, len, _) in files {
+        if remaining <= cap {
+            break;
+        }
+        if std::fs::remove_file(&path).is_ok() {
+            remaining -= len;
+        }
+    }
+    *usage = Some(remaining);
+}
+
+/// Every file directly in `dir`, with its size and mtime. Temp files still being written
+/// (`tempfile`'s `.tmp` prefix) are left out.
+fn cached_files(dir: &Path) -> Vec<(PathBuf, u64, SystemTime)> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(Result::ok)
+        .filter(|entry| !entry.file_name().to_string_lossy().starts_with(".tmp"))
+        .filter_map(|entry| {
+            let meta = entry.metadata().ok().filter(|meta| meta.is_file())?;
+            Some((entry.path(), meta.len(), meta.modified().unwrap_or(SystemTime::UNIX_EPOCH)))
+        })
+        .collect()
+}
+
+/// Total bytes and file count directly in `dir`.
+fn dir_usage(dir: &Path) -> (u64, usize) {
+    let files = cached_files(dir);
+    (files.iter().map(|(_, len, _)| len).sum(), files.len())
+}
+
+/// Decodes the first audio track of `data`, returning the loudest absolute sample of every
+/// [`WAVEFORM_WINDOW_FRAMES`] frames across all channels. Packets that fail to decode are
+/// skipped, as a damaged stretch shouldn't cost the whole waveform.
+fn decode_window_peaks(data: Zeroizing<Vec<u8>>, mime: Option<&str>) -> Result<Vec<f32>, WaveformError> {
+    let unsupported = |e: SymphoniaError| match e {
+        SymphoniaError::Unsupported(what) => WaveformError::Unsupported(what.to_string()),
+        other => WaveformError::Failed(other.to_string()),
+    };
+    let mut hint = Hint::new();
+    if let Some(ext) = mime.and_then(mime_extension) {
+        hint.with_extension(ext);
+    }
+    let stream = MediaSourceStream::new(Box::new(std::io::Cursor::new(data)), Default::default());
+    let probed = symphonia::default::get_probe()
+        .format(&hint, stream, &FormatOptions::default(), &MetadataOptions::default())
+        .map_err(unsupported)?;
+    let mut format = probed.format;
+    let track = format
+        .tracks()
+        .iter()
+        .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or_else(|| WaveformError::Unsupported("no audio track".to_string()))?;
+    let track_id = track.id;
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .map_err(unsupported)?;
+
+    let mut windows = Vec::new();
+    let (mut peak, mut frames) = (0f32, 0usize);
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(SymphoniaError::ResetRequired) => break,
+            Err(e) => return Err(WaveformError::Failed(e.to_string())),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            Err(SymphoniaError::DecodeError(_)) => continue,
+            Err(e) => return Err(unsupported(e)),
+        };
+        let spec = *decoded.spec();
+        let channels = spec.channels.count().max(1);
+        let mut samples = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+        samples.copy_interleaved_ref(decoded);
+        for frame in samples.samples().chunks(channels) {
+            peak = frame.iter().fold(peak, |loudest, sample| loudest.max(sample.abs()));
+            frames += 1;
+            if frames == WAVEFORM_WINDOW_FRAMES {
+                windows.push(peak);
+                (peak, frames) = (0.0, 0);
+            }
+        }
+    }
+    if frames > 0 {
+        windows.push(peak);
+    }
+    Ok(windows)
+}
+
+/// Folds window peaks into `buckets` peaks scaled to 0-255. Audio shorter than `buckets`
+/// windows repeats windows rather than leaving gaps.
+fn bucket_peaks(windows: &[f32], buckets: usize) -> Vec<u8> {
+    (0..buckets)
+        .map(|idx| {
+            let start = idx * windows.len() / buckets;
+            let end = ((idx + 1) * windows.len() / buckets).max(start + 1);
+            let loudest = windows[start..end].iter().fold(0f32, |a, &b| a.max(b));
+            (loudest.min(1.0) * 255.0).round() as u8
+        })
+        .collect()
+}
+
+fn thumb_path(state: &MediaState, sha256: &str, max_size: u32) -> PathBuf {
+    state.thumbs_dir.join(format!("{}_{}.bin", sha256, max_size))
+}
+
+/// The first `ffmpeg` on `PATH` or in [`FFMPEG_DIRS`].
+fn find_ffmpeg() -> Option<PathBuf> {
+    let path_dirs = std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).collect::<Vec<_>>())
+        .unwrap_or_default();
+    path_dirs
+        .into_iter()
+        .chain(FFMPEG_DIRS.iter().map(PathBuf::from))
+        .map(|dir| dir.join("ffmpeg"))
+        .find(|candidate| candidate.is_file())
+}
+
+/// Grabs one frame of `video` as PNG on ffmpeg's stdout, so the frame never touches disk.
+/// Clips shorter than [`POSTER_FRAME_SECS`] have no frame there and use their first one.
+fn extract_poster_frame(ffmpeg: &Path, video: &Path) -> Result<Zeroizing<Vec<u8>>, String> {
+    for seek in [POSTER_FRAME_SECS, "0"] {
+        let output = Command::new(ffmpeg)
+            .args(["-v", "error", "-nostdin", "-ss", seek, "-i"])
+            .arg(video)
+            .args(["-frames:v", "1", "-f", "image2pipe", "-c:v", "png", "-"])
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| format!("ffmpeg failed to start: {}", e))?;
+        let frame = Zeroizing::new(output.stdout);
+        if output.status.success() && !frame.is_empty() {
+            return Ok(frame);
+        }
+    }
+    Err("no video frame could be decoded".to_string())
+}
+
+/// Decrypts `path` into memory. The buffer is sized up front so it never reallocates and
+/// leaves plaintext copies behind, and is wiped when dropped.
+fn decrypt_to_bytes(path: &Path, key: &MasterKey) -> Result<Zeroizing<Vec<u8>>, CoreError> {
+    let len = crypto::encrypted_plaintext_len(path)?;
+    let mut reader = std::fs::File::open(path).map_err(|e| CoreError::Crypto(e.to_string()))?;
+    let mut out = Zeroizing::new(Vec::with_capacity(len as usize));
+    crypto::decrypt_stream(&mut reader, &mut *out, key)?;
     Ok(out)
 }
 
+/// Base64-encodes `data` straight into the data URL, with no intermediate encoded copy.
+fn data_url(mime: &str, data: &[u8]) -> String {
+    let prefix = format!("data:{};base64,", mime);
+    let mut url = String::with_capacity(prefix.len() + data.len().div_ceil(3) * 4);
+    url.push_str(&prefix);
+    BASE64_STANDARD.encode_string(data, &mut url);
+    url
+}
+
 fn mime_extension(mime: &str) -> Option<&'static str> {
     match mime {
         "image/jpeg" => Some("jpg"),
@@ -264,9 +1126,30 @@ fn mime_extension(mime: &str) -> Option<&'static str> {
 
 // --- MediaCache ---
 
+/// How many decrypted previews [`MediaCache`] keeps, and for how long after their last use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MediaCacheConfig {
+    pub max_files: usize,
+    pub ttl: Duration,
+}
+
+impl Default for MediaCacheConfig {
+    fn default() -> Self {
+        Self {
+            max_files: DEFAULT_MAX_MEDIA_FILES,
+            ttl: Duration::from_secs(DEFAULT_MEDIA_TTL_SECS),
+        }
+    }
+}
+
 pub struct MediaCache {
     entries: HashMap<String, MediaCacheEntry>,
     evicted: Vec<String>,
+    config: MediaCacheConfig,
+    /// SHA256 of the item on screen, whose previews LRU eviction leaves alone.
+    pinned: Option<String>,
+    /// Bumped by [`MediaCache::clear`].
+    generation: u64,
 }
 
 struct MediaCacheEntry {
@@ -275,13 +1158,36 @@ struct MediaCacheEntry {
 }
 
 impl MediaCache {
-    pub fn new() -> Self {
+    pub fn new(config: MediaCacheConfig) -> Self {
         Self {
             entries: HashMap::new(),
             evicted: Vec::new(),
+            config,
+            pinned: None,
+            generation: 0,
         }
     }
 
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Exempts `sha256`'s previews from LRU eviction, replacing any earlier pin. The TTL
+    /// still applies, so a pin left behind doesn't keep a file forever.
+    pub fn pin(&mut self, sha256: Option<String>) {
+        self.pinned = sha256;
+    }
+
+    pub fn config(&self) -> MediaCacheConfig {
+        self.config
+    }
+
+    pub fn set_config(&mut self, config: MediaCacheConfig) {
+        self.config = config;
+        self.evict_expired();
+        self.evict_lru();
+    }
+
     pub fn get(&mut self, key: &str) -> Option<PathBuf> {
         self.evict_expired();
         if let Some(entry) = self.entries.get_mut(key) {
@@ -309,6 +1215,12 @@ impl MediaCache {
         }
         self.entries.clear();
         self.evicted.clear();
+        self.pinned = None;
+        self.generation += 1;
+    }
+
+    fn remove(&mut self, key: &str) {
+        self.entries.remove(key);
     }
 
     pub fn drain_evictions(&mut self) -> Vec<String> {
@@ -320,7 +1232,7 @@ impl MediaCache {
         let expired: Vec<String> = self
             .entries
             .iter()
-            .filter(|(_, entry)| now.duration_since(entry.last_access) > MEDIA_TTL)
+            .filter(|(_, entry)| now.duration_since(entry.last_access) > self.config.ttl)
             .map(|(key, _)| key.clone())
             .collect();
         for key in expired {
@@ -332,10 +1244,12 @@ impl MediaCache {
     }
 
     fn evict_lru(&mut self) {
-        while self.entries.len() > MAX_MEDIA_FILES {
+        while self.entries.len() > self.config.max_files {
+            let pinned = self.pinned.as_deref();
             let oldest = self
                 .entries
                 .iter()
+                .filter(|(key, _)| pinned.is_none_or(|sha| key.split(':').next() != Some(sha)))
                 .min_by_key(|(_, entry)| entry.last_access)
                 .map(|(key, entry)| (key.clone(), entry.path.clone()));
             if let Some((key, path)) = oldest {
@@ -359,7 +1273,7 @@ impl MediaCache {
 
 impl Default for MediaCache {
     fn default() -> Self {
-        Self::new()
+        Self::new(MediaCacheConfig::default())
     }
 }
 
@@ -369,14 +1283,588 @@ mod tests {
 
     #[test]
     fn media_cache_eviction() {
-        let mut cache = MediaCache::new();
-        for idx in 0..(MAX_MEDIA_FILES + 2) {
+        let mut cache = MediaCache::default();
+        for idx in 0..(DEFAULT_MAX_MEDIA_FILES + 2) {
             cache.insert(
                 format!("k{}", idx),
                 PathBuf::from(format!("/tmp/{}.bin", idx)),
             );
         }
-        assert!(cache.entries.len() <= MAX_MEDIA_FILES);
+        assert!(cache.entries.len() <= DEFAULT_MAX_MEDIA_FILES);
+    }
+
+    #[test]
+    fn lowering_the_cache_cap_evicts_immediately() {
+        let dir = tempfile::tempdir().expect("temp");
+        let mut cache = MediaCache::default();
+        let paths: Vec<PathBuf> = (0..6).map(|idx| dir.path().join(format!("{idx}.bin"))).collect();
+        for (idx, path) in paths.iter().enumerate() {
+            std::fs::write(path, b"preview").expect("write");
+            cache.insert(format!("sha{idx}:mp4"), path.clone());
+            std::thread::sleep(Duration::from_millis(2));
+        }
+        assert!(cache.get("sha0:mp4").is_some());
+
+        cache.set_config(MediaCacheConfig {
+            max_files: 2,
+            ..cache.config()
+        });
+        assert_eq!(cache.entries.len(), 2);
+        let mut evicted = cache.drain_evictions();
+        evicted.sort();
+        assert_eq!(evicted, vec!["sha1", "sha2", "sha3", "sha4"]);
+        assert!(paths[0].exists() && paths[5].exists());
+        assert!(paths[1..5].iter().all(|path| !path.exists()));
+
+        cache.set_config(MediaCacheConfig {
+            max_files: 2,
+            ttl: Duration::ZERO,
+        });
+        std::thread::sleep(Duration::from_millis(2));
+        assert!(cache.get("sha5:mp4").is_none());
+        assert!(cache.entries.is_empty());
+    }
+
+    #[test]
+    fn pinned_previews_survive_lru_eviction() {
+        let dir = tempfile::tempdir().expect("temp");
+        let mut cache = MediaCache::new(MediaCacheConfig {
+            max_files: 2,
+            ..MediaCacheConfig::default()
+        });
+        cache.pin(Some("viewed".to_string()));
+        for key in ["viewed:jpg", "next:jpg", "prev:jpg"] {
+            let path = dir.path().join(key.replace(':', "."));
+            std::fs::write(&path, b"preview").expect("write");
+            cache.insert(key.to_string(), path);
+            std::thread::sleep(Duration::from_millis(2));
+        }
+        assert_eq!(cache.drain_evictions(), vec!["next"]);
+        assert!(cache.get("viewed:jpg").is_some());
+
+        cache.pin(None);
+        cache.set_config(MediaCacheConfig {
+            max_files: 1,
+            ..cache.config()
+        });
+        assert_eq!(cache.drain_evictions(), vec!["prev"]);
+    }
+
+    #[test]
+    fn media_bytes_round_trip_through_data_urls() {
+        crypto::set_test_key_from_passphrase("golden-thread-tests");
+        let dir = tempfile::tempdir().expect("temp");
+        let state = MediaState::new(
+            crypto::load_or_create_master_key().expect("key"),
+            dir.path().join("attachments"),
+            dir.path().join("thumbs"),
+            dir.path().join("media"),
+            dir.path().join("logs"),
+        );
+        std::fs::create_dir_all(&state.attachments_dir).expect("attachments dir");
+
+        let mut png = Vec::new();
+        image::RgbaImage::from_pixel(4, 2, image::Rgba([200, 30, 30, 255]))
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .expect("png");
+        let mut sealed = std::fs::File::create(state.attachments_dir.join("abc")).expect("create");
+        crypto::encrypt_stream(&mut png.as_slice(), &mut sealed, &state.key).expect("encrypt");
+        drop(sealed);
+
+        let decrypted = decrypt_to_bytes(&state.attachments_dir.join("abc"), &state.key).expect("decrypt");
+        assert_eq!(decrypted.as_slice(), png.as_slice());
+
+        let preview = generate_data_url(&state, "abc", "image/png", 1024 * 1024).expect("data url");
+        assert_eq!((preview.width, preview.height, preview.downscaled), (Some(4), Some(2), false));
+        let encoded = preview.url.strip_prefix("data:image/png;base64,").expect("prefix");
+        assert_eq!(BASE64_STANDARD.decode(encoded).expect("base64"), png);
+
+        let thumb = generate_thumbnail(&state, "abc", 8).expect("thumbnail");
+        let encoded = thumb.strip_prefix("data:image/webp;base64,").expect("prefix");
+        let webp = image::load_from_memory(&BASE64_STANDARD.decode(encoded).expect("base64")).expect("webp");
+        assert_eq!((webp.width(), webp.height()), (8, 4));
+        // The second call decrypts the cached thumbnail instead of regenerating it.
+        assert_eq!(generate_thumbnail(&state, "abc", 8).expect("cached thumbnail"), thumb);
+    }
+
+    #[test]
+    fn large_images_are_downscaled_for_data_urls() {
+        crypto::set_test_key_from_passphrase("golden-thread-tests");
+        let dir = tempfile::tempdir().expect("temp");
+        let state = MediaState::new(
+            crypto::load_or_create_master_key().expect("key"),
+            dir.path().join("attachments"),
+            dir.path().join("thumbs"),
+            dir.path().join("media"),
+            dir.path().join("logs"),
+        );
+        std::fs::create_dir_all(&state.attachments_dir).expect("attachments dir");
+        // Noise doesn't compress, so this PNG is well over the downscale threshold.
+        let mut seed = 0x2545_f491_u32;
+        let noisy = image::RgbImage::from_fn(2400, 1200, |_, _| {
+            seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            image::Rgb([(seed >> 24) as u8, (seed >> 16) as u8, (seed >> 8) as u8])
+        });
+        let mut png = Vec::new();
+        noisy.write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png).expect("png");
+        assert!(png.len() > PREVIEW_DOWNSCALE_BYTES);
+        let mut sealed = std::fs::File::create(state.attachments_dir.join("big")).expect("create");
+        crypto::encrypt_stream(&mut png.as_slice(), &mut sealed, &state.key).expect("encrypt");
+        drop(sealed);
+
+        let preview = generate_data_url(&state, "big", "image/png", 64 * 1024 * 1024).expect("data url");
+        assert_eq!((preview.width, preview.height, preview.downscaled), (Some(2048), Some(1024), true));
+        let encoded = preview.url.strip_prefix("data:image/jpeg;base64,").expect("re-encoded as jpeg");
+        let jpeg = image::load_from_memory(&BASE64_STANDARD.decode(encoded).expect("base64")).expect("jpeg");
+        assert_eq!((jpeg.width(), jpeg.height()), (2048, 1024));
+    }
+
+    #[test]
+    fn data_url_limit_applies_to_the_plaintext() {
+        crypto::set_test_key_from_passphrase("golden-thread-tests");
+        let dir = tempfile::tempdir().expect("temp");
+        let state = MediaState::new(
+            crypto::load_or_create_master_key().expect("key"),
+            dir.path().join("attachments"),
+            dir.path().join("thumbs"),
+            dir.path().join("media"),
+            dir.path().join("logs"),
+        );
+        std::fs::create_dir_all(&state.attachments_dir).expect("attachments dir");
+        let mut sealed = std::fs::File::create(state.attachments_dir.join("note")).expect("create");
+        crypto::encrypt_stream(&mut &[b'a'; 1000][..], &mut sealed, &state.key).expect("encrypt");
+        drop(sealed);
+        assert!(std::fs::metadata(state.attachments_dir.join("note")).expect("meta").len() > 1000);
+
+        // Exactly at the limit passes, though the blob on disk is bigger.
+        let preview = generate_data_url(&state, "note", "text/plain", 1000).expect("at the limit");
+        assert_eq!(preview.url, format!("data:text/plain;base64,{}", BASE64_STANDARD.encode([b'a'; 1000])));
+        let err = generate_data_url(&state, "note", "text/plain", 999).expect_err("over the limit");
+        assert_eq!(err, DataUrlError::TooLarge { size: 1000, limit: 999 });
+        assert_eq!(
+            serde_json::to_value(&err).expect("json"),
+            serde_json::json!({ "kind": "too_large", "size": 1000, "limit": 999 })
+        );
+    }
+
+    #[test]
+    fn decrypt_failures_are_logged_with_the_sha256_and_chunk() {
+        crypto::set_test_key_from_passphrase("golden-thread-tests");
+        let dir = tempfile::tempdir().expect("temp");
+        let state = MediaState::new(
+            crypto::load_or_create_master_key().expect("key"),
+            dir.path().join("attachments"),
+            dir.path().join("thumbs"),
+            dir.path().join("media"),
+            dir.path().join("logs"),
+        );
+        std::fs::create_dir_all(&state.attachments_dir).expect("attachments dir");
+        let sha256 = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+        let path = state.attachments_dir.join(sha256);
+        let mut sealed = Vec::new();
+        crypto::encrypt_stream(&mut &[1u8; 100][..], &mut sealed, &state.key).expect("encrypt");
+        let last = sealed.len() - 1;
+        sealed[last] ^= 1;
+        std::fs::write(&path, &sealed).expect("write");
+
+        let err = generate_data_url(&state, sha256, "image/png", 1024 * 1024).expect_err("damaged").to_string();
+        assert!(err.starts_with("crypto error: decrypt failed at chunk 0, byte offset "), "{err}");
+        assert!(err.ends_with(&format!(" in {}", path.display())));
+        let log = std::fs::read_to_string(dir.path().join("logs").join("diagnostics.log")).expect("log");
+        assert!(log.contains("decrypt_error"));
+        assert!(log.contains(&diagnostics::group_hex(sha256)));
+        assert!(log.contains("format 4: crypto error: decrypt failed at chunk 0"));
+    }
+
+    #[test]
+    fn undecodable_videos_fail_without_leaving_plaintext_behind() {
+        crypto::set_test_key_from_passphrase("golden-thread-tests");
+        let dir = tempfile::tempdir().expect("temp");
+        let state = MediaState::new(
+            crypto::load_or_create_master_key().expect("key"),
+            dir.path().join("attachments"),
+            dir.path().join("thumbs"),
+            dir.path().join("media"),
+            dir.path().join("logs"),
+        );
+        std::fs::create_dir_all(&state.attachments_dir).expect("attachments dir");
+        let mut sealed = std::fs::File::create(state.attachments_dir.join("vid")).expect("create");
+        crypto::encrypt_stream(&mut &b"not really a video"[..], &mut sealed, &state.key).expect("encrypt");
+        drop(sealed);
+
+        // Without ffmpeg this fails for the missing decoder, with it for the bad video.
+        let err = generate_video_thumbnail(&state, "vid", Some("video/mp4"), 64).expect_err("no frame");
+        assert!(err.contains("ffmpeg") || err.contains("no video frame"), "{err}");
+        let leftovers = std::fs::read_dir(&state.media_dir).map(|dir| dir.count()).unwrap_or(0);
+        assert_eq!(leftovers, 0);
+        assert!(!thumb_path(&state, "vid", 64).exists());
+    }
+
+    #[test]
+    fn prefetch_warms_previews_in_the_background() {
+        crypto::set_test_key_from_passphrase("golden-thread-tests");
+        let dir = tempfile::tempdir().expect("temp");
+        let state = Arc::new(MediaState::new(
+            crypto::load_or_create_master_key().expect("key"),
+            dir.path().join("attachments"),
+            dir.path().join("thumbs"),
+            dir.path().join("media"),
+            dir.path().join("logs"),
+        ));
+        std::fs::create_dir_all(&state.attachments_dir).expect("attachments dir");
+        for sha in ["next", "prev"] {
+            let mut sealed = std::fs::File::create(state.attachments_dir.join(sha)).expect("create");
+            crypto::encrypt_stream(&mut sha.as_bytes(), &mut sealed, &state.key).expect("encrypt");
+        }
+        let request = |sha256: &str| PrefetchRequest {
+            sha256: sha256.to_string(),
+            mime: Some("image/jpeg".to_string()),
+        };
+
+        prefetch(&state, Some("viewed"), vec![request("next"), request("missing"), request("prev")]);
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while state.cache.lock().expect("cache").get("prev:jpg").is_none() {
+            assert!(Instant::now() < deadline, "prefetch never finished");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(std::fs::read(state.media_dir.join("next.jpg")).expect("next"), b"next");
+        let mut cache = state.cache.lock().expect("cache");
+        assert!(cache.get("next:jpg").is_some());
+        assert_eq!(cache.pinned.as_deref(), Some("viewed"));
+    }
+
+    #[test]
+    fn cancelled_previews_stop_without_leaving_plaintext() {
+        crypto::set_test_key_from_passphrase("golden-thread-tests");
+        let dir = tempfile::tempdir().expect("temp");
+        let state = Arc::new(MediaState::new(
+            crypto::load_or_create_master_key().expect("key"),
+            dir.path().join("attachments"),
+            dir.path().join("thumbs"),
+            dir.path().join("media"),
+            dir.path().join("logs"),
+        ));
+        std::fs::create_dir_all(&state.attachments_dir).expect("attachments dir");
+        let mut sealed = std::fs::File::create(state.attachments_dir.join("big")).expect("create");
+        crypto::encrypt_stream_chunk(&mut &vec![7u8; 256 * 1024][..], &mut sealed, &state.key, 64 * 1024)
+            .expect("encrypt");
+        drop(sealed);
+
+        let request = track_request(&state, "path-1".to_string());
+        let cancel_midway = |done, _| {
+            if done > 0 {
+                assert!(cancel_request(&state, "path-1"));
+            }
+        };
+        let err = decrypt_to_preview(&state, "big", Some("video/mp4"), request.cancel_flag(), cancel_midway)
+            .expect_err("cancelled");
+        assert_eq!(err, "cancelled");
+        assert_eq!(std::fs::read_dir(&state.media_dir).expect("media dir").count(), 0);
+        assert!(state.cache.lock().expect("cache").get("big:mp4").is_none());
+        // Cancelling isn't a decrypt failure, so nothing is logged.
+        assert!(!state.log_dir.exists());
+
+        drop(request);
+        assert!(!cancel_request(&state, "path-1"));
+    }
+
+    #[test]
+    fn slow_preview_decrypts_do_not_hold_up_thumbnails() {
+        crypto::set_test_key_from_passphrase("golden-thread-tests");
+        let dir = tempfile::tempdir().expect("temp");
+        let state = MediaState::new(
+            crypto::load_or_create_master_key().expect("key"),
+            dir.path().join("attachments"),
+            dir.path().join("thumbs"),
+            dir.path().join("media"),
+            dir.path().join("logs"),
+        );
+        std::fs::create_dir_all(&state.attachments_dir).expect("attachments dir");
+        let mut sealed = std::fs::File::create(state.attachments_dir.join("big")).expect("create");
+        crypto::encrypt_stream(&mut &vec![7u8; 2 * 1024 * 1024][..], &mut sealed, &state.key).expect("encrypt");
+        drop(sealed);
+        let mut png = Vec::new();
+        image::RgbaImage::from_pixel(4, 4, image::Rgba([0, 0, 200, 255]))
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .expect("png");
+        let mut sealed = std::fs::File::create(state.attachments_dir.join("small")).expect("create");
+        crypto::encrypt_stream(&mut png.as_slice(), &mut sealed, &state.key).expect("encrypt");
+        drop(sealed);
+
+        // The preview decrypt stalls on its first progress report until the thumbnail is done,
+        // so it is mid-flight the whole time the thumbnail is generated.
+        let (thumb_done, thumb_done_rx) = std::sync::mpsc::channel::<()>();
+        let thumb_done_rx = Mutex::new(thumb_done_rx);
+        std::thread::scope(|scope| {
+            let preview = scope.spawn(|| {
+                let waited = std::sync::OnceLock::new();
+                let path = decrypt_to_preview(&state, "big", Some("video/mp4"), &AtomicBool::new(false), |_, _| {
+                    waited.get_or_init(|| {
+                        let rx = thumb_done_rx.lock().expect("receiver");
+                        rx.recv_timeout(std::time::Duration::from_secs(10)).is_ok()
+                    });
+                });
+                (path, waited.get().copied())
+            });
+            generate_thumbnail(&state, "small", 4).expect("thumbnail");
+            thumb_done.send(()).expect("send");
+            let (path, waited) = preview.join().expect("preview thread");
+            assert!(path.is_ok());
+            assert_eq!(waited, Some(true), "the thumbnail waited for the preview");
+        });
+    }
+
+    #[test]
+    fn thumbnail_batches_report_failures_per_item() {
+        crypto::set_test_key_from_passphrase("golden-thread-tests");
+        let dir = tempfile::tempdir().expect("temp");
+        let state = MediaState::new(
+            crypto::load_or_create_master_key().expect("key"),
+            dir.path().join("attachments"),
+            dir.path().join("thumbs"),
+            dir.path().join("media"),
+            dir.path().join("logs"),
+        );
+        std::fs::create_dir_all(&state.attachments_dir).expect("attachments dir");
+        let mut png = Vec::new();
+        image::RgbaImage::from_pixel(8, 8, image::Rgba([10, 120, 10, 255]))
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .expect("png");
+        let mut sealed = std::fs::File::create(state.attachments_dir.join("pic")).expect("create");
+        crypto::encrypt_stream(&mut png.as_slice(), &mut sealed, &state.key).expect("encrypt");
+        drop(sealed);
+
+        let request = |sha256: &str, mime: &str, max_size: u32| ThumbRequest {
+            sha256: sha256.to_string(),
+            mime: Some(mime.to_string()),
+            max_size,
+        };
+        let requests = [
+            request("pic", "image/png", 4),
+            request("pic", "image/png", 8),
+            request("gone", "image/jpeg", 4),
+            request("pic", "audio/mpeg", 16),
+        ];
+        let results = generate_thumbnails(&state, &requests);
+        assert_eq!(results.len(), 4);
+        assert_eq!(results["pic:4"], generate_thumbnail(&state, "pic", 4).into());
+        assert!(results["pic:8"].url.as_deref().is_some_and(|url| url.starts_with("data:image/webp;base64,")));
+        assert_eq!(results["gone:4"].error.as_deref(), Some("attachment missing"));
+        assert_eq!(results["pic:16"].error.as_deref(), Some("thumbnail only for images and videos"));
+        assert!(generate_thumbnails(&state, &[]).is_empty());
+    }
+
+    #[test]
+    fn thumbs_over_the_cap_evict_least_recently_used_first() {
+        crypto::set_test_key_from_passphrase("golden-thread-tests");
+        let dir = tempfile::tempdir().expect("temp");
+        let state = MediaState::new(
+            crypto::load_or_create_master_key().expect("key"),
+            dir.path().join("attachments"),
+            dir.path().join("thumbs"),
+            dir.path().join("media"),
+            dir.path().join("logs"),
+        );
+        std::fs::create_dir_all(&state.attachments_dir).expect("attachments dir");
+        let mut png = Vec::new();
+        image::RgbaImage::from_pixel(64, 64, image::Rgba([90, 40, 160, 255]))
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .expect("png");
+        let mut sealed = std::fs::File::create(state.attachments_dir.join("pic")).expect("create");
+        crypto::encrypt_stream(&mut png.as_slice(), &mut sealed, &state.key).expect("encrypt");
+        drop(sealed);
+
+        // Three thumbnails an hour apart, then the oldest is viewed again.
+        let hour = Duration::from_secs(3600);
+        let base = SystemTime::now() - 4 * hour;
+        for (idx, size) in [8u32, 16, 32].into_iter().enumerate() {
+            generate_thumbnail(&state, "pic", size).expect("thumbnail");
+            let file = std::fs::File::options().write(true).open(thumb_path(&state, "pic", size)).expect("open");
+            file.set_modified(base + hour * idx as u32).expect("mtime");
+        }
+        generate_thumbnail(&state, "pic", 8).expect("cached thumbnail");
+        let stats = media_cache_stats(&state);
+        assert_eq!((stats.thumbs_files, stats.thumbs_cap_bytes), (3, DEFAULT_THUMBS_CAP_BYTES));
+
+        // Room for two: the 16px thumbnail is now the least recently used.
+        let len = |size| std::fs::metadata(thumb_path(&state, "pic", size)).expect("meta").len();
+        let cap = len(8) + len(32);
+        set_thumbs_cap(&state, cap);
+        assert!(thumb_path(&state, "pic", 8).exists());
+        assert!(!thumb_path(&state, "pic", 16).exists());
+        assert!(thumb_path(&state, "pic", 32).exists());
+
+        // Each new write evicts again, and clearing empties the directory.
+        generate_thumbnail(&state, "pic", 24).expect("thumbnail");
+        assert!(media_cache_stats(&state).thumbs_bytes <= cap);
+        clear_thumbs(&state);
+        assert_eq!(media_cache_stats(&state).thumbs_files, 0);
+        assert!(generate_thumbnail(&state, "pic", 8).is_ok());
+    }
+
+    const TINY_HEIC: &[u8] = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tiny.heic"));
+
+    /// A `MediaState` holding the 32x16 HEIC fixture as attachment `heic`.
+    fn heic_state(dir: &Path) -> MediaState {
+        crypto::set_test_key_from_passphrase("golden-thread-tests");
+        let state = MediaState::new(
+            crypto::load_or_create_master_key().expect("key"),
+            dir.join("attachments"),
+            dir.join("thumbs"),
+            dir.join("media"),
+            dir.join("logs"),
+        );
+        std::fs::create_dir_all(&state.attachments_dir).expect("attachments dir");
+        let mut sealed = std::fs::File::create(state.attachments_dir.join("heic")).expect("create");
+        crypto::encrypt_stream(&mut &TINY_HEIC[..], &mut sealed, &state.key).expect("encrypt");
+        state
+    }
+
+    #[test]
+    fn heif_containers_are_sniffed_by_brand() {
+        assert!(is_heif(TINY_HEIC));
+        assert!(!is_heif(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"));
+        assert!(!is_heif(b"\0\0\0\x18ftypisom\0\0\x02\0"));
+        assert!(!is_heif(b"ftyp"));
+    }
+
+    #[cfg(not(feature = "heic"))]
+    #[test]
+    fn heic_without_the_feature_is_a_typed_error() {
+        let dir = tempfile::tempdir().expect("temp");
+        let state = heic_state(dir.path());
+        assert!(matches!(decode_image(TINY_HEIC), Err(ImageDecodeError::HeicUnsupported(_))));
+        let err = generate_thumbnail(&state, "heic", 16).expect_err("no decoder");
+        assert_eq!(err, "HEIC images are not supported: built without the `heic` feature");
+        assert!(generate_data_url(&state, "heic", "image/heic", 1024 * 1024).is_err());
+        assert!(!thumb_path(&state, "heic", 16).exists());
+    }
+
+    #[cfg(feature = "heic")]
+    #[test]
+    fn heic_photos_get_thumbnails_and_jpeg_previews() {
+        let dir = tempfile::tempdir().expect("temp");
+        let state = heic_state(dir.path());
+        let thumb = generate_thumbnail(&state, "heic", 16).expect("thumbnail");
+        let encoded = thumb.strip_prefix("data:image/webp;base64,").expect("prefix");
+        let webp = image::load_from_memory(&BASE64_STANDARD.decode(encoded).expect("base64")).expect("webp");
+        assert_eq!((webp.width(), webp.height()), (16, 8));
+
+        let preview = generate_data_url(&state, "heic", "image/heic", 1024 * 1024).expect("data url");
+        assert_eq!((preview.width, preview.height, preview.downscaled), (Some(32), Some(16), false));
+        let encoded = preview.url.strip_prefix("data:image/jpeg;base64,").expect("converted to jpeg");
+        let jpeg = image::load_from_memory(&BASE64_STANDARD.decode(encoded).expect("base64")).expect("jpeg");
+        assert_eq!((jpeg.width(), jpeg.height()), (32, 16));
+        // The fixture is a flat orange; lossy coding only nudges it.
+        let pixel = jpeg.to_rgb8().get_pixel(16, 8).0;
+        assert!(pixel[0] > 180 && pixel[1] < 90 && pixel[2] < 60, "{pixel:?}");
+    }
+
+    #[test]
+    fn ranges_decrypt_only_the_requested_bytes() {
+        crypto::set_test_key_from_passphrase("golden-thread-tests");
+        let dir = tempfile::tempdir().expect("temp");
+        let state = MediaState::new(
+            crypto::load_or_create_master_key().expect("key"),
+            dir.path().join("attachments"),
+            dir.path().join("thumbs"),
+            dir.path().join("media"),
+            dir.path().join("logs"),
+        );
+        std::fs::create_dir_all(&state.attachments_dir).expect("attachments dir");
+        let data: Vec<u8> = (0..100u8).collect();
+        let mut sealed = std::fs::File::create(state.attachments_dir.join("clip")).expect("create");
+        crypto::encrypt_stream_chunk(&mut data.as_slice(), &mut sealed, &state.key, 16).expect("encrypt");
+        drop(sealed);
+
+        let whole = read_range(&state, "clip", None).expect("whole");
+        assert_eq!((whole.status, whole.content_range()), (200, None));
+        assert_eq!(whole.body.as_slice(), data.as_slice());
+
+        let span = read_range(&state, "clip", Some("bytes=10-29")).expect("span");
+        assert_eq!((span.status, span.content_range().as_deref()), (206, Some("bytes 10-29/100")));
+        assert_eq!(span.body.as_slice(), &data[10..30]);
+
+        let open = read_range(&state, "clip", Some("bytes=90-")).expect("open ended");
+        assert_eq!(open.content_range().as_deref(), Some("bytes 90-99/100"));
+        let suffix = read_range(&state, "clip", Some("bytes=-5")).expect("suffix");
+        assert_eq!(suffix.body.as_slice(), &data[95..]);
+
+        let past_end = read_range(&state, "clip", Some("bytes=100-")).expect("unsatisfiable");
+        assert_eq!((past_end.status, past_end.content_range().as_deref()), (416, Some("bytes */100")));
+        assert!(read_range(&state, "missing", None).is_err());
+    }
+
+    #[test]
+    fn malformed_and_multiple_ranges_are_rejected() {
+        assert_eq!(parse_range("bytes=0-0", 10), Some((0, 1)));
+        assert_eq!(parse_range("bytes=5-500", 10), Some((5, 10)));
+        assert_eq!(parse_range("bytes=-50", 10), Some((0, 10)));
+        for header in ["bytes=4-2", "bytes=-0", "bytes=a-b", "items=0-1", "bytes=0-1,4-5", "bytes=10-"] {
+            assert_eq!(parse_range(header, 10), None, "{header}");
+        }
+    }
+
+    /// A mono 16-bit WAV whose amplitude ramps from silence to full scale.
+    fn ramp_wav(frames: u32) -> Vec<u8> {
+        let data_len = frames * 2;
+        let mut wav = Vec::new();
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        wav.extend_from_slice(&1u16.to_le_bytes()); // mono
+        wav.extend_from_slice(&8000u32.to_le_bytes());
+        wav.extend_from_slice(&16000u32.to_le_bytes());
+        wav.extend_from_slice(&2u16.to_le_bytes());
+        wav.extend_from_slice(&16u16.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&data_len.to_le_bytes());
+        for idx in 0..frames {
+            let amplitude = (idx as f32 / frames as f32) * i16::MAX as f32;
+            let sample = if idx % 2 == 0 { amplitude } else { -amplitude };
+            wav.extend_from_slice(&(sample as i16).to_le_bytes());
+        }
+        wav
+    }
+
+    #[test]
+    fn waveform_peaks_follow_the_audio_and_are_cached() {
+        crypto::set_test_key_from_passphrase("golden-thread-tests");
+        let dir = tempfile::tempdir().expect("temp");
+        let state = MediaState::new(
+            crypto::load_or_create_master_key().expect("key"),
+            dir.path().join("attachments"),
+            dir.path().join("thumbs"),
+            dir.path().join("media"),
+            dir.path().join("logs"),
+        );
+        std::fs::create_dir_all(&state.attachments_dir).expect("attachments dir");
+        let wav = ramp_wav(8000);
+        let mut sealed = std::fs::File::create(state.attachments_dir.join("voice")).expect("create");
+        crypto::encrypt_stream(&mut wav.as_slice(), &mut sealed, &state.key).expect("encrypt");
+        drop(sealed);
+
+        let peaks = generate_waveform(&state, "voice", Some("audio/wav"), 16).expect("waveform");
+        assert_eq!(peaks.len(), 16);
+        assert!(peaks.windows(2).all(|pair| pair[0] <= pair[1]), "{peaks:?}");
+        assert!(peaks[0] < 32 && peaks[15] > 240, "{peaks:?}");
+        assert!(state.thumbs_dir.join("voice_wave_16.bin").exists());
+        assert_eq!(generate_waveform(&state, "voice", Some("audio/wav"), 16).expect("cached"), peaks);
+        assert!(matches!(generate_waveform(&state, "voice", None, 0), Err(WaveformError::Failed(_))));
+
+        let mut sealed = std::fs::File::create(state.attachments_dir.join("noise")).expect("create");
+        crypto::encrypt_stream(&mut &[7u8; 512][..], &mut sealed, &state.key).expect("encrypt");
+        drop(sealed);
+        let err = generate_waveform(&state, "noise", None, 16).expect_err("not audio");
+        assert!(matches!(err, WaveformError::Unsupported(_)), "{err}");
+    }
+
+    #[test]
+    fn bucket_peaks_spread_short_audio_across_every_bucket() {
+        assert_eq!(bucket_peaks(&[0.0, 1.0], 4), vec![0, 0, 255, 255]);
+        assert_eq!(bucket_peaks(&[0.2, 0.4, 0.6, 0.8, 1.5, 0.0], 3), vec![102, 204, 255]);
     }
 
     #[test]
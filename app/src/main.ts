@@ -8,7 +8,10 @@ import flatpickr from "flatpickr";
 import "flatpickr/dist/flatpickr.min.css";
 import type {
   AttachmentRow,
+  DataUrlResponse,
+  ImportProgress,
   MediaAsset,
+  MediaProgress,
   MessageRow,
   MessageTags,
   ReactionSummary,
@@ -21,14 +24,21 @@ import type {
 } from "./ui/types";
 import {
   attachmentDataUrl,
+  prefetchMedia,
   attachmentPath,
   attachmentThumbnail,
+  attachmentThumbnails,
+  attachmentWaveform,
+  addMessageTag as apiAddMessageTag,
   createTag as apiCreateTag,
   deleteTag as apiDeleteTag,
   getDiagnostics as apiGetDiagnostics,
   getMessageTags as apiGetMessageTags,
   getMessageTagsBulk as apiGetMessageTagsBulk,
   importBackup as apiImportBackup,
+  inspectBackup as apiInspectBackup,
+  listImportIssues as apiListImportIssues,
+  listImports as apiListImports,
   listMessageAttachments as apiListMessageAttachments,
   listMessageReactions as apiListMessageReactions,
   listMessages as apiListMessages,
@@ -38,12 +48,17 @@ import {
   listTags as apiListTags,
   listThreadMedia as apiListThreadMedia,
   listThreads as apiListThreads,
+  lockArchive as apiLockArchive,
+  removeMessageTag as apiRemoveMessageTag,
   clearMediaCache as apiClearMediaCache,
+  mediaCacheStats,
+  setThumbsCacheCap,
+  setMediaCacheConfig,
   drainMediaEvictions as apiDrainMediaEvictions,
   resetArchive as apiResetArchive,
   searchMessages as apiSearchMessages,
   seedDemo as apiSeedDemo,
-  setMessageTags as apiSetMessageTags,
+  unlockArchive as apiUnlockArchive,
 } from "./ui/api";
 import { getDom } from "./ui/dom";
 import {
@@ -53,13 +68,24 @@ import {
   ATTACHMENT_LIST_CACHE_MAX,
   ATTACHMENT_THUMB_CACHE_MAX_BYTES,
   GALLERY_PAGE,
+  IDLE_LOCK_CHECK_MS,
   PAGE_SIZE,
+  WAVEFORM_BUCKETS,
+  WAVEFORM_CACHE_MAX,
+  DEFAULT_THUMBS_CAP_MB,
+  DEFAULT_PREVIEW_FILES,
+  DEFAULT_PREVIEW_TTL_SECS,
+  DEFAULT_MEDIA_PATH_TIMEOUT_MS,
+  MEDIA_PATH_TIMEOUT_MS,
 } from "./ui/constants";
+import { blurhashDataUrl } from "./ui/blurhash";
 import { LruCache, WeightedLruCache } from "./ui/cache";
 import {
   createMediaPlaceholder,
   debounce,
   escapeHtml,
+  formatExpireTimer,
+  formatImportProgress,
   highlightBody,
   mediaSortTs,
   messageSortTs,
@@ -76,11 +102,13 @@ const {
   messageList,
   importBtn,
   importPanel,
+  importHistory,
   chooseFileBtn,
   importFileEl,
   passphraseInput,
   passphraseCount,
   runImportBtn,
+  inspectBackupBtn,
   seedBtn,
   resetBtn,
   copyDiagBtn,
@@ -99,6 +127,13 @@ const {
   optionsMenu,
   mediaToggle,
   darkModeToggle,
+  idleLockSelect,
+  thumbsCapSelect,
+  previewFilesSelect,
+  previewTtlSelect,
+  mediaCacheStatsEl,
+  clearThumbsBtn,
+  lockBtn,
   tabMessages,
   tabGallery,
   messagesView,
@@ -110,6 +145,7 @@ const {
   galleryFromClear,
   galleryToClear,
   gallerySize,
+  galleryKind,
   gallerySort,
   tabScrapbook,
   scrapbookView,
@@ -122,9 +158,19 @@ const {
   tagColorPresets,
   createTagBtn,
   tagList,
+  lockModal,
+  lockPassphrase,
+  unlockBtn,
+  lockStatus,
 } = getDom();
 let selectedBackupPath: string | null = null;
 let isBusy = false;
+let isLocked = false;
+let idleLockMinutes = Number(localStorage.getItem("gt_idle_lock_minutes") || "0");
+let thumbsCapMb = Number(localStorage.getItem("gt_thumbs_cap_mb") || String(DEFAULT_THUMBS_CAP_MB));
+let previewFiles = Number(localStorage.getItem("gt_preview_files") || String(DEFAULT_PREVIEW_FILES));
+let previewTtlSecs = Number(localStorage.getItem("gt_preview_ttl_secs") || String(DEFAULT_PREVIEW_TTL_SECS));
+let lastActivityAt = Date.now();
 let currentThreadId: string | null = null;
 let currentBeforeTs: number | null = null;
 let currentBeforeId: string | null = null;
@@ -140,8 +186,10 @@ let viewportAnchor: { id: string; offset: number } | null = null;
 let anchorScheduled = false;
 let isSearchJumping = false;
 const attachmentCache = new LruCache<string, AttachmentRow[]>(ATTACHMENT_LIST_CACHE_MAX);
-const attachmentDataCache = new LruCache<string, string>(ATTACHMENT_DATA_URL_CACHE_MAX);
+const attachmentDataCache = new LruCache<string, DataUrlResponse>(ATTACHMENT_DATA_URL_CACHE_MAX);
 const attachmentFileCache = new LruCache<string, string>(ATTACHMENT_FILE_URL_CACHE_MAX);
+// null marks audio the backend can't decode, so it isn't asked again.
+const waveformCache = new LruCache<string, number[] | null>(WAVEFORM_CACHE_MAX);
 const attachmentThumbCache = new WeightedLruCache<string, string>(
   ATTACHMENT_THUMB_CACHE_MAX_BYTES,
   (value) => value.length,
@@ -169,10 +217,18 @@ let galleryLoading = false;
 let galleryHasMore = true;
 const galleryFilterReload = debounce(() => loadGallery(true), 200);
 const THUMB_CONCURRENCY = 4;
+const THUMB_BATCH_MAX = 32;
+const GALLERY_THUMB_SIZE = 320;
 const LARGE_MEDIA_BYTES = 10 * 1024 * 1024;
+const LIGHTBOX_PREFETCH_RADIUS = 1;
+const VIDEO_POSTER_SIZE = 320;
+// BlurHash placeholders only carry a 4x3 grid of colours; a small canvas, scaled up, is enough.
+const BLURHASH_SIZE = 32;
 let currentPane: "messages" | "search" | "gallery" | "scrapbook" = "messages";
 let thumbInFlight = 0;
 const thumbQueue: Array<() => void> = [];
+let thumbBatch: Array<{ asset: MediaAsset; resolve: (src: string | null) => void }> = [];
+let thumbBatchTimer: number | null = null;
 const threadFilterReload = debounce(() => applyThreadFilters(), 200);
 const searchDebounced = debounce(() => runSearch(), 250);
 let tagsStore: Tag[] = [];
@@ -180,6 +236,7 @@ let messageTagsCache = new Map<string, Tag[]>();
 let currentScrapbookTagId: string | null = null;
 let scrapbookBeforeTs: number | null = null;
 let scrapbookBeforeId: string | null = null;
+let scrapbookHasMore = true;
 let scrapbookMessages: ScrapbookMessage[] = [];
 let isLoadingScrapbook = false;
 const scrapbookScrollPositions = new Map<string, number>();
@@ -192,6 +249,7 @@ let resetConfirmState: "initial" | "confirming" = "initial";
 let resetConfirmTimeout: number | null = null;
 let deleteTagConfirmId: string | null = null;
 let deleteTagConfirmTimeout: number | null = null;
+let deleteTagForceId: string | null = null;
 let selectedTagColor = "#c3684a";
 let activeThreadId: string | null = null;
 let activeThreadEl: HTMLLIElement | null = null;
@@ -213,8 +271,17 @@ if (!isTauri && statusEl) {
   statusEl.textContent = "Tauri runtime not available. Open via `npm run tauri dev`.";
 }
 if (isTauri) {
-  listen<string>("import_status", (event) => {
-    if (statusEl) statusEl.textContent = event.payload;
+  listen<ImportProgress>("import_status", (event) => {
+    if (statusEl) statusEl.textContent = formatImportProgress(event.payload);
+  }).catch(() => {});
+  listen<MediaProgress>("media_progress", (event) => {
+    const { sha256, done, total } = event.payload;
+    const percent = total > 0 ? Math.floor((done / total) * 100) : 0;
+    document
+      .querySelectorAll<HTMLElement>(`.media-placeholder.loading[data-sha256="${sha256}"] .media-placeholder-text`)
+      .forEach((el) => {
+        el.textContent = `LOADING... ${percent}%`;
+      });
   }).catch(() => {});
 }
 
@@ -252,7 +319,9 @@ function runGalleryThumbTask(key: string, task: () => Promise<void>) {
         if (next) next();
       });
   };
-  if (galleryThumbInFlight < THUMB_CONCURRENCY) {
+  // Gallery tasks only queue into thumbnail batches, so a full batch can be in flight; the
+  // batches themselves share THUMB_CONCURRENCY with other thumbnail requests.
+  if (galleryThumbInFlight < THUMB_BATCH_MAX) {
     run();
   } else {
     // LIFO bias to prioritize most-recently-viewed items.
@@ -285,7 +354,7 @@ document.querySelectorAll(".color-swatch").forEach(swatch => {
 function setBusy(state: boolean, message?: string) {
   isBusy = state;
   const disabled = state;
-  [importBtn, chooseFileBtn, runImportBtn, seedBtn, resetBtn, passphraseInput].forEach((el) => {
+  [importBtn, chooseFileBtn, runImportBtn, inspectBackupBtn, seedBtn, resetBtn, passphraseInput].forEach((el) => {
     if (!el) return;
     (el as HTMLButtonElement | HTMLInputElement).disabled = disabled;
   });
@@ -415,7 +484,8 @@ function renderThreadList(threads: ThreadSummary[]) {
   const currentActiveId = activeThreadId ?? currentThreadId;
   threads.forEach((thread, idx) => {
     const li = document.createElement("li");
-    li.textContent = `${thread.name ?? "(unnamed)"} · ${thread.message_count} messages`;
+    const timer = formatExpireTimer(thread.expire_timer_ms);
+    li.textContent = `${thread.name ?? "(unnamed)"} · ${thread.message_count} messages${timer ? ` · ⏱ ${timer}` : ""}`;
     li.dataset.threadId = thread.id;
     if (idx === 0 && !currentThreadId) {
       li.classList.add("active");
@@ -473,6 +543,86 @@ function resolveQuoteText(message: MessageRow): string | null {
   return null;
 }
 
+function callLabel(message: MessageRow): string {
+  let call: { call_direction?: string; call_is_video?: boolean; call_duration_ms?: number | null } = {};
+  if (message.metadata_json) {
+    try {
+      call = JSON.parse(message.metadata_json);
+    } catch {
+      // ignore parse errors
+    }
+  }
+  const medium = call.call_is_video ? "video call" : "voice call";
+  const prefixes: Record<string, string> = { incoming: "Incoming", outgoing: "Outgoing", missed: "Missed", group: "Group" };
+  const label = `${prefixes[call.call_direction ?? ""] ?? "Incoming"} ${medium}`;
+  if (!call.call_duration_ms) return label;
+  const seconds = Math.round(call.call_duration_ms / 1000);
+  return `${label} · ${Math.floor(seconds / 60)}:${String(seconds % 60).padStart(2, "0")}`;
+}
+
+function eventText(message: MessageRow): string | null {
+  if (!message.metadata_json) return null;
+  try {
+    const parsed = JSON.parse(message.metadata_json) as { event_text?: string };
+    return parsed.event_text ?? null;
+  } catch {
+    return null;
+  }
+}
+
+type LinkPreview = { url: string; title?: string | null; description?: string | null; attachment_sha?: string | null };
+
+function linkPreviews(message: MessageRow): LinkPreview[] {
+  if (!message.metadata_json) return [];
+  try {
+    const parsed = JSON.parse(message.metadata_json) as { link_previews?: LinkPreview[] };
+    return parsed.link_previews ?? [];
+  } catch {
+    return [];
+  }
+}
+
+function renderLinkPreview(preview: LinkPreview): HTMLElement {
+  const card = document.createElement("div");
+  card.className = "link-preview";
+  if (preview.attachment_sha) {
+    card.dataset.attachmentSha = preview.attachment_sha;
+  }
+  if (preview.title) {
+    const title = document.createElement("div");
+    title.className = "link-preview-title";
+    title.textContent = preview.title;
+    card.appendChild(title);
+  }
+  if (preview.description) {
+    const description = document.createElement("div");
+    description.className = "link-preview-description";
+    description.textContent = preview.description;
+    card.appendChild(description);
+  }
+  const url = document.createElement("div");
+  url.className = "link-preview-url";
+  try {
+    url.textContent = new URL(preview.url).host;
+  } catch {
+    url.textContent = preview.url;
+  }
+  card.appendChild(url);
+  return card;
+}
+
+function renderReceipt(message: MessageRow): HTMLElement | null {
+  if (!message.is_outgoing || (!message.read_at && !message.delivered_at)) return null;
+  const mark = document.createElement("span");
+  mark.className = `receipt ${message.read_at ? "read" : "delivered"}`;
+  mark.textContent = message.read_at ? "✓✓" : "✓";
+  const lines: string[] = [];
+  if (message.delivered_at) lines.push(`Delivered ${new Date(message.delivered_at).toLocaleString()}`);
+  if (message.read_at) lines.push(`Read ${new Date(message.read_at).toLocaleString()}`);
+  mark.title = lines.join("\n");
+  return mark;
+}
+
 function captureViewportAnchor() {
   if (!messageList) return;
   const scrollTop = messageList.scrollTop;
@@ -604,7 +754,7 @@ async function runSearch() {
   }
   if (statusEl) statusEl.textContent = "Searching...";
   try {
-    const hits: SearchHit[] = await apiSearchMessages(query, currentThreadId, 200, 0);
+    const hits: SearchHit[] = await apiSearchMessages(query, { thread_id: currentThreadId, prefix: true }, 200, 0);
     if (requestId !== searchRequestId) return;
     const sorted = hits.sort(
       (a, b) =>
@@ -653,7 +803,9 @@ function renderSearchResultsList(hits: SearchHit[]) {
     const meta = document.createElement("div");
     meta.className = "meta";
     const ts = messageSortTs(hit.message);
-    meta.textContent = `${ts ? new Date(ts).toLocaleString() : ""} · Thread ${hit.message.thread_id}`;
+    meta.textContent = `${ts ? new Date(ts).toLocaleString() : ""} · Thread ${hit.message.thread_id}${
+      hit.matched_in_quote ? " · in quote" : ""
+    }`;
     div.appendChild(meta);
     div.addEventListener("click", async () => {
       searchResults.classList.add("hidden");
@@ -728,8 +880,15 @@ function renderMessages(messages: MessageRow[], mode: "replace" | "append" | "pr
       div.appendChild(quote);
     }
     const body = document.createElement("div");
-    const bodyText = msg.body ?? "(no text)";
-  if (searchQuery && searchMatchIds.has(msg.id)) {
+    const bodyText = msg.body ?? (msg.message_type === "sticker" ? "" : "(no text)");
+  const systemText = eventText(msg);
+  if (msg.message_type === "call") {
+    body.className = "call-chip";
+    body.textContent = callLabel(msg);
+  } else if (systemText) {
+    body.className = "event-chip";
+    body.textContent = systemText;
+  } else if (searchQuery && searchMatchIds.has(msg.id)) {
     body.innerHTML = highlightBody(bodyText, searchQuery);
     div.classList.add("match");
     if (msg.id === highlightMessageId) {
@@ -739,10 +898,13 @@ function renderMessages(messages: MessageRow[], mode: "replace" | "append" | "pr
     body.textContent = bodyText;
   }
     div.appendChild(body);
+    linkPreviews(msg).forEach((preview) => div.appendChild(renderLinkPreview(preview)));
     if (tsLabel) {
       const time = document.createElement("div");
       time.className = "meta";
       time.textContent = tsLabel;
+      const receipt = renderReceipt(msg);
+      if (receipt) time.appendChild(receipt);
       div.appendChild(time);
     }
     const media = document.createElement("div");
@@ -875,9 +1037,63 @@ async function loadNewerMessages() {
   }
 }
 
+async function loadImportHistory() {
+  if (!importHistory || !isTauri) return;
+  try {
+    const imports = await apiListImports();
+    importHistory.replaceChildren();
+    imports.forEach((record) => {
+      const li = document.createElement("li");
+      const when = new Date(record.imported_at).toLocaleString();
+      if (record.status === "success") {
+        const missing = record.attachments_missing ?? 0;
+        li.textContent = `${when} · ${record.source_filename} · ${record.messages_inserted ?? 0} messages${
+          missing > 0 ? ` · ${missing} attachments missing` : ""
+        }`;
+      } else {
+        li.classList.add("failed");
+        li.textContent = `${when} · ${record.source_filename} · ${record.status}${record.error ? `: ${record.error.split("\n")[0]}` : ""}`;
+      }
+      if (record.detected_version) {
+        li.title = record.detected_version;
+      }
+      li.addEventListener("click", () => void toggleImportIssues(li, record.id));
+      importHistory.appendChild(li);
+    });
+  } catch {
+    // history is informational only
+  }
+}
+
+async function toggleImportIssues(li: HTMLLIElement, importId: string) {
+  const existing = li.querySelector("ul.import-issues");
+  if (existing) {
+    existing.remove();
+    return;
+  }
+  try {
+    const issues = await apiListImportIssues(importId);
+    if (issues.length === 0) return;
+    const list = document.createElement("ul");
+    list.className = "import-issues";
+    issues.forEach((issue) => {
+      const item = document.createElement("li");
+      const source = issue.source_id ? `${issue.source_table} #${issue.source_id}` : issue.source_table;
+      item.textContent = `${issue.category} · ${source}${issue.detail ? ` · ${issue.detail}` : ""}`;
+      list.appendChild(item);
+    });
+    li.appendChild(list);
+  } catch {
+    // issues are informational only
+  }
+}
+
 importBtn?.addEventListener("click", () => {
   if (importPanel) {
     importPanel.classList.toggle("hidden");
+    if (!importPanel.classList.contains("hidden")) {
+      void loadImportHistory();
+    }
   }
   // Auto-close menu when opening import panel
   if (optionsMenu && !importPanel?.classList.contains("hidden")) {
@@ -937,6 +1153,38 @@ runImportBtn?.addEventListener("click", async () => {
   }
 });
 
+inspectBackupBtn?.addEventListener("click", async () => {
+  if (!isTauri || isBusy) return;
+  if (!selectedBackupPath || !selectedBackupPath.toLowerCase().endsWith(".backup")) {
+    if (statusEl) statusEl.textContent = "Inspect works on Android .backup files.";
+    return;
+  }
+  const passphrase = passphraseInput?.value ?? "";
+  if (!passphrase.trim()) {
+    if (statusEl) statusEl.textContent = "Enter the 30-digit passphrase.";
+    return;
+  }
+  try {
+    setBusy(true, "Inspecting backup...");
+    const report = await apiInspectBackup(selectedBackupPath, passphrase);
+    const range =
+      report.first_message_at && report.last_message_at
+        ? `, ${new Date(report.first_message_at).toLocaleDateString()} – ${new Date(report.last_message_at).toLocaleDateString()}`
+        : "";
+    const version = report.detected_version ? ` (${report.detected_version})` : "";
+    const megabytes = (report.attachment_bytes / (1024 * 1024)).toFixed(1);
+    if (statusEl) {
+      statusEl.textContent =
+        `Backup${version}: ${report.threads} threads, ${report.sms + report.mms} messages, ` +
+        `${report.attachments} attachments (${megabytes} MB)${range}.`;
+    }
+  } catch (err) {
+    if (statusEl) statusEl.textContent = `Inspect failed: ${err}`;
+  } finally {
+    setBusy(false);
+  }
+});
+
 passphraseInput?.addEventListener("input", () => {
   const raw = passphraseInput.value ?? "";
   const normalized = raw.replace(/[-\\s]/g, "");
@@ -1122,12 +1370,11 @@ async function loadGallery(reset: boolean) {
     const fromTs = parseDateFromInput(galleryFrom, false);
     const toTs = parseDateFromInput(galleryTo, true);
     const bucket = sizeBucket(gallerySize?.value ?? "all");
+    const kind = galleryKind && galleryKind.value !== "all" ? galleryKind.value : null;
     const sort = gallerySort?.value ?? "date_desc";
     const items: ThreadMediaRow[] = await apiListThreadMedia(
       currentThreadId,
-      fromTs,
-      toTs,
-      bucket,
+      { from_ts: fromTs, to_ts: toTs, size_bucket: bucket, kind },
       sort,
       GALLERY_PAGE,
       galleryOffset,
@@ -1179,8 +1426,14 @@ function insertGalleryPlaceholder(
 ) {
   const label = requireMediaClick ? "Media hidden" : "Large media — click to load";
   const placeholder = createMediaPlaceholder("gallery", label);
+  if (item.kind === "video" && !requireMediaClick) {
+    void applyVideoPoster(placeholder, toMediaAssetFromThreadMedia(item));
+  } else if (!requireMediaClick) {
+    applyBlurhash(placeholder, item);
+  }
   attachPlaceholderClick(placeholder, () => {
     const loading = createMediaPlaceholder("gallery", "LOADING...");
+    applyBlurhash(loading, item);
     placeholder.replaceWith(loading);
     renderGalleryMedia(card, item, loading);
     if (requireMediaClick) {
@@ -1259,6 +1512,7 @@ function toMediaAssetFromThreadMedia(item: ThreadMediaRow): MediaAsset {
 function renderGalleryMedia(card: HTMLDivElement, item: ThreadMediaRow, loading?: HTMLElement) {
   if (!item.kind || !item.mime) return;
   const asset = toMediaAssetFromThreadMedia(item);
+  if (loading) loading.dataset.sha256 = item.sha256;
   if (item.kind === "image") {
     const img = document.createElement("img");
     img.alt = item.original_filename ?? "image";
@@ -1268,6 +1522,7 @@ function renderGalleryMedia(card: HTMLDivElement, item: ThreadMediaRow, loading?
     img.dataset.itemId = item.id;
     if (!loading) {
       loading = createMediaPlaceholder("gallery", "LOADING...");
+      applyBlurhash(loading, item);
       card.prepend(loading);
     }
     card.prepend(img);
@@ -1292,6 +1547,8 @@ function renderGalleryMedia(card: HTMLDivElement, item: ThreadMediaRow, loading?
     video.muted = true;
     video.playsInline = true;
     video.classList.add("gallery-video");
+    const poster = attachmentThumbCache.get(`${item.sha256}:${VIDEO_POSTER_SIZE}`);
+    if (poster) video.poster = poster;
     video.addEventListener("mouseenter", () => {
       void video.play();
     });
@@ -1388,9 +1645,7 @@ async function loadThreadMedia(threadId: string) {
     // Fetch all media from thread sorted by date (newest first to match message order)
     const items: ThreadMediaRow[] = await apiListThreadMedia(
       threadId,
-      null,  // no date filter
-      null,
-      null,  // all sizes
+      { from_ts: null, to_ts: null, size_bucket: null, kind: null },  // no filters
       "date_desc",  // newest first
       10000,  // high limit to get all media
       0
@@ -1406,25 +1661,30 @@ async function loadThreadMedia(threadId: string) {
 async function loadAttachmentDataUrl(attachment: MediaAsset): Promise<string | null> {
   const key = attachment.sha256;
   if (attachmentDataCache.has(key)) {
-    return attachmentDataCache.get(key) || null;
+    return attachmentDataCache.get(key)?.url || null;
   }
   const mime = attachment.mime ?? "";
   try {
-    const dataUrl: string = await attachmentDataUrl(key, mime);
-    attachmentDataCache.set(key, dataUrl);
-    return dataUrl;
+    const preview = await attachmentDataUrl(key, mime);
+    attachmentDataCache.set(key, preview);
+    return preview.url;
   } catch {
     return null;
   }
 }
 
+function isDownscaledPreview(attachment: MediaAsset): boolean {
+  return attachmentDataCache.get(attachment.sha256)?.downscaled ?? false;
+}
+
 async function loadAttachmentFileUrl(attachment: MediaAsset): Promise<string | null> {
   const key = attachment.sha256;
   if (attachmentFileCache.has(key)) {
     return attachmentFileCache.get(key) || null;
   }
   try {
-    const path: string = await attachmentPath(key, attachment.mime ?? null);
+    const timeoutMs = MEDIA_PATH_TIMEOUT_MS[attachment.kind ?? ""] ?? DEFAULT_MEDIA_PATH_TIMEOUT_MS;
+    const path: string = await attachmentPath(key, attachment.mime ?? null, timeoutMs);
     const src = convertFileSrc(path);
     attachmentFileCache.set(key, src);
     return src;
@@ -1450,8 +1710,9 @@ async function loadAttachmentThumbUrl(attachment: MediaAsset, maxSize: number):
 
 async function loadAttachmentSrcInfo(attachment: MediaAsset): Promise<SourceInfo | null> {
   if (attachment.kind === "video" || attachment.kind === "audio") {
-    const src = await loadAttachmentFileUrl(attachment);
-    return src ? { src, via: "file" } : null;
+    // Decrypted range by range as the player reads; formats the webview can't stream fall
+    // back to a decrypted temp file.
+    return { src: convertFileSrc(attachment.sha256, "gtmedia"), via: "stream" };
   }
   const size = attachment.size_bytes ?? 0;
   if (size > LARGE_MEDIA_BYTES) {
@@ -1466,6 +1727,131 @@ async function loadAttachmentSrcInfo(attachment: MediaAsset): Promise<SourceInfo
   return fallback ? { src: fallback, via: "file" } : null;
 }
 
+async function loadWaveform(asset: MediaAsset): Promise<number[] | null> {
+  const cached = waveformCache.get(asset.sha256);
+  if (cached !== undefined) return cached;
+  try {
+    const peaks = await runThumbTask(() => attachmentWaveform(asset.sha256, asset.mime ?? null, WAVEFORM_BUCKETS));
+    waveformCache.set(asset.sha256, peaks);
+    return peaks;
+  } catch {
+    waveformCache.set(asset.sha256, null);
+    return null;
+  }
+}
+
+function drawWaveform(canvas: HTMLCanvasElement, peaks: number[], played: number) {
+  const ctx = canvas.getContext("2d");
+  if (!ctx) return;
+  const styles = getComputedStyle(canvas);
+  const barWidth = canvas.width / peaks.length;
+  ctx.clearRect(0, 0, canvas.width, canvas.height);
+  peaks.forEach((peak, idx) => {
+    const height = Math.max(2, (peak / 255) * canvas.height);
+    ctx.fillStyle = idx / peaks.length < played ? styles.getPropertyValue("--color-primary") : styles.color;
+    ctx.fillRect(idx * barWidth, (canvas.height - height) / 2, Math.max(1, barWidth - 1), height);
+  });
+}
+
+/** Puts a waveform above `audio` that tracks playback and seeks on click. Audio the backend
+ * can't decode keeps the plain player. */
+function attachWaveform(audio: HTMLAudioElement, asset: MediaAsset) {
+  const canvas = document.createElement("canvas");
+  canvas.className = "audio-waveform hidden";
+  canvas.width = WAVEFORM_BUCKETS * 3;
+  canvas.height = 32;
+  audio.before(canvas);
+  void loadWaveform(asset).then((peaks) => {
+    if (!peaks) {
+      canvas.remove();
+      return;
+    }
+    canvas.classList.remove("hidden");
+    const draw = () => drawWaveform(canvas, peaks, audio.duration ? audio.currentTime / audio.duration : 0);
+    draw();
+    audio.addEventListener("timeupdate", draw);
+    canvas.addEventListener("click", (event) => {
+      if (!audio.duration) return;
+      const rect = canvas.getBoundingClientRect();
+      audio.currentTime = ((event.clientX - rect.left) / rect.width) * audio.duration;
+    });
+  });
+}
+
+async function applyVideoPoster(placeholder: HTMLElement, asset: MediaAsset) {
+  const key = `${asset.sha256}:${VIDEO_POSTER_SIZE}`;
+  try {
+    let src = attachmentThumbCache.get(key);
+    if (!src) {
+      src = await runThumbTask(() => attachmentThumbnail(asset.sha256, asset.mime ?? null, VIDEO_POSTER_SIZE));
+      attachmentThumbCache.set(key, src);
+    }
+    placeholder.style.backgroundImage = `url("${src}")`;
+    placeholder.classList.add("has-poster");
+  } catch (err) {
+    // Usually ffmpeg isn't installed; the tile stays generic and says why on hover.
+    placeholder.title = String(err);
+  }
+}
+
+/** Paints an image's BlurHash behind a gallery placeholder while its thumbnail loads. */
+function applyBlurhash(placeholder: HTMLElement, item: ThreadMediaRow) {
+  if (!item.blurhash) return;
+  const src = blurhashDataUrl(item.blurhash, BLURHASH_SIZE);
+  if (!src) return;
+  placeholder.style.backgroundImage = `url("${src}")`;
+  placeholder.classList.add("has-poster");
+}
+
+/** Gallery-size thumbnail, fetched with other tiles that scroll into view together. */
+function loadBatchedThumbUrl(asset: MediaAsset): Promise<string | null> {
+  const cached = attachmentThumbCache.get(`${asset.sha256}:${GALLERY_THUMB_SIZE}`);
+  if (cached) return Promise.resolve(cached);
+  return new Promise((resolve) => {
+    thumbBatch.push({ asset, resolve });
+    if (thumbBatch.length >= THUMB_BATCH_MAX) {
+      flushThumbBatch();
+    } else if (thumbBatchTimer === null) {
+      thumbBatchTimer = window.setTimeout(flushThumbBatch, 16);
+    }
+  });
+}
+
+function flushThumbBatch() {
+  if (thumbBatchTimer !== null) {
+    window.clearTimeout(thumbBatchTimer);
+    thumbBatchTimer = null;
+  }
+  const batch = thumbBatch;
+  thumbBatch = [];
+  if (!batch.length) return;
+  const requests = batch.map(({ asset }) => ({
+    sha256: asset.sha256,
+    mime: asset.mime ?? null,
+    maxSize: GALLERY_THUMB_SIZE,
+  }));
+  void runThumbTask(() => attachmentThumbnails(requests))
+    .then((results) => {
+      batch.forEach(({ asset, resolve }) => {
+        const key = `${asset.sha256}:${GALLERY_THUMB_SIZE}`;
+        const url = results[key]?.url ?? null;
+        if (url) attachmentThumbCache.set(key, url);
+        resolve(url);
+      });
+    })
+    .catch(() => batch.forEach(({ resolve }) => resolve(null)));
+}
+
+async function applyGalleryThumbnail(img: HTMLImageElement, asset: MediaAsset) {
+  const thumb = await loadBatchedThumbUrl(asset);
+  img.classList.remove("pending");
+  if (thumb) {
+    img.src = thumb;
+    return;
+  }
+  await applyMediaSource(img, asset);
+}
+
 async function applyThumbnailSource(img: HTMLImageElement, attachment: MediaAsset, onFailure?: () => void) {
   const thumb = await loadAttachmentThumbUrl(attachment, 320);
   if (thumb) {
@@ -1499,7 +1885,7 @@ function ensureGalleryThumbObserver() {
               }
               return;
             }
-            await applyThumbnailSource(img, asset);
+            await applyGalleryThumbnail(img, asset);
             if (!img.src) {
               const placeholder = img.parentElement?.querySelector(".media-placeholder") as HTMLElement | null;
               if (placeholder) placeholder.textContent = "Media preview unavailable";
@@ -1732,6 +2118,25 @@ function updateGalleryCardAfterLightboxDecrypt(attachment: MediaAsset) {
   });
 }
 
+/** Offers the full-resolution file in place of a downscaled data-url preview. */
+function addViewOriginalButton(img: HTMLImageElement, attachment: MediaAsset) {
+  if (!lightboxContent || !img.isConnected) return;
+  const button = document.createElement("button");
+  button.className = "lightbox-original";
+  button.textContent = "View original";
+  button.addEventListener("click", async () => {
+    button.disabled = true;
+    const src = await loadAttachmentFileUrl(attachment);
+    if (src && img.isConnected) {
+      img.src = src;
+      button.remove();
+    } else {
+      button.disabled = false;
+    }
+  });
+  lightboxContent.appendChild(button);
+}
+
 function openLightbox(attachment: MediaAsset) {
   ensureLightbox();
   if (!lightboxEl || !lightboxContent) return;
@@ -1746,6 +2151,7 @@ function openLightbox(attachment: MediaAsset) {
     lightboxContent.appendChild(img);
     void applyMediaSource(img, attachment).then(() => {
       updateGalleryCardAfterLightboxDecrypt(attachment);
+      if (isDownscaledPreview(attachment)) addViewOriginalButton(img, attachment);
     });
   } else if (attachment.kind === "video") {
     const video = document.createElement("video");
@@ -1764,6 +2170,26 @@ function openLightbox(attachment: MediaAsset) {
     });
   }
   lightboxEl.classList.remove("hidden");
+  prefetchLightboxNeighbors(attachment);
+}
+
+/**
+ * Warms the images either side of the lightbox's current one, so stepping to them doesn't
+ * wait on a decrypt. Small images go through the data-URL cache; large ones, which open
+ * from a decrypted file, are decrypted into the backend's preview cache, with the current
+ * item pinned so that doesn't evict it. Videos and audio stream, so they're skipped.
+ */
+function prefetchLightboxNeighbors(current: MediaAsset) {
+  if (!isTauri || lightboxIndex < 0) return;
+  const neighbors = lightboxGallery
+    .slice(Math.max(0, lightboxIndex - LIGHTBOX_PREFETCH_RADIUS), lightboxIndex + LIGHTBOX_PREFETCH_RADIUS + 1)
+    .filter((asset) => asset.sha256 !== current.sha256 && asset.kind === "image");
+  const large = neighbors.filter((asset) => (asset.size_bytes ?? 0) > LARGE_MEDIA_BYTES);
+  neighbors
+    .filter((asset) => !large.includes(asset))
+    .forEach((asset) => void runThumbTask(() => loadAttachmentDataUrl(asset)));
+  const items = large.map((asset) => ({ sha256: asset.sha256, mime: asset.mime ?? null }));
+  void prefetchMedia(current.sha256, items).catch(() => {});
 }
 
 function renderAttachments(container: HTMLElement, attachments: AttachmentRow[], messageId?: string) {
@@ -1831,6 +2257,7 @@ function renderAttachments(container: HTMLElement, attachments: AttachmentRow[],
         }
       });
       item.appendChild(audio);
+      attachWaveform(audio, att);
       const meta = document.createElement("div");
       meta.className = "meta";
       meta.textContent = att.original_filename ?? att.mime ?? "audio";
@@ -2000,8 +2427,22 @@ document.addEventListener("click", (event) => {
 
 optionsBtn?.addEventListener("click", () => {
   optionsMenu?.classList.toggle("hidden");
+  if (!optionsMenu?.classList.contains("hidden")) void refreshMediaCacheStats();
 });
 
+async function refreshMediaCacheStats() {
+  if (!mediaCacheStatsEl || !isTauri) return;
+  try {
+    const stats = await mediaCacheStats();
+    const mb = (bytes: number) => (bytes / (1024 * 1024)).toFixed(1);
+    mediaCacheStatsEl.textContent =
+      `Thumbnails ${mb(stats.thumbs_bytes)} of ${mb(stats.thumbs_cap_bytes)} MB, ` +
+      `previews ${mb(stats.preview_bytes)} MB`;
+  } catch {
+    mediaCacheStatsEl.textContent = "Cache size unavailable";
+  }
+}
+
 document.addEventListener("click", (event) => {
   if (!optionsMenu || !optionsBtn) return;
   const target = event.target as Node;
@@ -2085,6 +2526,169 @@ document.querySelector(".color-swatch-group")?.addEventListener("keydown", (even
   }
 });
 
+// Archive lock: drops the key from memory, by hand or after idleLockMinutes of inactivity.
+async function lockArchive() {
+  if (!isTauri || isLocked) return;
+  try {
+    await apiLockArchive();
+  } catch (err) {
+    if (statusEl) statusEl.textContent = `Lock failed: ${err}`;
+    return;
+  }
+  isLocked = true;
+  optionsMenu?.classList.add("hidden");
+  resetArchiveState();
+  toggleTab("messages");
+  threadStore = [];
+  if (threadList) threadList.replaceChildren();
+  if (lockStatus) lockStatus.textContent = "";
+  lockModal?.classList.remove("hidden");
+  unlockBtn?.focus();
+  if (statusEl) statusEl.textContent = "Archive locked.";
+}
+
+async function unlockArchive() {
+  if (!isLocked || !unlockBtn) return;
+  const passphrase = lockPassphrase && !lockPassphrase.classList.contains("hidden") ? lockPassphrase.value : "";
+  unlockBtn.disabled = true;
+  if (lockStatus) lockStatus.textContent = "Unlocking...";
+  try {
+    await apiUnlockArchive(passphrase || null);
+  } catch (err) {
+    const message = String(err);
+    if (lockPassphrase && message.includes("passphrase")) {
+      lockPassphrase.classList.remove("hidden");
+      lockPassphrase.focus();
+    }
+    if (lockStatus) lockStatus.textContent = `Unlock failed: ${message}`;
+    unlockBtn.disabled = false;
+    return;
+  } finally {
+    if (lockPassphrase) lockPassphrase.value = "";
+  }
+  isLocked = false;
+  lastActivityAt = Date.now();
+  unlockBtn.disabled = false;
+  lockPassphrase?.classList.add("hidden");
+  lockModal?.classList.add("hidden");
+  toggleTab("messages");
+  try {
+    await refreshThreads();
+    if (statusEl) statusEl.textContent = "Archive unlocked.";
+  } catch (err) {
+    if (statusEl) statusEl.textContent = `Failed to load threads: ${err}`;
+  }
+}
+
+if (idleLockSelect) {
+  idleLockSelect.value = String(idleLockMinutes);
+  if (idleLockSelect.value !== String(idleLockMinutes)) {
+    idleLockMinutes = 0;
+    idleLockSelect.value = "0";
+  }
+}
+
+idleLockSelect?.addEventListener("change", () => {
+  idleLockMinutes = Number(idleLockSelect.value);
+  lastActivityAt = Date.now();
+  localStorage.setItem("gt_idle_lock_minutes", String(idleLockMinutes));
+});
+
+if (thumbsCapSelect) {
+  thumbsCapSelect.value = String(thumbsCapMb);
+  if (thumbsCapSelect.value !== String(thumbsCapMb)) {
+    thumbsCapMb = DEFAULT_THUMBS_CAP_MB;
+    thumbsCapSelect.value = String(thumbsCapMb);
+  }
+  // The backend starts at the default, so only a changed limit needs sending.
+  if (isTauri && thumbsCapMb !== DEFAULT_THUMBS_CAP_MB) {
+    void setThumbsCacheCap(thumbsCapMb).catch(() => {});
+  }
+}
+
+if (previewFilesSelect && previewTtlSelect) {
+  previewFilesSelect.value = String(previewFiles);
+  if (previewFilesSelect.value !== String(previewFiles)) {
+    previewFiles = DEFAULT_PREVIEW_FILES;
+    previewFilesSelect.value = String(previewFiles);
+  }
+  previewTtlSelect.value = String(previewTtlSecs);
+  if (previewTtlSelect.value !== String(previewTtlSecs)) {
+    previewTtlSecs = DEFAULT_PREVIEW_TTL_SECS;
+    previewTtlSelect.value = String(previewTtlSecs);
+  }
+  if (isTauri && (previewFiles !== DEFAULT_PREVIEW_FILES || previewTtlSecs !== DEFAULT_PREVIEW_TTL_SECS)) {
+    void setMediaCacheConfig(previewFiles, previewTtlSecs).catch(() => {});
+  }
+}
+
+async function onPreviewCacheChange() {
+  if (!previewFilesSelect || !previewTtlSelect) return;
+  previewFiles = Number(previewFilesSelect.value);
+  previewTtlSecs = Number(previewTtlSelect.value);
+  localStorage.setItem("gt_preview_files", String(previewFiles));
+  localStorage.setItem("gt_preview_ttl_secs", String(previewTtlSecs));
+  try {
+    await setMediaCacheConfig(previewFiles, previewTtlSecs);
+  } catch (err) {
+    if (statusEl) statusEl.textContent = `Failed to set preview cache limits: ${err}`;
+  }
+  await refreshMediaCacheStats();
+}
+
+previewFilesSelect?.addEventListener("change", () => void onPreviewCacheChange());
+previewTtlSelect?.addEventListener("change", () => void onPreviewCacheChange());
+
+thumbsCapSelect?.addEventListener("change", async () => {
+  thumbsCapMb = Number(thumbsCapSelect.value);
+  localStorage.setItem("gt_thumbs_cap_mb", String(thumbsCapMb));
+  try {
+    await setThumbsCacheCap(thumbsCapMb);
+  } catch (err) {
+    if (statusEl) statusEl.textContent = `Failed to set thumbnail cache limit: ${err}`;
+  }
+  await refreshMediaCacheStats();
+});
+
+clearThumbsBtn?.addEventListener("click", async () => {
+  try {
+    await apiClearMediaCache(true);
+    attachmentThumbCache.clear();
+    waveformCache.clear();
+  } catch (err) {
+    if (statusEl) statusEl.textContent = `Failed to clear thumbnail cache: ${err}`;
+  }
+  await refreshMediaCacheStats();
+});
+
+lockBtn?.addEventListener("click", () => {
+  void lockArchive();
+});
+
+unlockBtn?.addEventListener("click", () => {
+  void unlockArchive();
+});
+
+lockPassphrase?.addEventListener("keydown", (event) => {
+  if (event.key === "Enter") void unlockArchive();
+});
+
+["mousemove", "mousedown", "keydown", "wheel", "touchstart"].forEach((eventName) => {
+  window.addEventListener(eventName, () => {
+    lastActivityAt = Date.now();
+  }, { passive: true });
+});
+
+if (isTauri) {
+  window.setInterval(() => {
+    // An import keeps running in the background; don't lock underneath it.
+    if (isLocked || isBusy || idleLockMinutes <= 0) return;
+    if (Date.now() - lastActivityAt >= idleLockMinutes * 60 * 1000) {
+      void lockArchive();
+    }
+  }, IDLE_LOCK_CHECK_MS);
+}
+
 tabMessages?.addEventListener("click", () => {
   toggleTab("messages");
 });
@@ -2116,6 +2720,7 @@ const onScrapbookScroll = throttleRaf(() => {
 scrapbookMessageList?.addEventListener("scroll", onScrapbookScroll);
 
 gallerySize?.addEventListener("change", galleryFilterReload);
+galleryKind?.addEventListener("change", galleryFilterReload);
 gallerySort?.addEventListener("change", galleryFilterReload);
 
 galleryFromClear?.addEventListener("click", () => {
@@ -2305,15 +2910,24 @@ async function createTag(name: string, color: string) {
   }
 }
 
-async function deleteTag(tagId: string) {
+async function deleteTag(tagId: string, force: boolean, button: HTMLButtonElement) {
   try {
-    await apiDeleteTag(tagId);
+    const removed = await apiDeleteTag(tagId, force);
     messageTagsCache.clear();
     await refreshTags();
     // Re-render all visible message tags
     refreshVisibleMessageTags();
-    if (statusEl) statusEl.textContent = "Tag deleted.";
+    if (statusEl) {
+      statusEl.textContent = removed > 0 ? `Tag deleted from ${removed} items.` : "Tag deleted.";
+    }
   } catch (err) {
+    // Heavily used tags need a second, explicit confirmation with the real count.
+    if (!force && String(err).includes("confirm to delete")) {
+      armDeleteTagConfirm(tagId, button, "Delete anyway");
+      deleteTagForceId = tagId;
+      if (statusEl) statusEl.textContent = `${err}`.replace(/^.*?: /, "");
+      return;
+    }
     if (statusEl) statusEl.textContent = `Error: ${err}`;
   }
 }
@@ -2324,6 +2938,7 @@ function resetDeleteTagConfirm() {
     deleteTagConfirmTimeout = null;
   }
   deleteTagConfirmId = null;
+  deleteTagForceId = null;
   if (!tagList) return;
   tagList.querySelectorAll<HTMLButtonElement>(".tag-delete-btn").forEach((button) => {
     button.textContent = "Delete";
@@ -2331,20 +2946,25 @@ function resetDeleteTagConfirm() {
   });
 }
 
+function armDeleteTagConfirm(tagId: string, button: HTMLButtonElement, label: string) {
+  resetDeleteTagConfirm();
+  deleteTagConfirmId = tagId;
+  button.textContent = label;
+  button.setAttribute("data-confirm-state", "confirming");
+  deleteTagConfirmTimeout = window.setTimeout(() => {
+    resetDeleteTagConfirm();
+  }, 3000);
+}
+
 function handleDeleteTagClick(tagId: string, button: HTMLButtonElement) {
   if (deleteTagConfirmId !== tagId) {
-    resetDeleteTagConfirm();
-    deleteTagConfirmId = tagId;
-    button.textContent = "Click again to delete";
-    button.setAttribute("data-confirm-state", "confirming");
-    deleteTagConfirmTimeout = window.setTimeout(() => {
-      resetDeleteTagConfirm();
-    }, 3000);
+    armDeleteTagConfirm(tagId, button, "Click again to delete");
     return;
   }
 
+  const force = deleteTagForceId === tagId;
   resetDeleteTagConfirm();
-  void deleteTag(tagId);
+  void deleteTag(tagId, force, button);
 }
 
 function refreshVisibleMessageTags() {
@@ -2367,7 +2987,7 @@ async function fetchTagsForMessages(messageIds: string[]) {
       const batches = await apiGetMessageTagsBulk(missing);
       const seen = new Set<string>();
       batches.forEach((entry) => {
-        messageTagsCache.set(entry.message_id, entry.tags);
+        messageTagsCache.set(entry.message_id, entry.tags.map((tagged) => tagged.tag));
         seen.add(entry.message_id);
       });
       missing.forEach((id) => {
@@ -2515,12 +3135,15 @@ async function showTagPicker(messageId: string, triggerEl: HTMLElement) {
 async function toggleMessageTag(messageId: string, tagId: string, add: boolean) {
   if (!isTauri) return;
   try {
-    const current = await invoke<Tag[]>("get_message_tags_cmd", { messageId });
-    const currentIds = current.map((t) => t.id);
-    const newIds = add ? [...currentIds, tagId] : currentIds.filter((id) => id !== tagId);
-    await apiSetMessageTags(messageId, newIds);
+    if (add) {
+      await apiAddMessageTag(messageId, tagId);
+    } else {
+      await apiRemoveMessageTag(messageId, tagId);
+    }
 
-    const updated = tagsStore.filter((t) => newIds.includes(t.id));
+    const current = await invoke<Tag[]>("get_message_tags_cmd", { messageId });
+    const currentIds = new Set(current.map((t) => t.id));
+    const updated = tagsStore.filter((t) => currentIds.has(t.id));
     messageTagsCache.set(messageId, updated);
     renderMessageTags(messageId, updated);
   } catch (err) {
@@ -2530,6 +3153,7 @@ async function toggleMessageTag(messageId: string, tagId: string, add: boolean)
 
 async function loadScrapbook(reset: boolean) {
   if (!isTauri || !currentScrapbookTagId || isLoadingScrapbook || !scrapbookMessageList) return;
+  if (!reset && !scrapbookHasMore) return;
 
   isLoadingScrapbook = true;
   const requestId = ++scrapbookRequestId;
@@ -2540,11 +3164,12 @@ async function loadScrapbook(reset: boolean) {
       }
       scrapbookBeforeTs = null;
       scrapbookBeforeId = null;
+      scrapbookHasMore = true;
       scrapbookMessages = [];
       scrapbookMessageList.replaceChildren();
     }
 
-    const msgs = await apiListScrapbookMessages(
+    const page = await apiListScrapbookMessages(
       currentScrapbookTagId,
       scrapbookBeforeTs,
       scrapbookBeforeId,
@@ -2552,10 +3177,11 @@ async function loadScrapbook(reset: boolean) {
     );
     if (requestId !== scrapbookRequestId) return;
 
+    scrapbookBeforeTs = page.next_before_ts;
+    scrapbookBeforeId = page.next_before_id;
+    scrapbookHasMore = page.next_before_ts !== null;
+    const msgs = page.items;
     if (msgs.length > 0) {
-      const oldest = msgs[0].message;
-      scrapbookBeforeTs = messageSortTs(oldest);
-      scrapbookBeforeId = oldest.id;
       scrapbookMessages.push(...msgs);
       renderScrapbookMessages(msgs, reset ? "replace" : "prepend");
 
@@ -2623,6 +3249,17 @@ function renderScrapbookMessages(msgs: ScrapbookMessage[], mode: "replace" | "pr
       div.appendChild(meta);
     }
 
+    const scrapbookTag = item.tags.find((tagged) => tagged.tag.id === currentScrapbookTagId);
+    if (scrapbookTag) {
+      const added = document.createElement("div");
+      added.className = "meta scrapbook-message-added";
+      added.textContent = `Added ${new Date(scrapbookTag.tagged_at).toLocaleDateString(undefined, {
+        month: "long",
+        day: "numeric",
+      })}`;
+      div.appendChild(added);
+    }
+
     div.addEventListener("click", () => {
       void jumpToMessageInThread(item.message.id, item.message.thread_id);
     });
@@ -1,14 +1,48 @@
 import { invoke } from "@tauri-apps/api/core";
 import type {
+  ArchiveBundleSummary,
+  AttachmentAuditReport,
+  AttachmentDetail,
   AttachmentRow,
+  AttachmentTags,
+  AttachmentVerifyReport,
+  BackupInspection,
+  BenchReport,
+  BlurhashBackfillReport,
+  CryptoDiagnostics,
+  DataUrlResponse,
+  DimensionBackfillReport,
+  ExportFormat,
+  GcReport,
+  ImportIssue,
+  ImportRecord,
+  LargeAttachment,
+  MediaCacheStats,
+  MediaExportSummary,
+  MediaMonthBucket,
+  Mention,
+  MessageDetail,
+  MessageNote,
   MessageRow,
   MessageTags,
+  PrefetchRequest,
   ReactionSummary,
-  ScrapbookMessage,
+  ResniffReport,
+  ScrapbookContextMessage,
+  ScrapbookOrder,
+  ScrapbookPage,
   SearchHit,
+  SearchOptions,
+  StorageUsage,
   Tag,
+  TagImportSummary,
+  TagStats,
+  ThreadMediaFilters,
   ThreadMediaRow,
+  ThreadMediaSummary,
   ThreadSummary,
+  ThumbRequest,
+  ThumbResult,
 } from "./types";
 
 export function listThreads(limit: number, offset: number) {
@@ -37,30 +71,67 @@ export function listMessagesAround(messageId: string, before: number, after: num
   return invoke<MessageRow[]>("list_messages_around_cmd", { messageId, before, after });
 }
 
-export function searchMessages(query: string, threadId: string | null, limit: number, offset: number) {
-  return invoke<SearchHit[]>("search_messages_cmd", { query, threadId, limit, offset });
+export function searchMessages(query: string, options: SearchOptions, limit: number, offset: number) {
+  return invoke<SearchHit[]>("search_messages_cmd", { query, options, limit, offset });
 }
 
 export function listThreadMedia(
   threadId: string,
-  fromTs: number | null,
-  toTs: number | null,
-  sizeBucket: number | null,
+  filters: ThreadMediaFilters,
   sort: string,
   limit: number,
   offset: number,
 ) {
   return invoke<ThreadMediaRow[]>("list_thread_media_cmd", {
     threadId,
-    fromTs,
-    toTs,
-    sizeBucket,
+    filters,
     sort,
     limit,
     offset,
   });
 }
 
+export function listDocumentAttachments(
+  threadId: string | null,
+  filenameNeedle: string | null,
+  limit: number,
+  offset: number,
+) {
+  return invoke<ThreadMediaRow[]>("list_document_attachments_cmd", { threadId, filenameNeedle, limit, offset });
+}
+
+// Mirrors `query::OUTGOING_SENDER`: lists the media you sent.
+export const OUTGOING_SENDER = "self";
+
+export function listMediaBySender(senderId: string, kind: string | null, limit: number, offset: number) {
+  return invoke<ThreadMediaRow[]>("list_media_by_sender_cmd", { senderId, kind, limit, offset });
+}
+
+export function largestAttachments(limit: number, kind: string | null) {
+  return invoke<LargeAttachment[]>("largest_attachments_cmd", { limit, kind });
+}
+
+export function attachmentTotalBytes() {
+  return invoke<number>("attachment_total_bytes_cmd");
+}
+
+export function threadStorageUsage() {
+  return invoke<StorageUsage>("thread_storage_usage_cmd");
+}
+
+export function threadMediaMonthBuckets(threadId: string, kind: string | null) {
+  return invoke<MediaMonthBucket[]>("thread_media_month_buckets_cmd", { threadId, kind });
+}
+
+export function threadMediaSummary(
+  threadId: string,
+  fromTs: number | null,
+  toTs: number | null,
+  sizeBucket: number | null,
+) {
+  return invoke<ThreadMediaSummary>("thread_media_summary_cmd", { threadId, fromTs, toTs, sizeBucket });
+}
+
 export function listMessageAttachments(messageId: string) {
   return invoke<AttachmentRow[]>("list_message_attachments_cmd", { messageId });
 }
@@ -69,20 +140,70 @@ export function listMessageReactions(messageIds: string[]) {
   return invoke<ReactionSummary[]>("list_message_reactions_cmd", { messageIds });
 }
 
+export function listMessageMentions(messageIds: string[]) {
+  return invoke<Mention[]>("list_message_mentions_cmd", { messageIds });
+}
+
+/** Rejects with a `DataUrlError`. */
 export function attachmentDataUrl(sha256: string, mime: string) {
-  return invoke<string>("attachment_data_url_cmd", { sha256, mime });
+  return invoke<DataUrlResponse>("attachment_data_url_cmd", { sha256, mime });
 }
 
-export function attachmentPath(sha256: string, mime: string | null) {
-  return invoke<string>("attachment_path_cmd", { sha256, mime });
+let mediaRequestSeq = 0;
+
+/**
+ * Decrypts an attachment to a preview file. Past `timeoutMs` this rejects and asks the
+ * backend to stop decrypting, rather than leaving it to finish a file nobody will open.
+ */
+export async function attachmentPath(sha256: string, mime: string | null, timeoutMs: number) {
+  mediaRequestSeq += 1;
+  const requestId = `path-${mediaRequestSeq}`;
+  let timer: number | undefined;
+  const timedOut = new Promise<never>((_, reject) => {
+    timer = window.setTimeout(() => {
+      void invoke<boolean>("cancel_media_request_cmd", { requestId }).catch(() => {});
+      reject(new Error(`decrypt timed out after ${Math.round(timeoutMs / 1000)}s`));
+    }, timeoutMs);
+  });
+  try {
+    return await Promise.race([invoke<string>("attachment_path_cmd", { sha256, mime, requestId }), timedOut]);
+  } finally {
+    window.clearTimeout(timer);
+  }
 }
 
 export function attachmentThumbnail(sha256: string, mime: string | null, maxSize: number) {
   return invoke<string>("attachment_thumbnail_cmd", { sha256, mime, maxSize });
 }
 
-export function clearMediaCache() {
-  return invoke<void>("clear_media_cache_cmd");
+/** Results are keyed `${sha256}:${maxSize}`; at most 32 requests per call. */
+export function attachmentThumbnails(requests: ThumbRequest[]) {
+  return invoke<Record<string, ThumbResult>>("attachment_thumbnails_cmd", { requests });
+}
+
+/** Resolves at once; the backend decrypts in the background. */
+export function prefetchMedia(current: string | null, items: PrefetchRequest[]) {
+  return invoke<void>("prefetch_media_cmd", { current, items });
+}
+
+export function attachmentWaveform(sha256: string, mime: string | null, buckets: number) {
+  return invoke<number[]>("attachment_waveform_cmd", { sha256, mime, buckets });
+}
+
+export function clearMediaCache(includeThumbs = false) {
+  return invoke<void>("clear_media_cache_cmd", { includeThumbs });
+}
+
+export function mediaCacheStats() {
+  return invoke<MediaCacheStats>("media_cache_stats_cmd");
+}
+
+export function setThumbsCacheCap(capMb: number) {
+  return invoke<void>("set_thumbs_cache_cap_cmd", { capMb });
+}
+
+export function setMediaCacheConfig(maxFiles: number, ttlSecs: number) {
+  return invoke<void>("set_media_cache_config_cmd", { maxFiles, ttlSecs });
 }
 
 export function drainMediaEvictions() {
@@ -97,8 +218,16 @@ export function createTag(name: string, color: string) {
   return invoke<Tag>("create_tag_cmd", { name, color });
 }
 
-export function deleteTag(id: string) {
-  return invoke<void>("delete_tag_cmd", { id });
+export function suggestTagColor() {
+  return invoke<string>("suggest_tag_color_cmd");
+}
+
+export function deleteTag(id: string, force: boolean) {
+  return invoke<number>("delete_tag_cmd", { id, force });
+}
+
+export function mergeTags(sourceTagId: string, targetTagId: string) {
+  return invoke<void>("merge_tags_cmd", { sourceTagId, targetTagId });
 }
 
 export function getMessageTags(messageId: string) {
@@ -113,21 +242,183 @@ export function setMessageTags(messageId: string, tagIds: string[]) {
   return invoke<void>("set_message_tags_cmd", { messageId, tagIds });
 }
 
-export function listScrapbookMessages(tagId: string, beforeTs: number | null, beforeId: string | null, limit: number) {
-  return invoke<ScrapbookMessage[]>("list_scrapbook_messages_cmd", {
+export function addMessageTag(messageId: string, tagId: string) {
+  return invoke<void>("add_message_tag_cmd", { messageId, tagId });
+}
+
+export function removeMessageTag(messageId: string, tagId: string) {
+  return invoke<void>("remove_message_tag_cmd", { messageId, tagId });
+}
+
+export function bulkTag(tagId: string, messageIds: string[], add: boolean) {
+  return invoke<number>("bulk_tag_cmd", { tagId, messageIds, add });
+}
+
+export function getOrCreateTag(name: string, defaultColor: string) {
+  return invoke<Tag>("get_or_create_tag_cmd", { name, defaultColor });
+}
+
+export function tagStats(tagId: string) {
+  return invoke<TagStats>("tag_stats_cmd", { tagId });
+}
+
+export function exportTagsJson() {
+  return invoke<string>("export_tags_json_cmd");
+}
+
+export function importTagsJson(json: string) {
+  return invoke<TagImportSummary>("import_tags_json_cmd", { json });
+}
+
+export function setAttachmentTags(attachmentId: string, tagIds: string[]) {
+  return invoke<void>("set_attachment_tags_cmd", { attachmentId, tagIds });
+}
+
+export function getAttachmentTagsBulk(attachmentIds: string[]) {
+  return invoke<AttachmentTags[]>("get_attachment_tags_bulk_cmd", { attachmentIds });
+}
+
+export function listTaggedMedia(tagId: string, limit: number, offset: number) {
+  return invoke<ThreadMediaRow[]>("list_tagged_media_cmd", { tagId, limit, offset });
+}
+
+export function listScrapbookMessages(
+  tagId: string,
+  beforeTs: number | null,
+  beforeId: string | null,
+  limit: number,
+  order: ScrapbookOrder = "tagged_at",
+  threadId: string | null = null,
+) {
+  return invoke<ScrapbookPage>("list_scrapbook_messages_cmd", {
     tagId,
+    threadId,
+    order,
     beforeTs,
     beforeId,
     limit,
   });
 }
 
+export function scrapbookContext(tagId: string, messageId: string, before: number, after: number) {
+  return invoke<ScrapbookContextMessage[]>("scrapbook_context_cmd", { tagId, messageId, before, after });
+}
+
+export function getAttachment(attachmentId: string) {
+  return invoke<AttachmentDetail>("get_attachment_cmd", { attachmentId });
+}
+
+export function getMessageDetail(messageId: string) {
+  return invoke<MessageDetail>("get_message_detail_cmd", { messageId });
+}
+
+export function getMessageNote(messageId: string) {
+  return invoke<MessageNote | null>("get_message_note_cmd", { messageId });
+}
+
+export function getMessageNotesBulk(messageIds: string[]) {
+  return invoke<MessageNote[]>("get_message_notes_bulk_cmd", { messageIds });
+}
+
+export function setMessageNote(messageId: string, noteText: string) {
+  return invoke<MessageNote>("set_message_note_cmd", { messageId, noteText });
+}
+
+export function deleteMessageNote(messageId: string) {
+  return invoke<void>("delete_message_note_cmd", { messageId });
+}
+
+export function searchMessageNotes(query: string, limit: number) {
+  return invoke<MessageNote[]>("search_message_notes_cmd", { query, limit });
+}
+
 export function seedDemo(primaryCount: number, secondaryThreads: number) {
   return invoke<void>("seed_demo_cmd", { primaryCount, secondaryThreads });
 }
 
-export function importBackup(path: string, passphrase: string) {
-  return invoke<void>("import_backup_cmd", { path, passphrase });
+export function importBackup(path: string, passphrase: string, verifyAttachments = false) {
+  return invoke<void>("import_backup_cmd", { path, passphrase, verifyAttachments });
+}
+
+export function inspectBackup(path: string, passphrase: string) {
+  return invoke<BackupInspection>("inspect_backup_cmd", { path, passphrase });
+}
+
+export function exportThreadMedia(threadId: string, destDir: string, filters: ThreadMediaFilters) {
+  return invoke<MediaExportSummary>("export_thread_media_cmd", { threadId, destDir, filters });
+}
+
+export function auditAttachments() {
+  return invoke<AttachmentAuditReport>("audit_attachments_cmd");
+}
+
+export function gcOrphanedAttachments(dryRun = true) {
+  return invoke<GcReport>("gc_orphaned_attachments_cmd", { dryRun });
+}
+
+export function resniffAttachments() {
+  return invoke<ResniffReport>("resniff_attachments_cmd");
+}
+
+export function verifyAttachments() {
+  return invoke<AttachmentVerifyReport>("verify_attachments_cmd");
+}
+
+export function backfillImageDimensions() {
+  return invoke<DimensionBackfillReport>("backfill_image_dimensions_cmd");
+}
+
+export function backfillBlurhash() {
+  return invoke<BlurhashBackfillReport>("backfill_blurhash_cmd");
+}
+
+export function exportAttachment(
+  sha256: string,
+  destPath: string,
+  originalFilename: string | null,
+  overwrite = false,
+) {
+  return invoke<string>("export_attachment_cmd", { sha256, destPath, originalFilename, overwrite });
+}
+
+export function exportScrapbook(tagId: string, destDir: string, format: ExportFormat) {
+  return invoke<string>("export_scrapbook_cmd", { tagId, destDir, format });
+}
+
+export function listImports() {
+  return invoke<ImportRecord[]>("list_imports_cmd");
+}
+
+export function listImportIssues(importId: string) {
+  return invoke<ImportIssue[]>("list_import_issues_cmd", { importId });
+}
+
+export function rotateKey() {
+  return invoke<void>("rotate_key_cmd");
+}
+
+export function unlockKeyFile(passphrase: string) {
+  return invoke<void>("unlock_key_file_cmd", { passphrase });
+}
+
+export function changeKeyFilePassphrase(oldPassphrase: string, newPassphrase: string) {
+  return invoke<void>("change_key_file_passphrase_cmd", { oldPassphrase, newPassphrase });
+}
+
+export function lockArchive() {
+  return invoke<void>("lock_archive_cmd");
+}
+
+export function unlockArchive(passphrase: string | null = null) {
+  return invoke<void>("unlock_archive_cmd", { passphrase });
+}
+
+export function exportArchiveBundle(destPath: string, passphrase: string) {
+  return invoke<ArchiveBundleSummary>("export_archive_bundle_cmd", { destPath, passphrase });
+}
+
+export function importArchiveBundle(srcPath: string, passphrase: string) {
+  return invoke<ArchiveBundleSummary>("import_archive_bundle_cmd", { srcPath, passphrase });
 }
 
 export function resetArchive() {
@@ -137,3 +428,11 @@ export function resetArchive() {
 export function getDiagnostics() {
   return invoke<string>("get_diagnostics_cmd");
 }
+
+export function cryptoDiagnostics() {
+  return invoke<CryptoDiagnostics>("crypto_diagnostics_cmd");
+}
+
+export function cryptoBenchmark(sampleMb?: number) {
+  return invoke<BenchReport>("crypto_benchmark_cmd", { sampleMb });
+}
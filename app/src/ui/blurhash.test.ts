@@ -0,0 +1,21 @@
+import { describe, expect, it } from "vitest";
+import { decodeBlurhash } from "./blurhash";
+
+describe("blurhash", () => {
+  it("decodes a flat colour close to that colour", () => {
+    // What the core encoder gives a flat 0xff0000 image.
+    const pixels = decodeBlurhash("LDTI:j]9fQ]9|co1fQo1fQfQfQfQ", 8, 8);
+    expect(pixels).not.toBeNull();
+    const center = 4 * (4 + 4 * 8);
+    expect(pixels![center]).toBeGreaterThan(230);
+    expect(pixels![center + 1]).toBeLessThan(25);
+    expect(pixels![center + 2]).toBeLessThan(25);
+    expect(pixels![center + 3]).toBe(255);
+  });
+
+  it("rejects malformed hashes", () => {
+    expect(decodeBlurhash("", 4, 4)).toBeNull();
+    expect(decodeBlurhash("LDTI:j]9", 4, 4)).toBeNull();
+    expect(decodeBlurhash("LDTI:j]9fQ]9|co1fQo1fQfQfQf\"", 4, 4)).toBeNull();
+  });
+});
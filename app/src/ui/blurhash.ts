@@ -0,0 +1,91 @@
+const BASE83 = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz#$%*+,-.:;=?@[]^_{|}~";
+
+function decode83(value: string): number | null {
+  let result = 0;
+  for (const char of value) {
+    const digit = BASE83.indexOf(char);
+    if (digit < 0) return null;
+    result = result * 83 + digit;
+  }
+  return result;
+}
+
+function srgbToLinear(value: number): number {
+  const v = value / 255;
+  return v <= 0.04045 ? v / 12.92 : Math.pow((v + 0.055) / 1.055, 2.4);
+}
+
+function linearToSrgb(value: number): number {
+  const v = Math.max(0, Math.min(1, value));
+  return v <= 0.0031308 ? Math.round(v * 12.92 * 255) : Math.round((1.055 * Math.pow(v, 1 / 2.4) - 0.055) * 255);
+}
+
+function signPow(value: number, exp: number): number {
+  return Math.sign(value) * Math.pow(Math.abs(value), exp);
+}
+
+/**
+ * Decodes a BlurHash from `MediaRow.blurhash` into `width` x `height` RGBA pixels, or null
+ * if it is malformed.
+ */
+export function decodeBlurhash(hash: string, width: number, height: number): Uint8ClampedArray | null {
+  if (hash.length < 6) return null;
+  const sizeFlag = decode83(hash[0]);
+  const quantisedMax = decode83(hash[1]);
+  if (sizeFlag === null || quantisedMax === null) return null;
+  const componentsX = (sizeFlag % 9) + 1;
+  const componentsY = Math.floor(sizeFlag / 9) + 1;
+  if (hash.length !== 4 + 2 * componentsX * componentsY) return null;
+
+  const maxValue = (quantisedMax + 1) / 166;
+  const colors: Array<[number, number, number]> = [];
+  for (let i = 0; i < componentsX * componentsY; i += 1) {
+    if (i === 0) {
+      const dc = decode83(hash.slice(2, 6));
+      if (dc === null) return null;
+      colors.push([srgbToLinear(dc >> 16), srgbToLinear((dc >> 8) & 255), srgbToLinear(dc & 255)]);
+    } else {
+      const ac = decode83(hash.slice(4 + i * 2, 6 + i * 2));
+      if (ac === null) return null;
+      const channel = (q: number) => signPow((q - 9) / 9, 2) * maxValue;
+      colors.push([channel(Math.floor(ac / 361)), channel(Math.floor(ac / 19) % 19), channel(ac % 19)]);
+    }
+  }
+
+  const pixels = new Uint8ClampedArray(width * height * 4);
+  for (let y = 0; y < height; y += 1) {
+    for (let x = 0; x < width; x += 1) {
+      let r = 0;
+      let g = 0;
+      let b = 0;
+      for (let j = 0; j < componentsY; j += 1) {
+        for (let i = 0; i < componentsX; i += 1) {
+          const basis = Math.cos((Math.PI * x * i) / width) * Math.cos((Math.PI * y * j) / height);
+          const color = colors[i + j * componentsX];
+          r += color[0] * basis;
+          g += color[1] * basis;
+          b += color[2] * basis;
+        }
+      }
+      const offset = 4 * (x + y * width);
+      pixels[offset] = linearToSrgb(r);
+      pixels[offset + 1] = linearToSrgb(g);
+      pixels[offset + 2] = linearToSrgb(b);
+      pixels[offset + 3] = 255;
+    }
+  }
+  return pixels;
+}
+
+/** Paints a BlurHash onto a `size`-pixel square canvas and returns it as a PNG data URL. */
+export function blurhashDataUrl(hash: string, size: number): string | null {
+  const pixels = decodeBlurhash(hash, size, size);
+  if (!pixels) return null;
+  const canvas = document.createElement("canvas");
+  canvas.width = size;
+  canvas.height = size;
+  const ctx = canvas.getContext("2d");
+  if (!ctx) return null;
+  ctx.putImageData(new ImageData(pixels, size, size), 0, 0);
+  return canvas.toDataURL();
+}
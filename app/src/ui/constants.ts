@@ -5,6 +5,20 @@ export const ATTACHMENT_LIST_CACHE_MAX = 200;
 export const ATTACHMENT_DATA_URL_CACHE_MAX = 40;
 export const ATTACHMENT_FILE_URL_CACHE_MAX = 200;
 export const ATTACHMENT_THUMB_CACHE_MAX_BYTES = 64 * 1024 * 1024;
+export const WAVEFORM_BUCKETS = 96;
+export const DEFAULT_THUMBS_CAP_MB = 500;
+export const DEFAULT_PREVIEW_FILES = 20;
+export const DEFAULT_PREVIEW_TTL_SECS = 300;
+// How long a decrypt to a preview file may take, by media kind. Large videos legitimately
+// take minutes.
+export const MEDIA_PATH_TIMEOUT_MS: Record<string, number> = {
+  image: 60 * 1000,
+  audio: 2 * 60 * 1000,
+  video: 10 * 60 * 1000,
+};
+export const DEFAULT_MEDIA_PATH_TIMEOUT_MS = 2 * 60 * 1000;
+export const WAVEFORM_CACHE_MAX = 200;
+export const IDLE_LOCK_CHECK_MS = 30 * 1000;
 
 // Golden Thread logo - flowing infinity symbol
 export const LOGO_SVG_PATH = "M 20 50 C 20 35 30 30 40 40 C 50 50 50 50 60 40 C 70 30 80 35 80 50 C 80 65 70 70 60 60 C 50 50 50 50 40 60 C 30 70 20 65 20 50";
@@ -6,11 +6,13 @@ export function getDom() {
     messageList: document.getElementById("message-list") as HTMLDivElement | null,
     importBtn: document.getElementById("import-btn") as HTMLButtonElement | null,
     importPanel: document.getElementById("import-panel") as HTMLDivElement | null,
+    importHistory: document.getElementById("import-history") as HTMLUListElement | null,
     chooseFileBtn: document.getElementById("choose-file-btn") as HTMLButtonElement | null,
     importFileEl: document.getElementById("import-file") as HTMLSpanElement | null,
     passphraseInput: document.getElementById("passphrase-input") as HTMLInputElement | null,
     passphraseCount: document.getElementById("passphrase-count") as HTMLSpanElement | null,
     runImportBtn: document.getElementById("run-import-btn") as HTMLButtonElement | null,
+    inspectBackupBtn: document.getElementById("inspect-backup-btn") as HTMLButtonElement | null,
     seedBtn: document.getElementById("seed-btn") as HTMLButtonElement | null,
     resetBtn: document.getElementById("reset-btn") as HTMLButtonElement | null,
     copyDiagBtn: document.getElementById("copy-diag-btn") as HTMLButtonElement | null,
@@ -29,6 +31,13 @@ export function getDom() {
     optionsMenu: document.getElementById("options-menu") as HTMLDivElement | null,
     mediaToggle: document.getElementById("media-toggle") as HTMLButtonElement | null,
     darkModeToggle: document.getElementById("dark-mode-toggle") as HTMLButtonElement | null,
+    idleLockSelect: document.getElementById("idle-lock-select") as HTMLSelectElement | null,
+    thumbsCapSelect: document.getElementById("thumbs-cap-select") as HTMLSelectElement | null,
+    previewFilesSelect: document.getElementById("preview-files-select") as HTMLSelectElement | null,
+    previewTtlSelect: document.getElementById("preview-ttl-select") as HTMLSelectElement | null,
+    mediaCacheStatsEl: document.getElementById("media-cache-stats") as HTMLSpanElement | null,
+    clearThumbsBtn: document.getElementById("clear-thumbs-btn") as HTMLButtonElement | null,
+    lockBtn: document.getElementById("lock-btn") as HTMLButtonElement | null,
     tabMessages: document.getElementById("tab-messages") as HTMLButtonElement | null,
     tabGallery: document.getElementById("tab-gallery") as HTMLButtonElement | null,
     messagesView: document.getElementById("messages-view") as HTMLDivElement | null,
@@ -40,6 +49,7 @@ export function getDom() {
     galleryFromClear: document.getElementById("gallery-from-clear") as HTMLButtonElement | null,
     galleryToClear: document.getElementById("gallery-to-clear") as HTMLButtonElement | null,
     gallerySize: document.getElementById("gallery-size") as HTMLSelectElement | null,
+    galleryKind: document.getElementById("gallery-kind") as HTMLSelectElement | null,
     gallerySort: document.getElementById("gallery-sort") as HTMLSelectElement | null,
     tabScrapbook: document.getElementById("tab-scrapbook") as HTMLButtonElement | null,
     scrapbookView: document.getElementById("scrapbook-view") as HTMLDivElement | null,
@@ -52,5 +62,9 @@ export function getDom() {
     tagColorPresets: document.getElementById("tag-color-presets") as HTMLDivElement | null,
     createTagBtn: document.getElementById("create-tag-btn") as HTMLButtonElement | null,
     tagList: document.getElementById("tag-list") as HTMLDivElement | null,
+    lockModal: document.getElementById("lock-modal") as HTMLDivElement | null,
+    lockPassphrase: document.getElementById("lock-passphrase") as HTMLInputElement | null,
+    unlockBtn: document.getElementById("unlock-btn") as HTMLButtonElement | null,
+    lockStatus: document.getElementById("lock-status") as HTMLParagraphElement | null,
   };
 }
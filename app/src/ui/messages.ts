@@ -104,6 +104,39 @@ export function resolveQuoteText(message: MessageRow): string | null {
   return null;
 }
 
+/**
+ * Describes a call record from its metadata, e.g. "Missed video call · 2:05".
+ */
+export function callLabel(message: MessageRow): string {
+  let call: { call_direction?: string; call_is_video?: boolean; call_duration_ms?: number | null } = {};
+  if (message.metadata_json) {
+    try {
+      call = JSON.parse(message.metadata_json);
+    } catch {
+      // ignore parse errors
+    }
+  }
+  const medium = call.call_is_video ? "video call" : "voice call";
+  const prefixes: Record<string, string> = { incoming: "Incoming", outgoing: "Outgoing", missed: "Missed", group: "Group" };
+  const label = `${prefixes[call.call_direction ?? ""] ?? "Incoming"} ${medium}`;
+  if (!call.call_duration_ms) return label;
+  const seconds = Math.round(call.call_duration_ms / 1000);
+  return `${label} · ${Math.floor(seconds / 60)}:${String(seconds % 60).padStart(2, "0")}`;
+}
+
+/**
+ * Returns the synthesized text of a group update or other system event, if any.
+ */
+export function eventText(message: MessageRow): string | null {
+  if (!message.metadata_json) return null;
+  try {
+    const parsed = JSON.parse(message.metadata_json) as { event_text?: string };
+    return parsed.event_text ?? null;
+  } catch {
+    return null;
+  }
+}
+
 /**
  * Captures the current viewport anchor for scroll restoration.
  */
@@ -224,7 +257,7 @@ export async function fetchTagsForMessages(messageIds: string[]) {
       const batches = await apiGetMessageTagsBulk(missing);
       const seen = new Set<string>();
       batches.forEach((entry) => {
-        messageTagsCache.set(entry.message_id, entry.tags);
+        messageTagsCache.set(entry.message_id, entry.tags.map((tagged) => tagged.tag));
         seen.add(entry.message_id);
       });
       missing.forEach((id) => {
@@ -306,8 +339,15 @@ export function renderMessages(messages: MessageRow[], mode: "replace" | "append
     }
 
     const body = document.createElement("div");
-    const bodyText = msg.body ?? "(no text)";
-    if (searchQuery && searchMatchIds.has(msg.id)) {
+    const bodyText = msg.body ?? (msg.message_type === "sticker" ? "" : "(no text)");
+    const systemText = eventText(msg);
+    if (msg.message_type === "call") {
+      body.className = "call-chip";
+      body.textContent = callLabel(msg);
+    } else if (systemText) {
+      body.className = "event-chip";
+      body.textContent = systemText;
+    } else if (searchQuery && searchMatchIds.has(msg.id)) {
       body.innerHTML = highlightBody(bodyText, searchQuery);
       div.classList.add("match");
       if (msg.id === highlightMessageId) {
@@ -3,6 +3,7 @@ export type ThreadSummary = {
   name?: string | null;
   last_message_at?: number | null;
   message_count: number;
+  expire_timer_ms?: number | null;
 };
 
 export type MessageRow = {
@@ -17,11 +18,27 @@ export type MessageRow = {
   is_view_once: boolean;
   quote_message_id?: string | null;
   metadata_json?: string | null;
+  delivered_at?: number | null;
+  read_at?: number | null;
 };
 
 export type SearchHit = {
   message: MessageRow;
   rank: number;
+  matched_in_quote: boolean;
+};
+
+export type SearchOptions = {
+  thread_id?: string | null;
+  tag_id?: string | null;
+  only_tagged?: boolean;
+  prefix?: boolean;
+  exact?: boolean;
+  from_ts?: number | null;
+  to_ts?: number | null;
+  body_weight?: number;
+  quote_weight?: number;
+  recency_half_life_days?: number | null;
 };
 
 export type ReactionSummary = {
@@ -30,6 +47,14 @@ export type ReactionSummary = {
   count: number;
 };
 
+export type Mention = {
+  message_id: string;
+  recipient_id: string;
+  display_name: string | null;
+  range_start: number;
+  range_length: number;
+};
+
 export type AttachmentRow = {
   id: string;
   message_id: string;
@@ -41,6 +66,13 @@ export type AttachmentRow = {
   width?: number | null;
   height?: number | null;
   duration_ms?: number | null;
+  blurhash?: string | null;
+};
+
+export type AttachmentDetail = {
+  media: AttachmentRow;
+  message: MessageRow;
+  thread_name: string | null;
 };
 
 export type ThreadMediaRow = {
@@ -57,6 +89,41 @@ export type ThreadMediaRow = {
   duration_ms?: number | null;
   sent_at?: number | null;
   received_at?: number | null;
+  blurhash?: string | null;
+};
+
+export type LargeAttachment = {
+  media: ThreadMediaRow;
+  thread_name?: string | null;
+};
+
+export type ThreadStorage = {
+  thread_id: string;
+  thread_name?: string | null;
+  blob_count: number;
+  total_bytes: number;
+};
+
+export type StorageUsage = {
+  threads: ThreadStorage[];
+  unique_total_bytes: number;
+};
+
+export type MediaKindCount = {
+  kind?: string | null;
+  count: number;
+};
+
+export type ThreadMediaSummary = {
+  total_count: number;
+  total_bytes: number;
+  kinds: MediaKindCount[];
+};
+
+export type MediaMonthBucket = {
+  year: number;
+  month: number;
+  item_count: number;
 };
 
 export type MediaAsset = {
@@ -73,7 +140,7 @@ export type MediaAsset = {
 
 export type SourceInfo = {
   src: string;
-  via: "file" | "data";
+  via: "stream" | "file" | "data";
 };
 
 export type Tag = {
@@ -84,13 +151,268 @@ export type Tag = {
   display_order: number;
 };
 
+export type TaggedTag = {
+  tag: Tag;
+  tagged_at: number;
+};
+
 export type MessageTags = {
   message_id: string;
+  tags: TaggedTag[];
+};
+
+export type TagStats = {
+  tag_id: string;
+  message_count: number;
+  thread_count: number;
+  first_tagged_at: number | null;
+  last_tagged_at: number | null;
+  first_message_ts: number | null;
+  last_message_ts: number | null;
+  attachment_count: number;
+};
+
+export type TagImportSummary = {
+  tags_created: number;
+  tags_matched: number;
+  message_tags_applied: number;
+  message_tags_skipped: number;
+  attachment_tags_applied: number;
+  attachment_tags_skipped: number;
+};
+
+export type AttachmentTags = {
+  attachment_id: string;
+  tags: Tag[];
+};
+
+export type MessageNote = {
+  message_id: string;
+  note_text: string;
+  updated_at: number;
+};
+
+export type MessageDetail = {
+  message: MessageRow;
   tags: Tag[];
+  note: MessageNote | null;
+};
+
+export type ScrapbookOrder = "tagged_at" | "message_time";
+
+export type ExportFormat = "markdown" | "html";
+
+export type ThreadMediaFilters = {
+  from_ts: number | null;
+  to_ts: number | null;
+  size_bucket: number | null;
+  kind: string | null;
+};
+
+export type MediaProgress = {
+  sha256: string;
+  done: number;
+  total: number;
+};
+
+export type MediaCacheStats = {
+  thumbs_bytes: number;
+  thumbs_files: number;
+  thumbs_cap_bytes: number;
+  preview_bytes: number;
+  preview_files: number;
+};
+
+/**
+ * What `attachment_data_url_cmd` resolves with. Large images are re-encoded at
+ * `width` x `height`, with `downscaled` set; the original is still at `attachment_path_cmd`.
+ */
+export type DataUrlResponse = {
+  url: string;
+  width: number | null;
+  height: number | null;
+  downscaled: boolean;
+};
+
+/** How `attachment_data_url_cmd` rejects. `too_large` compares plaintext sizes in bytes. */
+export type DataUrlError =
+  | { kind: "too_large"; size: number; limit: number }
+  | { kind: "failed"; message: string };
+
+/** One lightbox neighbour to decrypt into the preview cache ahead of time. */
+export type PrefetchRequest = {
+  sha256: string;
+  mime: string | null;
+};
+
+export type ThumbRequest = {
+  sha256: string;
+  mime: string | null;
+  maxSize: number;
+};
+
+/** One batched thumbnail: a data URL, or why there isn't one. */
+export type ThumbResult = {
+  url: string | null;
+  error: string | null;
+};
+
+export type ArchiveBundleSummary = {
+  attachments: number;
+  unreadable: number;
+};
+
+export type MediaExportSummary = {
+  total: number;
+  exported: number;
+  missing: number;
+  failed: number;
 };
 
 export type ScrapbookMessage = {
   message: MessageRow;
   thread_name?: string | null;
   is_discontinuous: boolean;
+  tags: TaggedTag[];
+};
+
+export type ScrapbookPage = {
+  items: ScrapbookMessage[];
+  next_before_ts: number | null;
+  next_before_id: string | null;
+};
+
+export type ScrapbookContextMessage = {
+  message: MessageRow;
+  is_tagged: boolean;
+};
+
+export type MissingAttachment = {
+  sha256: string;
+  reference_count: number;
+  message_id: string;
+  thread_id?: string | null;
+  original_filename?: string | null;
+};
+
+export type AttachmentAuditReport = {
+  checked: number;
+  missing: MissingAttachment[];
+};
+
+export type GcReport = {
+  candidates: string[];
+  bytes: number;
+  deleted: number;
+};
+
+export type ResniffReport = {
+  checked: number;
+  updated: number;
+  missing: number;
+  failed: number;
+};
+
+export type CorruptAttachment = {
+  sha256: string;
+  message_id: string;
+  reason: string;
+};
+
+export type AttachmentVerifyReport = {
+  checked: number;
+  verified: number;
+  missing: number;
+  legacy_format: number;
+  corrupt: CorruptAttachment[];
+};
+
+export type ChunkBench = {
+  chunk_size: number;
+  encrypt_mb_s: number;
+  decrypt_mb_s: number;
+};
+
+export type BenchReport = {
+  sample_mb: number;
+  results: ChunkBench[];
+  disk_write_mb_s: number;
+  recommended_chunk_size: number;
+};
+
+export type CryptoSelfTestReport = {
+  passed: boolean;
+  aes_gcm: boolean;
+  hkdf: boolean;
+  stream_round_trip: boolean;
+  tamper_detected: boolean;
+};
+
+export type CryptoDiagnostics = {
+  key_fingerprint: string | null;
+  self_test: CryptoSelfTestReport;
+};
+
+export type DimensionBackfillReport = {
+  checked: number;
+  updated: number;
+  missing: number;
+  failed: number;
+};
+
+export type BlurhashBackfillReport = {
+  checked: number;
+  updated: number;
+  missing: number;
+  failed: number;
+};
+
+export type ImportStage =
+  | "hashing"
+  | "decoding"
+  | "recipients"
+  | "threads"
+  | "messages"
+  | "attachments"
+  | "reactions"
+  | "fts"
+  | "finalizing";
+
+export type ImportProgress = {
+  stage: ImportStage;
+  current: number;
+  total?: number | null;
+  detail?: string | null;
+  bytes_done?: number | null;
+  bytes_total?: number | null;
+};
+
+export type BackupInspection = {
+  detected_version?: string | null;
+  threads: number;
+  sms: number;
+  mms: number;
+  attachments: number;
+  attachment_bytes: number;
+  first_message_at?: number | null;
+  last_message_at?: number | null;
+};
+
+export type ImportIssue = {
+  category: string;
+  source_table: string;
+  source_id?: string | null;
+  detail?: string | null;
+};
+
+export type ImportRecord = {
+  id: string;
+  imported_at: number;
+  source_filename: string;
+  status: string;
+  detected_version?: string | null;
+  messages_inserted?: number | null;
+  attachments_found?: number | null;
+  attachments_missing?: number | null;
+  error?: string | null;
 };
@@ -1,5 +1,5 @@
 import { describe, expect, it, vi } from "vitest";
-import { escapeHtml, highlightBody, messageSortTs, throttleRaf } from "./utils";
+import { escapeHtml, formatExpireTimer, formatImportProgress, highlightBody, messageSortTs, throttleRaf } from "./utils";
 
 describe("utils", () => {
   it("messageSortTs prefers sent_at then received_at", () => {
@@ -32,4 +32,25 @@ describe("utils", () => {
     expect(calls).toEqual([1, 3]);
     vi.useRealTimers();
   });
+
+  it("formatExpireTimer uses the largest whole unit", () => {
+    expect(formatExpireTimer(604_800_000)).toBe("1w");
+    expect(formatExpireTimer(28_800_000)).toBe("8h");
+    expect(formatExpireTimer(30_000)).toBe("30s");
+    expect(formatExpireTimer(0)).toBeNull();
+    expect(formatExpireTimer(null)).toBeNull();
+  });
+
+  it("formatImportProgress shows the stage, percent and detail", () => {
+    expect(formatImportProgress({ stage: "messages", current: 5000, total: 20000, detail: "SMS" })).toBe(
+      "Importing messages... 25% (SMS)",
+    );
+    expect(formatImportProgress({ stage: "fts", current: 0, total: null, detail: "up to date" })).toBe(
+      "Building search index... (up to date)",
+    );
+    expect(formatImportProgress({ stage: "finalizing", current: 0 })).toBe("Finalizing import...");
+    expect(
+      formatImportProgress({ stage: "attachments", current: 900, total: 1000, bytes_done: 50, bytes_total: 200 }),
+    ).toBe("Importing attachments... 25%");
+  });
 });
@@ -1,4 +1,4 @@
-import type { MessageRow, ThreadMediaRow } from "./types";
+import type { ImportProgress, ImportStage, MessageRow, ThreadMediaRow } from "./types";
 
 export function messageSortTs(message: MessageRow): number {
   return message.sent_at ?? message.received_at ?? 0;
@@ -121,3 +121,51 @@ export function sizeBucket(value?: string | null): number | null {
   }
   return null;
 }
+
+export function formatExpireTimer(ms?: number | null): string | null {
+  if (!ms || ms <= 0) return null;
+  const seconds = Math.round(ms / 1000);
+  const units: [number, string][] = [
+    [7 * 86400, "w"],
+    [86400, "d"],
+    [3600, "h"],
+    [60, "m"],
+  ];
+  for (const [size, suffix] of units) {
+    if (seconds % size === 0) return `${seconds / size}${suffix}`;
+  }
+  return `${seconds}s`;
+}
+
+const IMPORT_STAGE_LABELS: Record<ImportStage, string> = {
+  hashing: "Preparing import",
+  decoding: "Decoding backup",
+  recipients: "Importing recipients",
+  threads: "Importing threads",
+  messages: "Importing messages",
+  attachments: "Importing attachments",
+  reactions: "Importing reactions",
+  fts: "Building search index",
+  finalizing: "Finalizing import",
+};
+
+export function importProgressPercent(progress: ImportProgress): number | null {
+  // Attachment sizes vary too much for item counts to track time, so prefer bytes.
+  if (progress.bytes_total) {
+    return Math.min(100, Math.floor(((progress.bytes_done ?? 0) / progress.bytes_total) * 100));
+  }
+  if (!progress.total) return null;
+  return Math.min(100, Math.floor((progress.current / progress.total) * 100));
+}
+
+export function formatImportProgress(progress: ImportProgress): string {
+  let text = `${IMPORT_STAGE_LABELS[progress.stage] ?? progress.stage}...`;
+  const percent = importProgressPercent(progress);
+  if (percent !== null) {
+    text += ` ${percent}%`;
+  }
+  if (progress.detail) {
+    text += ` (${progress.detail})`;
+  }
+  return text;
+}
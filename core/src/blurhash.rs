@@ -0,0 +1,144 @@
+//! [BlurHash](https://blurha.sh) placeholders for image attachments: a short base83 string
+//! of the image's lowest frequencies, painted by the gallery as a blurred preview before
+//! its thumbnail arrives. Computed from the plaintext at import, or by
+//! `maintenance::backfill_blurhash` for attachments imported before.
+
+use std::f32::consts::PI;
+
+const BASE83: &[u8; 83] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz#$%*+,-.:;=?@[]^_{|}~";
+/// Horizontal and vertical components: enough for a landscape photo's rough layout in 28
+/// characters.
+const COMPONENTS_X: usize = 4;
+const COMPONENTS_Y: usize = 3;
+/// Images are shrunk to fit this before hashing, since only the lowest frequencies are kept.
+const SAMPLE_SIZE: u32 = 32;
+/// Larger images aren't decoded for a placeholder; the gallery keeps its plain box for them.
+pub(crate) const MAX_BLURHASH_SOURCE_BYTES: usize = 64 * 1024 * 1024;
+
+/// The BlurHash of an encoded image, or `None` for formats the `image` crate can't decode
+/// (HEIC) and images over [`MAX_BLURHASH_SOURCE_BYTES`].
+pub(crate) fn image_blurhash(bytes: &[u8]) -> Option<String> {
+    if bytes.len() > MAX_BLURHASH_SOURCE_BYTES {
+        return None;
+    }
+    let sample = image::load_from_memory(bytes).ok()?.thumbnail(SAMPLE_SIZE, SAMPLE_SIZE).to_rgb8();
+    Some(encode(sample.width() as usize, sample.height() as usize, sample.as_raw()))
+}
+
+/// Encodes `width` x `height` RGB pixels, row by row.
+fn encode(width: usize, height: usize, rgb: &[u8]) -> String {
+    let linear: Vec<[f32; 3]> = rgb
+        .chunks_exact(3)
+        .map(|px| [srgb_to_linear(px[0]), srgb_to_linear(px[1]), srgb_to_linear(px[2])])
+        .collect();
+    let mut factors = Vec::with_capacity(COMPONENTS_X * COMPONENTS_Y);
+    for j in 0..COMPONENTS_Y {
+        for i in 0..COMPONENTS_X {
+            let normalisation = if i == 0 && j == 0 { 1.0 } else { 2.0 };
+            let mut factor = [0.0f32; 3];
+            for y in 0..height {
+                let basis_y = (PI * j as f32 * y as f32 / height as f32).cos();
+                for x in 0..width {
+                    let basis = basis_y * (PI * i as f32 * x as f32 / width as f32).cos();
+                    let px = linear[y * width + x];
+                    for c in 0..3 {
+                        factor[c] += basis * px[c];
+                    }
+                }
+            }
+            let scale = normalisation / (width * height) as f32;
+            factors.push(factor.map(|v| v * scale));
+        }
+    }
+
+    let mut hash = String::with_capacity(4 + 2 * factors.len());
+    push_base83(&mut hash, (COMPONENTS_X - 1 + (COMPONENTS_Y - 1) * 9) as u32, 1);
+    let (dc, ac) = factors.split_first().expect("at least one component");
+    let max_ac = ac.iter().flatten().fold(0.0f32, |max, v| max.max(v.abs()));
+    let quantised_max = ((max_ac * 166.0 - 0.5).floor()).clamp(0.0, 82.0) as u32;
+    let max_value = (quantised_max + 1) as f32 / 166.0;
+    push_base83(&mut hash, if ac.is_empty() { 0 } else { quantised_max }, 1);
+    let dc_value = (linear_to_srgb(dc[0]) << 16) + (linear_to_srgb(dc[1]) << 8) + linear_to_srgb(dc[2]);
+    push_base83(&mut hash, dc_value, 4);
+    for factor in ac {
+        let [r, g, b] = factor.map(|v| quantise_ac(v / max_value));
+        push_base83(&mut hash, r * 19 * 19 + g * 19 + b, 2);
+    }
+    hash
+}
+
+fn quantise_ac(v: f32) -> u32 {
+    (sign_pow(v, 0.5) * 9.0 + 9.5).floor().clamp(0.0, 18.0) as u32
+}
+
+fn sign_pow(v: f32, exp: f32) -> f32 {
+    v.abs().powf(exp).copysign(v)
+}
+
+fn srgb_to_linear(v: u8) -> f32 {
+    let v = v as f32 / 255.0;
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(v: f32) -> u32 {
+    let v = v.clamp(0.0, 1.0);
+    if v <= 0.003_130_8 {
+        (v * 12.92 * 255.0 + 0.5) as u32
+    } else {
+        ((1.055 * v.powf(1.0 / 2.4) - 0.055) * 255.0 + 0.5) as u32
+    }
+}
+
+fn push_base83(hash: &mut String, value: u32, digits: u32) {
+    for place in (0..digits).rev() {
+        let digit = (value / 83u32.pow(place)) % 83;
+        hash.push(BASE83[digit as usize] as char);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png(image: image::RgbImage) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        image
+            .write_to(&mut std::io::Cursor::new(&mut bytes), image::ImageFormat::Png)
+            .expect("png");
+        bytes
+    }
+
+    fn first_ac_red(hash: &str) -> usize {
+        let digits = hash[6..8].bytes().map(|b| BASE83.iter().position(|&c| c == b).expect("base83"));
+        digits.fold(0, |value, digit| value * 83 + digit) / (19 * 19)
+    }
+
+    #[test]
+    fn flat_images_match_the_reference_encoder() {
+        let hash = image_blurhash(&png(image::RgbImage::from_pixel(40, 30, image::Rgb([255, 0, 0])))).expect("hash");
+        assert_eq!(hash.len(), 28);
+        // DC 0xff0000 is "TI:j". The odd cosines don't sum to zero over a 32x24 sample, so the
+        // ACs aren't flat either; the reference implementation gives the same string.
+        assert_eq!(hash, "LDTI:j]9fQ]9|co1fQo1fQfQfQfQ");
+    }
+
+    #[test]
+    fn bright_left_halves_weigh_the_first_horizontal_component_up() {
+        let split = |bright_left: bool| {
+            image::RgbImage::from_fn(64, 16, move |x, _| {
+                let v = if (x < 32) == bright_left { 255 } else { 0 };
+                image::Rgb([v, v, v])
+            })
+        };
+        let left = image_blurhash(&png(split(true))).expect("hash");
+        let right = image_blurhash(&png(split(false))).expect("hash");
+        assert_eq!(&left[2..6], &right[2..6], "same average colour");
+        assert!(first_ac_red(&left) > 9, "{left}");
+        assert!(first_ac_red(&right) < 9, "{right}");
+        assert!(image_blurhash(b"not an image").is_none());
+    }
+}
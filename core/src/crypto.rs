@@ -1,11 +1,10 @@
 use std::fs::File;
 use std::io::{Read, Write};
 use std::os::unix::fs::FileExt;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{mpsc, Mutex, RwLock};
 use std::thread;
 use std::path::Path;
-use std::sync::OnceLock;
 
 use aes_gcm::aead::{Aead, KeyInit};
 use aes_gcm::{Aes256Gcm, Key, Nonce};
@@ -14,20 +13,54 @@ use keyring::Error as KeyringError;
 use rand::rngs::OsRng;
 use rand::RngCore;
 use sha2::{Digest, Sha256};
-use zeroize::Zeroizing;
+use zeroize::{Zeroize, Zeroizing};
 
 use crate::error::CoreError;
+use crate::models::CryptoSelfTestReport;
+
+#[path = "crypto/bench.rs"]
+mod bench;
+#[path = "crypto/cipher.rs"]
+mod cipher;
+#[path = "crypto/file_key.rs"]
+mod file_key;
+#[path = "crypto/format.rs"]
+mod format;
+#[path = "crypto/keyfile.rs"]
+mod keyfile;
+#[path = "crypto/rotation.rs"]
+mod rotation;
+
+pub use bench::{attachment_chunk_size, benchmark, has_tuned_chunk_size, BENCHMARK_CHUNK_SIZES};
+use cipher::ChunkCipher;
+pub use cipher::{attachment_cipher, set_attachment_cipher, CipherAlgorithm};
+use file_key::{new_file_key, unwrap_file_key, wrap_file_key, WRAPPED_KEY_LEN};
+pub use format::format_version_of;
+pub(crate) use keyfile::KdfCosts;
+pub use keyfile::{change_key_file_passphrase, create_key_file, unlock_key_file, KEY_FILE_NAME};
+pub use rotation::rotate_master_key;
 
 const KEYCHAIN_SERVICE: &str = "com.goldenthread.app";
 const KEYCHAIN_ACCOUNT: &str = "archive-master-key";
 const MASTER_KEY_ENV: &str = "GT_MASTER_KEY_HEX";
 
 const MAGIC: [u8; 4] = *b"GTAT";
-const VERSION: u8 = 1;
+/// Version 4 seals each blob under its own random key, stored wrapped at the end of the
+/// header (see `crypto/file_key.rs`); older versions use the master key directly. Version 3
+/// adds a cipher algorithm id after the version byte, followed by a nonce of that cipher's
+/// length. Version 2 authenticates each chunk's index and whether it is the last one as
+/// associated data, so truncating or reordering chunks fails to decrypt. Version 1 files,
+/// which have no associated data, are still read; versions 1 and 2 are AES-GCM.
+const VERSION: u8 = 4;
+const VERSION_SHARED_KEY: u8 = 3;
+const VERSION_NO_ALGORITHM: u8 = 2;
+const VERSION_NO_AAD: u8 = 1;
 const DEFAULT_CHUNK_SIZE: usize = 1024 * 1024;
 const MAX_CHUNK_SIZE: usize = 8 * 1024 * 1024;
 const TAG_LEN: usize = 16;
-const HEADER_LEN: u64 = 21;
+/// Header length of versions 1 and 2: magic, version, chunk size and a 12-byte nonce.
+const LEGACY_HEADER_LEN: u64 = 21;
+const MAX_NONCE_LEN: usize = 24;
 
 pub struct MasterKey(Zeroizing<[u8; 32]>);
 
@@ -54,6 +87,8 @@ pub enum KeyPurpose {
     Database,
     /// Key for attachment file encryption
     Attachments,
+    /// Input to [`key_fingerprint`], never used to encrypt anything
+    Fingerprint,
 }
 
 impl KeyPurpose {
@@ -61,6 +96,7 @@ impl KeyPurpose {
         match self {
             KeyPurpose::Database => b"golden-thread-db-v1",
             KeyPurpose::Attachments => b"golden-thread-attachments-v1",
+            KeyPurpose::Fingerprint => b"golden-thread-fingerprint-v1",
         }
     }
 }
@@ -78,7 +114,110 @@ pub fn derive_key(master: &MasterKey, purpose: KeyPurpose) -> Result<DerivedKey,
     Ok(DerivedKey(Zeroizing::new(okm)))
 }
 
-static MASTER_KEY_CACHE: OnceLock<[u8; 32]> = OnceLock::new();
+/// Hex SHA-256 of the master key's fingerprint expansion, safe to show or log: two installs
+/// print the same fingerprint exactly when they hold the same key, and neither the key nor
+/// any key used for encryption can be recovered from it.
+pub fn key_fingerprint(master: &MasterKey) -> String {
+    let hk = Hkdf::<Sha256>::new(None, master.as_bytes());
+    let mut okm = Zeroizing::new([0u8; 32]);
+    hk.expand(KeyPurpose::Fingerprint.info(), okm.as_mut())
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    hex::encode(Sha256::digest(okm.as_ref()))
+}
+
+/// AES-256-GCM test case 14 from the GCM specification: zero key, zero nonce and 16 zero
+/// bytes of plaintext.
+const SELF_TEST_GCM_CIPHERTEXT: &str = "cea7403d4d606b6e074ec5d3baf39d18d0d1c8a799996bf0265b98b5d48ab919";
+/// HKDF-SHA256 test case 1 from RFC 5869.
+const SELF_TEST_HKDF_OKM: &str =
+    "3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf34007208d5b887185865";
+
+/// Checks the primitives the archive depends on against known vectors and round-trips a
+/// multi-chunk attachment stream under a throwaway key. Never touches the master key.
+pub fn self_test() -> CryptoSelfTestReport {
+    let aes_gcm = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&[0u8; 32]))
+        .encrypt(Nonce::from_slice(&[0u8; 12]), [0u8; 16].as_ref())
+        .map(|ciphertext| hex::encode(ciphertext) == SELF_TEST_GCM_CIPHERTEXT)
+        .unwrap_or(false);
+
+    let salt: Vec<u8> = (0u8..=0x0c).collect();
+    let info: Vec<u8> = (0xf0u8..=0xf9).collect();
+    let mut okm = [0u8; 42];
+    let hkdf = Hkdf::<Sha256>::new(Some(&salt), &[0x0b; 22]).expand(&info, &mut okm).is_ok()
+        && hex::encode(okm) == SELF_TEST_HKDF_OKM;
+
+    let key = MasterKey(Zeroizing::new([0x5a; 32]));
+    let plaintext: Vec<u8> = (0..3 * 1024 + 17).map(|i| (i % 251) as u8).collect();
+    let mut sealed = Vec::new();
+    let stream_round_trip = encrypt_stream_chunk(&mut plaintext.as_slice(), &mut sealed, &key, 1024).is_ok() && {
+        let mut opened = Vec::new();
+        decrypt_stream(&mut sealed.as_slice(), &mut opened, &key).is_ok() && opened == plaintext
+    };
+    let tamper_detected = !sealed.is_empty() && {
+        let last = sealed.len() - 1;
+        sealed[last] ^= 1;
+        decrypt_stream(&mut sealed.as_slice(), &mut Vec::new(), &key).is_err()
+    };
+
+    CryptoSelfTestReport {
+        passed: aes_gcm && hkdf && stream_round_trip && tamper_detected,
+        aes_gcm,
+        hkdf,
+        stream_round_trip,
+        tamper_detected,
+    }
+}
+
+/// An Argon2id key for data sealed under a passphrase instead of the master key, usable
+/// with the stream functions below.
+pub(crate) fn passphrase_stream_key(passphrase: &str, salt: &[u8], costs: KdfCosts) -> Result<MasterKey, CoreError> {
+    Ok(MasterKey(keyfile::passphrase_key(passphrase, salt, costs)?))
+}
+
+/// The master key once loaded; replaced only by [`rotate_master_key`] and wiped by
+/// [`lock_master_key`].
+static MASTER_KEY_CACHE: RwLock<Option<[u8; 32]>> = RwLock::new(None);
+/// Set, under the cache's write lock, between [`lock_master_key`] and [`unlock_master_key`].
+static MASTER_KEY_LOCKED: AtomicBool = AtomicBool::new(false);
+
+fn cached_master_key() -> Option<[u8; 32]> {
+    *MASTER_KEY_CACHE.read().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Caches `bytes` unless a key is already cached, or unconditionally with `replace`. A
+/// no-op while locked, so a load that raced with [`lock_master_key`] doesn't undo it.
+fn cache_master_key(bytes: [u8; 32], replace: bool) {
+    let mut cache = MASTER_KEY_CACHE.write().unwrap_or_else(|e| e.into_inner());
+    if MASTER_KEY_LOCKED.load(Ordering::SeqCst) {
+        return;
+    }
+    if replace || cache.is_none() {
+        *cache = Some(bytes);
+    }
+}
+
+/// Wipes the cached master key and makes loading it fail with `CoreError::Locked` until
+/// [`unlock_master_key`]. Keys already handed out stay usable until their holders drop
+/// them, so callers should close database handles and media state as well.
+pub fn lock_master_key() {
+    let mut cache = MASTER_KEY_CACHE.write().unwrap_or_else(|e| e.into_inner());
+    MASTER_KEY_LOCKED.store(true, Ordering::SeqCst);
+    if let Some(bytes) = cache.as_mut() {
+        bytes.zeroize();
+    }
+    *cache = None;
+}
+
+/// Allows the master key to be loaded again after [`lock_master_key`]; the next load reads
+/// it from the keychain or key file.
+pub fn unlock_master_key() {
+    let _cache = MASTER_KEY_CACHE.write().unwrap_or_else(|e| e.into_inner());
+    MASTER_KEY_LOCKED.store(false, Ordering::SeqCst);
+}
+
+pub fn is_master_key_locked() -> bool {
+    MASTER_KEY_LOCKED.load(Ordering::SeqCst)
+}
 
 /// Test helper: derive a deterministic master key from a passphrase and install it
 /// for this process. Intended for tests only.
@@ -86,13 +225,16 @@ pub fn set_test_key_from_passphrase(passphrase: &str) {
     let digest = Sha256::digest(passphrase.as_bytes());
     let mut bytes = [0u8; 32];
     bytes.copy_from_slice(&digest);
-    let _ = MASTER_KEY_CACHE.set(bytes);
+    cache_master_key(bytes, false);
     std::env::set_var(MASTER_KEY_ENV, hex::encode(bytes));
 }
 
 pub fn load_or_create_master_key() -> Result<MasterKey, CoreError> {
-    if let Some(bytes) = MASTER_KEY_CACHE.get() {
-        return Ok(MasterKey(Zeroizing::new(*bytes)));
+    if is_master_key_locked() {
+        return Err(CoreError::Locked);
+    }
+    if let Some(bytes) = cached_master_key() {
+        return Ok(MasterKey(Zeroizing::new(bytes)));
     }
     // Environment variable override is only available in debug/test builds.
     // In release builds, environment variables are visible via `ps eww` which would
@@ -100,30 +242,69 @@ pub fn load_or_create_master_key() -> Result<MasterKey, CoreError> {
     #[cfg(any(debug_assertions, test))]
     if let Ok(hex) = std::env::var(MASTER_KEY_ENV) {
         let bytes = parse_hex_key(&hex)?;
-        let _ = MASTER_KEY_CACHE.set(bytes);
+        cache_master_key(bytes, false);
         return Ok(MasterKey(Zeroizing::new(bytes)));
     }
 
-    let entry = keyring::Entry::new(KEYCHAIN_SERVICE, KEYCHAIN_ACCOUNT)
-        .map_err(|e| CoreError::Crypto(format!("keychain init failed: {e}")))?;
+    match keychain_master_key()? {
+        Ok(key) => Ok(key),
+        Err((step, err)) => Err(CoreError::Crypto(format!("keychain {step} failed: {err}"))),
+    }
+}
+
+/// Like [`load_or_create_master_key`], but when the OS keychain itself is unusable (no
+/// keychain service, storage locked away) keeps the master key in the passphrase-protected
+/// key file at `key_file` instead. `passphrase_provider` is only asked for a passphrase in
+/// that case.
+pub fn load_or_create_master_key_with_fallback<F>(key_file: &Path, passphrase_provider: F) -> Result<MasterKey, CoreError>
+where
+    F: FnOnce() -> Result<String, CoreError>,
+{
+    if is_master_key_locked() {
+        return Err(CoreError::Locked);
+    }
+    if let Some(bytes) = cached_master_key() {
+        return Ok(MasterKey(Zeroizing::new(bytes)));
+    }
+    #[cfg(any(debug_assertions, test))]
+    if std::env::var(MASTER_KEY_ENV).is_ok() {
+        return load_or_create_master_key();
+    }
+    match keychain_master_key()? {
+        Ok(key) => Ok(key),
+        Err((_, KeyringError::PlatformFailure(_) | KeyringError::NoStorageAccess(_))) => {
+            let passphrase = Zeroizing::new(passphrase_provider()?);
+            keyfile::load_or_create_from_key_file(key_file, &passphrase)
+        }
+        Err((step, err)) => Err(CoreError::Crypto(format!("keychain {step} failed: {err}"))),
+    }
+}
+
+/// The master key from the keychain, created there on first use. The inner error names
+/// the keychain step that failed, for callers to report or fall back on.
+fn keychain_master_key() -> Result<Result<MasterKey, (&'static str, KeyringError)>, CoreError> {
+    let entry = match keyring::Entry::new(KEYCHAIN_SERVICE, KEYCHAIN_ACCOUNT) {
+        Ok(entry) => entry,
+        Err(err) => return Ok(Err(("init", err))),
+    };
 
     match entry.get_password() {
         Ok(secret) => {
             let bytes = parse_hex_key(&secret)?;
-            let _ = MASTER_KEY_CACHE.set(bytes);
-            Ok(MasterKey(Zeroizing::new(bytes)))
+            cache_master_key(bytes, false);
+            Ok(Ok(MasterKey(Zeroizing::new(bytes))))
         }
         Err(KeyringError::NoEntry) => {
             let mut key = [0u8; 32];
             OsRng.fill_bytes(&mut key);
             let hex = hex::encode(key);
-            entry
-                .set_password(&hex)
-                .map_err(|e| CoreError::Crypto(format!("keychain store failed: {e}")))?;
-            let _ = MASTER_KEY_CACHE.set(key);
-            Ok(MasterKey(Zeroizing::new(key)))
+            if let Err(err) = entry.set_password(&hex) {
+                return Ok(Err(("store", err)));
+            }
+            cache_master_key(key, false);
+            Ok(Ok(MasterKey(Zeroizing::new(key))))
         }
-        Err(err) => Err(CoreError::Crypto(format!("keychain read failed: {err}"))),
+        Err(err) => Ok(Err(("read", err))),
     }
 }
 
@@ -161,7 +342,8 @@ pub fn encrypt_stream_with_hash<R: Read, W: Write>(
     key: &MasterKey,
 ) -> Result<(String, u64), CoreError> {
     let mut hasher = Sha256::new();
-    let total = encrypt_stream_internal(reader, writer, key, DEFAULT_CHUNK_SIZE, Some(&mut hasher))?;
+    let total =
+        encrypt_stream_internal(reader, writer, key, DEFAULT_CHUNK_SIZE, attachment_cipher(), Some(&mut hasher))?;
     Ok((hex::encode(hasher.finalize()), total))
 }
 
@@ -172,7 +354,7 @@ pub fn encrypt_stream_with_hash_chunk<R: Read, W: Write>(
     chunk_size: usize,
 ) -> Result<(String, u64), CoreError> {
     let mut hasher = Sha256::new();
-    let total = encrypt_stream_internal(reader, writer, key, chunk_size, Some(&mut hasher))?;
+    let total = encrypt_stream_internal(reader, writer, key, chunk_size, attachment_cipher(), Some(&mut hasher))?;
     Ok((hex::encode(hasher.finalize()), total))
 }
 
@@ -181,7 +363,7 @@ pub fn encrypt_stream<R: Read, W: Write>(
     writer: &mut W,
     key: &MasterKey,
 ) -> Result<u64, CoreError> {
-    encrypt_stream_internal(reader, writer, key, DEFAULT_CHUNK_SIZE, None)
+    encrypt_stream_internal(reader, writer, key, DEFAULT_CHUNK_SIZE, attachment_cipher(), None)
 }
 
 pub fn encrypt_stream_chunk<R: Read, W: Write>(
@@ -190,7 +372,7 @@ pub fn encrypt_stream_chunk<R: Read, W: Write>(
     key: &MasterKey,
     chunk_size: usize,
 ) -> Result<u64, CoreError> {
-    encrypt_stream_internal(reader, writer, key, chunk_size, None)
+    encrypt_stream_internal(reader, writer, key, chunk_size, attachment_cipher(), None)
 }
 
 fn encrypt_stream_internal<R: Read, W: Write>(
@@ -198,37 +380,44 @@ fn encrypt_stream_internal<R: Read, W: Write>(
     writer: &mut W,
     key: &MasterKey,
     chunk_size: usize,
+    algorithm: CipherAlgorithm,
     mut hasher: Option<&mut Sha256>,
 ) -> Result<u64, CoreError> {
     if chunk_size == 0 || chunk_size > MAX_CHUNK_SIZE {
         return Err(CoreError::Crypto("invalid chunk size".to_string()));
     }
-    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key.as_bytes()));
-    let mut base_nonce = [0u8; 12];
-    OsRng.fill_bytes(&mut base_nonce);
-    write_header(writer, &base_nonce, chunk_size)?;
+    let (header, file_key) = Header::new(algorithm, chunk_size, key)?;
+    let cipher = ChunkCipher::new(algorithm, &file_key);
+    write_header(writer, &header)?;
 
-    let mut buf = vec![0u8; chunk_size];
+    // Reading one chunk ahead tells whether the current one is the last. An empty input
+    // still gets one (empty) final chunk, so cutting a file back to its header is detected.
+    // Both buffers hold plaintext and are wiped when dropped.
+    let mut buf = Zeroizing::new(vec![0u8; chunk_size]);
+    let mut next = Zeroizing::new(vec![0u8; chunk_size]);
+    let mut n = read_full(reader, &mut buf)?;
     let mut counter: u64 = 0;
     let mut total: u64 = 0;
 
     loop {
-        let n = reader.read(&mut buf).map_err(|e| CoreError::Crypto(e.to_string()))?;
-        if n == 0 {
-            break;
-        }
+        let next_n = if n == chunk_size { read_full(reader, &mut next)? } else { 0 };
+        let is_final = next_n == 0;
         if let Some(ref mut h) = hasher {
             h.update(&buf[..n]);
         }
-        let nonce = nonce_for_chunk(&base_nonce, counter);
-        let ct = cipher
-            .encrypt(Nonce::from_slice(&nonce), &buf[..n])
-            .map_err(|e| CoreError::Crypto(format!("encrypt failed: {e}")))?;
+        let nonce = header.chunk_nonce(counter);
+        let aad = chunk_aad(VERSION, counter, is_final);
+        let ct = cipher.seal(&nonce, &buf[..n], &aad)?;
         writer
             .write_all(&ct)
             .map_err(|e| CoreError::Crypto(e.to_string()))?;
         total = total.saturating_add(n as u64);
         counter = counter.saturating_add(1);
+        if is_final {
+            break;
+        }
+        std::mem::swap(&mut buf, &mut next);
+        n = next_n;
     }
     Ok(total)
 }
@@ -238,45 +427,168 @@ pub fn decrypt_stream<R: Read, W: Write>(
     writer: &mut W,
     key: &MasterKey,
 ) -> Result<u64, CoreError> {
-    let (chunk_size, base_nonce) = read_header(reader)?;
-    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key.as_bytes()));
+    decrypt_stream_with_progress(reader, writer, key, 0, |_, _| {})
+}
+
+/// [`decrypt_stream`], calling `progress(bytes_done, bytes_total)` after each chunk.
+/// `total` is the plaintext size, from [`encrypted_plaintext_len`]; it is only passed
+/// through to `progress`.
+pub fn decrypt_stream_with_progress<R, W, F>(
+    reader: &mut R,
+    writer: &mut W,
+    key: &MasterKey,
+    total: u64,
+    progress: F,
+) -> Result<u64, CoreError>
+where
+    R: Read,
+    W: Write,
+    F: Fn(u64, u64),
+{
+    decrypt_stream_cancellable(reader, writer, key, total, &AtomicBool::new(false), progress)
+}
+
+/// [`decrypt_stream_with_progress`], failing with [`CoreError::Cancelled`] before the next
+/// chunk once `cancel` is set.
+fn decrypt_stream_cancellable<R, W, F>(
+    reader: &mut R,
+    writer: &mut W,
+    key: &MasterKey,
+    total: u64,
+    cancel: &AtomicBool,
+    progress: F,
+) -> Result<u64, CoreError>
+where
+    R: Read,
+    W: Write,
+    F: Fn(u64, u64),
+{
+    let header = read_header(reader)?;
+    let cipher = header.cipher(key)?;
     let mut counter: u64 = 0;
-    let mut total: u64 = 0;
+    let mut done: u64 = 0;
 
-    let ct_chunk_size = chunk_size
+    let ct_chunk_size = header
+        .chunk_size
         .checked_add(TAG_LEN)
         .ok_or_else(|| CoreError::Crypto("chunk size overflow".to_string()))?;
     let mut buf = vec![0u8; ct_chunk_size];
+    let mut next = vec![0u8; ct_chunk_size];
+    let mut read = read_full(reader, &mut buf)?;
+    if read == 0 && header.version != VERSION_NO_AAD {
+        return Err(CoreError::Crypto("truncated attachment".to_string()));
+    }
 
-    loop {
-        let mut read = 0;
-        while read < buf.len() {
-            let n = reader
-                .read(&mut buf[read..])
-                .map_err(|e| CoreError::Crypto(e.to_string()))?;
-            if n == 0 {
-                break;
-            }
-            read += n;
-        }
-        if read == 0 {
-            break;
+    while read > 0 {
+        if cancel.load(Ordering::Relaxed) {
+            return Err(CoreError::Cancelled);
         }
-        let nonce = nonce_for_chunk(&base_nonce, counter);
-        let pt = cipher
-            .decrypt(Nonce::from_slice(&nonce), &buf[..read])
-            .map_err(|e| CoreError::Crypto(format!("decrypt failed: {e}")))?;
+        let next_read = if read == ct_chunk_size { read_full(reader, &mut next)? } else { 0 };
+        let is_final = next_read == 0;
+        let pt = open_chunk(&cipher, &header, counter, is_final, &buf[..read])?;
         writer
             .write_all(&pt)
             .map_err(|e| CoreError::Crypto(e.to_string()))?;
-        total = total.saturating_add(pt.len() as u64);
+        done = done.saturating_add(pt.len() as u64);
+        progress(done, total);
         counter = counter.saturating_add(1);
-        if read < buf.len() {
-            break;
-        }
+        std::mem::swap(&mut buf, &mut next);
+        read = next_read;
     }
 
-    Ok(total)
+    Ok(done)
+}
+
+/// Decrypts `reader` with `key` on a helper thread and hands the plaintext to `consume`
+/// through a pipe, so re-encrypting under another key never puts plaintext on disk. Returns
+/// the decrypt result, which fails for a source `key` doesn't open, and `consume`'s.
+pub(crate) fn pipe_decrypted<R, T, F>(
+    reader: &mut R,
+    key: &MasterKey,
+    consume: F,
+) -> (Result<u64, CoreError>, Result<T, CoreError>)
+where
+    R: Read + Send,
+    F: FnOnce(&mut std::io::PipeReader) -> Result<T, CoreError>,
+{
+    let (mut plain, writer) = match std::io::pipe() {
+        Ok(pipe) => pipe,
+        Err(err) => return (Err(CoreError::Crypto(err.to_string())), Err(CoreError::Crypto(err.to_string()))),
+    };
+    thread::scope(|scope| {
+        let decrypt = scope.spawn(move || {
+            let mut writer = writer;
+            decrypt_stream(reader, &mut writer, key)
+        });
+        let consumed = consume(&mut plain);
+        // Drain whatever `consume` left so stopping early doesn't block the decryptor.
+        let _ = std::io::copy(&mut plain, &mut std::io::sink());
+        let decrypted = decrypt
+            .join()
+            .unwrap_or_else(|_| Err(CoreError::Crypto("decrypt thread panicked".to_string())));
+        (decrypted, consumed)
+    })
+}
+
+/// Decrypts only the first chunk of an encrypted attachment, enough to inspect its
+/// header bytes without reading the whole blob. The plaintext is wiped when dropped.
+pub fn decrypt_first_chunk(src: &Path, key: &MasterKey) -> Result<Zeroizing<Vec<u8>>, CoreError> {
+    let file = File::open(src).map_err(|e| CoreError::Crypto(e.to_string()))?;
+    let mut reader = std::io::BufReader::new(file);
+    let header = read_header(&mut reader)?;
+    let ct_chunk_size = header.chunk_size + TAG_LEN;
+    // One byte past the chunk says whether another chunk follows it.
+    let mut buf = Vec::with_capacity(ct_chunk_size + 1);
+    reader
+        .take(ct_chunk_size as u64 + 1)
+        .read_to_end(&mut buf)
+        .map_err(|e| CoreError::Crypto(e.to_string()))?;
+    if buf.is_empty() && header.version == VERSION_NO_AAD {
+        return Ok(Zeroizing::new(Vec::new()));
+    }
+    let is_final = buf.len() <= ct_chunk_size;
+    buf.truncate(ct_chunk_size);
+    let cipher = header.cipher(key)?;
+    open_chunk(&cipher, &header, 0, is_final, &buf)
+}
+
+/// Decrypts plaintext bytes `offset..offset + len` of an encrypted blob, reading and
+/// verifying only the chunks that cover them. A range running past the end is cut short;
+/// one starting past it is an error. The plaintext is wiped when dropped.
+pub fn decrypt_range(path: &Path, key: &MasterKey, offset: u64, len: u64) -> Result<Zeroizing<Vec<u8>>, CoreError> {
+    let mut file = File::open(path).map_err(|e| CoreError::Crypto(e.to_string()))?;
+    let header = read_header(&mut file)?;
+    let total_plain = encrypted_plaintext_len(path)?;
+    if offset > total_plain {
+        return Err(CoreError::InvalidArgument("range starts past the end of the attachment".to_string()));
+    }
+    let end = offset.saturating_add(len).min(total_plain);
+    if offset == end {
+        return Ok(Zeroizing::new(Vec::new()));
+    }
+    let chunk_size = header.chunk_size as u64;
+    let ct_chunk_size = chunk_size + TAG_LEN as u64;
+    let payload_len = file
+        .metadata()
+        .map_err(|e| CoreError::Crypto(e.to_string()))?
+        .len()
+        - header.len();
+    let total_chunks = payload_len.div_ceil(ct_chunk_size);
+
+    let cipher = header.cipher(key)?;
+    // Sized up front so the buffer never reallocates and leaves plaintext copies behind.
+    let mut out = Zeroizing::new(Vec::with_capacity((end - offset) as usize));
+    for idx in offset / chunk_size..=(end - 1) / chunk_size {
+        let chunk_start = idx * chunk_size;
+        let plain_len = chunk_size.min(total_plain - chunk_start);
+        let mut ct_buf = vec![0u8; plain_len as usize + TAG_LEN];
+        read_exact_at(&file, &mut ct_buf, header.len() + idx * ct_chunk_size)?;
+        let pt = open_chunk(&cipher, &header, idx, idx == total_chunks - 1, &ct_buf)?;
+        let from = offset.saturating_sub(chunk_start) as usize;
+        let to = (end - chunk_start).min(plain_len) as usize;
+        out.extend_from_slice(&pt[from..to]);
+    }
+    Ok(out)
 }
 
 pub fn encrypt_file_to_path(src: &Path, dest: &Path, key: &MasterKey) -> Result<u64, CoreError> {
@@ -301,24 +613,84 @@ fn parse_hex_key(hex: &str) -> Result<[u8; 32], CoreError> {
     Ok(key)
 }
 
-fn write_header<W: Write>(writer: &mut W, base_nonce: &[u8; 12], chunk_size: usize) -> Result<(), CoreError> {
-    writer
-        .write_all(&MAGIC)
-        .map_err(|e| CoreError::Crypto(e.to_string()))?;
-    writer
-        .write_all(&[VERSION])
-        .map_err(|e| CoreError::Crypto(e.to_string()))?;
-    let chunk_size = (chunk_size as u32).to_le_bytes();
+/// Writes a current-version `header`, ending with its wrapped file key.
+fn write_header<W: Write>(writer: &mut W, header: &Header) -> Result<(), CoreError> {
     writer
-        .write_all(&chunk_size)
+        .write_all(&header.authenticated_bytes())
         .map_err(|e| CoreError::Crypto(e.to_string()))?;
     writer
-        .write_all(base_nonce)
+        .write_all(&header.wrapped_key)
         .map_err(|e| CoreError::Crypto(e.to_string()))?;
     Ok(())
 }
 
-fn read_header<R: Read>(reader: &mut R) -> Result<(usize, [u8; 12]), CoreError> {
+struct Header {
+    version: u8,
+    algorithm: CipherAlgorithm,
+    chunk_size: usize,
+    /// Only the algorithm's nonce length is used.
+    base_nonce: [u8; MAX_NONCE_LEN],
+    /// The file key wrapped under the attachments key; zero before version 4.
+    wrapped_key: [u8; WRAPPED_KEY_LEN],
+}
+
+impl Header {
+    /// A current-version header with a fresh random base nonce and file key, wrapped under
+    /// `key`. The file key is returned for sealing the chunks.
+    fn new(
+        algorithm: CipherAlgorithm,
+        chunk_size: usize,
+        key: &MasterKey,
+    ) -> Result<(Self, Zeroizing<[u8; 32]>), CoreError> {
+        let mut base_nonce = [0u8; MAX_NONCE_LEN];
+        OsRng.fill_bytes(&mut base_nonce[..algorithm.nonce_len()]);
+        let mut header = Header {
+            version: VERSION,
+            algorithm,
+            chunk_size,
+            base_nonce,
+            wrapped_key: [0u8; WRAPPED_KEY_LEN],
+        };
+        let file_key = new_file_key();
+        header.wrapped_key = wrap_file_key(key, &file_key, &header.authenticated_bytes())?;
+        Ok((header, file_key))
+    }
+
+    /// Bytes before the first chunk.
+    fn len(&self) -> u64 {
+        match self.version {
+            VERSION => (MAGIC.len() + 2 + 4 + self.algorithm.nonce_len() + WRAPPED_KEY_LEN) as u64,
+            VERSION_SHARED_KEY => (MAGIC.len() + 2 + 4 + self.algorithm.nonce_len()) as u64,
+            _ => LEGACY_HEADER_LEN,
+        }
+    }
+
+    /// A version 3 or later header up to the wrapped key, which authenticates them.
+    fn authenticated_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(MAGIC.len() + 2 + 4 + MAX_NONCE_LEN);
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&[self.version, self.algorithm.id()]);
+        bytes.extend_from_slice(&(self.chunk_size as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.base_nonce[..self.algorithm.nonce_len()]);
+        bytes
+    }
+
+    /// The chunk cipher: keyed with the unwrapped file key from version 4, else with `key`.
+    fn cipher(&self, key: &MasterKey) -> Result<ChunkCipher, CoreError> {
+        if self.version == VERSION {
+            let file_key = unwrap_file_key(key, &self.wrapped_key, &self.authenticated_bytes())?;
+            Ok(ChunkCipher::new(self.algorithm, &file_key))
+        } else {
+            Ok(ChunkCipher::new(self.algorithm, key.as_bytes()))
+        }
+    }
+
+    fn chunk_nonce(&self, counter: u64) -> Vec<u8> {
+        nonce_for_chunk(&self.base_nonce[..self.algorithm.nonce_len()], counter)
+    }
+}
+
+fn read_header<R: Read>(reader: &mut R) -> Result<Header, CoreError> {
     let mut magic = [0u8; 4];
     reader
         .read_exact(&mut magic)
@@ -330,9 +702,18 @@ fn read_header<R: Read>(reader: &mut R) -> Result<(usize, [u8; 12]), CoreError>
     reader
         .read_exact(&mut version)
         .map_err(|e| CoreError::Crypto(e.to_string()))?;
-    if version[0] != VERSION {
+    if ![VERSION, VERSION_SHARED_KEY, VERSION_NO_ALGORITHM, VERSION_NO_AAD].contains(&version[0]) {
         return Err(CoreError::Crypto("unsupported attachment version".to_string()));
     }
+    let algorithm = if version[0] >= VERSION_SHARED_KEY {
+        let mut id = [0u8; 1];
+        reader
+            .read_exact(&mut id)
+            .map_err(|e| CoreError::Crypto(e.to_string()))?;
+        CipherAlgorithm::from_id(id[0])?
+    } else {
+        CipherAlgorithm::Aes256Gcm
+    };
     let mut chunk = [0u8; 4];
     reader
         .read_exact(&mut chunk)
@@ -341,26 +722,62 @@ fn read_header<R: Read>(reader: &mut R) -> Result<(usize, [u8; 12]), CoreError>
     if chunk_size == 0 || chunk_size > MAX_CHUNK_SIZE {
         return Err(CoreError::Crypto("invalid chunk size".to_string()));
     }
-    let mut nonce = [0u8; 12];
+    let mut base_nonce = [0u8; MAX_NONCE_LEN];
     reader
-        .read_exact(&mut nonce)
+        .read_exact(&mut base_nonce[..algorithm.nonce_len()])
         .map_err(|e| CoreError::Crypto(e.to_string()))?;
-    Ok((chunk_size, nonce))
+    let mut wrapped_key = [0u8; WRAPPED_KEY_LEN];
+    if version[0] == VERSION {
+        reader
+            .read_exact(&mut wrapped_key)
+            .map_err(|e| CoreError::Crypto(e.to_string()))?;
+    }
+    Ok(Header {
+        version: version[0],
+        algorithm,
+        chunk_size,
+        base_nonce,
+        wrapped_key,
+    })
+}
+
+/// Fills `buf` as far as the reader allows, returning how much was read; less than
+/// `buf.len()` only at end of input.
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<usize, CoreError> {
+    let mut read = 0;
+    while read < buf.len() {
+        let n = reader
+            .read(&mut buf[read..])
+            .map_err(|e| CoreError::Crypto(e.to_string()))?;
+        if n == 0 {
+            break;
+        }
+        read += n;
+    }
+    Ok(read)
+}
+
+/// Whether the blob at `path` is in format version 1, whose chunks don't authenticate their
+/// position: a v1 file cut at a chunk boundary still decrypts, to a shorter plaintext, so
+/// only a hash check like `maintenance::verify_attachments` catches it.
+pub fn is_legacy_format(path: &Path) -> Result<bool, CoreError> {
+    Ok(format_version_of(path)? == VERSION_NO_AAD)
 }
 
 pub fn encrypted_plaintext_len(path: &Path) -> Result<u64, CoreError> {
     let mut file = File::open(path).map_err(|e| CoreError::Crypto(e.to_string()))?;
     let meta = file.metadata().map_err(|e| CoreError::Crypto(e.to_string()))?;
     let total_len = meta.len();
-    if total_len < HEADER_LEN + TAG_LEN as u64 {
+    let header = read_header(&mut file)?;
+    if total_len < header.len() + TAG_LEN as u64 {
         return Err(CoreError::Crypto("encrypted file too small".to_string()));
     }
-    let (chunk_size, _nonce) = read_header(&mut file)?;
+    let chunk_size = header.chunk_size;
     let ct_chunk_size = (chunk_size as u64)
         .checked_add(TAG_LEN as u64)
         .ok_or_else(|| CoreError::Crypto("chunk size overflow".to_string()))?;
     let payload_len = total_len
-        .checked_sub(HEADER_LEN)
+        .checked_sub(header.len())
         .ok_or_else(|| CoreError::Crypto("invalid encrypted length".to_string()))?;
     if payload_len == 0 {
         return Ok(0);
@@ -374,75 +791,274 @@ pub fn encrypted_plaintext_len(path: &Path) -> Result<u64, CoreError> {
     Ok(full_chunks * chunk_size as u64 + last_plain)
 }
 
+/// Plaintext size from which [`decrypt_attachment_to_path`] decrypts chunks in parallel.
+pub const PARALLEL_DECRYPT_THRESHOLD: u64 = 10 * 1024 * 1024;
+const PARALLEL_DECRYPT_WORKERS: usize = 4;
+
+/// Decrypts an attachment blob to `dest`, switching to [`decrypt_file_parallel`] for
+/// large files.
+pub fn decrypt_attachment_to_path(src: &Path, dest: &Path, key: &MasterKey) -> Result<u64, CoreError> {
+    decrypt_attachment_to_path_with_progress(src, dest, key, |_, _| {})
+}
+
+/// [`decrypt_attachment_to_path`], calling `progress(bytes_done, bytes_total)` as chunks
+/// are decrypted.
+pub fn decrypt_attachment_to_path_with_progress<F>(
+    src: &Path,
+    dest: &Path,
+    key: &MasterKey,
+    progress: F,
+) -> Result<u64, CoreError>
+where
+    F: Fn(u64, u64) + Sync,
+{
+    decrypt_attachment_to_path_cancellable(src, dest, key, &AtomicBool::new(false), progress)
+}
+
+/// [`decrypt_attachment_to_path_with_progress`] that stops between chunks once `cancel` is
+/// set, failing with [`CoreError::Cancelled`]. `dest` is left partly written; the caller
+/// removes it.
+pub fn decrypt_attachment_to_path_cancellable<F>(
+    src: &Path,
+    dest: &Path,
+    key: &MasterKey,
+    cancel: &AtomicBool,
+    progress: F,
+) -> Result<u64, CoreError>
+where
+    F: Fn(u64, u64) + Sync,
+{
+    match encrypted_plaintext_len(src) {
+        Ok(len) if len >= PARALLEL_DECRYPT_THRESHOLD => {
+            decrypt_file_parallel_cancellable(src, dest, key, PARALLEL_DECRYPT_WORKERS, cancel, progress)
+        }
+        Ok(len) => {
+            let mut reader = File::open(src).map_err(|e| CoreError::Crypto(e.to_string()))?;
+            let mut writer = File::create(dest).map_err(|e| CoreError::Crypto(e.to_string()))?;
+            decrypt_stream_cancellable(&mut reader, &mut writer, key, len, cancel, progress)
+        }
+        Err(_) => decrypt_file_to_path(src, dest, key),
+    }
+}
+
 pub fn decrypt_file_parallel(
     input: &Path,
     output: &Path,
     key: &MasterKey,
     workers: usize,
 ) -> Result<u64, CoreError> {
+    decrypt_file_parallel_with_progress(input, output, key, workers, |_, _| {})
+}
+
+/// [`decrypt_file_parallel`], calling `progress(bytes_done, bytes_total)` from the worker
+/// threads as each chunk lands.
+pub fn decrypt_file_parallel_with_progress<F>(
+    input: &Path,
+    output: &Path,
+    key: &MasterKey,
+    workers: usize,
+    progress: F,
+) -> Result<u64, CoreError>
+where
+    F: Fn(u64, u64) + Sync,
+{
+    decrypt_file_parallel_cancellable(input, output, key, workers, &AtomicBool::new(false), progress)
+}
+
+fn decrypt_file_parallel_cancellable<F>(
+    input: &Path,
+    output: &Path,
+    key: &MasterKey,
+    workers: usize,
+    cancel: &AtomicBool,
+    progress: F,
+) -> Result<u64, CoreError>
+where
+    F: Fn(u64, u64) + Sync,
+{
     if workers == 0 {
         return Err(CoreError::Crypto("workers must be >= 1".to_string()));
     }
     let mut header_file = File::open(input).map_err(|e| CoreError::Crypto(e.to_string()))?;
-    let (chunk_size, base_nonce) = read_header(&mut header_file)?;
+    let header = read_header(&mut header_file)?;
+    let chunk_size = header.chunk_size;
     let total_plain = encrypted_plaintext_len(input)?;
-    if total_plain == 0 {
+    let ct_chunk_size = chunk_size + TAG_LEN;
+    let payload_len = header_file
+        .metadata()
+        .map_err(|e| CoreError::Crypto(e.to_string()))?
+        .len()
+        - header.len();
+    let total_chunks = payload_len.div_ceil(ct_chunk_size as u64) as usize;
+    if total_chunks == 0 {
+        if header.version != VERSION_NO_AAD {
+            return Err(CoreError::Crypto("truncated attachment".to_string()));
+        }
         File::create(output).map_err(|e| CoreError::Crypto(e.to_string()))?;
         return Ok(0);
     }
-    let ct_chunk_size = chunk_size + TAG_LEN;
-    let total_chunks = ((total_plain + chunk_size as u64 - 1) / chunk_size as u64) as usize;
 
     let out_file = File::create(output).map_err(|e| CoreError::Crypto(e.to_string()))?;
     out_file
         .set_len(total_plain)
         .map_err(|e| CoreError::Crypto(e.to_string()))?;
 
-    let next_index = Arc::new(AtomicUsize::new(0));
-    let mut handles = Vec::new();
-
-    for _ in 0..workers {
-        let input_file = File::open(input).map_err(|e| CoreError::Crypto(e.to_string()))?;
-        let output_file = out_file.try_clone().map_err(|e| CoreError::Crypto(e.to_string()))?;
-        let next_index = Arc::clone(&next_index);
-        let key_bytes = *key.as_bytes();
-        let base_nonce = base_nonce;
-        let handle = thread::spawn(move || -> Result<(), CoreError> {
-            let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key_bytes));
-            loop {
-                let idx = next_index.fetch_add(1, Ordering::Relaxed);
-                if idx >= total_chunks {
-                    break;
+    let next_index = AtomicUsize::new(0);
+    let bytes_done = AtomicU64::new(0);
+    let cipher = header.cipher(key)?;
+
+    thread::scope(|scope| {
+        let mut handles = Vec::new();
+        for _ in 0..workers {
+            let input_file = File::open(input).map_err(|e| CoreError::Crypto(e.to_string()))?;
+            let (header, next_index, bytes_done, cipher, out_file, progress) =
+                (&header, &next_index, &bytes_done, &cipher, &out_file, &progress);
+            let handle = scope.spawn(move || -> Result<(), CoreError> {
+                loop {
+                    if cancel.load(Ordering::Relaxed) {
+                        return Err(CoreError::Cancelled);
+                    }
+                    let idx = next_index.fetch_add(1, Ordering::Relaxed);
+                    if idx >= total_chunks {
+                        break;
+                    }
+                    let plain_len = if idx == total_chunks - 1 {
+                        (total_plain - (idx as u64 * chunk_size as u64)) as usize
+                    } else {
+                        chunk_size
+                    };
+                    let ct_len = plain_len + TAG_LEN;
+                    let offset = header.len() + idx as u64 * ct_chunk_size as u64;
+                    let mut ct_buf = vec![0u8; ct_len];
+                    read_exact_at(&input_file, &mut ct_buf, offset)?;
+                    let pt = open_chunk(cipher, header, idx as u64, idx == total_chunks - 1, &ct_buf)?;
+                    write_exact_at(out_file, &pt, idx as u64 * chunk_size as u64)?;
+                    let done = bytes_done.fetch_add(pt.len() as u64, Ordering::Relaxed) + pt.len() as u64;
+                    progress(done, total_plain);
                 }
-                let plain_len = if idx == total_chunks - 1 {
-                    (total_plain - (idx as u64 * chunk_size as u64)) as usize
-                } else {
-                    chunk_size
-                };
-                let ct_len = plain_len + TAG_LEN;
-                let offset = HEADER_LEN + idx as u64 * ct_chunk_size as u64;
-
-                let mut ct_buf = vec![0u8; ct_len];
-                read_exact_at(&input_file, &mut ct_buf, offset)?;
-                let nonce = nonce_for_chunk(&base_nonce, idx as u64);
-                let pt = cipher
-                    .decrypt(Nonce::from_slice(&nonce), ct_buf.as_ref())
-                    .map_err(|e| CoreError::Crypto(format!("decrypt failed: {e}")))?;
-                write_exact_at(&output_file, &pt, idx as u64 * chunk_size as u64)?;
+                Ok(())
+            });
+            handles.push(handle);
+        }
+
+        for handle in handles {
+            match handle.join() {
+                Ok(res) => res?,
+                Err(_) => return Err(CoreError::Crypto("decrypt thread panicked".to_string())),
             }
-            Ok(())
-        });
-        handles.push(handle);
+        }
+        Ok(())
+    })?;
+
+    Ok(total_plain)
+}
+
+/// Encrypts `src` to `dest` in the attachment format with the default chunk size, using
+/// `workers` threads. See [`encrypt_file_parallel_chunk`].
+pub fn encrypt_file_parallel(src: &Path, dest: &Path, key: &MasterKey, workers: usize) -> Result<(String, u64), CoreError> {
+    encrypt_file_parallel_chunk(src, dest, key, workers, DEFAULT_CHUNK_SIZE)
+}
+
+/// The parallel counterpart of [`encrypt_stream_with_hash_chunk`], with the same output:
+/// `src` is read in order on this thread, which hashes it, while `workers` threads encrypt
+/// the chunks and write each at its offset in `dest`. Returns the plaintext's SHA-256 and
+/// size.
+pub fn encrypt_file_parallel_chunk(
+    src: &Path,
+    dest: &Path,
+    key: &MasterKey,
+    workers: usize,
+    chunk_size: usize,
+) -> Result<(String, u64), CoreError> {
+    encrypt_file_parallel_with(src, dest, key, workers, chunk_size, attachment_cipher())
+}
+
+fn encrypt_file_parallel_with(
+    src: &Path,
+    dest: &Path,
+    key: &MasterKey,
+    workers: usize,
+    chunk_size: usize,
+    algorithm: CipherAlgorithm,
+) -> Result<(String, u64), CoreError> {
+    if workers == 0 {
+        return Err(CoreError::Crypto("workers must be >= 1".to_string()));
+    }
+    if chunk_size == 0 || chunk_size > MAX_CHUNK_SIZE {
+        return Err(CoreError::Crypto("invalid chunk size".to_string()));
     }
+    let mut input = File::open(src).map_err(|e| CoreError::Crypto(e.to_string()))?;
+    let total_plain = input
+        .metadata()
+        .map_err(|e| CoreError::Crypto(e.to_string()))?
+        .len();
+    // An empty file still gets one (empty) final chunk, as in `encrypt_stream_internal`.
+    let total_chunks = total_plain.div_ceil(chunk_size as u64).max(1);
+    let ct_chunk_size = (chunk_size + TAG_LEN) as u64;
 
-    for handle in handles {
-        match handle.join() {
-            Ok(res) => res?,
-            Err(_) => return Err(CoreError::Crypto("decrypt thread panicked".to_string())),
+    let (header, file_key) = Header::new(algorithm, chunk_size, key)?;
+    let mut out_file = File::create(dest).map_err(|e| CoreError::Crypto(e.to_string()))?;
+    write_header(&mut out_file, &header)?;
+    out_file
+        .set_len(header.len() + total_plain + total_chunks * TAG_LEN as u64)
+        .map_err(|e| CoreError::Crypto(e.to_string()))?;
+
+    let cipher = ChunkCipher::new(algorithm, &file_key);
+    let (sender, receiver) = mpsc::sync_channel::<(u64, Zeroizing<Vec<u8>>)>(workers * 2);
+    let receiver = Mutex::new(receiver);
+    let mut hasher = Sha256::new();
+
+    thread::scope(|scope| {
+        let mut handles = Vec::new();
+        for _ in 0..workers {
+            let (header, cipher, receiver, out_file) = (&header, &cipher, &receiver, &out_file);
+            handles.push(scope.spawn(move || -> Result<(), CoreError> {
+                let mut result = Ok(());
+                loop {
+                    // The lock is released at the end of this statement, before encrypting.
+                    let next = receiver.lock().unwrap_or_else(|e| e.into_inner()).recv();
+                    let Ok((idx, chunk)) = next else {
+                        break;
+                    };
+                    // After a failure keep draining, so the reader never blocks on a full queue.
+                    if result.is_err() {
+                        continue;
+                    }
+                    let nonce = header.chunk_nonce(idx);
+                    let aad = chunk_aad(VERSION, idx, idx == total_chunks - 1);
+                    result = cipher
+                        .seal(&nonce, &chunk, &aad)
+                        .and_then(|ct| write_exact_at(out_file, &ct, header.len() + idx * ct_chunk_size));
+                }
+                result
+            }));
         }
-    }
 
-    Ok(total_plain)
+        let mut read = Ok(());
+        for idx in 0..total_chunks {
+            let len = (total_plain - idx * chunk_size as u64).min(chunk_size as u64) as usize;
+            let mut chunk = Zeroizing::new(vec![0u8; len]);
+            if let Err(err) = input.read_exact(&mut chunk) {
+                read = Err(CoreError::Crypto(format!("attachment changed while encrypting: {err}")));
+                break;
+            }
+            hasher.update(&chunk);
+            if sender.send((idx, chunk)).is_err() {
+                break;
+            }
+        }
+        drop(sender);
+
+        for handle in handles {
+            match handle.join() {
+                Ok(res) => res?,
+                Err(_) => return Err(CoreError::Crypto("encrypt thread panicked".to_string())),
+            }
+        }
+        read
+    })?;
+
+    Ok((hex::encode(hasher.finalize()), total_plain))
 }
 
 fn read_exact_at(file: &File, buf: &mut [u8], mut offset: u64) -> Result<(), CoreError> {
@@ -475,9 +1091,51 @@ fn write_exact_at(file: &File, buf: &[u8], mut offset: u64) -> Result<(), CoreEr
     Ok(())
 }
 
-fn nonce_for_chunk(base: &[u8; 12], counter: u64) -> [u8; 12] {
-    let mut nonce = *base;
-    nonce[4..].copy_from_slice(&counter.to_be_bytes());
+/// Decrypts chunk `index`. A final chunk that only opens as a non-final one is the last
+/// that survived of a file cut short at a chunk boundary, reported as truncated rather than
+/// damaged; other failures name the chunk and its byte offset in the file, so a damaged blob
+/// can be inspected. Callers add the path. The plaintext is wiped when dropped.
+fn open_chunk(
+    cipher: &ChunkCipher,
+    header: &Header,
+    index: u64,
+    is_final: bool,
+    ct: &[u8],
+) -> Result<Zeroizing<Vec<u8>>, CoreError> {
+    let nonce = header.chunk_nonce(index);
+    let open = |is_final: bool| {
+        let aad = chunk_aad(header.version, index, is_final);
+        cipher.open(&nonce, ct, &aad)
+    };
+    open(is_final).map(Zeroizing::new).map_err(|e| {
+        if is_final && header.version != VERSION_NO_AAD && open(false).is_ok() {
+            CoreError::Crypto("truncated attachment".to_string())
+        } else {
+            let offset = header.len() + index * (header.chunk_size + TAG_LEN) as u64;
+            CoreError::Crypto(format!("decrypt failed at chunk {index}, byte offset {offset}: {e}"))
+        }
+    })
+}
+
+/// Associated data for chunk `index`: the magic, format version, index and a final-chunk
+/// flag. Version 1 chunks were sealed without any.
+fn chunk_aad(version: u8, index: u64, is_final: bool) -> Vec<u8> {
+    if version == VERSION_NO_AAD {
+        return Vec::new();
+    }
+    let mut aad = Vec::with_capacity(MAGIC.len() + 1 + 8 + 1);
+    aad.extend_from_slice(&MAGIC);
+    aad.push(version);
+    aad.extend_from_slice(&index.to_be_bytes());
+    aad.push(u8::from(is_final));
+    aad
+}
+
+/// `base` with its last eight bytes replaced by the big-endian chunk counter.
+fn nonce_for_chunk(base: &[u8], counter: u64) -> Vec<u8> {
+    let mut nonce = base.to_vec();
+    let counter_at = nonce.len() - 8;
+    nonce[counter_at..].copy_from_slice(&counter.to_be_bytes());
     nonce
 }
 
@@ -487,6 +1145,9 @@ mod tests {
     use std::fs;
     use tempfile::tempdir;
 
+    /// Header length of a version 4 AES-256-GCM blob.
+    const HEADER_LEN: u64 = 4 + 1 + 1 + 4 + 12 + WRAPPED_KEY_LEN as u64;
+
     #[test]
     fn encrypt_decrypt_roundtrip() {
         set_test_key_from_passphrase("golden-thread-tests");
@@ -534,6 +1195,462 @@ mod tests {
         assert_eq!(roundtrip, data);
     }
 
+    #[test]
+    fn decrypt_progress_reaches_the_plaintext_length() {
+        set_test_key_from_passphrase("golden-thread-tests");
+        let key = load_or_create_master_key().expect("key");
+        let dir = tempdir().expect("temp");
+        let src = dir.path().join("src.bin");
+        let enc = dir.path().join("enc.bin");
+        let out = dir.path().join("out.bin");
+        let data = vec![9u8; 3 * 1024 * 1024 + 5];
+        fs::write(&src, &data).expect("write");
+        encrypt_file_to_path(&src, &enc, &key).expect("encrypt");
+        let total = encrypted_plaintext_len(&enc).expect("len");
+
+        let calls = std::sync::Mutex::new(Vec::new());
+        decrypt_file_parallel_with_progress(&enc, &out, &key, 3, |done, total| {
+            calls.lock().unwrap().push((done, total))
+        })
+        .expect("parallel decrypt");
+        let mut calls = calls.into_inner().unwrap();
+        calls.sort();
+        assert_eq!(calls.len(), 4);
+        assert_eq!(calls.last(), Some(&(total, total)));
+        assert_eq!(fs::read(&out).expect("read"), data);
+
+        let streamed = std::cell::RefCell::new(Vec::new());
+        let mut reader = File::open(&enc).expect("open");
+        decrypt_stream_with_progress(&mut reader, &mut std::io::sink(), &key, total, |done, total| {
+            streamed.borrow_mut().push((done, total))
+        })
+        .expect("stream decrypt");
+        let streamed = streamed.into_inner();
+        assert_eq!(streamed.len(), 4);
+        assert!(streamed.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert_eq!(streamed.last(), Some(&(total, total)));
+    }
+
+    #[test]
+    fn cancelled_decrypts_stop_between_chunks() {
+        set_test_key_from_passphrase("golden-thread-tests");
+        let key = load_or_create_master_key().expect("key");
+        let dir = tempdir().expect("temp");
+        let enc = dir.path().join("enc.bin");
+        let out = dir.path().join("out.bin");
+        let data = vec![3u8; 64 * 40];
+        let mut sealed = File::create(&enc).expect("create");
+        encrypt_stream_chunk(&mut data.as_slice(), &mut sealed, &key, 64).expect("encrypt");
+        drop(sealed);
+
+        let cancel = AtomicBool::new(false);
+        let calls = AtomicUsize::new(0);
+        let cancel_after_first = |_, _| {
+            calls.fetch_add(1, Ordering::Relaxed);
+            cancel.store(true, Ordering::Relaxed);
+        };
+        let mut reader = File::open(&enc).expect("open");
+        let err = decrypt_stream_cancellable(&mut reader, &mut std::io::sink(), &key, 0, &cancel, cancel_after_first)
+            .expect_err("cancelled");
+        assert!(matches!(err, CoreError::Cancelled));
+        assert_eq!(calls.swap(0, Ordering::Relaxed), 1);
+
+        cancel.store(false, Ordering::Relaxed);
+        let err = decrypt_file_parallel_cancellable(&enc, &out, &key, 2, &cancel, cancel_after_first)
+            .expect_err("cancelled");
+        assert!(matches!(err, CoreError::Cancelled));
+        // Each worker finishes the chunk it's on.
+        assert!(calls.load(Ordering::Relaxed) <= 2);
+
+        let err = decrypt_attachment_to_path_cancellable(&enc, &out, &key, &cancel, |_, _| {}).expect_err("cancelled");
+        assert!(matches!(err, CoreError::Cancelled));
+    }
+
+    #[test]
+    fn encrypt_file_parallel_matches_the_stream_format() {
+        set_test_key_from_passphrase("golden-thread-tests");
+        let key = load_or_create_master_key().expect("key");
+        let dir = tempdir().expect("temp");
+        let src = dir.path().join("src.bin");
+        let enc = dir.path().join("enc.bin");
+        let out = dir.path().join("out.bin");
+        // Empty, one partial chunk, exact multiples and a ragged tail.
+        for len in [0usize, 5, 64, 64 * 40, 64 * 40 + 17] {
+            let data: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+            fs::write(&src, &data).expect("write");
+            let (hash, total) = encrypt_file_parallel_chunk(&src, &enc, &key, 3, 64).expect("encrypt");
+            let mut reference = Vec::new();
+            let (stream_hash, stream_total) =
+                encrypt_stream_with_hash_chunk(&mut data.as_slice(), &mut reference, &key, 64).expect("stream encrypt");
+            assert_eq!((hash.as_str(), total), (stream_hash.as_str(), stream_total));
+            assert_eq!(fs::metadata(&enc).expect("metadata").len(), reference.len() as u64);
+
+            let encrypted = fs::read(&enc).expect("read");
+            assert_eq!(decrypt_bytes(&encrypted, &key).expect("stream decrypt"), data);
+            assert_eq!(encrypted_plaintext_len(&enc).expect("len"), len as u64);
+            if len > 0 {
+                decrypt_file_parallel(&enc, &out, &key, 2).expect("parallel decrypt");
+                assert_eq!(fs::read(&out).expect("read"), data);
+            }
+        }
+    }
+
+    #[test]
+    fn encrypt_file_parallel_output_rejects_truncation() {
+        set_test_key_from_passphrase("golden-thread-tests");
+        let key = load_or_create_master_key().expect("key");
+        let dir = tempdir().expect("temp");
+        let src = dir.path().join("src.bin");
+        let enc = dir.path().join("enc.bin");
+        fs::write(&src, vec![3u8; 64 * 4]).expect("write");
+        encrypt_file_parallel_chunk(&src, &enc, &key, 4, 64).expect("encrypt");
+        let encrypted = fs::read(&enc).expect("read");
+        let cut = HEADER_LEN as usize + 3 * (64 + TAG_LEN);
+        assert!(decrypt_bytes(&encrypted[..cut], &key).is_err());
+    }
+
+    #[test]
+    fn decrypt_range_reads_spans_across_chunks() {
+        set_test_key_from_passphrase("golden-thread-tests");
+        let key = load_or_create_master_key().expect("key");
+        let dir = tempdir().expect("temp");
+        let enc = dir.path().join("enc.bin");
+        // Six full 16-byte chunks and a 4-byte final one.
+        let data: Vec<u8> = (0..100u8).collect();
+        fs::write(&enc, encrypt_bytes(&data, &key, 16)).expect("write");
+
+        let range = |offset: u64, len: u64| decrypt_range(&enc, &key, offset, len).map(|pt| pt.to_vec());
+        assert_eq!(range(0, 100).expect("whole"), data);
+        assert_eq!(range(3, 5).expect("inside one chunk"), data[3..8]);
+        assert_eq!(range(10, 20).expect("across a boundary"), data[10..30]);
+        assert_eq!(range(16, 32).expect("aligned chunks"), data[16..48]);
+        assert_eq!(range(94, 6).expect("into the final chunk"), data[94..]);
+        assert_eq!(range(97, 3).expect("final chunk only"), data[97..]);
+        assert_eq!(range(90, 50).expect("past the end"), data[90..]);
+        assert!(range(100, 10).expect("at the end").is_empty());
+        assert!(range(40, 0).expect("empty").is_empty());
+        assert!(matches!(range(101, 1), Err(CoreError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn decrypt_range_verifies_only_the_chunks_it_reads() {
+        set_test_key_from_passphrase("golden-thread-tests");
+        let key = load_or_create_master_key().expect("key");
+        let dir = tempdir().expect("temp");
+        let enc = dir.path().join("enc.bin");
+        let data = vec![8u8; 64];
+        let mut encrypted = encrypt_bytes(&data, &key, 16);
+        // Corrupt the third chunk's ciphertext.
+        encrypted[HEADER_LEN as usize + 2 * (16 + TAG_LEN)] ^= 1;
+        fs::write(&enc, &encrypted).expect("write");
+
+        assert_eq!(decrypt_range(&enc, &key, 0, 32).expect("clean chunks").as_slice(), vec![8u8; 32]);
+        assert!(decrypt_range(&enc, &key, 30, 4).is_err());
+
+        // Cutting the file at a chunk boundary turns a non-final chunk into the last one.
+        fs::write(&enc, &encrypt_bytes(&data, &key, 16)[..HEADER_LEN as usize + 2 * (16 + TAG_LEN)]).expect("write");
+        assert!(decrypt_range(&enc, &key, 16, 16).is_err());
+    }
+
+    #[test]
+    fn decrypt_first_chunk_reads_only_leading_chunk() {
+        set_test_key_from_passphrase("golden-thread-tests");
+        let key = load_or_create_master_key().expect("key");
+        let dir = tempdir().expect("temp");
+        let src = dir.path().join("src.bin");
+        let enc = dir.path().join("enc.bin");
+        let mut data = vec![1u8; DEFAULT_CHUNK_SIZE];
+        data.extend_from_slice(&[2u8; 10]);
+        fs::write(&src, &data).expect("write");
+        encrypt_file_to_path(&src, &enc, &key).expect("encrypt");
+        let first = decrypt_first_chunk(&enc, &key).expect("decrypt");
+        assert_eq!(*first, vec![1u8; DEFAULT_CHUNK_SIZE]);
+    }
+
+    fn encrypt_bytes(data: &[u8], key: &MasterKey, chunk_size: usize) -> Vec<u8> {
+        let mut out = Vec::new();
+        encrypt_stream_chunk(&mut &data[..], &mut out, key, chunk_size).expect("encrypt");
+        out
+    }
+
+    fn decrypt_bytes(encrypted: &[u8], key: &MasterKey) -> Result<Vec<u8>, CoreError> {
+        let mut out = Vec::new();
+        decrypt_stream(&mut &encrypted[..], &mut out, key)?;
+        Ok(out)
+    }
+
+    fn is_truncated(result: Result<impl Sized, CoreError>) -> bool {
+        matches!(result, Err(CoreError::Crypto(msg)) if msg == "truncated attachment")
+    }
+
+    #[test]
+    fn decrypt_rejects_truncation_at_a_chunk_boundary() {
+        set_test_key_from_passphrase("golden-thread-tests");
+        let key = load_or_create_master_key().expect("key");
+        let ct_chunk = 16 + TAG_LEN;
+        for data in [vec![3u8; 40], vec![3u8; 32]] {
+            let encrypted = encrypt_bytes(&data, &key, 16);
+            assert_eq!(decrypt_bytes(&encrypted, &key).expect("decrypt"), data);
+            for chunks in 0..=1 {
+                let truncated = &encrypted[..HEADER_LEN as usize + chunks * ct_chunk];
+                assert!(is_truncated(decrypt_bytes(truncated, &key)));
+            }
+        }
+
+        let dir = tempdir().expect("temp");
+        let enc = dir.path().join("enc.bin");
+        let encrypted = encrypt_bytes(&[3u8; 40], &key, 16);
+        fs::write(&enc, &encrypted[..HEADER_LEN as usize + 2 * ct_chunk]).expect("write");
+        assert!(is_truncated(decrypt_file_parallel(&enc, &dir.path().join("out.bin"), &key, 2)));
+        assert!(is_truncated(decrypt_first_chunk(&enc, &key).and(decrypt_range(&enc, &key, 0, 32))));
+        fs::write(&enc, &encrypted[..HEADER_LEN as usize + ct_chunk]).expect("write");
+        assert!(is_truncated(decrypt_first_chunk(&enc, &key)));
+    }
+
+    #[test]
+    fn decrypt_rejects_truncation_mid_chunk() {
+        set_test_key_from_passphrase("golden-thread-tests");
+        let key = load_or_create_master_key().expect("key");
+        let ct_chunk = 16 + TAG_LEN;
+        let encrypted = encrypt_bytes(&[4u8; 40], &key, 16);
+        let dir = tempdir().expect("temp");
+        let enc = dir.path().join("enc.bin");
+        for cut in [HEADER_LEN as usize + 5, HEADER_LEN as usize + ct_chunk + TAG_LEN + 3, encrypted.len() - 1] {
+            assert!(matches!(decrypt_bytes(&encrypted[..cut], &key), Err(CoreError::Crypto(_))));
+            fs::write(&enc, &encrypted[..cut]).expect("write");
+            let result = decrypt_file_parallel(&enc, &dir.path().join("out.bin"), &key, 2);
+            assert!(matches!(result, Err(CoreError::Crypto(_))));
+        }
+    }
+
+    #[test]
+    fn decrypt_rejects_reordered_chunks() {
+        set_test_key_from_passphrase("golden-thread-tests");
+        let key = load_or_create_master_key().expect("key");
+        let mut encrypted = encrypt_bytes(&[1u8, 2, 3].repeat(20), &key, 16);
+        let first = HEADER_LEN as usize;
+        let second = first + 16 + TAG_LEN;
+        let chunk: Vec<u8> = encrypted[first..second].to_vec();
+        encrypted.copy_within(second..second + 16 + TAG_LEN, first);
+        encrypted[second..second + 16 + TAG_LEN].copy_from_slice(&chunk);
+        assert!(matches!(decrypt_bytes(&encrypted, &key), Err(CoreError::Crypto(_))));
+    }
+
+    #[test]
+    fn empty_attachments_roundtrip_with_a_final_chunk() {
+        set_test_key_from_passphrase("golden-thread-tests");
+        let key = load_or_create_master_key().expect("key");
+        let encrypted = encrypt_bytes(&[], &key, 16);
+        assert_eq!(encrypted.len(), HEADER_LEN as usize + TAG_LEN);
+        assert_eq!(decrypt_bytes(&encrypted, &key).expect("decrypt"), Vec::<u8>::new());
+        assert!(decrypt_bytes(&encrypted[..HEADER_LEN as usize], &key).is_err());
+    }
+
+    #[test]
+    fn decrypt_reads_version_1_attachments() {
+        set_test_key_from_passphrase("golden-thread-tests");
+        let key = load_or_create_master_key().expect("key");
+        let data: Vec<u8> = (0..40u8).collect();
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key.as_bytes()));
+        let base_nonce = [9u8; 12];
+        let mut encrypted = Vec::new();
+        encrypted.extend_from_slice(&MAGIC);
+        encrypted.push(VERSION_NO_AAD);
+        encrypted.extend_from_slice(&16u32.to_le_bytes());
+        encrypted.extend_from_slice(&base_nonce);
+        for (idx, chunk) in data.chunks(16).enumerate() {
+            let nonce = nonce_for_chunk(&base_nonce, idx as u64);
+            encrypted.extend(cipher.encrypt(Nonce::from_slice(&nonce), chunk).expect("encrypt"));
+        }
+        assert_eq!(decrypt_bytes(&encrypted, &key).expect("decrypt"), data);
+
+        let dir = tempdir().expect("temp");
+        let enc = dir.path().join("enc.bin");
+        let out = dir.path().join("out.bin");
+        fs::write(&enc, &encrypted).expect("write");
+        decrypt_file_parallel(&enc, &out, &key, 2).expect("parallel decrypt");
+        assert_eq!(fs::read(&out).expect("read"), data);
+        assert_eq!(decrypt_first_chunk(&enc, &key).expect("first chunk").as_slice(), data[..16].to_vec());
+        assert!(is_legacy_format(&enc).expect("version"));
+        fs::write(&enc, encrypt_bytes(&data, &key, 16)).expect("write");
+        assert!(!is_legacy_format(&enc).expect("version"));
+    }
+
+    fn encrypt_with(data: &[u8], key: &MasterKey, chunk_size: usize, algorithm: CipherAlgorithm) -> Vec<u8> {
+        let mut out = Vec::new();
+        encrypt_stream_internal(&mut &data[..], &mut out, key, chunk_size, algorithm, None).expect("encrypt");
+        out
+    }
+
+    #[test]
+    fn both_ciphers_roundtrip_through_every_decrypt_path() {
+        set_test_key_from_passphrase("golden-thread-tests");
+        let key = load_or_create_master_key().expect("key");
+        let data: Vec<u8> = (0..200u8).collect();
+        let dir = tempdir().expect("temp");
+        let enc = dir.path().join("enc.bin");
+        let out = dir.path().join("out.bin");
+        for algorithm in [CipherAlgorithm::Aes256Gcm, CipherAlgorithm::XChaCha20Poly1305] {
+            let encrypted = encrypt_with(&data, &key, 64, algorithm);
+            let header_len = 4 + 1 + 1 + 4 + algorithm.nonce_len() + WRAPPED_KEY_LEN;
+            assert_eq!(encrypted[4], VERSION);
+            assert_eq!(encrypted[5], algorithm.id());
+            assert_eq!(encrypted.len(), header_len + data.len() + 4 * TAG_LEN);
+            assert_eq!(decrypt_bytes(&encrypted, &key).expect("decrypt"), data);
+
+            fs::write(&enc, &encrypted).expect("write");
+            assert_eq!(encrypted_plaintext_len(&enc).expect("len"), data.len() as u64);
+            decrypt_file_parallel(&enc, &out, &key, 3).expect("parallel decrypt");
+            assert_eq!(fs::read(&out).expect("read"), data);
+            assert_eq!(decrypt_first_chunk(&enc, &key).expect("first chunk").as_slice(), &data[..64]);
+            assert_eq!(decrypt_range(&enc, &key, 60, 80).expect("range").as_slice(), &data[60..140]);
+            assert!(!is_legacy_format(&enc).expect("version"));
+
+            let src = dir.path().join("src.bin");
+            fs::write(&src, &data).expect("write");
+            encrypt_file_parallel_with(&src, &enc, &key, 2, 64, algorithm).expect("parallel encrypt");
+            assert_eq!(fs::read(&enc).expect("read")[5], algorithm.id());
+            decrypt_file_to_path(&enc, &out, &key).expect("decrypt");
+            assert_eq!(fs::read(&out).expect("read"), data);
+
+            let mut tampered = encrypted.clone();
+            tampered[header_len + 3] ^= 1;
+            assert!(decrypt_bytes(&tampered, &key).is_err());
+        }
+    }
+
+    #[test]
+    fn mixed_cipher_blobs_decrypt_under_one_key() {
+        set_test_key_from_passphrase("golden-thread-tests");
+        let key = load_or_create_master_key().expect("key");
+        let aes = encrypt_with(b"aes attachment", &key, 16, CipherAlgorithm::Aes256Gcm);
+        let xchacha = encrypt_with(b"xchacha attachment", &key, 16, CipherAlgorithm::XChaCha20Poly1305);
+        assert_eq!(decrypt_bytes(&aes, &key).expect("aes"), b"aes attachment");
+        assert_eq!(decrypt_bytes(&xchacha, &key).expect("xchacha"), b"xchacha attachment");
+
+        // Relabelling a blob as the other cipher must fail, not decrypt garbage.
+        let mut relabelled = aes.clone();
+        relabelled[5] = CipherAlgorithm::XChaCha20Poly1305.id();
+        assert!(decrypt_bytes(&relabelled, &key).is_err());
+    }
+
+    #[test]
+    fn decrypt_rejects_unknown_cipher_ids() {
+        set_test_key_from_passphrase("golden-thread-tests");
+        let key = load_or_create_master_key().expect("key");
+        let mut encrypted = encrypt_with(&[5u8; 40], &key, 16, CipherAlgorithm::Aes256Gcm);
+        for id in [0u8, 3, 0x7f] {
+            encrypted[5] = id;
+            let err = decrypt_bytes(&encrypted, &key).expect_err("unknown cipher");
+            assert!(matches!(err, CoreError::Crypto(msg) if msg == format!("unsupported cipher algorithm {id}")));
+        }
+        let dir = tempdir().expect("temp");
+        let enc = dir.path().join("enc.bin");
+        fs::write(&enc, &encrypted).expect("write");
+        assert!(decrypt_first_chunk(&enc, &key).is_err());
+        assert!(decrypt_file_parallel(&enc, &dir.path().join("out.bin"), &key, 2).is_err());
+        assert_eq!(CipherAlgorithm::default(), CipherAlgorithm::Aes256Gcm);
+    }
+
+    /// A version 3 blob, sealed directly under the master key as before per-file keys.
+    fn encrypt_shared_key(data: &[u8], key: &MasterKey, chunk_size: usize, algorithm: CipherAlgorithm) -> Vec<u8> {
+        let header = Header {
+            version: VERSION_SHARED_KEY,
+            algorithm,
+            chunk_size,
+            base_nonce: [3u8; MAX_NONCE_LEN],
+            wrapped_key: [0u8; WRAPPED_KEY_LEN],
+        };
+        let cipher = ChunkCipher::new(algorithm, key.as_bytes());
+        let mut encrypted = header.authenticated_bytes();
+        let chunks: Vec<&[u8]> = data.chunks(chunk_size).collect();
+        for (idx, chunk) in chunks.iter().enumerate() {
+            let aad = chunk_aad(VERSION_SHARED_KEY, idx as u64, idx == chunks.len() - 1);
+            encrypted.extend(cipher.seal(&header.chunk_nonce(idx as u64), chunk, &aad).expect("seal"));
+        }
+        encrypted
+    }
+
+    #[test]
+    fn shared_key_and_per_file_key_blobs_coexist() {
+        set_test_key_from_passphrase("golden-thread-tests");
+        let key = load_or_create_master_key().expect("key");
+        let data: Vec<u8> = (0..100u8).collect();
+        let dir = tempdir().expect("temp");
+        let out = dir.path().join("out.bin");
+        for algorithm in [CipherAlgorithm::Aes256Gcm, CipherAlgorithm::XChaCha20Poly1305] {
+            let shared = encrypt_shared_key(&data, &key, 32, algorithm);
+            let per_file = encrypt_with(&data, &key, 32, algorithm);
+            assert_eq!(shared[4], VERSION_SHARED_KEY);
+            assert_eq!(per_file[4], VERSION);
+            assert_eq!(per_file.len(), shared.len() + WRAPPED_KEY_LEN);
+
+            for (name, encrypted) in [("shared.bin", &shared), ("per_file.bin", &per_file)] {
+                assert_eq!(&decrypt_bytes(encrypted, &key).expect("decrypt"), &data);
+                let enc = dir.path().join(name);
+                fs::write(&enc, encrypted).expect("write");
+                assert_eq!(encrypted_plaintext_len(&enc).expect("len"), data.len() as u64);
+                decrypt_file_parallel(&enc, &out, &key, 2).expect("parallel decrypt");
+                assert_eq!(fs::read(&out).expect("read"), data);
+                assert_eq!(decrypt_first_chunk(&enc, &key).expect("first chunk").as_slice(), &data[..32]);
+                assert_eq!(decrypt_range(&enc, &key, 30, 40).expect("range").as_slice(), &data[30..70]);
+            }
+        }
+    }
+
+    #[test]
+    fn each_blob_is_sealed_under_its_own_wrapped_key() {
+        set_test_key_from_passphrase("golden-thread-tests");
+        let key = load_or_create_master_key().expect("key");
+        let data = [8u8; 48];
+        let first = encrypt_with(&data, &key, 16, CipherAlgorithm::Aes256Gcm);
+        let second = encrypt_with(&data, &key, 16, CipherAlgorithm::Aes256Gcm);
+        let wrapped = (HEADER_LEN as usize - WRAPPED_KEY_LEN)..HEADER_LEN as usize;
+        assert_ne!(first[wrapped.clone()], second[wrapped.clone()]);
+
+        let unwrap_failed = |result: Result<Vec<u8>, CoreError>| {
+            matches!(result, Err(CoreError::Crypto(msg)) if msg == "attachment key unwrap failed")
+        };
+        let other_key = MasterKey(Zeroizing::new([7u8; 32]));
+        assert!(unwrap_failed(decrypt_bytes(&first, &other_key)));
+        let mut tampered = first.clone();
+        tampered[wrapped.end - 1] ^= 1;
+        assert!(unwrap_failed(decrypt_bytes(&tampered, &key)));
+        // The wrapped key authenticates the header, so its fields can't be edited.
+        let mut resized = first.clone();
+        resized[6] ^= 1;
+        assert!(unwrap_failed(decrypt_bytes(&resized, &key)));
+
+        // The chunks aren't sealed under the master key itself.
+        let mut downgraded = first[..wrapped.start].to_vec();
+        downgraded[4] = VERSION_SHARED_KEY;
+        downgraded.extend_from_slice(&first[wrapped.end..]);
+        assert!(decrypt_bytes(&downgraded, &key).is_err());
+    }
+
+    #[test]
+    fn decrypt_errors_name_the_damaged_chunk() {
+        set_test_key_from_passphrase("golden-thread-tests");
+        let key = load_or_create_master_key().expect("key");
+        let data = [4u8; 5 * 16];
+        let mut encrypted = encrypt_bytes(&data, &key, 16);
+        let chunk_3 = HEADER_LEN + 3 * (16 + TAG_LEN) as u64;
+        encrypted[chunk_3 as usize + 1] ^= 1;
+        let names_chunk_3 = |err: CoreError| {
+            matches!(err, CoreError::Crypto(msg)
+                if msg.starts_with(&format!("decrypt failed at chunk 3, byte offset {chunk_3}:")))
+        };
+        assert!(names_chunk_3(decrypt_bytes(&encrypted, &key).expect_err("stream")));
+
+        let dir = tempdir().expect("temp");
+        let enc = dir.path().join("enc.bin");
+        fs::write(&enc, &encrypted).expect("write");
+        let out = dir.path().join("out.bin");
+        assert!(names_chunk_3(decrypt_file_parallel(&enc, &out, &key, 2).expect_err("parallel")));
+        assert!(names_chunk_3(decrypt_range(&enc, &key, 40, 20).expect_err("range")));
+        assert_eq!(decrypt_range(&enc, &key, 0, 48).expect("undamaged range").as_slice(), &data[..48]);
+    }
+
     #[test]
     fn derive_key_produces_different_keys_per_purpose() {
         set_test_key_from_passphrase("golden-thread-tests");
@@ -560,4 +1677,28 @@ mod tests {
         // Same master + purpose should produce same derived key
         assert_eq!(key1.as_bytes(), key2.as_bytes());
     }
+
+    #[test]
+    fn key_fingerprint_identifies_the_key_without_revealing_it() {
+        let key = MasterKey(Zeroizing::new([9u8; 32]));
+        let fingerprint = key_fingerprint(&key);
+        assert_eq!(fingerprint.len(), 64);
+        assert_eq!(fingerprint, key_fingerprint(&MasterKey(Zeroizing::new([9u8; 32]))));
+        assert_ne!(fingerprint, key_fingerprint(&MasterKey(Zeroizing::new([10u8; 32]))));
+        assert_ne!(fingerprint, hex::encode(key.as_bytes()));
+        for purpose in [KeyPurpose::Database, KeyPurpose::Attachments] {
+            let derived = derive_key(&key, purpose).expect("derive");
+            assert_ne!(fingerprint, hex::encode(derived.as_bytes()));
+        }
+    }
+
+    #[test]
+    fn self_test_passes() {
+        let report = self_test();
+        assert!(report.aes_gcm);
+        assert!(report.hkdf);
+        assert!(report.stream_round_trip);
+        assert!(report.tamper_detected);
+        assert!(report.passed);
+    }
 }
@@ -0,0 +1,137 @@
+//! Encryption throughput benchmark. Measures in-memory encrypt and decrypt speed for a set
+//! of chunk sizes and how fast the disk takes writes, so a slow import can be told apart as
+//! crypto-bound or IO-bound. The chunk size with the best worse-of-both speed is recorded
+//! and picked by [`attachment_chunk_size`] for later imports.
+
+use std::fs::File;
+use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use rand::rngs::OsRng;
+use rand::RngCore;
+use zeroize::Zeroizing;
+
+use super::{decrypt_stream, encrypt_stream_chunk, MasterKey, DEFAULT_CHUNK_SIZE, MAX_CHUNK_SIZE, TAG_LEN};
+use crate::error::CoreError;
+use crate::models::{BenchReport, ChunkBench};
+
+/// Chunk sizes compared when the caller has no preference.
+pub const BENCHMARK_CHUNK_SIZES: [usize; 4] = [256 * 1024, 1024 * 1024, 4 * 1024 * 1024, 8 * 1024 * 1024];
+const MAX_SAMPLE_MB: usize = 256;
+/// Attachments at least this large used 4 MB chunks before any benchmark ran.
+const LARGE_ATTACHMENT: u64 = 10 * 1024 * 1024;
+const LARGE_CHUNK_SIZE: usize = 4 * 1024 * 1024;
+/// Smallest chunk picked for a small attachment, so tiny files don't get tiny chunks.
+const MIN_CHUNK_SIZE: usize = 64 * 1024;
+
+/// The chunk size recommended by the last benchmark in this process, or 0 before one ran.
+static TUNED_CHUNK_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+/// Encrypts and decrypts `sample_mb` MB of random data under a throwaway key with each of
+/// `chunk_sizes`, then times writing the ciphertext to a temporary file. Records the
+/// recommended chunk size for [`attachment_chunk_size`].
+pub fn benchmark(sample_mb: usize, chunk_sizes: &[usize]) -> Result<BenchReport, CoreError> {
+    if sample_mb == 0 || sample_mb > MAX_SAMPLE_MB {
+        return Err(CoreError::InvalidArgument(format!("sample size must be 1-{MAX_SAMPLE_MB} MB")));
+    }
+    if chunk_sizes.is_empty() || chunk_sizes.iter().any(|&size| size == 0 || size > MAX_CHUNK_SIZE) {
+        return Err(CoreError::InvalidArgument("invalid benchmark chunk sizes".to_string()));
+    }
+    let mut key_bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut key_bytes);
+    let key = MasterKey(Zeroizing::new(key_bytes));
+    let mut sample = vec![0u8; sample_mb * 1024 * 1024];
+    OsRng.fill_bytes(&mut sample);
+
+    let mut results = Vec::with_capacity(chunk_sizes.len());
+    let mut last_sealed = Vec::new();
+    for &chunk_size in chunk_sizes {
+        let mut sealed = Vec::with_capacity(sample.len() + sample.len().div_ceil(chunk_size) * TAG_LEN + 64);
+        let started = Instant::now();
+        encrypt_stream_chunk(&mut sample.as_slice(), &mut sealed, &key, chunk_size)?;
+        let encrypt_time = started.elapsed();
+
+        let mut opened = Vec::with_capacity(sample.len());
+        let started = Instant::now();
+        decrypt_stream(&mut sealed.as_slice(), &mut opened, &key)?;
+        let decrypt_time = started.elapsed();
+        if opened != sample {
+            return Err(CoreError::Crypto("benchmark round trip mismatch".to_string()));
+        }
+        results.push(ChunkBench {
+            chunk_size,
+            encrypt_mb_s: mb_per_sec(sample_mb, encrypt_time),
+            decrypt_mb_s: mb_per_sec(sample_mb, decrypt_time),
+        });
+        last_sealed = sealed;
+    }
+
+    let dir = tempfile::tempdir().map_err(|e| CoreError::Crypto(e.to_string()))?;
+    let started = Instant::now();
+    let mut file = File::create(dir.path().join("probe.bin")).map_err(|e| CoreError::Crypto(e.to_string()))?;
+    file.write_all(&last_sealed).map_err(|e| CoreError::Crypto(e.to_string()))?;
+    file.sync_all().map_err(|e| CoreError::Crypto(e.to_string()))?;
+    let disk_write_mb_s = mb_per_sec(sample_mb, started.elapsed());
+
+    let recommended_chunk_size = results
+        .iter()
+        .max_by(|a, b| a.encrypt_mb_s.min(a.decrypt_mb_s).total_cmp(&b.encrypt_mb_s.min(b.decrypt_mb_s)))
+        .map(|result| result.chunk_size)
+        .unwrap_or(DEFAULT_CHUNK_SIZE);
+    TUNED_CHUNK_SIZE.store(recommended_chunk_size, Ordering::Relaxed);
+
+    Ok(BenchReport {
+        sample_mb,
+        results,
+        disk_write_mb_s,
+        recommended_chunk_size,
+    })
+}
+
+/// Whether a benchmark has recommended a chunk size in this process yet.
+pub fn has_tuned_chunk_size() -> bool {
+    TUNED_CHUNK_SIZE.load(Ordering::Relaxed) != 0
+}
+
+/// Chunk size for encrypting an attachment of `plaintext_len` bytes: the benchmark's
+/// recommendation, capped near the attachment's size so small files don't allocate large
+/// buffers. Before any benchmark, 4 MB for attachments of 10 MB or more and 1 MB otherwise.
+pub fn attachment_chunk_size(plaintext_len: u64) -> usize {
+    match TUNED_CHUNK_SIZE.load(Ordering::Relaxed) {
+        0 if plaintext_len >= LARGE_ATTACHMENT => LARGE_CHUNK_SIZE,
+        0 => DEFAULT_CHUNK_SIZE,
+        tuned => {
+            let fitted = usize::try_from(plaintext_len).unwrap_or(usize::MAX).max(MIN_CHUNK_SIZE);
+            tuned.min(fitted)
+        }
+    }
+}
+
+fn mb_per_sec(sample_mb: usize, elapsed: Duration) -> f64 {
+    sample_mb as f64 / elapsed.as_secs_f64().max(1e-6)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn benchmark_reports_each_chunk_size_and_tunes_attachments() {
+        assert!(benchmark(0, &[1024]).is_err());
+        assert!(benchmark(1, &[]).is_err());
+        assert!(benchmark(1, &[MAX_CHUNK_SIZE + 1]).is_err());
+
+        let report = benchmark(1, &[64 * 1024, 256 * 1024]).expect("benchmark");
+        assert_eq!(report.sample_mb, 1);
+        let sizes: Vec<usize> = report.results.iter().map(|result| result.chunk_size).collect();
+        assert_eq!(sizes, vec![64 * 1024, 256 * 1024]);
+        assert!(report.results.iter().all(|result| result.encrypt_mb_s > 0.0 && result.decrypt_mb_s > 0.0));
+        assert!(report.disk_write_mb_s > 0.0);
+        assert!(sizes.contains(&report.recommended_chunk_size));
+
+        assert!(has_tuned_chunk_size());
+        assert_eq!(attachment_chunk_size(100 * 1024 * 1024), report.recommended_chunk_size);
+        assert_eq!(attachment_chunk_size(10), MIN_CHUNK_SIZE);
+    }
+}
@@ -0,0 +1,66 @@
+//! Per-file attachment keys. From format version 4 every blob's chunks are sealed under its
+//! own random key, stored in the blob's header sealed with AES-256-GCM under the master
+//! key's attachments key ([`super::attachment_key`]). A leaked file key exposes one file,
+//! and chunk nonces only have to be unique within a file. Layout of the wrapped key:
+//!
+//! `[NONCE: 12][SEALED KEY: 48]`
+//!
+//! with the header bytes before it authenticated as associated data, so a wrapped key
+//! can't be moved to another header.
+
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use zeroize::Zeroizing;
+
+use super::{attachment_key, MasterKey, TAG_LEN};
+use crate::error::CoreError;
+
+const NONCE_LEN: usize = 12;
+pub(super) const WRAPPED_KEY_LEN: usize = NONCE_LEN + 32 + TAG_LEN;
+
+/// A fresh random file key.
+pub(super) fn new_file_key() -> Zeroizing<[u8; 32]> {
+    let mut file_key = Zeroizing::new([0u8; 32]);
+    OsRng.fill_bytes(file_key.as_mut());
+    file_key
+}
+
+/// Seals `file_key` under `master`'s attachments key, authenticating `header`.
+pub(super) fn wrap_file_key(
+    master: &MasterKey,
+    file_key: &[u8; 32],
+    header: &[u8],
+) -> Result<[u8; WRAPPED_KEY_LEN], CoreError> {
+    let wrapping_key = attachment_key(master)?;
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(wrapping_key.as_bytes()));
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce);
+    let sealed = cipher
+        .encrypt(Nonce::from_slice(&nonce), Payload { msg: file_key, aad: header })
+        .map_err(|e| CoreError::Crypto(format!("encrypt failed: {e}")))?;
+    let mut wrapped = [0u8; WRAPPED_KEY_LEN];
+    wrapped[..NONCE_LEN].copy_from_slice(&nonce);
+    wrapped[NONCE_LEN..].copy_from_slice(&sealed);
+    Ok(wrapped)
+}
+
+/// Opens a key sealed by [`wrap_file_key`]. Fails for another master key or a changed header.
+pub(super) fn unwrap_file_key(
+    master: &MasterKey,
+    wrapped: &[u8; WRAPPED_KEY_LEN],
+    header: &[u8],
+) -> Result<Zeroizing<[u8; 32]>, CoreError> {
+    let wrapping_key = attachment_key(master)?;
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(wrapping_key.as_bytes()));
+    let (nonce, sealed) = wrapped.split_at(NONCE_LEN);
+    let opened = Zeroizing::new(
+        cipher
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: sealed, aad: header })
+            .map_err(|_| CoreError::Crypto("attachment key unwrap failed".to_string()))?,
+    );
+    let mut file_key = Zeroizing::new([0u8; 32]);
+    file_key.copy_from_slice(&opened);
+    Ok(file_key)
+}
@@ -0,0 +1,179 @@
+//! The GTAT attachment container, pinned by committed test vectors so a refactor can't
+//! silently change it and strand existing archives. A blob is a header followed by chunks
+//! of `CHUNK_SIZE` plaintext bytes, the last one shorter or empty, each sealed with a
+//! 16-byte tag. Chunk `i`'s nonce is the base nonce with its last eight bytes replaced by
+//! `i` big-endian. Headers by version:
+//!
+//! - 1: `[MAGIC "GTAT": 4][VERSION: 1][CHUNK_SIZE: 4 LE][BASE NONCE: 12]`. AES-256-GCM under
+//!   the master key, without associated data; an empty blob has no chunks.
+//! - 2: as version 1, with `[MAGIC][VERSION][INDEX: 8 BE][FINAL: 1]` as each chunk's
+//!   associated data, and one empty final chunk for an empty blob.
+//! - 3: `[MAGIC][VERSION][CIPHER: 1][CHUNK_SIZE: 4 LE][BASE NONCE: 12 or 24]`, chunks as in
+//!   version 2. Cipher 1 is AES-256-GCM with a 12-byte nonce, 2 XChaCha20-Poly1305 with 24.
+//! - 4: version 3's header followed by `[WRAP NONCE: 12][WRAPPED FILE KEY: 48]`: a random
+//!   file key sealed with AES-256-GCM under the HKDF attachments key, with the header bytes
+//!   before the wrap nonce as associated data. Chunks are sealed under the file key.
+
+use std::fs::File;
+use std::path::Path;
+
+use super::read_header;
+use crate::error::CoreError;
+
+/// The format version of the blob at `path`, read from its header.
+pub fn format_version_of(path: &Path) -> Result<u8, CoreError> {
+    let mut file = File::open(path).map_err(|e| CoreError::Crypto(e.to_string()))?;
+    Ok(read_header(&mut file)?.version)
+}
+
+#[cfg(test)]
+mod tests {
+    use aes_gcm::aead::{Aead, KeyInit, Payload};
+    use aes_gcm::{Aes256Gcm, Key, Nonce};
+    use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+    use hkdf::Hkdf;
+    use sha2::Sha256;
+    use zeroize::Zeroizing;
+
+    use super::super::{decrypt_file_parallel, decrypt_stream, encrypt_stream_chunk, MasterKey};
+    use super::*;
+
+    const MASTER_KEY: [u8; 32] = [0x11; 32];
+    const PLAINTEXT: &[u8] = b"golden thread format vector, 40 bytes..!";
+    const CHUNK_SIZE: usize = 16;
+    const BASE_NONCE: [u8; 24] = [0xa5; 24];
+    const FILE_KEY: [u8; 32] = [0x22; 32];
+    const WRAP_NONCE: [u8; 12] = [0x33; 12];
+
+    // Generated once from the constants above; never regenerate these to make a test pass.
+    const VECTOR_V1: &str = concat!(
+        "475441540110000000a5a5a5a5a5a5a5a5a5a5a5a5c819a7f8464ca5233e2821eaaac5519e7906162a008ccf3db94048",
+        "31e2eeb0261cd167fc47be8fdaa2d4c6a1e4bcefccb3841f599c7229788a2d8a519162800fa0fad8f272630b4939ec4b",
+        "d4207484c9e1f039fcef6a5d0c",
+    );
+    const VECTOR_V2: &str = concat!(
+        "475441540210000000a5a5a5a5a5a5a5a5a5a5a5a5c819a7f8464ca5233e2821eaaac5519e143425d38c609276b16992",
+        "d71e9ee2b81cd167fc47be8fdaa2d4c6a1e4bcefcce1ce369a331eb86860b67fc4d7b76172a0fad8f272630b49819734",
+        "43d1bac1f9775f0fd215ebcde7",
+    );
+    const VECTOR_V3_XCHACHA: &str = concat!(
+        "47544154030210000000a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a55aa401c2b4008d8713f6d1a2d79f",
+        "8ee4a0289a8652a8059ce33a8f9dc9cb012023fdf5e18f407e992357739c1bffbc722e5cbc1f74a4bb7201696aa916d9",
+        "4a92fcb5cbf27bec389abbe0f2bc4c3c034bc06d9aff66338274",
+    );
+    const VECTOR_V4: &str = concat!(
+        "47544154040110000000a5a5a5a5a5a5a5a5a5a5a5a533333333333333333333333372bf7ff992626492ed2a6f1628aa",
+        "ad7310270f35fb3db487287f21ce7e4736579d069cdd0e362d32e5d12d914265c4f94c1f05f743d83a760808dfcfbac5",
+        "fcbc37f72aac514eef5f0504af405c7efaadcd0b8c40cb32d6d369066e34c0f831fe5b6ffd1fec2d04fa17e092449df5",
+        "e2ba84e82e318cf3e37aaa071b721747945da9174006a13c9ec1",
+    );
+
+    /// Seals `PLAINTEXT` the way the module docs describe, independently of the encryptor.
+    /// `file_key` and `wrap_nonce` are only used by version 4.
+    fn reference_blob(
+        version: u8,
+        cipher_id: u8,
+        base_nonce: &[u8],
+        file_key: &[u8; 32],
+        wrap_nonce: &[u8; 12],
+    ) -> Vec<u8> {
+        let nonce_len = if cipher_id == 2 { 24 } else { 12 };
+        let mut blob = b"GTAT".to_vec();
+        blob.push(version);
+        if version >= 3 {
+            blob.push(cipher_id);
+        }
+        blob.extend_from_slice(&(CHUNK_SIZE as u32).to_le_bytes());
+        blob.extend_from_slice(&base_nonce[..nonce_len]);
+        let chunk_key = if version == 4 {
+            let wrapped = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&wrapping_key()))
+                .encrypt(Nonce::from_slice(wrap_nonce), Payload { msg: file_key, aad: &blob })
+                .expect("wrap");
+            blob.extend_from_slice(wrap_nonce);
+            blob.extend_from_slice(&wrapped);
+            *file_key
+        } else {
+            MASTER_KEY
+        };
+
+        let chunks: Vec<&[u8]> = PLAINTEXT.chunks(CHUNK_SIZE).collect();
+        for (idx, chunk) in chunks.iter().enumerate() {
+            let mut nonce = base_nonce[..nonce_len].to_vec();
+            nonce[nonce_len - 8..].copy_from_slice(&(idx as u64).to_be_bytes());
+            let mut aad = Vec::new();
+            if version >= 2 {
+                aad.extend_from_slice(b"GTAT");
+                aad.push(version);
+                aad.extend_from_slice(&(idx as u64).to_be_bytes());
+                aad.push(u8::from(idx == chunks.len() - 1));
+            }
+            let payload = Payload { msg: chunk, aad: &aad };
+            let sealed = if cipher_id == 2 {
+                XChaCha20Poly1305::new(&chunk_key.into()).encrypt(XNonce::from_slice(&nonce), payload)
+            } else {
+                Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&chunk_key)).encrypt(Nonce::from_slice(&nonce), payload)
+            };
+            blob.extend(sealed.expect("seal"));
+        }
+        blob
+    }
+
+    /// HKDF-SHA256 of the master key with the attachments info string.
+    fn wrapping_key() -> [u8; 32] {
+        let mut key = [0u8; 32];
+        Hkdf::<Sha256>::new(None, &MASTER_KEY)
+            .expand(b"golden-thread-attachments-v1", &mut key)
+            .expect("hkdf");
+        key
+    }
+
+    fn vectors() -> [(u8, u8, &'static str); 4] {
+        [(1, 1, VECTOR_V1), (2, 1, VECTOR_V2), (3, 2, VECTOR_V3_XCHACHA), (4, 1, VECTOR_V4)]
+    }
+
+    #[test]
+    fn committed_vectors_match_the_documented_layout() {
+        for (version, cipher_id, vector) in vectors() {
+            let blob = reference_blob(version, cipher_id, &BASE_NONCE, &FILE_KEY, &WRAP_NONCE);
+            assert_eq!(hex::encode(blob), vector, "version {version}");
+        }
+    }
+
+    #[test]
+    fn committed_vectors_still_decrypt() {
+        let key = MasterKey(Zeroizing::new(MASTER_KEY));
+        let dir = tempfile::tempdir().expect("temp");
+        for (version, _, vector) in vectors() {
+            let blob = hex::decode(vector).expect("hex");
+            let mut out = Vec::new();
+            decrypt_stream(&mut blob.as_slice(), &mut out, &key).expect("decrypt");
+            assert_eq!(out, PLAINTEXT, "version {version}");
+
+            let path = dir.path().join(format!("v{version}.bin"));
+            std::fs::write(&path, &blob).expect("write");
+            assert_eq!(format_version_of(&path).expect("version"), version);
+            let out_path = dir.path().join("out.bin");
+            decrypt_file_parallel(&path, &out_path, &key, 2).expect("parallel decrypt");
+            assert_eq!(std::fs::read(&out_path).expect("read"), PLAINTEXT, "version {version}");
+        }
+    }
+
+    #[test]
+    fn new_blobs_follow_the_documented_layout() {
+        let key = MasterKey(Zeroizing::new(MASTER_KEY));
+        let mut blob = Vec::new();
+        encrypt_stream_chunk(&mut &PLAINTEXT[..], &mut blob, &key, CHUNK_SIZE).expect("encrypt");
+        assert_eq!(&blob[..6], b"GTAT\x04\x01");
+        assert_eq!(&blob[6..10], &(CHUNK_SIZE as u32).to_le_bytes());
+
+        // Re-seal with the blob's own nonces and file key: the bytes must match exactly.
+        let mut base_nonce = [0u8; 24];
+        base_nonce[..12].copy_from_slice(&blob[10..22]);
+        let wrap_nonce: [u8; 12] = blob[22..34].try_into().expect("wrap nonce");
+        let file_key = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&wrapping_key()))
+            .decrypt(Nonce::from_slice(&wrap_nonce), Payload { msg: &blob[34..82], aad: &blob[..22] })
+            .expect("unwrap");
+        let file_key: [u8; 32] = file_key.as_slice().try_into().expect("file key");
+        assert_eq!(blob, reference_blob(4, 1, &base_nonce, &file_key, &wrap_nonce));
+    }
+}
@@ -0,0 +1,244 @@
+//! Passphrase-protected key file, the master key's home when the OS keychain can't be used.
+//! The master key is sealed with AES-256-GCM under a key derived from the passphrase with
+//! Argon2id; the passphrase itself is never stored. Layout:
+//!
+//! `[MAGIC: 4][VERSION: 1][M_COST: 4][T_COST: 4][P_COST: 4][SALT: 16][NONCE: 12][SEALED KEY: 48]`
+//!
+//! with the little-endian Argon2 costs recorded so they can be raised without breaking
+//! existing files, and everything before the sealed key authenticated as associated data.
+
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use argon2::{Algorithm, Argon2, Params, Version};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use zeroize::Zeroizing;
+
+use super::{cache_master_key, MasterKey};
+use crate::error::CoreError;
+
+/// The key file's name in the archive directory.
+pub const KEY_FILE_NAME: &str = "master.key";
+
+const KEY_FILE_MAGIC: [u8; 4] = *b"GTKF";
+const KEY_FILE_VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const HEADER_LEN: usize = 4 + 1 + 12 + SALT_LEN + NONCE_LEN;
+const SEALED_LEN: usize = 32 + 16;
+
+/// Argon2id memory cost in KiB, iterations and lanes for new key files.
+const M_COST_KIB: u32 = 64 * 1024;
+const T_COST: u32 = 3;
+const P_COST: u32 = 1;
+
+/// Argon2id costs, as stored in a key file or an archive bundle header.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct KdfCosts {
+    m_cost_kib: u32,
+    t_cost: u32,
+    p_cost: u32,
+}
+
+impl KdfCosts {
+    pub(crate) const ENCODED_LEN: usize = 12;
+
+    /// Cheap costs so tests don't spend seconds in Argon2.
+    #[cfg(test)]
+    pub(crate) const TEST: KdfCosts = KdfCosts {
+        m_cost_kib: 64,
+        t_cost: 1,
+        p_cost: 1,
+    };
+
+    /// Memory cost, iterations and lanes as little-endian `u32`s.
+    pub(crate) fn to_le_bytes(self) -> [u8; Self::ENCODED_LEN] {
+        let mut bytes = [0u8; Self::ENCODED_LEN];
+        bytes[..4].copy_from_slice(&self.m_cost_kib.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.t_cost.to_le_bytes());
+        bytes[8..].copy_from_slice(&self.p_cost.to_le_bytes());
+        bytes
+    }
+
+    pub(crate) fn from_le_bytes(bytes: &[u8; Self::ENCODED_LEN]) -> Self {
+        let cost = |offset: usize| u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
+        KdfCosts {
+            m_cost_kib: cost(0),
+            t_cost: cost(4),
+            p_cost: cost(8),
+        }
+    }
+}
+
+impl Default for KdfCosts {
+    fn default() -> Self {
+        KdfCosts {
+            m_cost_kib: M_COST_KIB,
+            t_cost: T_COST,
+            p_cost: P_COST,
+        }
+    }
+}
+
+pub(super) fn passphrase_key(passphrase: &str, salt: &[u8], costs: KdfCosts) -> Result<Zeroizing<[u8; 32]>, CoreError> {
+    let params = Params::new(costs.m_cost_kib, costs.t_cost, costs.p_cost, Some(32))
+        .map_err(|e| CoreError::Crypto(format!("invalid key file parameters: {e}")))?;
+    let mut key = Zeroizing::new([0u8; 32]);
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, key.as_mut())
+        .map_err(|e| CoreError::Crypto(format!("key derivation failed: {e}")))?;
+    Ok(key)
+}
+
+/// Seals `key` into a new key file at `path` under `passphrase`, replacing any existing
+/// file atomically.
+pub fn create_key_file(path: &Path, passphrase: &str, key: &MasterKey) -> Result<(), CoreError> {
+    write_key_file(path, passphrase, key, KdfCosts::default())
+}
+
+fn write_key_file(path: &Path, passphrase: &str, key: &MasterKey, costs: KdfCosts) -> Result<(), CoreError> {
+    if passphrase.is_empty() {
+        return Err(CoreError::InvalidPassphrase("key file passphrase is empty".to_string()));
+    }
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut salt);
+    OsRng.fill_bytes(&mut nonce);
+
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.extend_from_slice(&KEY_FILE_MAGIC);
+    header.push(KEY_FILE_VERSION);
+    header.extend_from_slice(&costs.to_le_bytes());
+    header.extend_from_slice(&salt);
+    header.extend_from_slice(&nonce);
+
+    let wrapping_key = passphrase_key(passphrase, &salt, costs)?;
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(wrapping_key.as_ref()));
+    let sealed = cipher
+        .encrypt(Nonce::from_slice(&nonce), Payload { msg: key.as_bytes(), aad: &header })
+        .map_err(|e| CoreError::Crypto(format!("encrypt failed: {e}")))?;
+
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    fs::create_dir_all(dir).map_err(|e| CoreError::Crypto(e.to_string()))?;
+    let mut temp = tempfile::NamedTempFile::new_in(dir).map_err(|e| CoreError::Crypto(e.to_string()))?;
+    temp.as_file()
+        .set_permissions(fs::Permissions::from_mode(0o600))
+        .map_err(|e| CoreError::Crypto(e.to_string()))?;
+    temp.write_all(&header).map_err(|e| CoreError::Crypto(e.to_string()))?;
+    temp.write_all(&sealed).map_err(|e| CoreError::Crypto(e.to_string()))?;
+    temp.as_file().sync_all().map_err(|e| CoreError::Crypto(e.to_string()))?;
+    temp.persist(path).map_err(|e| CoreError::Crypto(e.error.to_string()))?;
+    Ok(())
+}
+
+/// Opens the key file at `path` with `passphrase`. A wrong passphrase is
+/// `CoreError::InvalidPassphrase`.
+pub fn unlock_key_file(path: &Path, passphrase: &str) -> Result<MasterKey, CoreError> {
+    open_key_file(path, passphrase).map(|(key, _)| key)
+}
+
+fn open_key_file(path: &Path, passphrase: &str) -> Result<(MasterKey, KdfCosts), CoreError> {
+    let mut contents = Vec::with_capacity(HEADER_LEN + SEALED_LEN);
+    File::open(path)
+        .and_then(|mut file| file.read_to_end(&mut contents))
+        .map_err(|e| CoreError::Crypto(format!("key file read failed: {e}")))?;
+    if contents.len() != HEADER_LEN + SEALED_LEN || contents[..4] != KEY_FILE_MAGIC {
+        return Err(CoreError::Crypto("invalid key file".to_string()));
+    }
+    if contents[4] != KEY_FILE_VERSION {
+        return Err(CoreError::Crypto("unsupported key file version".to_string()));
+    }
+    let (header, sealed) = contents.split_at(HEADER_LEN);
+    let mut encoded_costs = [0u8; KdfCosts::ENCODED_LEN];
+    encoded_costs.copy_from_slice(&header[5..17]);
+    let costs = KdfCosts::from_le_bytes(&encoded_costs);
+    let salt = &header[17..17 + SALT_LEN];
+    let nonce = &header[17 + SALT_LEN..];
+
+    let wrapping_key = passphrase_key(passphrase, salt, costs)?;
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(wrapping_key.as_ref()));
+    let key = Zeroizing::new(
+        cipher
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: sealed, aad: header })
+            .map_err(|_| CoreError::InvalidPassphrase("key file passphrase is incorrect".to_string()))?,
+    );
+    let mut bytes = [0u8; 32];
+    bytes.copy_from_slice(&key);
+    Ok((MasterKey(Zeroizing::new(bytes)), costs))
+}
+
+/// Re-seals the key file at `path` under `new_passphrase` with its existing Argon2 costs;
+/// the master key is unchanged.
+pub fn change_key_file_passphrase(path: &Path, old_passphrase: &str, new_passphrase: &str) -> Result<(), CoreError> {
+    let (key, costs) = open_key_file(path, old_passphrase)?;
+    write_key_file(path, new_passphrase, &key, costs)
+}
+
+/// The master key from the key file at `path`, or a new one sealed into it if there is no
+/// file yet, cached for [`super::load_or_create_master_key`].
+pub(super) fn load_or_create_from_key_file(path: &Path, passphrase: &str) -> Result<MasterKey, CoreError> {
+    let key = if path.exists() {
+        unlock_key_file(path, passphrase)?
+    } else {
+        let mut bytes = [0u8; 32];
+        OsRng.fill_bytes(&mut bytes);
+        let key = MasterKey(Zeroizing::new(bytes));
+        create_key_file(path, passphrase, &key)?;
+        key
+    };
+    cache_master_key(*key.as_bytes(), false);
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn key_file_unlocks_only_with_its_passphrase() {
+        let dir = tempdir().expect("temp");
+        let path = dir.path().join("master.key");
+        let key = MasterKey(Zeroizing::new([5u8; 32]));
+        write_key_file(&path, "correct horse", &key, KdfCosts::TEST).expect("write");
+
+        assert_eq!(unlock_key_file(&path, "correct horse").expect("unlock").as_bytes(), key.as_bytes());
+        assert!(matches!(unlock_key_file(&path, "wrong"), Err(CoreError::InvalidPassphrase(_))));
+        let mode = fs::metadata(&path).expect("metadata").permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        let contents = fs::read(&path).expect("read");
+        assert!(!contents.windows(32).any(|window| window == key.as_bytes()));
+    }
+
+    #[test]
+    fn changing_the_passphrase_keeps_the_key() {
+        let dir = tempdir().expect("temp");
+        let path = dir.path().join("master.key");
+        let key = MasterKey(Zeroizing::new([6u8; 32]));
+        write_key_file(&path, "first", &key, KdfCosts::TEST).expect("write");
+
+        assert!(change_key_file_passphrase(&path, "wrong", "second").is_err());
+        change_key_file_passphrase(&path, "first", "second").expect("change");
+        assert!(unlock_key_file(&path, "first").is_err());
+        assert_eq!(unlock_key_file(&path, "second").expect("unlock").as_bytes(), key.as_bytes());
+    }
+
+    #[test]
+    fn tampered_key_file_header_is_rejected() {
+        let dir = tempdir().expect("temp");
+        let path = dir.path().join("master.key");
+        write_key_file(&path, "pass", &MasterKey(Zeroizing::new([7u8; 32])), KdfCosts::TEST).expect("write");
+        let mut contents = fs::read(&path).expect("read");
+        // Same costs for the KDF, different salt byte: the derived key and the AAD change.
+        contents[17] ^= 1;
+        fs::write(&path, &contents).expect("write");
+        assert!(unlock_key_file(&path, "pass").is_err());
+        fs::write(&path, &contents[..HEADER_LEN]).expect("write");
+        assert!(matches!(unlock_key_file(&path, "pass"), Err(CoreError::Crypto(_))));
+    }
+}
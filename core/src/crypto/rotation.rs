@@ -0,0 +1,415 @@
+//! Master key rotation. Every attachment and thumbnail blob is re-encrypted under a fresh
+//! key, the database is re-keyed, and only then does the keychain entry change. The new key
+//! waits in a separate keychain entry while the rotation runs, and a journal next to the
+//! blobs records each step that is done, so a rotation cut short by a crash finishes when it
+//! is run again with the same old key.
+
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use rand::rngs::OsRng;
+use rand::RngCore;
+use rusqlite::Connection;
+use zeroize::Zeroizing;
+
+use super::{
+    cache_master_key, decrypt_stream, encrypt_stream_chunk, parse_hex_key, pipe_decrypted, read_header, MasterKey,
+    KEYCHAIN_ACCOUNT, KEYCHAIN_SERVICE,
+};
+use crate::error::CoreError;
+
+/// Keychain account holding the new key until the rotation completes.
+const PENDING_KEYCHAIN_ACCOUNT: &str = "archive-master-key-pending";
+/// Journal file, kept in the attachments directory; blob names never start with a dot.
+const JOURNAL_NAME: &str = ".key-rotation";
+const JOURNAL_DB_STARTED: &str = "database-started";
+const JOURNAL_DB_DONE: &str = "database";
+/// How many blobs are re-encrypted between progress callbacks.
+const ROTATION_PROGRESS_BATCH: usize = 100;
+
+/// Where the master key and the key being rotated to are kept.
+trait KeyStore {
+    fn pending(&self) -> Result<Option<[u8; 32]>, CoreError>;
+    fn set_pending(&self, key: &[u8; 32]) -> Result<(), CoreError>;
+    /// Makes `key` the master key.
+    fn commit(&self, key: &[u8; 32]) -> Result<(), CoreError>;
+    fn clear_pending(&self) -> Result<(), CoreError>;
+}
+
+struct Keychain;
+
+impl Keychain {
+    fn entry(account: &str) -> Result<keyring::Entry, CoreError> {
+        keyring::Entry::new(KEYCHAIN_SERVICE, account).map_err(|e| CoreError::Crypto(format!("keychain init failed: {e}")))
+    }
+}
+
+impl KeyStore for Keychain {
+    fn pending(&self) -> Result<Option<[u8; 32]>, CoreError> {
+        match Self::entry(PENDING_KEYCHAIN_ACCOUNT)?.get_password() {
+            Ok(secret) => Ok(Some(parse_hex_key(&secret)?)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(err) => Err(CoreError::Crypto(format!("keychain read failed: {err}"))),
+        }
+    }
+
+    fn set_pending(&self, key: &[u8; 32]) -> Result<(), CoreError> {
+        Self::entry(PENDING_KEYCHAIN_ACCOUNT)?
+            .set_password(&hex::encode(key))
+            .map_err(|e| CoreError::Crypto(format!("keychain store failed: {e}")))
+    }
+
+    fn commit(&self, key: &[u8; 32]) -> Result<(), CoreError> {
+        Self::entry(KEYCHAIN_ACCOUNT)?
+            .set_password(&hex::encode(key))
+            .map_err(|e| CoreError::Crypto(format!("keychain store failed: {e}")))?;
+        cache_master_key(*key, true);
+        Ok(())
+    }
+
+    fn clear_pending(&self) -> Result<(), CoreError> {
+        match Self::entry(PENDING_KEYCHAIN_ACCOUNT)?.delete_password() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(err) => Err(CoreError::Crypto(format!("keychain delete failed: {err}"))),
+        }
+    }
+}
+
+/// Replaces the master key: re-encrypts every blob in `attachments_dir` and `thumbs_dir`,
+/// re-keys `conn`'s database and stores the new key in the keychain, returning it. Run it
+/// with nothing else using the archive; `conn` must be opened with `old_key` and is only
+/// usable with the returned key afterwards.
+///
+/// If an earlier rotation was interrupted, this continues it, skipping the blobs it already
+/// converted, instead of starting over with another key.
+pub fn rotate_master_key<F>(
+    conn: &Connection,
+    attachments_dir: &Path,
+    thumbs_dir: &Path,
+    old_key: &MasterKey,
+    progress: F,
+) -> Result<MasterKey, CoreError>
+where
+    F: Fn(&str),
+{
+    rotate_with_store(conn, attachments_dir, thumbs_dir, old_key, &Keychain, progress)
+}
+
+fn rotate_with_store<F>(
+    conn: &Connection,
+    attachments_dir: &Path,
+    thumbs_dir: &Path,
+    old_key: &MasterKey,
+    store: &dyn KeyStore,
+    progress: F,
+) -> Result<MasterKey, CoreError>
+where
+    F: Fn(&str),
+{
+    fs::create_dir_all(attachments_dir).map_err(io_error)?;
+    let journal_path = attachments_dir.join(JOURNAL_NAME);
+    let new_key = if journal_path.exists() {
+        progress("Resuming key rotation...");
+        store
+            .pending()?
+            .ok_or_else(|| CoreError::Crypto("key rotation journal found without its pending key".to_string()))?
+    } else {
+        let mut key = [0u8; 32];
+        OsRng.fill_bytes(&mut key);
+        // The key is kept before any blob depends on it.
+        store.set_pending(&key)?;
+        key
+    };
+    let new_key = MasterKey(Zeroizing::new(new_key));
+    let mut journal = Journal::open(&journal_path)?;
+
+    let mut blobs = Vec::new();
+    for (label, dir) in [("attachments", attachments_dir), ("thumbs", thumbs_dir)] {
+        for path in blob_paths(dir)? {
+            let entry = format!("{}/{}", label, path.file_name().unwrap_or_default().to_string_lossy());
+            if !journal.contains(&entry) {
+                blobs.push((entry, path));
+            }
+        }
+    }
+    let total = blobs.len();
+    progress(&format!("Re-encrypting files... 0/{}", total));
+    let mut unreadable = 0;
+    for (idx, (entry, path)) in blobs.into_iter().enumerate() {
+        if !reencrypt_blob(&path, old_key, &new_key)? {
+            unreadable += 1;
+        }
+        journal.record(&entry)?;
+        let done = idx + 1;
+        if done % ROTATION_PROGRESS_BATCH == 0 || done == total {
+            progress(&format!("Re-encrypting files... {}/{}", done, total));
+        }
+    }
+    if unreadable > 0 {
+        progress(&format!("{} files could not be decrypted and were left as they were", unreadable));
+    }
+
+    if !journal.contains(JOURNAL_DB_DONE) {
+        progress("Re-keying database...");
+        // A database that no longer opens with the old key after a rekey was started has
+        // already been converted; the interruption came before it was journaled.
+        let converted = journal.contains(JOURNAL_DB_STARTED)
+            && conn
+                .query_row("SELECT COUNT(1) FROM sqlite_master;", [], |row| row.get::<_, i64>(0))
+                .is_err();
+        if !converted {
+            journal.record(JOURNAL_DB_STARTED)?;
+            conn.execute_batch(&format!("PRAGMA rekey = \"x'{}'\";", hex::encode(new_key.as_bytes())))?;
+        }
+        journal.record(JOURNAL_DB_DONE)?;
+    }
+
+    store.commit(new_key.as_bytes())?;
+    // The pending key goes last: a rerun that finds the journal needs it to finish.
+    fs::remove_file(&journal_path).map_err(io_error)?;
+    store.clear_pending()?;
+    progress("Key rotation complete");
+    Ok(new_key)
+}
+
+/// Converted entries, one per line, appended and synced as each step completes.
+struct Journal {
+    file: File,
+    done: HashSet<String>,
+}
+
+impl Journal {
+    fn open(path: &Path) -> Result<Self, CoreError> {
+        let mut done = HashSet::new();
+        if path.exists() {
+            let reader = BufReader::new(File::open(path).map_err(io_error)?);
+            for line in reader.lines() {
+                done.insert(line.map_err(io_error)?);
+            }
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path).map_err(io_error)?;
+        file.sync_all().map_err(io_error)?;
+        Ok(Journal { file, done })
+    }
+
+    fn contains(&self, entry: &str) -> bool {
+        self.done.contains(entry)
+    }
+
+    fn record(&mut self, entry: &str) -> Result<(), CoreError> {
+        writeln!(self.file, "{}", entry).map_err(io_error)?;
+        self.file.sync_data().map_err(io_error)?;
+        self.done.insert(entry.to_string());
+        Ok(())
+    }
+}
+
+/// Encrypted blobs in `dir`, skipping the journal and in-progress temp files.
+fn blob_paths(dir: &Path) -> Result<Vec<PathBuf>, CoreError> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir).map_err(io_error)? {
+        let entry = entry.map_err(io_error)?;
+        if entry.file_name().to_string_lossy().starts_with('.') || !entry.file_type().map_err(io_error)?.is_file() {
+            continue;
+        }
+        paths.push(entry.path());
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Re-encrypts `path` from `old_key` to `new_key` through a temp file that replaces it
+/// atomically. Returns false for a blob that neither key decrypts, which is left alone; a
+/// blob only `new_key` decrypts was converted by an interrupted earlier run.
+fn reencrypt_blob(path: &Path, old_key: &MasterKey, new_key: &MasterKey) -> Result<bool, CoreError> {
+    let chunk_size = match File::open(path).map_err(io_error).and_then(|mut file| read_header(&mut file)) {
+        Ok(header) => header.chunk_size,
+        Err(_) => return Ok(false),
+    };
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let mut temp = tempfile::NamedTempFile::new_in(dir).map_err(io_error)?;
+    let mut source = BufReader::new(File::open(path).map_err(io_error)?);
+    let (decrypted, encrypted) = pipe_decrypted(&mut source, old_key, |plain| {
+        encrypt_stream_chunk(plain, temp.as_file_mut(), new_key, chunk_size)
+    });
+    if decrypted.is_err() {
+        let mut source = BufReader::new(File::open(path).map_err(io_error)?);
+        return Ok(decrypt_stream(&mut source, &mut io::sink(), new_key).is_ok());
+    }
+    encrypted?;
+    temp.as_file().sync_all().map_err(io_error)?;
+    temp.persist(path).map_err(|e| io_error(e.error))?;
+    Ok(true)
+}
+
+fn io_error(err: io::Error) -> CoreError {
+    CoreError::Crypto(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::{Cell, RefCell};
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::sync::Mutex;
+    use tempfile::tempdir;
+
+    #[derive(Default)]
+    struct MemoryStore {
+        pending: Mutex<Option<[u8; 32]>>,
+        master: Mutex<Option<[u8; 32]>>,
+        crash_on_commit: Cell<bool>,
+    }
+
+    impl KeyStore for MemoryStore {
+        fn pending(&self) -> Result<Option<[u8; 32]>, CoreError> {
+            Ok(*self.pending.lock().unwrap())
+        }
+
+        fn set_pending(&self, key: &[u8; 32]) -> Result<(), CoreError> {
+            *self.pending.lock().unwrap() = Some(*key);
+            Ok(())
+        }
+
+        fn commit(&self, key: &[u8; 32]) -> Result<(), CoreError> {
+            *self.master.lock().unwrap() = Some(*key);
+            if self.crash_on_commit.get() {
+                panic!("rotation killed after commit");
+            }
+            Ok(())
+        }
+
+        fn clear_pending(&self) -> Result<(), CoreError> {
+            *self.pending.lock().unwrap() = None;
+            Ok(())
+        }
+    }
+
+    fn key_from(byte: u8) -> MasterKey {
+        MasterKey(Zeroizing::new([byte; 32]))
+    }
+
+    fn open_db(path: &Path, key: &MasterKey) -> Connection {
+        let conn = Connection::open(path).expect("open db");
+        super::super::apply_sqlcipher_key(&conn, key).expect("key");
+        conn
+    }
+
+    fn write_blob(path: &Path, data: &[u8], key: &MasterKey) {
+        let mut out = File::create(path).expect("create");
+        encrypt_stream_chunk(&mut &data[..], &mut out, key, 16).expect("encrypt");
+    }
+
+    fn read_blob(path: &Path, key: &MasterKey) -> Result<Vec<u8>, CoreError> {
+        let mut out = Vec::new();
+        decrypt_stream(&mut BufReader::new(File::open(path).expect("open")), &mut out, key)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn interrupted_rotation_completes_on_rerun() {
+        let dir = tempdir().expect("temp");
+        let attachments = dir.path().join("attachments");
+        let thumbs = dir.path().join("thumbs");
+        fs::create_dir_all(&attachments).unwrap();
+        fs::create_dir_all(&thumbs).unwrap();
+        let old_key = key_from(1);
+        let blobs: Vec<(PathBuf, Vec<u8>)> = (0..150u8)
+            .map(|i| (attachments.join(format!("{:064x}", i)), vec![i; 20 + i as usize]))
+            .chain([(thumbs.join("abc_256.bin"), b"thumbnail".to_vec())])
+            .collect();
+        for (path, data) in &blobs {
+            write_blob(path, data, &old_key);
+        }
+        let db_path = dir.path().join("archive.sqlite");
+        let conn = open_db(&db_path, &old_key);
+        conn.execute_batch("PRAGMA journal_mode = WAL; CREATE TABLE t (v TEXT); INSERT INTO t VALUES ('kept');")
+            .unwrap();
+
+        // Stop the first run after its first progress batch.
+        let store = MemoryStore::default();
+        let interrupted = catch_unwind(AssertUnwindSafe(|| {
+            rotate_with_store(&conn, &attachments, &thumbs, &old_key, &store, |msg| {
+                if msg.contains(&format!("{}/", ROTATION_PROGRESS_BATCH)) {
+                    panic!("rotation killed");
+                }
+            })
+        }));
+        assert!(interrupted.is_err());
+        assert!(attachments.join(JOURNAL_NAME).exists());
+        let pending = store.pending().unwrap().expect("pending key");
+        assert!(store.master.lock().unwrap().is_none());
+
+        // Simulate a crash after a blob was replaced but before the journal recorded it.
+        let (stray, stray_data) = &blobs[120];
+        write_blob(stray, stray_data, &MasterKey(Zeroizing::new(pending)));
+
+        // Then crash right after the new key became the master key.
+        store.crash_on_commit.set(true);
+        let interrupted =
+            catch_unwind(AssertUnwindSafe(|| rotate_with_store(&conn, &attachments, &thumbs, &old_key, &store, |_| {})));
+        assert!(interrupted.is_err());
+        assert_eq!(*store.master.lock().unwrap(), Some(pending));
+        assert!(attachments.join(JOURNAL_NAME).exists());
+        assert_eq!(store.pending().unwrap(), Some(pending));
+        store.crash_on_commit.set(false);
+
+        // The app now loads the committed key as the master key.
+        let master = MasterKey(Zeroizing::new(pending));
+        let messages = RefCell::new(Vec::new());
+        let new_key = rotate_with_store(&conn, &attachments, &thumbs, &master, &store, |msg| {
+            messages.borrow_mut().push(msg.to_string())
+        })
+        .expect("resumed rotation");
+        assert_eq!(new_key.as_bytes(), &pending);
+        assert_eq!(*store.master.lock().unwrap(), Some(pending));
+        assert!(store.pending().unwrap().is_none());
+        assert!(!attachments.join(JOURNAL_NAME).exists());
+        assert_eq!(messages.borrow()[0], "Resuming key rotation...");
+
+        for (path, data) in &blobs {
+            assert_eq!(&read_blob(path, &new_key).expect("new key decrypts"), data);
+            assert!(read_blob(path, &old_key).is_err());
+        }
+        drop(conn);
+        let reopened = open_db(&db_path, &new_key);
+        let value: String = reopened.query_row("SELECT v FROM t;", [], |row| row.get(0)).expect("rekeyed db");
+        assert_eq!(value, "kept");
+        drop(reopened);
+        assert!(open_db(&db_path, &old_key)
+            .query_row("SELECT COUNT(1) FROM sqlite_master;", [], |row| row.get::<_, i64>(0))
+            .is_err());
+    }
+
+    #[test]
+    fn rotation_resumes_after_database_was_rekeyed() {
+        let dir = tempdir().expect("temp");
+        let attachments = dir.path().join("attachments");
+        let old_key = key_from(3);
+        let new_key = key_from(4);
+        let db_path = dir.path().join("archive.sqlite");
+        let conn = open_db(&db_path, &old_key);
+        conn.execute_batch("CREATE TABLE t (v TEXT);").unwrap();
+        conn.execute_batch(&format!("PRAGMA rekey = \"x'{}'\";", hex::encode(new_key.as_bytes())))
+            .unwrap();
+        drop(conn);
+        // The rekey landed but the journal only says it started.
+        fs::create_dir_all(&attachments).unwrap();
+        fs::write(attachments.join(JOURNAL_NAME), format!("{}\n", JOURNAL_DB_STARTED)).unwrap();
+        let store = MemoryStore::default();
+        store.set_pending(new_key.as_bytes()).unwrap();
+
+        let conn = open_db(&db_path, &old_key);
+        let rotated = rotate_with_store(&conn, &attachments, &dir.path().join("thumbs"), &old_key, &store, |_| {})
+            .expect("rotation");
+        assert_eq!(rotated.as_bytes(), new_key.as_bytes());
+        drop(conn);
+        let reopened = open_db(&db_path, &new_key);
+        assert!(reopened.query_row("SELECT COUNT(1) FROM t;", [], |row| row.get::<_, i64>(0)).is_ok());
+    }
+}
@@ -5,6 +5,7 @@ use rusqlite::Connection;
 
 use crate::crypto;
 use crate::error::CoreError;
+use crate::importer::fts;
 use crate::migrations::MIGRATIONS;
 
 pub struct ArchiveDb {
@@ -32,17 +33,40 @@ pub fn open_archive(path: impl AsRef<Path>) -> Result<ArchiveDb, CoreError> {
     conn.execute(
         "UPDATE imports \
          SET status = 'failed', \
-             stats_json = COALESCE(stats_json, '{\"error\":\"import interrupted\"}') \
+             stats_json = json_set(COALESCE(stats_json, '{}'), '$.error', 'import interrupted') \
          WHERE status = 'running';",
         [],
     )?;
     Ok(ArchiveDb { path, conn })
 }
 
+/// Opens the archive with `key` without reading it, for [`crypto::rotate_master_key`]: an
+/// interrupted rotation can leave the database already on the new key, which
+/// [`open_archive`] would fail on.
+pub fn open_archive_for_key_rotation(path: impl AsRef<Path>, key: &crypto::MasterKey) -> Result<ArchiveDb, CoreError> {
+    let path = path.as_ref().to_path_buf();
+    let conn = Connection::open(&path)?;
+    crypto::apply_sqlcipher_key(&conn, key)?;
+    conn.busy_timeout(Duration::from_millis(500))?;
+    Ok(ArchiveDb { path, conn })
+}
+
 pub fn apply_migrations(conn: &Connection) -> Result<(), CoreError> {
+    apply_migrations_through(conn, MIGRATIONS.len())?;
+    fts::ensure_message_fts_current(conn)?;
+    Ok(())
+}
+
+/// Applies migrations up to and including `target`, so a test can write rows the way an
+/// older schema held them before migrating the rest of the way.
+pub fn apply_migrations_through_for_tests(conn: &Connection, target: usize) -> Result<(), CoreError> {
+    apply_migrations_through(conn, target)
+}
+
+fn apply_migrations_through(conn: &Connection, target: usize) -> Result<(), CoreError> {
     let current_version: i64 = conn.query_row("PRAGMA user_version;", [], |row| row.get(0))?;
     let mut version = current_version as usize;
-    for (idx, sql) in MIGRATIONS.iter().enumerate() {
+    for (idx, sql) in MIGRATIONS.iter().enumerate().take(target) {
         let next_version = idx + 1;
         if next_version <= version {
             continue;
@@ -39,6 +39,16 @@ fn sanitize(input: &str) -> String {
     out
 }
 
+/// `hex` in space-separated groups of eight, so a sha256 or key fingerprint survives the
+/// redaction of words with ten or more digits.
+pub fn group_hex(hex: &str) -> String {
+    hex.as_bytes()
+        .chunks(8)
+        .map(|group| String::from_utf8_lossy(group).into_owned())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
 pub fn log_event(log_dir: &Path, kind: &str, message: &str) -> io::Result<()> {
     fs::create_dir_all(log_dir)?;
     let path = log_dir.join("diagnostics.log");
@@ -81,6 +91,15 @@ mod tests {
         assert!(!cleaned.contains("Users"));
     }
 
+    #[test]
+    fn grouped_hex_is_not_redacted() {
+        let sha = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+        assert_eq!(sanitize(sha), "[redacted]");
+        let grouped = group_hex(sha);
+        assert_eq!(sanitize(&grouped), grouped);
+        assert_eq!(grouped.replace(' ', ""), sha);
+    }
+
     #[test]
     fn log_event_writes_and_trims() {
         let dir = tempdir().expect("temp");
@@ -12,4 +12,8 @@ pub enum CoreError {
     NotImplemented(String),
     #[error("crypto error: {0}")]
     Crypto(String),
+    #[error("archive is locked")]
+    Locked,
+    #[error("cancelled")]
+    Cancelled,
 }
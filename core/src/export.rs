@@ -0,0 +1,400 @@
+//! Getting content out of the archive: scrapbooks as standalone Markdown or HTML with their
+//! attachments decrypted next to them, single attachments under their original names, and
+//! the whole archive as a passphrase-sealed bundle that restores on another machine.
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::DateTime;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::crypto::{self, MasterKey};
+use crate::error::CoreError;
+use crate::models::{ExportFormat, MediaExportSummary, MediaRow, ThreadMediaFilters, ThreadMediaRow};
+use crate::query::{list_attachments_for_message, list_thread_media};
+
+#[path = "export/bundle.rs"]
+mod bundle;
+
+pub use bundle::{export_archive_bundle, import_archive_bundle};
+
+const ASSETS_DIR: &str = "assets";
+const MEDIA_EXPORT_PAGE: i64 = 500;
+
+struct ExportMessage {
+    id: String,
+    sort_ts: i64,
+    sender_name: Option<String>,
+    thread_name: Option<String>,
+    body: Option<String>,
+}
+
+/// An attachment as it appears in the export: its path relative to the export file, or
+/// `None` when the encrypted source is missing from `attachments_dir`.
+struct ExportAsset {
+    label: String,
+    mime: Option<String>,
+    path: Option<String>,
+}
+
+/// Exports the messages tagged with `tag_id`, oldest first, to `dest_dir`.
+///
+/// Each entry carries its timestamp (UTC), resolved sender name, thread name and body.
+/// Attachments are decrypted from `attachments_dir` with `key` into `dest_dir/assets/`
+/// (named by content hash, so repeats are written once) and linked relatively. Returns
+/// the path of the written `.md` or `.html` file, named after the tag.
+pub fn export_scrapbook<F>(
+    conn: &Connection,
+    tag_id: &str,
+    attachments_dir: &Path,
+    key: &MasterKey,
+    dest_dir: &Path,
+    format: ExportFormat,
+    progress: F,
+) -> Result<PathBuf, CoreError>
+where
+    F: Fn(&str),
+{
+    let tag_name: String = conn
+        .query_row("SELECT name FROM tags WHERE id = ?1;", params![tag_id], |row| row.get(0))
+        .optional()?
+        .ok_or_else(|| CoreError::InvalidArgument("tag not found".to_string()))?;
+
+    progress("Collecting tagged messages...");
+    let mut stmt = conn.prepare(
+        "SELECT m.id, m.sort_ts, \
+                COALESCE(r.nickname, r.contact_name, r.profile_name, r.username, r.phone_e164, m.sender_id), \
+                t.name, m.body \
+         FROM message_tags mt \
+         JOIN messages m ON m.id = mt.message_id \
+         LEFT JOIN threads t ON t.id = m.thread_id \
+         LEFT JOIN recipients r ON r.id = m.sender_id \
+         WHERE mt.tag_id = ?1 \
+         ORDER BY m.sort_ts ASC, m.id ASC;",
+    )?;
+    let messages: Vec<ExportMessage> = stmt
+        .query_map(params![tag_id], |row| {
+            Ok(ExportMessage {
+                id: row.get(0)?,
+                sort_ts: row.get(1)?,
+                sender_name: row.get(2)?,
+                thread_name: row.get(3)?,
+                body: row.get(4)?,
+            })
+        })?
+        .filter_map(Result::ok)
+        .collect();
+
+    let assets_dir = dest_dir.join(ASSETS_DIR);
+    fs::create_dir_all(&assets_dir).map_err(|e| CoreError::InvalidArgument(e.to_string()))?;
+
+    let mut written: HashMap<String, Option<String>> = HashMap::new();
+    let total = messages.len();
+    let mut entries = Vec::with_capacity(total);
+    for (idx, message) in messages.into_iter().enumerate() {
+        progress(&format!("Exporting messages... {}/{}", idx + 1, total));
+        let mut assets = Vec::new();
+        for media in list_attachments_for_message(conn, &message.id)? {
+            let path = match written.get(&media.sha256) {
+                Some(path) => path.clone(),
+                None => {
+                    let path = export_asset(&media, attachments_dir, &assets_dir, key)?;
+                    written.insert(media.sha256.clone(), path.clone());
+                    path
+                }
+            };
+            assets.push(ExportAsset {
+                label: media.original_filename.clone().unwrap_or_else(|| "attachment".to_string()),
+                mime: media.mime.clone(),
+                path,
+            });
+        }
+        entries.push((message, assets));
+    }
+
+    let (contents, extension) = match format {
+        ExportFormat::Markdown => (render_markdown(&tag_name, &entries), "md"),
+        ExportFormat::Html => (render_html(&tag_name, &entries), "html"),
+    };
+    let out_path = dest_dir.join(format!("{}.{}", file_stem(&tag_name), extension));
+    fs::write(&out_path, contents).map_err(|e| CoreError::InvalidArgument(e.to_string()))?;
+    progress(&format!("Exported {} messages", entries.len()));
+    Ok(out_path)
+}
+
+/// Decrypts one attachment into `assets_dir` and returns its relative path, or `None` if
+/// the archive no longer has the file.
+fn export_asset(
+    media: &MediaRow,
+    attachments_dir: &Path,
+    assets_dir: &Path,
+    key: &MasterKey,
+) -> Result<Option<String>, CoreError> {
+    let source = attachments_dir.join(&media.sha256);
+    if !source.exists() {
+        return Ok(None);
+    }
+    let name = match asset_extension(media.original_filename.as_deref(), media.mime.as_deref()) {
+        Some(ext) => format!("{}.{}", media.sha256, ext),
+        None => media.sha256.clone(),
+    };
+    crypto::decrypt_attachment_to_path(&source, &assets_dir.join(&name), key)?;
+    Ok(Some(format!("{}/{}", ASSETS_DIR, name)))
+}
+
+/// Decrypts the blob `sha256` from `attachments_dir` into `dest_dir` under
+/// `original_filename` (sanitized, falling back to the hash) and returns the written path.
+///
+/// The plaintext lands in a temporary file first and is renamed into place, so a failed
+/// decrypt never leaves a partial file. An existing file is only replaced with `overwrite`.
+pub fn export_attachment(
+    attachments_dir: &Path,
+    sha256: &str,
+    dest_dir: &Path,
+    original_filename: Option<&str>,
+    key: &MasterKey,
+    overwrite: bool,
+) -> Result<PathBuf, CoreError> {
+    let source = attachments_dir.join(sha256);
+    if !source.is_file() {
+        return Err(CoreError::InvalidArgument("attachment missing".to_string()));
+    }
+    let name = original_filename
+        .and_then(sanitize_filename)
+        .unwrap_or_else(|| sha256.to_string());
+    let dest = dest_dir.join(name);
+    if !overwrite && dest.exists() {
+        return Err(CoreError::InvalidArgument(format!("{} already exists", dest.display())));
+    }
+
+    let temp = tempfile::NamedTempFile::new_in(dest_dir).map_err(|e| CoreError::InvalidArgument(e.to_string()))?;
+    crypto::decrypt_attachment_to_path(&source, temp.path(), key)?;
+    if overwrite {
+        temp.persist(&dest).map_err(|e| CoreError::InvalidArgument(e.to_string()))?;
+    } else {
+        temp.persist_noclobber(&dest)
+            .map_err(|e| CoreError::InvalidArgument(format!("{} already exists: {}", dest.display(), e)))?;
+    }
+    Ok(dest)
+}
+
+/// Decrypts every attachment of `thread_id` matching `filters` into `dest_dir`, oldest
+/// first, as `YYYY-MM-DD_originalname` (UTC message date), adding ` (2)`, ` (3)`, ... on
+/// name collisions. One bad attachment doesn't stop the batch: it is counted in the
+/// returned summary and the export moves on.
+pub fn export_thread_media<F>(
+    conn: &Connection,
+    attachments_dir: &Path,
+    key: &MasterKey,
+    thread_id: &str,
+    dest_dir: &Path,
+    filters: &ThreadMediaFilters,
+    progress: F,
+) -> Result<MediaExportSummary, CoreError>
+where
+    F: Fn(&str),
+{
+    progress("Collecting media...");
+    let mut items: Vec<ThreadMediaRow> = Vec::new();
+    loop {
+        let page = list_thread_media(conn, thread_id, filters, "date_asc", MEDIA_EXPORT_PAGE, items.len() as i64)?;
+        let done = (page.len() as i64) < MEDIA_EXPORT_PAGE;
+        items.extend(page);
+        if done {
+            break;
+        }
+    }
+    fs::create_dir_all(dest_dir).map_err(|e| CoreError::InvalidArgument(e.to_string()))?;
+
+    let mut summary = MediaExportSummary {
+        total: items.len() as i64,
+        ..MediaExportSummary::default()
+    };
+    for (idx, item) in items.iter().enumerate() {
+        progress(&format!("Exporting media... {}/{}", idx + 1, items.len()));
+        if !attachments_dir.join(&item.sha256).is_file() {
+            summary.missing += 1;
+            continue;
+        }
+        let name = unique_file_name(dest_dir, &media_export_name(item));
+        match export_attachment(attachments_dir, &item.sha256, dest_dir, Some(&name), key, false) {
+            Ok(_) => summary.exported += 1,
+            Err(_) => summary.failed += 1,
+        }
+    }
+    progress(&format!("Exported {} of {} attachments", summary.exported, summary.total));
+    Ok(summary)
+}
+
+fn media_export_name(item: &ThreadMediaRow) -> String {
+    let date = item
+        .sent_at
+        .or(item.received_at)
+        .and_then(DateTime::from_timestamp_millis)
+        .map(|dt| dt.format("%Y-%m-%d").to_string())
+        .unwrap_or_else(|| "undated".to_string());
+    let original = item.original_filename.as_deref().and_then(sanitize_filename);
+    let name = original.unwrap_or_else(|| {
+        let extension = asset_extension(item.original_filename.as_deref(), item.mime.as_deref());
+        match extension {
+            Some(ext) => format!("{}.{}", item.sha256, ext),
+            None => item.sha256.clone(),
+        }
+    });
+    format!("{}_{}", date, name)
+}
+
+/// `name`, or `stem (n).ext` with the first `n` from 2 that isn't taken in `dir`.
+fn unique_file_name(dir: &Path, name: &str) -> String {
+    if !dir.join(name).exists() {
+        return name.to_string();
+    }
+    let path = Path::new(name);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or(name);
+    let extension = path.extension().and_then(|e| e.to_str());
+    (2..)
+        .map(|n| match extension {
+            Some(ext) => format!("{} ({}).{}", stem, n, ext),
+            None => format!("{} ({})", stem, n),
+        })
+        .find(|candidate| !dir.join(candidate).exists())
+        .unwrap_or_else(|| name.to_string())
+}
+
+/// Reduces an untrusted filename to a single safe path component: no directories, no
+/// separators or control characters, and no leading dots that would hide the file.
+fn sanitize_filename(name: &str) -> Option<String> {
+    let base = name.rsplit(['/', '\\']).next().unwrap_or(name);
+    let cleaned: String = base
+        .chars()
+        .map(|c| if c.is_control() || c == ':' { '_' } else { c })
+        .collect();
+    let cleaned = cleaned.trim().trim_start_matches('.').trim();
+    if cleaned.is_empty() {
+        return None;
+    }
+    // Keep within the 255-byte name limit without splitting a character.
+    let mut end = cleaned.len().min(255);
+    while !cleaned.is_char_boundary(end) {
+        end -= 1;
+    }
+    Some(cleaned[..end].to_string())
+}
+
+/// File extension for an exported attachment, from its original name or else its mime type.
+fn asset_extension(original_filename: Option<&str>, mime: Option<&str>) -> Option<String> {
+    let from_name = original_filename
+        .and_then(|name| Path::new(name).extension())
+        .and_then(|ext| ext.to_str())
+        .filter(|ext| !ext.is_empty() && ext.chars().all(|c| c.is_ascii_alphanumeric()))
+        .map(|ext| ext.to_ascii_lowercase());
+    from_name.or_else(|| {
+        let subtype = mime?.split('/').nth(1)?;
+        let subtype = subtype.split(['+', ';']).next()?;
+        let ext = match subtype {
+            "jpeg" => "jpg",
+            "quicktime" => "mov",
+            "mpeg" => "mp3",
+            other => other,
+        };
+        (!ext.is_empty() && ext.chars().all(|c| c.is_ascii_alphanumeric())).then(|| ext.to_string())
+    })
+}
+
+fn is_image(asset: &ExportAsset) -> bool {
+    asset.mime.as_deref().is_some_and(|mime| mime.starts_with("image/"))
+}
+
+fn format_timestamp(ts: i64) -> String {
+    DateTime::from_timestamp_millis(ts)
+        .map(|dt| dt.format("%Y-%m-%d %H:%M UTC").to_string())
+        .unwrap_or_else(|| ts.to_string())
+}
+
+/// Lowercase ASCII slug of the tag name for the output file; falls back to `scrapbook`.
+fn file_stem(tag_name: &str) -> String {
+    let slug: String = tag_name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '-' })
+        .collect();
+    let slug = slug.split('-').filter(|part| !part.is_empty()).collect::<Vec<_>>().join("-");
+    if slug.is_empty() {
+        "scrapbook".to_string()
+    } else {
+        slug
+    }
+}
+
+fn render_markdown(tag_name: &str, entries: &[(ExportMessage, Vec<ExportAsset>)]) -> String {
+    let mut out = format!("# {}\n", tag_name);
+    for (message, assets) in entries {
+        let _ = write!(
+            out,
+            "\n## {} · {}\n\n*{}*\n",
+            format_timestamp(message.sort_ts),
+            message.sender_name.as_deref().unwrap_or("Unknown"),
+            message.thread_name.as_deref().unwrap_or("Unknown thread"),
+        );
+        if let Some(body) = message.body.as_deref().filter(|body| !body.trim().is_empty()) {
+            let _ = write!(out, "\n{}\n", body);
+        }
+        for asset in assets {
+            let _ = match (&asset.path, is_image(asset)) {
+                (Some(path), true) => write!(out, "\n![{}]({})\n", asset.label, path),
+                (Some(path), false) => write!(out, "\n[{}]({})\n", asset.label, path),
+                (None, _) => write!(out, "\n*{} (unavailable)*\n", asset.label),
+            };
+        }
+    }
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+fn render_html(tag_name: &str, entries: &[(ExportMessage, Vec<ExportAsset>)]) -> String {
+    let title = escape_html(tag_name);
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n\
+         <style>body{{font-family:-apple-system,sans-serif;max-width:42rem;margin:2rem auto;padding:0 1rem}}\
+         article{{margin:2rem 0}}header{{color:#666;font-size:.9rem}}p{{white-space:pre-wrap}}\
+         img{{max-width:100%}}</style>\n</head>\n<body>\n<h1>{title}</h1>\n"
+    );
+    for (message, assets) in entries {
+        let _ = write!(
+            out,
+            "<article>\n<header>{} · {} · <em>{}</em></header>\n",
+            format_timestamp(message.sort_ts),
+            escape_html(message.sender_name.as_deref().unwrap_or("Unknown")),
+            escape_html(message.thread_name.as_deref().unwrap_or("Unknown thread")),
+        );
+        if let Some(body) = message.body.as_deref().filter(|body| !body.trim().is_empty()) {
+            let _ = writeln!(out, "<p>{}</p>", escape_html(body));
+        }
+        for asset in assets {
+            let label = escape_html(&asset.label);
+            let _ = match (&asset.path, is_image(asset)) {
+                (Some(path), true) => writeln!(out, "<img src=\"{}\" alt=\"{}\">", escape_html(path), label),
+                (Some(path), false) => writeln!(out, "<p><a href=\"{}\">{}</a></p>", escape_html(path), label),
+                (None, _) => writeln!(out, "<p><em>{} (unavailable)</em></p>", label),
+            };
+        }
+        out.push_str("</article>\n");
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
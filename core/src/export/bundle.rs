@@ -0,0 +1,528 @@
+//! Portable archive bundles: the whole archive in one `.gtarchive` file, sealed under a
+//! passphrase instead of this machine's master key so it can be restored elsewhere. Layout:
+//!
+//! `[MAGIC: 4][VERSION: 1][M_COST: 4][T_COST: 4][P_COST: 4][SALT: 16]`, then entries of
+//! `[LEN: 8][STREAM: LEN]`
+//!
+//! Each entry is an attachment-format stream (chunked AES-256-GCM) under the Argon2id key
+//! derived from the passphrase and salt, whose plaintext starts with
+//! `[KIND: 1][NAME_LEN: 1][NAME]`. A bundle holds the manifest, the database (a SQLCipher
+//! copy keyed with the bundle key), every attachment blob under its name, and an end marker
+//! with the attachment count so a bundle cut off between entries is noticed. Thumbnails are
+//! left out; they are regenerated on demand.
+
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use chrono::Utc;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use tempfile::NamedTempFile;
+
+use crate::crypto::{self, KdfCosts, MasterKey};
+use crate::db::open_archive;
+use crate::error::CoreError;
+use crate::migrations::MIGRATIONS;
+use crate::models::ArchiveBundleSummary;
+
+const BUNDLE_MAGIC: [u8; 4] = *b"GTAB";
+const BUNDLE_VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+const HEADER_LEN: usize = 4 + 1 + KdfCosts::ENCODED_LEN + SALT_LEN;
+
+const KIND_MANIFEST: u8 = b'M';
+const KIND_DATABASE: u8 = b'D';
+const KIND_ATTACHMENT: u8 = b'A';
+const KIND_END: u8 = b'E';
+
+const ARCHIVE_DB_NAME: &str = "archive.sqlite";
+const ATTACHMENTS_DIR: &str = "attachments";
+/// The database copy while it is written out or read back, inside a staging directory.
+const BUNDLE_DB_NAME: &str = "bundle.sqlite";
+/// How many attachments are written or restored between progress callbacks.
+const BUNDLE_PROGRESS_BATCH: usize = 100;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BundleManifest {
+    created_at: i64,
+    schema_version: i64,
+    /// Attachments the export found; unreadable ones among them are left out.
+    attachments: usize,
+}
+
+/// Writes the archive in `archive_dir` (its database and attachment blobs) to a bundle at
+/// `dest_path` sealed under `passphrase`, replacing any file there once it is complete.
+/// Attachments that no longer decrypt are left out and counted as `unreadable`.
+pub fn export_archive_bundle<F>(
+    archive_dir: &Path,
+    dest_path: &Path,
+    passphrase: &str,
+    progress: F,
+) -> Result<ArchiveBundleSummary, CoreError>
+where
+    F: Fn(&str),
+{
+    export_with_costs(archive_dir, dest_path, passphrase, KdfCosts::default(), progress)
+}
+
+fn export_with_costs<F>(
+    archive_dir: &Path,
+    dest_path: &Path,
+    passphrase: &str,
+    costs: KdfCosts,
+    progress: F,
+) -> Result<ArchiveBundleSummary, CoreError>
+where
+    F: Fn(&str),
+{
+    if passphrase.is_empty() {
+        return Err(CoreError::InvalidPassphrase("bundle passphrase is empty".to_string()));
+    }
+    let db_path = archive_dir.join(ARCHIVE_DB_NAME);
+    if !db_path.exists() {
+        return Err(CoreError::InvalidArgument("there is no archive to export".to_string()));
+    }
+    progress("Deriving bundle key...");
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let bundle_key = crypto::passphrase_stream_key(passphrase, &salt, costs)?;
+    let master_key = crypto::load_or_create_master_key()?;
+
+    progress("Copying database...");
+    let staging = staging_dir(archive_dir, ".bundle-export")?;
+    let db_copy = staging.path().join(BUNDLE_DB_NAME);
+    let schema_version = {
+        let archive = open_archive(&db_path)?;
+        copy_database(&archive.conn, &db_copy, &bundle_key, "main", "bundle")?
+    };
+    let blobs = blob_names(&archive_dir.join(ATTACHMENTS_DIR))?;
+
+    let dest_dir = dest_path.parent().unwrap_or_else(|| Path::new("."));
+    fs::create_dir_all(dest_dir).map_err(io_error)?;
+    let mut temp = NamedTempFile::new_in(dest_dir).map_err(io_error)?;
+    let out = temp.as_file_mut();
+    out.write_all(&BUNDLE_MAGIC).map_err(io_error)?;
+    out.write_all(&[BUNDLE_VERSION]).map_err(io_error)?;
+    out.write_all(&costs.to_le_bytes()).map_err(io_error)?;
+    out.write_all(&salt).map_err(io_error)?;
+
+    let manifest = BundleManifest {
+        created_at: Utc::now().timestamp_millis(),
+        schema_version,
+        attachments: blobs.len(),
+    };
+    let manifest = serde_json::to_vec(&manifest).map_err(|e| CoreError::InvalidArgument(e.to_string()))?;
+    write_entry(out, &bundle_key, KIND_MANIFEST, "manifest", &mut manifest.as_slice())?;
+    let mut db_file = BufReader::new(File::open(&db_copy).map_err(io_error)?);
+    write_entry(out, &bundle_key, KIND_DATABASE, ARCHIVE_DB_NAME, &mut db_file)?;
+    drop(db_file);
+    drop(staging);
+
+    let total = blobs.len();
+    let mut summary = ArchiveBundleSummary::default();
+    progress(&format!("Exporting attachments... 0/{}", total));
+    for (idx, name) in blobs.iter().enumerate() {
+        let start = out.stream_position().map_err(io_error)?;
+        let mut source = BufReader::new(File::open(archive_dir.join(ATTACHMENTS_DIR).join(name)).map_err(io_error)?);
+        let (decrypted, written) = crypto::pipe_decrypted(&mut source, &master_key, |plain| {
+            write_entry(out, &bundle_key, KIND_ATTACHMENT, name, plain)
+        });
+        if decrypted.is_err() {
+            // Drop whatever part of the blob made it into the entry.
+            out.set_len(start).map_err(io_error)?;
+            out.seek(SeekFrom::Start(start)).map_err(io_error)?;
+            summary.unreadable += 1;
+        } else {
+            written?;
+            summary.attachments += 1;
+        }
+        let done = idx + 1;
+        if done % BUNDLE_PROGRESS_BATCH == 0 || done == total {
+            progress(&format!("Exporting attachments... {}/{}", done, total));
+        }
+    }
+    if summary.unreadable > 0 {
+        progress(&format!("{} attachments could not be decrypted and were left out", summary.unreadable));
+    }
+    write_entry(out, &bundle_key, KIND_END, "end", &mut &(summary.attachments as u64).to_le_bytes()[..])?;
+
+    out.sync_all().map_err(io_error)?;
+    temp.persist(dest_path).map_err(|e| io_error(e.error))?;
+    progress("Archive exported");
+    Ok(summary)
+}
+
+/// Restores the bundle at `src`, sealed under `passphrase`, into `archive_dir`, replacing an
+/// archive there only if it is empty. Attachments are re-encrypted under this machine's master key as
+/// they are read; nothing lands in `archive_dir` until the whole bundle has checked out.
+pub fn import_archive_bundle<F>(
+    src: &Path,
+    archive_dir: &Path,
+    passphrase: &str,
+    progress: F,
+) -> Result<ArchiveBundleSummary, CoreError>
+where
+    F: Fn(&str),
+{
+    let db_path = archive_dir.join(ARCHIVE_DB_NAME);
+    let attachments_dir = archive_dir.join(ATTACHMENTS_DIR);
+    if !archive_is_empty(&db_path, &attachments_dir)? {
+        return Err(CoreError::InvalidArgument(
+            "the archive is not empty; reset it before restoring a bundle".to_string(),
+        ));
+    }
+    let mut input = BufReader::new(File::open(src).map_err(io_error)?);
+    let mut header = [0u8; HEADER_LEN];
+    if input.read_exact(&mut header).is_err() || header[..4] != BUNDLE_MAGIC {
+        return Err(CoreError::InvalidArgument("not an archive bundle".to_string()));
+    }
+    if header[4] != BUNDLE_VERSION {
+        return Err(CoreError::InvalidArgument("unsupported archive bundle version".to_string()));
+    }
+    let mut encoded_costs = [0u8; KdfCosts::ENCODED_LEN];
+    encoded_costs.copy_from_slice(&header[5..5 + KdfCosts::ENCODED_LEN]);
+    let costs = KdfCosts::from_le_bytes(&encoded_costs);
+    let salt = &header[5 + KdfCosts::ENCODED_LEN..];
+
+    progress("Deriving bundle key...");
+    let bundle_key = crypto::passphrase_stream_key(passphrase, salt, costs)?;
+    let master_key = crypto::load_or_create_master_key()?;
+
+    fs::create_dir_all(archive_dir).map_err(io_error)?;
+    let staging = staging_dir(archive_dir, ".bundle-import")?;
+    let staged_db = staging.path().join(ARCHIVE_DB_NAME);
+    let staged_attachments = staging.path().join(ATTACHMENTS_DIR);
+    fs::create_dir_all(&staged_attachments).map_err(io_error)?;
+
+    let mut manifest: Option<BundleManifest> = None;
+    let mut restored_db = false;
+    let mut end_count: Option<u64> = None;
+    let mut summary = ArchiveBundleSummary::default();
+    loop {
+        if input.fill_buf().map_err(io_error)?.is_empty() {
+            break;
+        }
+        if end_count.is_some() {
+            return Err(damaged("entries after its end marker"));
+        }
+        let mut len = [0u8; 8];
+        input.read_exact(&mut len).map_err(|_| damaged("a truncated entry"))?;
+        let mut entry = (&mut input).take(u64::from_le_bytes(len));
+        let (decrypted, restored) = crypto::pipe_decrypted(&mut entry, &bundle_key, |plain| {
+            read_entry(plain, staging.path(), &staged_attachments, &master_key)
+        });
+        if let Err(err) = decrypted {
+            return Err(match manifest {
+                None => CoreError::InvalidPassphrase("bundle passphrase is incorrect".to_string()),
+                Some(_) => err,
+            });
+        }
+        if entry.limit() > 0 {
+            return Err(damaged("a truncated entry"));
+        }
+        match (restored?, &manifest) {
+            (Entry::Manifest(read), None) => {
+                if read.schema_version > MIGRATIONS.len() as i64 {
+                    return Err(CoreError::InvalidArgument(
+                        "this bundle was made by a newer version of the app".to_string(),
+                    ));
+                }
+                manifest = Some(read);
+            }
+            (Entry::Database, Some(_)) if !restored_db => {
+                progress("Restoring database...");
+                let conn = Connection::open(&staged_db)?;
+                crypto::apply_sqlcipher_key(&conn, &master_key)?;
+                copy_database(&conn, &staging.path().join(BUNDLE_DB_NAME), &bundle_key, "bundle", "main")?;
+                drop(conn);
+                fs::remove_file(staging.path().join(BUNDLE_DB_NAME)).map_err(io_error)?;
+                restored_db = true;
+                progress(&format!("Restoring attachments... 0/{}", manifest.as_ref().map_or(0, |m| m.attachments)));
+            }
+            (Entry::Attachment(name, temp), Some(manifest)) if restored_db => {
+                temp.persist(staged_attachments.join(&name)).map_err(|e| io_error(e.error))?;
+                summary.attachments += 1;
+                let done = summary.attachments as usize;
+                if done.is_multiple_of(BUNDLE_PROGRESS_BATCH) || done == manifest.attachments {
+                    progress(&format!("Restoring attachments... {}/{}", done, manifest.attachments));
+                }
+            }
+            (Entry::End(count), Some(_)) if restored_db => end_count = Some(count),
+            _ => return Err(damaged("entries out of order")),
+        }
+    }
+    if end_count != Some(summary.attachments as u64) {
+        return Err(damaged("missing entries"));
+    }
+
+    // The database goes last: its presence is what marks the archive as restored.
+    if attachments_dir.exists() {
+        fs::remove_dir_all(&attachments_dir).map_err(io_error)?;
+    }
+    fs::rename(&staged_attachments, &attachments_dir).map_err(io_error)?;
+    for suffix in ["-wal", "-shm"] {
+        let sidecar = archive_dir.join(format!("{ARCHIVE_DB_NAME}{suffix}"));
+        if sidecar.exists() {
+            fs::remove_file(&sidecar).map_err(io_error)?;
+        }
+    }
+    fs::rename(&staged_db, &db_path).map_err(io_error)?;
+    progress("Archive restored");
+    Ok(summary)
+}
+
+enum Entry {
+    Manifest(BundleManifest),
+    /// Written to `BUNDLE_DB_NAME` in the staging directory.
+    Database,
+    /// Re-encrypted under the master key, waiting to be persisted under its name.
+    Attachment(String, NamedTempFile),
+    End(u64),
+}
+
+/// Reads one entry's plaintext from `plain`. The caller acts on it only once the entry
+/// decrypted in full.
+fn read_entry<R: Read>(
+    plain: &mut R,
+    staging: &Path,
+    staged_attachments: &Path,
+    master_key: &MasterKey,
+) -> Result<Entry, CoreError> {
+    let mut prefix = [0u8; 2];
+    plain.read_exact(&mut prefix).map_err(|_| damaged("an empty entry"))?;
+    let mut name = vec![0u8; prefix[1] as usize];
+    plain.read_exact(&mut name).map_err(|_| damaged("a truncated entry"))?;
+    let name = String::from_utf8(name).map_err(|_| damaged("an invalid entry name"))?;
+    match prefix[0] {
+        KIND_MANIFEST => {
+            let mut json = Vec::new();
+            plain.read_to_end(&mut json).map_err(io_error)?;
+            let manifest = serde_json::from_slice(&json).map_err(|_| damaged("an invalid manifest"))?;
+            Ok(Entry::Manifest(manifest))
+        }
+        KIND_DATABASE => {
+            let mut file = File::create(staging.join(BUNDLE_DB_NAME)).map_err(io_error)?;
+            io::copy(plain, &mut file).map_err(io_error)?;
+            file.sync_all().map_err(io_error)?;
+            Ok(Entry::Database)
+        }
+        KIND_ATTACHMENT => {
+            if !is_blob_name(&name) {
+                return Err(damaged("an invalid attachment name"));
+            }
+            let mut temp = NamedTempFile::new_in(staged_attachments).map_err(io_error)?;
+            let (hash, _) = crypto::encrypt_stream_with_hash(plain, temp.as_file_mut(), master_key)?;
+            // Blobs are named by content hash; one that doesn't match was swapped with another.
+            if name.len() == 64 && hash != name {
+                return Err(damaged("an attachment that does not match its name"));
+            }
+            Ok(Entry::Attachment(name, temp))
+        }
+        KIND_END => {
+            let mut count = [0u8; 8];
+            plain.read_exact(&mut count).map_err(|_| damaged("an invalid end marker"))?;
+            Ok(Entry::End(u64::from_le_bytes(count)))
+        }
+        _ => Err(damaged("an unknown entry")),
+    }
+}
+
+/// Appends one entry, `kind` and `name` followed by `content`, encrypted under `key`.
+fn write_entry<R: Read>(out: &mut File, key: &MasterKey, kind: u8, name: &str, content: &mut R) -> Result<(), CoreError> {
+    let start = out.stream_position().map_err(io_error)?;
+    out.write_all(&[0u8; 8]).map_err(io_error)?;
+    let mut prefix = vec![kind, name.len() as u8];
+    prefix.extend_from_slice(name.as_bytes());
+    crypto::encrypt_stream(&mut prefix.as_slice().chain(content), out, key)?;
+    let end = out.stream_position().map_err(io_error)?;
+    out.seek(SeekFrom::Start(start)).map_err(io_error)?;
+    out.write_all(&(end - start - 8).to_le_bytes()).map_err(io_error)?;
+    out.seek(SeekFrom::Start(end)).map_err(io_error)?;
+    Ok(())
+}
+
+/// Copies database `source` into the empty database `target` with `sqlcipher_export`, one
+/// of them being `path`, attached as `bundle` under `key`. Returns the schema version.
+fn copy_database(conn: &Connection, path: &Path, key: &MasterKey, source: &str, target: &str) -> Result<i64, CoreError> {
+    conn.execute(
+        "ATTACH DATABASE ?1 AS bundle KEY ?2;",
+        params![path.to_string_lossy(), format!("x'{}'", hex::encode(key.as_bytes()))],
+    )?;
+    let copied = (|| -> Result<i64, CoreError> {
+        let version: i64 = conn.query_row(&format!("PRAGMA {source}.user_version;"), [], |row| row.get(0))?;
+        conn.query_row(&format!("SELECT sqlcipher_export('{target}', '{source}');"), [], |_| Ok(()))?;
+        conn.execute_batch(&format!("PRAGMA {target}.user_version = {version};"))?;
+        Ok(version)
+    })();
+    let detached = conn.execute_batch("DETACH DATABASE bundle;");
+    let version = copied?;
+    detached?;
+    Ok(version)
+}
+
+/// Whether the archive holds nothing a restore would lose: no messages and no blobs. A
+/// fresh install has opened an empty archive before there is anything to restore into it.
+fn archive_is_empty(db_path: &Path, attachments_dir: &Path) -> Result<bool, CoreError> {
+    if !blob_names(attachments_dir)?.is_empty() {
+        return Ok(false);
+    }
+    if !db_path.exists() {
+        return Ok(true);
+    }
+    let archive = open_archive(db_path)?;
+    let messages: i64 = archive.conn.query_row("SELECT COUNT(1) FROM messages;", [], |row| row.get(0))?;
+    Ok(messages == 0)
+}
+
+/// A private scratch directory in `archive_dir`, removed when dropped.
+fn staging_dir(archive_dir: &Path, prefix: &str) -> Result<tempfile::TempDir, CoreError> {
+    tempfile::Builder::new()
+        .prefix(prefix)
+        .tempdir_in(archive_dir)
+        .map_err(io_error)
+}
+
+/// Attachment blob names in `dir`, sorted; anything else there is skipped.
+fn blob_names(dir: &Path) -> Result<Vec<String>, CoreError> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut names = Vec::new();
+    for entry in fs::read_dir(dir).map_err(io_error)? {
+        let entry = entry.map_err(io_error)?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if is_blob_name(&name) && entry.file_type().map_err(io_error)?.is_file() {
+            names.push(name);
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Blob names are content hashes; this also keeps a bundle's names from escaping the
+/// attachments directory.
+fn is_blob_name(name: &str) -> bool {
+    !name.is_empty() && name.len() <= 128 && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+}
+
+fn damaged(what: &str) -> CoreError {
+    CoreError::Crypto(format!("archive bundle is damaged: {what}"))
+}
+
+fn io_error(err: io::Error) -> CoreError {
+    CoreError::Crypto(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::seed::seed_demo;
+    use std::cell::RefCell;
+    use tempfile::tempdir;
+
+    /// A seeded archive in `dir` with `blobs` attachments, returning each blob's name and
+    /// plaintext.
+    fn seed_archive(dir: &Path, blobs: u8) -> Vec<(String, Vec<u8>)> {
+        crypto::set_test_key_from_passphrase("golden-thread-tests");
+        let key = crypto::load_or_create_master_key().expect("key");
+        let archive = open_archive(dir.join(ARCHIVE_DB_NAME)).expect("open");
+        seed_demo(&archive.conn, 6, 1).expect("seed");
+        let attachments = dir.join(ATTACHMENTS_DIR);
+        fs::create_dir_all(&attachments).unwrap();
+        (0..blobs)
+            .map(|i| {
+                let data = vec![i; 100 + i as usize * 10];
+                let mut temp = NamedTempFile::new_in(&attachments).unwrap();
+                let (hash, _) = crypto::encrypt_stream_with_hash(&mut data.as_slice(), temp.as_file_mut(), &key).unwrap();
+                temp.persist(attachments.join(&hash)).unwrap();
+                (hash, data)
+            })
+            .collect()
+    }
+
+    fn message_count(dir: &Path) -> i64 {
+        let archive = open_archive(dir.join(ARCHIVE_DB_NAME)).expect("open");
+        archive.conn.query_row("SELECT COUNT(1) FROM messages;", [], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn bundle_round_trips_a_seeded_archive() {
+        let source = tempdir().expect("temp");
+        let blobs = seed_archive(source.path(), 5);
+        // Not a blob: left out of the bundle.
+        fs::write(source.path().join(ATTACHMENTS_DIR).join(".key-rotation"), b"stray").unwrap();
+        let bundle = source.path().join("export").join("archive.gtarchive");
+
+        let messages = RefCell::new(Vec::new());
+        let exported = export_with_costs(source.path(), &bundle, "bundle pass", KdfCosts::TEST, |msg| {
+            messages.borrow_mut().push(msg.to_string())
+        })
+        .expect("export");
+        assert_eq!(exported.attachments, 5);
+        assert_eq!(exported.unreadable, 0);
+        assert_eq!(messages.borrow().last().map(String::as_str), Some("Archive exported"));
+        let contents = fs::read(&bundle).unwrap();
+        for (_, data) in &blobs {
+            assert!(!contents.windows(data.len()).any(|window| window == data.as_slice()));
+        }
+
+        let restored = tempdir().expect("temp");
+        // An empty archive, as a fresh install opens one, is replaced.
+        drop(open_archive(restored.path().join(ARCHIVE_DB_NAME)).expect("open"));
+        let imported = import_archive_bundle(&bundle, restored.path(), "bundle pass", |_| {}).expect("import");
+        assert_eq!(imported.attachments, 5);
+        assert_eq!(message_count(restored.path()), message_count(source.path()));
+        let key = crypto::load_or_create_master_key().expect("key");
+        for (name, data) in &blobs {
+            let mut plain = Vec::new();
+            let mut blob = BufReader::new(File::open(restored.path().join(ATTACHMENTS_DIR).join(name)).unwrap());
+            crypto::decrypt_stream(&mut blob, &mut plain, &key).expect("decrypts under the master key");
+            assert_eq!(&plain, data);
+        }
+        let leftovers: Vec<_> = fs::read_dir(restored.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| name.starts_with(".bundle"))
+            .collect();
+        assert!(leftovers.is_empty());
+
+        // A second restore into the same archive is refused.
+        assert!(matches!(
+            import_archive_bundle(&bundle, restored.path(), "bundle pass", |_| {}),
+            Err(CoreError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn bundle_rejects_wrong_passphrase_and_truncation() {
+        let source = tempdir().expect("temp");
+        seed_archive(source.path(), 3);
+        let bundle = source.path().join("archive.gtarchive");
+        export_with_costs(source.path(), &bundle, "right", KdfCosts::TEST, |_| {}).expect("export");
+
+        let target = tempdir().expect("temp");
+        assert!(matches!(
+            import_archive_bundle(&bundle, target.path(), "wrong", |_| {}),
+            Err(CoreError::InvalidPassphrase(_))
+        ));
+        assert!(!target.path().join(ARCHIVE_DB_NAME).exists());
+
+        // Drop the end marker: every remaining entry is intact, but the bundle is incomplete.
+        let contents = fs::read(&bundle).unwrap();
+        let mut offset = HEADER_LEN;
+        let mut last = offset;
+        while offset < contents.len() {
+            last = offset;
+            let len = u64::from_le_bytes(contents[offset..offset + 8].try_into().unwrap()) as usize;
+            offset += 8 + len;
+        }
+        let cut = source.path().join("cut.gtarchive");
+        fs::write(&cut, &contents[..last]).unwrap();
+        assert!(import_archive_bundle(&cut, target.path(), "right", |_| {}).is_err());
+        assert!(!target.path().join(ARCHIVE_DB_NAME).exists());
+        assert!(!target.path().join(ATTACHMENTS_DIR).exists());
+    }
+}
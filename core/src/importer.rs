@@ -1,9 +1,11 @@
+use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
 use crate::error::CoreError;
 use crate::ffi::signalbackup;
-use crate::{db::open_archive};
-use rusqlite::{params, Connection, OptionalExtension};
+use crate::{crypto, db::open_archive, maintenance};
+use crate::models::{BackupInspection, ImportProgress, ImportStage};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
 use sha2::{Digest, Sha256};
 use uuid::Uuid;
 use chrono::Utc;
@@ -11,16 +13,46 @@ use libc::statvfs;
 
 #[path = "importer/attachments.rs"]
 mod attachments;
+#[path = "importer/calls.rs"]
+mod calls;
+#[path = "importer/events.rs"]
+mod events;
 #[path = "importer/fts.rs"]
-mod fts;
+pub(crate) mod fts;
+#[path = "importer/identity.rs"]
+mod identity;
+#[path = "importer/issues.rs"]
+mod issues;
+#[path = "importer/phone.rs"]
+mod phone;
+#[path = "importer/previews.rs"]
+mod previews;
+#[path = "importer/receipts.rs"]
+mod receipts;
+#[path = "importer/resume.rs"]
+mod resume;
+
+pub use fts::rebuild_fts;
 use rusqlite::types::Value;
 
+/// Source message rows committed per transaction, each commit recording a checkpoint.
+const MESSAGE_CHUNK_ROWS: i64 = 50_000;
+
 #[derive(Debug, Clone)]
 pub struct ImportPlan {
     pub source_path: String,
     pub normalized_passphrase: String,
     pub source_filename: String,
     pub source_hash: String,
+    /// Decrypt and rehash every stored attachment once the import commits, recording
+    /// corrupt blobs in the import's stats. Off by default: it reads the whole store.
+    pub verify_attachments: bool,
+    /// Skip the post-import `ANALYZE`, `PRAGMA optimize` and WAL checkpoint, which tests
+    /// on small archives don't need.
+    pub skip_optimize: bool,
+    /// Calling code (digits, e.g. "1") given to recipient numbers stored without one.
+    /// `None` uses the backup owner's own number's code when the backup has it.
+    pub default_country_code: Option<String>,
 }
 
 pub fn normalize_passphrase(raw: &str) -> Result<String, CoreError> {
@@ -52,7 +84,7 @@ pub fn plan_import_with_progress<F>(
     progress: F,
 ) -> Result<ImportPlan, CoreError>
 where
-    F: Fn(&str),
+    F: Fn(ImportProgress),
 {
     let normalized = normalize_passphrase(passphrase)?;
     if !source_path.exists() {
@@ -66,7 +98,7 @@ where
     if metadata.len() == 0 {
         return Err(CoreError::InvalidArgument("backup file is empty".to_string()));
     }
-    progress("Preparing import...");
+    progress(ImportProgress::new(ImportStage::Hashing));
     let source_hash = hash_file_sha256_with_progress(source_path, &progress)?;
     let source_filename = source_path
         .file_name()
@@ -78,18 +110,21 @@ where
         normalized_passphrase: normalized,
         source_filename,
         source_hash,
+        verify_attachments: false,
+        skip_optimize: false,
+        default_country_code: None,
     })
 }
 
 fn hash_file_sha256_with_progress<F>(path: &Path, progress: F) -> Result<String, CoreError>
 where
-    F: Fn(&str),
+    F: Fn(ImportProgress),
 {
     let mut file = fs::File::open(path)
         .map_err(|e| CoreError::InvalidArgument(format!("backup open failed: {}", e)))?;
     let metadata = fs::metadata(path)
         .map_err(|e| CoreError::InvalidArgument(format!("backup stat failed: {}", e)))?;
-    let total = metadata.len().max(1);
+    let total = metadata.len();
     let mut hasher = Sha256::new();
     let mut buf = [0u8; 8192];
     let mut processed: u64 = 0;
@@ -102,11 +137,11 @@ where
         }
         hasher.update(&buf[..n]);
         processed = processed.saturating_add(n as u64);
-        let percent = (processed * 100) / total;
+        let percent = (processed * 100) / total.max(1);
         if percent > last_percent {
             last_percent = percent;
             if percent <= 100 {
-                progress(&format!("Preparing import... {}%", percent));
+                progress(ImportProgress::counted(ImportStage::Hashing, processed, total));
             }
         }
     }
@@ -123,10 +158,9 @@ pub fn import_backup_with_progress<F>(
     progress: F,
 ) -> Result<(), CoreError>
 where
-    F: Fn(&str),
+    F: Fn(ImportProgress),
 {
-    progress("Decoding backup...");
-    let import_id = Uuid::new_v4().to_string();
+    progress(ImportProgress::new(ImportStage::Decoding));
     let temp_dir = tempfile::tempdir()
         .map_err(|e| CoreError::InvalidArgument(format!("temp dir failed: {}", e)))?;
     check_disk_space(&temp_dir.path(), archive_path, &plan.source_path)?;
@@ -135,45 +169,18 @@ where
     fs::create_dir_all(&frames_dir)
         .map_err(|e| CoreError::InvalidArgument(format!("frames dir failed: {}", e)))?;
 
+    let attachments_dir = archive_path
+        .parent()
+        .ok_or_else(|| CoreError::InvalidArgument("archive path missing parent".to_string()))?
+        .join("attachments");
     let mut archive = open_archive(archive_path)?;
-    let import_started_at = Utc::now().timestamp_millis();
-    let exists: Option<String> = archive
-        .conn
-        .query_row(
-            "SELECT id FROM imports WHERE source_hash = ?1 AND status = 'success' LIMIT 1;",
-            params![plan.source_hash],
-            |row| row.get(0),
-        )
-        .optional()?;
-    if exists.is_some() {
-        return Err(CoreError::InvalidArgument(
-            "archive already loaded for this backup".to_string(),
-        ));
-    }
-
-    archive.conn.execute(
-        "INSERT INTO imports (id, imported_at, source_filename, source_hash, detected_version, status, stats_json)
-         VALUES (?1, ?2, ?3, ?4, NULL, 'running', NULL);",
-        params![
-            import_id,
-            import_started_at,
-            plan.source_filename,
-            plan.source_hash
-        ],
-    )?;
+    let import_id = begin_import(&archive.conn, &plan.source_filename, &plan.source_hash)?;
 
-    if let Err(err) = signalbackup::decode_backup(
-        Path::new(&plan.source_path),
-        &plan.normalized_passphrase,
-        &db_path,
-        &frames_dir,
-        true,
-    ) {
-        let err_msg = err.to_string();
-        if err_msg.to_lowercase().contains("unsupported") {
-            return Err(CoreError::InvalidArgument(
-                "unsupported backup version detected".to_string(),
-            ));
+    if let Err(err) = decode_plan(plan, &db_path, &frames_dir) {
+        if is_unsupported_version(&err) {
+            let msg = "unsupported backup version detected".to_string();
+            mark_import_failed(&archive.conn, &import_id, &msg);
+            return Err(CoreError::InvalidArgument(msg));
         }
         // keep temp dir for inspection
         let keep_dir = temp_dir.keep().to_string_lossy().to_string();
@@ -184,39 +191,48 @@ where
         } else {
             format!("{} (logs: {})\n{}", err, log_path.display(), log_tail)
         };
-        let _ = archive.conn.execute(
-            "UPDATE imports SET status = 'failed', stats_json = ?2 WHERE id = ?1;",
-            params![import_id, format!(r#"{{"error":{}}}"#, serde_json::to_string(&msg).unwrap_or("null".to_string()))],
-        );
+        mark_import_failed(&archive.conn, &import_id, &msg);
         return Err(CoreError::InvalidArgument(msg));
     }
 
-    progress("Opening decrypted database...");
-    let signal_conn = Connection::open(&db_path)?;
-    let attachments_dir = archive_path
-        .parent()
-        .ok_or_else(|| CoreError::InvalidArgument("archive path missing parent".to_string()))?
-        .join("attachments");
-    let stats = match map_signal_db(
-        &signal_conn,
-        &mut archive.conn,
-        &progress,
-        &frames_dir,
-        &attachments_dir,
-    ) {
+    progress(ImportProgress::new(ImportStage::Decoding).with_detail("opening database"));
+    let opened = Connection::open(&db_path).map_err(CoreError::from).and_then(|signal_conn| {
+        let detected_version = detect_signal_version(&signal_conn)?;
+        archive.conn.execute(
+            "UPDATE imports SET detected_version = ?2 WHERE id = ?1;",
+            params![import_id, detected_version],
+        )?;
+        Ok(signal_conn)
+    });
+    let signal_conn = match opened {
+        Ok(signal_conn) => signal_conn,
+        Err(err) => {
+            mark_import_failed(&archive.conn, &import_id, &err.to_string());
+            return Err(err);
+        }
+    };
+    let mapped = resume::ImportCheckpoint::load(&archive.conn, &import_id).and_then(|mut checkpoint| {
+        map_signal_db(
+            &signal_conn,
+            &mut archive.conn,
+            &progress,
+            &frames_dir,
+            &attachments_dir,
+            plan.default_country_code.as_deref(),
+            &mut checkpoint,
+        )
+    })
+    .and_then(|stats| {
+        if plan.verify_attachments {
+            verify_imported_attachments(&archive.conn, &attachments_dir, &stats, &progress)
+        } else {
+            Ok(stats)
+        }
+    });
+    let stats = match mapped {
         Ok(stats) => stats,
         Err(err) => {
-            let msg = err.to_string();
-            let _ = archive.conn.execute(
-                "UPDATE imports SET status = 'failed', stats_json = ?2 WHERE id = ?1;",
-                params![
-                    import_id,
-                    format!(
-                        r#"{{"error":{}}}"#,
-                        serde_json::to_string(&msg).unwrap_or("null".to_string())
-                    )
-                ],
-            );
+            mark_import_failed(&archive.conn, &import_id, &err.to_string());
             return Err(err);
         }
     };
@@ -224,9 +240,193 @@ where
         "UPDATE imports SET status = 'success', stats_json = ?2 WHERE id = ?1;",
         params![import_id, stats],
     );
+    let _ = archive.conn.execute("DELETE FROM import_checkpoints WHERE import_id = ?1;", params![import_id]);
+    let _ = archive.conn.execute("DELETE FROM import_message_ids WHERE import_id = ?1;", params![import_id]);
+    if !plan.skip_optimize {
+        // The import is already committed; a failure here only costs query speed.
+        let _ = optimize_imported_archive(&archive.conn, &import_id, &progress);
+    }
+    Ok(())
+}
+
+/// Gathers planner statistics and truncates the WAL once an import has committed, so the
+/// first thread list and search after a large import don't run on default query plans.
+/// Records the elapsed time as `optimize_ms` in the import's `stats_json`.
+fn optimize_imported_archive<F>(conn: &Connection, import_id: &str, progress: &F) -> Result<(), CoreError>
+where
+    F: Fn(ImportProgress),
+{
+    let start = std::time::Instant::now();
+    progress(ImportProgress::new(ImportStage::Finalizing).with_detail("analyzing"));
+    conn.execute_batch("ANALYZE;")?;
+    progress(ImportProgress::new(ImportStage::Finalizing).with_detail("optimizing"));
+    conn.execute_batch("PRAGMA optimize;")?;
+    progress(ImportProgress::new(ImportStage::Finalizing).with_detail("checkpointing"));
+    conn.query_row("PRAGMA wal_checkpoint(TRUNCATE);", [], |_| Ok(()))?;
+    conn.execute(
+        "UPDATE imports SET stats_json = json_set(COALESCE(stats_json, '{}'), '$.optimize_ms', ?2) WHERE id = ?1;",
+        params![import_id, start.elapsed().as_millis() as i64],
+    )?;
     Ok(())
 }
 
+/// Decodes the backup and counts what it holds without touching the archive, so a long
+/// import can be previewed first. The decoded copy lives in a temp dir removed on return.
+pub fn inspect_backup<F>(plan: &ImportPlan, progress: F) -> Result<BackupInspection, CoreError>
+where
+    F: Fn(ImportProgress),
+{
+    progress(ImportProgress::new(ImportStage::Decoding));
+    let temp_dir = tempfile::tempdir()
+        .map_err(|e| CoreError::InvalidArgument(format!("temp dir failed: {}", e)))?;
+    let db_path = temp_dir.path().join("signal.sqlite");
+    let frames_dir = temp_dir.path().join("frames");
+    fs::create_dir_all(&frames_dir)
+        .map_err(|e| CoreError::InvalidArgument(format!("frames dir failed: {}", e)))?;
+    if let Err(err) = decode_plan(plan, &db_path, &frames_dir) {
+        if is_unsupported_version(&err) {
+            return Err(CoreError::InvalidArgument(
+                "unsupported backup version detected".to_string(),
+            ));
+        }
+        return Err(err);
+    }
+    progress(ImportProgress::new(ImportStage::Decoding).with_detail("counting"));
+    let signal_conn = Connection::open_with_flags(&db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    inspect_signal_db(&signal_conn)
+}
+
+fn decode_plan(plan: &ImportPlan, db_path: &Path, frames_dir: &Path) -> Result<(), CoreError> {
+    signalbackup::decode_backup(
+        Path::new(&plan.source_path),
+        &plan.normalized_passphrase,
+        db_path,
+        frames_dir,
+        true,
+    )
+    .map(|_| ())
+}
+
+fn is_unsupported_version(err: &CoreError) -> bool {
+    err.to_string().to_lowercase().contains("unsupported")
+}
+
+fn inspect_signal_db(signal: &Connection) -> Result<BackupInspection, CoreError> {
+    let count = |table: &str| -> Result<i64, CoreError> {
+        if !table_exists(signal, table)? {
+            return Ok(0);
+        }
+        Ok(signal.query_row(&format!("SELECT COUNT(1) FROM {};", table), [], |row| row.get(0))?)
+    };
+    let mms_table = if table_exists(signal, "message")? { "message" } else { "mms" };
+    let mut inspection = BackupInspection {
+        detected_version: detect_signal_version(signal)?,
+        threads: count("thread")?,
+        sms: count("sms")?,
+        mms: count(mms_table)?,
+        ..BackupInspection::default()
+    };
+
+    let part_table = ["part", "attachment"].into_iter().find(|t| table_exists(signal, t).unwrap_or(false));
+    if let Some(part_table) = part_table {
+        inspection.attachments = count(part_table)?;
+        if let Some(size_col) = pick_column(signal, part_table, &["data_size", "size"])? {
+            inspection.attachment_bytes = signal.query_row(
+                &format!("SELECT COALESCE(SUM({size_col}), 0) FROM {part_table} WHERE {size_col} > 0;"),
+                [],
+                |row| row.get(0),
+            )?;
+        }
+    }
+
+    for table in ["sms", mms_table] {
+        if !table_exists(signal, table)? {
+            continue;
+        }
+        let Some(date_col) = pick_column(signal, table, &["date_sent", "date"])? else {
+            continue;
+        };
+        let (first, last): (Option<i64>, Option<i64>) = signal.query_row(
+            &format!("SELECT MIN({date_col}), MAX({date_col}) FROM {table} WHERE {date_col} > 0;"),
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        inspection.first_message_at = match (inspection.first_message_at, first) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        inspection.last_message_at = inspection.last_message_at.max(last);
+    }
+    Ok(inspection)
+}
+
+/// Runs [`maintenance::verify_attachments`] over the archive and adds its counts and the
+/// corrupt blobs' hashes to the import's `stats_json`.
+fn verify_imported_attachments<F>(
+    conn: &Connection,
+    attachments_dir: &Path,
+    stats_json: &str,
+    progress: &F,
+) -> Result<String, CoreError>
+where
+    F: Fn(ImportProgress),
+{
+    let key = crypto::load_or_create_master_key()?;
+    let report = maintenance::verify_attachments(conn, attachments_dir, &key, |msg| {
+        progress(ImportProgress::new(ImportStage::Finalizing).with_detail(msg))
+    })?;
+    let mut stats: serde_json::Map<String, serde_json::Value> =
+        serde_json::from_str(stats_json).unwrap_or_default();
+    stats.insert("attachments_verified".to_string(), serde_json::json!(report.verified));
+    stats.insert("attachments_corrupt".to_string(), serde_json::json!(report.corrupt.len()));
+    stats.insert(
+        "attachments_corrupt_shas".to_string(),
+        serde_json::json!(report.corrupt.iter().map(|c| c.sha256.as_str()).collect::<Vec<_>>()),
+    );
+    Ok(serde_json::Value::Object(stats).to_string())
+}
+
+/// Records a running import in `imports` and returns its id. Refuses a source whose hash
+/// already has a successful import; an earlier failed or interrupted run of the same source
+/// is picked up again, keeping its id and any resume checkpoint.
+fn begin_import(conn: &Connection, source_filename: &str, source_hash: &str) -> Result<String, CoreError> {
+    let existing: Option<(String, String)> = conn
+        .query_row(
+            "SELECT id, status FROM imports WHERE source_hash = ?1 LIMIT 1;",
+            params![source_hash],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+    if let Some((import_id, status)) = existing {
+        if status == "success" {
+            return Err(CoreError::InvalidArgument(
+                "archive already loaded for this backup".to_string(),
+            ));
+        }
+        conn.execute(
+            "UPDATE imports SET status = 'running', imported_at = ?2, source_filename = ?3 WHERE id = ?1;",
+            params![import_id, Utc::now().timestamp_millis(), source_filename],
+        )?;
+        return Ok(import_id);
+    }
+    let import_id = Uuid::new_v4().to_string();
+    conn.execute(
+        "INSERT INTO imports (id, imported_at, source_filename, source_hash, detected_version, status, stats_json)
+         VALUES (?1, ?2, ?3, ?4, NULL, 'running', NULL);",
+        params![import_id, Utc::now().timestamp_millis(), source_filename, source_hash],
+    )?;
+    Ok(import_id)
+}
+
+/// Marks an import failed with `msg`, keeping any stats a resumable run recorded so far.
+fn mark_import_failed(conn: &Connection, import_id: &str, msg: &str) {
+    let _ = conn.execute(
+        "UPDATE imports SET status = 'failed', stats_json = json_set(COALESCE(stats_json, '{}'), '$.error', ?2)
+         WHERE id = ?1;",
+        params![import_id, msg],
+    );
+}
+
 fn check_disk_space(temp_dir: &Path, archive_path: &Path, source_path: &str) -> Result<(), CoreError> {
     let source_meta = fs::metadata(source_path)
         .map_err(|e| CoreError::InvalidArgument(format!("backup stat failed: {}", e)))?;
@@ -282,20 +482,120 @@ pub fn format_bytes(bytes: u64) -> String {
     }
 }
 
+/// Runs the mapping step against a plain Signal SQLite database and returns the import
+/// stats JSON that a real import records in `imports.stats_json`.
 pub fn import_from_signal_db_for_tests(
     signal_db_path: &Path,
     archive_path: &Path,
     export_dir: &Path,
-) -> Result<(), CoreError> {
+) -> Result<String, CoreError> {
+    import_from_signal_db_with_progress_for_tests(signal_db_path, archive_path, export_dir, |_| {})
+}
+
+/// [`import_from_signal_db_for_tests`] with a progress callback.
+pub fn import_from_signal_db_with_progress_for_tests<F>(
+    signal_db_path: &Path,
+    archive_path: &Path,
+    export_dir: &Path,
+    progress: F,
+) -> Result<String, CoreError>
+where
+    F: Fn(ImportProgress),
+{
     let mut archive = open_archive(archive_path)?;
     let signal_conn = Connection::open(signal_db_path)?;
     let attachments_dir = archive_path
         .parent()
         .unwrap_or_else(|| Path::new("."))
         .join("attachments");
-    let progress = |_msg: &str| {};
-    map_signal_db(&signal_conn, &mut archive.conn, &progress, export_dir, &attachments_dir)?;
-    Ok(())
+    map_signal_db(
+        &signal_conn,
+        &mut archive.conn,
+        &progress,
+        export_dir,
+        &attachments_dir,
+        None,
+        &mut resume::ImportCheckpoint::untracked(),
+    )
+}
+
+/// [`import_from_signal_db_with_progress_for_tests`] tracked in `imports` under
+/// `source_hash` and optimized afterwards the way a real import is, so an interrupted run
+/// resumes from its checkpoint when called again with the same hash.
+pub fn import_from_signal_db_resumable_for_tests<F>(
+    signal_db_path: &Path,
+    archive_path: &Path,
+    export_dir: &Path,
+    source_hash: &str,
+    progress: F,
+) -> Result<String, CoreError>
+where
+    F: Fn(ImportProgress),
+{
+    let mut archive = open_archive(archive_path)?;
+    let import_id = begin_import(&archive.conn, "signal.sqlite", source_hash)?;
+    let signal_conn = Connection::open(signal_db_path)?;
+    let attachments_dir = archive_path
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join("attachments");
+    let mapped = resume::ImportCheckpoint::load(&archive.conn, &import_id).and_then(|mut checkpoint| {
+        map_signal_db(
+            &signal_conn,
+            &mut archive.conn,
+            &progress,
+            export_dir,
+            &attachments_dir,
+            None,
+            &mut checkpoint,
+        )
+    });
+    let stats = match mapped {
+        Ok(stats) => stats,
+        Err(err) => {
+            mark_import_failed(&archive.conn, &import_id, &err.to_string());
+            return Err(err);
+        }
+    };
+    archive.conn.execute(
+        "UPDATE imports SET status = 'success', stats_json = ?2 WHERE id = ?1;",
+        params![import_id, stats],
+    )?;
+    archive.conn.execute("DELETE FROM import_checkpoints WHERE import_id = ?1;", params![import_id])?;
+    archive.conn.execute("DELETE FROM import_message_ids WHERE import_id = ?1;", params![import_id])?;
+    optimize_imported_archive(&archive.conn, &import_id, &progress)?;
+    Ok(stats)
+}
+
+/// [`inspect_backup`]'s counts for a plain Signal SQLite database.
+pub fn inspect_signal_db_for_tests(signal_db_path: &Path) -> Result<BackupInspection, CoreError> {
+    let signal_conn = Connection::open_with_flags(signal_db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    inspect_signal_db(&signal_conn)
+}
+
+/// Describes the decoded database for `imports.detected_version`: its `PRAGMA user_version`,
+/// preceded by the app version from the `key_value` table's `signal_version` entry when the
+/// backup has one, e.g. `signal-android 7.12.3, db 215`.
+fn detect_signal_version(signal: &Connection) -> Result<Option<String>, CoreError> {
+    let user_version: i64 = signal.query_row("PRAGMA user_version;", [], |row| row.get(0))?;
+    let mut app_version = None;
+    if table_exists(signal, "key_value")? {
+        let value: Option<Value> = signal
+            .query_row("SELECT value FROM key_value WHERE key = 'signal_version';", [], |row| row.get(0))
+            .optional()?;
+        app_version = match value {
+            Some(Value::Text(text)) if !text.trim().is_empty() => Some(text.trim().to_string()),
+            Some(Value::Integer(n)) => Some(n.to_string()),
+            Some(Value::Blob(bytes)) => Some(String::from_utf8_lossy(&bytes).trim().to_string()).filter(|v| !v.is_empty()),
+            _ => None,
+        };
+    }
+    Ok(match (app_version, user_version) {
+        (Some(app), 0) => Some(format!("signal-android {}", app)),
+        (Some(app), db) => Some(format!("signal-android {}, db {}", app, db)),
+        (None, 0) => None,
+        (None, db) => Some(format!("signal-android db {}", db)),
+    })
 }
 
 pub(super) fn table_exists(conn: &Connection, name: &str) -> Result<bool, CoreError> {
@@ -330,18 +630,28 @@ pub(super) fn pick_column(conn: &Connection, table: &str, preferred: &[&str]) ->
     Ok(None)
 }
 
+/// Maps a decoded Signal database into the archive. Every stage commits on its own, and
+/// the message stages every [`MESSAGE_CHUNK_ROWS`] rows, recording `checkpoint` with it
+/// so that a later run can skip what is already in.
 fn map_signal_db<F>(
     signal: &Connection,
     archive: &mut Connection,
     progress: &F,
     export_dir: &Path,
     attachments_dir: &Path,
+    default_country_code: Option<&str>,
+    checkpoint: &mut resume::ImportCheckpoint,
 ) -> Result<String, CoreError>
 where
-    F: Fn(&str),
+    F: Fn(ImportProgress),
 {
-    progress("Importing recipients...");
-    let tx = archive.transaction()?;
+    let detected_version = detect_signal_version(signal)?;
+    let country_code = match default_country_code {
+        Some(code) => Some(code.trim_start_matches('+').to_string()),
+        None => phone::owner_calling_code(signal)?,
+    };
+    progress(ImportProgress::new(ImportStage::Recipients));
+    let mut tx = archive.transaction()?;
 
     let mms_table = if table_exists(signal, "message")? {
         "message".to_string()
@@ -355,6 +665,7 @@ where
         pick_column(signal, "thread", &["recipient_id", "thread_recipient_id", "recipient_ids"])?;
     let thread_message_count_col =
         pick_column(signal, "thread", &["meaningful_messages", "message_count"])?;
+    let thread_expires_col = pick_column(signal, "thread", &["expires_in", "expire_timer"])?;
     let sms_recipient_col = pick_column(signal, "sms", &["recipient_id", "address"])?;
     let sms_date_col = pick_column(signal, "sms", &["date_received", "date"])?;
     let mms_type_col = pick_column(signal, &mms_table, &["type", "msg_box"])?;
@@ -366,14 +677,29 @@ where
     let rec_e164 = pick_column(signal, "recipient", &["e164", "phone"])?;
     let rec_system = pick_column(signal, "recipient", &["system_joined_name", "system_display_name"])?;
     let rec_profile = pick_column(signal, "recipient", &["profile_given_name", "signal_profile_name"])?;
+    let rec_expiration = pick_column(signal, "recipient", &["message_expiration_time"])?;
+    let rec_group = pick_column(signal, "recipient", &["group_id"])?;
+    let rec_nickname_given = pick_column(signal, "recipient", &["nickname_given_name"])?;
+    let rec_nickname_family = pick_column(signal, "recipient", &["nickname_family_name"])?;
+    let rec_username = pick_column(signal, "recipient", &["username"])?;
+    let recipient_col = |col: &Option<String>| {
+        col.as_deref()
+            .map(|col| format!("recipient.{col}"))
+            .unwrap_or_else(|| "NULL".to_string())
+    };
 
     // recipients
     let mut rec_stmt = signal.prepare(&format!(
-        "SELECT _id, {aci}, {e164}, {system}, {profile} FROM recipient;",
+        "SELECT _id, {aci}, {e164}, {system}, {profile}, {group}, {nickname_given}, {nickname_family}, {username} \
+         FROM recipient;",
         aci = rec_aci.as_deref().unwrap_or("NULL"),
         e164 = rec_e164.as_deref().unwrap_or("NULL"),
         system = rec_system.as_deref().unwrap_or("NULL"),
         profile = rec_profile.as_deref().unwrap_or("NULL"),
+        group = rec_group.as_deref().unwrap_or("NULL"),
+        nickname_given = recipient_col(&rec_nickname_given),
+        nickname_family = recipient_col(&rec_nickname_family),
+        username = recipient_col(&rec_username),
     ))?;
     let rec_rows = rec_stmt.query_map([], |row| {
         let id: i64 = row.get(0)?;
@@ -381,33 +707,69 @@ where
         let e164: Option<String> = row.get(2)?;
         let system_name: Option<String> = row.get(3)?;
         let profile_name: Option<String> = row.get(4)?;
-        Ok((id, aci, e164, system_name, profile_name))
+        let group_id = match row.get::<_, Value>(5)? {
+            Value::Integer(v) => Some(v.to_string()),
+            Value::Text(v) => Some(v),
+            _ => None,
+        };
+        let nickname = full_name(row.get(6)?, row.get(7)?);
+        let username: Option<String> = row.get(8)?;
+        Ok((id, aci, e164, system_name, profile_name, group_id, nickname, username))
     })?;
+    let mut ids = identity::IdentityMap::default();
+    ids.load_messages(&tx, checkpoint.import_id())?;
     for rec in rec_rows {
-        let (id, _aci, e164, system_name, profile_name) = rec?;
+        let (id, aci, raw_phone, system_name, profile_name, group_id, nickname, username) = rec?;
+        let e164 = raw_phone.as_deref().and_then(|raw| phone::normalize_phone(raw, country_code.as_deref()));
+        let identities = identity::recipient_identities(aci.as_deref(), e164.as_deref(), group_id.as_deref());
+        let recipient_id = ids.resolve_recipient(&tx, id, &identities)?;
+        // Kept only where normalization changed it.
+        let raw_phone = raw_phone.filter(|raw| Some(raw.as_str()) != e164.as_deref());
+        let username = username.filter(|username| !username.trim().is_empty());
+        // A later backup may know a name or number an earlier one didn't.
         tx.execute(
-            "INSERT OR IGNORE INTO recipients (id, phone_e164, profile_name, contact_name) VALUES (?1, ?2, ?3, ?4);",
-            params![id.to_string(), e164, profile_name, system_name],
+            "INSERT INTO recipients (id, phone_e164, profile_name, contact_name, raw_phone, nickname, username)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+             ON CONFLICT(id) DO UPDATE SET
+               phone_e164 = COALESCE(excluded.phone_e164, phone_e164),
+               profile_name = COALESCE(excluded.profile_name, profile_name),
+               contact_name = COALESCE(excluded.contact_name, contact_name),
+               raw_phone = COALESCE(excluded.raw_phone, raw_phone),
+               nickname = COALESCE(excluded.nickname, nickname),
+               username = COALESCE(excluded.username, username);",
+            params![recipient_id, e164, profile_name, system_name, raw_phone, nickname, username],
         )?;
     }
 
     // threads
-    progress("Importing threads...");
+    progress(ImportProgress::new(ImportStage::Threads));
     if let Some(thread_recipient_col) = thread_recipient_col {
         let msg_count_expr = thread_message_count_col
             .as_deref()
             .map(|col| format!("thread.{col}"))
             .unwrap_or_else(|| "NULL".to_string());
+        // Older backups keep the timer on the thread in milliseconds; newer ones on the
+        // recipient in seconds.
+        let expire_timer_expr = match (&thread_expires_col, &rec_expiration) {
+            (Some(col), _) => format!("thread.{col}"),
+            (None, Some(col)) => format!("recipient.{col} * 1000"),
+            (None, None) => "NULL".to_string(),
+        };
         let mut thread_stmt = signal.prepare(&format!(
-            "SELECT thread._id, thread.{rec_col}, thread.date, {msg_count}, groups.title, recipient.{system}, recipient.{profile}, recipient.{e164}
+            "SELECT thread._id, thread.{rec_col}, thread.date, {msg_count}, groups.title, recipient.{system}, recipient.{profile}, recipient.{e164}, {expire_timer},
+                    {nickname_given}, {nickname_family}, {username}
              FROM thread
              LEFT JOIN recipient ON recipient._id = thread.{rec_col}
              LEFT JOIN groups ON recipient.group_id = groups.group_id;",
             rec_col = thread_recipient_col,
             msg_count = msg_count_expr,
+            expire_timer = expire_timer_expr,
             system = rec_system.as_deref().unwrap_or("NULL"),
             profile = rec_profile.as_deref().unwrap_or("NULL"),
             e164 = rec_e164.as_deref().unwrap_or("NULL"),
+            nickname_given = recipient_col(&rec_nickname_given),
+            nickname_family = recipient_col(&rec_nickname_family),
+            username = recipient_col(&rec_username),
         ))?;
 
         let thread_rows = thread_stmt.query_map([], |row| {
@@ -419,20 +781,35 @@ where
             let system_name: Option<String> = row.get(5)?;
             let profile_name: Option<String> = row.get(6)?;
             let e164: Option<String> = row.get(7)?;
-            let name = group_title.or(system_name).or(profile_name).or(e164);
-            Ok((id, rec_id, date, message_count.unwrap_or(0), name))
+            let expire_timer_ms: Option<i64> = row.get(8)?;
+            let nickname = full_name(row.get(9)?, row.get(10)?);
+            let username: Option<String> = row.get(11)?;
+            let e164 = e164.and_then(|raw| phone::normalize_phone(&raw, country_code.as_deref()));
+            let name = group_title
+                .or(nickname)
+                .or(system_name)
+                .or(profile_name)
+                .or(username.filter(|username| !username.trim().is_empty()))
+                .or(e164);
+            Ok((id, rec_id, date, message_count.unwrap_or(0), name, expire_timer_ms.filter(|ms| *ms > 0)))
         })?;
 
         for row in thread_rows {
-            let (id, rec_id, date, message_count, name) = row?;
+            let (id, rec_id, date, message_count, name, expire_timer_ms) = row?;
+            let recipient_id = rec_id.map(|r| ids.recipient(r));
+            let thread_id = ids.resolve_thread(&tx, id, recipient_id.as_deref())?;
             tx.execute(
                 "INSERT OR IGNORE INTO threads (id, name, last_message_at) VALUES (?1, ?2, ?3);",
-                params![id.to_string(), name, date],
+                params![thread_id, name, date],
+            )?;
+            tx.execute(
+                "UPDATE threads SET expire_timer_ms = ?2 WHERE id = ?1;",
+                params![thread_id, expire_timer_ms],
             )?;
-            if let Some(rec_id) = rec_id {
+            if let Some(recipient_id) = recipient_id {
                 tx.execute(
                     "INSERT OR IGNORE INTO thread_members (thread_id, recipient_id) VALUES (?1, ?2);",
-                    params![id.to_string(), rec_id.to_string()],
+                    params![thread_id, recipient_id],
                 )?;
             }
             if message_count > 0 {
@@ -440,9 +817,12 @@ where
             }
         }
     }
+    checkpoint.save(&tx, resume::ResumeStage::Sms, 0)?;
+    tx.commit()?;
+    tx = archive.transaction()?;
 
     let mut sms_count: i64 = 0;
-    let mut sms_inserted: i64 = 0;
+    let mut sms_inserted: i64 = checkpoint.stat("sms_inserted");
     let mut sms_total: Option<i64> = None;
     let sms_quote_id_col = pick_column(signal, "sms", &["quote_id", "quote_id"])?
         .unwrap_or_else(|| "NULL".to_string());
@@ -452,25 +832,28 @@ where
     let sms_quote_body_col =
         pick_column(signal, "sms", &["quote_body", "quote_text", "quote"])?
             .unwrap_or_else(|| "NULL".to_string());
+    let sms_expires_col = pick_column(signal, "sms", &["expires_in", "expire_timer"])?.unwrap_or_else(|| "NULL".to_string());
     // sms messages
-    if table_exists(signal, "sms")? {
+    if checkpoint.pending(resume::ResumeStage::Sms) && table_exists(signal, "sms")? {
         sms_total = Some(signal
             .query_row("SELECT COUNT(1) FROM sms;", [], |row| row.get(0))
             .unwrap_or(0));
-        progress("Importing SMS messages...");
+        progress(ImportProgress::counted(ImportStage::Messages, 0, sms_total.unwrap_or(0) as u64).with_detail("SMS"));
         let sms_recipient_col = sms_recipient_col.clone().unwrap_or_else(|| "recipient_id".to_string());
         let sms_date_col = sms_date_col.clone().unwrap_or_else(|| "date".to_string());
         let mut sms_stmt = signal.prepare(&format!(
             "SELECT _id, thread_id, body, {date_col} AS date_recv, date_sent, type, {rec_col} AS recipient_id, \
-                    {quote_id} AS quote_id, {quote_author} AS quote_author, {quote_body} AS quote_body \
-             FROM sms;",
+                    {quote_author} AS quote_author, {quote_body} AS quote_body, {expires_in} AS expires_in \
+             FROM sms \
+             WHERE _id > ?1 \
+             ORDER BY _id;",
             date_col = sms_date_col,
             rec_col = sms_recipient_col,
-            quote_id = sms_quote_id_col,
             quote_author = sms_quote_author_col,
             quote_body = sms_quote_body_col,
+            expires_in = sms_expires_col,
         ))?;
-        let sms_rows = sms_stmt.query_map([], |row| {
+        let sms_rows = sms_stmt.query_map([checkpoint.offset(resume::ResumeStage::Sms)], |row| {
             let id: i64 = row.get(0)?;
             let thread_id: i64 = row.get(1)?;
             let body: Option<String> = row.get(2)?;
@@ -478,66 +861,119 @@ where
             let date_sent: Option<i64> = row.get(4)?;
             let msg_type: Option<i64> = row.get(5)?;
             let recipient_id: Option<i64> = row.get(6)?;
-            let quote_id: Option<i64> = row.get(7)?;
-            let quote_author: Option<i64> = row.get(8)?;
-            let quote_body: Option<String> = row.get(9)?;
-            Ok((id, thread_id, body, date_recv, date_sent, msg_type, recipient_id, quote_id, quote_author, quote_body))
+            let quote_author: Option<i64> = row.get(7)?;
+            let quote_body: Option<String> = row.get(8)?;
+            let expires_in: Option<i64> = row.get(9)?;
+            Ok((
+                id,
+                thread_id,
+                body,
+                date_recv,
+                date_sent,
+                msg_type,
+                recipient_id,
+                quote_author,
+                quote_body,
+                expires_in,
+            ))
         })?;
         let mut sms_batch: Vec<MessageRowData> = Vec::with_capacity(100);
+        let receipts = receipts::receipts(signal, "sms")?;
         for row in sms_rows {
-            let (id, thread_id, body, date_recv, date_sent, msg_type, recipient_id, quote_id, quote_author, quote_body) = row?;
+            let (
+                id,
+                thread_id,
+                body,
+                date_recv,
+                date_sent,
+                msg_type,
+                recipient_id,
+                quote_author,
+                quote_body,
+                expires_in,
+            ) = row?;
+            let event = msg_type.and_then(|t| events::system_event(t, expires_in));
+            let body = if event.is_some() { None } else { body };
             let is_outgoing = msg_type.map(is_outgoing_type).unwrap_or(false);
-            let sender_id = if is_outgoing { None } else { recipient_id.map(|v| v.to_string()) };
-            let msg_id = format!("sms:{}", id);
-            let quote_message_id = quote_id.map(|v| format!("sms:{}", v));
-            let metadata_json = if quote_body.is_some() || quote_author.is_some() {
-                Some(
-                    serde_json::json!({
-                        "quote_body": quote_body,
-                        "quote_author_id": quote_author,
-                    })
-                    .to_string(),
-                )
-            } else {
+            let sender_id = if is_outgoing { None } else { recipient_id.map(|v| ids.recipient(v)) };
+            let thread_id = ids.thread(thread_id);
+            let quote_author = quote_author.map(|v| ids.recipient(v));
+            let call = msg_type
+                .filter(|_| event.is_none() && is_blank(body.as_deref()))
+                .and_then(calls::call_from_type);
+            let mut metadata = serde_json::Map::new();
+            if quote_body.is_some() || quote_author.is_some() {
+                metadata.insert("quote_body".to_string(), serde_json::json!(quote_body));
+                metadata.insert("quote_author_id".to_string(), serde_json::json!(quote_author));
+            }
+            if let Some(call) = &call {
+                call.write_metadata(&mut metadata);
+            }
+            if let Some(event) = &event {
+                metadata.insert("event_text".to_string(), serde_json::json!(event.text));
+            }
+            if let Some(expires_in) = expires_in.filter(|ms| *ms > 0) {
+                metadata.insert("expires_in_ms".to_string(), serde_json::json!(expires_in));
+            }
+            if let Some(receipt) = receipts.get(&id) {
+                receipt.write_metadata(&mut metadata);
+            }
+            let metadata_json = if metadata.is_empty() {
                 None
-            };
-            let dedupe_key = if id > 0 {
-                format!("sms:{}", id)
             } else {
-                fallback_dedupe_key(
-                    "sms",
-                    &thread_id.to_string(),
-                    sender_id.as_deref(),
-                    date_sent.or(date_recv),
-                    "text",
-                    body.as_deref(),
-                    is_outgoing,
-                )
+                Some(serde_json::Value::Object(metadata).to_string())
             };
-            sms_batch.push(MessageRowData {
-                id: msg_id,
-                thread_id: thread_id.to_string(),
-                sender_id,
-                sent_at: date_sent.or(date_recv),
-                received_at: date_recv,
-                message_type: "text".to_string(),
-                body,
-                is_outgoing: if is_outgoing { 1 } else { 0 },
-                is_view_once: 0,
-                quote_message_id,
-                metadata_json,
-                dedupe_key,
-            });
+            let message_type = match (&event, &call) {
+                (Some(event), _) => event.message_type,
+                (None, Some(_)) => "call",
+                (None, None) => "text",
+            };
+            let dedupe_key = message_dedupe_key(
+                "sms",
+                &thread_id,
+                sender_id.as_deref(),
+                date_sent.or(date_recv),
+                message_type,
+                body.as_deref(),
+                is_outgoing,
+            );
+            let msg_id = ids.resolve_message(&tx, checkpoint.import_id(), "sms", id, &dedupe_key, &sms_batch)?;
+            if let Some(msg_id) = msg_id {
+                sms_batch.push(MessageRowData {
+                    id: msg_id,
+                    thread_id,
+                    sender_id,
+                    sent_at: date_sent.or(date_recv),
+                    received_at: date_recv,
+                    message_type: message_type.to_string(),
+                    body,
+                    is_outgoing: if is_outgoing { 1 } else { 0 },
+                    is_view_once: 0,
+                    quote_message_id: None,
+                    metadata_json,
+                    dedupe_key,
+                });
+            }
             if sms_batch.len() >= 100 {
                 sms_inserted += insert_message_batch(&tx, &sms_batch)?;
                 sms_batch.clear();
             }
             sms_count += 1;
+            if sms_count % MESSAGE_CHUNK_ROWS == 0 {
+                sms_inserted += insert_message_batch(&tx, &sms_batch)?;
+                sms_batch.clear();
+                checkpoint.set_stat("sms_inserted", sms_inserted);
+                checkpoint.save(&tx, resume::ResumeStage::Sms, id)?;
+                tx.commit()?;
+                tx = archive.transaction()?;
+            }
             if sms_count % 5000 == 0 {
                 let total = sms_total.unwrap_or(0);
                 if total > 0 {
-                    let msg = format!("Importing SMS messages... {}/{}", sms_count, total);
-                    progress(&msg);
+                    progress(
+                        ImportProgress::counted(ImportStage::Messages, sms_count as u64, total as u64)
+                            .with_detail("SMS"),
+                    );
                 }
             }
         }
@@ -545,14 +981,20 @@ where
             sms_inserted += insert_message_batch(&tx, &sms_batch)?;
         }
     }
+    if checkpoint.pending(resume::ResumeStage::Sms) {
+        checkpoint.set_stat("sms_total", sms_total.unwrap_or(sms_count));
+        checkpoint.set_stat("sms_inserted", sms_inserted);
+        checkpoint.save(&tx, resume::ResumeStage::Mms, 0)?;
+        tx.commit()?;
+        tx = archive.transaction()?;
+    }
 
     // mms/messages table
     let mms_total: i64 = signal
         .query_row(&format!("SELECT COUNT(1) FROM {};", mms_table), [], |row| row.get(0))
         .unwrap_or(0);
     let mut mms_count: i64 = 0;
-    let mut mms_inserted: i64 = 0;
-    progress("Importing MMS messages...");
+    let mut mms_inserted: i64 = checkpoint.stat("mms_inserted");
     let mms_type_col = mms_type_col.clone().unwrap_or_else(|| "type".to_string());
     let mms_recipient_col = mms_recipient_col.clone().unwrap_or_else(|| "recipient_id".to_string());
     let mms_date_sent_col = mms_date_sent_col.clone().unwrap_or_else(|| "date_sent".to_string());
@@ -568,123 +1010,334 @@ where
     let mms_quote_body_col =
         pick_column(signal, &mms_table, &["quote_body", "quote_text", "quote"])?
             .unwrap_or_else(|| "NULL".to_string());
+    let mms_view_once_col =
+        pick_column(signal, &mms_table, &["view_once", "is_view_once"])?.unwrap_or_else(|| "0".to_string());
+    let mms_revealed_col =
+        pick_column(signal, &mms_table, &["revealed", "view_once_revealed"])?.unwrap_or_else(|| "0".to_string());
+    let mms_expires_col = pick_column(signal, &mms_table, &["expires_in", "expire_timer"])?.unwrap_or_else(|| "NULL".to_string());
 
-    let mut mms_stmt = signal.prepare(&format!(
-        "SELECT _id, thread_id, body, {date_recv} AS date_recv, {date_sent} AS date_sent, \
-                {type_col} AS msg_type, {rec_col} AS recipient_id, \
-                {quote_id} AS quote_id, {quote_author} AS quote_author, {quote_body} AS quote_body \
-         FROM {mms_table};",
-        date_recv = mms_date_recv_col,
-        date_sent = mms_date_sent_col,
-        type_col = mms_type_col,
-        rec_col = mms_recipient_col,
-        quote_id = mms_quote_id_col,
-        quote_author = mms_quote_author_col,
-        quote_body = mms_quote_body_col,
-        mms_table = mms_table,
-    ))?;
-    let mms_rows = mms_stmt.query_map([], |row| {
-        let id: i64 = row.get(0)?;
-        let thread_id: i64 = row.get(1)?;
-        let body: Option<String> = row.get(2)?;
-        let date_recv: Option<i64> = row.get(3)?;
-        let date_sent: Option<i64> = row.get(4)?;
-        let msg_type: Option<i64> = row.get(5)?;
-        let recipient_id: Option<i64> = row.get(6)?;
-        let quote_id: Option<i64> = row.get(7)?;
-        let quote_author: Option<i64> = row.get(8)?;
-        let quote_body: Option<String> = row.get(9)?;
-        Ok((id, thread_id, body, date_recv, date_sent, msg_type, recipient_id, quote_id, quote_author, quote_body))
-    })?;
-    let mut mms_batch: Vec<MessageRowData> = Vec::with_capacity(100);
-    for row in mms_rows {
-        let (id, thread_id, body, date_recv, date_sent, msg_type, recipient_id, quote_id, quote_author, quote_body) = row?;
-        let is_outgoing = msg_type.map(is_outgoing_type).unwrap_or(false);
-        let sender_id = if is_outgoing { None } else { recipient_id.map(|v| v.to_string()) };
-        let msg_id = format!("mms:{}", id);
-        let quote_message_id = quote_id.map(|v| format!("mms:{}", v));
-        let metadata_json = if quote_body.is_some() || quote_author.is_some() {
-            Some(
-                serde_json::json!({
-                    "quote_body": quote_body,
-                    "quote_author_id": quote_author,
-                })
-                .to_string(),
-            )
-        } else {
-            None
-        };
-        let dedupe_key = if id > 0 {
-            format!("mms:{}", id)
-        } else {
-            fallback_dedupe_key(
+    if checkpoint.pending(resume::ResumeStage::Mms) {
+        progress(ImportProgress::counted(ImportStage::Messages, 0, mms_total as u64).with_detail("MMS"));
+        let mut mms_stmt = signal.prepare(&format!(
+            "SELECT _id, thread_id, body, {date_recv} AS date_recv, {date_sent} AS date_sent, \
+                    {type_col} AS msg_type, {rec_col} AS recipient_id, \
+                    {quote_author} AS quote_author, {quote_body} AS quote_body, \
+                    {view_once} AS view_once, {expires_in} AS expires_in \
+             FROM {mms_table} \
+             WHERE _id > ?1 \
+             ORDER BY _id;",
+            date_recv = mms_date_recv_col,
+            date_sent = mms_date_sent_col,
+            type_col = mms_type_col,
+            rec_col = mms_recipient_col,
+            quote_author = mms_quote_author_col,
+            quote_body = mms_quote_body_col,
+            view_once = mms_view_once_col,
+            expires_in = mms_expires_col,
+            mms_table = mms_table,
+        ))?;
+        let mms_rows = mms_stmt.query_map([checkpoint.offset(resume::ResumeStage::Mms)], |row| {
+            let id: i64 = row.get(0)?;
+            let thread_id: i64 = row.get(1)?;
+            let body: Option<String> = row.get(2)?;
+            let date_recv: Option<i64> = row.get(3)?;
+            let date_sent: Option<i64> = row.get(4)?;
+            let msg_type: Option<i64> = row.get(5)?;
+            let recipient_id: Option<i64> = row.get(6)?;
+            let quote_author: Option<i64> = row.get(7)?;
+            let quote_body: Option<String> = row.get(8)?;
+            let view_once: Option<i64> = row.get(9)?;
+            let expires_in: Option<i64> = row.get(10)?;
+            Ok((
+                id,
+                thread_id,
+                body,
+                date_recv,
+                date_sent,
+                msg_type,
+                recipient_id,
+                quote_author,
+                quote_body,
+                view_once.unwrap_or(0) != 0,
+                expires_in,
+            ))
+        })?;
+        let stickers = attachments::sticker_refs(signal)?;
+        let call_log = calls::call_log(signal)?;
+        let receipts = receipts::receipts(signal, &mms_table)?;
+        let mut mms_batch: Vec<MessageRowData> = Vec::with_capacity(100);
+        for row in mms_rows {
+            let (
+                id,
+                thread_id,
+                body,
+                date_recv,
+                date_sent,
+                msg_type,
+                recipient_id,
+                quote_author,
+                quote_body,
+                is_view_once,
+                expires_in,
+            ) = row?;
+            let event = msg_type.and_then(|t| events::system_event(t, expires_in));
+            let body = if event.is_some() { None } else { body };
+            let call = call_log.get(&id).cloned().or_else(|| {
+                msg_type
+                    .filter(|_| event.is_none() && is_blank(body.as_deref()))
+                    .and_then(calls::call_from_type)
+            });
+            let is_outgoing = match &call {
+                Some(call) if call.direction != "group" => call.direction == "outgoing",
+                _ => msg_type.map(is_outgoing_type).unwrap_or(false),
+            };
+            let sender_id = if is_outgoing { None } else { recipient_id.map(|v| ids.recipient(v)) };
+            let thread_id = ids.thread(thread_id);
+            let quote_author = quote_author.map(|v| ids.recipient(v));
+            let sticker = stickers.get(&id);
+            let mut metadata = serde_json::Map::new();
+            if quote_body.is_some() || quote_author.is_some() {
+                metadata.insert("quote_body".to_string(), serde_json::json!(quote_body));
+                metadata.insert("quote_author_id".to_string(), serde_json::json!(quote_author));
+            }
+            if let Some(sticker) = sticker {
+                metadata.insert("sticker_pack_id".to_string(), serde_json::json!(sticker.pack_id));
+                metadata.insert("sticker_pack_title".to_string(), serde_json::json!(sticker.pack_title));
+                metadata.insert("sticker_emoji".to_string(), serde_json::json!(sticker.emoji));
+            }
+            if let Some(call) = &call {
+                call.write_metadata(&mut metadata);
+            }
+            if let Some(event) = &event {
+                metadata.insert("event_text".to_string(), serde_json::json!(event.text));
+            }
+            if let Some(expires_in) = expires_in.filter(|ms| *ms > 0) {
+                metadata.insert("expires_in_ms".to_string(), serde_json::json!(expires_in));
+            }
+            if let Some(receipt) = receipts.get(&id) {
+                receipt.write_metadata(&mut metadata);
+            }
+            let metadata_json = if metadata.is_empty() {
+                None
+            } else {
+                Some(serde_json::Value::Object(metadata).to_string())
+            };
+            let message_type = if let Some(event) = &event {
+                event.message_type
+            } else if call.is_some() {
+                "call"
+            } else if sticker.is_some() {
+                "sticker"
+            } else {
+                "text"
+            };
+            let dedupe_key = message_dedupe_key(
                 "mms",
-                &thread_id.to_string(),
+                &thread_id,
                 sender_id.as_deref(),
                 date_sent.or(date_recv),
-                "text",
+                message_type,
                 body.as_deref(),
                 is_outgoing,
-            )
-        };
-        mms_batch.push(MessageRowData {
-            id: msg_id,
-            thread_id: thread_id.to_string(),
-            sender_id,
-            sent_at: date_sent.or(date_recv),
-            received_at: date_recv,
-            message_type: "text".to_string(),
-            body,
-            is_outgoing: if is_outgoing { 1 } else { 0 },
-            is_view_once: 0,
-            quote_message_id,
-            metadata_json,
-            dedupe_key,
-        });
-        if mms_batch.len() >= 100 {
-            mms_inserted += insert_message_batch(&tx, &mms_batch)?;
-            mms_batch.clear();
+            );
+            let msg_id = ids.resolve_message(&tx, checkpoint.import_id(), "mms", id, &dedupe_key, &mms_batch)?;
+            if let Some(msg_id) = msg_id {
+                mms_batch.push(MessageRowData {
+                    id: msg_id,
+                    thread_id,
+                    sender_id,
+                    sent_at: date_sent.or(date_recv),
+                    received_at: date_recv,
+                    message_type: message_type.to_string(),
+                    body,
+                    is_outgoing: if is_outgoing { 1 } else { 0 },
+                    is_view_once: if is_view_once { 1 } else { 0 },
+                    quote_message_id: None,
+                    metadata_json,
+                    dedupe_key,
+                });
+            }
+            if mms_batch.len() >= 100 {
+                mms_inserted += insert_message_batch(&tx, &mms_batch)?;
+                mms_batch.clear();
+            }
+            mms_count += 1;
+            if mms_count % MESSAGE_CHUNK_ROWS == 0 {
+                mms_inserted += insert_message_batch(&tx, &mms_batch)?;
+                mms_batch.clear();
+                checkpoint.set_stat("mms_inserted", mms_inserted);
+                checkpoint.save(&tx, resume::ResumeStage::Mms, id)?;
+                tx.commit()?;
+                tx = archive.transaction()?;
+            }
+            if mms_count % 5000 == 0 && mms_total > 0 {
+                progress(
+                    ImportProgress::counted(ImportStage::Messages, mms_count as u64, mms_total as u64).with_detail("MMS"),
+                );
+            }
         }
-        mms_count += 1;
-        if mms_count % 5000 == 0 && mms_total > 0 {
-            let msg = format!("Importing MMS messages... {}/{}", mms_count, mms_total);
-            progress(&msg);
+        if !mms_batch.is_empty() {
+            mms_inserted += insert_message_batch(&tx, &mms_batch)?;
         }
+        // Quotes are read back from the source so replies committed by an earlier run
+        // are linked too.
+        let mut quotes = quote_refs(signal, "sms", &sms_quote_id_col, &ids)?;
+        quotes.extend(quote_refs(signal, &mms_table, &mms_quote_id_col, &ids)?);
+        let quotes_resolved = resolve_quotes(&tx, &quotes)?;
+        checkpoint.set_stat("mms_total", mms_total);
+        checkpoint.set_stat("mms_inserted", mms_inserted);
+        checkpoint.set_stat("messages_inserted_total", checkpoint.stat("sms_inserted") + mms_inserted);
+        checkpoint.set_stat("quotes_total", quotes.len() as i64);
+        checkpoint.set_stat("quotes_resolved", quotes_resolved);
+        checkpoint.save(&tx, resume::ResumeStage::Attachments, 0)?;
+        tx.commit()?;
+        tx = archive.transaction()?;
     }
-    if !mms_batch.is_empty() {
-        mms_inserted += insert_message_batch(&tx, &mms_batch)?;
+    let mut issues = issues::ImportIssues::for_import(&tx, checkpoint.import_id())?;
+    if checkpoint.pending(resume::ResumeStage::Attachments) {
+        let view_once_messages = view_once_messages(signal, &mms_table, &mms_view_once_col, &mms_revealed_col)?;
+        let link_previews = previews::link_previews(signal, &mms_table)?;
+        let mut message_lookup =
+            tx.prepare("SELECT id FROM messages WHERE id IN (?1, ?2) ORDER BY id = ?1 DESC LIMIT 1;")?;
+        // Parts belong to MMS rows, but some old schemas attach them to `sms` rows too; an
+        // id present in both tables resolves to the MMS message.
+        let part_message = |mid: i64| -> Result<Option<(String, Option<bool>)>, CoreError> {
+            let mms_id = ids.message("mms", mid);
+            let message_id: Option<String> = message_lookup
+                .query_row(params![mms_id, ids.message("sms", mid)], |row| row.get(0))
+                .optional()?;
+            Ok(message_id.map(|message_id| {
+                let view_once = if message_id == mms_id { view_once_messages.get(&mid).copied() } else { None };
+                (message_id, view_once)
+            }))
+        };
+        let attachment_stats =
+            attachments::map_attachments(signal, &tx, export_dir, attachments_dir, part_message, &mut issues, progress)?;
+        drop(message_lookup);
+        let link_preview_messages = previews::apply_link_previews(signal, &tx, export_dir, &link_previews, &ids)?;
+        checkpoint.set_stat("attachments_total", attachment_stats.total);
+        checkpoint.set_stat("attachments_found", attachment_stats.found);
+        checkpoint.set_stat("attachments_missing", attachment_stats.missing);
+        checkpoint.set_stat("attachments_inserted", attachment_stats.inserted);
+        checkpoint.set_stat("attachments_view_once_purged", attachment_stats.view_once_purged);
+        checkpoint.set_stat("long_text_bodies", attachment_stats.long_text_bodies);
+        checkpoint.set_stat("attachments_unresolved", attachment_stats.unresolved);
+        checkpoint.set_stat("attachments_bytes", attachment_stats.bytes);
+        checkpoint.set_stat("link_preview_messages", link_preview_messages);
+        checkpoint.save(&tx, resume::ResumeStage::Reactions, 0)?;
+        tx.commit()?;
+        tx = archive.transaction()?;
     }
 
-    let attachment_stats = attachments::map_attachments(signal, &tx, export_dir, attachments_dir, progress)?;
-    map_reactions(signal, &tx, progress)?;
+    if checkpoint.pending(resume::ResumeStage::Reactions) {
+        map_reactions(signal, &tx, &ids, &mut issues, progress)?;
+        map_mentions(signal, &tx, &ids, &mut issues, progress)?;
+        checkpoint.save(&tx, resume::ResumeStage::Fts, 0)?;
+        tx.commit()?;
+        tx = archive.transaction()?;
+    }
 
-    progress("Updating thread activity...");
-    update_thread_activity(&tx)?;
-    fts::build_message_fts(&tx, progress)?;
+    if fts::message_fts_in_sync(&tx)? {
+        progress(ImportProgress::new(ImportStage::Fts).with_detail("up to date"));
+    } else {
+        fts::build_message_fts(&tx, progress)?;
+    }
 
-    progress("Finalizing import...");
+    progress(ImportProgress::new(ImportStage::Finalizing));
+    update_thread_activity(&tx)?;
     tx.commit()?;
 
-    let stats_json = serde_json::json!({
-        "sms_total": sms_total.unwrap_or(sms_count),
-        "sms_inserted": sms_inserted,
-        "mms_total": mms_count,
-        "mms_inserted": mms_inserted,
-        "messages_inserted_total": sms_inserted + mms_inserted,
-        "attachments_total": attachment_stats.total,
-        "attachments_found": attachment_stats.found,
-        "attachments_missing": attachment_stats.missing,
-        "attachments_inserted": attachment_stats.inserted,
-    })
-    .to_string();
-    Ok(stats_json)
+    let mut stats = std::mem::replace(checkpoint, resume::ImportCheckpoint::untracked()).into_stats();
+    stats.insert("detected_version".to_string(), serde_json::json!(detected_version));
+    Ok(serde_json::Value::Object(stats).to_string())
+}
+
+/// Joins a given and family name part, either of which may be missing or blank.
+fn full_name(given: Option<String>, family: Option<String>) -> Option<String> {
+    let name = [given, family]
+        .into_iter()
+        .flatten()
+        .map(|part| part.trim().to_string())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    (!name.is_empty()).then_some(name)
 }
 
+/// Source messages of `table` that quote another, as (archive message id, archive thread
+/// id, quoted message's sent timestamp) for [`resolve_quotes`].
+fn quote_refs(
+    signal: &Connection,
+    table: &str,
+    quote_id_col: &str,
+    ids: &identity::IdentityMap,
+) -> Result<Vec<(String, String, i64)>, CoreError> {
+    if !table_exists(signal, table)? {
+        return Ok(Vec::new());
+    }
+    let kind = if table == "sms" { "sms" } else { "mms" };
+    let mut stmt = signal.prepare(&format!(
+        "SELECT _id, thread_id, {quote_id_col} FROM {table} WHERE {quote_id_col} IS NOT NULL;"
+    ))?;
+    let rows = stmt.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?, row.get::<_, i64>(2)?)))?;
+    let mut quotes = Vec::new();
+    for row in rows {
+        let (id, thread_id, quoted_at) = row?;
+        quotes.push((ids.message(kind, id), ids.thread(thread_id), quoted_at));
+    }
+    Ok(quotes)
+}
+
+/// View-once MMS messages by `_id`, each with whether it has been opened.
+fn view_once_messages(
+    signal: &Connection,
+    mms_table: &str,
+    view_once_col: &str,
+    revealed_col: &str,
+) -> Result<HashMap<i64, bool>, CoreError> {
+    let mut stmt = signal.prepare(&format!(
+        "SELECT _id, {revealed_col} FROM {mms_table} WHERE COALESCE({view_once_col}, 0) != 0;"
+    ))?;
+    let rows = stmt.query_map([], |row| {
+        Ok((row.get::<_, i64>(0)?, row.get::<_, Option<i64>>(1)?.unwrap_or(0) != 0))
+    })?;
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+/// Timeline events (group updates, timer changes, safety-number changes) are never shown
+/// as outgoing, even when their base type is an outbox type.
 fn is_outgoing_type(msg_type: i64) -> bool {
+    if events::system_event(msg_type, None).is_some() {
+        return false;
+    }
     let base = (msg_type as u64) & 0x1F;
     matches!(base, 21 | 22 | 23 | 24 | 25 | 26 | 2 | 11)
 }
 
+/// Call records carry no text, so a type that decodes as a call only counts when the body
+/// is empty.
+fn is_blank(body: Option<&str>) -> bool {
+    body.unwrap_or("").trim().is_empty()
+}
+
+/// Identifies a message across backups, whose `_id`s Signal renumbers: its thread, author
+/// and sent time, in archive ids. The kind is left out because newer Signal versions keep
+/// SMS in the `message` table. Messages without a timestamp fall back to a content hash.
+fn message_dedupe_key(
+    kind: &str,
+    thread_id: &str,
+    sender_id: Option<&str>,
+    timestamp: Option<i64>,
+    message_type: &str,
+    body: Option<&str>,
+    is_outgoing: bool,
+) -> String {
+    match timestamp.filter(|ts| *ts > 0) {
+        Some(ts) => format!("sent:{}:{}:{}", thread_id, sender_id.unwrap_or("self"), ts),
+        None => fallback_dedupe_key(kind, thread_id, sender_id, timestamp, message_type, body, is_outgoing),
+    }
+}
+
 fn fallback_dedupe_key(
     kind: &str,
     thread_id: &str,
@@ -716,6 +1369,30 @@ fn fallback_dedupe_key(
     format!("fb:{}", hex::encode(hasher.finalize()))
 }
 
+/// Signal's `quote_id` is the quoted message's sent timestamp, not its row id. Links each
+/// reply to the message in its thread sent at that time (within 1 ms, for clients that
+/// round). Unmatched replies keep only the quote text in `metadata_json`.
+fn resolve_quotes(tx: &rusqlite::Transaction, quotes: &[(String, String, i64)]) -> Result<i64, CoreError> {
+    let mut find = tx.prepare(
+        "SELECT id FROM messages
+         WHERE thread_id = ?1 AND sent_at BETWEEN ?2 - 1 AND ?2 + 1 AND id != ?3
+         ORDER BY ABS(sent_at - ?2), id
+         LIMIT 1;",
+    )?;
+    let mut link = tx.prepare("UPDATE messages SET quote_message_id = ?2 WHERE id = ?1;")?;
+    let mut resolved = 0;
+    for (reply_id, thread_id, quoted_at) in quotes {
+        let target: Option<String> = find
+            .query_row(params![thread_id, quoted_at, reply_id], |row| row.get(0))
+            .optional()?;
+        if let Some(target) = target {
+            link.execute(params![reply_id, target])?;
+            resolved += 1;
+        }
+    }
+    Ok(resolved)
+}
+
 fn update_thread_activity(tx: &rusqlite::Transaction) -> Result<(), CoreError> {
     tx.execute(
         "UPDATE threads
@@ -729,9 +1406,15 @@ fn update_thread_activity(tx: &rusqlite::Transaction) -> Result<(), CoreError> {
     Ok(())
 }
 
-fn map_reactions<F>(signal: &Connection, tx: &rusqlite::Transaction, progress: &F) -> Result<(), CoreError>
+fn map_reactions<F>(
+    signal: &Connection,
+    tx: &rusqlite::Transaction,
+    ids: &identity::IdentityMap,
+    issues: &mut issues::ImportIssues,
+    progress: &F,
+) -> Result<(), CoreError>
 where
-    F: Fn(&str),
+    F: Fn(ImportProgress),
 {
     let table = if table_exists(signal, "reaction")? {
         "reaction"
@@ -747,9 +1430,9 @@ where
     if msg_col.is_none() || emoji_col.is_none() {
         return Ok(());
     }
-    progress("Importing reactions...");
+    progress(ImportProgress::new(ImportStage::Reactions));
     let query = format!(
-        "SELECT {msg}, {emoji}, {author}, {date} FROM {table};",
+        "SELECT {msg}, {emoji}, {author}, {date}, _ROWID_ FROM {table};",
         msg = msg_col.unwrap(),
         emoji = emoji_col.unwrap(),
         author = author_col.unwrap_or_else(|| "NULL".to_string()),
@@ -762,28 +1445,33 @@ where
         let emoji: Option<String> = row.get(1)?;
         let author_id: Option<i64> = row.get(2)?;
         let reacted_at: Option<i64> = row.get(3)?;
-        Ok((msg_raw, emoji, author_id, reacted_at))
+        let source_id: i64 = row.get(4)?;
+        Ok((msg_raw, emoji, author_id, reacted_at, source_id))
     })?;
     let mut batch: Vec<(String, String, String, Option<i64>)> = Vec::with_capacity(200);
     for row in rows {
-        let (msg_raw, emoji, author_id, reacted_at) = row?;
+        let (msg_raw, emoji, author_id, reacted_at, source_id) = row?;
         let msg_id = match msg_raw {
-            Value::Integer(v) => format!("mms:{}", v),
-            Value::Text(v) => {
-                if v.contains(':') {
-                    v
-                } else {
-                    format!("mms:{}", v)
-                }
+            Value::Integer(v) => ids.message("mms", v),
+            Value::Text(v) => match v.parse::<i64>() {
+                Ok(v) => ids.message("mms", v),
+                Err(_) if v.contains(':') => v,
+                Err(_) => format!("mms:{}", v),
+            },
+            _ => {
+                issues.record(tx, "reaction_without_message", table, Some(source_id.to_string()), None)?;
+                continue;
             }
-            _ => continue,
         };
         let emoji = match emoji {
             Some(v) => v,
-            None => continue,
+            None => {
+                issues.record(tx, "reaction_without_emoji", table, Some(source_id.to_string()), Some(msg_id))?;
+                continue;
+            }
         };
         let author = author_id
-            .map(|v| v.to_string())
+            .map(|v| ids.recipient(v))
             .unwrap_or_else(|| "unknown".to_string());
         batch.push((msg_id, author, emoji, reacted_at));
         if batch.len() >= 500 {
@@ -822,6 +1510,85 @@ fn insert_reaction_batch(
     Ok(())
 }
 
+fn map_mentions<F>(
+    signal: &Connection,
+    tx: &rusqlite::Transaction,
+    ids: &identity::IdentityMap,
+    issues: &mut issues::ImportIssues,
+    progress: &F,
+) -> Result<(), CoreError>
+where
+    F: Fn(ImportProgress),
+{
+    if !table_exists(signal, "mention")? {
+        return Ok(());
+    }
+    let msg_col = pick_column(signal, "mention", &["message_id", "mms_id"])?;
+    let recipient_col = pick_column(signal, "mention", &["recipient_id"])?;
+    let start_col = pick_column(signal, "mention", &["range_start"])?;
+    let length_col = pick_column(signal, "mention", &["range_length"])?;
+    let (Some(msg_col), Some(recipient_col), Some(start_col), Some(length_col)) =
+        (msg_col, recipient_col, start_col, length_col)
+    else {
+        return Ok(());
+    };
+    progress(ImportProgress::new(ImportStage::Reactions).with_detail("mentions"));
+    let query = format!(
+        "SELECT {msg}, {recipient}, {start}, {length}, _ROWID_ FROM mention;",
+        msg = msg_col,
+        recipient = recipient_col,
+        start = start_col,
+        length = length_col,
+    );
+    let mut stmt = signal.prepare(&query)?;
+    let rows = stmt.query_map([], |row| {
+        let message_id: Option<i64> = row.get(0)?;
+        let recipient_id: Option<i64> = row.get(1)?;
+        let range_start: Option<i64> = row.get(2)?;
+        let range_length: Option<i64> = row.get(3)?;
+        let source_id: i64 = row.get(4)?;
+        Ok((message_id, recipient_id, range_start, range_length, source_id))
+    })?;
+    let mut batch: Vec<(String, String, i64, i64)> = Vec::with_capacity(500);
+    for row in rows {
+        let (message_id, recipient_id, range_start, range_length, source_id) = row?;
+        let (Some(message_id), Some(recipient_id), Some(range_start), Some(range_length)) =
+            (message_id, recipient_id, range_start, range_length)
+        else {
+            issues.record(tx, "mention_incomplete", "mention", Some(source_id.to_string()), None)?;
+            continue;
+        };
+        batch.push((ids.message("mms", message_id), ids.recipient(recipient_id), range_start, range_length));
+        if batch.len() >= 500 {
+            insert_mention_batch(tx, &batch)?;
+            batch.clear();
+        }
+    }
+    if !batch.is_empty() {
+        insert_mention_batch(tx, &batch)?;
+    }
+    Ok(())
+}
+
+fn insert_mention_batch(tx: &rusqlite::Transaction, batch: &[(String, String, i64, i64)]) -> Result<(), CoreError> {
+    let mut sql = String::from(
+        "INSERT OR IGNORE INTO mentions (message_id, recipient_id, range_start, range_length) VALUES ",
+    );
+    let mut params_vec: Vec<Value> = Vec::with_capacity(batch.len() * 4);
+    for (idx, row) in batch.iter().enumerate() {
+        if idx > 0 {
+            sql.push(',');
+        }
+        sql.push_str("(?, ?, ?, ?)");
+        params_vec.push(Value::from(row.0.clone()));
+        params_vec.push(Value::from(row.1.clone()));
+        params_vec.push(Value::from(row.2));
+        params_vec.push(Value::from(row.3));
+    }
+    tx.execute(&sql, rusqlite::params_from_iter(params_vec))?;
+    Ok(())
+}
+
 
 struct MessageRowData {
     id: String,
@@ -1,41 +1,93 @@
+use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::mpsc;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::thread;
 
-use rusqlite::Connection;
+use rusqlite::{params, Connection};
 use rusqlite::types::Value;
 use tempfile::NamedTempFile;
 
+use crate::blurhash::{image_blurhash, MAX_BLURHASH_SOURCE_BYTES};
 use crate::crypto;
 use crate::error::CoreError;
+use crate::mime::{image_dimensions, infer_kind, sniff_mime, DIMENSION_PROBE_LEN, SNIFF_LEN};
+use crate::models::{ImportProgress, ImportStage};
 
-use super::{pick_column, table_exists};
+use super::issues::{ImportIssues, MAX_ISSUES_PER_IMPORT};
+use super::{column_exists, pick_column, table_exists};
 
 const ATTACHMENT_BATCH_SIZE: usize = 500;
 const ATTACHMENT_PROGRESS_EVERY: i64 = 2000;
 const ATTACHMENT_WORKERS: usize = 4;
+/// Jobs buffered ahead of the workers while the part table is read.
+const ATTACHMENT_QUEUE_DEPTH: usize = 256;
+/// Files from this size are encrypted on several threads; AES on one core is the import's
+/// bottleneck for large videos.
+const PARALLEL_ENCRYPT_THRESHOLD: u64 = 20 * 1024 * 1024;
+const PARALLEL_ENCRYPT_WORKERS: usize = 4;
 const SIZE_SMALL_MAX: i64 = 1 * 1024 * 1024 - 1;
 const SIZE_MEDIUM_MAX: i64 = 10 * 1024 * 1024 - 1;
+/// Signal sends bodies over ~2000 characters as a part of this type and truncates the body.
+const LONG_TEXT_MIME: &str = "text/x-signal-plain";
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub(super) struct AttachmentImportStats {
     pub total: i64,
     pub found: i64,
     pub missing: i64,
     pub inserted: i64,
+    /// Parts of view-once messages whose bytes Signal already deleted; not counted as missing.
+    pub view_once_purged: i64,
+    /// Long-message text parts folded into their message's body instead of stored.
+    pub long_text_bodies: i64,
+    /// Parts whose message is in neither the MMS nor the SMS table; skipped.
+    pub unresolved: i64,
+    /// Plaintext bytes read into the attachment store.
+    pub bytes: u64,
+    /// The missing files behind `missing`, up to the import issue cap.
+    pub missing_files: Vec<MissingAttachment>,
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct MissingAttachment {
+    pub message_id: String,
+    pub source_id: Option<i64>,
+    pub path: PathBuf,
+}
+
+impl MissingAttachment {
+    /// Adds this file to the import's issues under `source_table`.
+    pub fn record(&self, tx: &Connection, issues: &mut ImportIssues, source_table: &str) -> Result<(), CoreError> {
+        let file = self.path.file_name().map(|name| name.to_string_lossy().into_owned());
+        issues.record(
+            tx,
+            "attachment_missing",
+            source_table,
+            Some(self.source_id.map_or_else(|| self.message_id.clone(), |id| id.to_string())),
+            Some(format!("{} has no file {}", self.message_id, file.unwrap_or_default())),
+        )
+    }
 }
 
-pub(super) fn map_attachments<F>(
+/// `part_message` resolves a part's message `_id` to its archive message and, for a
+/// view-once message, whether it has been opened, after which Signal deletes its attachment
+/// bytes. `None` means the message was not imported.
+pub(super) fn map_attachments<F, M>(
     signal: &Connection,
     tx: &rusqlite::Transaction,
     export_dir: &Path,
     attachments_dir: &Path,
+    mut part_message: M,
+    issues: &mut ImportIssues,
     progress: &F,
 ) -> Result<AttachmentImportStats, CoreError>
 where
-    F: Fn(&str),
+    F: Fn(ImportProgress),
+    M: FnMut(i64) -> Result<Option<(String, Option<bool>)>, CoreError>,
 {
     let master_key = crypto::load_or_create_master_key()?;
     let part_table = if table_exists(signal, "part")? {
@@ -43,22 +95,12 @@ where
     } else if table_exists(signal, "attachment")? {
         "attachment".to_string()
     } else {
-        return Ok(AttachmentImportStats {
-            total: 0,
-            found: 0,
-            missing: 0,
-            inserted: 0,
-        });
+        return Ok(AttachmentImportStats::default());
     };
 
     let part_mid = pick_column(signal, &part_table, &["message_id", "mid"])?;
     if part_mid.is_none() {
-        return Ok(AttachmentImportStats {
-            total: 0,
-            found: 0,
-            missing: 0,
-            inserted: 0,
-        });
+        return Ok(AttachmentImportStats::default());
     }
     let part_unique = pick_column(signal, &part_table, &["unique_id"])?;
     let part_ct = pick_column(signal, &part_table, &["content_type", "ct"])?;
@@ -75,16 +117,21 @@ where
         .query_row(&format!("SELECT COUNT(1) FROM {part_table};"), [], |row| row.get(0))
         .unwrap_or(0);
     if total_rows == 0 {
-        progress("No attachments found.");
-        return Ok(AttachmentImportStats {
-            total: 0,
-            found: 0,
-            missing: 0,
-            inserted: 0,
-        });
+        progress(ImportProgress::new(ImportStage::Attachments).with_detail("none found"));
+        return Ok(AttachmentImportStats::default());
     }
 
-    progress("Importing attachments...");
+    let total_bytes: i64 = match &part_size {
+        Some(size) => signal
+            .query_row(&format!("SELECT COALESCE(SUM({size}), 0) FROM {part_table} WHERE {size} > 0;"), [], |row| {
+                row.get(0)
+            })
+            .unwrap_or(0),
+        None => 0,
+    };
+    progress(
+        ImportProgress::counted(ImportStage::Attachments, 0, total_rows as u64).with_bytes(0, total_bytes as u64),
+    );
     let query = format!(
         "SELECT _id, {mid}, {unique}, {ct}, {size}, {file}, {width}, {height}, {duration} FROM {table};",
         mid = part_mid.as_deref().unwrap_or("NULL"),
@@ -111,142 +158,389 @@ where
         Ok((id, mid, unique_id, mime, data_size, file_name, width, height, duration))
     })?;
 
-    let mut jobs: Vec<AttachmentJob> = Vec::with_capacity(total_rows as usize);
+    let mut pipeline = AttachmentPipeline::start(attachments_dir, master_key, total_rows, total_bytes as u64);
+    let mut long_text_bodies: i64 = 0;
+    let mut unresolved: i64 = 0;
 
     for row in rows {
         let (id, mid, unique_id, mime, data_size, file_name, width, height, duration) = row?;
         let mid = match mid {
             Some(mid) => mid,
-            None => continue,
+            None => {
+                issues.record(tx, "attachment_without_message", &part_table, Some(id.to_string()), None)?;
+                continue;
+            }
+        };
+        let Some((message_id, view_once_state)) = part_message(mid)? else {
+            unresolved += 1;
+            issues.record(
+                tx,
+                "attachment_unresolved",
+                &part_table,
+                Some(id.to_string()),
+                Some(format!("no message {} in sms or mms", mid)),
+            )?;
+            continue;
         };
-        let mut unique_id_val = unique_id.unwrap_or(-1);
-        if unique_id_val == 0 {
-            unique_id_val = -1;
+        if view_once_state == Some(true) {
+            pipeline.skip_revealed_view_once();
+            continue;
         }
-        let attachment_path = export_dir.join(format!("Attachment_{}_{}.bin", id, unique_id_val));
-        jobs.push(AttachmentJob {
-            mid,
-            attachment_path,
-            mime,
-            data_size,
-            file_name,
-            width,
-            height,
-            duration_ms: duration,
-        });
+        let attachment_path = part_path(export_dir, id, unique_id);
+        if mime.as_deref() == Some(LONG_TEXT_MIME) {
+            // The update trigger reindexes the message for search.
+            if let Ok(bytes) = fs::read(&attachment_path) {
+                let text = String::from_utf8_lossy(&bytes);
+                tx.execute(
+                    "UPDATE messages SET body = ?2 WHERE id = ?1 AND body IS NOT ?2;",
+                    params![message_id, text],
+                )?;
+                long_text_bodies += 1;
+            }
+            continue;
+        }
+        pipeline.push(
+            tx,
+            AttachmentJob {
+                message_id,
+                source_id: Some(id),
+                is_view_once: view_once_state.is_some(),
+                attachment_path,
+                mime,
+                data_size,
+                file_name,
+                width,
+                height,
+                duration_ms: duration,
+            },
+            progress,
+        )?;
+    }
+
+    let mut stats = pipeline.finish(tx, progress)?;
+    for missing in &stats.missing_files {
+        missing.record(tx, issues, &part_table)?;
     }
+    stats.long_text_bodies = long_text_bodies;
+    stats.unresolved = unresolved;
+    Ok(stats)
+}
+
+/// Where the backup export wrote part `id`. A missing or zero `unique_id` is written as -1.
+pub(super) fn part_path(export_dir: &Path, id: i64, unique_id: Option<i64>) -> PathBuf {
+    let unique_id = unique_id.filter(|v| *v != 0).unwrap_or(-1);
+    export_dir.join(format!("Attachment_{}_{}.bin", id, unique_id))
+}
+
+/// Workers that copy attachment files while the caller is still producing jobs. Jobs go
+/// through a bounded queue, so a caller reading from a SQL cursor blocks instead of
+/// buffering the whole part table; results are drained and inserted on the caller's
+/// thread, which owns the transaction.
+struct AttachmentPipeline {
+    jobs: Option<mpsc::SyncSender<AttachmentJob>>,
+    results: mpsc::Receiver<AttachmentResult>,
+    /// Row count used for progress until the real total is known.
+    expected: i64,
+    /// Bytes the workers have copied so far, out of the sizes the source declared.
+    bytes_done: Arc<AtomicU64>,
+    bytes_total: u64,
+    reported_bytes: u64,
+    queued: i64,
+    revealed_view_once: i64,
+    processed: i64,
+    found: i64,
+    missing: i64,
+    view_once_purged: i64,
+    inserted: i64,
+    missing_files: Vec<MissingAttachment>,
+    batch: Vec<AttachmentRowData>,
+}
 
-    if jobs.is_empty() {
-        progress("No attachments found.");
-        return Ok(AttachmentImportStats {
-            total: 0,
+impl AttachmentPipeline {
+    fn start(attachments_dir: &Path, master_key: crypto::MasterKey, expected: i64, bytes_total: u64) -> Self {
+        let (job_tx, job_rx) = mpsc::sync_channel::<AttachmentJob>(ATTACHMENT_QUEUE_DEPTH);
+        let (result_tx, result_rx) = mpsc::channel();
+        let job_rx = Arc::new(Mutex::new(job_rx));
+        let key = Arc::new(master_key);
+        let dest_dir = Arc::new(attachments_dir.to_path_buf());
+        let bytes_done = Arc::new(AtomicU64::new(0));
+
+        for _ in 0..ATTACHMENT_WORKERS {
+            let worker_jobs = Arc::clone(&job_rx);
+            let worker_tx = result_tx.clone();
+            let worker_key = Arc::clone(&key);
+            let worker_dest = Arc::clone(&dest_dir);
+            let worker_bytes = Arc::clone(&bytes_done);
+            thread::spawn(move || loop {
+                let job = match worker_jobs.lock() {
+                    Ok(jobs) => jobs.recv(),
+                    Err(_) => break,
+                };
+                let Ok(job) = job else {
+                    break;
+                };
+                let result = process_job(&job, worker_dest.as_path(), worker_key.as_ref(), &worker_bytes);
+                let failed = matches!(result, AttachmentResult::Error(_));
+                let _ = worker_tx.send(result);
+                if failed {
+                    break;
+                }
+            });
+        }
+
+        AttachmentPipeline {
+            jobs: Some(job_tx),
+            results: result_rx,
+            expected,
+            bytes_done,
+            bytes_total,
+            reported_bytes: 0,
+            queued: 0,
+            revealed_view_once: 0,
+            processed: 0,
             found: 0,
             missing: 0,
+            view_once_purged: 0,
             inserted: 0,
-        });
+            missing_files: Vec::new(),
+            batch: Vec::with_capacity(ATTACHMENT_BATCH_SIZE),
+        }
     }
 
-    let total: i64 = jobs.len() as i64;
-    let worker_count = ATTACHMENT_WORKERS.min(jobs.len().max(1));
-    let chunk_size = (jobs.len() + worker_count - 1) / worker_count;
-
-    let (result_tx, result_rx) = mpsc::channel();
-    let key = Arc::new(master_key);
-    let dest_dir = Arc::new(attachments_dir.to_path_buf());
-
-    for chunk in jobs.chunks(chunk_size) {
-        let worker_jobs = chunk.to_vec();
-        let worker_tx = result_tx.clone();
-        let worker_key = Arc::clone(&key);
-        let worker_dest = Arc::clone(&dest_dir);
-        thread::spawn(move || {
-            for job in worker_jobs {
-                if !job.attachment_path.exists() {
-                    let _ = worker_tx.send(AttachmentResult::Missing);
-                    continue;
-                }
-                match copy_attachment(&job.attachment_path, worker_dest.as_path(), worker_key.as_ref()) {
-                    Ok((sha256, file_size)) => {
-                        let size_bytes = job.data_size.or(Some(file_size as i64));
-                        let size_bucket = size_bytes.map(bucket_from_size);
-                        let kind = job.mime.as_deref().map(infer_kind).unwrap_or_else(|| "file".to_string());
-                        let message_id = format!("mms:{}", job.mid);
-                        let attachment_id = format!("att:{}:{}", message_id, sha256);
-                        let row = AttachmentRowData {
-                            id: attachment_id,
-                            message_id,
-                            sha256,
-                            mime: job.mime.clone(),
-                            size_bytes,
-                            size_bucket,
-                            original_filename: job.file_name.clone(),
-                            kind,
-                            width: job.width,
-                            height: job.height,
-                            duration_ms: job.duration_ms,
-                        };
-                        let _ = worker_tx.send(AttachmentResult::Found(row));
-                    }
-                    Err(err) => {
-                        let _ = worker_tx.send(AttachmentResult::Error(err.to_string()));
-                        break;
-                    }
-                }
-            }
-        });
+    /// Counts a part of an opened view-once message, whose bytes Signal already deleted.
+    fn skip_revealed_view_once(&mut self) {
+        self.revealed_view_once += 1;
+        self.view_once_purged += 1;
+        self.processed += 1;
     }
-    drop(result_tx);
 
-    let mut processed: i64 = 0;
-    let mut found_files: i64 = 0;
-    let mut missing_files: i64 = 0;
-    let mut inserted: i64 = 0;
-    let mut batch: Vec<AttachmentRowData> = Vec::with_capacity(ATTACHMENT_BATCH_SIZE);
+    fn push<F>(&mut self, tx: &rusqlite::Transaction, job: AttachmentJob, progress: &F) -> Result<(), CoreError>
+    where
+        F: Fn(ImportProgress),
+    {
+        self.queued += 1;
+        let sent = match &self.jobs {
+            Some(jobs) => jobs.send(job).is_ok(),
+            None => false,
+        };
+        if !sent {
+            // Every worker has stopped; one of them reported why.
+            self.jobs = None;
+            while let Ok(result) = self.results.recv() {
+                self.record(tx, result, progress)?;
+            }
+            return Err(CoreError::InvalidArgument("attachment workers stopped".to_string()));
+        }
+        while let Ok(result) = self.results.try_recv() {
+            self.record(tx, result, progress)?;
+        }
+        Ok(())
+    }
 
-    for result in result_rx {
+    fn record<F>(&mut self, tx: &rusqlite::Transaction, result: AttachmentResult, progress: &F) -> Result<(), CoreError>
+    where
+        F: Fn(ImportProgress),
+    {
         match result {
             AttachmentResult::Found(row) => {
-                processed += 1;
-                found_files += 1;
-                batch.push(row);
-                if batch.len() >= ATTACHMENT_BATCH_SIZE {
-                    inserted += insert_attachment_batch(tx, &batch)?;
-                    batch.clear();
+                self.processed += 1;
+                self.found += 1;
+                self.batch.push(row);
+                if self.batch.len() >= ATTACHMENT_BATCH_SIZE {
+                    self.inserted += insert_attachment_batch(tx, &self.batch)?;
+                    self.batch.clear();
+                }
+            }
+            AttachmentResult::Missing(file) => {
+                self.processed += 1;
+                self.missing += 1;
+                if (self.missing_files.len() as i64) < MAX_ISSUES_PER_IMPORT {
+                    self.missing_files.push(file);
                 }
             }
-            AttachmentResult::Missing => {
-                processed += 1;
-                missing_files += 1;
+            AttachmentResult::ViewOncePurged => {
+                self.processed += 1;
+                self.view_once_purged += 1;
             }
             AttachmentResult::Error(msg) => {
                 return Err(CoreError::InvalidArgument(msg));
             }
         }
 
-        if processed % ATTACHMENT_PROGRESS_EVERY == 0 {
-            let msg = format!(
-                "Importing attachments... {}/{} (found {}, missing {}, inserted {})",
-                processed, total, found_files, missing_files, inserted
+        // Report every few thousand items, and whenever another percent of the bytes is in so
+        // a run of large files doesn't look stalled.
+        let bytes = self.bytes_done.load(Ordering::Relaxed);
+        let byte_step = (self.bytes_total / 100).max(1);
+        if self.processed % ATTACHMENT_PROGRESS_EVERY == 0 || (self.bytes_total > 0 && bytes >= self.reported_bytes + byte_step) {
+            self.reported_bytes = bytes;
+            progress(
+                ImportProgress::counted(
+                    ImportStage::Attachments,
+                    self.processed as u64,
+                    self.expected.max(self.processed) as u64,
+                )
+                .with_bytes(bytes, self.bytes_total.max(bytes))
+                .with_detail(format!(
+                    "found {}, missing {}, inserted {}",
+                    self.found, self.missing, self.inserted
+                )),
             );
-            progress(&msg);
         }
+        Ok(())
     }
 
-    if !batch.is_empty() {
-        inserted += insert_attachment_batch(tx, &batch)?;
+    fn finish<F>(mut self, tx: &rusqlite::Transaction, progress: &F) -> Result<AttachmentImportStats, CoreError>
+    where
+        F: Fn(ImportProgress),
+    {
+        self.jobs = None;
+        let total = self.queued + self.revealed_view_once;
+        if self.queued == 0 {
+            progress(ImportProgress::new(ImportStage::Attachments).with_detail("none found"));
+            return Ok(AttachmentImportStats {
+                total,
+                view_once_purged: self.revealed_view_once,
+                ..AttachmentImportStats::default()
+            });
+        }
+
+        while let Ok(result) = self.results.recv() {
+            self.record(tx, result, progress)?;
+        }
+        if !self.batch.is_empty() {
+            self.inserted += insert_attachment_batch(tx, &self.batch)?;
+        }
+
+        let bytes = self.bytes_done.load(Ordering::Relaxed);
+        progress(
+            ImportProgress::counted(ImportStage::Attachments, self.processed as u64, total as u64)
+                .with_bytes(bytes, self.bytes_total.max(bytes))
+                .with_detail(format!(
+                    "found {}, missing {}, view-once purged {}, inserted {}",
+                    self.found, self.missing, self.view_once_purged, self.inserted
+                )),
+        );
+
+        Ok(AttachmentImportStats {
+            total,
+            found: self.found,
+            missing: self.missing,
+            inserted: self.inserted,
+            view_once_purged: self.view_once_purged,
+            bytes,
+            missing_files: self.missing_files,
+            ..AttachmentImportStats::default()
+        })
     }
+}
 
-    progress(&format!(
-        "Attachments imported: total {}, found {}, missing {}, inserted {}",
-        total, found_files, missing_files, inserted
-    ));
-
-    Ok(AttachmentImportStats {
-        total,
-        found: found_files,
-        missing: missing_files,
-        inserted,
-    })
+fn process_job(
+    job: &AttachmentJob,
+    dest_dir: &Path,
+    master_key: &crypto::MasterKey,
+    bytes_done: &AtomicU64,
+) -> AttachmentResult {
+    if !job.attachment_path.exists() {
+        return if job.is_view_once {
+            AttachmentResult::ViewOncePurged
+        } else {
+            AttachmentResult::Missing(MissingAttachment {
+                message_id: job.message_id.clone(),
+                source_id: job.source_id,
+                path: job.attachment_path.clone(),
+            })
+        };
+    }
+    match copy_attachment(&job.attachment_path, dest_dir, master_key) {
+        Ok((sha256, file_size)) => {
+            bytes_done.fetch_add(file_size, Ordering::Relaxed);
+            let size_bytes = job.data_size.or(Some(file_size as i64));
+            let size_bucket = size_bytes.map(bucket_from_size);
+            let mime = job.mime.clone().or_else(|| sniff_file(&job.attachment_path));
+            let kind = mime.as_deref().map(infer_kind).unwrap_or_else(|| "file".to_string());
+            let (width, height) = match (job.width, job.height) {
+                (Some(w), Some(h)) => (Some(w), Some(h)),
+                (w, h) if kind == "image" => {
+                    probe_dimensions(&job.attachment_path).map_or((w, h), |(w, h)| (Some(w), Some(h)))
+                }
+                other => other,
+            };
+            let blurhash = if kind == "image" { probe_blurhash(&job.attachment_path) } else { None };
+            AttachmentResult::Found(AttachmentRowData {
+                id: format!("att:{}:{}", job.message_id, sha256),
+                message_id: job.message_id.clone(),
+                sha256,
+                mime,
+                size_bytes,
+                size_bucket,
+                original_filename: job.file_name.clone(),
+                kind,
+                width,
+                height,
+                duration_ms: job.duration_ms,
+                blurhash,
+            })
+        }
+        Err(err) => AttachmentResult::Error(err.to_string()),
+    }
+}
+
+/// The sticker carried by an MMS message, keyed by message `_id` in [`sticker_refs`].
+#[derive(Debug, Clone)]
+pub(super) struct StickerRef {
+    pub pack_id: String,
+    pub pack_title: Option<String>,
+    pub emoji: Option<String>,
+}
+
+/// Messages whose attachment is a sticker. The sticker image itself is an ordinary part
+/// row, so [`map_attachments`] imports it; this only recovers the pack metadata.
+pub(super) fn sticker_refs(signal: &Connection) -> Result<HashMap<i64, StickerRef>, CoreError> {
+    let part_table = if table_exists(signal, "part")? {
+        "part"
+    } else if table_exists(signal, "attachment")? {
+        "attachment"
+    } else {
+        return Ok(HashMap::new());
+    };
+    let part_mid = pick_column(signal, part_table, &["message_id", "mid"])?;
+    let pack_col = pick_column(signal, part_table, &["sticker_pack_id"])?;
+    let (Some(part_mid), Some(pack_col)) = (part_mid, pack_col) else {
+        return Ok(HashMap::new());
+    };
+    let emoji_col = pick_column(signal, part_table, &["sticker_emoji"])?;
+    let title_expr = if table_exists(signal, "sticker")? && column_exists(signal, "sticker", "pack_title")? {
+        format!(
+            "(SELECT s.pack_title FROM sticker s WHERE s.pack_id = p.{} AND s.pack_title IS NOT NULL LIMIT 1)",
+            pack_col
+        )
+    } else {
+        "NULL".to_string()
+    };
+    let query = format!(
+        "SELECT p.{mid}, p.{pack}, {emoji}, {title} FROM {table} p WHERE p.{pack} IS NOT NULL;",
+        mid = part_mid,
+        pack = pack_col,
+        emoji = emoji_col.map(|c| format!("p.{}", c)).unwrap_or_else(|| "NULL".to_string()),
+        title = title_expr,
+        table = part_table,
+    );
+    let mut stmt = signal.prepare(&query)?;
+    let rows = stmt.query_map([], |row| {
+        let mid: Option<i64> = row.get(0)?;
+        let pack_id: String = row.get(1)?;
+        let emoji: Option<String> = row.get(2)?;
+        let pack_title: Option<String> = row.get(3)?;
+        Ok((mid, pack_id, emoji, pack_title))
+    })?;
+    let mut refs = HashMap::new();
+    for row in rows {
+        let (mid, pack_id, emoji, pack_title) = row?;
+        if let Some(mid) = mid {
+            refs.insert(mid, StickerRef { pack_id, pack_title, emoji });
+        }
+    }
+    Ok(refs)
 }
 
 struct AttachmentRowData {
@@ -261,11 +555,17 @@ struct AttachmentRowData {
     width: Option<i64>,
     height: Option<i64>,
     duration_ms: Option<i64>,
+    blurhash: Option<String>,
 }
 
+/// One attachment file to import for the archive message `message_id`. Missing optional
+/// fields are filled in from the file itself.
 #[derive(Clone)]
 struct AttachmentJob {
-    mid: i64,
+    message_id: String,
+    /// The source row's `_id`, when it has one, for import issues.
+    source_id: Option<i64>,
+    is_view_once: bool,
     attachment_path: std::path::PathBuf,
     mime: Option<String>,
     data_size: Option<i64>,
@@ -277,7 +577,8 @@ struct AttachmentJob {
 
 enum AttachmentResult {
     Found(AttachmentRowData),
-    Missing,
+    Missing(MissingAttachment),
+    ViewOncePurged,
     Error(String),
 }
 
@@ -299,14 +600,14 @@ fn insert_attachment_batch(
         return Ok(0);
     }
     let mut sql = String::from(
-        "INSERT OR IGNORE INTO attachments (id, message_id, sha256, mime, size_bytes, size_bucket, original_filename, kind, width, height, duration_ms) VALUES ",
+        "INSERT OR IGNORE INTO attachments (id, message_id, sha256, mime, size_bytes, size_bucket, original_filename, kind, width, height, duration_ms, blurhash) VALUES ",
     );
-    let mut params_vec: Vec<Value> = Vec::with_capacity(batch.len() * 11);
+    let mut params_vec: Vec<Value> = Vec::with_capacity(batch.len() * 12);
     for (idx, row) in batch.iter().enumerate() {
         if idx > 0 {
             sql.push(',');
         }
-        sql.push_str("(?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)");
+        sql.push_str("(?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)");
         params_vec.push(Value::from(row.id.clone()));
         params_vec.push(Value::from(row.message_id.clone()));
         params_vec.push(Value::from(row.sha256.clone()));
@@ -339,6 +640,10 @@ fn insert_attachment_batch(
             Some(v) => params_vec.push(Value::from(v)),
             None => params_vec.push(Value::Null),
         }
+        match &row.blurhash {
+            Some(v) => params_vec.push(Value::from(v.clone())),
+            None => params_vec.push(Value::Null),
+        }
     }
     let changes = tx.execute(&sql, rusqlite::params_from_iter(params_vec))?;
     Ok(changes as i64)
@@ -353,8 +658,13 @@ fn copy_attachment(
         .map_err(|e| CoreError::InvalidArgument(format!("attachment open failed: {}", e)))?;
     let mut temp = NamedTempFile::new_in(dest_dir)
         .map_err(|e| CoreError::InvalidArgument(format!("attachment temp failed: {}", e)))?;
-    let chunk_size = attachment_chunk_size(src).unwrap_or(1024 * 1024);
-    let (hash, total) = crypto::encrypt_stream_with_hash_chunk(&mut file, &mut temp, master_key, chunk_size)?;
+    let size = file.metadata().map(|meta| meta.len()).unwrap_or(0);
+    let chunk_size = crypto::attachment_chunk_size(size);
+    let (hash, total) = if size >= PARALLEL_ENCRYPT_THRESHOLD {
+        crypto::encrypt_file_parallel_chunk(src, temp.path(), master_key, PARALLEL_ENCRYPT_WORKERS, chunk_size)?
+    } else {
+        crypto::encrypt_stream_with_hash_chunk(&mut file, &mut temp, master_key, chunk_size)?
+    };
     let dest = dest_dir.join(&hash);
     if dest.exists() {
         return Ok((hash, total));
@@ -364,24 +674,26 @@ fn copy_attachment(
     Ok((hash, total))
 }
 
-fn attachment_chunk_size(path: &Path) -> Option<usize> {
-    let meta = fs::metadata(path).ok()?;
-    let size = meta.len();
-    if size >= 10 * 1024 * 1024 {
-        Some(4 * 1024 * 1024)
-    } else {
-        Some(1024 * 1024)
-    }
+fn read_prefix(path: &Path, len: usize) -> Option<Vec<u8>> {
+    let mut header = Vec::with_capacity(len);
+    fs::File::open(path).ok()?.take(len as u64).read_to_end(&mut header).ok()?;
+    Some(header)
 }
 
-fn infer_kind(mime: &str) -> String {
-    if mime.starts_with("image/") {
-        "image".to_string()
-    } else if mime.starts_with("video/") {
-        "video".to_string()
-    } else if mime.starts_with("audio/") {
-        "audio".to_string()
-    } else {
-        "file".to_string()
+/// Some backups leave `content_type` NULL; fall back to the file's magic number.
+fn sniff_file(path: &Path) -> Option<String> {
+    sniff_mime(&read_prefix(path, SNIFF_LEN)?).map(str::to_string)
+}
+
+/// Signal doesn't always record image sizes; read them from the file's headers instead.
+fn probe_dimensions(path: &Path) -> Option<(i64, i64)> {
+    image_dimensions(&read_prefix(path, DIMENSION_PROBE_LEN)?)
+}
+
+/// The gallery placeholder for an image, from the plaintext file before it's encrypted away.
+fn probe_blurhash(path: &Path) -> Option<String> {
+    if fs::metadata(path).ok()?.len() > MAX_BLURHASH_SOURCE_BYTES as u64 {
+        return None;
     }
+    image_blurhash(&fs::read(path).ok()?)
 }
@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+
+use rusqlite::Connection;
+use serde_json::{json, Map, Value};
+
+use crate::error::CoreError;
+
+use super::{pick_column, table_exists};
+
+// Base message types (low five bits of `type`) Signal uses for call records.
+const INCOMING_AUDIO_CALL: u64 = 1;
+const OUTGOING_AUDIO_CALL: u64 = 2;
+const MISSED_AUDIO_CALL: u64 = 3;
+const MISSED_VIDEO_CALL: u64 = 8;
+const INCOMING_VIDEO_CALL: u64 = 10;
+const OUTGOING_VIDEO_CALL: u64 = 11;
+const GROUP_CALL: u64 = 12;
+
+// `call` table enums.
+const CALL_TYPE_VIDEO: i64 = 1;
+const CALL_TYPE_GROUP: i64 = 2;
+const CALL_TYPE_AD_HOC: i64 = 3;
+const CALL_DIRECTION_OUTGOING: i64 = 1;
+const CALL_EVENT_MISSED: i64 = 3;
+const CALL_EVENT_MISSED_NOTIFICATION_PROFILE: i64 = 10;
+
+/// A call record, stored on its message as `call_direction`, `call_is_video` and
+/// `call_duration_ms` in `metadata_json`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct CallInfo {
+    /// "incoming", "outgoing", "missed", or "group" for group calls with no direction.
+    pub direction: &'static str,
+    pub is_video: bool,
+    pub duration_ms: Option<i64>,
+}
+
+impl CallInfo {
+    pub fn write_metadata(&self, metadata: &mut Map<String, Value>) {
+        metadata.insert("call_direction".to_string(), json!(self.direction));
+        metadata.insert("call_is_video".to_string(), json!(self.is_video));
+        metadata.insert("call_duration_ms".to_string(), json!(self.duration_ms));
+    }
+}
+
+/// Decodes a call from a message's type bits; `None` for anything that isn't a call.
+pub(super) fn call_from_type(msg_type: i64) -> Option<CallInfo> {
+    let (direction, is_video) = match (msg_type as u64) & 0x1F {
+        INCOMING_AUDIO_CALL => ("incoming", false),
+        OUTGOING_AUDIO_CALL => ("outgoing", false),
+        MISSED_AUDIO_CALL => ("missed", false),
+        MISSED_VIDEO_CALL => ("missed", true),
+        INCOMING_VIDEO_CALL => ("incoming", true),
+        OUTGOING_VIDEO_CALL => ("outgoing", true),
+        GROUP_CALL => ("group", true),
+        _ => return None,
+    };
+    Some(CallInfo {
+        direction,
+        is_video,
+        duration_ms: None,
+    })
+}
+
+/// Calls from Signal's `call` table keyed by the `_id` of their message row. Newer backups
+/// record direction and outcome here rather than in the message type.
+pub(super) fn call_log(signal: &Connection) -> Result<HashMap<i64, CallInfo>, CoreError> {
+    if !table_exists(signal, "call")? {
+        return Ok(HashMap::new());
+    }
+    let Some(msg_col) = pick_column(signal, "call", &["message_id"])? else {
+        return Ok(HashMap::new());
+    };
+    let type_col = pick_column(signal, "call", &["type"])?;
+    let direction_col = pick_column(signal, "call", &["direction"])?;
+    let event_col = pick_column(signal, "call", &["event"])?;
+    let duration_col = pick_column(signal, "call", &["duration", "duration_ms"])?;
+    let query = format!(
+        "SELECT {msg}, {call_type}, {direction}, {event}, {duration} FROM call WHERE {msg} IS NOT NULL;",
+        msg = msg_col,
+        call_type = type_col.as_deref().unwrap_or("NULL"),
+        direction = direction_col.as_deref().unwrap_or("NULL"),
+        event = event_col.as_deref().unwrap_or("NULL"),
+        duration = duration_col.as_deref().unwrap_or("NULL"),
+    );
+    let mut stmt = signal.prepare(&query)?;
+    let rows = stmt.query_map([], |row| {
+        let message_id: i64 = row.get(0)?;
+        let call_type: Option<i64> = row.get(1)?;
+        let direction: Option<i64> = row.get(2)?;
+        let event: Option<i64> = row.get(3)?;
+        let duration_ms: Option<i64> = row.get(4)?;
+        Ok((message_id, call_type, direction, event, duration_ms))
+    })?;
+    let mut calls = HashMap::new();
+    for row in rows {
+        let (message_id, call_type, direction, event, duration_ms) = row?;
+        let is_group = matches!(call_type, Some(CALL_TYPE_GROUP | CALL_TYPE_AD_HOC));
+        let direction = if matches!(event, Some(CALL_EVENT_MISSED | CALL_EVENT_MISSED_NOTIFICATION_PROFILE)) {
+            "missed"
+        } else if direction == Some(CALL_DIRECTION_OUTGOING) {
+            "outgoing"
+        } else if direction.is_some() {
+            "incoming"
+        } else if is_group {
+            "group"
+        } else {
+            continue;
+        };
+        calls.insert(
+            message_id,
+            CallInfo {
+                direction,
+                is_video: call_type == Some(CALL_TYPE_VIDEO) || is_group,
+                duration_ms,
+            },
+        );
+    }
+    Ok(calls)
+}
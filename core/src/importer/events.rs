@@ -0,0 +1,155 @@
+const BASE_TYPE_MASK: u64 = 0x1F;
+
+// Base types for events Signal inserts into the timeline itself.
+const JOINED_TYPE: u64 = 4;
+const PROFILE_CHANGE_TYPE: u64 = 7;
+const GV1_MIGRATION_TYPE: u64 = 9;
+const CHANGE_NUMBER_TYPE: u64 = 14;
+
+// Flag bits layered on top of the base type.
+const KEY_EXCHANGE_IDENTITY_UPDATE_BIT: u64 = 0x200;
+const KEY_EXCHANGE_IDENTITY_DEFAULT_BIT: u64 = 0x2000;
+const KEY_EXCHANGE_IDENTITY_VERIFIED_BIT: u64 = 0x4000;
+const GROUP_UPDATE_BIT: u64 = 0x10000;
+const GROUP_LEAVE_BIT: u64 = 0x20000;
+const EXPIRATION_TIMER_UPDATE_BIT: u64 = 0x40000;
+const END_SESSION_BIT: u64 = 0x400000;
+
+/// A timeline event decoded from a message's type bits. `text` is what the archive shows in
+/// place of the body and is stored as `event_text` in `metadata_json`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct SystemEvent {
+    pub message_type: &'static str,
+    pub text: String,
+}
+
+/// Decodes group updates, member leaves, disappearing-message timer changes, identity-key
+/// changes and the other events Signal records as messages. Ordinary messages and calls
+/// give `None`. `expires_in_ms` is the message's timer, used to describe timer changes.
+pub(super) fn system_event(msg_type: i64, expires_in_ms: Option<i64>) -> Option<SystemEvent> {
+    let bits = msg_type as u64;
+    let (message_type, text) = if bits & GROUP_UPDATE_BIT != 0 && bits & GROUP_LEAVE_BIT != 0 {
+        ("group_leave", "A member left the group".to_string())
+    } else if bits & GROUP_UPDATE_BIT != 0 {
+        ("group_update", "The group was updated".to_string())
+    } else if bits & EXPIRATION_TIMER_UPDATE_BIT != 0 {
+        ("expiration_timer", expiration_text(expires_in_ms))
+    } else if bits & KEY_EXCHANGE_IDENTITY_UPDATE_BIT != 0 {
+        ("identity_change", "Safety number changed".to_string())
+    } else if bits & KEY_EXCHANGE_IDENTITY_VERIFIED_BIT != 0 {
+        ("identity_verified", "Safety number marked as verified".to_string())
+    } else if bits & KEY_EXCHANGE_IDENTITY_DEFAULT_BIT != 0 {
+        ("identity_verified", "Safety number marked as not verified".to_string())
+    } else if bits & END_SESSION_BIT != 0 {
+        ("session_reset", "Secure session reset".to_string())
+    } else {
+        match bits & BASE_TYPE_MASK {
+            JOINED_TYPE => ("joined", "Joined Signal".to_string()),
+            PROFILE_CHANGE_TYPE => ("profile_change", "Changed their profile name".to_string()),
+            GV1_MIGRATION_TYPE => ("group_update", "The group was upgraded to a new group".to_string()),
+            CHANGE_NUMBER_TYPE => ("number_change", "Changed their phone number".to_string()),
+            _ => return None,
+        }
+    };
+    Some(SystemEvent { message_type, text })
+}
+
+fn expiration_text(expires_in_ms: Option<i64>) -> String {
+    let seconds = expires_in_ms.unwrap_or(0) / 1000;
+    if seconds <= 0 {
+        return "Disappearing messages turned off".to_string();
+    }
+    let (amount, unit) = if seconds % (7 * 86_400) == 0 {
+        (seconds / (7 * 86_400), "week")
+    } else if seconds % 86_400 == 0 {
+        (seconds / 86_400, "day")
+    } else if seconds % 3_600 == 0 {
+        (seconds / 3_600, "hour")
+    } else if seconds % 60 == 0 {
+        (seconds / 60, "minute")
+    } else {
+        (seconds, "second")
+    };
+    let plural = if amount == 1 { "" } else { "s" };
+    format!("Disappearing messages set to {} {}{}", amount, unit, plural)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE_INBOX_TYPE: i64 = 20;
+    const BASE_SENT_TYPE: i64 = 23;
+    const SECURE_PUSH_BITS: i64 = 0x800000 | 0x200000;
+    const GROUP_V2_BIT: i64 = 0x80000;
+
+    #[test]
+    fn decodes_known_type_constants() {
+        let cases: &[(i64, Option<i64>, Option<&str>, &str)] = &[
+            (BASE_INBOX_TYPE | SECURE_PUSH_BITS, None, None, ""),
+            (BASE_SENT_TYPE | SECURE_PUSH_BITS, None, None, ""),
+            (1, None, None, ""),
+            (11, None, None, ""),
+            (
+                BASE_INBOX_TYPE | SECURE_PUSH_BITS | GROUP_V2_BIT | GROUP_UPDATE_BIT as i64,
+                None,
+                Some("group_update"),
+                "The group was updated",
+            ),
+            (
+                BASE_SENT_TYPE | SECURE_PUSH_BITS | GROUP_UPDATE_BIT as i64,
+                None,
+                Some("group_update"),
+                "The group was updated",
+            ),
+            (
+                BASE_INBOX_TYPE | GROUP_V2_BIT | GROUP_UPDATE_BIT as i64 | GROUP_LEAVE_BIT as i64,
+                None,
+                Some("group_leave"),
+                "A member left the group",
+            ),
+            (
+                BASE_SENT_TYPE | SECURE_PUSH_BITS | EXPIRATION_TIMER_UPDATE_BIT as i64,
+                Some(604_800_000),
+                Some("expiration_timer"),
+                "Disappearing messages set to 1 week",
+            ),
+            (
+                BASE_INBOX_TYPE | EXPIRATION_TIMER_UPDATE_BIT as i64,
+                Some(0),
+                Some("expiration_timer"),
+                "Disappearing messages turned off",
+            ),
+            (
+                BASE_INBOX_TYPE | EXPIRATION_TIMER_UPDATE_BIT as i64,
+                Some(30_000),
+                Some("expiration_timer"),
+                "Disappearing messages set to 30 seconds",
+            ),
+            (
+                BASE_INBOX_TYPE | KEY_EXCHANGE_IDENTITY_UPDATE_BIT as i64,
+                None,
+                Some("identity_change"),
+                "Safety number changed",
+            ),
+            (
+                BASE_SENT_TYPE | KEY_EXCHANGE_IDENTITY_VERIFIED_BIT as i64,
+                None,
+                Some("identity_verified"),
+                "Safety number marked as verified",
+            ),
+            (BASE_INBOX_TYPE | END_SESSION_BIT as i64, None, Some("session_reset"), "Secure session reset"),
+            (JOINED_TYPE as i64, None, Some("joined"), "Joined Signal"),
+            (PROFILE_CHANGE_TYPE as i64, None, Some("profile_change"), "Changed their profile name"),
+            (GV1_MIGRATION_TYPE as i64, None, Some("group_update"), "The group was upgraded to a new group"),
+            (CHANGE_NUMBER_TYPE as i64, None, Some("number_change"), "Changed their phone number"),
+        ];
+        for &(msg_type, expires_in, expected_type, expected_text) in cases {
+            let event = system_event(msg_type, expires_in);
+            assert_eq!(event.as_ref().map(|e| e.message_type), expected_type, "type {:#x}", msg_type);
+            if let Some(event) = event {
+                assert_eq!(event.text, expected_text, "type {:#x}", msg_type);
+            }
+        }
+    }
+}
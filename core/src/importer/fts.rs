@@ -1,24 +1,40 @@
 use std::time::Instant;
 
+use rusqlite::{params, Connection, OptionalExtension};
+
 use crate::error::CoreError;
+use crate::models::{ImportProgress, ImportStage};
+
+/// Version of the `message_fts` layout (columns + tokenizer options).
+///
+/// Bump this whenever a migration recreates `message_fts`; archives whose stored version
+/// differs get their index rebuilt from `messages` the next time they are opened.
+pub(crate) const FTS_SCHEMA_VERSION: i64 = 5;
+const FTS_VERSION_KEY: &str = "fts_schema_version";
+const FTS_INSERT_TRIGGER: &str = "trg_messages_fts_insert";
+
+/// Messages worth indexing: a non-empty body or a non-empty `metadata_json.quote_body`.
+/// Must stay in step with the `trg_messages_fts_*` triggers in migrations.
+const INDEXED_MESSAGES_SQL: &str = "SELECT rowid, id, thread_id, sender_id, body, quote_body FROM ( \
+       SELECT rowid, id, thread_id, sender_id, body, \
+              CASE WHEN json_valid(metadata_json) THEN json_extract(metadata_json, '$.quote_body') END AS quote_body \
+       FROM messages \
+     ) \
+     WHERE length(trim(COALESCE(body, ''))) > 0 OR length(trim(COALESCE(quote_body, ''))) > 0";
 
-pub(super) fn build_message_fts<F>(tx: &rusqlite::Transaction, progress: &F) -> Result<(), CoreError>
+pub(crate) fn build_message_fts<F>(conn: &Connection, progress: &F) -> Result<(), CoreError>
 where
-    F: Fn(&str),
+    F: Fn(ImportProgress),
 {
-    progress("Building search index...");
+    progress(ImportProgress::new(ImportStage::Fts));
     let build_start = Instant::now();
-    tx.execute("DELETE FROM message_fts;", [])?;
+    conn.execute("DELETE FROM message_fts;", [])?;
 
-    let total: i64 = tx
-        .query_row(
-            "SELECT COUNT(1) FROM messages WHERE body IS NOT NULL AND length(trim(body)) > 0;",
-            [],
-            |row| row.get(0),
-        )
+    let total: i64 = conn
+        .query_row(&format!("SELECT COUNT(1) FROM ({});", INDEXED_MESSAGES_SQL), [], |row| row.get(0))
         .unwrap_or(0);
 
-    let max_rowid: i64 = tx
+    let max_rowid: i64 = conn
         .query_row("SELECT COALESCE(MAX(rowid), 0) FROM messages;", [], |row| row.get(0))
         .unwrap_or(0);
 
@@ -27,29 +43,94 @@ where
     let mut start: i64 = 0;
     while start < max_rowid {
         let end = start + batch_size;
-        tx.execute(
-            "INSERT INTO message_fts (message_id, thread_id, sender_id, body)
-             SELECT id, thread_id, sender_id, body
-             FROM messages
-             WHERE rowid > ?1 AND rowid <= ?2
-               AND body IS NOT NULL AND length(trim(body)) > 0;",
+        conn.execute(
+            &format!(
+                "INSERT INTO message_fts (rowid, message_id, thread_id, sender_id, body, quote_body)
+                 SELECT * FROM ({}) WHERE rowid > ?1 AND rowid <= ?2;",
+                INDEXED_MESSAGES_SQL
+            ),
             rusqlite::params![start, end],
         )?;
-        inserted += tx.changes() as i64;
+        inserted += conn.changes() as i64;
         if total > 0 {
-            let msg = format!("Building search index... {}/{}", inserted, total);
-            progress(&msg);
+            progress(ImportProgress::counted(ImportStage::Fts, inserted as u64, total as u64));
         }
         start = end;
     }
 
     let build_secs = build_start.elapsed().as_secs_f32();
-    progress(&format!("Search index built in {:.1}s", build_secs));
+    progress(
+        ImportProgress::counted(ImportStage::Fts, inserted as u64, total as u64)
+            .with_detail(format!("built in {:.1}s", build_secs)),
+    );
 
     let optimize_start = Instant::now();
-    tx.execute("INSERT INTO message_fts(message_fts) VALUES('optimize');", [])?;
+    conn.execute("INSERT INTO message_fts(message_fts) VALUES('optimize');", [])?;
     let optimize_secs = optimize_start.elapsed().as_secs_f32();
-    progress(&format!("Search index optimized in {:.1}s", optimize_secs));
+    progress(
+        ImportProgress::counted(ImportStage::Fts, inserted as u64, total as u64)
+            .with_detail(format!("optimized in {:.1}s", optimize_secs)),
+    );
 
+    conn.execute(
+        "INSERT INTO archive_meta (key, value) VALUES (?1, ?2)
+         ON CONFLICT(key) DO UPDATE SET value = excluded.value;",
+        params![FTS_VERSION_KEY, FTS_SCHEMA_VERSION.to_string()],
+    )?;
+
+    Ok(())
+}
+
+/// Drops and rebuilds the whole search index from `messages` in one transaction.
+///
+/// Normal imports keep the index current through triggers; this is the recovery path
+/// for archives whose index is missing or out of step with `messages`.
+pub fn rebuild_fts<F>(conn: &Connection, progress: &F) -> Result<(), CoreError>
+where
+    F: Fn(ImportProgress),
+{
+    let tx = conn.unchecked_transaction()?;
+    build_message_fts(&tx, progress)?;
+    tx.commit()?;
     Ok(())
 }
+
+/// Whether the sync triggers are installed and the index covers every message with a body.
+///
+/// Used after an import to decide if the trigger-maintained index can be trusted or a
+/// full rebuild is needed (e.g. the index was empty before the triggers existed).
+pub(crate) fn message_fts_in_sync(conn: &Connection) -> Result<bool, CoreError> {
+    let trigger: Option<String> = conn
+        .query_row(
+            "SELECT name FROM sqlite_master WHERE type = 'trigger' AND name = ?1;",
+            params![FTS_INSERT_TRIGGER],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if trigger.is_none() {
+        return Ok(false);
+    }
+    let indexed: i64 = conn.query_row("SELECT COUNT(1) FROM message_fts;", [], |row| row.get(0))?;
+    let expected: i64 = conn.query_row(&format!("SELECT COUNT(1) FROM ({});", INDEXED_MESSAGES_SQL), [], |row| {
+        row.get(0)
+    })?;
+    Ok(indexed == expected)
+}
+
+/// Rebuilds `message_fts` when the archive was indexed with an older FTS layout.
+///
+/// Runs after migrations so archives created before a tokenizer/column change are
+/// reindexed transparently on open.
+pub(crate) fn ensure_message_fts_current(conn: &Connection) -> Result<(), CoreError> {
+    let stored: Option<String> = conn
+        .query_row(
+            "SELECT value FROM archive_meta WHERE key = ?1;",
+            params![FTS_VERSION_KEY],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if stored.as_deref() == Some(FTS_SCHEMA_VERSION.to_string().as_str()) {
+        return Ok(());
+    }
+    rebuild_fts(conn, &|_| {})
+}
@@ -0,0 +1,216 @@
+//! Resolves a backup's recipients, threads and messages to the archive's own ids. Signal
+//! renumbers `recipient._id`, `thread._id` and message `_id`s over time, so two backups of
+//! the same phone can use different numbers for one person; recipients are matched on their
+//! ACI, phone number or group id instead, recorded in `recipient_identities`, and messages on
+//! their `dedupe_key`, which is built from the message rather than its row number.
+
+use std::collections::HashMap;
+
+use rusqlite::{params, OptionalExtension, Transaction};
+use uuid::Uuid;
+
+use crate::error::CoreError;
+
+use super::MessageRowData;
+
+/// Stable identities for a backup recipient, strongest first.
+pub(super) fn recipient_identities(aci: Option<&str>, e164: Option<&str>, group_id: Option<&str>) -> Vec<String> {
+    let mut identities = Vec::new();
+    if let Some(aci) = aci.map(str::trim).filter(|s| !s.is_empty()) {
+        identities.push(format!("aci:{}", aci.to_ascii_lowercase()));
+    }
+    if let Some(e164) = e164.map(str::trim).filter(|s| !s.is_empty()) {
+        identities.push(format!("e164:{}", e164));
+    }
+    if let Some(group_id) = group_id.map(str::trim).filter(|s| !s.is_empty()) {
+        identities.push(format!("group:{}", group_id));
+    }
+    identities
+}
+
+/// Backup recipient and thread ids mapped to archive ids for one import. Messages are
+/// only listed when their archive id is not the usual `sms:`/`mms:` plus their `_id`.
+#[derive(Default)]
+pub(super) struct IdentityMap {
+    recipients: HashMap<i64, String>,
+    threads: HashMap<i64, String>,
+    messages: HashMap<String, String>,
+}
+
+impl IdentityMap {
+    /// The archive id for a backup recipient; unmapped ids keep their number.
+    pub fn recipient(&self, id: i64) -> String {
+        self.recipients.get(&id).cloned().unwrap_or_else(|| id.to_string())
+    }
+
+    /// The archive id for a backup thread; unmapped ids keep their number.
+    pub fn thread(&self, id: i64) -> String {
+        self.threads.get(&id).cloned().unwrap_or_else(|| id.to_string())
+    }
+
+    /// The archive id for backup message `id` of `kind` (`sms` or `mms`); unmapped ids keep
+    /// the form `kind:id`.
+    pub fn message(&self, kind: &str, id: i64) -> String {
+        let source_id = format!("{}:{}", kind, id);
+        self.messages.get(&source_id).cloned().unwrap_or(source_id)
+    }
+
+    /// Loads the messages an earlier, interrupted run of `import_id` stored under other ids.
+    pub fn load_messages(&mut self, tx: &Transaction, import_id: Option<&str>) -> Result<(), CoreError> {
+        let Some(import_id) = import_id else {
+            return Ok(());
+        };
+        let mut stmt = tx.prepare("SELECT source_id, message_id FROM import_message_ids WHERE import_id = ?1;")?;
+        let rows = stmt.query_map(params![import_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        for row in rows {
+            let (source_id, message_id) = row?;
+            self.messages.insert(source_id, message_id);
+        }
+        Ok(())
+    }
+
+    /// Finds or allocates the archive message for backup message `id` of `kind`. Returns
+    /// `None` when a message with `dedupe_key` is already in the archive or in `pending`
+    /// (rows not yet inserted), mapping the backup row to it. Otherwise returns the id to
+    /// insert it under: `kind:id`, unless another message already holds that. Ids other
+    /// than `kind:id` are recorded under `import_id`, so a resumed run finds them.
+    pub fn resolve_message(
+        &mut self,
+        tx: &Transaction,
+        import_id: Option<&str>,
+        kind: &str,
+        id: i64,
+        dedupe_key: &str,
+        pending: &[MessageRowData],
+    ) -> Result<Option<String>, CoreError> {
+        let existing = match pending.iter().find(|row| row.dedupe_key == dedupe_key) {
+            Some(row) => Some(row.id.clone()),
+            None => tx
+                .query_row(
+                    "SELECT id FROM messages WHERE dedupe_key = ?1;",
+                    params![dedupe_key],
+                    |row| row.get::<_, String>(0),
+                )
+                .optional()?,
+        };
+        let source_id = format!("{}:{}", kind, id);
+        let (archive_id, insert) = match existing {
+            Some(existing) => (existing, false),
+            None => {
+                let taken: bool = tx.query_row(
+                    "SELECT EXISTS(SELECT 1 FROM messages WHERE id = ?1);",
+                    params![source_id],
+                    |row| row.get(0),
+                )?;
+                if taken {
+                    (format!("{}:{}", kind, Uuid::new_v4()), true)
+                } else {
+                    (source_id.clone(), true)
+                }
+            }
+        };
+        if archive_id != source_id {
+            if let Some(import_id) = import_id {
+                tx.execute(
+                    "INSERT OR REPLACE INTO import_message_ids (import_id, source_id, message_id) VALUES (?1, ?2, ?3);",
+                    params![import_id, source_id, archive_id],
+                )?;
+            }
+            self.messages.insert(source_id, archive_id.clone());
+        }
+        Ok(insert.then_some(archive_id))
+    }
+
+    /// Finds or allocates the archive recipient for backup recipient `id`. A recipient
+    /// already known by any of `identities` is reused; otherwise the backup's number is
+    /// kept unless another person already holds it.
+    pub fn resolve_recipient(
+        &mut self,
+        tx: &Transaction,
+        id: i64,
+        identities: &[String],
+    ) -> Result<String, CoreError> {
+        let mut archive_id = None;
+        for identity in identities {
+            archive_id = tx
+                .query_row(
+                    "SELECT recipient_id FROM recipient_identities WHERE identity = ?1;",
+                    params![identity],
+                    |row| row.get::<_, String>(0),
+                )
+                .optional()?;
+            if archive_id.is_some() {
+                break;
+            }
+        }
+        let archive_id = match archive_id {
+            Some(archive_id) => archive_id,
+            None => {
+                let candidate = id.to_string();
+                let taken: bool = tx.query_row(
+                    "SELECT EXISTS(SELECT 1 FROM recipient_identities WHERE recipient_id = ?1);",
+                    params![candidate],
+                    |row| row.get(0),
+                )?;
+                // A number held by an identified recipient belongs to someone else. Numbers
+                // held by recipients with no identity are reused, as before identities existed.
+                if taken && !identities.is_empty() {
+                    format!("r:{}", Uuid::new_v4())
+                } else {
+                    candidate
+                }
+            }
+        };
+        for identity in identities {
+            tx.execute(
+                "INSERT OR IGNORE INTO recipient_identities (identity, recipient_id) VALUES (?1, ?2);",
+                params![identity, archive_id],
+            )?;
+        }
+        self.recipients.insert(id, archive_id.clone());
+        Ok(archive_id)
+    }
+
+    /// Finds or allocates the archive thread for backup thread `id`, whose conversation is
+    /// with archive recipient `recipient_id` (a person, or a group for group threads). An
+    /// existing thread with exactly that member is reused.
+    pub fn resolve_thread(
+        &mut self,
+        tx: &Transaction,
+        id: i64,
+        recipient_id: Option<&str>,
+    ) -> Result<String, CoreError> {
+        let existing = match recipient_id {
+            Some(recipient_id) => tx
+                .query_row(
+                    "SELECT tm.thread_id FROM thread_members tm
+                     WHERE tm.recipient_id = ?1
+                       AND (SELECT COUNT(1) FROM thread_members other WHERE other.thread_id = tm.thread_id) = 1
+                     ORDER BY tm.thread_id
+                     LIMIT 1;",
+                    params![recipient_id],
+                    |row| row.get::<_, String>(0),
+                )
+                .optional()?,
+            None => None,
+        };
+        let archive_id = match existing {
+            Some(thread_id) => thread_id,
+            None => {
+                let candidate = id.to_string();
+                let taken: bool = tx.query_row(
+                    "SELECT EXISTS(SELECT 1 FROM thread_members WHERE thread_id = ?1);",
+                    params![candidate],
+                    |row| row.get(0),
+                )?;
+                if taken && recipient_id.is_some() {
+                    format!("t:{}", Uuid::new_v4())
+                } else {
+                    candidate
+                }
+            }
+        };
+        self.threads.insert(id, archive_id.clone());
+        Ok(archive_id)
+    }
+}
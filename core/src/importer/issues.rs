@@ -0,0 +1,65 @@
+//! Rows an import skipped or could only partly bring in, kept in `import_issues` so a
+//! count like `attachments_missing` can be traced back to the source rows behind it.
+
+use rusqlite::{params, Connection};
+
+use crate::error::CoreError;
+
+/// Issues kept per import; a backup with more of one problem than this is summarized by
+/// its stats counts anyway.
+pub(super) const MAX_ISSUES_PER_IMPORT: i64 = 5000;
+
+pub(super) struct ImportIssues {
+    import_id: Option<String>,
+    remaining: i64,
+}
+
+impl ImportIssues {
+    /// Records nothing, for mapping runs without an `imports` row.
+    pub fn untracked() -> Self {
+        ImportIssues {
+            import_id: None,
+            remaining: 0,
+        }
+    }
+
+    /// Continues the issue list of `import_id`, counting what an interrupted earlier run
+    /// already recorded against the cap.
+    pub fn for_import(conn: &Connection, import_id: Option<&str>) -> Result<Self, CoreError> {
+        let Some(import_id) = import_id else {
+            return Ok(ImportIssues::untracked());
+        };
+        let recorded: i64 = conn.query_row(
+            "SELECT COUNT(1) FROM import_issues WHERE import_id = ?1;",
+            params![import_id],
+            |row| row.get(0),
+        )?;
+        Ok(ImportIssues {
+            import_id: Some(import_id.to_string()),
+            remaining: (MAX_ISSUES_PER_IMPORT - recorded).max(0),
+        })
+    }
+
+    pub fn record(
+        &mut self,
+        conn: &Connection,
+        category: &str,
+        source_table: &str,
+        source_id: Option<String>,
+        detail: Option<String>,
+    ) -> Result<(), CoreError> {
+        let Some(import_id) = &self.import_id else {
+            return Ok(());
+        };
+        if self.remaining <= 0 {
+            return Ok(());
+        }
+        let inserted = conn.execute(
+            "INSERT OR IGNORE INTO import_issues (import_id, category, source_table, source_id, detail)
+             VALUES (?1, ?2, ?3, ?4, ?5);",
+            params![import_id, category, source_table, source_id, detail],
+        )?;
+        self.remaining -= inserted as i64;
+        Ok(())
+    }
+}
@@ -0,0 +1,77 @@
+//! Phone number normalization for recipient import. Backups mix "+15550001111",
+//! "555-000-1111" and "(555) 000-1111" for the same person; reducing them to one E.164 form
+//! keeps identity matching from splitting them into separate recipients.
+
+use rusqlite::{Connection, OptionalExtension};
+
+use crate::error::CoreError;
+
+use super::table_exists;
+
+/// Two-digit ITU calling codes; 1 and 7 are one digit and every other code three.
+const TWO_DIGIT_CALLING_CODES: &[&str] = &[
+    "20", "27", "30", "31", "32", "33", "34", "36", "39", "40", "41", "43", "44", "45", "46", "47", "48", "49",
+    "51", "52", "53", "54", "55", "56", "57", "58", "60", "61", "62", "63", "64", "65", "66", "81", "82", "84",
+    "86", "90", "91", "92", "93", "94", "95", "98",
+];
+
+/// Normalizes `raw` to `+` and digits. Numbers without a country code get
+/// `default_country_code` (digits only, e.g. "1"), dropping a national trunk `0`; without
+/// one they are kept as bare digits. Anything that isn't a phone number (emails, usernames)
+/// is returned trimmed and unchanged.
+pub(super) fn normalize_phone(raw: &str, default_country_code: Option<&str>) -> Option<String> {
+    let trimmed = raw.trim();
+    if trimmed.is_empty() {
+        return None;
+    }
+    let is_phone = trimmed
+        .chars()
+        .all(|c| c.is_ascii_digit() || matches!(c, '+' | '-' | ' ' | '(' | ')' | '.' | '/'));
+    if !is_phone {
+        return Some(trimmed.to_string());
+    }
+    let digits: String = trimmed.chars().filter(char::is_ascii_digit).collect();
+    if digits.is_empty() {
+        return None;
+    }
+    if trimmed.starts_with('+') {
+        return Some(format!("+{}", digits));
+    }
+    if let Some(international) = digits.strip_prefix("00").filter(|rest| rest.len() > 6) {
+        return Some(format!("+{}", international));
+    }
+    match default_country_code {
+        // NANP numbers are often written with the leading 1 but no plus.
+        Some("1") if digits.len() == 11 && digits.starts_with('1') => Some(format!("+{}", digits)),
+        Some(code) if digits.len() > 6 => Some(format!("+{}{}", code, digits.strip_prefix('0').unwrap_or(&digits))),
+        _ => Some(digits),
+    }
+}
+
+/// The calling code of an E.164 number, e.g. "1" for "+15550001111".
+pub(super) fn calling_code(e164: &str) -> Option<String> {
+    let digits = e164.strip_prefix('+')?;
+    if digits.len() < 4 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let len = if digits.starts_with('1') || digits.starts_with('7') {
+        1
+    } else if TWO_DIGIT_CALLING_CODES.contains(&&digits[..2]) {
+        2
+    } else {
+        3
+    };
+    Some(digits[..len].to_string())
+}
+
+/// The backup owner's calling code, from their own number in the `key_value` table.
+pub(super) fn owner_calling_code(signal: &Connection) -> Result<Option<String>, CoreError> {
+    if !table_exists(signal, "key_value")? {
+        return Ok(None);
+    }
+    let own_number: Option<String> = signal
+        .query_row("SELECT CAST(value AS TEXT) FROM key_value WHERE key = 'account.e164';", [], |row| row.get(0))
+        .optional()?
+        .flatten();
+    Ok(own_number.and_then(|number| normalize_phone(&number, None)).and_then(|e164| calling_code(&e164)))
+}
@@ -0,0 +1,208 @@
+//! Link previews attached to MMS messages. Older schemas keep them as a JSON array in the
+//! message table's `previews` column, newer ones in `link_previews`, and some builds in a
+//! separate `link_preview` table keyed by message. The preview image is an ordinary part row,
+//! imported by [`super::attachments::map_attachments`]; this only recovers the card text and
+//! which stored attachment is its thumbnail.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::error::CoreError;
+
+use super::attachments::part_path;
+use super::identity::IdentityMap;
+use super::{pick_column, table_exists};
+
+/// One preview card. `part_id` is the `_id` of the part holding its thumbnail, if any.
+#[derive(Debug, Clone)]
+pub(super) struct LinkPreview {
+    pub url: String,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub part_id: Option<i64>,
+}
+
+/// Previews keyed by MMS message `_id`.
+pub(super) fn link_previews(
+    signal: &Connection,
+    mms_table: &str,
+) -> Result<HashMap<i64, Vec<LinkPreview>>, CoreError> {
+    let mut previews: HashMap<i64, Vec<LinkPreview>> = HashMap::new();
+    if let Some(col) = pick_column(signal, mms_table, &["link_previews", "previews"])? {
+        let mut stmt = signal.prepare(&format!(
+            "SELECT _id, {col} FROM {mms_table} WHERE {col} IS NOT NULL AND {col} != '';",
+        ))?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?;
+        for row in rows {
+            let (id, json) = row?;
+            let parsed = parse_previews_json(&json);
+            if !parsed.is_empty() {
+                previews.entry(id).or_default().extend(parsed);
+            }
+        }
+    }
+
+    let table = if table_exists(signal, "link_preview")? {
+        "link_preview"
+    } else if table_exists(signal, "link_previews")? {
+        "link_previews"
+    } else {
+        return Ok(previews);
+    };
+    let msg_col = pick_column(signal, table, &["message_id", "mms_id"])?;
+    let url_col = pick_column(signal, table, &["url"])?;
+    let (msg_col, url_col) = match (msg_col, url_col) {
+        (Some(msg_col), Some(url_col)) => (msg_col, url_col),
+        _ => return Ok(previews),
+    };
+    let title_col = pick_column(signal, table, &["title"])?.unwrap_or_else(|| "NULL".to_string());
+    let description_col =
+        pick_column(signal, table, &["description", "desc"])?.unwrap_or_else(|| "NULL".to_string());
+    let part_col =
+        pick_column(signal, table, &["attachment_id", "part_id"])?.unwrap_or_else(|| "NULL".to_string());
+    let mut stmt = signal.prepare(&format!(
+        "SELECT {msg_col}, {url_col}, {title_col}, {description_col}, {part_col} FROM {table} ORDER BY _ROWID_;",
+    ))?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, Option<i64>>(0)?,
+            row.get::<_, Option<String>>(1)?,
+            row.get::<_, Option<String>>(2)?,
+            row.get::<_, Option<String>>(3)?,
+            row.get::<_, Option<i64>>(4)?,
+        ))
+    })?;
+    for row in rows {
+        let (mid, url, title, description, part_id) = row?;
+        let (Some(mid), Some(url)) = (mid, url.filter(|u| !u.trim().is_empty())) else {
+            continue;
+        };
+        previews.entry(mid).or_default().push(LinkPreview {
+            url,
+            title: non_empty(title),
+            description: non_empty(description),
+            part_id,
+        });
+    }
+    Ok(previews)
+}
+
+/// Accepts a bare array or `{"previews": [...]}`. `attachmentId` is `{rowId, uniqueId}` in
+/// most versions and a plain row id in a few.
+fn parse_previews_json(json: &str) -> Vec<LinkPreview> {
+    let value: Value = match serde_json::from_str(json) {
+        Ok(value) => value,
+        Err(_) => return Vec::new(),
+    };
+    let items = match &value {
+        Value::Array(items) => items.as_slice(),
+        Value::Object(obj) => obj.get("previews").and_then(Value::as_array).map_or(&[][..], Vec::as_slice),
+        _ => &[],
+    };
+    items
+        .iter()
+        .filter_map(|item| {
+            let url = item.get("url").and_then(Value::as_str).filter(|u| !u.trim().is_empty())?;
+            let text = |key: &str| non_empty(item.get(key).and_then(Value::as_str).map(str::to_string));
+            let part_id = match item.get("attachmentId") {
+                Some(Value::Object(id)) => id.get("rowId").and_then(Value::as_i64),
+                Some(id) => id.as_i64(),
+                None => None,
+            };
+            Some(LinkPreview {
+                url: url.to_string(),
+                title: text("title"),
+                description: text("description"),
+                part_id,
+            })
+        })
+        .collect()
+}
+
+fn non_empty(text: Option<String>) -> Option<String> {
+    text.filter(|t| !t.trim().is_empty())
+}
+
+/// Writes `link_previews` into each message's `metadata_json`. Runs after attachments are
+/// imported: a thumbnail's `attachment_sha` is the plaintext SHA-256 the attachment store
+/// keys it by, and is only recorded when that attachment was actually stored for the
+/// message. Returns the number of messages updated.
+pub(super) fn apply_link_previews(
+    signal: &Connection,
+    tx: &rusqlite::Transaction,
+    export_dir: &Path,
+    previews: &HashMap<i64, Vec<LinkPreview>>,
+    ids: &IdentityMap,
+) -> Result<i64, CoreError> {
+    if previews.is_empty() {
+        return Ok(0);
+    }
+    let part_unique = match ["part", "attachment"].into_iter().find(|t| table_exists(signal, t).unwrap_or(false)) {
+        Some(table) => pick_column(signal, table, &["unique_id"])?.map(|col| (table, col)),
+        None => None,
+    };
+    let mut stored = tx.prepare("SELECT EXISTS(SELECT 1 FROM attachments WHERE message_id = ?1 AND sha256 = ?2);")?;
+    let mut read_meta = tx.prepare("SELECT metadata_json FROM messages WHERE id = ?1;")?;
+    let mut write_meta = tx.prepare("UPDATE messages SET metadata_json = ?2 WHERE id = ?1;")?;
+
+    let mut updated = 0;
+    for (mid, cards) in previews {
+        let message_id = ids.message("mms", *mid);
+        let existing: Option<Option<String>> =
+            read_meta.query_row(params![message_id], |row| row.get(0)).optional()?;
+        let Some(existing) = existing else {
+            continue;
+        };
+        let mut entries = Vec::with_capacity(cards.len());
+        for card in cards {
+            let mut sha = None;
+            if let Some(part_id) = card.part_id {
+                let unique_id = match &part_unique {
+                    Some((table, col)) => signal
+                        .query_row(&format!("SELECT {col} FROM {table} WHERE _id = ?1;"), [part_id], |row| {
+                            row.get::<_, Option<i64>>(0)
+                        })
+                        .optional()?
+                        .flatten(),
+                    None => None,
+                };
+                sha = hash_file(&part_path(export_dir, part_id, unique_id))
+                    .filter(|sha| stored.query_row(params![message_id, sha], |row| row.get(0)).unwrap_or(false));
+            }
+            entries.push(serde_json::json!({
+                "url": card.url,
+                "title": card.title,
+                "description": card.description,
+                "attachment_sha": sha,
+            }));
+        }
+        let mut metadata = existing
+            .as_deref()
+            .and_then(|json| serde_json::from_str::<serde_json::Map<String, Value>>(json).ok())
+            .unwrap_or_default();
+        metadata.insert("link_previews".to_string(), Value::Array(entries));
+        write_meta.execute(params![message_id, Value::Object(metadata).to_string()])?;
+        updated += 1;
+    }
+    Ok(updated)
+}
+
+fn hash_file(path: &Path) -> Option<String> {
+    let mut file = fs::File::open(path).ok()?;
+    let mut hasher = Sha256::new();
+    let mut buf = [0u8; 8192];
+    loop {
+        let n = file.read(&mut buf).ok()?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Some(hex::encode(hasher.finalize()))
+}
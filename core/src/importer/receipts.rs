@@ -0,0 +1,142 @@
+//! Delivery and read receipts on outgoing messages. The message tables carry a receipt count
+//! (`delivery_receipt_count`, `read_receipt_count`; `has_delivery_receipt`, `has_read_receipt`
+//! in newer schemas) and `receipt_timestamp`, the time of the latest receipt. Group messages
+//! additionally have one `group_receipts` row per member with that member's status and time.
+
+use std::collections::HashMap;
+
+use rusqlite::Connection;
+use serde_json::{json, Map, Value};
+
+use crate::error::CoreError;
+
+use super::{pick_column, table_exists};
+
+// `group_receipts.status` values.
+const GROUP_RECEIPT_DELIVERED: i64 = 1;
+const GROUP_RECEIPT_READ: i64 = 2;
+const GROUP_RECEIPT_VIEWED: i64 = 3;
+
+/// When a message was first delivered and first read, stored as `delivered_at` and `read_at`
+/// in `metadata_json`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(super) struct Receipt {
+    pub delivered_at: Option<i64>,
+    pub read_at: Option<i64>,
+}
+
+impl Receipt {
+    pub fn write_metadata(&self, metadata: &mut Map<String, Value>) {
+        if let Some(delivered_at) = self.delivered_at {
+            metadata.insert("delivered_at".to_string(), json!(delivered_at));
+        }
+        if let Some(read_at) = self.read_at {
+            metadata.insert("read_at".to_string(), json!(read_at));
+        }
+    }
+
+    fn merge(&mut self, other: Receipt) {
+        self.delivered_at = earliest(self.delivered_at, other.delivered_at);
+        self.read_at = earliest(self.read_at, other.read_at);
+    }
+}
+
+fn earliest(a: Option<i64>, b: Option<i64>) -> Option<i64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// Receipts for the rows of `table` (`sms` or the MMS/message table) keyed by `_id`. Only
+/// messages with a receipt time are included; a receipt count without `receipt_timestamp`
+/// says nothing about when it arrived.
+pub(super) fn receipts(signal: &Connection, table: &str) -> Result<HashMap<i64, Receipt>, CoreError> {
+    let mut receipts: HashMap<i64, Receipt> = HashMap::new();
+    if !table_exists(signal, table)? {
+        return Ok(receipts);
+    }
+    let ts_col = pick_column(signal, table, &["receipt_timestamp"])?;
+    let delivered_col = pick_column(signal, table, &["has_delivery_receipt", "delivery_receipt_count"])?;
+    let read_col = pick_column(signal, table, &["has_read_receipt", "read_receipt_count"])?;
+    if let (Some(ts_col), true) = (ts_col, delivered_col.is_some() || read_col.is_some()) {
+        let query = format!(
+            "SELECT _id, {ts}, {delivered}, {read} FROM {table} WHERE {ts} > 0 AND ({delivered} > 0 OR {read} > 0);",
+            ts = ts_col,
+            delivered = delivered_col.as_deref().unwrap_or("0"),
+            read = read_col.as_deref().unwrap_or("0"),
+            table = table,
+        );
+        let mut stmt = signal.prepare(&query)?;
+        let rows = stmt.query_map([], |row| {
+            let id: i64 = row.get(0)?;
+            let ts: i64 = row.get(1)?;
+            let delivered: Option<i64> = row.get(2)?;
+            let read: Option<i64> = row.get(3)?;
+            Ok((id, ts, delivered.unwrap_or(0) > 0, read.unwrap_or(0) > 0))
+        })?;
+        for row in rows {
+            let (id, ts, delivered, read) = row?;
+            // The timestamp is the latest receipt, so once read it no longer dates delivery.
+            let receipt = if read {
+                Receipt { delivered_at: None, read_at: Some(ts) }
+            } else if delivered {
+                Receipt { delivered_at: Some(ts), read_at: None }
+            } else {
+                continue;
+            };
+            receipts.insert(id, receipt);
+        }
+    }
+    if table != "sms" {
+        for (id, receipt) in group_receipts(signal)? {
+            receipts.entry(id).or_default().merge(receipt);
+        }
+    }
+    Ok(receipts)
+}
+
+/// The first member delivery and first member read of each group message.
+fn group_receipts(signal: &Connection) -> Result<HashMap<i64, Receipt>, CoreError> {
+    if !table_exists(signal, "group_receipts")? {
+        return Ok(HashMap::new());
+    }
+    let msg_col = pick_column(signal, "group_receipts", &["mms_id", "message_id"])?;
+    let status_col = pick_column(signal, "group_receipts", &["status"])?;
+    let ts_col = pick_column(signal, "group_receipts", &["timestamp"])?;
+    let (Some(msg_col), Some(status_col), Some(ts_col)) = (msg_col, status_col, ts_col) else {
+        return Ok(HashMap::new());
+    };
+    let query = format!(
+        "SELECT {msg}, \
+                MIN(CASE WHEN {status} IN ({delivered}, {read}, {viewed}) THEN {ts} END), \
+                MIN(CASE WHEN {status} IN ({read}, {viewed}) THEN {ts} END) \
+         FROM group_receipts \
+         WHERE {msg} IS NOT NULL AND {ts} > 0 \
+         GROUP BY {msg};",
+        msg = msg_col,
+        status = status_col,
+        ts = ts_col,
+        delivered = GROUP_RECEIPT_DELIVERED,
+        read = GROUP_RECEIPT_READ,
+        viewed = GROUP_RECEIPT_VIEWED,
+    );
+    let mut stmt = signal.prepare(&query)?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            Receipt {
+                delivered_at: row.get(1)?,
+                read_at: row.get(2)?,
+            },
+        ))
+    })?;
+    let mut receipts = HashMap::new();
+    for row in rows {
+        let (id, receipt) = row?;
+        if receipt != Receipt::default() {
+            receipts.insert(id, receipt);
+        }
+    }
+    Ok(receipts)
+}
@@ -0,0 +1,143 @@
+//! Checkpoints that let an interrupted Android import continue where it stopped. Each
+//! stage commits its own transaction together with the next stage to run and the last
+//! source row it finished (for the chunked message stages), kept in `import_checkpoints`,
+//! and the stats counted so far, kept in the import's `stats_json`. Recipients and threads
+//! are not checkpointed: they are cheap, their upserts are idempotent, and rerunning them
+//! rebuilds the id mapping the later stages need.
+
+use rusqlite::{params, Connection};
+use serde_json::{Map, Value};
+
+use crate::error::CoreError;
+
+/// Stages after recipients and threads, in the order an import runs them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(super) enum ResumeStage {
+    Sms,
+    Mms,
+    Attachments,
+    Reactions,
+    Fts,
+}
+
+impl ResumeStage {
+    fn as_str(self) -> &'static str {
+        match self {
+            ResumeStage::Sms => "sms",
+            ResumeStage::Mms => "mms",
+            ResumeStage::Attachments => "attachments",
+            ResumeStage::Reactions => "reactions",
+            ResumeStage::Fts => "fts",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "sms" => Some(ResumeStage::Sms),
+            "mms" => Some(ResumeStage::Mms),
+            "attachments" => Some(ResumeStage::Attachments),
+            "reactions" => Some(ResumeStage::Reactions),
+            "fts" => Some(ResumeStage::Fts),
+            _ => None,
+        }
+    }
+}
+
+/// How far an import has got: `stage` is the next stage to run and `offset` the last
+/// source `_id` it already committed.
+pub(super) struct ImportCheckpoint {
+    import_id: Option<String>,
+    stage: ResumeStage,
+    offset: i64,
+    stats: Map<String, Value>,
+}
+
+impl ImportCheckpoint {
+    /// A checkpoint that is never persisted, for mapping runs without an `imports` row.
+    pub fn untracked() -> Self {
+        ImportCheckpoint {
+            import_id: None,
+            stage: ResumeStage::Sms,
+            offset: 0,
+            stats: Map::new(),
+        }
+    }
+
+    /// Reads the checkpoint an earlier run of `import_id` left behind, or starts from the
+    /// beginning if it stopped before committing any stage.
+    pub fn load(conn: &Connection, import_id: &str) -> Result<Self, CoreError> {
+        let (stage, offset, stats_json): (Option<String>, Option<i64>, Option<String>) = conn.query_row(
+            "SELECT c.stage, c.resume_offset, i.stats_json
+             FROM imports i
+             LEFT JOIN import_checkpoints c ON c.import_id = i.id
+             WHERE i.id = ?1;",
+            params![import_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+        let mut checkpoint = ImportCheckpoint {
+            import_id: Some(import_id.to_string()),
+            ..ImportCheckpoint::untracked()
+        };
+        if let Some(stage) = stage.as_deref().and_then(ResumeStage::parse) {
+            checkpoint.stage = stage;
+            checkpoint.offset = offset.unwrap_or(0);
+            checkpoint.stats = stats_json
+                .and_then(|json| serde_json::from_str(&json).ok())
+                .unwrap_or_default();
+            checkpoint.stats.remove("error");
+        }
+        Ok(checkpoint)
+    }
+
+    pub fn import_id(&self) -> Option<&str> {
+        self.import_id.as_deref()
+    }
+
+    /// Whether `stage` still has work left.
+    pub fn pending(&self, stage: ResumeStage) -> bool {
+        self.stage <= stage
+    }
+
+    /// The last source row of `stage` already committed, or 0.
+    pub fn offset(&self, stage: ResumeStage) -> i64 {
+        if self.stage == stage {
+            self.offset
+        } else {
+            0
+        }
+    }
+
+    /// A counter recorded by an earlier run or stage, or 0.
+    pub fn stat(&self, key: &str) -> i64 {
+        self.stats.get(key).and_then(Value::as_i64).unwrap_or(0)
+    }
+
+    pub fn set_stat(&mut self, key: &str, value: impl Into<Value>) {
+        self.stats.insert(key.to_string(), value.into());
+    }
+
+    /// Records, inside `tx`, that everything before `offset` in `stage` is committed along
+    /// with it. Never moves the checkpoint backwards.
+    pub fn save(&mut self, tx: &rusqlite::Transaction, stage: ResumeStage, offset: i64) -> Result<(), CoreError> {
+        if (stage, offset) < (self.stage, self.offset) {
+            return Ok(());
+        }
+        self.stage = stage;
+        self.offset = offset;
+        if let Some(import_id) = &self.import_id {
+            tx.execute(
+                "INSERT OR REPLACE INTO import_checkpoints (import_id, stage, resume_offset) VALUES (?1, ?2, ?3);",
+                params![import_id, stage.as_str(), offset],
+            )?;
+            tx.execute(
+                "UPDATE imports SET stats_json = ?2 WHERE id = ?1;",
+                params![import_id, Value::Object(self.stats.clone()).to_string()],
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn into_stats(self) -> Map<String, Value> {
+        self.stats
+    }
+}
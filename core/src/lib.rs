@@ -2,12 +2,16 @@ pub mod crypto;
 pub mod db;
 pub mod diagnostics;
 pub mod error;
+pub mod export;
 pub mod ffi;
 pub mod importer;
+pub mod maintenance;
 pub mod models;
 pub mod query;
 pub mod seed;
+mod blurhash;
 mod migrations;
+mod mime;
 
 pub use db::{open_archive, ArchiveDb};
 pub use error::CoreError;
@@ -0,0 +1,382 @@
+//! Consistency checks and repairs between the `attachments` table and the encrypted blobs
+//! on disk.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, BufReader, Write};
+use std::path::Path;
+use std::sync::mpsc;
+use std::thread;
+
+use rusqlite::Connection;
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
+
+use crate::blurhash::{image_blurhash, MAX_BLURHASH_SOURCE_BYTES};
+use crate::crypto::{self, MasterKey};
+use crate::error::CoreError;
+use crate::mime::{image_dimensions, infer_kind, sniff_mime};
+use crate::models::{
+    AttachmentAuditReport, AttachmentVerifyReport, BlurhashBackfillReport, CorruptAttachment, DimensionBackfillReport,
+    GcReport, MissingAttachment, ResniffReport,
+};
+
+/// How many blobs are checked between progress callbacks.
+const AUDIT_PROGRESS_BATCH: usize = 500;
+/// Threads decrypting blobs in `verify_attachments`.
+const VERIFY_WORKERS: usize = 4;
+
+/// Checks that every distinct `attachments.sha256` has a blob in `attachments_dir` and
+/// reports the ones that don't, each with one referencing message for context.
+pub fn audit_attachments<F>(
+    conn: &Connection,
+    attachments_dir: &Path,
+    progress: F,
+) -> Result<AttachmentAuditReport, CoreError>
+where
+    F: Fn(&str),
+{
+    // With a single MIN() aggregate, SQLite takes the bare columns from that same row.
+    let mut stmt = conn.prepare(
+        "SELECT a.sha256, COUNT(1), MIN(a.id), a.message_id, m.thread_id, a.original_filename \
+         FROM attachments a \
+         LEFT JOIN messages m ON m.id = a.message_id \
+         GROUP BY a.sha256 \
+         ORDER BY a.sha256 ASC;",
+    )?;
+    let rows: Vec<MissingAttachment> = stmt
+        .query_map([], |row| {
+            Ok(MissingAttachment {
+                sha256: row.get(0)?,
+                reference_count: row.get(1)?,
+                message_id: row.get(3)?,
+                thread_id: row.get(4)?,
+                original_filename: row.get(5)?,
+            })
+        })?
+        .filter_map(Result::ok)
+        .collect();
+
+    let total = rows.len();
+    progress(&format!("Checking attachments... 0/{}", total));
+    let mut missing = Vec::new();
+    for (idx, row) in rows.into_iter().enumerate() {
+        if !attachments_dir.join(&row.sha256).is_file() {
+            missing.push(row);
+        }
+        let checked = idx + 1;
+        if checked % AUDIT_PROGRESS_BATCH == 0 || checked == total {
+            progress(&format!("Checking attachments... {}/{}", checked, total));
+        }
+    }
+
+    Ok(AttachmentAuditReport {
+        checked: total as i64,
+        missing,
+    })
+}
+
+/// Blobs are named by the hex SHA-256 of their contents. Anything else in the directory,
+/// notably the `.tmpXXXXXX` files `NamedTempFile` creates while an attachment is being
+/// written, is never a collection candidate.
+fn is_blob_name(name: &str) -> bool {
+    name.len() == 64 && name.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+/// Finds blobs in `attachments_dir` that no `attachments` row references and, unless
+/// `dry_run` is set, deletes them. Run it while no import is writing to the directory: a
+/// freshly persisted blob has no row until the import commits.
+pub fn gc_orphaned_attachments(
+    conn: &Connection,
+    attachments_dir: &Path,
+    dry_run: bool,
+) -> Result<GcReport, CoreError> {
+    let mut stmt = conn.prepare("SELECT DISTINCT sha256 FROM attachments;")?;
+    let referenced: HashSet<String> = stmt
+        .query_map([], |row| row.get(0))?
+        .filter_map(Result::ok)
+        .collect();
+
+    let mut report = GcReport::default();
+    if !attachments_dir.exists() {
+        return Ok(report);
+    }
+    let entries = fs::read_dir(attachments_dir).map_err(|e| CoreError::InvalidArgument(e.to_string()))?;
+    for entry in entries {
+        let entry = entry.map_err(|e| CoreError::InvalidArgument(e.to_string()))?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !is_blob_name(&name) || referenced.contains(&name) {
+            continue;
+        }
+        let metadata = entry.metadata().map_err(|e| CoreError::InvalidArgument(e.to_string()))?;
+        if !metadata.is_file() {
+            continue;
+        }
+        report.bytes += metadata.len() as i64;
+        if !dry_run {
+            fs::remove_file(entry.path()).map_err(|e| CoreError::InvalidArgument(e.to_string()))?;
+            report.deleted += 1;
+        }
+        report.candidates.push(name);
+    }
+    report.candidates.sort();
+    Ok(report)
+}
+
+/// Fills in `mime` and `kind` for attachments imported without a content type by sniffing
+/// the first decrypted chunk of each blob. Rows that already have a MIME type are left alone.
+pub fn resniff_attachments(
+    conn: &Connection,
+    attachments_dir: &Path,
+    key: &MasterKey,
+) -> Result<ResniffReport, CoreError> {
+    let mut stmt = conn.prepare("SELECT DISTINCT sha256 FROM attachments WHERE mime IS NULL ORDER BY sha256 ASC;")?;
+    let hashes: Vec<String> = stmt
+        .query_map([], |row| row.get(0))?
+        .filter_map(Result::ok)
+        .collect();
+
+    let mut report = ResniffReport::default();
+    let tx = conn.unchecked_transaction()?;
+    for sha256 in hashes {
+        report.checked += 1;
+        let path = attachments_dir.join(&sha256);
+        if !path.is_file() {
+            report.missing += 1;
+            continue;
+        }
+        let header = match crypto::decrypt_first_chunk(&path, key) {
+            Ok(header) => header,
+            Err(_) => {
+                report.failed += 1;
+                continue;
+            }
+        };
+        if let Some(mime) = sniff_mime(&header) {
+            let changed = tx.execute(
+                "UPDATE attachments SET mime = ?1, kind = ?2 WHERE sha256 = ?3 AND mime IS NULL;",
+                rusqlite::params![mime, infer_kind(mime), sha256],
+            )?;
+            report.updated += changed as i64;
+        }
+    }
+    tx.commit()?;
+    Ok(report)
+}
+
+/// Fills in `width` and `height` for image attachments imported without them, reading the
+/// image headers from the first decrypted chunk of each blob. Images whose headers can't be
+/// parsed (HEIC, or a frame header past the first chunk) are left as they are.
+pub fn backfill_image_dimensions(
+    conn: &Connection,
+    attachments_dir: &Path,
+    key: &MasterKey,
+) -> Result<DimensionBackfillReport, CoreError> {
+    let mut stmt = conn.prepare(
+        "SELECT DISTINCT sha256 FROM attachments \
+         WHERE kind = 'image' AND (width IS NULL OR height IS NULL) \
+         ORDER BY sha256 ASC;",
+    )?;
+    let hashes: Vec<String> = stmt
+        .query_map([], |row| row.get(0))?
+        .filter_map(Result::ok)
+        .collect();
+
+    let mut report = DimensionBackfillReport::default();
+    let tx = conn.unchecked_transaction()?;
+    for sha256 in hashes {
+        report.checked += 1;
+        let path = attachments_dir.join(&sha256);
+        if !path.is_file() {
+            report.missing += 1;
+            continue;
+        }
+        let header = match crypto::decrypt_first_chunk(&path, key) {
+            Ok(header) => header,
+            Err(_) => {
+                report.failed += 1;
+                continue;
+            }
+        };
+        if let Some((width, height)) = image_dimensions(&header) {
+            let changed = tx.execute(
+                "UPDATE attachments SET width = ?1, height = ?2 \
+                 WHERE sha256 = ?3 AND (width IS NULL OR height IS NULL);",
+                rusqlite::params![width, height, sha256],
+            )?;
+            report.updated += changed as i64;
+        }
+    }
+    tx.commit()?;
+    Ok(report)
+}
+
+/// Computes BlurHash placeholders for image blobs imported before they were recorded at
+/// import. Each blob is decrypted into memory and decoded, so images over
+/// `MAX_BLURHASH_SOURCE_BYTES` are skipped; those and undecodable ones (HEIC) count as
+/// failed and are tried again on the next run.
+pub fn backfill_blurhash<F>(
+    conn: &Connection,
+    attachments_dir: &Path,
+    key: &MasterKey,
+    progress: F,
+) -> Result<BlurhashBackfillReport, CoreError>
+where
+    F: Fn(&str),
+{
+    let mut stmt = conn.prepare(
+        "SELECT DISTINCT sha256 FROM attachments \
+         WHERE kind = 'image' AND blurhash IS NULL \
+         ORDER BY sha256 ASC;",
+    )?;
+    let hashes: Vec<String> = stmt
+        .query_map([], |row| row.get(0))?
+        .filter_map(Result::ok)
+        .collect();
+
+    let total = hashes.len();
+    let mut report = BlurhashBackfillReport::default();
+    let tx = conn.unchecked_transaction()?;
+    for sha256 in hashes {
+        report.checked += 1;
+        let path = attachments_dir.join(&sha256);
+        if !path.is_file() {
+            report.missing += 1;
+        } else {
+            match decrypt_image(&path, key).and_then(|bytes| image_blurhash(&bytes)) {
+                Some(blurhash) => {
+                    report.updated += tx.execute(
+                        "UPDATE attachments SET blurhash = ?1 WHERE sha256 = ?2 AND blurhash IS NULL;",
+                        rusqlite::params![blurhash, sha256],
+                    )? as i64;
+                }
+                None => report.failed += 1,
+            }
+        }
+        if report.checked % AUDIT_PROGRESS_BATCH as i64 == 0 || report.checked as usize == total {
+            progress(&format!("Computing image placeholders... {}/{}", report.checked, total));
+        }
+    }
+    tx.commit()?;
+    Ok(report)
+}
+
+/// The plaintext of an image blob small enough to decode for a placeholder.
+fn decrypt_image(path: &Path, key: &MasterKey) -> Option<Zeroizing<Vec<u8>>> {
+    let len = crypto::encrypted_plaintext_len(path).ok()?;
+    if len > MAX_BLURHASH_SOURCE_BYTES as u64 {
+        return None;
+    }
+    let file = fs::File::open(path).ok()?;
+    let mut plain = Zeroizing::new(Vec::with_capacity(len as usize));
+    crypto::decrypt_stream(&mut BufReader::new(file), &mut *plain, key).ok()?;
+    Some(plain)
+}
+
+/// Decrypts every referenced blob and checks that its plaintext still hashes to the
+/// `sha256` it is stored under, catching blobs truncated or damaged after they were written
+/// (a full disk mid-import, say). Blobs are streamed through the hasher chunk by chunk, so
+/// memory use does not depend on attachment size. Missing blobs are counted, not reported
+/// as corrupt; `audit_attachments` lists them.
+pub fn verify_attachments<F>(
+    conn: &Connection,
+    attachments_dir: &Path,
+    key: &MasterKey,
+    progress: F,
+) -> Result<AttachmentVerifyReport, CoreError>
+where
+    F: Fn(&str),
+{
+    let mut stmt = conn.prepare(
+        "SELECT sha256, MIN(message_id) FROM attachments GROUP BY sha256 ORDER BY sha256 ASC;",
+    )?;
+    let rows: Vec<(String, String)> = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .filter_map(Result::ok)
+        .collect();
+
+    let total = rows.len();
+    let mut report = AttachmentVerifyReport::default();
+    progress(&format!("Verifying attachments... 0/{}", total));
+    if rows.is_empty() {
+        return Ok(report);
+    }
+
+    let chunk_size = rows.len().div_ceil(VERIFY_WORKERS);
+    thread::scope(|scope| {
+        let (result_tx, result_rx) = mpsc::channel();
+        for chunk in rows.chunks(chunk_size) {
+            let worker_tx = result_tx.clone();
+            scope.spawn(move || {
+                for (sha256, message_id) in chunk {
+                    let result = verify_blob(&attachments_dir.join(sha256), sha256, key);
+                    if worker_tx.send((sha256, message_id, result)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(result_tx);
+
+        for (sha256, message_id, result) in result_rx {
+            report.checked += 1;
+            match result {
+                BlobCheck::Verified { legacy } => {
+                    report.verified += 1;
+                    report.legacy_format += legacy as i64;
+                }
+                BlobCheck::Missing => report.missing += 1,
+                BlobCheck::Corrupt(reason) => report.corrupt.push(CorruptAttachment {
+                    sha256: sha256.clone(),
+                    message_id: message_id.clone(),
+                    reason,
+                }),
+            }
+            if report.checked % AUDIT_PROGRESS_BATCH as i64 == 0 || report.checked as usize == total {
+                progress(&format!("Verifying attachments... {}/{}", report.checked, total));
+            }
+        }
+    });
+    report.corrupt.sort_by(|a, b| a.sha256.cmp(&b.sha256));
+    Ok(report)
+}
+
+enum BlobCheck {
+    Verified { legacy: bool },
+    Missing,
+    Corrupt(String),
+}
+
+fn verify_blob(path: &Path, expected: &str, key: &MasterKey) -> BlobCheck {
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return BlobCheck::Missing,
+        Err(err) => return BlobCheck::Corrupt(err.to_string()),
+    };
+    let mut hasher = HashWriter(Sha256::new());
+    if let Err(err) = crypto::decrypt_stream(&mut BufReader::new(file), &mut hasher, key) {
+        return BlobCheck::Corrupt(err.to_string());
+    }
+    let actual = hex::encode(hasher.0.finalize());
+    if actual == expected {
+        BlobCheck::Verified {
+            legacy: crypto::is_legacy_format(path).unwrap_or(false),
+        }
+    } else {
+        BlobCheck::Corrupt(format!("hash mismatch: plaintext hashes to {}", actual))
+    }
+}
+
+/// Feeds decrypted chunks straight into the hasher instead of a buffer or file.
+struct HashWriter(Sha256);
+
+impl Write for HashWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -149,4 +149,261 @@ pub const MIGRATIONS: &[&str] = &[
       WHERE id = NEW.id;
     END;
     "#,
+    r#"
+    CREATE TABLE IF NOT EXISTS archive_meta (
+      key TEXT PRIMARY KEY,
+      value TEXT NOT NULL
+    );
+
+    DROP TABLE IF EXISTS message_fts;
+    CREATE VIRTUAL TABLE message_fts USING fts5(
+      message_id UNINDEXED,
+      thread_id UNINDEXED,
+      sender_id UNINDEXED,
+      body,
+      tokenize = "unicode61 remove_diacritics 2"
+    );
+    "#,
+    r#"
+    DROP TABLE IF EXISTS message_fts;
+    CREATE VIRTUAL TABLE message_fts USING fts5(
+      message_id UNINDEXED,
+      thread_id UNINDEXED,
+      sender_id UNINDEXED,
+      body,
+      tokenize = "unicode61 remove_diacritics 2",
+      prefix = '2 3 4'
+    );
+    "#,
+    r#"
+    CREATE TRIGGER IF NOT EXISTS trg_messages_fts_insert
+    AFTER INSERT ON messages
+    FOR EACH ROW
+    WHEN NEW.body IS NOT NULL AND length(trim(NEW.body)) > 0
+    BEGIN
+      INSERT INTO message_fts (rowid, message_id, thread_id, sender_id, body)
+      VALUES (NEW.rowid, NEW.id, NEW.thread_id, NEW.sender_id, NEW.body);
+    END;
+
+    CREATE TRIGGER IF NOT EXISTS trg_messages_fts_delete
+    AFTER DELETE ON messages
+    FOR EACH ROW
+    BEGIN
+      DELETE FROM message_fts WHERE rowid = OLD.rowid;
+    END;
+
+    CREATE TRIGGER IF NOT EXISTS trg_messages_fts_update
+    AFTER UPDATE OF id, thread_id, sender_id, body ON messages
+    FOR EACH ROW
+    BEGIN
+      DELETE FROM message_fts WHERE rowid = OLD.rowid;
+      INSERT INTO message_fts (rowid, message_id, thread_id, sender_id, body)
+      SELECT NEW.rowid, NEW.id, NEW.thread_id, NEW.sender_id, NEW.body
+      WHERE NEW.body IS NOT NULL AND length(trim(NEW.body)) > 0;
+    END;
+    "#,
+    r#"
+    DROP TRIGGER IF EXISTS trg_messages_fts_insert;
+    DROP TRIGGER IF EXISTS trg_messages_fts_delete;
+    DROP TRIGGER IF EXISTS trg_messages_fts_update;
+    DROP TABLE IF EXISTS message_fts;
+    CREATE VIRTUAL TABLE message_fts USING fts5(
+      message_id UNINDEXED,
+      thread_id UNINDEXED,
+      sender_id UNINDEXED,
+      body,
+      quote_body,
+      tokenize = "unicode61 remove_diacritics 2",
+      prefix = '2 3 4'
+    );
+
+    CREATE TRIGGER trg_messages_fts_insert
+    AFTER INSERT ON messages
+    FOR EACH ROW
+    BEGIN
+      INSERT INTO message_fts (rowid, message_id, thread_id, sender_id, body, quote_body)
+      SELECT NEW.rowid, NEW.id, NEW.thread_id, NEW.sender_id, NEW.body, q.quote_body
+      FROM (
+        SELECT CASE WHEN json_valid(NEW.metadata_json)
+               THEN json_extract(NEW.metadata_json, '$.quote_body') END AS quote_body
+      ) q
+      WHERE length(trim(COALESCE(NEW.body, ''))) > 0
+         OR length(trim(COALESCE(q.quote_body, ''))) > 0;
+    END;
+
+    CREATE TRIGGER trg_messages_fts_delete
+    AFTER DELETE ON messages
+    FOR EACH ROW
+    BEGIN
+      DELETE FROM message_fts WHERE rowid = OLD.rowid;
+    END;
+
+    CREATE TRIGGER trg_messages_fts_update
+    AFTER UPDATE OF id, thread_id, sender_id, body, metadata_json ON messages
+    FOR EACH ROW
+    BEGIN
+      DELETE FROM message_fts WHERE rowid = OLD.rowid;
+      INSERT INTO message_fts (rowid, message_id, thread_id, sender_id, body, quote_body)
+      SELECT NEW.rowid, NEW.id, NEW.thread_id, NEW.sender_id, NEW.body, q.quote_body
+      FROM (
+        SELECT CASE WHEN json_valid(NEW.metadata_json)
+               THEN json_extract(NEW.metadata_json, '$.quote_body') END AS quote_body
+      ) q
+      WHERE length(trim(COALESCE(NEW.body, ''))) > 0
+         OR length(trim(COALESCE(q.quote_body, ''))) > 0;
+    END;
+    "#,
+    r#"
+    DROP TABLE IF EXISTS message_fts;
+    CREATE VIRTUAL TABLE message_fts USING fts5(
+      message_id UNINDEXED,
+      thread_id,
+      sender_id UNINDEXED,
+      body,
+      quote_body,
+      tokenize = "unicode61 remove_diacritics 2",
+      prefix = '2 3 4'
+    );
+    "#,
+    r#"
+    CREATE TABLE IF NOT EXISTS message_notes (
+      message_id TEXT PRIMARY KEY,
+      note_text TEXT NOT NULL,
+      updated_at INTEGER NOT NULL,
+      FOREIGN KEY (message_id) REFERENCES messages(id) ON DELETE CASCADE
+    );
+
+    CREATE VIRTUAL TABLE IF NOT EXISTS note_fts USING fts5(
+      message_id UNINDEXED,
+      note_text,
+      tokenize = "unicode61 remove_diacritics 2",
+      prefix = '2 3 4'
+    );
+
+    CREATE TRIGGER IF NOT EXISTS trg_message_notes_fts_insert
+    AFTER INSERT ON message_notes
+    FOR EACH ROW
+    BEGIN
+      INSERT INTO note_fts (rowid, message_id, note_text)
+      VALUES (NEW.rowid, NEW.message_id, NEW.note_text);
+    END;
+
+    CREATE TRIGGER IF NOT EXISTS trg_message_notes_fts_delete
+    AFTER DELETE ON message_notes
+    FOR EACH ROW
+    BEGIN
+      DELETE FROM note_fts WHERE rowid = OLD.rowid;
+    END;
+
+    CREATE TRIGGER IF NOT EXISTS trg_message_notes_fts_update
+    AFTER UPDATE OF message_id, note_text ON message_notes
+    FOR EACH ROW
+    BEGIN
+      DELETE FROM note_fts WHERE rowid = OLD.rowid;
+      INSERT INTO note_fts (rowid, message_id, note_text)
+      VALUES (NEW.rowid, NEW.message_id, NEW.note_text);
+    END;
+    "#,
+    r#"
+    CREATE TABLE IF NOT EXISTS attachment_tags (
+      attachment_id TEXT NOT NULL,
+      tag_id TEXT NOT NULL,
+      tagged_at INTEGER NOT NULL,
+      PRIMARY KEY (attachment_id, tag_id),
+      FOREIGN KEY (attachment_id) REFERENCES attachments(id) ON DELETE CASCADE,
+      FOREIGN KEY (tag_id) REFERENCES tags(id) ON DELETE CASCADE
+    );
+
+    CREATE INDEX IF NOT EXISTS idx_attachment_tags_tag_id
+      ON attachment_tags(tag_id, tagged_at DESC);
+    "#,
+    r#"
+    UPDATE attachments
+    SET kind = 'document'
+    WHERE kind = 'file'
+      AND (
+        mime LIKE 'text/%'
+        OR mime IN ('application/pdf', 'application/rtf', 'application/msword')
+        OR mime LIKE 'application/vnd.openxmlformats-officedocument.%'
+        OR mime LIKE 'application/vnd.oasis.opendocument.%'
+        OR mime LIKE 'application/vnd.ms-excel%'
+        OR mime LIKE 'application/vnd.ms-powerpoint%'
+      );
+    "#,
+    r#"
+    CREATE TABLE IF NOT EXISTS mentions (
+      message_id TEXT NOT NULL,
+      recipient_id TEXT NOT NULL,
+      range_start INTEGER NOT NULL,
+      range_length INTEGER NOT NULL,
+      PRIMARY KEY (message_id, range_start)
+    );
+    "#,
+    r#"
+    -- The disappearing-message timer a thread had when it was last imported.
+    ALTER TABLE threads ADD COLUMN expire_timer_ms INTEGER;
+    "#,
+    r#"
+    CREATE TABLE IF NOT EXISTS recipient_identities (
+      identity TEXT PRIMARY KEY,
+      recipient_id TEXT NOT NULL
+    );
+
+    CREATE INDEX IF NOT EXISTS idx_recipient_identities_recipient_id
+      ON recipient_identities(recipient_id);
+
+    -- Android recipients imported so far are keyed by their backup's numeric id.
+    INSERT OR IGNORE INTO recipient_identities (identity, recipient_id)
+    SELECT 'e164:' || phone_e164, id
+    FROM recipients
+    WHERE phone_e164 IS NOT NULL AND id NOT GLOB '*[^0-9]*';
+    "#,
+    r#"
+    CREATE TABLE IF NOT EXISTS import_checkpoints (
+      import_id TEXT PRIMARY KEY,
+      stage TEXT NOT NULL,
+      resume_offset INTEGER NOT NULL DEFAULT 0
+    );
+    "#,
+    r#"
+    -- Recipient numbers as the backup wrote them, where that differs from the normalized
+    -- E.164 in phone_e164.
+    ALTER TABLE recipients ADD COLUMN raw_phone TEXT;
+    "#,
+    r#"
+    -- Source rows an import skipped or only partly imported, capped per import.
+    CREATE TABLE IF NOT EXISTS import_issues (
+      import_id TEXT NOT NULL,
+      category TEXT NOT NULL,
+      source_table TEXT NOT NULL,
+      source_id TEXT,
+      detail TEXT,
+      UNIQUE (import_id, category, source_table, source_id)
+    );
+    "#,
+    r#"
+    -- Names newer backups carry besides the contact and profile name: the nickname the
+    -- owner set for someone and their Signal username.
+    ALTER TABLE recipients ADD COLUMN nickname TEXT;
+    ALTER TABLE recipients ADD COLUMN username TEXT;
+    "#,
+    r#"
+    -- BlurHash placeholders for image attachments, computed at import or by
+    -- maintenance::backfill_blurhash.
+    ALTER TABLE attachments ADD COLUMN blurhash TEXT;
+    "#,
+    r#"
+    -- Messages are matched across backups on thread, author and sent time rather than
+    -- Signal's row id, which changes between backups. A clash keeps the old key.
+    UPDATE OR IGNORE messages
+    SET dedupe_key = 'sent:' || thread_id || ':' || COALESCE(sender_id, 'self') || ':' || sent_at
+    WHERE sent_at > 0 AND (dedupe_key GLOB 'sms:*' OR dedupe_key GLOB 'mms:*');
+    -- Backup message ids an unfinished import stored under another archive id.
+    CREATE TABLE IF NOT EXISTS import_message_ids (
+      import_id TEXT NOT NULL,
+      source_id TEXT NOT NULL,
+      message_id TEXT NOT NULL,
+      PRIMARY KEY (import_id, source_id)
+    );
+    "#,
 ];
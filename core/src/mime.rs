@@ -0,0 +1,84 @@
+//! Attachment content inspection: kind inference from a MIME type, magic-number sniffing
+//! for attachments whose backup row has no content type, and image dimensions read from
+//! file headers.
+
+use std::io::Cursor;
+
+use image::ImageReader;
+
+/// Bytes needed by [`sniff_mime`]; the ISO-BMFF brand ends at offset 12.
+pub(crate) const SNIFF_LEN: usize = 16;
+
+/// Leading bytes handed to [`image_dimensions`]. Large enough for JPEG EXIF blocks ahead
+/// of the frame header, and within the first encrypted chunk of any blob.
+pub(crate) const DIMENSION_PROBE_LEN: usize = 256 * 1024;
+
+pub(crate) fn infer_kind(mime: &str) -> String {
+    if mime.starts_with("image/") {
+        "image".to_string()
+    } else if mime.starts_with("video/") {
+        "video".to_string()
+    } else if mime.starts_with("audio/") {
+        "audio".to_string()
+    } else if is_document_mime(mime) {
+        "document".to_string()
+    } else {
+        "file".to_string()
+    }
+}
+
+/// PDFs, plain text and office formats. Keep in sync with the `document` backfill migration.
+fn is_document_mime(mime: &str) -> bool {
+    mime.starts_with("text/")
+        || matches!(mime, "application/pdf" | "application/rtf" | "application/msword")
+        || mime.starts_with("application/vnd.openxmlformats-officedocument.")
+        || mime.starts_with("application/vnd.oasis.opendocument.")
+        || mime.starts_with("application/vnd.ms-excel")
+        || mime.starts_with("application/vnd.ms-powerpoint")
+}
+
+/// Guesses a MIME type from the leading bytes of a plaintext attachment. Only the formats
+/// Signal commonly sends are recognised: JPEG, PNG, GIF, WebP, HEIC/HEIF, MP4-family
+/// containers and MP3.
+pub(crate) fn sniff_mime(header: &[u8]) -> Option<&'static str> {
+    if header.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        return Some("image/jpeg");
+    }
+    if header.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Some("image/png");
+    }
+    if header.starts_with(b"GIF87a") || header.starts_with(b"GIF89a") {
+        return Some("image/gif");
+    }
+    if header.len() >= 12 && &header[..4] == b"RIFF" && &header[8..12] == b"WEBP" {
+        return Some("image/webp");
+    }
+    if header.len() >= 12 && &header[4..8] == b"ftyp" {
+        return Some(match &header[8..12] {
+            b"heic" | b"heix" | b"heim" | b"heis" | b"hevc" | b"hevx" => "image/heic",
+            b"mif1" | b"msf1" => "image/heif",
+            b"M4A " | b"M4B " => "audio/mp4",
+            b"qt  " => "video/quicktime",
+            _ => "video/mp4",
+        });
+    }
+    if header.starts_with(b"ID3") {
+        return Some("audio/mpeg");
+    }
+    // MPEG audio frame sync with layer III; ADTS AAC (layer 00) is left alone.
+    if header.len() >= 2 && header[0] == 0xFF && header[1] & 0xE0 == 0xE0 && header[1] & 0x06 == 0x02 {
+        return Some("audio/mpeg");
+    }
+    None
+}
+
+/// Width and height from an image's headers, without decoding pixels. `header` may be a
+/// truncated prefix of the file; formats the `image` crate can't parse (HEIC) give `None`.
+pub(crate) fn image_dimensions(header: &[u8]) -> Option<(i64, i64)> {
+    let (width, height) = ImageReader::new(Cursor::new(header))
+        .with_guessed_format()
+        .ok()?
+        .into_dimensions()
+        .ok()?;
+    Some((width as i64, height as i64))
+}
@@ -6,6 +6,8 @@ pub struct ThreadSummary {
     pub name: Option<String>,
     pub last_message_at: Option<i64>,
     pub message_count: i64,
+    /// Disappearing-messages timer the thread had when imported; `None` when off.
+    pub expire_timer_ms: Option<i64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -21,12 +23,108 @@ pub struct MessageRow {
     pub is_view_once: bool,
     pub quote_message_id: Option<String>,
     pub metadata_json: Option<String>,
+    /// When an outgoing message was first delivered, from `delivered_at` in `metadata_json`.
+    pub delivered_at: Option<i64>,
+    /// When an outgoing message was first read, from `read_at` in `metadata_json`.
+    pub read_at: Option<i64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SearchHit {
     pub message: MessageRow,
     pub rank: f64,
+    /// True when the query matched only the quoted text, not the message body.
+    pub matched_in_quote: bool,
+}
+
+/// Filters and ranking knobs for message search.
+///
+/// Missing fields deserialize to their defaults, and the defaults reproduce plain bm25
+/// ordering with quote matches weighted below body matches.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchOptions {
+    pub thread_id: Option<String>,
+    /// Only messages carrying this tag.
+    pub tag_id: Option<String>,
+    /// Only messages carrying any tag.
+    pub only_tagged: bool,
+    /// Treat the last query token as a prefix (as-you-type search).
+    pub prefix: bool,
+    /// Bypass FTS and match the query as a literal substring of the body, newest first.
+    /// Much slower than FTS on large archives; only honoured by `search_messages`.
+    pub exact: bool,
+    /// Inclusive lower bound on the message sort timestamp (ms).
+    pub from_ts: Option<i64>,
+    /// Inclusive upper bound on the message sort timestamp (ms).
+    pub to_ts: Option<i64>,
+    pub body_weight: f64,
+    pub quote_weight: f64,
+    /// When set, newer messages get a boost of `1 + h / (h + age_days)` for a value of `h`,
+    /// with age measured back from the newest message in the archive: 2x for the newest,
+    /// 1.5x at `h` days old, 1.33x at `2h`, tailing off hyperbolically rather than halving.
+    pub recency_half_life_days: Option<f64>,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            thread_id: None,
+            tag_id: None,
+            only_tagged: false,
+            prefix: false,
+            exact: false,
+            from_ts: None,
+            to_ts: None,
+            body_weight: 1.0,
+            quote_weight: 0.4,
+            recency_half_life_days: None,
+        }
+    }
+}
+
+/// Position of the last hit on a search page; the next page continues strictly after it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchCursor {
+    pub rank: f64,
+    pub message_id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchPage {
+    pub hits: Vec<SearchHit>,
+    pub next_cursor: Option<SearchCursor>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThreadSearchGroup {
+    pub thread_id: String,
+    pub thread_name: Option<String>,
+    pub total_hits: i64,
+    pub top_hits: Vec<SearchHit>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Recipient {
+    pub id: String,
+    pub phone_e164: Option<String>,
+    pub profile_name: Option<String>,
+    pub contact_name: Option<String>,
+    pub nickname: Option<String>,
+    pub username: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PersonMatch {
+    pub recipient: Recipient,
+    pub threads: Vec<ThreadSummary>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GlobalSearchResult {
+    pub people: Vec<PersonMatch>,
+    pub threads: Vec<ThreadSummary>,
+    pub message_hits: Vec<SearchHit>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -36,6 +134,17 @@ pub struct ReactionSummary {
     pub count: i64,
 }
 
+/// An @mention in a message body. The range is in UTF-16 code units and covers the
+/// placeholder character Signal stores in place of the name.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Mention {
+    pub message_id: String,
+    pub recipient_id: String,
+    pub display_name: Option<String>,
+    pub range_start: i64,
+    pub range_length: i64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MediaRow {
     pub id: String,
@@ -48,6 +157,8 @@ pub struct MediaRow {
     pub width: Option<i64>,
     pub height: Option<i64>,
     pub duration_ms: Option<i64>,
+    /// Placeholder painted before the thumbnail loads; images only.
+    pub blurhash: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -65,6 +176,63 @@ pub struct ThreadMediaRow {
     pub duration_ms: Option<i64>,
     pub sent_at: Option<i64>,
     pub received_at: Option<i64>,
+    /// Placeholder painted before the thumbnail loads; images only.
+    pub blurhash: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MediaKindCount {
+    pub kind: Option<String>,
+    pub count: i64,
+}
+
+/// Totals for a filtered thread media listing. Attachments without a recorded size count
+/// toward `total_count` but add nothing to `total_bytes`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThreadMediaSummary {
+    pub total_count: i64,
+    pub total_bytes: i64,
+    pub kinds: Vec<MediaKindCount>,
+}
+
+/// An attachment with its owning message, for the media lightbox.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttachmentDetail {
+    pub media: MediaRow,
+    pub message: MessageRow,
+    pub thread_name: Option<String>,
+}
+
+/// An attachment in the storage report, with its thread's name for context.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LargeAttachment {
+    pub media: ThreadMediaRow,
+    pub thread_name: Option<String>,
+}
+
+/// Bytes of media in one thread, counting each distinct blob once.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThreadStorage {
+    pub thread_id: String,
+    pub thread_name: Option<String>,
+    pub blob_count: i64,
+    pub total_bytes: i64,
+}
+
+/// Per-thread media usage, largest first. `unique_total_bytes` counts a blob shared by
+/// several threads once, so it can be less than the sum of the thread totals.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageUsage {
+    pub threads: Vec<ThreadStorage>,
+    pub unique_total_bytes: i64,
+}
+
+/// Number of attachments sent in one calendar month (UTC); `month` is 1-based.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MediaMonthBucket {
+    pub year: i64,
+    pub month: i64,
+    pub item_count: i64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -75,6 +243,48 @@ pub struct ArchiveStats {
     pub attachments: i64,
 }
 
+/// One run recorded in `imports`, with the headline numbers from its `stats_json`.
+/// Counts are `None` for runs that failed before mapping or predate the stat; `error` is
+/// set for failed runs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportRecord {
+    pub id: String,
+    pub imported_at: i64,
+    pub source_filename: String,
+    pub status: String,
+    /// Which app and database version the source came from, when the importer could tell.
+    pub detected_version: Option<String>,
+    pub messages_inserted: Option<i64>,
+    pub attachments_found: Option<i64>,
+    pub attachments_missing: Option<i64>,
+    pub error: Option<String>,
+}
+
+/// A source row an import skipped or only partly imported. `source_id` is the row's id in
+/// `source_table` when it has one; `detail` says what was wrong in words.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportIssue {
+    pub category: String,
+    pub source_table: String,
+    pub source_id: Option<String>,
+    pub detail: Option<String>,
+}
+
+/// What an Android backup holds, counted from its decoded database before importing it.
+/// Message times are `date_sent` in ms; `None` when the backup has no messages.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BackupInspection {
+    pub detected_version: Option<String>,
+    pub threads: i64,
+    pub sms: i64,
+    pub mms: i64,
+    pub attachments: i64,
+    /// Sum of the attachment sizes the backup declares.
+    pub attachment_bytes: i64,
+    pub first_message_at: Option<i64>,
+    pub last_message_at: Option<i64>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Tag {
     pub id: String,
@@ -84,15 +294,371 @@ pub struct Tag {
     pub display_order: i64,
 }
 
+/// A tag as applied to one message, with when it was applied.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaggedTag {
+    pub tag: Tag,
+    pub tagged_at: i64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MessageTags {
     pub message_id: String,
+    pub tags: Vec<TaggedTag>,
+}
+
+/// Aggregates for a tag's overview page. The time bounds are `None` while nothing is tagged.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TagStats {
+    pub tag_id: String,
+    pub message_count: i64,
+    pub thread_count: i64,
+    pub first_tagged_at: Option<i64>,
+    pub last_tagged_at: Option<i64>,
+    pub first_message_ts: Option<i64>,
+    pub last_message_ts: Option<i64>,
+    pub attachment_count: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttachmentTags {
+    pub attachment_id: String,
     pub tags: Vec<Tag>,
 }
 
+/// Portable copy of the user's tag curation, written by `export_tags_json`. Messages are
+/// referenced by `dedupe_key` rather than id so the backup still applies after a reset and
+/// re-import; attachments are referenced by their message plus content hash.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TagBackup {
+    pub version: i64,
+    pub tags: Vec<Tag>,
+    pub message_tags: Vec<MessageTagBackup>,
+    pub attachment_tags: Vec<AttachmentTagBackup>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageTagBackup {
+    pub dedupe_key: String,
+    pub tag_id: String,
+    pub tagged_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttachmentTagBackup {
+    pub dedupe_key: String,
+    pub sha256: String,
+    pub tag_id: String,
+    pub tagged_at: i64,
+}
+
+/// Outcome of `import_tags_json`. `*_skipped` counts entries whose message or attachment
+/// is no longer in the archive; entries that were already applied count as neither.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TagImportSummary {
+    pub tags_created: i64,
+    pub tags_matched: i64,
+    pub message_tags_applied: i64,
+    pub message_tags_skipped: i64,
+    pub attachment_tags_applied: i64,
+    pub attachment_tags_skipped: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageNote {
+    pub message_id: String,
+    pub note_text: String,
+    pub updated_at: i64,
+}
+
+/// A message together with its local annotations.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageDetail {
+    pub message: MessageRow,
+    pub tags: Vec<Tag>,
+    pub note: Option<MessageNote>,
+}
+
+/// Which timeline the scrapbook is read along. Both orders page newest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScrapbookOrder {
+    /// When the tag was applied (`message_tags.tagged_at`).
+    #[default]
+    TaggedAt,
+    /// When the message itself was sent (`messages.sort_ts`).
+    MessageTime,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ScrapbookMessage {
     pub message: MessageRow,
     pub thread_name: Option<String>,
     pub is_discontinuous: bool,
+    /// Every tag on the message, including the scrapbook's own.
+    pub tags: Vec<TaggedTag>,
+}
+
+/// One page of scrapbook messages. The `next_before_*` fields feed straight back into
+/// `list_scrapbook_messages` and are `None` once the last page has been returned.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScrapbookPage {
+    pub items: Vec<ScrapbookMessage>,
+    pub next_before_ts: Option<i64>,
+    pub next_before_id: Option<String>,
+}
+
+/// A message in the window around a scrapbook entry. `is_tagged` is set when the message
+/// carries the scrapbook's tag, so the UI knows where the expanded "⋯" section ends.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScrapbookContextMessage {
+    pub message: MessageRow,
+    pub is_tagged: bool,
+}
+
+/// Filters accepted by `list_thread_media`, bundled for callers that pass them through.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ThreadMediaFilters {
+    pub from_ts: Option<i64>,
+    pub to_ts: Option<i64>,
+    pub size_bucket: Option<i64>,
+    pub kind: Option<String>,
+}
+
+/// Outcome of `export::export_thread_media`. `missing` counts attachments whose blob is
+/// gone from the archive; `failed` counts ones that could not be decrypted or written.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MediaExportSummary {
+    pub total: i64,
+    pub exported: i64,
+    pub missing: i64,
+    pub failed: i64,
+}
+
+/// Outcome of `export::export_archive_bundle` and `export::import_archive_bundle`.
+/// `unreadable` counts blobs an export left out because they no longer decrypt.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ArchiveBundleSummary {
+    pub attachments: i64,
+    pub unreadable: i64,
+}
+
+/// Output format for `export::export_scrapbook`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    Markdown,
+    Html,
+}
+
+/// A blob referenced by `attachments.sha256` that is absent from the attachments directory.
+/// `message_id`, `thread_id` and `original_filename` describe one of its references.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MissingAttachment {
+    pub sha256: String,
+    pub reference_count: i64,
+    pub message_id: String,
+    pub thread_id: Option<String>,
+    pub original_filename: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttachmentAuditReport {
+    pub checked: i64,
+    pub missing: Vec<MissingAttachment>,
+}
+
+/// Result of `gc_orphaned_attachments`: unreferenced blob names, their combined size, and
+/// how many were removed (zero on a dry run).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GcReport {
+    pub candidates: Vec<String>,
+    pub bytes: i64,
+    pub deleted: i64,
+}
+
+/// Result of `resniff_attachments`: blobs inspected, attachment rows given a sniffed MIME
+/// type, and blobs that were missing or could not be decrypted.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ResniffReport {
+    pub checked: i64,
+    pub updated: i64,
+    pub missing: i64,
+    pub failed: i64,
+}
+
+/// Result of `backfill_image_dimensions`: image blobs inspected, attachment rows given a
+/// width and height, and blobs that were missing or could not be decrypted.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DimensionBackfillReport {
+    pub checked: i64,
+    pub updated: i64,
+    pub missing: i64,
+    pub failed: i64,
+}
+
+/// Result of `backfill_blurhash`: image blobs inspected, attachment rows given a placeholder,
+/// and blobs that were missing or could not be decrypted or decoded.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BlurhashBackfillReport {
+    pub checked: i64,
+    pub updated: i64,
+    pub missing: i64,
+    pub failed: i64,
+}
+
+/// A blob whose decrypted contents no longer hash to its `attachments.sha256`, or that
+/// could not be decrypted at all. `message_id` is one message referencing it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CorruptAttachment {
+    pub sha256: String,
+    pub message_id: String,
+    pub reason: String,
+}
+
+/// Result of `verify_attachments`: blobs decrypted and rehashed, blobs absent from disk,
+/// and the ones that failed the check. `legacy_format` counts verified blobs still in the
+/// version 1 format, which can't detect truncation by themselves.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AttachmentVerifyReport {
+    pub checked: i64,
+    pub verified: i64,
+    pub missing: i64,
+    pub legacy_format: i64,
+    pub corrupt: Vec<CorruptAttachment>,
+}
+
+/// Result of `crypto::self_test`: AES-256-GCM and HKDF against published vectors, and an
+/// attachment stream that round-trips and rejects a flipped byte.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CryptoSelfTestReport {
+    pub passed: bool,
+    pub aes_gcm: bool,
+    pub hkdf: bool,
+    pub stream_round_trip: bool,
+    pub tamper_detected: bool,
+}
+
+/// What support needs to compare two installs: the master key's fingerprint, `None` when
+/// the key can't be loaded, and a fresh self-test.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CryptoDiagnostics {
+    pub key_fingerprint: Option<String>,
+    pub self_test: CryptoSelfTestReport,
+}
+
+/// Throughput of one chunk size in `crypto::benchmark`, in MB/s.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkBench {
+    pub chunk_size: usize,
+    pub encrypt_mb_s: f64,
+    pub decrypt_mb_s: f64,
+}
+
+/// Result of `crypto::benchmark`: in-memory crypto speed per chunk size, how fast the disk
+/// took the same amount of ciphertext, and the chunk size picked for new attachments.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchReport {
+    pub sample_mb: usize,
+    pub results: Vec<ChunkBench>,
+    pub disk_write_mb_s: f64,
+    pub recommended_chunk_size: usize,
+}
+
+/// Import phases, in the order an import passes through them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportStage {
+    Hashing,
+    Decoding,
+    Recipients,
+    Threads,
+    Messages,
+    Attachments,
+    Reactions,
+    Fts,
+    Finalizing,
+}
+
+impl ImportStage {
+    pub fn label(self) -> &'static str {
+        match self {
+            ImportStage::Hashing => "Preparing import",
+            ImportStage::Decoding => "Decoding backup",
+            ImportStage::Recipients => "Importing recipients",
+            ImportStage::Threads => "Importing threads",
+            ImportStage::Messages => "Importing messages",
+            ImportStage::Attachments => "Importing attachments",
+            ImportStage::Reactions => "Importing reactions",
+            ImportStage::Fts => "Building search index",
+            ImportStage::Finalizing => "Finalizing import",
+        }
+    }
+}
+
+/// A progress update from an import or search-index rebuild, emitted as the
+/// `import_status` event. `current` counts items (bytes while hashing) out of `total`
+/// when the stage knows its size; `detail` narrows the stage, e.g. "SMS" or "up to date".
+/// The attachment stage also reports `bytes_done` out of `bytes_total`, since a handful of
+/// large videos can take longer than thousands of stickers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImportProgress {
+    pub stage: ImportStage,
+    pub current: u64,
+    pub total: Option<u64>,
+    pub detail: Option<String>,
+    #[serde(default)]
+    pub bytes_done: Option<u64>,
+    #[serde(default)]
+    pub bytes_total: Option<u64>,
+}
+
+impl ImportProgress {
+    pub fn new(stage: ImportStage) -> Self {
+        Self {
+            stage,
+            current: 0,
+            total: None,
+            detail: None,
+            bytes_done: None,
+            bytes_total: None,
+        }
+    }
+
+    pub fn counted(stage: ImportStage, current: u64, total: u64) -> Self {
+        Self {
+            stage,
+            current,
+            total: Some(total),
+            detail: None,
+            bytes_done: None,
+            bytes_total: None,
+        }
+    }
+
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    pub fn with_bytes(mut self, done: u64, total: u64) -> Self {
+        self.bytes_done = Some(done);
+        self.bytes_total = Some(total);
+        self
+    }
+}
+
+/// The free-form status line older builds emitted, kept for logs:
+/// "Importing messages... 5000/123456 (SMS)".
+impl std::fmt::Display for ImportProgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}...", self.stage.label())?;
+        if let Some(total) = self.total {
+            write!(f, " {}/{}", self.current, total)?;
+        }
+        if let Some(detail) = &self.detail {
+            write!(f, " ({})", detail)?;
+        }
+        Ok(())
+    }
 }
@@ -1,9 +1,18 @@
-use rusqlite::{params, Connection, OptionalExtension};
+use rusqlite::{named_params, params, Connection, OptionalExtension};
+use uuid::Uuid;
 
 use crate::error::CoreError;
-use crate::models::{ArchiveStats, MediaRow, MessageRow, ReactionSummary, ScrapbookMessage, SearchHit, Tag, ThreadMediaRow, ThreadSummary, MessageTags};
+use crate::models::{
+    ArchiveStats, AttachmentDetail, AttachmentTagBackup, AttachmentTags, GlobalSearchResult, ImportIssue, ImportRecord, LargeAttachment, MediaKindCount, Mention, MediaMonthBucket, MediaRow, MessageDetail, MessageNote,
+    MessageRow, MessageTagBackup, MessageTags, PersonMatch, ReactionSummary, Recipient, ScrapbookContextMessage,
+    ScrapbookMessage, ScrapbookOrder, ScrapbookPage, SearchCursor, SearchHit, SearchOptions, SearchPage, StorageUsage, Tag,
+    TagBackup, TagImportSummary, TagStats, TaggedTag, ThreadMediaFilters, ThreadMediaRow, ThreadMediaSummary,
+    ThreadSearchGroup, ThreadStorage, ThreadSummary,
+};
 
 fn message_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<MessageRow> {
+    let metadata_json: Option<String> = row.get(10)?;
+    let (delivered_at, read_at) = receipt_times(metadata_json.as_deref());
     Ok(MessageRow {
         id: row.get(0)?,
         thread_id: row.get(1)?,
@@ -15,10 +24,21 @@ fn message_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<MessageRow> {
         is_outgoing: row.get::<_, i64>(7)? != 0,
         is_view_once: row.get::<_, i64>(8)? != 0,
         quote_message_id: row.get(9)?,
-        metadata_json: row.get(10)?,
+        metadata_json,
+        delivered_at,
+        read_at,
     })
 }
 
+/// `delivered_at` and `read_at` recorded in a message's `metadata_json` at import.
+fn receipt_times(metadata_json: Option<&str>) -> (Option<i64>, Option<i64>) {
+    let Some(json) = metadata_json.filter(|json| json.contains("delivered_at") || json.contains("read_at")) else {
+        return (None, None);
+    };
+    let metadata: serde_json::Value = serde_json::from_str(json).unwrap_or_default();
+    (metadata["delivered_at"].as_i64(), metadata["read_at"].as_i64())
+}
+
 fn media_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<MediaRow> {
     Ok(MediaRow {
         id: row.get(0)?,
@@ -31,6 +51,26 @@ fn media_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<MediaRow> {
         width: row.get(7)?,
         height: row.get(8)?,
         duration_ms: row.get(9)?,
+        blurhash: row.get(10)?,
+    })
+}
+
+fn thread_media_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<ThreadMediaRow> {
+    Ok(ThreadMediaRow {
+        id: row.get(0)?,
+        message_id: row.get(1)?,
+        thread_id: row.get(2)?,
+        sha256: row.get(3)?,
+        mime: row.get(4)?,
+        size_bytes: row.get(5)?,
+        original_filename: row.get(6)?,
+        kind: row.get(7)?,
+        width: row.get(8)?,
+        height: row.get(9)?,
+        duration_ms: row.get(10)?,
+        sent_at: row.get(11)?,
+        received_at: row.get(12)?,
+        blurhash: row.get(13)?,
     })
 }
 
@@ -48,10 +88,20 @@ fn placeholders(count: usize) -> String {
     std::iter::repeat("?").take(count).collect::<Vec<_>>().join(",")
 }
 
+/// Threads most recently active first. A thread imported without a name takes its only
+/// member's display name: nickname, then contact name, profile name, username and phone.
 pub fn list_threads(conn: &Connection, limit: i64, offset: i64) -> Result<Vec<ThreadSummary>, CoreError> {
     let mut stmt = conn.prepare(
-        "SELECT t.id, t.name, t.last_message_at, \
-         (SELECT COUNT(1) FROM messages m WHERE m.thread_id = t.id) AS message_count \
+        "SELECT t.id, \
+         COALESCE(t.name, \
+           (SELECT COALESCE(r.nickname, r.contact_name, r.profile_name, r.username, r.phone_e164) \
+            FROM thread_members tm \
+            JOIN recipients r ON r.id = tm.recipient_id \
+            WHERE tm.thread_id = t.id \
+              AND (SELECT COUNT(1) FROM thread_members other WHERE other.thread_id = t.id) = 1)), \
+         t.last_message_at, \
+         (SELECT COUNT(1) FROM messages m WHERE m.thread_id = t.id) AS message_count, \
+         t.expire_timer_ms \
          FROM threads t \
          ORDER BY t.last_message_at DESC NULLS LAST, t.id ASC \
          LIMIT ?1 OFFSET ?2;",
@@ -62,6 +112,7 @@ pub fn list_threads(conn: &Connection, limit: i64, offset: i64) -> Result<Vec<Th
             name: row.get(1)?,
             last_message_at: row.get(2)?,
             message_count: row.get(3)?,
+            expire_timer_ms: row.get(4)?,
         })
     })?;
     Ok(rows.filter_map(Result::ok).collect())
@@ -187,31 +238,469 @@ pub fn list_messages_around(
     Ok(result)
 }
 
+/// Weighted bm25 rank (only the body and quote columns contribute) plus a flag that is
+/// set when none of the matched terms landed in the message body, i.e. the hit came from
+/// the quote.
+///
+/// bm25 is negative (lower is better), so the recency factor in `[1, 2]` pulls newer
+/// messages up. It decays hyperbolically: `half_life / (half_life + age_days)` is 1 for
+/// the newest message and 0.5 once a message is `half_life` days older. Binds
+/// `:body_weight`, `:quote_weight`, `:half_life` and `:newest_ts`.
+const HIT_RANK_COLUMNS: &str = "bm25(message_fts, 0.0, 0.0, 0.0, :body_weight, :quote_weight) \
+       * (CASE WHEN :half_life IS NULL THEN 1.0 \
+          ELSE 1.0 + :half_life / (:half_life + MAX(0, :newest_ts - m.sort_ts) / 86400000.0) END) AS rank, \
+     COALESCE(instr(highlight(message_fts, 3, char(1), char(2)), char(1)), 0) = 0 AS matched_in_quote";
+
+/// Rejects ranking weights and half-lives that are not finite and positive; bm25 with a
+/// zero, negative or NaN weight orders hits arbitrarily.
+fn validate_rank_options(options: &SearchOptions) -> Result<(), CoreError> {
+    let values = [
+        ("body_weight", Some(options.body_weight)),
+        ("quote_weight", Some(options.quote_weight)),
+        ("recency_half_life_days", options.recency_half_life_days),
+    ];
+    for (name, value) in values {
+        if let Some(value) = value.filter(|v| !v.is_finite() || *v <= 0.0) {
+            return Err(CoreError::InvalidArgument(format!("{} must be a positive number, got {}", name, value)));
+        }
+    }
+    Ok(())
+}
+
+/// Sort timestamp of the newest message, the reference point for the recency boost.
+/// Only computed when a half-life is requested.
+fn recency_anchor(conn: &Connection, options: &SearchOptions) -> Result<Option<i64>, CoreError> {
+    if options.recency_half_life_days.is_none() {
+        return Ok(None);
+    }
+    let newest: Option<i64> = conn.query_row("SELECT MAX(sort_ts) FROM messages;", [], |row| row.get(0))?;
+    Ok(newest)
+}
+
+fn search_hit_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<SearchHit> {
+    Ok(SearchHit {
+        message: message_from_row(row)?,
+        rank: row.get(11)?,
+        matched_in_quote: row.get(12)?,
+    })
+}
+
+/// Turns free-form user input into an FTS5 MATCH expression.
+///
+/// Each whitespace-separated token becomes a quoted phrase so punctuation and FTS
+/// operators in the input can't produce syntax errors. With `prefix`, the final token
+/// also matches as a prefix (`"birthd"*`); the exact phrase is OR-ed in so whole-word
+/// hits score higher under bm25. User terms are restricted to the text columns; a
+/// `thread_id` is matched against its own indexed column so FTS only walks that
+/// thread's postings instead of ranking the whole corpus. Returns `None` when there is
+/// nothing to search for.
+fn fts_match_expr(query: &str, prefix: bool, thread_id: Option<&str>) -> Option<String> {
+    let phrases: Vec<String> = query.split_whitespace().map(fts_phrase).collect();
+    let (last, rest) = phrases.split_last()?;
+    let mut parts: Vec<String> = rest.to_vec();
+    if prefix {
+        parts.push(format!("({} OR {}*)", last, last));
+    } else {
+        parts.push(last.clone());
+    }
+    let text_expr = format!("{{body quote_body}} : ({})", parts.join(" AND "));
+    match thread_id {
+        Some(thread_id) => Some(format!("thread_id : {} AND {}", fts_phrase(thread_id), text_expr)),
+        None => Some(text_expr),
+    }
+}
+
+fn fts_phrase(token: &str) -> String {
+    format!("\"{}\"", token.replace('"', "\"\""))
+}
+
+/// Full-text search over message bodies and quotes, ranked by (optionally recency-boosted) bm25.
+///
+/// `options.tag_id` limits hits to messages carrying that tag; `options.only_tagged` limits
+/// them to messages carrying any tag (used by the scrapbook search box).
 pub fn search_messages(
     conn: &Connection,
     query: &str,
-    thread_id: Option<&str>,
+    options: &SearchOptions,
     limit: i64,
     offset: i64,
 ) -> Result<Vec<SearchHit>, CoreError> {
-    let mut stmt = conn.prepare(
+    validate_rank_options(options)?;
+    if options.exact {
+        return search_messages_exact(conn, query, options, None, limit, offset);
+    }
+    let Some(match_expr) = fts_match_expr(query, options.prefix, options.thread_id.as_deref()) else {
+        return Ok(Vec::new());
+    };
+    let newest_ts = recency_anchor(conn, options)?;
+    let mut stmt = conn.prepare(&format!(
         "SELECT m.id, m.thread_id, m.sender_id, m.sent_at, m.received_at, m.type, m.body, \
                 m.is_outgoing, m.is_view_once, m.quote_message_id, m.metadata_json, \
-                bm25(message_fts) AS rank \
+                {} \
          FROM message_fts \
          JOIN messages m ON m.id = message_fts.message_id \
-         WHERE message_fts MATCH ?1 AND (?2 IS NULL OR m.thread_id = ?2) \
-         ORDER BY rank \
-         LIMIT ?3 OFFSET ?4;",
+         WHERE message_fts MATCH :match AND (:thread_id IS NULL OR m.thread_id = :thread_id) \
+           AND (:tag_id IS NULL OR EXISTS ( \
+             SELECT 1 FROM message_tags mt WHERE mt.message_id = m.id AND mt.tag_id = :tag_id)) \
+           AND (:only_tagged = 0 OR EXISTS (SELECT 1 FROM message_tags mt WHERE mt.message_id = m.id)) \
+           AND (:from_ts IS NULL OR m.sort_ts >= :from_ts) \
+           AND (:to_ts IS NULL OR m.sort_ts <= :to_ts) \
+         ORDER BY rank, m.id \
+         LIMIT :limit OFFSET :offset;",
+        HIT_RANK_COLUMNS
+    ))?;
+    let rows = stmt.query_map(
+        named_params! {
+            ":match": match_expr,
+            ":thread_id": options.thread_id,
+            ":tag_id": options.tag_id,
+            ":only_tagged": options.only_tagged,
+            ":from_ts": options.from_ts,
+            ":to_ts": options.to_ts,
+            ":body_weight": options.body_weight,
+            ":quote_weight": options.quote_weight,
+            ":half_life": options.recency_half_life_days,
+            ":newest_ts": newest_ts,
+            ":limit": limit,
+            ":offset": offset,
+        },
+        search_hit_from_row,
     )?;
-    let rows = stmt.query_map(params![query, thread_id, limit, offset], |row| {
-        let message = message_from_row(row)?;
-        Ok(SearchHit {
-            message,
-            rank: row.get(11)?,
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+/// Literal substring scan behind `SearchOptions::exact`.
+///
+/// Finds text the FTS tokenizer drops, like ":)" or the middle of a word ("ology"). This
+/// walks `messages` row by row rather than using the index, so it is much slower than
+/// FTS; `limit` bounds the result and the scan stops once enough rows match. Matching is
+/// case-insensitive for ASCII only. Hits are ordered newest first and carry a rank of 0;
+/// with `after_id`, they start after that message in `(sort_ts, id)` order.
+fn search_messages_exact(
+    conn: &Connection,
+    query: &str,
+    options: &SearchOptions,
+    after_id: Option<&str>,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<SearchHit>, CoreError> {
+    let needle = query.trim();
+    if needle.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut stmt = conn.prepare(
+        "SELECT m.id, m.thread_id, m.sender_id, m.sent_at, m.received_at, m.type, m.body, \
+                m.is_outgoing, m.is_view_once, m.quote_message_id, m.metadata_json, \
+                0.0 AS rank, 0 AS matched_in_quote \
+         FROM messages m \
+         WHERE m.body LIKE :pattern ESCAPE '\\' \
+           AND (:thread_id IS NULL OR m.thread_id = :thread_id) \
+           AND (:tag_id IS NULL OR EXISTS ( \
+             SELECT 1 FROM message_tags mt WHERE mt.message_id = m.id AND mt.tag_id = :tag_id)) \
+           AND (:only_tagged = 0 OR EXISTS (SELECT 1 FROM message_tags mt WHERE mt.message_id = m.id)) \
+           AND (:from_ts IS NULL OR m.sort_ts >= :from_ts) \
+           AND (:to_ts IS NULL OR m.sort_ts <= :to_ts) \
+           AND (:after_id IS NULL OR (m.sort_ts, m.id) < (SELECT sort_ts, id FROM messages WHERE id = :after_id)) \
+         ORDER BY m.sort_ts DESC, m.id DESC \
+         LIMIT :limit OFFSET :offset;",
+    )?;
+    let rows = stmt.query_map(
+        named_params! {
+            ":pattern": like_pattern(needle),
+            ":thread_id": options.thread_id,
+            ":tag_id": options.tag_id,
+            ":only_tagged": options.only_tagged,
+            ":from_ts": options.from_ts,
+            ":to_ts": options.to_ts,
+            ":after_id": after_id,
+            ":limit": limit,
+            ":offset": offset,
+        },
+        search_hit_from_row,
+    )?;
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+/// Keyset-paginated variant of [`search_messages`].
+///
+/// Hits are ordered by `(rank, message_id)`; pass the previous page's `next_cursor` as
+/// `after` to continue. `next_cursor` is `None` once the last page has been returned.
+/// `options.exact` pages through the literal scan of [`search_messages`] instead, newest
+/// first by `(sort_ts, message_id)`; its cursors carry a rank of 0.
+pub fn search_messages_page(
+    conn: &Connection,
+    query: &str,
+    options: &SearchOptions,
+    after: Option<&SearchCursor>,
+    limit: i64,
+) -> Result<SearchPage, CoreError> {
+    validate_rank_options(options)?;
+    let limit = limit.max(1);
+    if options.exact {
+        let after_id = after.map(|cursor| cursor.message_id.as_str());
+        let hits = search_messages_exact(conn, query, options, after_id, limit + 1, 0)?;
+        return Ok(search_page(hits, limit));
+    }
+    let Some(match_expr) = fts_match_expr(query, options.prefix, options.thread_id.as_deref()) else {
+        return Ok(SearchPage {
+            hits: Vec::new(),
+            next_cursor: None,
+        });
+    };
+    let newest_ts = recency_anchor(conn, options)?;
+    let mut stmt = conn.prepare(&format!(
+        "WITH hits AS ( \
+           SELECT m.id, m.thread_id, m.sender_id, m.sent_at, m.received_at, m.type, m.body, \
+                  m.is_outgoing, m.is_view_once, m.quote_message_id, m.metadata_json, \
+                  {} \
+           FROM message_fts \
+           JOIN messages m ON m.id = message_fts.message_id \
+           WHERE message_fts MATCH :match AND (:thread_id IS NULL OR m.thread_id = :thread_id) \
+             AND (:tag_id IS NULL OR EXISTS ( \
+               SELECT 1 FROM message_tags mt WHERE mt.message_id = m.id AND mt.tag_id = :tag_id)) \
+             AND (:only_tagged = 0 OR EXISTS (SELECT 1 FROM message_tags mt WHERE mt.message_id = m.id)) \
+             AND (:from_ts IS NULL OR m.sort_ts >= :from_ts) \
+             AND (:to_ts IS NULL OR m.sort_ts <= :to_ts) \
+         ) \
+         SELECT * FROM hits \
+         WHERE :after_rank IS NULL OR rank > :after_rank OR (rank = :after_rank AND id > :after_id) \
+         ORDER BY rank, id \
+         LIMIT :limit;",
+        HIT_RANK_COLUMNS
+    ))?;
+    let rows = stmt.query_map(
+        named_params! {
+            ":match": match_expr,
+            ":thread_id": options.thread_id,
+            ":tag_id": options.tag_id,
+            ":only_tagged": options.only_tagged,
+            ":from_ts": options.from_ts,
+            ":to_ts": options.to_ts,
+            ":body_weight": options.body_weight,
+            ":quote_weight": options.quote_weight,
+            ":half_life": options.recency_half_life_days,
+            ":newest_ts": newest_ts,
+            ":after_rank": after.map(|cursor| cursor.rank),
+            ":after_id": after.map(|cursor| cursor.message_id.as_str()),
+            ":limit": limit + 1,
+        },
+        search_hit_from_row,
+    )?;
+    let hits: Vec<SearchHit> = rows.collect::<Result<_, _>>()?;
+    Ok(search_page(hits, limit))
+}
+
+/// Cuts `hits`, fetched with one extra row, to a page of `limit`.
+fn search_page(mut hits: Vec<SearchHit>, limit: i64) -> SearchPage {
+    let has_more = hits.len() as i64 > limit;
+    hits.truncate(limit as usize);
+    let next_cursor = if has_more {
+        hits.last().map(|hit| SearchCursor {
+            rank: hit.rank,
+            message_id: hit.message.id.clone(),
         })
+    } else {
+        None
+    };
+    SearchPage { hits, next_cursor }
+}
+
+/// Runs a global search and buckets the hits per conversation.
+///
+/// Threads are ordered by their best bm25 rank; each group carries the total number of
+/// hits in that thread but only the `limit_per_thread` best-ranked hits, so broad queries
+/// stay cheap to ship to the UI. Ranking always uses the default [`SearchOptions`]; there
+/// is no exact mode, since groups are ordered by rank.
+pub fn search_messages_grouped(
+    conn: &Connection,
+    query: &str,
+    limit_per_thread: i64,
+) -> Result<Vec<ThreadSearchGroup>, CoreError> {
+    let Some(match_expr) = fts_match_expr(query, false, None) else {
+        return Ok(Vec::new());
+    };
+    let limit_per_thread = limit_per_thread.max(1);
+    let mut stmt = conn.prepare(&format!(
+        "WITH hits AS ( \
+           SELECT m.id, m.thread_id, m.sender_id, m.sent_at, m.received_at, m.type, m.body, \
+                  m.is_outgoing, m.is_view_once, m.quote_message_id, m.metadata_json, \
+                  {} \
+           FROM message_fts \
+           JOIN messages m ON m.id = message_fts.message_id \
+           WHERE message_fts MATCH :match \
+         ), ranked AS ( \
+           SELECT hits.*, \
+                  ROW_NUMBER() OVER (PARTITION BY thread_id ORDER BY rank, id) AS hit_idx, \
+                  COUNT(1) OVER (PARTITION BY thread_id) AS total_hits, \
+                  MIN(rank) OVER (PARTITION BY thread_id) AS best_rank \
+           FROM hits \
+         ) \
+         SELECT r.id, r.thread_id, r.sender_id, r.sent_at, r.received_at, r.type, r.body, \
+                r.is_outgoing, r.is_view_once, r.quote_message_id, r.metadata_json, \
+                r.rank, r.matched_in_quote, r.total_hits, t.name \
+         FROM ranked r \
+         LEFT JOIN threads t ON t.id = r.thread_id \
+         WHERE r.hit_idx <= :limit_per_thread \
+         ORDER BY r.best_rank, r.thread_id, r.hit_idx;",
+        HIT_RANK_COLUMNS
+    ))?;
+    let options = SearchOptions::default();
+    let params = named_params! {
+        ":match": match_expr,
+        ":body_weight": options.body_weight,
+        ":quote_weight": options.quote_weight,
+        ":half_life": options.recency_half_life_days,
+        ":newest_ts": Option::<i64>::None,
+        ":limit_per_thread": limit_per_thread,
+    };
+    let rows = stmt.query_map(params, |row| {
+        let hit = search_hit_from_row(row)?;
+        let total_hits: i64 = row.get(13)?;
+        let thread_name: Option<String> = row.get(14)?;
+        Ok((hit, total_hits, thread_name))
     })?;
-    Ok(rows.filter_map(Result::ok).collect())
+
+    let mut groups: Vec<ThreadSearchGroup> = Vec::new();
+    for row in rows {
+        let (hit, total_hits, thread_name) = row?;
+        match groups.last_mut() {
+            Some(group) if group.thread_id == hit.message.thread_id => group.top_hits.push(hit),
+            _ => groups.push(ThreadSearchGroup {
+                thread_id: hit.message.thread_id.clone(),
+                thread_name,
+                total_hits,
+                top_hits: vec![hit],
+            }),
+        }
+    }
+    Ok(groups)
+}
+
+/// Escapes `%`, `_` and `\` so user input is matched literally by `LIKE ... ESCAPE '\'`.
+fn like_pattern(needle: &str) -> String {
+    let mut escaped = String::with_capacity(needle.len() + 2);
+    escaped.push('%');
+    for ch in needle.chars() {
+        if matches!(ch, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(ch);
+    }
+    escaped.push('%');
+    escaped
+}
+
+fn thread_summary_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<ThreadSummary> {
+    Ok(ThreadSummary {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        last_message_at: row.get(2)?,
+        message_count: row.get(3)?,
+        expire_timer_ms: row.get(4)?,
+    })
+}
+
+/// Finds recipients whose nickname, contact name, profile name, username or phone number
+/// contains `needle`.
+///
+/// Names match case-insensitively. When the needle looks like a phone number, formatting
+/// (spaces, dashes, parentheses) is ignored and only its digits are compared. Each match
+/// carries the threads that recipient belongs to, most recently active first.
+pub fn search_people(conn: &Connection, needle: &str, limit: i64) -> Result<Vec<PersonMatch>, CoreError> {
+    let needle = needle.trim();
+    if needle.is_empty() {
+        return Ok(Vec::new());
+    }
+    let name_pattern = like_pattern(needle);
+    let looks_like_phone = needle
+        .chars()
+        .all(|ch| ch.is_ascii_digit() || matches!(ch, '+' | '-' | ' ' | '(' | ')' | '.'));
+    let digits: String = needle.chars().filter(|ch| ch.is_ascii_digit()).collect();
+    let phone_pattern: Option<String> = if looks_like_phone && !digits.is_empty() {
+        Some(like_pattern(&digits))
+    } else {
+        None
+    };
+
+    let mut stmt = conn.prepare(
+        "SELECT r.id, r.phone_e164, r.profile_name, r.contact_name, r.nickname, r.username \
+         FROM recipients r \
+         WHERE r.contact_name LIKE ?1 ESCAPE '\\' \
+            OR r.profile_name LIKE ?1 ESCAPE '\\' \
+            OR r.nickname LIKE ?1 ESCAPE '\\' \
+            OR r.username LIKE ?1 ESCAPE '\\' \
+            OR (?2 IS NOT NULL AND replace(r.phone_e164, '+', '') LIKE ?2 ESCAPE '\\') \
+         ORDER BY COALESCE(r.nickname, r.contact_name, r.profile_name, r.username, r.phone_e164, r.id) COLLATE NOCASE, r.id \
+         LIMIT ?3;",
+    )?;
+    let recipients: Vec<Recipient> = stmt
+        .query_map(params![name_pattern, phone_pattern, limit], |row| {
+            Ok(Recipient {
+                id: row.get(0)?,
+                phone_e164: row.get(1)?,
+                profile_name: row.get(2)?,
+                contact_name: row.get(3)?,
+                nickname: row.get(4)?,
+                username: row.get(5)?,
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+
+    let mut thread_stmt = conn.prepare(
+        "SELECT t.id, t.name, t.last_message_at, \
+         (SELECT COUNT(1) FROM messages m WHERE m.thread_id = t.id) AS message_count, \
+         t.expire_timer_ms \
+         FROM thread_members tm \
+         JOIN threads t ON t.id = tm.thread_id \
+         WHERE tm.recipient_id = ?1 \
+         ORDER BY t.last_message_at DESC NULLS LAST, t.id ASC;",
+    )?;
+    let mut people = Vec::with_capacity(recipients.len());
+    for recipient in recipients {
+        let threads = thread_stmt
+            .query_map(params![recipient.id], thread_summary_from_row)?
+            .collect::<Result<_, _>>()?;
+        people.push(PersonMatch { recipient, threads });
+    }
+    Ok(people)
+}
+
+/// One-shot search for the search palette: matching people, threads whose name matches,
+/// and prefix-matched message hits.
+pub fn global_search(conn: &Connection, query: &str, limit: i64) -> Result<GlobalSearchResult, CoreError> {
+    let people = search_people(conn, query, limit)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT t.id, t.name, t.last_message_at, \
+         (SELECT COUNT(1) FROM messages m WHERE m.thread_id = t.id) AS message_count, \
+         t.expire_timer_ms \
+         FROM threads t \
+         WHERE t.name LIKE ?1 ESCAPE '\\' \
+         ORDER BY t.last_message_at DESC NULLS LAST, t.id ASC \
+         LIMIT ?2;",
+    )?;
+    let threads = if query.trim().is_empty() {
+        Vec::new()
+    } else {
+        stmt.query_map(params![like_pattern(query.trim()), limit], thread_summary_from_row)?
+            .collect::<Result<_, _>>()?
+    };
+
+    let message_hits = search_messages(
+        conn,
+        query,
+        &SearchOptions {
+            prefix: true,
+            ..SearchOptions::default()
+        },
+        limit,
+        0,
+    )?;
+    Ok(GlobalSearchResult {
+        people,
+        threads,
+        message_hits,
+    })
 }
 
 pub fn list_reactions_for_messages(
@@ -242,6 +731,35 @@ pub fn list_reactions_for_messages(
     Ok(rows.filter_map(Result::ok).collect())
 }
 
+/// Mentions in the given messages, in body order, with the mentioned recipient's name.
+pub fn list_mentions_for_messages(conn: &Connection, message_ids: &[String]) -> Result<Vec<Mention>, CoreError> {
+    if message_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let sql = format!(
+        "SELECT mn.message_id, mn.recipient_id, \
+                COALESCE(r.nickname, r.contact_name, r.profile_name, r.username, r.phone_e164), \
+                mn.range_start, mn.range_length \
+         FROM mentions mn \
+         LEFT JOIN recipients r ON r.id = mn.recipient_id \
+         WHERE mn.message_id IN ({}) \
+         ORDER BY mn.message_id, mn.range_start;",
+        placeholders(message_ids.len())
+    );
+    let params_vec: Vec<rusqlite::types::Value> = message_ids.iter().cloned().map(|v| v.into()).collect();
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt.query_map(rusqlite::params_from_iter(params_vec), |row| {
+        Ok(Mention {
+            message_id: row.get(0)?,
+            recipient_id: row.get(1)?,
+            display_name: row.get(2)?,
+            range_start: row.get(3)?,
+            range_length: row.get(4)?,
+        })
+    })?;
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
 pub fn list_media(
     conn: &Connection,
     thread_id: Option<&str>,
@@ -250,7 +768,7 @@ pub fn list_media(
 ) -> Result<Vec<MediaRow>, CoreError> {
     let mut stmt = conn.prepare(
         "SELECT a.id, a.message_id, a.sha256, a.mime, a.size_bytes, a.original_filename, \
-                a.kind, a.width, a.height, a.duration_ms \
+                a.kind, a.width, a.height, a.duration_ms, a.blurhash \
          FROM attachments a \
          JOIN messages m ON m.id = a.message_id \
          WHERE (?1 IS NULL OR m.thread_id = ?1) \
@@ -261,35 +779,101 @@ pub fn list_media(
     Ok(rows.filter_map(Result::ok).collect())
 }
 
-pub fn list_thread_media(
-    conn: &Connection,
+/// One attachment with its message and thread name. Unknown ids are an
+/// "attachment not found" error rather than a raw SQLite one.
+pub fn get_attachment(conn: &Connection, attachment_id: &str) -> Result<AttachmentDetail, CoreError> {
+    let found = conn
+        .query_row(
+            "SELECT a.id, a.message_id, a.sha256, a.mime, a.size_bytes, a.original_filename, \
+                    a.kind, a.width, a.height, a.duration_ms, a.blurhash, t.name \
+             FROM attachments a \
+             JOIN messages m ON m.id = a.message_id \
+             LEFT JOIN threads t ON t.id = m.thread_id \
+             WHERE a.id = ?1;",
+            params![attachment_id],
+            |row| Ok((media_from_row(row)?, row.get::<_, Option<String>>(11)?)),
+        )
+        .optional()?;
+    let Some((media, thread_name)) = found else {
+        return Err(CoreError::InvalidArgument("attachment not found".to_string()));
+    };
+    let message = get_message(conn, &media.message_id)?;
+    Ok(AttachmentDetail {
+        media,
+        message,
+        thread_name,
+    })
+}
+
+/// The recorded MIME type of the blob `sha256`, or `None` when no attachment row has one.
+pub fn attachment_mime(conn: &Connection, sha256: &str) -> Result<Option<String>, CoreError> {
+    let mime = conn
+        .query_row(
+            "SELECT mime FROM attachments WHERE sha256 = ?1 AND mime IS NOT NULL ORDER BY id ASC LIMIT 1;",
+            params![sha256],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(mime)
+}
+
+/// Values the importer stores in `attachments.kind`.
+pub const MEDIA_KINDS: &[&str] = &["image", "video", "audio", "document", "file"];
+
+fn validate_media_kind(kind: Option<&str>) -> Result<(), CoreError> {
+    match kind {
+        Some(kind) if !MEDIA_KINDS.contains(&kind) => Err(CoreError::InvalidArgument(format!(
+            "unknown media kind {:?}; expected one of {}",
+            kind,
+            MEDIA_KINDS.join(", ")
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// WHERE clause and parameters shared by the thread media listing and its summary.
+fn thread_media_filter(
     thread_id: &str,
     from_ts: Option<i64>,
     to_ts: Option<i64>,
     size_bucket: Option<i64>,
-    sort: &str,
-    limit: i64,
-    offset: i64,
-) -> Result<Vec<ThreadMediaRow>, CoreError> {
+    kind: Option<&str>,
+) -> (String, Vec<rusqlite::types::Value>) {
     let mut where_clauses = vec!["m.thread_id = ?1".to_string()];
     let mut params: Vec<rusqlite::types::Value> = vec![thread_id.to_string().into()];
-    let mut next_idx = 2;
 
     if let Some(from_ts) = from_ts {
-        where_clauses.push(format!("COALESCE(m.sent_at, m.received_at, 0) >= ?{}", next_idx));
         params.push(from_ts.into());
-        next_idx += 1;
+        where_clauses.push(format!("COALESCE(m.sent_at, m.received_at, 0) >= ?{}", params.len()));
     }
     if let Some(to_ts) = to_ts {
-        where_clauses.push(format!("COALESCE(m.sent_at, m.received_at, 0) <= ?{}", next_idx));
         params.push(to_ts.into());
-        next_idx += 1;
+        where_clauses.push(format!("COALESCE(m.sent_at, m.received_at, 0) <= ?{}", params.len()));
     }
     if let Some(size_bucket) = size_bucket {
-        where_clauses.push(format!("a.size_bucket = ?{}", next_idx));
         params.push(size_bucket.into());
-        next_idx += 1;
+        where_clauses.push(format!("a.size_bucket = ?{}", params.len()));
+    }
+    if let Some(kind) = kind {
+        params.push(kind.to_string().into());
+        where_clauses.push(format!("a.kind = ?{}", params.len()));
     }
+    (where_clauses.join(" AND "), params)
+}
+
+pub fn list_thread_media(
+    conn: &Connection,
+    thread_id: &str,
+    filters: &ThreadMediaFilters,
+    sort: &str,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<ThreadMediaRow>, CoreError> {
+    let kind = filters.kind.as_deref();
+    validate_media_kind(kind)?;
+    let (where_sql, mut params) =
+        thread_media_filter(thread_id, filters.from_ts, filters.to_ts, filters.size_bucket, kind);
+    let next_idx = params.len() + 1;
 
     let order_by = match sort {
         "size_asc" => "IFNULL(a.size_bytes, 0) ASC, COALESCE(m.sent_at, m.received_at, 0) DESC",
@@ -300,13 +884,13 @@ pub fn list_thread_media(
 
     let sql = format!(
         "SELECT a.id, a.message_id, m.thread_id, a.sha256, a.mime, a.size_bytes, a.original_filename, \
-                a.kind, a.width, a.height, a.duration_ms, m.sent_at, m.received_at \
+                a.kind, a.width, a.height, a.duration_ms, m.sent_at, m.received_at, a.blurhash \
          FROM attachments a \
          JOIN messages m ON m.id = a.message_id \
          WHERE {} \
          ORDER BY {} \
          LIMIT ?{} OFFSET ?{};",
-        where_clauses.join(" AND "),
+        where_sql,
         order_by,
         next_idx,
         next_idx + 1
@@ -315,33 +899,229 @@ pub fn list_thread_media(
     params.push(offset.into());
 
     let mut stmt = conn.prepare(&sql)?;
-    let rows = stmt.query_map(rusqlite::params_from_iter(params), |row| {
-        Ok(ThreadMediaRow {
-            id: row.get(0)?,
-            message_id: row.get(1)?,
-            thread_id: row.get(2)?,
-            sha256: row.get(3)?,
-            mime: row.get(4)?,
-            size_bytes: row.get(5)?,
-            original_filename: row.get(6)?,
-            kind: row.get(7)?,
-            width: row.get(8)?,
-            height: row.get(9)?,
-            duration_ms: row.get(10)?,
-            sent_at: row.get(11)?,
-            received_at: row.get(12)?,
+    let rows = stmt.query_map(rusqlite::params_from_iter(params), thread_media_from_row)?;
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+/// Count, byte total and per-kind counts for the media [`list_thread_media`] would return
+/// with the same filters, for gallery headers and scroller sizing.
+pub fn thread_media_summary(
+    conn: &Connection,
+    thread_id: &str,
+    from_ts: Option<i64>,
+    to_ts: Option<i64>,
+    size_bucket: Option<i64>,
+) -> Result<ThreadMediaSummary, CoreError> {
+    let (where_sql, params) = thread_media_filter(thread_id, from_ts, to_ts, size_bucket, None);
+    let (total_count, total_bytes) = conn.query_row(
+        &format!(
+            "SELECT COUNT(1), COALESCE(SUM(a.size_bytes), 0) \
+             FROM attachments a \
+             JOIN messages m ON m.id = a.message_id \
+             WHERE {};",
+            where_sql
+        ),
+        rusqlite::params_from_iter(params.iter()),
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT a.kind, COUNT(1) \
+         FROM attachments a \
+         JOIN messages m ON m.id = a.message_id \
+         WHERE {} \
+         GROUP BY a.kind \
+         ORDER BY COUNT(1) DESC, a.kind ASC;",
+        where_sql
+    ))?;
+    let kinds = stmt
+        .query_map(rusqlite::params_from_iter(params.iter()), |row| {
+            Ok(MediaKindCount {
+                kind: row.get(0)?,
+                count: row.get(1)?,
+            })
+        })?
+        .filter_map(Result::ok)
+        .collect();
+
+    Ok(ThreadMediaSummary {
+        total_count,
+        total_bytes,
+        kinds,
+    })
+}
+
+/// Attachment counts per month of a thread's media, newest month first, so the gallery can
+/// lay out its month sections before paging rows with [`list_thread_media`].
+pub fn thread_media_month_buckets(
+    conn: &Connection,
+    thread_id: &str,
+    kind: Option<&str>,
+) -> Result<Vec<MediaMonthBucket>, CoreError> {
+    validate_media_kind(kind)?;
+    let mut stmt = conn.prepare(
+        "SELECT CAST(strftime('%Y', ts / 1000, 'unixepoch') AS INTEGER) AS year, \
+                CAST(strftime('%m', ts / 1000, 'unixepoch') AS INTEGER) AS month, \
+                COUNT(1) \
+         FROM ( \
+           SELECT COALESCE(m.sent_at, m.received_at, 0) AS ts \
+           FROM attachments a \
+           JOIN messages m ON m.id = a.message_id \
+           WHERE m.thread_id = ?1 AND (?2 IS NULL OR a.kind = ?2) \
+         ) \
+         GROUP BY year, month \
+         ORDER BY year DESC, month DESC;",
+    )?;
+    let rows = stmt.query_map(params![thread_id, kind], |row| {
+        Ok(MediaMonthBucket {
+            year: row.get(0)?,
+            month: row.get(1)?,
+            item_count: row.get(2)?,
+        })
+    })?;
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+/// The biggest attachments in the archive, largest first. Attachments without a recorded
+/// size are left out.
+pub fn largest_attachments(
+    conn: &Connection,
+    limit: i64,
+    kind: Option<&str>,
+) -> Result<Vec<LargeAttachment>, CoreError> {
+    validate_media_kind(kind)?;
+    let mut stmt = conn.prepare(
+        "SELECT a.id, a.message_id, m.thread_id, a.sha256, a.mime, a.size_bytes, a.original_filename, \
+                a.kind, a.width, a.height, a.duration_ms, m.sent_at, m.received_at, a.blurhash, t.name \
+         FROM attachments a \
+         JOIN messages m ON m.id = a.message_id \
+         LEFT JOIN threads t ON t.id = m.thread_id \
+         WHERE a.size_bytes IS NOT NULL AND (?1 IS NULL OR a.kind = ?1) \
+         ORDER BY a.size_bytes DESC, a.id ASC \
+         LIMIT ?2;",
+    )?;
+    let rows = stmt.query_map(params![kind, limit], |row| {
+        Ok(LargeAttachment {
+            media: thread_media_from_row(row)?,
+            thread_name: row.get(14)?,
         })
     })?;
     Ok(rows.filter_map(Result::ok).collect())
 }
 
+/// Non-media attachments (`document` and `file` kinds), newest first, optionally limited to
+/// one thread and to original filenames containing `filename_needle` (case-insensitive).
+pub fn list_document_attachments(
+    conn: &Connection,
+    thread_id: Option<&str>,
+    filename_needle: Option<&str>,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<ThreadMediaRow>, CoreError> {
+    let pattern = filename_needle
+        .map(str::trim)
+        .filter(|needle| !needle.is_empty())
+        .map(like_pattern);
+    let mut stmt = conn.prepare(
+        "SELECT a.id, a.message_id, m.thread_id, a.sha256, a.mime, a.size_bytes, a.original_filename, \
+                a.kind, a.width, a.height, a.duration_ms, m.sent_at, m.received_at, a.blurhash \
+         FROM attachments a \
+         JOIN messages m ON m.id = a.message_id \
+         WHERE a.kind IN ('document', 'file') \
+           AND (?1 IS NULL OR m.thread_id = ?1) \
+           AND (?2 IS NULL OR a.original_filename LIKE ?2 ESCAPE '\\') \
+         ORDER BY COALESCE(m.sent_at, m.received_at, 0) DESC, a.id ASC \
+         LIMIT ?3 OFFSET ?4;",
+    )?;
+    let rows = stmt.query_map(params![thread_id, pattern, limit, offset], thread_media_from_row)?;
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+/// Sender selector for [`list_media_by_sender`] meaning "media I sent". Outgoing messages
+/// often have no `sender_id`, so they are matched on `is_outgoing` instead.
+pub const OUTGOING_SENDER: &str = "self";
+
+/// Media from one sender across all threads, newest first.
+pub fn list_media_by_sender(
+    conn: &Connection,
+    sender_id: &str,
+    kind: Option<&str>,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<ThreadMediaRow>, CoreError> {
+    validate_media_kind(kind)?;
+    let sender_clause = if sender_id == OUTGOING_SENDER {
+        "m.is_outgoing = 1"
+    } else {
+        "m.sender_id = ?1"
+    };
+    let sql = format!(
+        "SELECT a.id, a.message_id, m.thread_id, a.sha256, a.mime, a.size_bytes, a.original_filename, \
+                a.kind, a.width, a.height, a.duration_ms, m.sent_at, m.received_at, a.blurhash \
+         FROM attachments a \
+         JOIN messages m ON m.id = a.message_id \
+         WHERE {} AND (?2 IS NULL OR a.kind = ?2) \
+         ORDER BY COALESCE(m.sent_at, m.received_at, 0) DESC, a.id ASC \
+         LIMIT ?3 OFFSET ?4;",
+        sender_clause
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt.query_map(params![sender_id, kind, limit, offset], thread_media_from_row)?;
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+/// Sum of `size_bytes` over every attachment in the archive.
+pub fn attachment_total_bytes(conn: &Connection) -> Result<i64, CoreError> {
+    let total = conn.query_row("SELECT COALESCE(SUM(size_bytes), 0) FROM attachments;", [], |row| row.get(0))?;
+    Ok(total)
+}
+
+/// Media bytes per thread, deduplicated by `sha256` within each thread (the same photo
+/// sent twice is stored once), plus the archive-wide total over distinct blobs.
+pub fn thread_storage_usage(conn: &Connection) -> Result<StorageUsage, CoreError> {
+    let mut stmt = conn.prepare(
+        "SELECT b.thread_id, t.name, COUNT(1), SUM(b.size_bytes) AS total_bytes \
+         FROM ( \
+             SELECT m.thread_id AS thread_id, a.sha256, MAX(COALESCE(a.size_bytes, 0)) AS size_bytes \
+             FROM attachments a \
+             JOIN messages m ON m.id = a.message_id \
+             GROUP BY m.thread_id, a.sha256 \
+         ) b \
+         LEFT JOIN threads t ON t.id = b.thread_id \
+         GROUP BY b.thread_id \
+         ORDER BY total_bytes DESC, b.thread_id ASC;",
+    )?;
+    let threads = stmt
+        .query_map([], |row| {
+            Ok(ThreadStorage {
+                thread_id: row.get(0)?,
+                thread_name: row.get(1)?,
+                blob_count: row.get(2)?,
+                total_bytes: row.get(3)?,
+            })
+        })?
+        .filter_map(Result::ok)
+        .collect();
+    let unique_total_bytes = conn.query_row(
+        "SELECT COALESCE(SUM(size_bytes), 0) \
+         FROM (SELECT MAX(COALESCE(size_bytes, 0)) AS size_bytes FROM attachments GROUP BY sha256);",
+        [],
+        |row| row.get(0),
+    )?;
+    Ok(StorageUsage {
+        threads,
+        unique_total_bytes,
+    })
+}
+
 pub fn list_attachments_for_message(
     conn: &Connection,
     message_id: &str,
 ) -> Result<Vec<MediaRow>, CoreError> {
     let mut stmt = conn.prepare(
         "SELECT id, message_id, sha256, mime, size_bytes, original_filename, \
-                kind, width, height, duration_ms \
+                kind, width, height, duration_ms, \
+                blurhash \
          FROM attachments \
          WHERE message_id = ?1 \
          ORDER BY id ASC;",
@@ -374,27 +1154,141 @@ pub fn archive_stats(conn: &Connection) -> Result<ArchiveStats, CoreError> {
     })
 }
 
-// ===== Tag Management Functions =====
+fn import_record_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<ImportRecord> {
+    let stats_json: Option<String> = row.get(4)?;
+    let stats: serde_json::Value = stats_json
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or(serde_json::Value::Null);
+    Ok(ImportRecord {
+        id: row.get(0)?,
+        imported_at: row.get(1)?,
+        source_filename: row.get(2)?,
+        status: row.get(3)?,
+        detected_version: row.get(5)?,
+        messages_inserted: stats["messages_inserted_total"].as_i64(),
+        attachments_found: stats["attachments_found"].as_i64(),
+        attachments_missing: stats["attachments_missing"].as_i64(),
+        error: stats["error"].as_str().map(str::to_string),
+    })
+}
 
-pub fn list_tags(conn: &Connection) -> Result<Vec<Tag>, CoreError> {
+/// Every recorded import run, newest first.
+pub fn list_imports(conn: &Connection) -> Result<Vec<ImportRecord>, CoreError> {
     let mut stmt = conn.prepare(
-        "SELECT id, name, color, created_at, display_order \
-         FROM tags \
-         ORDER BY display_order ASC, created_at ASC;"
+        "SELECT id, imported_at, source_filename, status, stats_json, detected_version \
+         FROM imports \
+         ORDER BY imported_at DESC, id ASC;",
     )?;
-    let rows = stmt.query_map([], |row| tag_from_row(row, 0))?;
-    Ok(rows.filter_map(Result::ok).collect())
+    let rows = stmt.query_map([], import_record_from_row)?;
+    Ok(rows.collect::<Result<_, _>>()?)
 }
 
-pub fn create_tag(conn: &Connection, name: &str, color: &str) -> Result<Tag, CoreError> {
-    let tx = conn.unchecked_transaction()?;
+pub fn get_last_successful_import(conn: &Connection) -> Result<Option<ImportRecord>, CoreError> {
+    Ok(conn
+        .query_row(
+            "SELECT id, imported_at, source_filename, status, stats_json, detected_version \
+             FROM imports \
+             WHERE status = 'success' \
+             ORDER BY imported_at DESC, id ASC \
+             LIMIT 1;",
+            [],
+            import_record_from_row,
+        )
+        .optional()?)
+}
 
-    // Generate a simple ID (timestamp-based)
-    let now = std::time::SystemTime::now()
+/// The issues recorded for `import_id`, in the order the import ran into them.
+pub fn list_import_issues(conn: &Connection, import_id: &str) -> Result<Vec<ImportIssue>, CoreError> {
+    let mut stmt = conn.prepare(
+        "SELECT category, source_table, source_id, detail \
+         FROM import_issues \
+         WHERE import_id = ?1 \
+         ORDER BY rowid ASC;",
+    )?;
+    let rows = stmt.query_map(params![import_id], |row| {
+        Ok(ImportIssue {
+            category: row.get(0)?,
+            source_table: row.get(1)?,
+            source_id: row.get(2)?,
+            detail: row.get(3)?,
+        })
+    })?;
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+// ===== Tag Management Functions =====
+
+/// Colors offered for new tags, in preference order. Mirrors `TAG_COLOR_PRESETS` in the UI.
+pub const TAG_PALETTE: &[&str] = &[
+    "#c3684a", "#d3a24b", "#9b7a52", "#5f8f6b", "#5f88b3", "#b36a78", "#7a6bb0", "#4f8f86",
+];
+
+/// Accepts `#rrggbb` in either case and returns it lowercased.
+fn normalize_tag_color(color: &str) -> Result<String, CoreError> {
+    match color.strip_prefix('#') {
+        Some(hex) if hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()) => {
+            Ok(format!("#{}", hex.to_ascii_lowercase()))
+        }
+        _ => Err(CoreError::InvalidArgument(format!(
+            "tag color must be a #rrggbb hex string, got {:?}",
+            color
+        ))),
+    }
+}
+
+pub fn list_tags(conn: &Connection) -> Result<Vec<Tag>, CoreError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, name, color, created_at, display_order \
+         FROM tags \
+         ORDER BY display_order ASC, created_at ASC;"
+    )?;
+    let rows = stmt.query_map([], |row| tag_from_row(row, 0))?;
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+/// How tag names are compared everywhere: ignoring case and surrounding whitespace, so
+/// "Work" and " work" are one tag. Binds the candidate name as `?1`.
+const TAG_NAME_MATCHES: &str = "lower(trim(name)) = lower(trim(?1))";
+
+/// Rejects `name` if another tag (other than `except_id`) already uses it, per
+/// [`TAG_NAME_MATCHES`], so a duplicate name surfaces as a clear error instead of two tags
+/// quick-tag can't tell apart.
+fn ensure_tag_name_free(conn: &Connection, name: &str, except_id: Option<&str>) -> Result<(), CoreError> {
+    let taken: Option<i64> = conn
+        .query_row(
+            &format!("SELECT 1 FROM tags WHERE {TAG_NAME_MATCHES} AND (?2 IS NULL OR id != ?2) LIMIT 1;"),
+            params![name, except_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    match taken {
+        Some(_) => Err(CoreError::InvalidArgument(format!("a tag named {:?} already exists", name))),
+        None => Ok(()),
+    }
+}
+
+/// A tag name with surrounding whitespace removed; blank names are rejected.
+fn trimmed_tag_name(name: &str) -> Result<&str, CoreError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(CoreError::InvalidArgument("tag name must not be empty".to_string()));
+    }
+    Ok(name)
+}
+
+/// Creates a tag with id `tag:{uuid}`. Older archives hold `tag:{millis}` ids; ids are
+/// opaque everywhere else, so both forms keep working side by side.
+pub fn create_tag(conn: &Connection, name: &str, color: &str) -> Result<Tag, CoreError> {
+    let name = trimmed_tag_name(name)?;
+    let color = normalize_tag_color(color)?;
+    let tx = conn.unchecked_transaction()?;
+    ensure_tag_name_free(&tx, name, None)?;
+
+    let now = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .unwrap()
         .as_millis() as i64;
-    let id = format!("tag:{}", now);
+    let id = format!("tag:{}", Uuid::new_v4());
 
     // Get next display order
     let max_order: Option<i64> = tx
@@ -405,20 +1299,43 @@ pub fn create_tag(conn: &Connection, name: &str, color: &str) -> Result<Tag, Cor
 
     tx.execute(
         "INSERT INTO tags (id, name, color, created_at, display_order) VALUES (?1, ?2, ?3, ?4, ?5);",
-        params![&id, name, color, now, display_order],
+        params![&id, name, &color, now, display_order],
     )?;
 
     tx.commit()?;
     Ok(Tag {
         id,
         name: name.to_string(),
-        color: color.to_string(),
+        color,
         created_at: now,
         display_order,
     })
 }
 
+/// Returns the tag whose name matches `name` ignoring case and surrounding whitespace,
+/// creating it with `default_color` if none does. Backs the quick-tag flow.
+pub fn get_or_create_tag(conn: &Connection, name: &str, default_color: &str) -> Result<Tag, CoreError> {
+    let name = trimmed_tag_name(name)?;
+    let existing = conn
+        .query_row(
+            &format!(
+                "SELECT id, name, color, created_at, display_order FROM tags WHERE {TAG_NAME_MATCHES} \
+                 ORDER BY display_order ASC, created_at ASC LIMIT 1;"
+            ),
+            params![name],
+            |row| tag_from_row(row, 0),
+        )
+        .optional()?;
+    match existing {
+        Some(tag) => Ok(tag),
+        None => create_tag(conn, name, default_color),
+    }
+}
+
 pub fn update_tag(conn: &Connection, id: &str, name: &str, color: &str) -> Result<(), CoreError> {
+    let name = trimmed_tag_name(name)?;
+    let color = normalize_tag_color(color)?;
+    ensure_tag_name_free(conn, name, Some(id))?;
     conn.execute(
         "UPDATE tags SET name = ?1, color = ?2 WHERE id = ?3;",
         params![name, color, id],
@@ -426,9 +1343,117 @@ pub fn update_tag(conn: &Connection, id: &str, name: &str, color: &str) -> Resul
     Ok(())
 }
 
-pub fn delete_tag(conn: &Connection, id: &str) -> Result<(), CoreError> {
-    // CASCADE DELETE will handle message_tags cleanup
-    conn.execute("DELETE FROM tags WHERE id = ?1;", params![id])?;
+/// Picks the [`TAG_PALETTE`] color used by the fewest existing tags, earliest in the
+/// palette on ties, so consecutive new tags get distinct colors.
+pub fn suggest_tag_color(conn: &Connection) -> Result<String, CoreError> {
+    let mut stmt = conn.prepare("SELECT lower(color), COUNT(1) FROM tags GROUP BY lower(color);")?;
+    let usage: std::collections::HashMap<String, i64> = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .filter_map(Result::ok)
+        .collect();
+    let color = TAG_PALETTE
+        .iter()
+        .min_by_key(|color| usage.get(**color).copied().unwrap_or(0))
+        .unwrap_or(&TAG_PALETTE[0]);
+    Ok(color.to_string())
+}
+
+/// Usage above which the app asks for confirmation before deleting a tag.
+pub const TAG_DELETE_CONFIRM_USES: usize = 50;
+
+/// Messages and attachments carrying the tag.
+fn tag_use_count(conn: &Connection, id: &str) -> Result<usize, CoreError> {
+    let uses: i64 = conn.query_row(
+        "SELECT (SELECT COUNT(1) FROM message_tags WHERE tag_id = ?1) \
+              + (SELECT COUNT(1) FROM attachment_tags WHERE tag_id = ?1);",
+        params![id],
+        |row| row.get(0),
+    )?;
+    Ok(uses as usize)
+}
+
+/// Deletes a tag and returns how many messages and attachments it was removed from.
+pub fn delete_tag(conn: &Connection, id: &str) -> Result<usize, CoreError> {
+    delete_tag_checked(conn, id, usize::MAX, true)
+}
+
+/// Like [`delete_tag`], but refuses when the tag is on more than `expected_max_uses`
+/// messages and attachments together unless `force` is set. The error carries the real
+/// count for the confirmation. Counting and deleting share a transaction, so a tagging in
+/// between can't slip past the check.
+pub fn delete_tag_checked(
+    conn: &Connection,
+    id: &str,
+    expected_max_uses: usize,
+    force: bool,
+) -> Result<usize, CoreError> {
+    let tx = conn.unchecked_transaction()?;
+    let uses = tag_use_count(&tx, id)?;
+    if !force && uses > expected_max_uses {
+        return Err(CoreError::InvalidArgument(format!(
+            "tag is used on {} items; confirm to delete it",
+            uses
+        )));
+    }
+    // CASCADE DELETE will handle message_tags and attachment_tags cleanup
+    tx.execute("DELETE FROM tags WHERE id = ?1;", params![id])?;
+    tx.commit()?;
+    Ok(uses)
+}
+
+/// Folds `source_tag_id` into `target_tag_id` and deletes the source tag.
+///
+/// Every message or attachment tagged with the source ends up tagged with the target. When
+/// one carried both, it keeps a single row with the earlier of the two `tagged_at` values.
+pub fn merge_tags(conn: &Connection, source_tag_id: &str, target_tag_id: &str) -> Result<(), CoreError> {
+    if source_tag_id == target_tag_id {
+        return Err(CoreError::InvalidArgument("cannot merge a tag into itself".to_string()));
+    }
+    let tx = conn.unchecked_transaction()?;
+    let found: i64 = tx.query_row(
+        "SELECT COUNT(1) FROM tags WHERE id IN (?1, ?2);",
+        params![source_tag_id, target_tag_id],
+        |row| row.get(0),
+    )?;
+    if found != 2 {
+        return Err(CoreError::InvalidArgument("tag not found".to_string()));
+    }
+
+    // Messages carrying both tags keep the earliest tagged_at on the surviving row.
+    tx.execute(
+        "UPDATE message_tags \
+         SET tagged_at = MIN(tagged_at, ( \
+           SELECT src.tagged_at FROM message_tags src \
+           WHERE src.message_id = message_tags.message_id AND src.tag_id = ?1)) \
+         WHERE tag_id = ?2 \
+           AND message_id IN (SELECT message_id FROM message_tags WHERE tag_id = ?1);",
+        params![source_tag_id, target_tag_id],
+    )?;
+    tx.execute(
+        "INSERT OR IGNORE INTO message_tags (message_id, tag_id, tagged_at) \
+         SELECT message_id, ?2, tagged_at FROM message_tags WHERE tag_id = ?1;",
+        params![source_tag_id, target_tag_id],
+    )?;
+    tx.execute("DELETE FROM message_tags WHERE tag_id = ?1;", params![source_tag_id])?;
+
+    // Same again for tagged attachments.
+    tx.execute(
+        "UPDATE attachment_tags \
+         SET tagged_at = MIN(tagged_at, ( \
+           SELECT src.tagged_at FROM attachment_tags src \
+           WHERE src.attachment_id = attachment_tags.attachment_id AND src.tag_id = ?1)) \
+         WHERE tag_id = ?2 \
+           AND attachment_id IN (SELECT attachment_id FROM attachment_tags WHERE tag_id = ?1);",
+        params![source_tag_id, target_tag_id],
+    )?;
+    tx.execute(
+        "INSERT OR IGNORE INTO attachment_tags (attachment_id, tag_id, tagged_at) \
+         SELECT attachment_id, ?2, tagged_at FROM attachment_tags WHERE tag_id = ?1;",
+        params![source_tag_id, target_tag_id],
+    )?;
+    tx.execute("DELETE FROM attachment_tags WHERE tag_id = ?1;", params![source_tag_id])?;
+    tx.execute("DELETE FROM tags WHERE id = ?1;", params![source_tag_id])?;
+    tx.commit()?;
     Ok(())
 }
 
@@ -450,7 +1475,7 @@ pub fn get_message_tags_bulk(conn: &Connection, message_ids: &[String]) -> Resul
     }
     let placeholders = placeholders(message_ids.len());
     let sql = format!(
-        "SELECT mt.message_id, t.id, t.name, t.color, t.created_at, t.display_order \
+        "SELECT mt.message_id, t.id, t.name, t.color, t.created_at, t.display_order, mt.tagged_at \
          FROM message_tags mt \
          JOIN tags t ON mt.tag_id = t.id \
          WHERE mt.message_id IN ({}) \
@@ -459,10 +1484,16 @@ pub fn get_message_tags_bulk(conn: &Connection, message_ids: &[String]) -> Resul
     );
     let mut stmt = conn.prepare(&sql)?;
     let rows = stmt.query_map(rusqlite::params_from_iter(message_ids.iter()), |row| {
-        Ok((row.get::<_, String>(0)?, tag_from_row(row, 1)?))
+        Ok((
+            row.get::<_, String>(0)?,
+            TaggedTag {
+                tag: tag_from_row(row, 1)?,
+                tagged_at: row.get(6)?,
+            },
+        ))
     })?;
 
-    let mut map: std::collections::HashMap<String, Vec<Tag>> = std::collections::HashMap::new();
+    let mut map: std::collections::HashMap<String, Vec<TaggedTag>> = std::collections::HashMap::new();
     for row in rows {
         let (message_id, tag) = row?;
         map.entry(message_id).or_default().push(tag);
@@ -479,22 +1510,193 @@ pub fn get_message_tags_bulk(conn: &Connection, message_ids: &[String]) -> Resul
     Ok(result)
 }
 
+/// Drops repeated ids (keeping first occurrences) and fails with `InvalidArgument` naming
+/// the first id that has no matching tag.
+fn existing_tag_ids(conn: &Connection, tag_ids: &[String]) -> Result<Vec<String>, CoreError> {
+    let mut seen = std::collections::HashSet::new();
+    let unique: Vec<String> = tag_ids.iter().filter(|id| seen.insert(id.as_str())).cloned().collect();
+    if unique.is_empty() {
+        return Ok(unique);
+    }
+    let sql = format!("SELECT id FROM tags WHERE id IN ({});", placeholders(unique.len()));
+    let mut stmt = conn.prepare(&sql)?;
+    let found: std::collections::HashSet<String> = stmt
+        .query_map(rusqlite::params_from_iter(unique.iter()), |row| row.get(0))?
+        .filter_map(Result::ok)
+        .collect();
+    if let Some(missing) = unique.iter().find(|id| !found.contains(*id)) {
+        return Err(CoreError::InvalidArgument(format!("unknown tag id {:?}", missing)));
+    }
+    Ok(unique)
+}
+
+/// Replaces all tags on a message.
+///
+/// Every tag id is checked before anything is written, and the delete and insert share one
+/// transaction, so a bad id leaves the message's existing tags untouched.
 pub fn set_message_tags(conn: &Connection, message_id: &str, tag_ids: &[String]) -> Result<(), CoreError> {
     let tx = conn.unchecked_transaction()?;
+    let tag_ids = existing_tag_ids(&tx, tag_ids)?;
 
-    // Delete existing tags for this message
     tx.execute("DELETE FROM message_tags WHERE message_id = ?1;", params![message_id])?;
 
-    // Insert new tags
+    if !tag_ids.is_empty() {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as i64;
+        let sql = format!(
+            "INSERT INTO message_tags (message_id, tag_id, tagged_at) VALUES {};",
+            vec!["(?, ?, ?)"; tag_ids.len()].join(",")
+        );
+        let mut values: Vec<rusqlite::types::Value> = Vec::with_capacity(tag_ids.len() * 3);
+        for tag_id in tag_ids {
+            values.push(message_id.to_string().into());
+            values.push(tag_id.into());
+            values.push(now.into());
+        }
+        tx.execute(&sql, rusqlite::params_from_iter(values))?;
+    }
+
+    tx.commit()?;
+    Ok(())
+}
+
+/// Adds a single tag to a message without touching its other tags.
+///
+/// Re-adding a tag the message already carries is a no-op and keeps the original
+/// `tagged_at`, so the scrapbook order doesn't shift.
+pub fn add_message_tag(conn: &Connection, message_id: &str, tag_id: &str) -> Result<(), CoreError> {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as i64;
+    conn.execute(
+        "INSERT OR IGNORE INTO message_tags (message_id, tag_id, tagged_at) VALUES (?1, ?2, ?3);",
+        params![message_id, tag_id, now],
+    )?;
+    Ok(())
+}
+
+/// Removes a single tag from a message, leaving its other tags in place.
+pub fn remove_message_tag(conn: &Connection, message_id: &str, tag_id: &str) -> Result<(), CoreError> {
+    conn.execute(
+        "DELETE FROM message_tags WHERE message_id = ?1 AND tag_id = ?2;",
+        params![message_id, tag_id],
+    )?;
+    Ok(())
+}
+
+/// Rows per statement for bulk tag writes, keeping well under SQLite's bound-parameter limit.
+const BULK_TAG_CHUNK: usize = 300;
+
+/// Tags every message in `message_ids` with `tag_id` in one transaction.
+///
+/// Messages that already carry the tag are left alone (their `tagged_at` is kept).
+/// Returns how many messages were newly tagged.
+pub fn add_tag_to_messages(conn: &Connection, tag_id: &str, message_ids: &[String]) -> Result<usize, CoreError> {
+    let tx = conn.unchecked_transaction()?;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as i64;
+    let mut changed = 0;
+    for chunk in message_ids.chunks(BULK_TAG_CHUNK) {
+        let sql = format!(
+            "INSERT OR IGNORE INTO message_tags (message_id, tag_id, tagged_at) VALUES {};",
+            vec!["(?, ?, ?)"; chunk.len()].join(",")
+        );
+        let mut values: Vec<rusqlite::types::Value> = Vec::with_capacity(chunk.len() * 3);
+        for message_id in chunk {
+            values.push(message_id.clone().into());
+            values.push(tag_id.to_string().into());
+            values.push(now.into());
+        }
+        changed += tx.execute(&sql, rusqlite::params_from_iter(values))?;
+    }
+    tx.commit()?;
+    Ok(changed)
+}
+
+/// Removes `tag_id` from every message in `message_ids` in one transaction.
+///
+/// Returns how many messages actually lost the tag.
+pub fn remove_tag_from_messages(conn: &Connection, tag_id: &str, message_ids: &[String]) -> Result<usize, CoreError> {
+    let tx = conn.unchecked_transaction()?;
+    let mut changed = 0;
+    for chunk in message_ids.chunks(BULK_TAG_CHUNK) {
+        let sql = format!(
+            "DELETE FROM message_tags WHERE tag_id = ? AND message_id IN ({});",
+            placeholders(chunk.len())
+        );
+        let values = std::iter::once(tag_id).chain(chunk.iter().map(String::as_str));
+        changed += tx.execute(&sql, rusqlite::params_from_iter(values))?;
+    }
+    tx.commit()?;
+    Ok(changed)
+}
+
+/// Counts and time bounds for everything tagged with `tag_id`.
+///
+/// `attachment_count` covers attachments on tagged messages, not attachments tagged directly.
+pub fn tag_stats(conn: &Connection, tag_id: &str) -> Result<TagStats, CoreError> {
+    let exists: Option<i64> = conn
+        .query_row("SELECT 1 FROM tags WHERE id = ?1;", params![tag_id], |row| row.get(0))
+        .optional()?;
+    if exists.is_none() {
+        return Err(CoreError::InvalidArgument("tag not found".to_string()));
+    }
+    let mut stats = conn.query_row(
+        "SELECT COUNT(1), COUNT(DISTINCT m.thread_id), MIN(mt.tagged_at), MAX(mt.tagged_at), \
+                MIN(m.sort_ts), MAX(m.sort_ts) \
+         FROM message_tags mt \
+         JOIN messages m ON m.id = mt.message_id \
+         WHERE mt.tag_id = ?1;",
+        params![tag_id],
+        |row| {
+            Ok(TagStats {
+                tag_id: tag_id.to_string(),
+                message_count: row.get(0)?,
+                thread_count: row.get(1)?,
+                first_tagged_at: row.get(2)?,
+                last_tagged_at: row.get(3)?,
+                first_message_ts: row.get(4)?,
+                last_message_ts: row.get(5)?,
+                attachment_count: 0,
+            })
+        },
+    )?;
+    stats.attachment_count = conn.query_row(
+        "SELECT COUNT(1) FROM attachments a \
+         JOIN message_tags mt ON mt.message_id = a.message_id \
+         WHERE mt.tag_id = ?1;",
+        params![tag_id],
+        |row| row.get(0),
+    )?;
+    Ok(stats)
+}
+
+// ===== Attachment Tag Functions =====
+
+/// Replaces the tags on an attachment. Tags it already carried keep their `tagged_at`.
+pub fn set_attachment_tags(conn: &Connection, attachment_id: &str, tag_ids: &[String]) -> Result<(), CoreError> {
+    let tx = conn.unchecked_transaction()?;
+    let tag_ids = existing_tag_ids(&tx, tag_ids)?;
     let now = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .unwrap()
         .as_millis() as i64;
 
-    for tag_id in tag_ids {
+    let sql = format!(
+        "DELETE FROM attachment_tags WHERE attachment_id = ? AND tag_id NOT IN ({});",
+        placeholders(tag_ids.len())
+    );
+    let values = std::iter::once(attachment_id).chain(tag_ids.iter().map(String::as_str));
+    tx.execute(&sql, rusqlite::params_from_iter(values))?;
+    for tag_id in &tag_ids {
         tx.execute(
-            "INSERT INTO message_tags (message_id, tag_id, tagged_at) VALUES (?1, ?2, ?3);",
-            params![message_id, tag_id, now],
+            "INSERT OR IGNORE INTO attachment_tags (attachment_id, tag_id, tagged_at) VALUES (?1, ?2, ?3);",
+            params![attachment_id, tag_id, now],
         )?;
     }
 
@@ -502,69 +1704,259 @@ pub fn set_message_tags(conn: &Connection, message_id: &str, tag_ids: &[String])
     Ok(())
 }
 
-// ===== Scrapbook Functions =====
+pub fn get_attachment_tags_bulk(conn: &Connection, attachment_ids: &[String]) -> Result<Vec<AttachmentTags>, CoreError> {
+    if attachment_ids.is_empty() {
+        return Ok(vec![]);
+    }
+    let sql = format!(
+        "SELECT att.attachment_id, t.id, t.name, t.color, t.created_at, t.display_order \
+         FROM attachment_tags att \
+         JOIN tags t ON att.tag_id = t.id \
+         WHERE att.attachment_id IN ({}) \
+         ORDER BY att.attachment_id ASC, t.display_order ASC, t.created_at ASC;",
+        placeholders(attachment_ids.len())
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt.query_map(rusqlite::params_from_iter(attachment_ids.iter()), |row| {
+        Ok((row.get::<_, String>(0)?, tag_from_row(row, 1)?))
+    })?;
 
-/// Checks if two messages are adjacent (consecutive) in their thread's timeline.
-///
-/// Returns `true` if there are no other messages between the two messages in the same thread.
-/// This is used for discontinuity detection in the scrapbook view.
-///
-/// # Algorithm
-///
-/// Given two message IDs (earlier and later based on sent_at/received_at timestamps):
-/// 1. Verify both messages exist and are in the same thread
-/// 2. Check if any message m3 exists in the same thread where:
-///    - m3's timestamp > earlier message's timestamp
-///    - m3's timestamp < later message's timestamp
-/// 3. If no such message exists (count == 0), the messages are adjacent
-///
-/// # Timestamp Handling
-///
-/// - Uses `COALESCE(sent_at, received_at, 0)` to handle messages with only one timestamp
-/// - When timestamps are equal, uses message ID for deterministic ordering
-///
-/// # Example
-///
-/// ```text
-/// Thread timeline: [msg1 (t=1), msg2 (t=5), msg3 (t=10)]
-/// are_messages_adjacent(msg1, msg2)? -> true (no messages between)
-/// are_messages_adjacent(msg1, msg3)? -> false (msg2 exists between them)
-/// ```
-fn are_messages_adjacent(conn: &Connection, earlier_id: &str, later_id: &str) -> Result<bool, CoreError> {
-    // Count messages in the same thread that fall between the two given messages
-    let count: i64 = conn.query_row(
-        "SELECT COUNT(1) FROM messages m1, messages m2
-         WHERE m1.id = ?1 AND m2.id = ?2 AND m1.thread_id = m2.thread_id
-         AND EXISTS (
-           SELECT 1 FROM messages m3 WHERE m3.thread_id = m1.thread_id
-           AND (COALESCE(m3.sent_at, m3.received_at, 0) > COALESCE(m1.sent_at, m1.received_at, 0)
-                OR (COALESCE(m3.sent_at, m3.received_at, 0) = COALESCE(m1.sent_at, m1.received_at, 0)
-                    AND m3.id > m1.id))
-           AND (COALESCE(m3.sent_at, m3.received_at, 0) < COALESCE(m2.sent_at, m2.received_at, 0)
-                OR (COALESCE(m3.sent_at, m3.received_at, 0) = COALESCE(m2.sent_at, m2.received_at, 0)
-                    AND m3.id < m2.id))
-         )",
-        params![earlier_id, later_id],
-        |row| row.get(0),
+    let mut map: std::collections::HashMap<String, Vec<Tag>> = std::collections::HashMap::new();
+    for row in rows {
+        let (attachment_id, tag) = row?;
+        map.entry(attachment_id).or_default().push(tag);
+    }
+
+    Ok(attachment_ids
+        .iter()
+        .map(|attachment_id| AttachmentTags {
+            attachment_id: attachment_id.clone(),
+            tags: map.remove(attachment_id).unwrap_or_default(),
+        })
+        .collect())
+}
+
+/// Media tagged with `tag_id` across all threads, most recently tagged first.
+pub fn list_tagged_media(
+    conn: &Connection,
+    tag_id: &str,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<ThreadMediaRow>, CoreError> {
+    let mut stmt = conn.prepare(
+        "SELECT a.id, a.message_id, m.thread_id, a.sha256, a.mime, a.size_bytes, a.original_filename, \
+                a.kind, a.width, a.height, a.duration_ms, m.sent_at, m.received_at, a.blurhash \
+         FROM attachment_tags att \
+         JOIN attachments a ON a.id = att.attachment_id \
+         JOIN messages m ON m.id = a.message_id \
+         WHERE att.tag_id = ?1 \
+         ORDER BY att.tagged_at DESC, a.id ASC \
+         LIMIT ?2 OFFSET ?3;",
     )?;
-    Ok(count == 0)
+    let rows = stmt.query_map(params![tag_id, limit, offset], thread_media_from_row)?;
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+// ===== Tag Backup Functions =====
+
+const TAG_BACKUP_VERSION: i64 = 1;
+
+/// Serializes every tag and tag assignment to JSON (see [`TagBackup`]) so curation can be
+/// restored with [`import_tags_json`] after the archive is reset and re-imported.
+pub fn export_tags_json(conn: &Connection) -> Result<String, CoreError> {
+    let tags = list_tags(conn)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT m.dedupe_key, mt.tag_id, mt.tagged_at \
+         FROM message_tags mt \
+         JOIN messages m ON m.id = mt.message_id \
+         WHERE m.dedupe_key IS NOT NULL \
+         ORDER BY mt.tagged_at ASC, m.dedupe_key ASC;",
+    )?;
+    let message_tags = stmt
+        .query_map([], |row| {
+            Ok(MessageTagBackup {
+                dedupe_key: row.get(0)?,
+                tag_id: row.get(1)?,
+                tagged_at: row.get(2)?,
+            })
+        })?
+        .filter_map(Result::ok)
+        .collect();
+
+    let mut stmt = conn.prepare(
+        "SELECT m.dedupe_key, a.sha256, att.tag_id, att.tagged_at \
+         FROM attachment_tags att \
+         JOIN attachments a ON a.id = att.attachment_id \
+         JOIN messages m ON m.id = a.message_id \
+         WHERE m.dedupe_key IS NOT NULL \
+         ORDER BY att.tagged_at ASC, m.dedupe_key ASC, a.sha256 ASC;",
+    )?;
+    let attachment_tags = stmt
+        .query_map([], |row| {
+            Ok(AttachmentTagBackup {
+                dedupe_key: row.get(0)?,
+                sha256: row.get(1)?,
+                tag_id: row.get(2)?,
+                tagged_at: row.get(3)?,
+            })
+        })?
+        .filter_map(Result::ok)
+        .collect();
+
+    let backup = TagBackup {
+        version: TAG_BACKUP_VERSION,
+        tags,
+        message_tags,
+        attachment_tags,
+    };
+    serde_json::to_string_pretty(&backup).map_err(|e| CoreError::InvalidArgument(e.to_string()))
+}
+
+/// Re-applies a backup written by [`export_tags_json`] in one transaction.
+///
+/// A backed-up tag maps onto an existing tag with the same id or a matching name (see
+/// [`TAG_NAME_MATCHES`]); otherwise it is recreated with its original id, color and
+/// creation time. Assignments are matched to messages by `dedupe_key` and skipped when the
+/// message (or attachment) is gone.
+pub fn import_tags_json(conn: &Connection, json: &str) -> Result<TagImportSummary, CoreError> {
+    let backup: TagBackup = serde_json::from_str(json)
+        .map_err(|e| CoreError::InvalidArgument(format!("invalid tag backup: {}", e)))?;
+    if backup.version != TAG_BACKUP_VERSION {
+        return Err(CoreError::InvalidArgument(format!(
+            "unsupported tag backup version {}",
+            backup.version
+        )));
+    }
+
+    let tx = conn.unchecked_transaction()?;
+    let mut summary = TagImportSummary::default();
+    let mut tag_ids = std::collections::HashMap::new();
+    for tag in &backup.tags {
+        let existing: Option<String> = tx
+            .query_row(
+                &format!("SELECT id FROM tags WHERE id = ?2 OR {TAG_NAME_MATCHES} ORDER BY id = ?2 DESC LIMIT 1;"),
+                params![tag.name, tag.id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let local_id = match existing {
+            Some(id) => {
+                summary.tags_matched += 1;
+                id
+            }
+            None => {
+                let color = normalize_tag_color(&tag.color)?;
+                let max_order: Option<i64> = tx
+                    .query_row("SELECT MAX(display_order) FROM tags;", [], |row| row.get(0))
+                    .optional()?
+                    .flatten();
+                tx.execute(
+                    "INSERT INTO tags (id, name, color, created_at, display_order) VALUES (?1, ?2, ?3, ?4, ?5);",
+                    params![tag.id, tag.name, color, tag.created_at, max_order.unwrap_or(-1) + 1],
+                )?;
+                summary.tags_created += 1;
+                tag.id.clone()
+            }
+        };
+        tag_ids.insert(tag.id.as_str(), local_id);
+    }
+
+    for entry in &backup.message_tags {
+        let Some(tag_id) = tag_ids.get(entry.tag_id.as_str()) else {
+            continue;
+        };
+        // Backups from before messages were keyed by sent time carry the `sms:`/`mms:` row
+        // key, which is still the id of messages first imported under it.
+        let message_id: Option<String> = tx
+            .query_row(
+                "SELECT id FROM messages WHERE dedupe_key = ?1 OR (id = ?1 AND ?1 GLOB '[ms]ms:*') \
+                 ORDER BY dedupe_key = ?1 DESC LIMIT 1;",
+                params![entry.dedupe_key],
+                |row| row.get(0),
+            )
+            .optional()?;
+        match message_id {
+            Some(message_id) => {
+                summary.message_tags_applied += tx.execute(
+                    "INSERT OR IGNORE INTO message_tags (message_id, tag_id, tagged_at) VALUES (?1, ?2, ?3);",
+                    params![message_id, tag_id, entry.tagged_at],
+                )? as i64;
+            }
+            None => summary.message_tags_skipped += 1,
+        }
+    }
+
+    for entry in &backup.attachment_tags {
+        let Some(tag_id) = tag_ids.get(entry.tag_id.as_str()) else {
+            continue;
+        };
+        let attachment_ids: Vec<String> = {
+            let mut stmt = tx.prepare(
+                "SELECT a.id FROM attachments a \
+                 JOIN messages m ON m.id = a.message_id \
+                 WHERE (m.dedupe_key = ?1 OR (m.id = ?1 AND ?1 GLOB '[ms]ms:*')) AND a.sha256 = ?2;",
+            )?;
+            let rows = stmt.query_map(params![entry.dedupe_key, entry.sha256], |row| row.get(0))?;
+            rows.filter_map(Result::ok).collect()
+        };
+        if attachment_ids.is_empty() {
+            summary.attachment_tags_skipped += 1;
+            continue;
+        }
+        for attachment_id in attachment_ids {
+            summary.attachment_tags_applied += tx.execute(
+                "INSERT OR IGNORE INTO attachment_tags (attachment_id, tag_id, tagged_at) VALUES (?1, ?2, ?3);",
+                params![attachment_id, tag_id, entry.tagged_at],
+            )? as i64;
+        }
+    }
+
+    tx.commit()?;
+    Ok(summary)
 }
 
-/// Lists messages tagged with a specific tag, ordered by when they were tagged (newest first).
+// ===== Scrapbook Functions =====
+
+/// Correlated subquery yielding the id of the message that directly follows `m` in its
+/// thread's timeline; an index seek on `idx_messages_thread_sort`.
+const NEXT_IN_THREAD_SQL: &str = "SELECT n.id FROM messages n \
+     WHERE n.thread_id = m.thread_id AND (n.sort_ts, n.id) > (m.sort_ts, m.id) \
+     ORDER BY n.sort_ts, n.id LIMIT 1";
+
+/// `EXPLAIN QUERY PLAN` details for [`NEXT_IN_THREAD_SQL`], so tests can check it stays an
+/// index seek.
+pub fn next_in_thread_query_plan_for_tests(conn: &Connection) -> Result<Vec<String>, CoreError> {
+    let mut stmt = conn.prepare(&format!("EXPLAIN QUERY PLAN SELECT ({}) FROM messages m;", NEXT_IN_THREAD_SQL))?;
+    let rows = stmt.query_map([], |row| row.get(3))?;
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+/// A fetched scrapbook row: message, thread name, `sort_ts`, the id of the next message in
+/// its thread, and the page cursor timestamp.
+type ScrapbookRow = (MessageRow, Option<String>, i64, Option<String>, i64);
+
+/// Lists messages tagged with a specific tag, newest first along the chosen `order`.
 ///
 /// # Scrapbook View
 ///
 /// This function powers the Scrapbook tab, which shows a cross-thread view of all messages
-/// tagged with a specific tag. Messages are ordered by `tagged_at DESC` (when the tag was
-/// applied), not by message timestamp.
+/// tagged with a specific tag. With [`ScrapbookOrder::TaggedAt`] messages are ordered by
+/// `tagged_at DESC` (when the tag was applied); with [`ScrapbookOrder::MessageTime`] they
+/// follow the message timeline, `sort_ts DESC, id DESC`, like a memory book.
 ///
 /// # Pagination
 ///
-/// Uses cursor-based pagination with `tagged_at` timestamp and message ID:
-/// - `before_ts`: Only return messages tagged before this timestamp
+/// Uses cursor-based pagination with the order's timestamp and message ID:
+/// - `before_ts`: Only return messages whose `tagged_at` (or `sort_ts`) is before this timestamp
 /// - `before_id`: When timestamp matches, only return messages with ID < this ID
 /// - `limit`: Maximum number of messages to return
 ///
+/// The returned [`ScrapbookPage`] carries the cursor for the next page, so callers never
+/// need to look up `tagged_at` themselves.
+///
+/// Passing `thread_id` narrows the view to one conversation; the cursor works the same way.
+///
 /// # Discontinuity Detection
 ///
 /// Each message includes an `is_discontinuous` flag that indicates whether there are
@@ -580,136 +1972,260 @@ fn are_messages_adjacent(conn: &Connection, earlier_id: &str, later_id: &str) ->
 ///
 /// ```rust,ignore
 /// // Get first page of messages for a tag
-/// let messages = list_scrapbook_messages(&conn, "tag:123", None, None, 50)?;
+/// let page = list_scrapbook_messages(&conn, "tag:123", None, ScrapbookOrder::TaggedAt, None, None, 50)?;
 ///
 /// // Get next page
-/// let last_msg = messages.last().unwrap();
-/// let tagged_at = /* get from message_tags */;
-/// let next_page = list_scrapbook_messages(&conn, "tag:123", Some(tagged_at), Some(&last_msg.message.id), 50)?;
+/// let next_page = list_scrapbook_messages(
+///     &conn, "tag:123", None, ScrapbookOrder::TaggedAt,
+///     page.next_before_ts, page.next_before_id.as_deref(), 50,
+/// )?;
 /// ```
 pub fn list_scrapbook_messages(
     conn: &Connection,
     tag_id: &str,
+    thread_id: Option<&str>,
+    order: ScrapbookOrder,
     before_ts: Option<i64>,
     before_id: Option<&str>,
     limit: i64,
-) -> Result<Vec<ScrapbookMessage>, CoreError> {
-    // Build query to get tagged messages with thread names
-    let (sql, params_vec): (String, Vec<rusqlite::types::Value>) = match (before_ts, before_id) {
-        (Some(ts), Some(id)) => (
-            "SELECT m.id, m.thread_id, m.sender_id, m.sent_at, m.received_at, m.type, m.body, \
-                    m.is_outgoing, m.is_view_once, m.quote_message_id, m.metadata_json, t.name \
-             FROM messages m \
-             JOIN message_tags mt ON mt.message_id = m.id \
-             JOIN threads t ON t.id = m.thread_id \
-             WHERE mt.tag_id = ?1 AND (mt.tagged_at < ?2 \
-               OR (mt.tagged_at = ?2 AND m.id < ?3)) \
-             ORDER BY mt.tagged_at DESC, m.id DESC \
-             LIMIT ?4;"
-                .to_string(),
-            vec![
-                tag_id.to_string().into(),
-                ts.into(),
-                id.to_string().into(),
-                limit.into(),
-            ],
-        ),
-        (Some(ts), None) => (
-            "SELECT m.id, m.thread_id, m.sender_id, m.sent_at, m.received_at, m.type, m.body, \
-                    m.is_outgoing, m.is_view_once, m.quote_message_id, m.metadata_json, t.name \
-             FROM messages m \
-             JOIN message_tags mt ON mt.message_id = m.id \
-             JOIN threads t ON t.id = m.thread_id \
-             WHERE mt.tag_id = ?1 AND mt.tagged_at < ?2 \
-             ORDER BY mt.tagged_at DESC, m.id DESC \
-             LIMIT ?3;"
-                .to_string(),
-            vec![tag_id.to_string().into(), ts.into(), limit.into()],
-        ),
-        (None, _) => (
-            "SELECT m.id, m.thread_id, m.sender_id, m.sent_at, m.received_at, m.type, m.body, \
-                    m.is_outgoing, m.is_view_once, m.quote_message_id, m.metadata_json, t.name \
-             FROM messages m \
-             JOIN message_tags mt ON mt.message_id = m.id \
-             JOIN threads t ON t.id = m.thread_id \
-             WHERE mt.tag_id = ?1 \
-             ORDER BY mt.tagged_at DESC, m.id DESC \
-             LIMIT ?2;"
-                .to_string(),
-            vec![tag_id.to_string().into(), limit.into()],
-        ),
+) -> Result<ScrapbookPage, CoreError> {
+    let limit = limit.max(1);
+    let key = match order {
+        ScrapbookOrder::TaggedAt => "mt.tagged_at",
+        ScrapbookOrder::MessageTime => "m.sort_ts",
     };
+    let mut params_vec: Vec<rusqlite::types::Value> = vec![tag_id.to_string().into()];
+    let mut filters = String::new();
+    if let Some(thread_id) = thread_id {
+        params_vec.push(thread_id.to_string().into());
+        filters.push_str(&format!(" AND m.thread_id = ?{}", params_vec.len()));
+    }
+    match (before_ts, before_id) {
+        (Some(ts), Some(id)) => {
+            params_vec.push(ts.into());
+            let ts_idx = params_vec.len();
+            params_vec.push(id.to_string().into());
+            let id_idx = params_vec.len();
+            filters.push_str(&format!(
+                " AND ({key} < ?{ts_idx} OR ({key} = ?{ts_idx} AND m.id < ?{id_idx}))"
+            ));
+        }
+        (Some(ts), None) => {
+            params_vec.push(ts.into());
+            filters.push_str(&format!(" AND {key} < ?{}", params_vec.len()));
+        }
+        (None, _) => {}
+    }
+    params_vec.push((limit + 1).into());
+
+    // Build query to get tagged messages with thread names
+    let sql = format!(
+        "SELECT m.id, m.thread_id, m.sender_id, m.sent_at, m.received_at, m.type, m.body, \
+                m.is_outgoing, m.is_view_once, m.quote_message_id, m.metadata_json, t.name, \
+                m.sort_ts, ({next}) AS next_id, {key} AS cursor_ts \
+         FROM messages m \
+         JOIN message_tags mt ON mt.message_id = m.id \
+         JOIN threads t ON t.id = m.thread_id \
+         WHERE mt.tag_id = ?1{filters} \
+         ORDER BY {key} DESC, m.id DESC \
+         LIMIT ?{limit_idx};",
+        next = NEXT_IN_THREAD_SQL,
+        limit_idx = params_vec.len(),
+    );
 
     let mut stmt = conn.prepare(&sql)?;
-    let messages_with_threads: Vec<(MessageRow, Option<String>)> = stmt
+    let mut rows: Vec<ScrapbookRow> = stmt
         .query_map(rusqlite::params_from_iter(params_vec), |row| {
-            let message = MessageRow {
-                id: row.get(0)?,
-                thread_id: row.get(1)?,
-                sender_id: row.get(2)?,
-                sent_at: row.get(3)?,
-                received_at: row.get(4)?,
-                message_type: row.get(5)?,
-                body: row.get(6)?,
-                is_outgoing: row.get::<_, i64>(7)? != 0,
-                is_view_once: row.get::<_, i64>(8)? != 0,
-                quote_message_id: row.get(9)?,
-                metadata_json: row.get(10)?,
-            };
+            let message = message_from_row(row)?;
             let thread_name: Option<String> = row.get(11)?;
-            Ok((message, thread_name))
+            let sort_ts: i64 = row.get(12)?;
+            let next_id: Option<String> = row.get(13)?;
+            let cursor_ts: i64 = row.get(14)?;
+            Ok((message, thread_name, sort_ts, next_id, cursor_ts))
         })?
         .filter_map(Result::ok)
         .collect();
 
-    // Helper to get message sort timestamp (same logic as frontend/backend ordering)
-    let msg_ts = |msg: &MessageRow| msg.sent_at.or(msg.received_at).unwrap_or(0);
+    // One extra row is fetched only to learn whether another page exists.
+    let has_more = rows.len() as i64 > limit;
+    rows.truncate(limit as usize);
+    let (next_before_ts, next_before_id) = match rows.last() {
+        Some((message, _, _, _, cursor_ts)) if has_more => (Some(*cursor_ts), Some(message.id.clone())),
+        _ => (None, None),
+    };
 
     // Discontinuity Detection:
     //
-    // The scrapbook results are ordered by `tagged_at DESC` (when the tag was applied),
-    // but discontinuity must be detected based on the message timeline (sent_at/received_at).
+    // The scrapbook results may be ordered by `tagged_at DESC` (when the tag was applied),
+    // but discontinuity must be detected based on the message timeline (sort_ts, id).
     //
-    // For each pair of consecutive messages in the result set that belong to the same thread,
-    // we check if there are any messages between them in the original conversation timeline.
+    // Each row carries `next_id`, the message that directly follows it in its thread. Two
+    // consecutive results from the same thread are adjacent exactly when the earlier one's
+    // `next_id` is the later one, so no per-pair query is needed.
     //
     // Example:
     // - Thread has messages: [m1(t=1), m2(t=5), m3(t=10), m4(t=15)]
     // - User tags m1 and m3 (skipping m2)
     // - Scrapbook shows: [m3, m1] (ordered by tagged_at)
     // - When processing m1 (index 1), we compare it to m3 (index 0)
-    // - We find m2 exists between them -> m1 is marked discontinuous
+    // - m1's next message is m2, not m3 -> m1 is marked discontinuous
     // - The "⋯" indicator will show above m1 in the UI
-    let mut result = Vec::new();
-    for (i, (message, thread_name)) in messages_with_threads.iter().enumerate() {
-        let is_discontinuous = if i > 0 {
-            let prev_message = &messages_with_threads[i - 1].0;
-            // Only check discontinuity if same thread (cross-thread gaps don't need indicators)
-            if prev_message.thread_id == message.thread_id {
-                // Determine which message is earlier in the MESSAGE timeline
-                // (not tagged_at, which is how results are ordered)
-                let prev_ts = msg_ts(prev_message);
-                let curr_ts = msg_ts(message);
-                let (earlier_id, later_id) = if curr_ts < prev_ts {
-                    (&message.id, &prev_message.id)
+    let message_ids: Vec<String> = rows.iter().map(|(message, ..)| message.id.clone()).collect();
+    let mut tags = get_message_tags_bulk(conn, &message_ids)?.into_iter();
+
+    let mut result = Vec::with_capacity(rows.len());
+    for (i, (message, thread_name, sort_ts, next_id, _)) in rows.iter().enumerate() {
+        let is_discontinuous = match i.checked_sub(1).map(|prev| &rows[prev]) {
+            // Only same-thread neighbours get an indicator; cross-thread gaps are expected.
+            Some((prev_message, _, prev_sort_ts, prev_next_id, _)) if prev_message.thread_id == message.thread_id => {
+                let current_is_earlier = (*sort_ts, &message.id) < (*prev_sort_ts, &prev_message.id);
+                let (earlier_next_id, later_id) = if current_is_earlier {
+                    (next_id, &prev_message.id)
                 } else {
-                    (&prev_message.id, &message.id)
+                    (prev_next_id, &message.id)
                 };
-                // Check if there are messages between them in the original thread
-                !are_messages_adjacent(conn, earlier_id, later_id)?
-            } else {
-                false // Different thread, no discontinuity indicator
+                earlier_next_id.as_ref() != Some(later_id)
             }
-        } else {
-            false // First message in results, never discontinuous
+            _ => false,
         };
 
         result.push(ScrapbookMessage {
             message: message.clone(),
             thread_name: thread_name.clone(),
             is_discontinuous,
+            tags: tags.next().map(|entry| entry.tags).unwrap_or_default(),
         });
     }
 
-    Ok(result)
+    Ok(ScrapbookPage {
+        items: result,
+        next_before_ts,
+        next_before_id,
+    })
+}
+
+/// Returns up to `before` older and `after` newer messages around a scrapbook entry,
+/// oldest first and including the entry itself.
+///
+/// The window comes from [`list_messages_around`], so it never crosses into another
+/// thread. Each message is flagged with whether it also carries `tag_id`.
+pub fn scrapbook_context(
+    conn: &Connection,
+    tag_id: &str,
+    message_id: &str,
+    before: i64,
+    after: i64,
+) -> Result<Vec<ScrapbookContextMessage>, CoreError> {
+    let messages = list_messages_around(conn, message_id, before, after)?;
+    let sql = format!(
+        "SELECT message_id FROM message_tags WHERE tag_id = ? AND message_id IN ({});",
+        placeholders(messages.len())
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let values = std::iter::once(tag_id).chain(messages.iter().map(|m| m.id.as_str()));
+    let tagged: std::collections::HashSet<String> = stmt
+        .query_map(rusqlite::params_from_iter(values), |row| row.get(0))?
+        .filter_map(Result::ok)
+        .collect();
+
+    Ok(messages
+        .into_iter()
+        .map(|message| ScrapbookContextMessage {
+            is_tagged: tagged.contains(&message.id),
+            message,
+        })
+        .collect())
+}
+
+// ===== Note Functions =====
+
+fn note_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<MessageNote> {
+    Ok(MessageNote {
+        message_id: row.get(0)?,
+        note_text: row.get(1)?,
+        updated_at: row.get(2)?,
+    })
+}
+
+/// Creates or replaces the private note on a message.
+///
+/// Blank notes are rejected; use [`delete_message_note`] to clear one.
+pub fn set_message_note(conn: &Connection, message_id: &str, note_text: &str) -> Result<MessageNote, CoreError> {
+    if note_text.trim().is_empty() {
+        return Err(CoreError::InvalidArgument("note text is empty".to_string()));
+    }
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as i64;
+    conn.execute(
+        "INSERT INTO message_notes (message_id, note_text, updated_at) VALUES (?1, ?2, ?3) \
+         ON CONFLICT(message_id) DO UPDATE SET note_text = excluded.note_text, updated_at = excluded.updated_at;",
+        params![message_id, note_text, now],
+    )?;
+    Ok(MessageNote {
+        message_id: message_id.to_string(),
+        note_text: note_text.to_string(),
+        updated_at: now,
+    })
+}
+
+pub fn get_message_note(conn: &Connection, message_id: &str) -> Result<Option<MessageNote>, CoreError> {
+    conn.query_row(
+        "SELECT message_id, note_text, updated_at FROM message_notes WHERE message_id = ?1;",
+        params![message_id],
+        note_from_row,
+    )
+    .optional()
+    .map_err(CoreError::from)
+}
+
+pub fn delete_message_note(conn: &Connection, message_id: &str) -> Result<(), CoreError> {
+    conn.execute("DELETE FROM message_notes WHERE message_id = ?1;", params![message_id])?;
+    Ok(())
+}
+
+/// Notes for whichever of `message_ids` have one; messages without a note are skipped.
+pub fn get_message_notes_bulk(conn: &Connection, message_ids: &[String]) -> Result<Vec<MessageNote>, CoreError> {
+    if message_ids.is_empty() {
+        return Ok(vec![]);
+    }
+    let sql = format!(
+        "SELECT message_id, note_text, updated_at FROM message_notes WHERE message_id IN ({}) \
+         ORDER BY message_id ASC;",
+        placeholders(message_ids.len())
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt.query_map(rusqlite::params_from_iter(message_ids.iter()), note_from_row)?;
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+/// Full-text search over note text, best bm25 match first. Every term must match; the last
+/// one also matches as a prefix so the box works while typing.
+pub fn search_message_notes(conn: &Connection, query: &str, limit: i64) -> Result<Vec<MessageNote>, CoreError> {
+    let phrases: Vec<String> = query.split_whitespace().map(fts_phrase).collect();
+    let Some((last, rest)) = phrases.split_last() else {
+        return Ok(Vec::new());
+    };
+    let mut parts: Vec<String> = rest.to_vec();
+    parts.push(format!("({} OR {}*)", last, last));
+    let mut stmt = conn.prepare(
+        "SELECT n.message_id, n.note_text, n.updated_at \
+         FROM note_fts \
+         JOIN message_notes n ON n.rowid = note_fts.rowid \
+         WHERE note_fts MATCH ?1 \
+         ORDER BY bm25(note_fts), n.message_id \
+         LIMIT ?2;",
+    )?;
+    let rows = stmt.query_map(params![parts.join(" AND "), limit], note_from_row)?;
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+/// A message with its tags and note, for the message detail panel.
+pub fn get_message_detail(conn: &Connection, message_id: &str) -> Result<MessageDetail, CoreError> {
+    Ok(MessageDetail {
+        message: get_message(conn, message_id)?,
+        tags: get_message_tags(conn, message_id)?,
+        note: get_message_note(conn, message_id)?,
+    })
 }
@@ -34,10 +34,6 @@ pub fn seed_demo(conn: &Connection, primary_count: i64, secondary_threads: i64)
              (id, thread_id, sender_id, sent_at, sort_ts, type, body, is_outgoing, quote_message_id, metadata_json) \
              VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10);",
         )?;
-        let mut fts_stmt = conn.prepare(
-            "INSERT OR IGNORE INTO message_fts (message_id, thread_id, sender_id, body) \
-             VALUES (?1, ?2, ?3, ?4);",
-        )?;
         let mut reaction_stmt = conn.prepare(
             "INSERT OR IGNORE INTO reactions (message_id, reactor_id, emoji, reacted_at) \
              VALUES (?1, ?2, ?3, ?4);",
@@ -69,7 +65,6 @@ pub fn seed_demo(conn: &Connection, primary_count: i64, secondary_threads: i64)
                 (None, None)
             };
             msg_stmt.execute(params![id, "t1", sender, ts, ts, "text", body, outgoing, quote_id, metadata_json])?;
-            fts_stmt.execute(params![id, "t1", sender, body])?;
             if (idx + 1) % 4 == 0 {
                 reaction_stmt.execute(params![id, "r2", "👍", ts + 5])?;
             }
@@ -105,7 +100,6 @@ pub fn seed_demo(conn: &Connection, primary_count: i64, secondary_threads: i64)
                 Option::<String>::None,
                 Option::<String>::None,
             ])?;
-            fts_stmt.execute(params![msg_id, thread_id, "r2", body])?;
         }
 
         Ok(())
@@ -0,0 +1,167 @@
+use std::fs;
+
+use golden_thread_core::crypto;
+use golden_thread_core::db::apply_migrations;
+use golden_thread_core::export::{export_attachment, export_scrapbook, export_thread_media};
+use golden_thread_core::models::{ExportFormat, ThreadMediaFilters};
+use golden_thread_core::query::{add_message_tag, create_tag};
+use golden_thread_core::seed::seed_demo;
+use rusqlite::Connection;
+use tempfile::tempdir;
+
+fn setup_db() -> Connection {
+    let conn = Connection::open_in_memory().expect("memory db");
+    apply_migrations(&conn).expect("migrate");
+    seed_demo(&conn, 4, 0).expect("seed");
+    conn
+}
+
+#[test]
+fn exports_markdown_with_decrypted_assets() {
+    crypto::set_test_key_from_passphrase("golden-thread-tests");
+    let key = crypto::load_or_create_master_key().expect("key");
+    let conn = setup_db();
+
+    let archive = tempdir().expect("archive dir");
+    let attachments_dir = archive.path().join("attachments");
+    fs::create_dir_all(&attachments_dir).unwrap();
+    let plain = archive.path().join("photo.jpg");
+    fs::write(&plain, b"not really a jpeg").unwrap();
+    crypto::encrypt_file_to_path(&plain, &attachments_dir.join("abc123"), &key).expect("encrypt");
+    conn.execute(
+        "INSERT INTO attachments (id, message_id, sha256, mime, original_filename, kind) \
+         VALUES ('a1', 'demo:m2', 'abc123', 'image/jpeg', 'photo.jpg', 'image');",
+        [],
+    )
+    .unwrap();
+
+    let tag = create_tag(&conn, "Our Trip!", "#ff0000").expect("tag");
+    add_message_tag(&conn, "demo:m3", &tag.id).expect("tag m3");
+    add_message_tag(&conn, "demo:m2", &tag.id).expect("tag m2");
+
+    let dest = tempdir().expect("dest dir");
+    let out = export_scrapbook(
+        &conn,
+        &tag.id,
+        &attachments_dir,
+        &key,
+        dest.path(),
+        ExportFormat::Markdown,
+        |_| {},
+    )
+    .expect("export");
+
+    assert_eq!(out, dest.path().join("our-trip.md"));
+    let markdown = fs::read_to_string(&out).unwrap();
+    assert_eq!(
+        markdown,
+        "# Our Trip!\n\
+         \n## 1970-01-20 16:13 UTC · Partner\n\n*Chat with Partner*\n\nReply 2\n\
+         \n![photo.jpg](assets/abc123.jpg)\n\
+         \n## 1970-01-20 16:13 UTC · You\n\n*Chat with Partner*\n\nDemo message 3\n"
+    );
+    assert_eq!(fs::read(dest.path().join("assets/abc123.jpg")).unwrap(), b"not really a jpeg");
+}
+
+#[test]
+fn html_export_escapes_and_notes_missing_attachments() {
+    crypto::set_test_key_from_passphrase("golden-thread-tests");
+    let key = crypto::load_or_create_master_key().expect("key");
+    let conn = setup_db();
+    conn.execute("UPDATE messages SET body = '<b>hi</b> & bye' WHERE id = 'demo:m1';", []).unwrap();
+    conn.execute(
+        "INSERT INTO attachments (id, message_id, sha256, mime, original_filename, kind) \
+         VALUES ('a1', 'demo:m1', 'missing', 'application/pdf', 'plan.pdf', 'file');",
+        [],
+    )
+    .unwrap();
+    let tag = create_tag(&conn, "🎉", "#00ff00").expect("tag");
+    add_message_tag(&conn, "demo:m1", &tag.id).expect("tag m1");
+
+    let archive = tempdir().expect("archive dir");
+    let dest = tempdir().expect("dest dir");
+    let out = export_scrapbook(&conn, &tag.id, archive.path(), &key, dest.path(), ExportFormat::Html, |_| {})
+        .expect("export");
+
+    assert_eq!(out, dest.path().join("scrapbook.html"));
+    let html = fs::read_to_string(&out).unwrap();
+    assert!(html.contains("<p>&lt;b&gt;hi&lt;/b&gt; &amp; bye</p>"));
+    assert!(html.contains("plan.pdf (unavailable)"));
+}
+
+#[test]
+fn export_rejects_unknown_tag() {
+    crypto::set_test_key_from_passphrase("golden-thread-tests");
+    let key = crypto::load_or_create_master_key().expect("key");
+    let conn = setup_db();
+    let dest = tempdir().expect("dest dir");
+    assert!(export_scrapbook(&conn, "tag:nope", dest.path(), &key, dest.path(), ExportFormat::Markdown, |_| {}).is_err());
+}
+
+#[test]
+fn export_attachment_uses_sanitized_original_name() {
+    crypto::set_test_key_from_passphrase("golden-thread-tests");
+    let key = crypto::load_or_create_master_key().expect("key");
+    let archive = tempdir().expect("archive dir");
+    let plain = archive.path().join("plain");
+    fs::write(&plain, b"holiday photo").unwrap();
+    crypto::encrypt_file_to_path(&plain, &archive.path().join("abc123"), &key).expect("encrypt");
+    let dest = tempdir().expect("dest dir");
+
+    let path = export_attachment(archive.path(), "abc123", dest.path(), Some("../../.beach:day.jpg"), &key, false)
+        .expect("export");
+    assert_eq!(path, dest.path().join("beach_day.jpg"));
+    assert_eq!(fs::read(&path).unwrap(), b"holiday photo");
+
+    assert!(export_attachment(archive.path(), "abc123", dest.path(), Some("beach:day.jpg"), &key, false).is_err());
+    fs::write(&path, b"stale").unwrap();
+    export_attachment(archive.path(), "abc123", dest.path(), Some("beach:day.jpg"), &key, true).expect("overwrite");
+    assert_eq!(fs::read(&path).unwrap(), b"holiday photo");
+
+    let fallback = export_attachment(archive.path(), "abc123", dest.path(), Some("..."), &key, false).expect("fallback");
+    assert_eq!(fallback, dest.path().join("abc123"));
+    assert!(export_attachment(archive.path(), "missing", dest.path(), None, &key, false).is_err());
+}
+
+#[test]
+fn export_thread_media_names_by_date_and_skips_missing_blobs() {
+    crypto::set_test_key_from_passphrase("golden-thread-tests");
+    let key = crypto::load_or_create_master_key().expect("key");
+    let conn = setup_db();
+    let archive = tempdir().expect("archive dir");
+    for (id, message_id, sha256, kind, filename, stored) in [
+        ("a1", "demo:m1", "sha1", "image", Some("IMG.jpg"), true),
+        ("a2", "demo:m2", "sha2", "image", Some("IMG.jpg"), true),
+        ("a3", "demo:m3", "sha3", "image", None, true),
+        ("a4", "demo:m4", "sha4", "image", Some("gone.jpg"), false),
+        ("a5", "demo:m4", "sha5", "audio", Some("voice.m4a"), true),
+    ] {
+        if stored {
+            let plain = archive.path().join(format!("{}.plain", sha256));
+            fs::write(&plain, id.as_bytes()).unwrap();
+            crypto::encrypt_file_to_path(&plain, &archive.path().join(sha256), &key).expect("encrypt");
+        }
+        conn.execute(
+            "INSERT INTO attachments (id, message_id, sha256, mime, original_filename, kind) \
+             VALUES (?1, ?2, ?3, 'image/png', ?4, ?5);",
+            rusqlite::params![id, message_id, sha256, filename, kind],
+        )
+        .unwrap();
+    }
+
+    let dest = tempdir().expect("dest dir");
+    let filters = ThreadMediaFilters {
+        kind: Some("image".to_string()),
+        ..ThreadMediaFilters::default()
+    };
+    let summary = export_thread_media(&conn, archive.path(), &key, "t1", dest.path(), &filters, |_| {}).expect("export");
+
+    assert_eq!((summary.total, summary.exported, summary.missing, summary.failed), (4, 3, 1, 0));
+    let mut names: Vec<String> = fs::read_dir(dest.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+        .collect();
+    names.sort();
+    assert_eq!(names, vec!["1970-01-20_IMG (2).jpg", "1970-01-20_IMG.jpg", "1970-01-20_sha3.png"]);
+    assert_eq!(fs::read(dest.path().join("1970-01-20_IMG.jpg")).unwrap(), b"a1");
+}
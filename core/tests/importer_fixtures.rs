@@ -1,7 +1,16 @@
+use std::cell::RefCell;
 use std::fs;
+use std::panic::{catch_unwind, AssertUnwindSafe};
 use std::path::Path;
 
-use golden_thread_core::importer::import_from_signal_db_for_tests;
+use golden_thread_core::importer::{
+    import_from_signal_db_for_tests, import_from_signal_db_resumable_for_tests,
+    import_from_signal_db_with_progress_for_tests, inspect_signal_db_for_tests,
+};
+use golden_thread_core::models::{ImportProgress, ImportStage, SearchOptions};
+use golden_thread_core::query::{
+    list_import_issues, list_imports, list_mentions_for_messages, list_messages, list_threads, search_messages,
+};
 use golden_thread_core::{crypto, open_archive, CoreError};
 use rusqlite::Connection;
 use tempfile::tempdir;
@@ -30,7 +39,8 @@ fn create_signal_db(path: &Path) -> Result<(), CoreError> {
           _id INTEGER PRIMARY KEY,
           recipient_id INTEGER,
           date INTEGER,
-          message_count INTEGER
+          message_count INTEGER,
+          expires_in INTEGER DEFAULT 0
         );
         CREATE TABLE sms (
           _id INTEGER PRIMARY KEY,
@@ -54,7 +64,9 @@ fn create_signal_db(path: &Path) -> Result<(), CoreError> {
           recipient_id INTEGER,
           quote_id INTEGER,
           quote_author INTEGER,
-          quote_body TEXT
+          quote_body TEXT,
+          view_once INTEGER DEFAULT 0,
+          expires_in INTEGER DEFAULT 0
         );
         CREATE TABLE part (
           _id INTEGER PRIMARY KEY,
@@ -70,6 +82,14 @@ fn create_signal_db(path: &Path) -> Result<(), CoreError> {
           author_id INTEGER,
           date INTEGER
         );
+        CREATE TABLE mention (
+          _id INTEGER PRIMARY KEY,
+          thread_id INTEGER,
+          message_id INTEGER,
+          recipient_id INTEGER,
+          range_start INTEGER,
+          range_length INTEGER
+        );
         "#,
     )?;
 
@@ -78,7 +98,7 @@ fn create_signal_db(path: &Path) -> Result<(), CoreError> {
         [],
     )?;
     conn.execute(
-        "INSERT INTO thread (_id, recipient_id, date, message_count) VALUES (1, 1, 3, 2);",
+        "INSERT INTO thread (_id, recipient_id, date, message_count, expires_in) VALUES (1, 1, 3, 2, 604800000);",
         [],
     )?;
     conn.execute(
@@ -87,8 +107,8 @@ fn create_signal_db(path: &Path) -> Result<(), CoreError> {
         [],
     )?;
     conn.execute(
-        "INSERT INTO mms (_id, thread_id, body, date_received, date_sent, type, recipient_id, quote_id, quote_author, quote_body) \
-         VALUES (1, 1, 'mms body', 2, 2, 1, 1, NULL, NULL, NULL);",
+        "INSERT INTO mms (_id, thread_id, body, date_received, date_sent, type, recipient_id, quote_id, quote_author, quote_body, expires_in) \
+         VALUES (1, 1, 'mms body', 2, 2, 1, 1, NULL, NULL, NULL, 604800000);",
         [],
     )?;
     conn.execute(
@@ -100,6 +120,10 @@ fn create_signal_db(path: &Path) -> Result<(), CoreError> {
         "INSERT INTO reaction (message_id, emoji, author_id, date) VALUES (1, '👍', 1, 2);",
         [],
     )?;
+    conn.execute(
+        "INSERT INTO mention (_id, thread_id, message_id, recipient_id, range_start, range_length) VALUES (1, 1, 1, 1, 0, 1);",
+        [],
+    )?;
     Ok(())
 }
 
@@ -148,6 +172,344 @@ fn importer_ingests_messages_attachments_reactions() {
     assert!(metadata.is_some());
 }
 
+#[test]
+fn importer_reports_progress_stages_in_order() {
+    set_test_key();
+    let tmp = tempdir().expect("temp");
+    let signal_db = tmp.path().join("signal.sqlite");
+    create_signal_db(&signal_db).expect("signal db");
+
+    let export_dir = tmp.path().join("frames");
+    fs::create_dir_all(&export_dir).expect("frames dir");
+    fs::write(export_dir.join("Attachment_5_1.bin"), b"test").expect("attachment file");
+    let archive_dir = tmp.path().join("archive");
+    fs::create_dir_all(&archive_dir).expect("archive dir");
+    let archive_path = archive_dir.join("archive.sqlite");
+
+    let events: RefCell<Vec<ImportProgress>> = RefCell::new(Vec::new());
+    import_from_signal_db_with_progress_for_tests(&signal_db, &archive_path, &export_dir, |progress| {
+        events.borrow_mut().push(progress)
+    })
+    .expect("import");
+
+    let events = events.into_inner();
+    let mut stages: Vec<ImportStage> = events.iter().map(|event| event.stage).collect();
+    stages.dedup();
+    assert_eq!(
+        stages,
+        vec![
+            ImportStage::Recipients,
+            ImportStage::Threads,
+            ImportStage::Messages,
+            ImportStage::Attachments,
+            ImportStage::Reactions,
+            ImportStage::Fts,
+            ImportStage::Finalizing,
+        ]
+    );
+    let sms = events
+        .iter()
+        .find(|event| event.detail.as_deref() == Some("SMS"))
+        .expect("sms progress");
+    assert_eq!((sms.current, sms.total), (0, Some(1)));
+    assert_eq!(sms.to_string(), "Importing messages... 0/1 (SMS)");
+    // The part declares 4 bytes and its file has 4.
+    let attachments: Vec<&ImportProgress> =
+        events.iter().filter(|event| event.stage == ImportStage::Attachments).collect();
+    assert_eq!(attachments.first().map(|e| (e.bytes_done, e.bytes_total)), Some((Some(0), Some(4))));
+    assert_eq!(attachments.last().map(|e| (e.bytes_done, e.bytes_total)), Some((Some(4), Some(4))));
+}
+
+#[test]
+fn importer_maps_mentions_to_archive_messages() {
+    set_test_key();
+    let tmp = tempdir().expect("temp");
+    let signal_db = tmp.path().join("signal.sqlite");
+    create_signal_db(&signal_db).expect("signal db");
+
+    let export_dir = tmp.path().join("frames");
+    fs::create_dir_all(&export_dir).expect("frames dir");
+    let archive_dir = tmp.path().join("archive");
+    fs::create_dir_all(&archive_dir).expect("archive dir");
+    let archive_path = archive_dir.join("archive.sqlite");
+
+    import_from_signal_db_for_tests(&signal_db, &archive_path, &export_dir).expect("import");
+
+    let archive = open_archive(&archive_path).expect("open archive");
+    let mentions = list_mentions_for_messages(&archive.conn, &["mms:1".to_string()]).expect("mentions");
+    assert_eq!(mentions.len(), 1);
+    assert_eq!(mentions[0].recipient_id, "1");
+    assert_eq!(mentions[0].display_name.as_deref(), Some("Alice"));
+    assert_eq!((mentions[0].range_start, mentions[0].range_length), (0, 1));
+}
+
+#[test]
+fn importer_links_quotes_by_sent_timestamp() {
+    set_test_key();
+    let tmp = tempdir().expect("temp");
+    let signal_db = tmp.path().join("signal.sqlite");
+    create_signal_db(&signal_db).expect("signal db");
+    let conn = Connection::open(&signal_db).expect("signal conn");
+    // quote_id carries the quoted message's date_sent: mms:1 was sent at 2, so 3 is within
+    // a millisecond of it; nothing was sent at 999.
+    conn.execute_batch(
+        "INSERT INTO mms (_id, thread_id, body, date_received, date_sent, type, recipient_id, quote_id, quote_author, quote_body) \
+         VALUES (2, 1, 'reply', 50, 50, 1, 1, 3, 1, 'mms body');
+         INSERT INTO mms (_id, thread_id, body, date_received, date_sent, type, recipient_id, quote_id, quote_author, quote_body) \
+         VALUES (3, 1, 'reply to deleted', 60, 60, 1, 1, 999, 1, 'gone');",
+    )
+    .unwrap();
+
+    let export_dir = tmp.path().join("frames");
+    fs::create_dir_all(&export_dir).expect("frames dir");
+    let archive_dir = tmp.path().join("archive");
+    fs::create_dir_all(&archive_dir).expect("archive dir");
+    let archive_path = archive_dir.join("archive.sqlite");
+    import_from_signal_db_for_tests(&signal_db, &archive_path, &export_dir).expect("import");
+
+    let archive = open_archive(&archive_path).expect("open archive");
+    let quote = |id: &str| -> (Option<String>, serde_json::Value) {
+        archive
+            .conn
+            .query_row(
+                "SELECT quote_message_id, metadata_json FROM messages WHERE id = ?1;",
+                [id],
+                |row| {
+                    let metadata: Option<String> = row.get(1)?;
+                    Ok((row.get(0)?, serde_json::from_str(&metadata.unwrap_or_default()).unwrap_or_default()))
+                },
+            )
+            .unwrap()
+    };
+    assert_eq!(quote("mms:2").0.as_deref(), Some("mms:1"));
+    let (unresolved, metadata) = quote("mms:3");
+    assert_eq!(unresolved, None);
+    assert_eq!(metadata["quote_body"], "gone");
+}
+
+#[test]
+fn importer_folds_long_message_text_into_body() {
+    set_test_key();
+    let tmp = tempdir().expect("temp");
+    let signal_db = tmp.path().join("signal.sqlite");
+    create_signal_db(&signal_db).expect("signal db");
+    let conn = Connection::open(&signal_db).expect("signal conn");
+    conn.execute_batch(
+        "INSERT INTO mms (_id, thread_id, body, date_received, date_sent, type, recipient_id) \
+         VALUES (2, 1, 'Once upon a time', 5, 5, 1, 1);
+         INSERT INTO part (_id, message_id, unique_id, content_type, data_size, file_name) \
+         VALUES (6, 2, 2, 'text/x-signal-plain', NULL, NULL);",
+    )
+    .unwrap();
+
+    let export_dir = tmp.path().join("frames");
+    fs::create_dir_all(&export_dir).expect("frames dir");
+    fs::write(export_dir.join("Attachment_5_1.bin"), b"test").expect("attachment");
+    let long_text = format!("Once upon a time {} the end, zeppelin", "and then ".repeat(300));
+    fs::write(export_dir.join("Attachment_6_2.bin"), long_text.as_bytes()).expect("long text");
+    let archive_dir = tmp.path().join("archive");
+    fs::create_dir_all(&archive_dir).expect("archive dir");
+    let archive_path = archive_dir.join("archive.sqlite");
+    import_from_signal_db_for_tests(&signal_db, &archive_path, &export_dir).expect("import");
+
+    let archive = open_archive(&archive_path).expect("open archive");
+    let body: String = archive
+        .conn
+        .query_row("SELECT body FROM messages WHERE id = 'mms:2';", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(body, long_text);
+    let attachments: i64 = archive
+        .conn
+        .query_row("SELECT COUNT(1) FROM attachments WHERE message_id = 'mms:2';", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(attachments, 0);
+    let hits = search_messages(&archive.conn, "zeppelin", &SearchOptions::default(), 10, 0).expect("search");
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].message.id, "mms:2");
+}
+
+#[test]
+fn importer_extracts_link_previews_from_column_and_table() {
+    set_test_key();
+    let tmp = tempdir().expect("temp");
+    let signal_db = tmp.path().join("signal.sqlite");
+    create_signal_db(&signal_db).expect("signal db");
+    let conn = Connection::open(&signal_db).expect("signal conn");
+    conn.execute_batch(
+        r#"ALTER TABLE mms ADD COLUMN previews TEXT;
+         CREATE TABLE link_preview (_id INTEGER PRIMARY KEY, message_id INTEGER, url TEXT, title TEXT, description TEXT, attachment_id INTEGER);
+         INSERT INTO mms (_id, thread_id, body, date_received, date_sent, type, recipient_id, previews)
+         VALUES (2, 1, 'https://example.org/post', 5, 5, 1, 1,
+           '[{"url":"https://example.org/post","title":"A post","description":"About things","date":0,"attachmentId":{"rowId":6,"uniqueId":2}}]');
+         INSERT INTO mms (_id, thread_id, body, date_received, date_sent, type, recipient_id)
+         VALUES (3, 1, 'https://example.com', 6, 6, 1, 1);
+         INSERT INTO link_preview (message_id, url, title, description, attachment_id)
+         VALUES (3, 'https://example.com', 'Example', '', NULL);
+         INSERT INTO part (_id, message_id, unique_id, content_type, data_size, file_name)
+         VALUES (6, 2, 2, 'image/jpeg', NULL, NULL);"#,
+    )
+    .unwrap();
+
+    let export_dir = tmp.path().join("frames");
+    fs::create_dir_all(&export_dir).expect("frames dir");
+    fs::write(export_dir.join("Attachment_5_1.bin"), b"test").expect("attachment");
+    fs::write(export_dir.join("Attachment_6_2.bin"), b"preview thumbnail").expect("thumbnail");
+    let archive_dir = tmp.path().join("archive");
+    fs::create_dir_all(&archive_dir).expect("archive dir");
+    let archive_path = archive_dir.join("archive.sqlite");
+    import_from_signal_db_for_tests(&signal_db, &archive_path, &export_dir).expect("import");
+
+    let archive = open_archive(&archive_path).expect("open archive");
+    let metadata = |id: &str| -> serde_json::Value {
+        let json: Option<String> = archive
+            .conn
+            .query_row("SELECT metadata_json FROM messages WHERE id = ?1;", [id], |row| row.get(0))
+            .unwrap();
+        json.map(|json| serde_json::from_str(&json).unwrap()).unwrap_or_default()
+    };
+    let thumbnail_sha: String = archive
+        .conn
+        .query_row("SELECT sha256 FROM attachments WHERE message_id = 'mms:2';", [], |row| row.get(0))
+        .unwrap();
+    let post = metadata("mms:2");
+    assert_eq!(
+        post["link_previews"],
+        serde_json::json!([{
+            "url": "https://example.org/post",
+            "title": "A post",
+            "description": "About things",
+            "attachment_sha": thumbnail_sha,
+        }])
+    );
+    let example = metadata("mms:3");
+    assert_eq!(example["link_previews"][0]["title"], "Example");
+    assert!(example["link_previews"][0]["description"].is_null());
+    assert!(example["link_previews"][0]["attachment_sha"].is_null());
+    assert!(metadata("mms:1").get("link_previews").is_none());
+    assert_eq!(metadata("mms:1")["expires_in_ms"], 604_800_000);
+}
+
+#[test]
+fn importer_records_delivery_and_read_receipts() {
+    set_test_key();
+    let tmp = tempdir().expect("temp");
+    let signal_db = tmp.path().join("signal.sqlite");
+    create_signal_db(&signal_db).expect("signal db");
+    let conn = Connection::open(&signal_db).expect("signal conn");
+    // sms uses the old count columns, mms the newer flags plus per-member group receipts
+    // (status 1 delivered, 2 read, 0 undelivered).
+    conn.execute_batch(
+        "ALTER TABLE sms ADD COLUMN delivery_receipt_count INTEGER DEFAULT 0;
+         ALTER TABLE sms ADD COLUMN read_receipt_count INTEGER DEFAULT 0;
+         ALTER TABLE sms ADD COLUMN receipt_timestamp INTEGER DEFAULT -1;
+         ALTER TABLE mms ADD COLUMN has_delivery_receipt INTEGER DEFAULT 0;
+         ALTER TABLE mms ADD COLUMN has_read_receipt INTEGER DEFAULT 0;
+         ALTER TABLE mms ADD COLUMN receipt_timestamp INTEGER DEFAULT -1;
+         UPDATE sms SET type = 23, delivery_receipt_count = 1, receipt_timestamp = 5 WHERE _id = 10;
+         UPDATE mms SET type = 23, has_delivery_receipt = 1, has_read_receipt = 1, receipt_timestamp = 9 WHERE _id = 1;
+         CREATE TABLE group_receipts (_id INTEGER PRIMARY KEY, mms_id INTEGER, address INTEGER, status INTEGER, timestamp INTEGER);
+         INSERT INTO group_receipts (mms_id, address, status, timestamp) VALUES (1, 2, 1, 4), (1, 3, 2, 7), (1, 4, 0, 1);",
+    )
+    .unwrap();
+
+    let export_dir = tmp.path().join("frames");
+    fs::create_dir_all(&export_dir).expect("frames dir");
+    let archive_dir = tmp.path().join("archive");
+    fs::create_dir_all(&archive_dir).expect("archive dir");
+    let archive_path = archive_dir.join("archive.sqlite");
+    import_from_signal_db_for_tests(&signal_db, &archive_path, &export_dir).expect("import");
+
+    let archive = open_archive(&archive_path).expect("open archive");
+    let messages = list_messages(&archive.conn, "1", None, None, 10).expect("messages");
+    let receipt = |id: &str| {
+        let msg = messages.iter().find(|m| m.id == id).expect("message");
+        (msg.delivered_at, msg.read_at)
+    };
+    assert_eq!(receipt("sms:10"), (Some(5), None));
+    assert_eq!(receipt("mms:1"), (Some(4), Some(7)));
+}
+
+#[test]
+fn inspection_counts_backup_contents_without_an_archive() {
+    let tmp = tempdir().expect("temp");
+    let signal_db = tmp.path().join("signal.sqlite");
+    create_signal_db(&signal_db).expect("signal db");
+    Connection::open(&signal_db)
+        .expect("signal conn")
+        .execute_batch(
+            "PRAGMA user_version = 215;
+             INSERT INTO part (_id, message_id, unique_id, content_type, data_size, file_name) VALUES (6, 1, 1, 'video/mp4', 2000, 'clip.mp4');",
+        )
+        .unwrap();
+
+    let inspection = inspect_signal_db_for_tests(&signal_db).expect("inspect");
+    assert_eq!(inspection.detected_version.as_deref(), Some("signal-android db 215"));
+    assert_eq!((inspection.threads, inspection.sms, inspection.mms), (1, 1, 1));
+    assert_eq!((inspection.attachments, inspection.attachment_bytes), (2, 2004));
+    assert_eq!((inspection.first_message_at, inspection.last_message_at), (Some(1), Some(2)));
+    assert!(!tmp.path().join("archive.sqlite").exists());
+}
+
+#[test]
+fn importer_reports_detected_database_version() {
+    set_test_key();
+    let tmp = tempdir().expect("temp");
+    let signal_db = tmp.path().join("signal.sqlite");
+    create_signal_db(&signal_db).expect("signal db");
+    let export_dir = tmp.path().join("frames");
+    fs::create_dir_all(&export_dir).expect("frames dir");
+    let archive_dir = tmp.path().join("archive");
+    fs::create_dir_all(&archive_dir).expect("archive dir");
+
+    let conn = Connection::open(&signal_db).expect("signal conn");
+    conn.execute_batch("PRAGMA user_version = 215;").unwrap();
+    let stats = import_from_signal_db_for_tests(&signal_db, &archive_dir.join("first.sqlite"), &export_dir)
+        .expect("import");
+    let stats: serde_json::Value = serde_json::from_str(&stats).unwrap();
+    assert_eq!(stats["detected_version"], "signal-android db 215");
+
+    conn.execute_batch(
+        "CREATE TABLE key_value (_id INTEGER PRIMARY KEY, key TEXT UNIQUE, value BLOB, type INTEGER);
+         INSERT INTO key_value (key, value, type) VALUES ('signal_version', '7.12.3', 4);",
+    )
+    .unwrap();
+    let stats = import_from_signal_db_for_tests(&signal_db, &archive_dir.join("second.sqlite"), &export_dir)
+        .expect("import");
+    let stats: serde_json::Value = serde_json::from_str(&stats).unwrap();
+    assert_eq!(stats["detected_version"], "signal-android 7.12.3, db 215");
+}
+
+#[test]
+fn importer_records_disappearing_message_timers() {
+    set_test_key();
+    let tmp = tempdir().expect("temp");
+    let signal_db = tmp.path().join("signal.sqlite");
+    create_signal_db(&signal_db).expect("signal db");
+
+    let export_dir = tmp.path().join("frames");
+    fs::create_dir_all(&export_dir).expect("frames dir");
+    let archive_dir = tmp.path().join("archive");
+    fs::create_dir_all(&archive_dir).expect("archive dir");
+    let archive_path = archive_dir.join("archive.sqlite");
+
+    import_from_signal_db_for_tests(&signal_db, &archive_path, &export_dir).expect("import");
+
+    let archive = open_archive(&archive_path).expect("open archive");
+    let metadata = |id: &str| -> serde_json::Value {
+        let json: Option<String> = archive
+            .conn
+            .query_row("SELECT metadata_json FROM messages WHERE id = ?1;", [id], |row| row.get(0))
+            .unwrap();
+        json.map(|json| serde_json::from_str(&json).unwrap()).unwrap_or_default()
+    };
+    assert_eq!(metadata("mms:1")["expires_in_ms"], 604_800_000);
+    assert!(metadata("sms:10").get("expires_in_ms").is_none());
+
+    let threads = list_threads(&archive.conn, 10, 0).expect("threads");
+    assert_eq!(threads.len(), 1);
+    assert_eq!(threads[0].expire_timer_ms, Some(604_800_000));
+}
+
 #[test]
 fn importer_handles_missing_attachment_files() {
     set_test_key();
@@ -173,6 +535,51 @@ fn importer_handles_missing_attachment_files() {
     assert_eq!(attachment_count, 0);
 }
 
+#[test]
+fn importer_attaches_parts_of_sms_messages_to_sms_ids() {
+    set_test_key();
+    let tmp = tempdir().expect("temp");
+    let signal_db = tmp.path().join("signal.sqlite");
+    create_signal_db(&signal_db).expect("signal db");
+    let conn = Connection::open(&signal_db).expect("signal conn");
+    // sms:10 has no mms counterpart; nothing at all has _id 99.
+    conn.execute_batch(
+        "INSERT INTO part (_id, message_id, unique_id, content_type, data_size, file_name) VALUES (6, 10, 1, 'image/png', 3, 'old.png');
+         INSERT INTO part (_id, message_id, unique_id, content_type, data_size, file_name) VALUES (7, 99, 1, 'image/png', 3, 'lost.png');",
+    )
+    .unwrap();
+
+    let export_dir = tmp.path().join("frames");
+    fs::create_dir_all(&export_dir).expect("frames dir");
+    fs::write(export_dir.join("Attachment_5_1.bin"), b"test").expect("attachment");
+    fs::write(export_dir.join("Attachment_6_1.bin"), b"old").expect("attachment");
+    fs::write(export_dir.join("Attachment_7_1.bin"), b"gone").expect("attachment");
+    let archive_dir = tmp.path().join("archive");
+    fs::create_dir_all(&archive_dir).expect("archive dir");
+    let archive_path = archive_dir.join("archive.sqlite");
+    let stats = import_from_signal_db_for_tests(&signal_db, &archive_path, &export_dir).expect("import");
+    let stats: serde_json::Value = serde_json::from_str(&stats).unwrap();
+    assert_eq!(stats["attachments_unresolved"], 1);
+
+    let archive = open_archive(&archive_path).expect("open archive");
+    let mut stmt = archive
+        .conn
+        .prepare("SELECT message_id, original_filename FROM attachments ORDER BY message_id;")
+        .unwrap();
+    let attached: Vec<(String, Option<String>)> = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .unwrap()
+        .map(|row| row.unwrap())
+        .collect();
+    assert_eq!(
+        attached,
+        vec![
+            ("mms:1".to_string(), Some("pic.jpg".to_string())),
+            ("sms:10".to_string(), Some("old.png".to_string())),
+        ]
+    );
+}
+
 #[test]
 fn importer_dedupes_attachment_files_by_hash() {
     set_test_key();
@@ -308,73 +715,69 @@ fn importer_handles_missing_attachment_metadata() {
 }
 
 #[test]
-fn importer_idempotent_same_archive() {
+fn importer_sniffs_attachments_without_content_type() {
     set_test_key();
     let tmp = tempdir().expect("temp");
     let signal_db = tmp.path().join("signal.sqlite");
     create_signal_db(&signal_db).expect("signal db");
+    let conn = Connection::open(&signal_db).expect("db");
+    conn.execute("UPDATE part SET content_type = NULL;", []).unwrap();
+    drop(conn);
 
     let export_dir = tmp.path().join("frames");
     fs::create_dir_all(&export_dir).expect("frames dir");
-    fs::write(export_dir.join("Attachment_5_1.bin"), b"test").expect("attachment");
+    fs::write(export_dir.join("Attachment_5_1.bin"), [0xFF, 0xD8, 0xFF, 0xE1, 0x00, 0x18, b'E', b'x', b'i', b'f'])
+        .expect("attachment");
 
     let archive_dir = tmp.path().join("archive");
     fs::create_dir_all(&archive_dir).expect("archive dir");
     let archive_path = archive_dir.join("archive.sqlite");
 
-    import_from_signal_db_for_tests(&signal_db, &archive_path, &export_dir).expect("import 1");
-    import_from_signal_db_for_tests(&signal_db, &archive_path, &export_dir).expect("import 2");
+    import_from_signal_db_for_tests(&signal_db, &archive_path, &export_dir).expect("import");
 
     let archive = open_archive(&archive_path).expect("open archive");
-    let msg_count: i64 = archive
+    let (mime, kind): (Option<String>, String) = archive
         .conn
-        .query_row("SELECT COUNT(1) FROM messages;", [], |row| row.get(0))
-        .unwrap();
-    assert_eq!(msg_count, 2);
-    let attachment_count: i64 = archive
-        .conn
-        .query_row("SELECT COUNT(1) FROM attachments;", [], |row| row.get(0))
+        .query_row("SELECT mime, kind FROM attachments LIMIT 1;", [], |row| Ok((row.get(0)?, row.get(1)?)))
         .unwrap();
-    assert_eq!(attachment_count, 1);
+    assert_eq!(mime.as_deref(), Some("image/jpeg"));
+    assert_eq!(kind, "image");
 }
 
 #[test]
-fn importer_incremental_adds_new_messages_only() {
+fn importer_reads_missing_image_dimensions_and_blurhash() {
     set_test_key();
     let tmp = tempdir().expect("temp");
-    let signal_db_a = tmp.path().join("signal_a.sqlite");
-    create_signal_db(&signal_db_a).expect("signal db a");
-
-    let signal_db_b = tmp.path().join("signal_b.sqlite");
-    create_signal_db(&signal_db_b).expect("signal db b");
-    let conn_b = Connection::open(&signal_db_b).expect("db b");
-    conn_b.execute(
-        "INSERT INTO sms (_id, thread_id, body, date, date_sent, type, recipient_id, quote_id, quote_author, quote_body) \
-         VALUES (11, 1, 'new message', 4, 4, 1, 1, NULL, NULL, NULL);",
-        [],
-    )
-    .unwrap();
+    let signal_db = tmp.path().join("signal.sqlite");
+    create_signal_db(&signal_db).expect("signal db");
 
     let export_dir = tmp.path().join("frames");
     fs::create_dir_all(&export_dir).expect("frames dir");
-    fs::write(export_dir.join("Attachment_5_1.bin"), b"test").expect("attachment");
+    image::RgbImage::new(5, 3)
+        .save_with_format(export_dir.join("Attachment_5_1.bin"), image::ImageFormat::Jpeg)
+        .expect("attachment");
 
     let archive_dir = tmp.path().join("archive");
     fs::create_dir_all(&archive_dir).expect("archive dir");
     let archive_path = archive_dir.join("archive.sqlite");
 
-    import_from_signal_db_for_tests(&signal_db_a, &archive_path, &export_dir).expect("import a");
-    import_from_signal_db_for_tests(&signal_db_b, &archive_path, &export_dir).expect("import b");
+    import_from_signal_db_for_tests(&signal_db, &archive_path, &export_dir).expect("import");
 
     let archive = open_archive(&archive_path).expect("open archive");
-    let msg_count: i64 = archive
+    let dims: (Option<i64>, Option<i64>) = archive
         .conn
-        .query_row("SELECT COUNT(1) FROM messages;", [], |row| row.get(0))
+        .query_row("SELECT width, height FROM attachments LIMIT 1;", [], |row| Ok((row.get(0)?, row.get(1)?)))
         .unwrap();
-    assert_eq!(msg_count, 3);
+    assert_eq!(dims, (Some(5), Some(3)));
+    let blurhash: String = archive
+        .conn
+        .query_row("SELECT blurhash FROM attachments WHERE blurhash IS NOT NULL LIMIT 1;", [], |row| row.get(0))
+        .expect("blurhash recorded at import");
+    assert_eq!(blurhash.len(), 28);
 }
+
 #[test]
-fn importer_handles_missing_optional_columns() {
+fn importer_marks_sticker_messages_and_imports_sticker_image() {
     set_test_key();
     let tmp = tempdir().expect("temp");
     let signal_db = tmp.path().join("signal.sqlite");
@@ -383,45 +786,764 @@ fn importer_handles_missing_optional_columns() {
         r#"
         CREATE TABLE recipient (_id INTEGER PRIMARY KEY, e164 TEXT, system_joined_name TEXT, profile_given_name TEXT, group_id INTEGER);
         CREATE TABLE groups (group_id INTEGER PRIMARY KEY, title TEXT);
-        CREATE TABLE thread (_id INTEGER PRIMARY KEY, recipient_id INTEGER, date INTEGER);
-        CREATE TABLE sms (_id INTEGER PRIMARY KEY, thread_id INTEGER, body TEXT, date INTEGER, date_sent INTEGER, type INTEGER, recipient_id INTEGER);
+        CREATE TABLE thread (_id INTEGER PRIMARY KEY, recipient_id INTEGER, date INTEGER, message_count INTEGER);
         CREATE TABLE mms (_id INTEGER PRIMARY KEY, thread_id INTEGER, body TEXT, date_received INTEGER, date_sent INTEGER, type INTEGER, recipient_id INTEGER);
+        CREATE TABLE part (_id INTEGER PRIMARY KEY, message_id INTEGER, unique_id INTEGER, content_type TEXT, data_size INTEGER, file_name TEXT, sticker_pack_id TEXT, sticker_pack_key TEXT, sticker_id INTEGER, sticker_emoji TEXT);
+        CREATE TABLE sticker (_id INTEGER PRIMARY KEY, pack_id TEXT, pack_title TEXT, pack_author TEXT, sticker_id INTEGER, cover INTEGER, emoji TEXT);
+        INSERT INTO recipient (_id, e164, system_joined_name, profile_given_name, group_id) VALUES (1, '+15550001111', 'Alice', 'Alice', NULL);
+        INSERT INTO thread (_id, recipient_id, date, message_count) VALUES (1, 1, 2, 2);
+        INSERT INTO mms (_id, thread_id, body, date_received, date_sent, type, recipient_id) VALUES (1, 1, NULL, 1, 1, 20, 1);
+        INSERT INTO mms (_id, thread_id, body, date_received, date_sent, type, recipient_id) VALUES (2, 1, 'plain', 2, 2, 20, 1);
+        INSERT INTO part (_id, message_id, unique_id, content_type, data_size, file_name, sticker_pack_id, sticker_pack_key, sticker_id, sticker_emoji)
+          VALUES (5, 1, 1, 'image/webp', 4, NULL, 'abc123', 'key', 7, '🐱');
+        INSERT INTO sticker (_id, pack_id, pack_title, pack_author, sticker_id, cover, emoji) VALUES (1, 'abc123', 'Cats', 'Someone', 0, 1, '🐱');
         "#,
     )
     .unwrap();
-    conn.execute(
-        "INSERT INTO recipient (_id, e164) VALUES (1, '+15550001111');",
-        [],
-    )
-    .unwrap();
-    conn.execute(
-        "INSERT INTO thread (_id, recipient_id, date) VALUES (1, 1, 2);",
-        [],
-    )
-    .unwrap();
-    conn.execute(
-        "INSERT INTO sms (_id, thread_id, body, date, date_sent, type, recipient_id) VALUES (1, 1, 'hi', 1, 1, 1, 1);",
-        [],
-    )
-    .unwrap();
-    conn.execute(
-        "INSERT INTO mms (_id, thread_id, body, date_received, date_sent, type, recipient_id) VALUES (2, 1, 'hey', 2, 2, 1, 1);",
-        [],
-    )
-    .unwrap();
+    drop(conn);
 
     let export_dir = tmp.path().join("frames");
     fs::create_dir_all(&export_dir).expect("frames dir");
+    fs::write(export_dir.join("Attachment_5_1.bin"), b"RIFF\x04\0\0\0WEBP").expect("sticker file");
+
     let archive_dir = tmp.path().join("archive");
     fs::create_dir_all(&archive_dir).expect("archive dir");
     let archive_path = archive_dir.join("archive.sqlite");
 
     import_from_signal_db_for_tests(&signal_db, &archive_path, &export_dir).expect("import");
 
+    let archive = open_archive(&archive_path).expect("open archive");
+    let (message_type, metadata): (String, Option<String>) = archive
+        .conn
+        .query_row("SELECT type, metadata_json FROM messages WHERE id = 'mms:1';", [], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })
+        .unwrap();
+    assert_eq!(message_type, "sticker");
+    let metadata: serde_json::Value = serde_json::from_str(&metadata.expect("metadata")).unwrap();
+    assert_eq!(metadata["sticker_pack_title"], "Cats");
+    assert_eq!(metadata["sticker_emoji"], "🐱");
+    let plain_type: String = archive
+        .conn
+        .query_row("SELECT type FROM messages WHERE id = 'mms:2';", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(plain_type, "text");
+    let kind: String = archive
+        .conn
+        .query_row("SELECT kind FROM attachments WHERE message_id = 'mms:1';", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(kind, "image");
+}
+
+#[test]
+fn importer_imports_calls_as_timeline_events() {
+    set_test_key();
+    let tmp = tempdir().expect("temp");
+    let signal_db = tmp.path().join("signal.sqlite");
+    let conn = Connection::open(&signal_db).expect("db");
+    conn.execute_batch(
+        r#"
+        CREATE TABLE recipient (_id INTEGER PRIMARY KEY, e164 TEXT, system_joined_name TEXT, profile_given_name TEXT, group_id INTEGER);
+        CREATE TABLE groups (group_id INTEGER PRIMARY KEY, title TEXT);
+        CREATE TABLE thread (_id INTEGER PRIMARY KEY, recipient_id INTEGER, date INTEGER, message_count INTEGER);
+        CREATE TABLE sms (_id INTEGER PRIMARY KEY, thread_id INTEGER, body TEXT, date INTEGER, date_sent INTEGER, type INTEGER, recipient_id INTEGER);
+        CREATE TABLE mms (_id INTEGER PRIMARY KEY, thread_id INTEGER, body TEXT, date_received INTEGER, date_sent INTEGER, type INTEGER, recipient_id INTEGER);
+        CREATE TABLE call (_id INTEGER PRIMARY KEY, call_id INTEGER, message_id INTEGER, peer INTEGER, type INTEGER, direction INTEGER, event INTEGER, timestamp INTEGER);
+        INSERT INTO recipient (_id, e164, system_joined_name, profile_given_name, group_id) VALUES (1, '+15550001111', 'Alice', 'Alice', NULL);
+        INSERT INTO thread (_id, recipient_id, date, message_count) VALUES (1, 1, 3, 3);
+        -- Older backups: a missed video call recorded only by its type bits.
+        INSERT INTO sms (_id, thread_id, body, date, date_sent, type, recipient_id) VALUES (10, 1, NULL, 1, 1, 8, 1);
+        -- Newer backups: an outgoing voice call with its details in the call table.
+        INSERT INTO mms (_id, thread_id, body, date_received, date_sent, type, recipient_id) VALUES (1, 1, NULL, 2, 2, 2, 1);
+        INSERT INTO call (_id, call_id, message_id, peer, type, direction, event, timestamp) VALUES (1, 99, 1, 1, 0, 1, 1, 2);
+        INSERT INTO mms (_id, thread_id, body, date_received, date_sent, type, recipient_id) VALUES (2, 1, 'after the call', 3, 3, 20, 1);
+        "#,
+    )
+    .unwrap();
+    drop(conn);
+
+    let export_dir = tmp.path().join("frames");
+    fs::create_dir_all(&export_dir).expect("frames dir");
+    let archive_dir = tmp.path().join("archive");
+    fs::create_dir_all(&archive_dir).expect("archive dir");
+    let archive_path = archive_dir.join("archive.sqlite");
+
+    import_from_signal_db_for_tests(&signal_db, &archive_path, &export_dir).expect("import");
+
+    let archive = open_archive(&archive_path).expect("open archive");
+    let messages = list_messages(&archive.conn, "1", None, None, 10).expect("messages");
+    let types: Vec<(&str, &str, bool)> =
+        messages.iter().map(|m| (m.id.as_str(), m.message_type.as_str(), m.is_outgoing)).collect();
+    assert_eq!(
+        types,
+        vec![("mms:2", "text", false), ("mms:1", "call", true), ("sms:10", "call", false)]
+    );
+
+    let call_metadata = |id: &str| -> serde_json::Value {
+        let message = messages.iter().find(|m| m.id == id).expect("call message");
+        serde_json::from_str(message.metadata_json.as_deref().expect("metadata")).unwrap()
+    };
+    let outgoing = call_metadata("mms:1");
+    assert_eq!(outgoing["call_direction"], "outgoing");
+    assert_eq!(outgoing["call_is_video"], false);
+    assert!(outgoing["call_duration_ms"].is_null());
+    let missed = call_metadata("sms:10");
+    assert_eq!(missed["call_direction"], "missed");
+    assert_eq!(missed["call_is_video"], true);
+}
+
+#[test]
+fn importer_flags_view_once_and_skips_purged_media() {
+    set_test_key();
+    let tmp = tempdir().expect("temp");
+    let signal_db = tmp.path().join("signal.sqlite");
+    create_signal_db(&signal_db).expect("signal db");
+    let conn = Connection::open(&signal_db).expect("db");
+    conn.execute_batch(
+        r#"
+        INSERT INTO mms (_id, thread_id, body, date_received, date_sent, type, recipient_id, view_once)
+          VALUES (2, 1, NULL, 3, 3, 20, 1, 1), (3, 1, NULL, 4, 4, 20, 1, 1);
+        INSERT INTO part (_id, message_id, unique_id, content_type, data_size, file_name)
+          VALUES (6, 2, 1, 'image/jpeg', 4, NULL), (7, 3, 1, 'image/jpeg', 4, NULL);
+        "#,
+    )
+    .unwrap();
+    drop(conn);
+
+    let export_dir = tmp.path().join("frames");
+    fs::create_dir_all(&export_dir).expect("frames dir");
+    fs::write(export_dir.join("Attachment_5_1.bin"), b"test").expect("regular attachment");
+    // The unopened view-once photo is still on disk; the opened one (mms 3) was purged.
+    fs::write(export_dir.join("Attachment_6_1.bin"), b"secret").expect("view-once attachment");
+
+    let archive_dir = tmp.path().join("archive");
+    fs::create_dir_all(&archive_dir).expect("archive dir");
+    let archive_path = archive_dir.join("archive.sqlite");
+
+    let stats = import_from_signal_db_for_tests(&signal_db, &archive_path, &export_dir).expect("import");
+    let stats: serde_json::Value = serde_json::from_str(&stats).unwrap();
+    assert_eq!(stats["attachments_found"], 2);
+    assert_eq!(stats["attachments_missing"], 0);
+    assert_eq!(stats["attachments_view_once_purged"], 1);
+    assert_eq!(stats["attachments_bytes"], b"test".len() + b"secret".len());
+
+    let archive = open_archive(&archive_path).expect("open archive");
+    let flags: Vec<(String, bool)> = archive
+        .conn
+        .prepare("SELECT id, is_view_once FROM messages WHERE id LIKE 'mms:%' ORDER BY id;")
+        .unwrap()
+        .query_map([], |row| Ok((row.get(0)?, row.get::<_, i64>(1)? != 0)))
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(
+        flags,
+        vec![("mms:1".to_string(), false), ("mms:2".to_string(), true), ("mms:3".to_string(), true)]
+    );
+    let attachment_count: i64 = archive
+        .conn
+        .query_row("SELECT COUNT(1) FROM attachments;", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(attachment_count, 2);
+}
+
+#[test]
+fn importer_idempotent_same_archive() {
+    set_test_key();
+    let tmp = tempdir().expect("temp");
+    let signal_db = tmp.path().join("signal.sqlite");
+    create_signal_db(&signal_db).expect("signal db");
+
+    let export_dir = tmp.path().join("frames");
+    fs::create_dir_all(&export_dir).expect("frames dir");
+    fs::write(export_dir.join("Attachment_5_1.bin"), b"test").expect("attachment");
+
+    let archive_dir = tmp.path().join("archive");
+    fs::create_dir_all(&archive_dir).expect("archive dir");
+    let archive_path = archive_dir.join("archive.sqlite");
+
+    import_from_signal_db_for_tests(&signal_db, &archive_path, &export_dir).expect("import 1");
+    import_from_signal_db_for_tests(&signal_db, &archive_path, &export_dir).expect("import 2");
+
     let archive = open_archive(&archive_path).expect("open archive");
     let msg_count: i64 = archive
         .conn
         .query_row("SELECT COUNT(1) FROM messages;", [], |row| row.get(0))
         .unwrap();
     assert_eq!(msg_count, 2);
+    let attachment_count: i64 = archive
+        .conn
+        .query_row("SELECT COUNT(1) FROM attachments;", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(attachment_count, 1);
+}
+
+#[test]
+fn importer_resumes_after_interruption_between_stages() {
+    set_test_key();
+    // (stage to interrupt at, detail to interrupt at, stage the resumed run must not revisit)
+    let kill_points = [
+        (ImportStage::Messages, Some("MMS"), Some("SMS")),
+        (ImportStage::Attachments, None, Some("MMS")),
+    ];
+    for (kill_stage, kill_detail, skipped_detail) in kill_points {
+        let tmp = tempdir().expect("temp");
+        let signal_db = tmp.path().join("signal.sqlite");
+        create_signal_db(&signal_db).expect("signal db");
+        // A reply to mms:1 (sent at 2), so quote links have to be rebuilt on resume.
+        Connection::open(&signal_db)
+            .expect("signal conn")
+            .execute_batch(
+                "INSERT INTO mms (_id, thread_id, body, date_received, date_sent, type, recipient_id, quote_id, quote_author, quote_body) \
+                 VALUES (2, 1, 'reply', 50, 50, 1, 1, 2, 1, 'mms body');",
+            )
+            .unwrap();
+        let export_dir = tmp.path().join("frames");
+        fs::create_dir_all(&export_dir).expect("frames dir");
+        fs::write(export_dir.join("Attachment_5_1.bin"), b"test").expect("attachment");
+        let archive_dir = tmp.path().join("archive");
+        fs::create_dir_all(&archive_dir).expect("archive dir");
+        let archive_path = archive_dir.join("archive.sqlite");
+
+        let interrupted = catch_unwind(AssertUnwindSafe(|| {
+            import_from_signal_db_resumable_for_tests(&signal_db, &archive_path, &export_dir, "hash-1", |event| {
+                if event.stage == kill_stage && (kill_detail.is_none() || event.detail.as_deref() == kill_detail) {
+                    panic!("import killed");
+                }
+            })
+        }));
+        assert!(interrupted.is_err(), "import should have been interrupted at {:?}", kill_stage);
+
+        let details: RefCell<Vec<String>> = RefCell::new(Vec::new());
+        let stats = import_from_signal_db_resumable_for_tests(&signal_db, &archive_path, &export_dir, "hash-1", |event| {
+            details.borrow_mut().extend(event.detail)
+        })
+        .expect("resumed import");
+        assert!(!details.into_inner().iter().any(|d| Some(d.as_str()) == skipped_detail));
+        let stats: serde_json::Value = serde_json::from_str(&stats).expect("stats json");
+        assert_eq!(stats["sms_inserted"], 1);
+        assert_eq!(stats["mms_inserted"], 2);
+        assert_eq!(stats["attachments_inserted"], 1);
+        assert_eq!(stats["quotes_resolved"], 1);
+
+        let archive = open_archive(&archive_path).expect("open archive");
+        let count = |sql: &str| -> i64 { archive.conn.query_row(sql, [], |row| row.get(0)).unwrap() };
+        assert_eq!(count("SELECT COUNT(1) FROM messages;"), 3);
+        assert_eq!(count("SELECT COUNT(1) FROM attachments;"), 1);
+        assert_eq!(count("SELECT COUNT(1) FROM reactions;"), 1);
+        assert_eq!(count("SELECT COUNT(1) FROM messages WHERE quote_message_id = 'mms:1';"), 1);
+        assert_eq!(count("SELECT COUNT(1) FROM imports WHERE status = 'success';"), 1);
+        assert_eq!(count("SELECT COUNT(1) FROM imports;"), 1);
+        assert_eq!(count("SELECT COUNT(1) FROM import_checkpoints;"), 0);
+    }
+}
+
+#[test]
+fn importer_analyzes_archive_after_import() {
+    set_test_key();
+    let tmp = tempdir().expect("temp");
+    let signal_db = tmp.path().join("signal.sqlite");
+    create_signal_db(&signal_db).expect("signal db");
+    let export_dir = tmp.path().join("frames");
+    fs::create_dir_all(&export_dir).expect("frames dir");
+    let archive_dir = tmp.path().join("archive");
+    fs::create_dir_all(&archive_dir).expect("archive dir");
+    let archive_path = archive_dir.join("archive.sqlite");
+
+    let details: RefCell<Vec<String>> = RefCell::new(Vec::new());
+    import_from_signal_db_resumable_for_tests(&signal_db, &archive_path, &export_dir, "hash-1", |event| {
+        details.borrow_mut().extend(event.detail)
+    })
+    .expect("import");
+    let details = details.into_inner();
+    assert!(details.ends_with(&["analyzing".to_string(), "optimizing".to_string(), "checkpointing".to_string()]));
+
+    let archive = open_archive(&archive_path).expect("open archive");
+    let stat_rows: i64 = archive
+        .conn
+        .query_row("SELECT COUNT(1) FROM sqlite_stat1;", [], |row| row.get(0))
+        .expect("sqlite_stat1");
+    assert!(stat_rows > 0);
+    let optimize_ms: Option<i64> = archive
+        .conn
+        .query_row("SELECT json_extract(stats_json, '$.optimize_ms') FROM imports;", [], |row| row.get(0))
+        .unwrap();
+    assert!(optimize_ms.is_some());
+}
+
+#[test]
+fn importer_incremental_adds_new_messages_only() {
+    set_test_key();
+    let tmp = tempdir().expect("temp");
+    let signal_db_a = tmp.path().join("signal_a.sqlite");
+    create_signal_db(&signal_db_a).expect("signal db a");
+
+    let signal_db_b = tmp.path().join("signal_b.sqlite");
+    create_signal_db(&signal_db_b).expect("signal db b");
+    let conn_b = Connection::open(&signal_db_b).expect("db b");
+    conn_b.execute(
+        "INSERT INTO sms (_id, thread_id, body, date, date_sent, type, recipient_id, quote_id, quote_author, quote_body) \
+         VALUES (11, 1, 'new message', 4, 4, 1, 1, NULL, NULL, NULL);",
+        [],
+    )
+    .unwrap();
+
+    let export_dir = tmp.path().join("frames");
+    fs::create_dir_all(&export_dir).expect("frames dir");
+    fs::write(export_dir.join("Attachment_5_1.bin"), b"test").expect("attachment");
+
+    let archive_dir = tmp.path().join("archive");
+    fs::create_dir_all(&archive_dir).expect("archive dir");
+    let archive_path = archive_dir.join("archive.sqlite");
+
+    import_from_signal_db_for_tests(&signal_db_a, &archive_path, &export_dir).expect("import a");
+    import_from_signal_db_for_tests(&signal_db_b, &archive_path, &export_dir).expect("import b");
+
+    let archive = open_archive(&archive_path).expect("open archive");
+    let msg_count: i64 = archive
+        .conn
+        .query_row("SELECT COUNT(1) FROM messages;", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(msg_count, 3);
+}
+
+#[test]
+fn importer_merges_renumbered_recipients_across_backups() {
+    set_test_key();
+    let tmp = tempdir().expect("temp");
+    let signal_db_a = tmp.path().join("signal_a.sqlite");
+    create_signal_db(&signal_db_a).expect("signal db a");
+
+    // A later backup where Alice became recipient 7 / thread 9, her messages were renumbered,
+    // and Bob took her old recipient number and her old message numbers.
+    let signal_db_b = tmp.path().join("signal_b.sqlite");
+    create_signal_db(&signal_db_b).expect("signal db b");
+    let conn_b = Connection::open(&signal_db_b).expect("db b");
+    conn_b
+        .execute_batch(
+            "UPDATE recipient SET _id = 7 WHERE _id = 1;
+             UPDATE thread SET _id = 9, recipient_id = 7;
+             UPDATE sms SET _id = 20, thread_id = 9, recipient_id = 7, quote_author = 7;
+             UPDATE mms SET _id = 3, thread_id = 9, recipient_id = 7;
+             UPDATE part SET message_id = 3;
+             UPDATE reaction SET message_id = 3, author_id = 7;
+             UPDATE mention SET message_id = 3, thread_id = 9, recipient_id = 7;
+             INSERT INTO recipient (_id, e164, system_joined_name, profile_given_name, group_id) VALUES (1, '+15550002222', 'Bob', 'Bob', NULL);
+             INSERT INTO thread (_id, recipient_id, date, message_count) VALUES (1, 1, 6, 2);
+             INSERT INTO sms (_id, thread_id, body, date, date_sent, type, recipient_id) VALUES (11, 9, 'alice again', 4, 4, 1, 7);
+             INSERT INTO sms (_id, thread_id, body, date, date_sent, type, recipient_id) VALUES (10, 1, 'hi from bob', 5, 5, 1, 1);
+             INSERT INTO mms (_id, thread_id, body, date_received, date_sent, type, recipient_id) VALUES (1, 1, 'bob mms', 6, 6, 1, 1);
+             INSERT INTO reaction (message_id, emoji, author_id, date) VALUES (1, '🎉', 7, 7);",
+        )
+        .unwrap();
+
+    let export_dir = tmp.path().join("frames");
+    fs::create_dir_all(&export_dir).expect("frames dir");
+    fs::write(export_dir.join("Attachment_5_1.bin"), b"test").expect("attachment");
+    let archive_dir = tmp.path().join("archive");
+    fs::create_dir_all(&archive_dir).expect("archive dir");
+    let archive_path = archive_dir.join("archive.sqlite");
+
+    import_from_signal_db_for_tests(&signal_db_a, &archive_path, &export_dir).expect("import a");
+    import_from_signal_db_for_tests(&signal_db_b, &archive_path, &export_dir).expect("import b");
+
+    let archive = open_archive(&archive_path).expect("open archive");
+    let alice: Vec<String> = archive
+        .conn
+        .prepare("SELECT id FROM recipients WHERE phone_e164 = '+15550001111';")
+        .unwrap()
+        .query_map([], |row| row.get(0))
+        .unwrap()
+        .map(|r| r.unwrap())
+        .collect();
+    assert_eq!(alice, vec!["1".to_string()]);
+    let bob: String = archive
+        .conn
+        .query_row("SELECT id FROM recipients WHERE phone_e164 = '+15550002222';", [], |row| row.get(0))
+        .unwrap();
+    assert_ne!(bob, "1");
+
+    let threads = list_threads(&archive.conn, 10, 0).expect("threads");
+    assert_eq!(threads.len(), 2);
+    let alice_messages: Vec<(String, Option<String>)> = archive
+        .conn
+        .prepare("SELECT id, sender_id FROM messages WHERE thread_id = '1' ORDER BY sent_at;")
+        .unwrap()
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .unwrap()
+        .map(|r| r.unwrap())
+        .collect();
+    let ids: Vec<&str> = alice_messages.iter().map(|m| m.0.as_str()).collect();
+    assert_eq!(ids, vec!["sms:10", "mms:1", "sms:11"]);
+    assert!(alice_messages.iter().all(|m| m.1.as_deref() == Some("1")));
+
+    // Bob's messages reuse Alice's old numbers but are kept under new ids.
+    let bob_messages: Vec<(String, String, Option<String>)> = archive
+        .conn
+        .prepare("SELECT id, body, sender_id FROM messages WHERE thread_id != '1' ORDER BY sent_at;")
+        .unwrap()
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+        .unwrap()
+        .map(|r| r.unwrap())
+        .collect();
+    assert_eq!(bob_messages.len(), 2);
+    assert_eq!(bob_messages[0].1, "hi from bob");
+    assert_eq!(bob_messages[1].1, "bob mms");
+    assert!(bob_messages[0].0.starts_with("sms:") && bob_messages[0].0 != "sms:10");
+    assert!(bob_messages[1].0.starts_with("mms:") && bob_messages[1].0 != "mms:1");
+    assert!(bob_messages.iter().all(|m| m.2.as_deref() == Some(bob.as_str())));
+
+    let message_count: i64 = archive.conn.query_row("SELECT COUNT(*) FROM messages;", [], |row| row.get(0)).unwrap();
+    assert_eq!(message_count, 5);
+    let reactions: Vec<(String, String)> = archive
+        .conn
+        .prepare("SELECT message_id, emoji FROM reactions ORDER BY reacted_at;")
+        .unwrap()
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .unwrap()
+        .map(|r| r.unwrap())
+        .collect();
+    assert_eq!(reactions, vec![("mms:1".to_string(), "👍".to_string()), (bob_messages[1].0.clone(), "🎉".to_string())]);
+    let reactors: i64 = archive
+        .conn
+        .query_row("SELECT COUNT(DISTINCT reactor_id) FROM reactions;", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(reactors, 1);
+    let attachments: Vec<String> = archive
+        .conn
+        .prepare("SELECT message_id FROM attachments;")
+        .unwrap()
+        .query_map([], |row| row.get(0))
+        .unwrap()
+        .map(|r| r.unwrap())
+        .collect();
+    assert_eq!(attachments, vec!["mms:1".to_string()]);
+    let mentions: i64 = archive
+        .conn
+        .query_row("SELECT COUNT(*) FROM mentions WHERE message_id = 'mms:1';", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(mentions, 1);
+}
+
+#[test]
+fn importer_incremental_indexes_new_messages_without_rebuild() {
+    set_test_key();
+    let tmp = tempdir().expect("temp");
+    let signal_db_a = tmp.path().join("signal_a.sqlite");
+    create_signal_db(&signal_db_a).expect("signal db a");
+
+    let signal_db_b = tmp.path().join("signal_b.sqlite");
+    create_signal_db(&signal_db_b).expect("signal db b");
+    let conn_b = Connection::open(&signal_db_b).expect("db b");
+    conn_b.execute(
+        "INSERT INTO sms (_id, thread_id, body, date, date_sent, type, recipient_id, quote_id, quote_author, quote_body) \
+         VALUES (11, 1, 'fresh avocado toast', 4, 4, 1, 1, NULL, NULL, NULL);",
+        [],
+    )
+    .unwrap();
+
+    let export_dir = tmp.path().join("frames");
+    fs::create_dir_all(&export_dir).expect("frames dir");
+    fs::write(export_dir.join("Attachment_5_1.bin"), b"test").expect("attachment");
+
+    let archive_dir = tmp.path().join("archive");
+    fs::create_dir_all(&archive_dir).expect("archive dir");
+    let archive_path = archive_dir.join("archive.sqlite");
+
+    import_from_signal_db_for_tests(&signal_db_a, &archive_path, &export_dir).expect("import a");
+    {
+        // Tag an indexed row directly; a full rebuild would wipe the marker.
+        let archive = open_archive(&archive_path).expect("open archive");
+        archive
+            .conn
+            .execute(
+                "UPDATE message_fts SET body = body || ' rebuildmarker' WHERE message_id = 'mms:1';",
+                [],
+            )
+            .unwrap();
+    }
+    import_from_signal_db_for_tests(&signal_db_b, &archive_path, &export_dir).expect("import b");
+
+    let archive = open_archive(&archive_path).expect("open archive");
+    let hits = search_messages(&archive.conn, "avocado", &SearchOptions::default(), 10, 0).expect("search new");
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].message.id, "sms:11");
+    let marker: i64 = archive
+        .conn
+        .query_row(
+            "SELECT COUNT(1) FROM message_fts WHERE message_fts MATCH 'rebuildmarker';",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(marker, 1);
+}
+#[test]
+fn importer_normalizes_recipient_phone_numbers() {
+    set_test_key();
+    let tmp = tempdir().expect("temp");
+    let signal_db = tmp.path().join("signal.sqlite");
+    create_signal_db(&signal_db).expect("signal db");
+    let conn = Connection::open(&signal_db).expect("signal conn");
+    // The owner's own number supplies the default calling code for local formats.
+    conn.execute_batch(
+        "CREATE TABLE key_value (_id INTEGER PRIMARY KEY, key TEXT UNIQUE, value BLOB, type INTEGER);
+         INSERT INTO key_value (key, value, type) VALUES ('account.e164', '+15559998888', 4);
+         INSERT INTO recipient (_id, e164) VALUES
+           (2, '555-000-1111'), (3, '(555) 000-2222'), (4, '1 555 000 3333'), (5, '+44 7700 900123'), (6, '72345');",
+    )
+    .unwrap();
+
+    let export_dir = tmp.path().join("frames");
+    fs::create_dir_all(&export_dir).expect("frames dir");
+    let archive_dir = tmp.path().join("archive");
+    fs::create_dir_all(&archive_dir).expect("archive dir");
+    let archive_path = archive_dir.join("archive.sqlite");
+    import_from_signal_db_for_tests(&signal_db, &archive_path, &export_dir).expect("import");
+
+    let archive = open_archive(&archive_path).expect("open archive");
+    let rows = |sql: &str| -> Vec<(String, Option<String>)> {
+        let mut stmt = archive.conn.prepare(sql).unwrap();
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .map(|row| row.unwrap())
+            .collect()
+    };
+    let phone = |e164: &str| Some(e164.to_string());
+    // "555-000-1111" is Alice's "+15550001111" and merges into her recipient.
+    assert_eq!(
+        rows("SELECT id, phone_e164 FROM recipients ORDER BY id;"),
+        vec![
+            ("1".to_string(), phone("+15550001111")),
+            ("3".to_string(), phone("+15550002222")),
+            ("4".to_string(), phone("+15550003333")),
+            ("5".to_string(), phone("+447700900123")),
+            ("6".to_string(), phone("72345")),
+        ]
+    );
+    assert_eq!(
+        rows("SELECT id, raw_phone FROM recipients ORDER BY id;"),
+        vec![
+            ("1".to_string(), phone("555-000-1111")),
+            ("3".to_string(), phone("(555) 000-2222")),
+            ("4".to_string(), phone("1 555 000 3333")),
+            ("5".to_string(), phone("+44 7700 900123")),
+            ("6".to_string(), None),
+        ]
+    );
+}
+
+#[test]
+fn importer_handles_missing_optional_columns() {
+    set_test_key();
+    let tmp = tempdir().expect("temp");
+    let signal_db = tmp.path().join("signal.sqlite");
+    let conn = Connection::open(&signal_db).expect("db");
+    conn.execute_batch(
+        r#"
+        CREATE TABLE recipient (_id INTEGER PRIMARY KEY, e164 TEXT, system_joined_name TEXT, profile_given_name TEXT, group_id INTEGER);
+        CREATE TABLE groups (group_id INTEGER PRIMARY KEY, title TEXT);
+        CREATE TABLE thread (_id INTEGER PRIMARY KEY, recipient_id INTEGER, date INTEGER);
+        CREATE TABLE sms (_id INTEGER PRIMARY KEY, thread_id INTEGER, body TEXT, date INTEGER, date_sent INTEGER, type INTEGER, recipient_id INTEGER);
+        CREATE TABLE mms (_id INTEGER PRIMARY KEY, thread_id INTEGER, body TEXT, date_received INTEGER, date_sent INTEGER, type INTEGER, recipient_id INTEGER);
+        "#,
+    )
+    .unwrap();
+    conn.execute(
+        "INSERT INTO recipient (_id, e164) VALUES (1, '+15550001111');",
+        [],
+    )
+    .unwrap();
+    conn.execute(
+        "INSERT INTO thread (_id, recipient_id, date) VALUES (1, 1, 2);",
+        [],
+    )
+    .unwrap();
+    conn.execute(
+        "INSERT INTO sms (_id, thread_id, body, date, date_sent, type, recipient_id) VALUES (1, 1, 'hi', 1, 1, 1, 1);",
+        [],
+    )
+    .unwrap();
+    conn.execute(
+        "INSERT INTO mms (_id, thread_id, body, date_received, date_sent, type, recipient_id) VALUES (2, 1, 'hey', 2, 2, 1, 1);",
+        [],
+    )
+    .unwrap();
+
+    let export_dir = tmp.path().join("frames");
+    fs::create_dir_all(&export_dir).expect("frames dir");
+    let archive_dir = tmp.path().join("archive");
+    fs::create_dir_all(&archive_dir).expect("archive dir");
+    let archive_path = archive_dir.join("archive.sqlite");
+
+    import_from_signal_db_for_tests(&signal_db, &archive_path, &export_dir).expect("import");
+
+    let archive = open_archive(&archive_path).expect("open archive");
+    let msg_count: i64 = archive
+        .conn
+        .query_row("SELECT COUNT(1) FROM messages;", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(msg_count, 2);
+}
+
+#[test]
+fn importer_types_group_updates_and_system_events() {
+    set_test_key();
+    let tmp = tempdir().expect("temp");
+    let signal_db = tmp.path().join("signal.sqlite");
+    let conn = Connection::open(&signal_db).expect("db");
+    conn.execute_batch(
+        r#"
+        CREATE TABLE recipient (_id INTEGER PRIMARY KEY, e164 TEXT, system_joined_name TEXT, profile_given_name TEXT, group_id INTEGER);
+        CREATE TABLE groups (group_id INTEGER PRIMARY KEY, title TEXT);
+        CREATE TABLE thread (_id INTEGER PRIMARY KEY, recipient_id INTEGER, date INTEGER, message_count INTEGER);
+        CREATE TABLE sms (_id INTEGER PRIMARY KEY, thread_id INTEGER, body TEXT, date INTEGER, date_sent INTEGER, type INTEGER, recipient_id INTEGER);
+        CREATE TABLE mms (_id INTEGER PRIMARY KEY, thread_id INTEGER, body TEXT, date_received INTEGER, date_sent INTEGER, type INTEGER, recipient_id INTEGER, expires_in INTEGER);
+        INSERT INTO recipient (_id, e164, system_joined_name, profile_given_name, group_id) VALUES (1, '+15550001111', 'Alice', 'Alice', NULL);
+        INSERT INTO thread (_id, recipient_id, date, message_count) VALUES (1, 1, 4, 4);
+        -- Sent GV2 group update (base 23 | secure | push | GV2 | group update); the body is an encoded payload.
+        INSERT INTO mms (_id, thread_id, body, date_received, date_sent, type, recipient_id, expires_in) VALUES (1, 1, 'CiQKIAAA', 1, 1, 11075607, 1, 0);
+        -- Outgoing disappearing-messages timer change to one day.
+        INSERT INTO mms (_id, thread_id, body, date_received, date_sent, type, recipient_id, expires_in) VALUES (2, 1, NULL, 2, 2, 10747927, 1, 86400000);
+        -- Incoming safety-number change.
+        INSERT INTO sms (_id, thread_id, body, date, date_sent, type, recipient_id) VALUES (3, 1, NULL, 3, 3, 532, 1);
+        INSERT INTO mms (_id, thread_id, body, date_received, date_sent, type, recipient_id, expires_in) VALUES (4, 1, 'hello', 4, 4, 23, 1, 0);
+        "#,
+    )
+    .unwrap();
+    drop(conn);
+
+    let export_dir = tmp.path().join("frames");
+    fs::create_dir_all(&export_dir).expect("frames dir");
+    let archive_dir = tmp.path().join("archive");
+    fs::create_dir_all(&archive_dir).expect("archive dir");
+    let archive_path = archive_dir.join("archive.sqlite");
+
+    import_from_signal_db_for_tests(&signal_db, &archive_path, &export_dir).expect("import");
+
+    let archive = open_archive(&archive_path).expect("open archive");
+    let messages = list_messages(&archive.conn, "1", None, None, 10).expect("messages");
+    let rows: Vec<(&str, &str, bool, Option<&str>)> = messages
+        .iter()
+        .map(|m| (m.id.as_str(), m.message_type.as_str(), m.is_outgoing, m.body.as_deref()))
+        .collect();
+    assert_eq!(
+        rows,
+        vec![
+            ("mms:4", "text", true, Some("hello")),
+            ("sms:3", "identity_change", false, None),
+            ("mms:2", "expiration_timer", false, None),
+            ("mms:1", "group_update", false, None),
+        ]
+    );
+
+    let event_text = |id: &str| -> String {
+        let message = messages.iter().find(|m| m.id == id).expect("event message");
+        let metadata: serde_json::Value =
+            serde_json::from_str(message.metadata_json.as_deref().expect("metadata")).unwrap();
+        metadata["event_text"].as_str().expect("event_text").to_string()
+    };
+    assert_eq!(event_text("mms:1"), "The group was updated");
+    assert_eq!(event_text("mms:2"), "Disappearing messages set to 1 day");
+    assert_eq!(event_text("sms:3"), "Safety number changed");
+}
+
+#[test]
+fn importer_records_skipped_rows_as_import_issues() {
+    set_test_key();
+    let tmp = tempdir().expect("temp");
+    let signal_db = tmp.path().join("signal.sqlite");
+    create_signal_db(&signal_db).expect("signal db");
+    let conn = Connection::open(&signal_db).expect("signal conn");
+    conn.execute_batch(
+        "INSERT INTO part (_id, message_id, unique_id, content_type, data_size, file_name) VALUES (7, 99, 1, 'image/png', 3, 'lost.png');
+         INSERT INTO reaction (message_id, emoji, author_id, date) VALUES (1, NULL, 1, 3);
+         INSERT INTO mention (_id, thread_id, message_id, recipient_id, range_start, range_length) VALUES (4, 1, 1, NULL, 0, 1);",
+    )
+    .unwrap();
+    // No frames for part 5, so its file is missing.
+    let export_dir = tmp.path().join("frames");
+    fs::create_dir_all(&export_dir).expect("frames dir");
+    let archive_dir = tmp.path().join("archive");
+    fs::create_dir_all(&archive_dir).expect("archive dir");
+    let archive_path = archive_dir.join("archive.sqlite");
+    import_from_signal_db_resumable_for_tests(&signal_db, &archive_path, &export_dir, "hash-1", |_| {})
+        .expect("import");
+
+    let archive = open_archive(&archive_path).expect("open archive");
+    let import_id = list_imports(&archive.conn).expect("imports")[0].id.clone();
+    let issues: Vec<(String, String, Option<String>)> = list_import_issues(&archive.conn, &import_id)
+        .expect("issues")
+        .into_iter()
+        .map(|issue| (issue.category, issue.source_table, issue.source_id))
+        .collect();
+    assert_eq!(
+        issues,
+        vec![
+            ("attachment_unresolved".to_string(), "part".to_string(), Some("7".to_string())),
+            ("attachment_missing".to_string(), "part".to_string(), Some("5".to_string())),
+            ("reaction_without_emoji".to_string(), "reaction".to_string(), Some("2".to_string())),
+            ("mention_incomplete".to_string(), "mention".to_string(), Some("4".to_string())),
+        ]
+    );
+    assert!(list_import_issues(&archive.conn, "other-import").expect("issues").is_empty());
+}
+
+#[test]
+fn importer_prefers_nicknames_and_falls_back_to_usernames() {
+    set_test_key();
+    let tmp = tempdir().expect("temp");
+    let signal_db = tmp.path().join("signal.sqlite");
+    create_signal_db(&signal_db).expect("signal db");
+    let conn = Connection::open(&signal_db).expect("signal conn");
+    conn.execute_batch(
+        "ALTER TABLE recipient ADD COLUMN nickname_given_name TEXT;
+         ALTER TABLE recipient ADD COLUMN nickname_family_name TEXT;
+         ALTER TABLE recipient ADD COLUMN username TEXT;
+         UPDATE recipient SET nickname_given_name = 'Al', nickname_family_name = 'Smith' WHERE _id = 1;
+         INSERT INTO recipient (_id, username) VALUES (2, 'bob.42');
+         INSERT INTO recipient (_id, e164, profile_given_name, username) VALUES (3, '+15550003333', 'Carol', 'carol.7');
+         INSERT INTO thread (_id, recipient_id, date, message_count) VALUES (2, 2, 5, 0), (3, 3, 4, 0);",
+    )
+    .unwrap();
+
+    let export_dir = tmp.path().join("frames");
+    fs::create_dir_all(&export_dir).expect("frames dir");
+    let archive_dir = tmp.path().join("archive");
+    fs::create_dir_all(&archive_dir).expect("archive dir");
+    let archive_path = archive_dir.join("archive.sqlite");
+    import_from_signal_db_for_tests(&signal_db, &archive_path, &export_dir).expect("import");
+
+    let archive = open_archive(&archive_path).expect("open archive");
+    let mut names: Vec<Option<String>> = list_threads(&archive.conn, 10, 0)
+        .expect("threads")
+        .into_iter()
+        .map(|thread| thread.name)
+        .collect();
+    names.sort();
+    assert_eq!(
+        names,
+        vec![Some("Al Smith".to_string()), Some("Carol".to_string()), Some("bob.42".to_string())]
+    );
+    let usernames: Vec<(String, Option<String>, Option<String>)> = {
+        let mut stmt = archive
+            .conn
+            .prepare(
+                "SELECT id, nickname, username FROM recipients \
+                 WHERE nickname IS NOT NULL OR username IS NOT NULL ORDER BY id;",
+            )
+            .unwrap();
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .map(|row| row.unwrap())
+            .collect()
+    };
+    assert_eq!(
+        usernames,
+        vec![
+            ("1".to_string(), Some("Al Smith".to_string()), None),
+            ("2".to_string(), None, Some("bob.42".to_string())),
+            ("3".to_string(), None, Some("carol.7".to_string())),
+        ]
+    );
 }
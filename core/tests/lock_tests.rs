@@ -0,0 +1,33 @@
+// Locking is process-wide, so these tests live in their own binary rather than next to
+// the unit tests that share the cached master key.
+use golden_thread_core::crypto::{
+    is_master_key_locked, load_or_create_master_key, lock_master_key, set_test_key_from_passphrase,
+    unlock_master_key,
+};
+use golden_thread_core::query::list_threads;
+use golden_thread_core::{open_archive, CoreError};
+use tempfile::tempdir;
+
+#[test]
+fn locked_archive_refuses_to_open_until_unlocked() {
+    set_test_key_from_passphrase("golden-thread-tests");
+    let dir = tempdir().expect("temp");
+    let path = dir.path().join("archive.sqlite");
+    let db = open_archive(&path).expect("open");
+    db.conn
+        .execute("INSERT INTO threads (id, name, last_message_at) VALUES ('t1', 'Test Thread', 1);", [])
+        .expect("insert");
+    drop(db);
+
+    lock_master_key();
+    assert!(is_master_key_locked());
+    assert!(matches!(load_or_create_master_key(), Err(CoreError::Locked)));
+    assert!(matches!(open_archive(&path), Err(CoreError::Locked)));
+
+    unlock_master_key();
+    assert!(!is_master_key_locked());
+    let db = open_archive(&path).expect("reopen");
+    let threads = list_threads(&db.conn, 10, 0).expect("query");
+    assert_eq!(threads.len(), 1);
+    assert_eq!(threads[0].id, "t1");
+}
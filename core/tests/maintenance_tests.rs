@@ -0,0 +1,227 @@
+use std::fs;
+use std::sync::Mutex;
+
+use golden_thread_core::crypto;
+use golden_thread_core::db::apply_migrations;
+use golden_thread_core::maintenance::{
+    audit_attachments, backfill_blurhash, backfill_image_dimensions, gc_orphaned_attachments, resniff_attachments,
+    verify_attachments,
+};
+use rusqlite::Connection;
+use sha2::{Digest, Sha256};
+use tempfile::tempdir;
+
+fn setup_db() -> Connection {
+    let conn = Connection::open_in_memory().expect("memory db");
+    apply_migrations(&conn).expect("migrate");
+    conn.execute(
+        "INSERT INTO threads (id, name, last_message_at) VALUES ('t1', 'Test Thread', 2);",
+        [],
+    )
+    .unwrap();
+    for (id, ts) in [("m1", 1_i64), ("m2", 2_i64)] {
+        conn.execute(
+            "INSERT INTO messages (id, thread_id, sender_id, sent_at, received_at, type, body, is_outgoing, is_view_once, dedupe_key) \
+             VALUES (?1, 't1', 'r1', ?2, ?2, 'text', 'hello', 0, 0, ?1);",
+            rusqlite::params![id, ts],
+        )
+        .unwrap();
+    }
+    // "shared" is referenced twice, once from each message.
+    for (id, message_id, sha256, filename) in [
+        ("a1", "m1", "aaa", "one.jpg"),
+        ("a2", "m1", "shared", "two.jpg"),
+        ("a3", "m2", "shared", "two-again.jpg"),
+    ] {
+        conn.execute(
+            "INSERT INTO attachments (id, message_id, sha256, original_filename, kind) VALUES (?1, ?2, ?3, ?4, 'image');",
+            rusqlite::params![id, message_id, sha256, filename],
+        )
+        .unwrap();
+    }
+    conn
+}
+
+#[test]
+fn audit_attachments_reports_missing_blobs() {
+    let conn = setup_db();
+    let dir = tempdir().expect("attachments dir");
+    fs::write(dir.path().join("aaa"), b"blob").unwrap();
+    fs::write(dir.path().join("shared"), b"blob").unwrap();
+
+    let clean = audit_attachments(&conn, dir.path(), |_| {}).expect("audit");
+    assert_eq!(clean.checked, 2);
+    assert!(clean.missing.is_empty());
+
+    fs::remove_file(dir.path().join("shared")).unwrap();
+    let messages = Mutex::new(Vec::new());
+    let report = audit_attachments(&conn, dir.path(), |msg| messages.lock().unwrap().push(msg.to_string()))
+        .expect("audit");
+
+    assert_eq!(report.checked, 2);
+    assert_eq!(report.missing.len(), 1);
+    let missing = &report.missing[0];
+    assert_eq!(missing.sha256, "shared");
+    assert_eq!(missing.reference_count, 2);
+    assert_eq!((missing.message_id.as_str(), missing.original_filename.as_deref()), ("m1", Some("two.jpg")));
+    assert_eq!(missing.thread_id.as_deref(), Some("t1"));
+    assert_eq!(messages.lock().unwrap().last().map(String::as_str), Some("Checking attachments... 2/2"));
+}
+
+#[test]
+fn gc_orphaned_attachments_spares_referenced_and_temp_files() {
+    let conn = setup_db();
+    let referenced = "a".repeat(64);
+    let orphan = "b".repeat(64);
+    conn.execute("UPDATE attachments SET sha256 = ?1 WHERE id = 'a1';", [&referenced]).unwrap();
+
+    let dir = tempdir().expect("attachments dir");
+    fs::write(dir.path().join(&referenced), b"kept").unwrap();
+    fs::write(dir.path().join(&orphan), b"orphaned").unwrap();
+    let in_flight = tempfile::NamedTempFile::new_in(dir.path()).expect("temp file");
+    fs::write(in_flight.path(), b"half written").unwrap();
+    fs::write(dir.path().join(".DS_Store"), b"finder").unwrap();
+
+    let dry = gc_orphaned_attachments(&conn, dir.path(), true).expect("dry run");
+    assert_eq!(dry.candidates, vec![orphan.clone()]);
+    assert_eq!(dry.bytes, 8);
+    assert_eq!(dry.deleted, 0);
+    assert!(dir.path().join(&orphan).exists());
+
+    let report = gc_orphaned_attachments(&conn, dir.path(), false).expect("gc");
+    assert_eq!(report.deleted, 1);
+    assert!(!dir.path().join(&orphan).exists());
+    assert!(dir.path().join(&referenced).exists());
+    assert!(in_flight.path().exists());
+    assert!(dir.path().join(".DS_Store").exists());
+}
+
+#[test]
+fn resniff_attachments_fills_in_jpeg_without_mime() {
+    crypto::set_test_key_from_passphrase("golden-thread-tests");
+    let key = crypto::load_or_create_master_key().expect("key");
+    let conn = setup_db();
+    conn.execute("UPDATE attachments SET kind = 'file';", []).unwrap();
+    conn.execute(
+        "INSERT INTO attachments (id, message_id, sha256, original_filename, kind) VALUES ('a4', 'm2', 'gone', 'x.bin', 'file');",
+        [],
+    )
+    .unwrap();
+
+    let dir = tempdir().expect("attachments dir");
+    let plain = dir.path().join("plain");
+    fs::write(&plain, [0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10, b'J', b'F', b'I', b'F']).unwrap();
+    crypto::encrypt_file_to_path(&plain, &dir.path().join("shared"), &key).expect("encrypt jpeg");
+    fs::write(&plain, b"just some notes").unwrap();
+    crypto::encrypt_file_to_path(&plain, &dir.path().join("aaa"), &key).expect("encrypt text");
+
+    let report = resniff_attachments(&conn, dir.path(), &key).expect("resniff");
+    assert_eq!((report.checked, report.updated, report.missing, report.failed), (3, 2, 1, 0));
+
+    let rows: Vec<(String, Option<String>, String)> = conn
+        .prepare("SELECT id, mime, kind FROM attachments ORDER BY id;")
+        .unwrap()
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    let jpeg = (Some("image/jpeg".to_string()), "image".to_string());
+    assert_eq!((rows[0].1.clone(), rows[0].2.clone()), (None, "file".to_string()));
+    assert_eq!((rows[1].1.clone(), rows[1].2.clone()), jpeg);
+    assert_eq!((rows[2].1.clone(), rows[2].2.clone()), jpeg);
+    assert_eq!((rows[3].1.clone(), rows[3].2.clone()), (None, "file".to_string()));
+}
+
+#[test]
+fn backfill_image_dimensions_reads_headers_from_first_chunk() {
+    crypto::set_test_key_from_passphrase("golden-thread-tests");
+    let key = crypto::load_or_create_master_key().expect("key");
+    let conn = setup_db();
+    conn.execute("UPDATE attachments SET width = 10, height = 10 WHERE id = 'a3';", []).unwrap();
+
+    let dir = tempdir().expect("attachments dir");
+    let plain = dir.path().join("plain.png");
+    image::RgbImage::new(7, 4).save(&plain).expect("png");
+    crypto::encrypt_file_to_path(&plain, &dir.path().join("shared"), &key).expect("encrypt png");
+    fs::write(&plain, b"not an image").unwrap();
+    crypto::encrypt_file_to_path(&plain, &dir.path().join("aaa"), &key).expect("encrypt text");
+
+    let report = backfill_image_dimensions(&conn, dir.path(), &key).expect("backfill");
+    assert_eq!((report.checked, report.updated, report.missing, report.failed), (2, 1, 0, 0));
+
+    let dims: Vec<(Option<i64>, Option<i64>)> = conn
+        .prepare("SELECT width, height FROM attachments ORDER BY id;")
+        .unwrap()
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(dims, vec![(None, None), (Some(7), Some(4)), (Some(10), Some(10))]);
+}
+
+#[test]
+fn backfill_blurhash_hashes_each_image_blob_once() {
+    crypto::set_test_key_from_passphrase("golden-thread-tests");
+    let key = crypto::load_or_create_master_key().expect("key");
+    let conn = setup_db();
+
+    let dir = tempdir().expect("attachments dir");
+    let plain = dir.path().join("plain.png");
+    image::RgbImage::from_pixel(8, 6, image::Rgb([255, 0, 0])).save(&plain).expect("png");
+    crypto::encrypt_file_to_path(&plain, &dir.path().join("shared"), &key).expect("encrypt png");
+    fs::write(&plain, b"not an image").unwrap();
+    crypto::encrypt_file_to_path(&plain, &dir.path().join("aaa"), &key).expect("encrypt text");
+
+    let report = backfill_blurhash(&conn, dir.path(), &key, |_| {}).expect("backfill");
+    assert_eq!((report.checked, report.updated, report.missing, report.failed), (2, 2, 0, 1));
+    let hashes: Vec<(String, String)> = conn
+        .prepare("SELECT sha256, blurhash FROM attachments WHERE blurhash IS NOT NULL ORDER BY id;")
+        .unwrap()
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(hashes.len(), 2, "both rows sharing the blob");
+    assert_eq!(hashes[0].0, "shared");
+    assert_eq!(&hashes[0].1[2..6], "TI:j", "average colour of a flat red image");
+    assert_eq!(hashes[0].1, hashes[1].1);
+
+    // Only the blob that failed is looked at again.
+    let report = backfill_blurhash(&conn, dir.path(), &key, |_| {}).expect("second backfill");
+    assert_eq!((report.checked, report.updated, report.failed), (1, 0, 1));
+}
+
+#[test]
+fn verify_attachments_flags_truncated_and_mismatched_blobs() {
+    crypto::set_test_key_from_passphrase("golden-thread-tests");
+    let key = crypto::load_or_create_master_key().expect("key");
+    let conn = setup_db();
+    let dir = tempdir().expect("attachments dir");
+    let plain = dir.path().join("plain");
+    fs::write(&plain, vec![7u8; 3 * 1024 * 1024]).unwrap();
+    let sha = hex::encode(Sha256::digest(fs::read(&plain).unwrap()));
+    crypto::encrypt_file_to_path(&plain, &dir.path().join(&sha), &key).expect("encrypt");
+    conn.execute(
+        "INSERT INTO attachments (id, message_id, sha256, kind) VALUES ('a4', 'm2', ?1, 'file');",
+        [&sha],
+    )
+    .unwrap();
+    // "aaa" decrypts cleanly but to the wrong bytes; "shared" is missing.
+    crypto::encrypt_file_to_path(&plain, &dir.path().join("aaa"), &key).expect("encrypt");
+
+    let report = verify_attachments(&conn, dir.path(), &key, |_| {}).expect("verify");
+    assert_eq!((report.checked, report.verified, report.missing), (3, 1, 1));
+    assert_eq!(report.legacy_format, 0);
+    assert_eq!(report.corrupt.len(), 1);
+    assert_eq!((report.corrupt[0].sha256.as_str(), report.corrupt[0].message_id.as_str()), ("aaa", "m1"));
+
+    let blob = dir.path().join(&sha);
+    let len = fs::metadata(&blob).unwrap().len();
+    fs::OpenOptions::new().write(true).open(&blob).unwrap().set_len(len - 100).unwrap();
+    let messages = Mutex::new(Vec::new());
+    let report = verify_attachments(&conn, dir.path(), &key, |msg| messages.lock().unwrap().push(msg.to_string()))
+        .expect("verify");
+    assert_eq!((report.verified, report.corrupt.len()), (0, 2));
+    assert!(report.corrupt.iter().any(|c| c.sha256 == sha && c.message_id == "m2"));
+    assert_eq!(messages.lock().unwrap().last().map(String::as_str), Some("Verifying attachments... 3/3"));
+}
@@ -1,4 +1,4 @@
-use golden_thread_core::db::apply_migrations;
+use golden_thread_core::db::{apply_migrations, apply_migrations_through_for_tests};
 use rusqlite::Connection;
 
 #[test]
@@ -61,3 +61,121 @@ fn imports_source_hash_unique_index_exists() {
         .expect("index query");
     assert_eq!(count, 1);
 }
+
+#[test]
+fn stale_fts_index_is_rebuilt_on_migrate() {
+    let conn = Connection::open_in_memory().expect("memory db");
+    apply_migrations(&conn).expect("migrate");
+    conn.execute(
+        "INSERT INTO messages (id, thread_id, type, body, dedupe_key) VALUES ('m1', 't1', 'text', 'crème brûlée', 'd1');",
+        [],
+    )
+    .expect("insert message");
+    conn.execute("DELETE FROM message_fts;", []).expect("clear fts");
+    conn.execute("DELETE FROM archive_meta WHERE key = 'fts_schema_version';", [])
+        .expect("forget fts version");
+
+    apply_migrations(&conn).expect("migrate again");
+
+    let hits: i64 = conn
+        .query_row(
+            "SELECT COUNT(1) FROM message_fts WHERE message_fts MATCH 'creme';",
+            [],
+            |row| row.get(0),
+        )
+        .expect("fts query");
+    assert_eq!(hits, 1);
+    let version: Option<String> = conn
+        .query_row(
+            "SELECT value FROM archive_meta WHERE key = 'fts_schema_version';",
+            [],
+            |row| row.get(0),
+        )
+        .expect("version row");
+    assert!(version.is_some());
+}
+
+#[test]
+fn document_kind_backfill_reclassifies_file_attachments() {
+    let conn = Connection::open_in_memory().expect("memory db");
+    // Stop just before the backfill (migration 15) so it runs over these rows.
+    apply_migrations_through_for_tests(&conn, 14).expect("migrate to 14");
+    for (id, mime) in [("a1", "application/pdf"), ("a2", "text/plain"), ("a3", "application/zip")] {
+        conn.execute(
+            "INSERT INTO attachments (id, message_id, sha256, mime, kind) VALUES (?1, 'm1', ?1, ?2, 'file');",
+            rusqlite::params![id, mime],
+        )
+        .expect("insert");
+    }
+    apply_migrations(&conn).expect("migrate the rest");
+
+    let mut stmt = conn.prepare("SELECT id, kind FROM attachments ORDER BY id;").expect("prepare");
+    let kinds: Vec<(String, String)> = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .expect("query")
+        .map(|row| row.expect("row"))
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            ("a1".to_string(), "document".to_string()),
+            ("a2".to_string(), "document".to_string()),
+            ("a3".to_string(), "file".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn recipient_identity_backfill_keys_android_recipients_by_phone() {
+    let conn = Connection::open_in_memory().expect("memory db");
+    // Stop just before the identity table (migration 18) so the backfill sees these rows.
+    apply_migrations_through_for_tests(&conn, 17).expect("migrate to 17");
+    conn.execute_batch(
+        "INSERT INTO recipients (id, phone_e164) VALUES ('1', '+15550001111'), ('2', NULL), ('r-abc', '+15550002222');",
+    )
+    .expect("insert");
+    apply_migrations(&conn).expect("migrate the rest");
+
+    let mut stmt = conn
+        .prepare("SELECT identity, recipient_id FROM recipient_identities ORDER BY identity;")
+        .expect("prepare");
+    let identities: Vec<(String, String)> = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .expect("query")
+        .map(|row| row.expect("row"))
+        .collect();
+    assert_eq!(identities, vec![("e164:+15550001111".to_string(), "1".to_string())]);
+}
+
+#[test]
+fn message_dedupe_keys_move_from_row_ids_to_sent_time() {
+    let conn = Connection::open_in_memory().expect("memory db");
+    // Stop just before the sent-time key migration (migration 24).
+    apply_migrations_through_for_tests(&conn, 23).expect("migrate to 23");
+    conn.execute_batch(
+        "INSERT INTO messages (id, thread_id, sender_id, sent_at, type, dedupe_key) VALUES
+           ('sms:1', '1', '2', 100, 'text', 'sms:1'),
+           ('mms:1', '1', NULL, 200, 'text', 'mms:1'),
+           ('mms:2', '1', NULL, 200, 'text', 'mms:2'),
+           ('sms:3', '1', '2', NULL, 'text', 'sms:3');",
+    )
+    .expect("insert");
+    apply_migrations(&conn).expect("migrate the rest");
+
+    let mut stmt = conn.prepare("SELECT id, dedupe_key FROM messages ORDER BY id;").expect("prepare");
+    let keys: Vec<(String, String)> = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .expect("query")
+        .map(|row| row.expect("row"))
+        .collect();
+    assert_eq!(
+        keys,
+        vec![
+            ("mms:1".to_string(), "sent:1:self:200".to_string()),
+            // Clashes with mms:1, so it keeps its row key.
+            ("mms:2".to_string(), "mms:2".to_string()),
+            ("sms:1".to_string(), "sent:1:2:100".to_string()),
+            ("sms:3".to_string(), "sms:3".to_string()),
+        ]
+    );
+}
@@ -0,0 +1,106 @@
+use golden_thread_core::db::apply_migrations;
+use golden_thread_core::query::{
+    add_message_tag, create_tag, delete_message_note, get_message_detail, get_message_note, get_message_notes_bulk,
+    search_message_notes, set_message_note,
+};
+use rusqlite::Connection;
+
+fn setup_db() -> Connection {
+    let conn = Connection::open_in_memory().expect("memory db");
+    conn.execute_batch("PRAGMA foreign_keys = ON;").expect("foreign keys");
+    apply_migrations(&conn).expect("migrate");
+    conn.execute(
+        "INSERT INTO threads (id, name, last_message_at) VALUES ('t1', 'Test Thread', 2);",
+        [],
+    )
+    .unwrap();
+    for (id, ts) in [("m1", 1_i64), ("m2", 2_i64)] {
+        conn.execute(
+            "INSERT INTO messages (id, thread_id, sender_id, sent_at, received_at, type, body, is_outgoing, is_view_once, dedupe_key) \
+             VALUES (?1, 't1', 'r1', ?2, ?2, 'text', 'hello', 0, 0, ?1);",
+            rusqlite::params![id, ts],
+        )
+        .unwrap();
+    }
+    conn
+}
+
+#[test]
+fn set_get_and_delete_message_note() {
+    let conn = setup_db();
+    assert!(get_message_note(&conn, "m1").expect("get").is_none());
+
+    set_message_note(&conn, "m1", "first draft").expect("set");
+    let note = set_message_note(&conn, "m1", "this is when we decided to move").expect("replace");
+    let stored = get_message_note(&conn, "m1").expect("get").expect("note");
+    assert_eq!(stored.note_text, "this is when we decided to move");
+    assert_eq!(stored.updated_at, note.updated_at);
+
+    delete_message_note(&conn, "m1").expect("delete");
+    assert!(get_message_note(&conn, "m1").expect("get").is_none());
+}
+
+#[test]
+fn set_message_note_rejects_blank_text() {
+    let conn = setup_db();
+    assert!(set_message_note(&conn, "m1", "   ").is_err());
+}
+
+#[test]
+fn get_message_notes_bulk_skips_messages_without_notes() {
+    let conn = setup_db();
+    set_message_note(&conn, "m2", "only this one").expect("set");
+
+    let notes = get_message_notes_bulk(&conn, &["m1".to_string(), "m2".to_string()]).expect("bulk");
+    assert_eq!(notes.len(), 1);
+    assert_eq!(notes[0].message_id, "m2");
+    assert!(get_message_notes_bulk(&conn, &[]).expect("empty").is_empty());
+}
+
+#[test]
+fn notes_are_searchable_and_follow_edits() {
+    let conn = setup_db();
+    set_message_note(&conn, "m1", "Décidé de déménager").expect("set m1");
+    set_message_note(&conn, "m2", "birthday plans").expect("set m2");
+
+    let hits = search_message_notes(&conn, "demenager", 10).expect("search");
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].message_id, "m1");
+    assert_eq!(search_message_notes(&conn, "birth", 10).expect("prefix").len(), 1);
+
+    set_message_note(&conn, "m2", "anniversary").expect("edit");
+    assert!(search_message_notes(&conn, "birthday", 10).expect("stale").is_empty());
+    assert_eq!(search_message_notes(&conn, "anniversary", 10).expect("fresh").len(), 1);
+
+    delete_message_note(&conn, "m1").expect("delete");
+    assert!(search_message_notes(&conn, "demenager", 10).expect("deleted").is_empty());
+    assert!(search_message_notes(&conn, "  ", 10).expect("blank").is_empty());
+}
+
+#[test]
+fn notes_cascade_with_their_message() {
+    let conn = setup_db();
+    set_message_note(&conn, "m1", "gone soon").expect("set");
+
+    conn.execute("DELETE FROM messages WHERE id = 'm1';", []).unwrap();
+
+    assert!(get_message_note(&conn, "m1").expect("get").is_none());
+    assert!(search_message_notes(&conn, "gone", 10).expect("search").is_empty());
+}
+
+#[test]
+fn message_detail_includes_tags_and_note() {
+    let conn = setup_db();
+    let tag = create_tag(&conn, "Moving", "#00ff00").expect("create tag");
+    add_message_tag(&conn, "m1", &tag.id).expect("tag");
+    set_message_note(&conn, "m1", "the big day").expect("set");
+
+    let detail = get_message_detail(&conn, "m1").expect("detail");
+    assert_eq!(detail.message.id, "m1");
+    assert_eq!(detail.tags.len(), 1);
+    assert_eq!(detail.note.map(|n| n.note_text), Some("the big day".to_string()));
+
+    let bare = get_message_detail(&conn, "m2").expect("detail");
+    assert!(bare.tags.is_empty());
+    assert!(bare.note.is_none());
+}
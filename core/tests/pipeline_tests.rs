@@ -3,6 +3,7 @@ use std::path::Path;
 
 use golden_thread_core::crypto;
 use golden_thread_core::importer::import_from_signal_db_for_tests;
+use golden_thread_core::models::{SearchOptions, ThreadMediaFilters};
 use golden_thread_core::query::{list_messages, list_thread_media, list_threads, search_messages};
 use golden_thread_core::open_archive;
 use rusqlite::Connection;
@@ -76,9 +77,13 @@ fn pipeline_import_populates_queries() {
     let messages = list_messages(&archive.conn, "1", None, None, 10).expect("messages");
     assert_eq!(messages.len(), 2);
 
-    let media = list_thread_media(&archive.conn, "1", None, None, None, "date_desc", 10, 0).expect("media");
+    let media = list_thread_media(&archive.conn, "1", &ThreadMediaFilters::default(), "date_desc", 10, 0).expect("media");
     assert_eq!(media.len(), 1);
 
-    let hits = search_messages(&archive.conn, "hello", Some("1"), 10, 0).expect("search");
+    let options = SearchOptions {
+        thread_id: Some("1".to_string()),
+        ..SearchOptions::default()
+    };
+    let hits = search_messages(&archive.conn, "hello", &options, 10, 0).expect("search");
     assert_eq!(hits.len(), 1);
 }
@@ -1,4 +1,5 @@
 use golden_thread_core::{db::apply_migrations, seed::seed_demo};
+use golden_thread_core::models::SearchOptions;
 use golden_thread_core::query::{list_messages, list_threads, search_messages};
 use rusqlite::Connection;
 
@@ -14,6 +15,6 @@ fn demo_seed_query_roundtrip() {
     let messages = list_messages(&conn, "t1", None, None, 50).expect("messages");
     assert_eq!(messages.len(), 2);
 
-    let hits = search_messages(&conn, "Demo", None, 10, 0).expect("search");
+    let hits = search_messages(&conn, "Demo", &SearchOptions::default(), 10, 0).expect("search");
     assert_eq!(hits.len(), 1);
 }
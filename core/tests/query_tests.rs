@@ -1,6 +1,10 @@
 use golden_thread_core::db::apply_migrations;
+use golden_thread_core::models::{SearchOptions, ThreadMediaFilters};
 use golden_thread_core::query::{
-    list_messages, list_messages_after, list_messages_around, list_threads, search_messages,
+    attachment_mime, attachment_total_bytes, get_attachment, get_last_successful_import, largest_attachments, list_imports, list_document_attachments, list_media_by_sender, list_messages,
+    list_messages_after,
+    list_mentions_for_messages, list_messages_around, list_thread_media, list_threads, search_messages, thread_media_month_buckets,
+    thread_media_summary, thread_storage_usage, OUTGOING_SENDER,
 };
 use rusqlite::Connection;
 
@@ -50,6 +54,31 @@ fn list_threads_returns_message_count() {
     assert_eq!(threads[0].message_count, 3);
 }
 
+#[test]
+fn list_threads_names_unnamed_threads_after_their_member() {
+    let conn = setup_db();
+    seed_messages(&conn);
+    conn.execute_batch(
+        "INSERT INTO threads (id, name, last_message_at) VALUES ('t2', NULL, 2), ('t3', NULL, 1);
+         INSERT INTO recipients (id, phone_e164, username) VALUES ('r2', '+15550002222', 'bob.42'), ('r3', '+15550003333', NULL);
+         INSERT INTO thread_members (thread_id, recipient_id) VALUES ('t2', 'r2'), ('t3', 'r2'), ('t3', 'r3');",
+    )
+    .unwrap();
+    let names: Vec<(String, Option<String>)> = list_threads(&conn, 10, 0)
+        .expect("list threads")
+        .into_iter()
+        .map(|thread| (thread.id, thread.name))
+        .collect();
+    assert_eq!(
+        names,
+        vec![
+            ("t1".to_string(), Some("Thread 1".to_string())),
+            ("t2".to_string(), Some("bob.42".to_string())),
+            ("t3".to_string(), None),
+        ]
+    );
+}
+
 #[test]
 fn list_messages_paginates_desc() {
     let conn = setup_db();
@@ -62,6 +91,25 @@ fn list_messages_paginates_desc() {
     assert_eq!(next[0].id, "m1");
 }
 
+#[test]
+fn list_messages_exposes_receipt_times_from_metadata() {
+    let conn = setup_db();
+    seed_messages(&conn);
+    conn.execute_batch(
+        "UPDATE messages SET is_outgoing = 1, metadata_json = '{\"delivered_at\":10,\"read_at\":20}' WHERE id = 'm1';
+         UPDATE messages SET metadata_json = '{\"expires_in_ms\":1000}' WHERE id = 'm2';",
+    )
+    .unwrap();
+    let messages = list_messages(&conn, "t1", None, None, 10).expect("list");
+    let receipt = |id: &str| {
+        let msg = messages.iter().find(|m| m.id == id).expect("message");
+        (msg.delivered_at, msg.read_at)
+    };
+    assert_eq!(receipt("m1"), (Some(10), Some(20)));
+    assert_eq!(receipt("m2"), (None, None));
+    assert_eq!(receipt("m3"), (None, None));
+}
+
 #[test]
 fn list_messages_after_asc() {
     let conn = setup_db();
@@ -85,15 +133,358 @@ fn list_messages_around_includes_center() {
 fn search_messages_filters_thread() {
     let conn = setup_db();
     seed_messages(&conn);
-    // build FTS
-    conn.execute("INSERT INTO message_fts (message_id, thread_id, sender_id, body) VALUES (?1, ?2, ?3, ?4);",
-        rusqlite::params!["m1", "t1", "r1", "hello world"]).unwrap();
-    conn.execute("INSERT INTO message_fts (message_id, thread_id, sender_id, body) VALUES (?1, ?2, ?3, ?4);",
-        rusqlite::params!["m2", "t1", "r1", "another note"]).unwrap();
-    conn.execute("INSERT INTO message_fts (message_id, thread_id, sender_id, body) VALUES (?1, ?2, ?3, ?4);",
-        rusqlite::params!["m3", "t1", "r1", "search me"]).unwrap();
 
-    let hits = search_messages(&conn, "search", Some("t1"), 10, 0).expect("search");
+    let options = SearchOptions {
+        thread_id: Some("t1".to_string()),
+        ..SearchOptions::default()
+    };
+    let hits = search_messages(&conn, "search", &options, 10, 0).expect("search");
     assert_eq!(hits.len(), 1);
     assert_eq!(hits[0].message.id, "m3");
 }
+
+#[test]
+fn thread_media_summary_matches_listing_filters() {
+    let conn = setup_db();
+    seed_messages(&conn);
+    for (id, message_id, kind, size, bucket) in [
+        ("a1", "m1", "image", Some(1_000_i64), Some(0_i64)),
+        ("a2", "m2", "image", Some(2_000_000), Some(1)),
+        ("a3", "m3", "video", None, None),
+    ] {
+        conn.execute(
+            "INSERT INTO attachments (id, message_id, sha256, kind, size_bytes, size_bucket) VALUES (?1, ?2, ?1, ?3, ?4, ?5);",
+            rusqlite::params![id, message_id, kind, size, bucket],
+        )
+        .unwrap();
+    }
+
+    let all = thread_media_summary(&conn, "t1", None, None, None).expect("summary");
+    assert_eq!(all.total_count, 3);
+    assert_eq!(all.total_bytes, 2_001_000);
+    let kinds: Vec<(Option<&str>, i64)> = all.kinds.iter().map(|k| (k.kind.as_deref(), k.count)).collect();
+    assert_eq!(kinds, vec![(Some("image"), 2), (Some("video"), 1)]);
+
+    let filtered = thread_media_summary(&conn, "t1", Some(2), None, Some(1)).expect("filtered");
+    let filters = ThreadMediaFilters {
+        from_ts: Some(2),
+        size_bucket: Some(1),
+        ..ThreadMediaFilters::default()
+    };
+    let listed = list_thread_media(&conn, "t1", &filters, "date_desc", 50, 0).expect("list");
+    assert_eq!(filtered.total_count, listed.len() as i64);
+    assert_eq!(filtered.total_bytes, 2_000_000);
+
+    let empty = thread_media_summary(&conn, "t1", Some(100), None, None).expect("empty");
+    assert_eq!((empty.total_count, empty.total_bytes), (0, 0));
+    assert!(empty.kinds.is_empty());
+}
+
+#[test]
+fn thread_media_month_buckets_group_by_utc_month() {
+    let conn = setup_db();
+    seed_messages(&conn);
+    // m1: 2021-08-31 23:59 UTC, m2: 2021-09-01 00:00 UTC, m3: 2021-09-15 UTC
+    for (id, ts) in [("m1", 1_630_454_399_000_i64), ("m2", 1_630_454_400_000), ("m3", 1_631_664_000_000)] {
+        conn.execute("UPDATE messages SET sent_at = ?2 WHERE id = ?1;", rusqlite::params![id, ts]).unwrap();
+    }
+    for (id, message_id, kind) in [("a1", "m1", "image"), ("a2", "m2", "image"), ("a3", "m3", "video"), ("a4", "m3", "image")] {
+        conn.execute(
+            "INSERT INTO attachments (id, message_id, sha256, kind) VALUES (?1, ?2, ?1, ?3);",
+            rusqlite::params![id, message_id, kind],
+        )
+        .unwrap();
+    }
+
+    let buckets = thread_media_month_buckets(&conn, "t1", None).expect("buckets");
+    let got: Vec<(i64, i64, i64)> = buckets.iter().map(|b| (b.year, b.month, b.item_count)).collect();
+    assert_eq!(got, vec![(2021, 9, 3), (2021, 8, 1)]);
+
+    let videos = thread_media_month_buckets(&conn, "t1", Some("video")).expect("videos");
+    assert_eq!(videos.len(), 1);
+    assert_eq!((videos[0].month, videos[0].item_count), (9, 1));
+    assert!(thread_media_month_buckets(&conn, "missing", None).expect("empty").is_empty());
+}
+
+#[test]
+fn list_thread_media_combines_kind_size_and_date_filters() {
+    let conn = setup_db();
+    seed_messages(&conn);
+    for (id, message_id, kind, bucket) in [
+        ("a1", "m1", "image", 0_i64),
+        ("a2", "m2", "image", 0),
+        ("a3", "m2", "video", 0),
+        ("a4", "m3", "image", 1),
+        ("a5", "m3", "image", 0),
+    ] {
+        conn.execute(
+            "INSERT INTO attachments (id, message_id, sha256, kind, size_bucket) VALUES (?1, ?2, ?1, ?3, ?4);",
+            rusqlite::params![id, message_id, kind, bucket],
+        )
+        .unwrap();
+    }
+    let ids = |from_ts, size_bucket, kind: Option<&str>, sort| -> Vec<String> {
+        let filters = ThreadMediaFilters {
+            from_ts,
+            to_ts: None,
+            size_bucket,
+            kind: kind.map(str::to_string),
+        };
+        list_thread_media(&conn, "t1", &filters, sort, 50, 0)
+            .expect("list")
+            .into_iter()
+            .map(|row| row.id)
+            .collect()
+    };
+
+    assert_eq!(ids(None, None, Some("video"), "date_desc"), vec!["a3"]);
+    assert_eq!(ids(Some(2), Some(0), Some("image"), "date_desc"), vec!["a5", "a2"]);
+    assert_eq!(ids(Some(2), Some(0), Some("image"), "date_asc"), vec!["a2", "a5"]);
+    assert!(ids(None, Some(1), Some("audio"), "date_desc").is_empty());
+
+    let gif = ThreadMediaFilters {
+        kind: Some("gif".to_string()),
+        ..ThreadMediaFilters::default()
+    };
+    let err = list_thread_media(&conn, "t1", &gif, "date_desc", 50, 0);
+    assert!(err.is_err());
+    assert!(thread_media_month_buckets(&conn, "t1", Some("gif")).is_err());
+}
+
+#[test]
+fn largest_attachments_skips_unknown_sizes() {
+    let conn = setup_db();
+    seed_messages(&conn);
+    for (id, message_id, kind, size) in [
+        ("a1", "m1", "image", Some(500_i64)),
+        ("a2", "m2", "video", Some(9_000)),
+        ("a3", "m3", "file", None),
+        ("a4", "m3", "image", Some(2_000)),
+    ] {
+        conn.execute(
+            "INSERT INTO attachments (id, message_id, sha256, kind, size_bytes) VALUES (?1, ?2, ?1, ?3, ?4);",
+            rusqlite::params![id, message_id, kind, size],
+        )
+        .unwrap();
+    }
+
+    let largest = largest_attachments(&conn, 10, None).expect("largest");
+    let ids: Vec<&str> = largest.iter().map(|a| a.media.id.as_str()).collect();
+    assert_eq!(ids, vec!["a2", "a4", "a1"]);
+    assert_eq!(largest[0].thread_name.as_deref(), Some("Thread 1"));
+
+    let images = largest_attachments(&conn, 1, Some("image")).expect("images");
+    assert_eq!(images.len(), 1);
+    assert_eq!(images[0].media.id, "a4");
+    assert!(largest_attachments(&conn, 10, Some("gif")).is_err());
+
+    assert_eq!(attachment_total_bytes(&conn).expect("total"), 11_500);
+}
+
+#[test]
+fn list_media_by_sender_spans_threads_and_supports_outgoing() {
+    let conn = setup_db();
+    seed_messages(&conn);
+    conn.execute(
+        "INSERT INTO threads (id, name, last_message_at) VALUES ('t2', 'Thread 2', 4);",
+        [],
+    )
+    .unwrap();
+    conn.execute(
+        "INSERT INTO messages (id, thread_id, sender_id, sent_at, received_at, type, body, is_outgoing, is_view_once, dedupe_key) \
+         VALUES ('m4', 't2', 'r1', 4, 4, 'text', 'from t2', 0, 0, 'd4'), \
+                ('m5', 't2', NULL, 5, 5, 'text', 'mine', 1, 0, 'd5');",
+        [],
+    )
+    .unwrap();
+    for (id, message_id, kind) in [("a1", "m1", "image"), ("a2", "m4", "image"), ("a3", "m4", "video"), ("a4", "m5", "image")] {
+        conn.execute(
+            "INSERT INTO attachments (id, message_id, sha256, kind) VALUES (?1, ?2, ?1, ?3);",
+            rusqlite::params![id, message_id, kind],
+        )
+        .unwrap();
+    }
+
+    let ids = |sender: &str, kind: Option<&str>| -> Vec<String> {
+        list_media_by_sender(&conn, sender, kind, 50, 0)
+            .expect("list")
+            .into_iter()
+            .map(|row| row.id)
+            .collect()
+    };
+    assert_eq!(ids("r1", None), vec!["a2", "a3", "a1"]);
+    assert_eq!(ids("r1", Some("image")), vec!["a2", "a1"]);
+    assert_eq!(ids(OUTGOING_SENDER, None), vec!["a4"]);
+    assert!(list_media_by_sender(&conn, "r1", Some("gif"), 50, 0).is_err());
+}
+
+#[test]
+fn list_document_attachments_matches_filenames_literally() {
+    let conn = setup_db();
+    seed_messages(&conn);
+    for (id, message_id, kind, filename) in [
+        ("a1", "m1", "document", "Lease_2021.pdf"),
+        ("a2", "m2", "file", "lease-notes.zip"),
+        ("a3", "m3", "image", "lease.jpg"),
+        ("a4", "m3", "document", "LeaseX2021.pdf"),
+    ] {
+        conn.execute(
+            "INSERT INTO attachments (id, message_id, sha256, kind, original_filename) VALUES (?1, ?2, ?1, ?3, ?4);",
+            rusqlite::params![id, message_id, kind, filename],
+        )
+        .unwrap();
+    }
+
+    let ids = |thread_id: Option<&str>, needle: Option<&str>| -> Vec<String> {
+        list_document_attachments(&conn, thread_id, needle, 50, 0)
+            .expect("list")
+            .into_iter()
+            .map(|row| row.id)
+            .collect()
+    };
+    assert_eq!(ids(None, None), vec!["a4", "a2", "a1"]);
+    assert_eq!(ids(Some("t1"), Some("LEASE")), vec!["a4", "a2", "a1"]);
+    assert_eq!(ids(None, Some("lease_")), vec!["a1"]);
+    assert!(ids(Some("t2"), None).is_empty());
+}
+
+#[test]
+fn get_attachment_returns_message_and_thread_name() {
+    let conn = setup_db();
+    seed_messages(&conn);
+    conn.execute(
+        "INSERT INTO attachments (id, message_id, sha256, mime, original_filename, kind, width, height, blurhash) \
+         VALUES ('a1', 'm2', 'sha1', 'image/jpeg', 'IMG_1.jpg', 'image', 640, 480, 'LKO2?U%2Tw=w]~RBVZRi};RPxuwH');",
+        [],
+    )
+    .unwrap();
+
+    let detail = get_attachment(&conn, "a1").expect("detail");
+    assert_eq!(detail.media.sha256, "sha1");
+    assert_eq!((detail.media.width, detail.media.height), (Some(640), Some(480)));
+    assert_eq!(detail.media.blurhash.as_deref(), Some("LKO2?U%2Tw=w]~RBVZRi};RPxuwH"));
+    assert_eq!(detail.message.id, "m2");
+    assert_eq!(detail.message.body.as_deref(), Some("another note"));
+    assert_eq!(detail.thread_name.as_deref(), Some("Thread 1"));
+
+    let err = get_attachment(&conn, "nope").expect_err("unknown id");
+    assert_eq!(err.to_string(), "invalid argument: attachment not found");
+}
+
+#[test]
+fn attachment_mime_skips_rows_without_one() {
+    let conn = setup_db();
+    seed_messages(&conn);
+    conn.execute(
+        "INSERT INTO attachments (id, message_id, sha256, mime, kind) VALUES \
+         ('a1', 'm1', 'sha1', NULL, 'file'), ('a2', 'm2', 'sha1', 'video/mp4', 'video');",
+        [],
+    )
+    .unwrap();
+
+    assert_eq!(attachment_mime(&conn, "sha1").expect("mime").as_deref(), Some("video/mp4"));
+    assert_eq!(attachment_mime(&conn, "sha2").expect("mime"), None);
+}
+
+#[test]
+fn thread_storage_usage_dedupes_blobs_per_thread_and_globally() {
+    let conn = setup_db();
+    seed_messages(&conn);
+    conn.execute("INSERT INTO threads (id, name, last_message_at) VALUES ('t2', 'Thread 2', 4);", []).unwrap();
+    conn.execute(
+        "INSERT INTO messages (id, thread_id, sender_id, sent_at, received_at, type, body, is_outgoing, is_view_once, dedupe_key) \
+         VALUES ('m4', 't2', 'r1', 4, 4, 'text', 'forwarded', 0, 0, 'd4');",
+        [],
+    )
+    .unwrap();
+    for (id, message_id, sha256, size) in [
+        ("a1", "m1", "shared", Some(100_i64)),
+        ("a2", "m2", "shared", Some(100)),
+        ("a3", "m3", "solo", Some(30)),
+        ("a4", "m3", "unsized", None),
+        ("a5", "m4", "shared", Some(100)),
+    ] {
+        conn.execute(
+            "INSERT INTO attachments (id, message_id, sha256, size_bytes, kind) VALUES (?1, ?2, ?3, ?4, 'image');",
+            rusqlite::params![id, message_id, sha256, size],
+        )
+        .unwrap();
+    }
+
+    let usage = thread_storage_usage(&conn).expect("usage");
+    let threads: Vec<(&str, Option<&str>, i64, i64)> = usage
+        .threads
+        .iter()
+        .map(|t| (t.thread_id.as_str(), t.thread_name.as_deref(), t.blob_count, t.total_bytes))
+        .collect();
+    assert_eq!(threads, vec![("t1", Some("Thread 1"), 3, 130), ("t2", Some("Thread 2"), 1, 100)]);
+    assert_eq!(usage.unique_total_bytes, 130);
+}
+
+#[test]
+fn list_mentions_for_messages_returns_names_in_body_order() {
+    let conn = setup_db();
+    seed_messages(&conn);
+    for (message_id, recipient_id, start) in [("m1", "r1", 6_i64), ("m1", "r9", 0), ("m2", "r1", 3), ("m3", "r1", 0)] {
+        conn.execute(
+            "INSERT INTO mentions (message_id, recipient_id, range_start, range_length) VALUES (?1, ?2, ?3, 1);",
+            rusqlite::params![message_id, recipient_id, start],
+        )
+        .unwrap();
+    }
+
+    let mentions = list_mentions_for_messages(&conn, &["m1".to_string(), "m2".to_string()]).expect("mentions");
+    let got: Vec<(&str, &str, Option<&str>, i64)> = mentions
+        .iter()
+        .map(|m| (m.message_id.as_str(), m.recipient_id.as_str(), m.display_name.as_deref(), m.range_start))
+        .collect();
+    assert_eq!(
+        got,
+        vec![("m1", "r9", None, 0), ("m1", "r1", Some("Alice"), 6), ("m2", "r1", Some("Alice"), 3)]
+    );
+    assert!(list_mentions_for_messages(&conn, &[]).expect("empty").is_empty());
+}
+
+#[test]
+fn list_imports_parses_stats_newest_first() {
+    let conn = setup_db();
+    assert!(get_last_successful_import(&conn).expect("none").is_none());
+    for (id, imported_at, status, stats) in [
+        (
+            "i1",
+            1_i64,
+            "success",
+            Some(r#"{"messages_inserted_total":10,"attachments_found":4,"attachments_missing":2}"#),
+        ),
+        ("i2", 2, "success", Some(r#"{"messages_inserted_total":0}"#)),
+        ("i3", 3, "failed", Some(r#"{"error":"bad passphrase"}"#)),
+        ("i4", 4, "running", None),
+    ] {
+        conn.execute(
+            "INSERT INTO imports (id, imported_at, source_filename, source_hash, status, stats_json) \
+             VALUES (?1, ?2, 'signal.backup', ?1, ?3, ?4);",
+            rusqlite::params![id, imported_at, status, stats],
+        )
+        .unwrap();
+    }
+
+    conn.execute("UPDATE imports SET detected_version = 'signal-android db 215' WHERE id = 'i1';", [])
+        .unwrap();
+
+    let imports = list_imports(&conn).expect("imports");
+    let ids: Vec<&str> = imports.iter().map(|i| i.id.as_str()).collect();
+    assert_eq!(ids, vec!["i4", "i3", "i2", "i1"]);
+    assert_eq!(imports[3].detected_version.as_deref(), Some("signal-android db 215"));
+    assert_eq!(imports[2].detected_version, None);
+    let first = &imports[3];
+    assert_eq!(
+        (first.messages_inserted, first.attachments_found, first.attachments_missing),
+        (Some(10), Some(4), Some(2))
+    );
+    assert_eq!(imports[1].error.as_deref(), Some("bad passphrase"));
+    assert_eq!(imports[2].attachments_missing, None);
+    assert_eq!(imports[0].messages_inserted, None);
+
+    let last = get_last_successful_import(&conn).expect("last").expect("some");
+    assert_eq!(last.id, "i2");
+}
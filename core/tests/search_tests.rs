@@ -0,0 +1,590 @@
+use golden_thread_core::db::apply_migrations;
+use golden_thread_core::importer::rebuild_fts;
+use golden_thread_core::models::{SearchHit, SearchOptions};
+use golden_thread_core::query::{
+    create_tag, global_search, search_messages, search_messages_grouped, search_messages_page, search_people,
+    set_message_tags,
+};
+use golden_thread_core::CoreError;
+use rusqlite::Connection;
+
+fn setup_db() -> Connection {
+    let conn = Connection::open_in_memory().expect("memory db");
+    apply_migrations(&conn).expect("migrate");
+    conn
+}
+
+fn search(conn: &Connection, query: &str) -> Vec<SearchHit> {
+    search_messages(conn, query, &SearchOptions::default(), 10, 0).expect("search")
+}
+
+fn prefix_search() -> SearchOptions {
+    SearchOptions {
+        prefix: true,
+        ..SearchOptions::default()
+    }
+}
+
+fn insert_thread(conn: &Connection, id: &str, name: &str) {
+    conn.execute(
+        "INSERT INTO threads (id, name, last_message_at) VALUES (?1, ?2, 0);",
+        rusqlite::params![id, name],
+    )
+    .unwrap();
+}
+
+fn insert_message(conn: &Connection, id: &str, thread_id: &str, ts: i64, body: &str) {
+    conn.execute(
+        "INSERT INTO messages (id, thread_id, sender_id, sent_at, received_at, type, body, is_outgoing, is_view_once, dedupe_key) \
+         VALUES (?1, ?2, 'r1', ?3, ?3, 'text', ?4, 0, 0, ?1);",
+        rusqlite::params![id, thread_id, ts, body],
+    )
+    .unwrap();
+}
+
+#[test]
+fn search_grouped_buckets_hits_per_thread() {
+    let conn = setup_db();
+    insert_thread(&conn, "t1", "Alice");
+    insert_thread(&conn, "t2", "Bob");
+    for idx in 0..5 {
+        insert_message(&conn, &format!("a{}", idx), "t1", idx, "pizza tonight?");
+    }
+    insert_message(&conn, "b1", "t2", 1, "pizza");
+    insert_message(&conn, "b2", "t2", 2, "unrelated");
+
+    let groups = search_messages_grouped(&conn, "pizza", 3).expect("grouped search");
+    assert_eq!(groups.len(), 2);
+
+    let alice = groups.iter().find(|g| g.thread_id == "t1").expect("t1 group");
+    assert_eq!(alice.total_hits, 5);
+    assert_eq!(alice.top_hits.len(), 3);
+    assert_eq!(alice.thread_name.as_deref(), Some("Alice"));
+
+    let bob = groups.iter().find(|g| g.thread_id == "t2").expect("t2 group");
+    assert_eq!(bob.total_hits, 1);
+    assert_eq!(bob.top_hits.len(), 1);
+    assert_eq!(bob.top_hits[0].message.id, "b1");
+}
+
+#[test]
+fn search_grouped_orders_threads_by_best_rank() {
+    let conn = setup_db();
+    insert_thread(&conn, "t1", "Noisy");
+    insert_thread(&conn, "t2", "Focused");
+    insert_message(
+        &conn,
+        "n1",
+        "t1",
+        1,
+        "we talked about a lot of things today including the garden and maybe the beach",
+    );
+    insert_message(&conn, "f1", "t2", 1, "beach");
+
+    let groups = search_messages_grouped(&conn, "beach", 3).expect("grouped search");
+    assert_eq!(groups.len(), 2);
+    assert_eq!(groups[0].thread_id, "t2");
+    assert!(groups[0].top_hits[0].rank <= groups[1].top_hits[0].rank);
+}
+
+#[test]
+fn search_folds_diacritics() {
+    let conn = setup_db();
+    insert_thread(&conn, "t1", "Alice");
+    insert_message(&conn, "m1", "t1", 1, "Meet at the café near Müller's place");
+    insert_message(&conn, "m2", "t1", 2, "nothing to see here");
+
+    let hits = search(&conn, "cafe");
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].message.id, "m1");
+
+    let hits = search(&conn, "MULLER");
+    assert_eq!(hits.len(), 1);
+
+    let hits = search(&conn, "café");
+    assert_eq!(hits.len(), 1);
+}
+
+#[test]
+fn search_handles_mixed_scripts() {
+    let conn = setup_db();
+    insert_thread(&conn, "t1", "Alice");
+    insert_message(&conn, "m1", "t1", 1, "Привет, как дела? see you tomorrow");
+    insert_message(&conn, "m2", "t1", 2, "Ёлка стоит в углу");
+    insert_message(&conn, "m3", "t1", 3, "tomorrow works");
+
+    let hits = search(&conn, "привет");
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].message.id, "m1");
+
+    let hits = search(&conn, "ПРИВЕТ tomorrow");
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].message.id, "m1");
+
+    let hits = search(&conn, "ёлка");
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].message.id, "m2");
+}
+
+#[test]
+fn search_prefix_matches_partial_words() {
+    let conn = setup_db();
+    insert_thread(&conn, "t1", "Alice");
+    insert_message(&conn, "m1", "t1", 1, "hello world");
+    insert_message(&conn, "m2", "t1", 2, "goodbye world");
+
+    let hits = search_messages(&conn, "hel", &prefix_search(), 10, 0).expect("prefix search");
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].message.id, "m1");
+
+    let hits = search(&conn, "hel");
+    assert!(hits.is_empty());
+
+    let hits = search_messages(&conn, "goodbye wor", &prefix_search(), 10, 0).expect("prefix last token");
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].message.id, "m2");
+}
+
+#[test]
+fn search_prefix_ranks_whole_words_first() {
+    let conn = setup_db();
+    insert_thread(&conn, "t1", "Alice");
+    insert_message(&conn, "m1", "t1", 1, "happy birthday to you");
+    insert_message(&conn, "m2", "t1", 2, "birth certificate arrived today");
+
+    let hits = search_messages(&conn, "birth", &prefix_search(), 10, 0).expect("prefix search");
+    assert_eq!(hits.len(), 2);
+    assert_eq!(hits[0].message.id, "m2");
+    assert!(hits[0].rank < hits[1].rank);
+}
+
+#[test]
+fn search_tolerates_fts_syntax_in_input() {
+    let conn = setup_db();
+    insert_thread(&conn, "t1", "Alice");
+    insert_message(&conn, "m1", "t1", 1, "she said \"ok\" - then AND left");
+
+    let hits = search(&conn, "\"ok");
+    assert_eq!(hits.len(), 1);
+
+    let hits = search_messages(&conn, "AND", &prefix_search(), 10, 0).expect("operator keyword");
+    assert_eq!(hits.len(), 1);
+
+    let hits = search_messages(&conn, "   ", &prefix_search(), 10, 0).expect("blank query");
+    assert!(hits.is_empty());
+}
+
+#[test]
+fn fts_triggers_follow_message_changes() {
+    let conn = setup_db();
+    insert_thread(&conn, "t1", "Alice");
+    insert_message(&conn, "m1", "t1", 1, "original wording");
+    insert_message(&conn, "m2", "t1", 2, "short lived");
+
+    conn.execute("UPDATE messages SET body = 'edited wording' WHERE id = 'm1';", [])
+        .unwrap();
+    assert!(search(&conn, "original").is_empty());
+    assert_eq!(search(&conn, "edited").len(), 1);
+
+    conn.execute("DELETE FROM messages WHERE id = 'm2';", []).unwrap();
+    assert!(search(&conn, "lived").is_empty());
+}
+
+#[test]
+fn rebuild_fts_restores_missing_index() {
+    let conn = setup_db();
+    insert_thread(&conn, "t1", "Alice");
+    insert_message(&conn, "m1", "t1", 1, "recover me please");
+    conn.execute("DELETE FROM message_fts;", []).unwrap();
+    assert!(search(&conn, "recover").is_empty());
+
+    rebuild_fts(&conn, &|_| {}).expect("rebuild");
+    let hits = search(&conn, "recover");
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].message.id, "m1");
+}
+
+#[test]
+fn search_filters_by_tag() {
+    let conn = setup_db();
+    insert_thread(&conn, "t1", "Alice");
+    insert_message(&conn, "m1", "t1", 1, "sunset over the lake");
+    insert_message(&conn, "m2", "t1", 2, "another sunset photo");
+    insert_message(&conn, "m3", "t1", 3, "sunset again");
+    let favorite = create_tag(&conn, "Favorite", "#ff0000").expect("tag");
+    let funny = create_tag(&conn, "Funny", "#00ff00").expect("tag");
+    set_message_tags(&conn, "m1", std::slice::from_ref(&favorite.id)).expect("tag m1");
+    set_message_tags(&conn, "m3", std::slice::from_ref(&funny.id)).expect("tag m3");
+
+    let all = search(&conn, "sunset");
+    assert_eq!(all.len(), 3);
+
+    let by_tag = SearchOptions {
+        tag_id: Some(favorite.id.clone()),
+        ..SearchOptions::default()
+    };
+    let hits = search_messages(&conn, "sunset", &by_tag, 10, 0)
+        .expect("tag search");
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].message.id, "m1");
+
+    let any_tag = SearchOptions {
+        only_tagged: true,
+        ..SearchOptions::default()
+    };
+    let mut ids: Vec<String> = search_messages(&conn, "sunset", &any_tag, 10, 0)
+        .expect("tagged search")
+        .into_iter()
+        .map(|hit| hit.message.id)
+        .collect();
+    ids.sort();
+    assert_eq!(ids, vec!["m1".to_string(), "m3".to_string()]);
+}
+
+fn insert_recipient(conn: &Connection, id: &str, phone: Option<&str>, profile: Option<&str>, contact: Option<&str>) {
+    conn.execute(
+        "INSERT INTO recipients (id, phone_e164, profile_name, contact_name) VALUES (?1, ?2, ?3, ?4);",
+        rusqlite::params![id, phone, profile, contact],
+    )
+    .unwrap();
+}
+
+fn add_member(conn: &Connection, thread_id: &str, recipient_id: &str) {
+    conn.execute(
+        "INSERT INTO thread_members (thread_id, recipient_id) VALUES (?1, ?2);",
+        rusqlite::params![thread_id, recipient_id],
+    )
+    .unwrap();
+}
+
+#[test]
+fn search_people_matches_names_and_phone_numbers() {
+    let conn = setup_db();
+    insert_thread(&conn, "t1", "Alice");
+    insert_thread(&conn, "t2", "Book club");
+    insert_recipient(&conn, "r-alice", Some("+15550001111"), Some("Ally"), Some("Alice Smith"));
+    insert_recipient(&conn, "r-bob", Some("+15550002222"), Some("Bobby"), None);
+    add_member(&conn, "t1", "r-alice");
+    add_member(&conn, "t2", "r-alice");
+    add_member(&conn, "t2", "r-bob");
+
+    let people = search_people(&conn, "alice", 10).expect("by contact name");
+    assert_eq!(people.len(), 1);
+    assert_eq!(people[0].recipient.id, "r-alice");
+    assert_eq!(people[0].threads.len(), 2);
+
+    let people = search_people(&conn, "BOBBY", 10).expect("by profile name");
+    assert_eq!(people.len(), 1);
+    assert_eq!(people[0].threads[0].id, "t2");
+
+    let people = search_people(&conn, "(555) 000-2222", 10).expect("by phone");
+    assert_eq!(people.len(), 1);
+    assert_eq!(people[0].recipient.id, "r-bob");
+
+    assert!(search_people(&conn, "%", 10).expect("wildcard").is_empty());
+}
+
+#[test]
+fn global_search_returns_people_threads_and_messages() {
+    let conn = setup_db();
+    insert_thread(&conn, "t1", "Alice");
+    insert_thread(&conn, "t2", "Book club");
+    insert_recipient(&conn, "r-alice", None, None, Some("Alice Smith"));
+    add_member(&conn, "t1", "r-alice");
+    insert_message(&conn, "m1", "t2", 1, "alice is bringing the book");
+
+    let result = global_search(&conn, "alic", 10).expect("global search");
+    assert_eq!(result.people.len(), 1);
+    assert_eq!(result.threads.len(), 1);
+    assert_eq!(result.threads[0].id, "t1");
+    assert_eq!(result.message_hits.len(), 1);
+    assert_eq!(result.message_hits[0].message.id, "m1");
+}
+
+#[test]
+fn search_page_cursor_matches_offset_pages() {
+    let conn = setup_db();
+    insert_thread(&conn, "t1", "Alice");
+    for idx in 0..200 {
+        // Vary the body length so ranks differ, with plenty of ties in between.
+        let filler = "word ".repeat((idx % 7) as usize);
+        insert_message(&conn, &format!("m{:03}", idx), "t1", idx, &format!("lunch {}", filler));
+    }
+
+    let page_size = 25;
+    let mut cursor = None;
+    for _ in 0..2 {
+        let page = search_messages_page(&conn, "lunch", &SearchOptions::default(), cursor.as_ref(), page_size)
+            .expect("page");
+        assert_eq!(page.hits.len(), page_size as usize);
+        cursor = page.next_cursor;
+    }
+    let page3 = search_messages_page(&conn, "lunch", &SearchOptions::default(), cursor.as_ref(), page_size)
+        .expect("page 3");
+    let offset3 = search_messages(&conn, "lunch", &SearchOptions::default(), page_size, 2 * page_size)
+        .expect("offset page 3");
+
+    let cursor_ids: Vec<&str> = page3.hits.iter().map(|hit| hit.message.id.as_str()).collect();
+    let offset_ids: Vec<&str> = offset3.iter().map(|hit| hit.message.id.as_str()).collect();
+    assert_eq!(cursor_ids, offset_ids);
+
+    let mut seen = 0;
+    let mut cursor = None;
+    loop {
+        let page = search_messages_page(&conn, "lunch", &SearchOptions::default(), cursor.as_ref(), 60).expect("page");
+        seen += page.hits.len();
+        match page.next_cursor {
+            Some(next) => cursor = Some(next),
+            None => break,
+        }
+    }
+    assert_eq!(seen, 200);
+}
+
+#[test]
+fn search_finds_terms_in_quote_bodies() {
+    let conn = setup_db();
+    insert_thread(&conn, "t1", "Alice");
+    conn.execute(
+        "INSERT INTO messages (id, thread_id, sender_id, sent_at, received_at, type, body, is_outgoing, is_view_once, metadata_json, dedupe_key) \
+         VALUES ('q1', 't1', 'r1', 1, 1, 'text', 'haha yes', 0, 0, '{\"quote_body\":\"the marmalade incident\"}', 'q1');",
+        [],
+    )
+    .unwrap();
+    insert_message(&conn, "b1", "t1", 2, "marmalade on toast again");
+    insert_message(&conn, "b2", "t1", 3, "nothing relevant");
+    conn.execute(
+        "INSERT INTO messages (id, thread_id, sender_id, sent_at, received_at, type, body, is_outgoing, is_view_once, metadata_json, dedupe_key) \
+         VALUES ('bad', 't1', 'r1', 4, 4, 'text', 'broken metadata', 0, 0, '{not json', 'bad');",
+        [],
+    )
+    .expect("malformed metadata still inserts");
+
+    let hits = search(&conn, "marmalade");
+    assert_eq!(hits.len(), 2);
+    assert_eq!(hits[0].message.id, "b1");
+    assert!(!hits[0].matched_in_quote);
+    assert_eq!(hits[1].message.id, "q1");
+    assert!(hits[1].matched_in_quote);
+
+    let hits = search(&conn, "haha");
+    assert_eq!(hits.len(), 1);
+    assert!(!hits[0].matched_in_quote);
+}
+
+#[test]
+fn search_recency_boost_prefers_newer_equal_matches() {
+    let conn = setup_db();
+    insert_thread(&conn, "t1", "Alice");
+    let day_ms = 86_400_000;
+    // Insert the newer message first so id order alone would not explain the result.
+    insert_message(&conn, "m2", "t1", 400 * day_ms, "dinner reservation confirmed");
+    insert_message(&conn, "m1", "t1", day_ms, "dinner reservation confirmed");
+
+    let plain = search(&conn, "dinner");
+    assert_eq!(plain[0].rank, plain[1].rank);
+    assert_eq!(plain[0].message.id, "m1");
+
+    let boosted = SearchOptions {
+        recency_half_life_days: Some(30.0),
+        ..SearchOptions::default()
+    };
+    let hits = search_messages(&conn, "dinner", &boosted, 10, 0).expect("boosted search");
+    assert_eq!(hits[0].message.id, "m2");
+    assert!(hits[0].rank < hits[1].rank);
+}
+
+#[test]
+fn search_recency_boost_follows_the_documented_curve() {
+    let conn = setup_db();
+    insert_thread(&conn, "t1", "Alice");
+    let day_ms = 86_400_000;
+    insert_message(&conn, "m0", "t1", 100 * day_ms, "dinner reservation confirmed");
+    insert_message(&conn, "m1", "t1", 90 * day_ms, "dinner reservation confirmed");
+    insert_message(&conn, "m2", "t1", 80 * day_ms, "dinner reservation confirmed");
+
+    let plain = search(&conn, "dinner");
+    let boosted = SearchOptions {
+        recency_half_life_days: Some(10.0),
+        ..SearchOptions::default()
+    };
+    let hits = search_messages(&conn, "dinner", &boosted, 10, 0).expect("boosted search");
+    let boost = |id: &str| {
+        let rank = |hits: &[SearchHit]| hits.iter().find(|hit| hit.message.id == id).expect("hit").rank;
+        rank(&hits) / rank(&plain)
+    };
+    // Age 0, h and 2h days: 1 + h / (h + age).
+    assert!((boost("m0") - 2.0).abs() < 1e-9, "{}", boost("m0"));
+    assert!((boost("m1") - 1.5).abs() < 1e-9, "{}", boost("m1"));
+    assert!((boost("m2") - 4.0 / 3.0).abs() < 1e-9, "{}", boost("m2"));
+}
+
+#[test]
+fn search_rejects_invalid_rank_weights() {
+    let conn = setup_db();
+    insert_thread(&conn, "t1", "Alice");
+    insert_message(&conn, "m1", "t1", 1, "dinner reservation confirmed");
+
+    let invalid = [
+        SearchOptions { body_weight: 0.0, ..SearchOptions::default() },
+        SearchOptions { quote_weight: -1.0, ..SearchOptions::default() },
+        SearchOptions { body_weight: f64::NAN, ..SearchOptions::default() },
+        SearchOptions { recency_half_life_days: Some(f64::INFINITY), ..SearchOptions::default() },
+        SearchOptions { recency_half_life_days: Some(0.0), ..SearchOptions::default() },
+    ];
+    for options in &invalid {
+        assert!(matches!(search_messages(&conn, "dinner", options, 10, 0), Err(CoreError::InvalidArgument(_))));
+        assert!(matches!(
+            search_messages_page(&conn, "dinner", options, None, 10),
+            Err(CoreError::InvalidArgument(_))
+        ));
+    }
+}
+
+fn exact_search() -> SearchOptions {
+    SearchOptions {
+        exact: true,
+        ..SearchOptions::default()
+    }
+}
+
+#[test]
+fn exact_search_finds_punctuation_and_word_fragments() {
+    let conn = setup_db();
+    insert_thread(&conn, "t1", "Alice");
+    insert_message(&conn, "m1", "t1", 1, "see you soon :)");
+    insert_message(&conn, "m2", "t1", 2, "biology exam tomorrow");
+    insert_message(&conn, "m3", "t1", 3, "geology field trip :)");
+
+    assert!(search(&conn, ":)").is_empty());
+
+    let hits = search_messages(&conn, ":)", &exact_search(), 10, 0).expect("smiley");
+    let ids: Vec<&str> = hits.iter().map(|hit| hit.message.id.as_str()).collect();
+    assert_eq!(ids, vec!["m3", "m1"]);
+
+    let hits = search_messages(&conn, "ology", &exact_search(), 10, 0).expect("fragment");
+    assert_eq!(hits.len(), 2);
+
+    let hits = search_messages(&conn, "ology", &exact_search(), 1, 0).expect("capped");
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].message.id, "m3");
+}
+
+#[test]
+fn exact_search_pages_by_sort_time() {
+    let conn = setup_db();
+    insert_thread(&conn, "t1", "Alice");
+    insert_message(&conn, "m1", "t1", 1, "ok :)");
+    insert_message(&conn, "m2", "t1", 2, "sure :)");
+    insert_message(&conn, "m3", "t1", 2, "fine :)");
+    insert_message(&conn, "m4", "t1", 3, "no smiley");
+    insert_message(&conn, "m5", "t1", 4, "yes :)");
+
+    let mut ids = Vec::new();
+    let mut cursor = None;
+    loop {
+        let page = search_messages_page(&conn, ":)", &exact_search(), cursor.as_ref(), 2).expect("page");
+        ids.extend(page.hits.iter().map(|hit| hit.message.id.clone()));
+        cursor = page.next_cursor;
+        if cursor.is_none() {
+            break;
+        }
+    }
+    let offset_ids: Vec<String> = search_messages(&conn, ":)", &exact_search(), 10, 0)
+        .expect("offset")
+        .into_iter()
+        .map(|hit| hit.message.id)
+        .collect();
+    assert_eq!(ids, vec!["m5", "m3", "m2", "m1"]);
+    assert_eq!(ids, offset_ids);
+}
+
+#[test]
+fn exact_search_escapes_like_wildcards() {
+    let conn = setup_db();
+    insert_thread(&conn, "t1", "Alice");
+    insert_message(&conn, "m1", "t1", 1, "100% sure");
+    insert_message(&conn, "m2", "t1", 2, "100 percent sure");
+    insert_message(&conn, "m3", "t1", 3, "file_name.txt");
+    insert_message(&conn, "m4", "t1", 4, "filename.txt");
+    insert_message(&conn, "m5", "t1", 5, "C:\\temp");
+
+    let hits = search_messages(&conn, "0%", &exact_search(), 10, 0).expect("percent");
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].message.id, "m1");
+
+    let hits = search_messages(&conn, "e_n", &exact_search(), 10, 0).expect("underscore");
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].message.id, "m3");
+
+    let hits = search_messages(&conn, ":\\t", &exact_search(), 10, 0).expect("backslash");
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].message.id, "m5");
+}
+
+#[test]
+fn search_respects_date_range() {
+    let conn = setup_db();
+    insert_thread(&conn, "t1", "Alice");
+    insert_thread(&conn, "t2", "Bob");
+    insert_message(&conn, "m1", "t1", 100, "picnic :)");
+    insert_message(&conn, "m2", "t1", 200, "picnic :)");
+    insert_message(&conn, "m3", "t2", 200, "picnic :)");
+    insert_message(&conn, "m4", "t1", 300, "picnic :)");
+
+    let options = SearchOptions {
+        thread_id: Some("t1".to_string()),
+        from_ts: Some(150),
+        to_ts: Some(250),
+        ..SearchOptions::default()
+    };
+    let hits = search_messages(&conn, "picnic", &options, 10, 0).expect("fts range");
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].message.id, "m2");
+
+    let exact = SearchOptions { exact: true, ..options };
+    let hits = search_messages(&conn, ":)", &exact, 10, 0).expect("exact range");
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].message.id, "m2");
+}
+
+#[test]
+fn thread_filter_in_match_matches_post_filtering() {
+    let conn = setup_db();
+    for thread in 0..6 {
+        insert_thread(&conn, &format!("t{}", thread), "Thread");
+    }
+    // One giant thread plus several small ones, all sharing the search term.
+    for idx in 0..600 {
+        let thread = if idx % 10 == 0 { format!("t{}", 1 + (idx / 10) % 5) } else { "t0".to_string() };
+        let filler = "and so on ".repeat((idx % 5) as usize);
+        insert_message(&conn, &format!("m{:04}", idx), &thread, idx, &format!("ferry schedule {}", filler));
+    }
+
+    for thread in ["t0", "t3"] {
+        let options = SearchOptions {
+            thread_id: Some(thread.to_string()),
+            ..SearchOptions::default()
+        };
+        let filtered = search_messages(&conn, "ferry schedule", &options, 1000, 0).expect("thread search");
+
+        let global = search_messages(&conn, "ferry schedule", &SearchOptions::default(), 1000, 0).expect("global");
+        let post_filtered: Vec<_> = global.into_iter().filter(|hit| hit.message.thread_id == thread).collect();
+
+        let filtered_ids: Vec<(&str, f64)> =
+            filtered.iter().map(|hit| (hit.message.id.as_str(), hit.rank)).collect();
+        let expected_ids: Vec<(&str, f64)> =
+            post_filtered.iter().map(|hit| (hit.message.id.as_str(), hit.rank)).collect();
+        assert!(!filtered_ids.is_empty());
+        assert_eq!(filtered_ids, expected_ids);
+    }
+}
+
+#[test]
+fn search_terms_do_not_match_thread_ids() {
+    let conn = setup_db();
+    insert_thread(&conn, "t1", "Alice");
+    insert_message(&conn, "m1", "t1", 1, "hello there");
+
+    assert!(search(&conn, "t1").is_empty());
+}
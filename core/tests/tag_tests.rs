@@ -1,7 +1,12 @@
 use golden_thread_core::db::apply_migrations;
+use golden_thread_core::error::CoreError;
+use golden_thread_core::models::ScrapbookOrder;
 use golden_thread_core::query::{
-    create_tag, delete_tag, get_message_tags, list_scrapbook_messages, list_tags,
-    set_message_tags, update_tag,
+    add_message_tag, add_tag_to_messages, create_tag, delete_tag, delete_tag_checked, export_tags_json,
+    get_attachment_tags_bulk, get_message_tags, get_message_tags_bulk, get_or_create_tag, import_tags_json,
+    list_scrapbook_messages, list_tagged_media, list_tags, merge_tags, next_in_thread_query_plan_for_tests,
+    remove_message_tag, remove_tag_from_messages, scrapbook_context, set_attachment_tags, set_message_tags,
+    suggest_tag_color, tag_stats, update_tag, TAG_PALETTE,
 };
 use rusqlite::Connection;
 
@@ -50,6 +55,16 @@ fn seed_test_data(conn: &Connection) {
     .unwrap();
 }
 
+fn seed_attachments(conn: &Connection) {
+    for (id, message_id) in [("a1", "m1"), ("a2", "m3")] {
+        conn.execute(
+            "INSERT INTO attachments (id, message_id, sha256, mime, kind) VALUES (?1, ?2, ?1, 'image/jpeg', 'image');",
+            rusqlite::params![id, message_id],
+        )
+        .unwrap();
+    }
+}
+
 #[test]
 fn create_tag_generates_id() {
     let conn = setup_db();
@@ -66,7 +81,6 @@ fn create_tag_generates_id() {
 fn create_tag_increments_display_order() {
     let conn = setup_db();
     let tag1 = create_tag(&conn, "First", "#ff0000").expect("create tag1");
-    std::thread::sleep(std::time::Duration::from_millis(2)); // Ensure unique timestamp
     let tag2 = create_tag(&conn, "Second", "#00ff00").expect("create tag2");
 
     assert_eq!(tag1.display_order, 0);
@@ -79,16 +93,73 @@ fn create_tag_enforces_unique_name() {
     create_tag(&conn, "Duplicate", "#ff0000").expect("create first tag");
     let result = create_tag(&conn, "Duplicate", "#00ff00");
 
-    assert!(result.is_err(), "Should fail on duplicate tag name");
+    assert!(
+        matches!(result, Err(CoreError::InvalidArgument(ref msg)) if msg.contains("already exists")),
+        "Should fail on duplicate tag name"
+    );
+
+    let other = create_tag(&conn, "Other", "#00ff00").expect("create other");
+    assert!(matches!(
+        update_tag(&conn, &other.id, "Duplicate", "#00ff00"),
+        Err(CoreError::InvalidArgument(_))
+    ));
+    update_tag(&conn, &other.id, "Other", "#0000ff").expect("keeping its own name is fine");
+
+    // Names are compared the way quick-tag looks them up.
+    assert!(create_tag(&conn, "duplicate ", "#00ff00").is_err());
+    assert!(update_tag(&conn, &other.id, "DUPLICATE", "#00ff00").is_err());
+    update_tag(&conn, &other.id, "other", "#0000ff").expect("recasing its own name is fine");
+}
+
+#[test]
+fn tag_names_are_trimmed_and_must_not_be_blank() {
+    let conn = setup_db();
+    let tag = create_tag(&conn, "  Trip  ", "#ff0000").expect("create");
+    assert_eq!(tag.name, "Trip");
+    assert!(matches!(create_tag(&conn, "   ", "#ff0000"), Err(CoreError::InvalidArgument(_))));
+
+    update_tag(&conn, &tag.id, " Holiday\t", "#ff0000").expect("rename");
+    let name: String = conn
+        .query_row("SELECT name FROM tags WHERE id = ?1;", [&tag.id], |row| row.get(0))
+        .expect("name");
+    assert_eq!(name, "Holiday");
+    assert!(matches!(update_tag(&conn, &tag.id, "", "#ff0000"), Err(CoreError::InvalidArgument(_))));
+}
+
+#[test]
+fn create_tag_ids_do_not_collide_within_a_millisecond() {
+    let conn = setup_db();
+    let ids: std::collections::HashSet<String> = (0..20)
+        .map(|idx| create_tag(&conn, &format!("Tag {}", idx), "#ff0000").expect("create").id)
+        .collect();
+    assert_eq!(ids.len(), 20);
+    assert!(ids.iter().all(|id| id.starts_with("tag:")));
+}
+
+#[test]
+fn legacy_timestamp_tag_ids_keep_working() {
+    let conn = setup_db();
+    seed_test_data(&conn);
+    conn.execute(
+        "INSERT INTO tags (id, name, color, created_at, display_order) VALUES ('tag:1700000000000', 'Old', '#ff0000', 1700000000000, 0);",
+        [],
+    )
+    .unwrap();
+    let new = create_tag(&conn, "New", "#00ff00").expect("create");
+    assert_eq!(new.display_order, 1);
+
+    set_message_tags(&conn, "m1", &["tag:1700000000000".to_string(), new.id.clone()]).expect("set tags");
+    let tags = get_message_tags(&conn, "m1").expect("get tags");
+    let ids: Vec<&str> = tags.iter().map(|t| t.id.as_str()).collect();
+    assert_eq!(ids, vec!["tag:1700000000000", new.id.as_str()]);
+    update_tag(&conn, "tag:1700000000000", "Older", "#ff0000").expect("update legacy");
 }
 
 #[test]
 fn list_tags_returns_ordered_by_display_order() {
     let conn = setup_db();
     create_tag(&conn, "Third", "#0000ff").expect("tag 3");
-    std::thread::sleep(std::time::Duration::from_millis(2));
     create_tag(&conn, "First", "#ff0000").expect("tag 1");
-    std::thread::sleep(std::time::Duration::from_millis(2));
     create_tag(&conn, "Second", "#00ff00").expect("tag 2");
 
     let tags = list_tags(&conn).expect("list tags");
@@ -110,6 +181,42 @@ fn update_tag_changes_name_and_color() {
     assert_eq!(tags[0].color, "#00ff00");
 }
 
+#[test]
+fn tag_colors_are_validated_and_lowercased() {
+    let conn = setup_db();
+    let tag = create_tag(&conn, "Loud", "#FF00Aa").expect("create");
+    assert_eq!(tag.color, "#ff00aa");
+    assert_eq!(list_tags(&conn).expect("list")[0].color, "#ff00aa");
+
+    for bad in ["red", "#fff", "ff0000", "#gg0000", "#ff00001", ""] {
+        let err = create_tag(&conn, "Bad", bad).expect_err(bad);
+        assert!(matches!(err, CoreError::InvalidArgument(ref msg) if msg.contains("#rrggbb")), "{bad}: {err}");
+    }
+    assert!(matches!(
+        update_tag(&conn, &tag.id, "Loud", "rgb(0,0,0)"),
+        Err(CoreError::InvalidArgument(_))
+    ));
+
+    update_tag(&conn, &tag.id, "Loud", "#ABCDEF").expect("update");
+    assert_eq!(list_tags(&conn).expect("list")[0].color, "#abcdef");
+}
+
+#[test]
+fn suggest_tag_color_prefers_least_used_palette_color() {
+    let conn = setup_db();
+    assert_eq!(suggest_tag_color(&conn).expect("suggest"), TAG_PALETTE[0]);
+
+    create_tag(&conn, "One", TAG_PALETTE[0]).expect("one");
+    create_tag(&conn, "Two", &TAG_PALETTE[1].to_uppercase()).expect("two");
+    assert_eq!(suggest_tag_color(&conn).expect("suggest"), TAG_PALETTE[2]);
+
+    for (idx, color) in TAG_PALETTE.iter().enumerate().skip(2) {
+        create_tag(&conn, &format!("Tag {}", idx), color).expect("fill palette");
+    }
+    create_tag(&conn, "Again", TAG_PALETTE[0]).expect("again");
+    assert_eq!(suggest_tag_color(&conn).expect("suggest"), TAG_PALETTE[1]);
+}
+
 #[test]
 fn delete_tag_removes_tag() {
     let conn = setup_db();
@@ -134,7 +241,7 @@ fn delete_tag_cascades_to_message_tags() {
     assert_eq!(tags_before.len(), 1);
 
     // Delete tag
-    delete_tag(&conn, &tag.id).expect("delete");
+    assert_eq!(delete_tag(&conn, &tag.id).expect("delete"), 1);
 
     // Verify message_tags were cascaded
     let tags_after = get_message_tags(&conn, "m1").expect("get tags");
@@ -147,7 +254,6 @@ fn set_message_tags_replaces_existing() {
     seed_test_data(&conn);
 
     let tag1 = create_tag(&conn, "Tag 1", "#ff0000").expect("tag 1");
-    std::thread::sleep(std::time::Duration::from_millis(2));
     let tag2 = create_tag(&conn, "Tag 2", "#00ff00").expect("tag 2");
 
     // Set initial tags
@@ -162,15 +268,33 @@ fn set_message_tags_replaces_existing() {
     assert_eq!(tags[0].id, tag2.id);
 }
 
+#[test]
+fn set_message_tags_rejects_unknown_tag_without_touching_existing() {
+    let conn = setup_db();
+    seed_test_data(&conn);
+    let tag1 = create_tag(&conn, "Tag 1", "#ff0000").expect("tag 1");
+    let tag2 = create_tag(&conn, "Tag 2", "#00ff00").expect("tag 2");
+    set_message_tags(&conn, "m1", std::slice::from_ref(&tag1.id)).expect("initial tags");
+
+    let err = set_message_tags(&conn, "m1", &[tag2.id.clone(), "tag:bogus".to_string()]).expect_err("bogus id");
+    assert!(matches!(err, CoreError::InvalidArgument(ref msg) if msg.contains("tag:bogus")), "{err}");
+
+    let tags = get_message_tags(&conn, "m1").expect("get tags");
+    let ids: Vec<&str> = tags.iter().map(|t| t.id.as_str()).collect();
+    assert_eq!(ids, vec![tag1.id.as_str()]);
+
+    // Repeated ids collapse instead of tripping the primary key.
+    set_message_tags(&conn, "m1", &[tag2.id.clone(), tag2.id.clone()]).expect("duplicate ids");
+    assert_eq!(get_message_tags(&conn, "m1").expect("get tags").len(), 1);
+}
+
 #[test]
 fn set_message_tags_supports_multiple_tags() {
     let conn = setup_db();
     seed_test_data(&conn);
 
     let tag1 = create_tag(&conn, "Tag 1", "#ff0000").expect("tag 1");
-    std::thread::sleep(std::time::Duration::from_millis(2));
     let tag2 = create_tag(&conn, "Tag 2", "#00ff00").expect("tag 2");
-    std::thread::sleep(std::time::Duration::from_millis(2));
     let tag3 = create_tag(&conn, "Tag 3", "#0000ff").expect("tag 3");
 
     set_message_tags(&conn, "m1", &[tag1.id.clone(), tag2.id.clone(), tag3.id.clone()])
@@ -186,9 +310,7 @@ fn get_message_tags_returns_ordered_by_display_order() {
     seed_test_data(&conn);
 
     let tag1 = create_tag(&conn, "Tag 1", "#ff0000").expect("tag 1");
-    std::thread::sleep(std::time::Duration::from_millis(2));
     let tag2 = create_tag(&conn, "Tag 2", "#00ff00").expect("tag 2");
-    std::thread::sleep(std::time::Duration::from_millis(2));
     let tag3 = create_tag(&conn, "Tag 3", "#0000ff").expect("tag 3");
 
     set_message_tags(&conn, "m1", &[tag3.id.clone(), tag1.id.clone(), tag2.id.clone()])
@@ -211,8 +333,9 @@ fn list_scrapbook_messages_returns_tagged_messages() {
     set_message_tags(&conn, "m1", &[tag.id.clone()]).expect("tag m1");
     set_message_tags(&conn, "m3", &[tag.id.clone()]).expect("tag m3");
 
-    let scrapbook = list_scrapbook_messages(&conn, &tag.id, None, None, 10)
-        .expect("list scrapbook");
+    let scrapbook = list_scrapbook_messages(&conn, &tag.id, None, ScrapbookOrder::TaggedAt, None, None, 10)
+        .expect("list scrapbook")
+        .items;
 
     assert_eq!(scrapbook.len(), 2);
     assert_eq!(scrapbook[0].message.id, "m3"); // Newest first
@@ -227,8 +350,9 @@ fn list_scrapbook_messages_includes_thread_name() {
     let tag = create_tag(&conn, "Test", "#ff0000").expect("create tag");
     set_message_tags(&conn, "m1", &[tag.id.clone()]).expect("tag m1");
 
-    let scrapbook = list_scrapbook_messages(&conn, &tag.id, None, None, 10)
-        .expect("list scrapbook");
+    let scrapbook = list_scrapbook_messages(&conn, &tag.id, None, ScrapbookOrder::TaggedAt, None, None, 10)
+        .expect("list scrapbook")
+        .items;
 
     assert_eq!(scrapbook[0].thread_name, Some("Test Thread".to_string()));
 }
@@ -245,8 +369,9 @@ fn list_scrapbook_messages_detects_discontinuity() {
     std::thread::sleep(std::time::Duration::from_millis(2));
     set_message_tags(&conn, "m2", &[tag.id.clone()]).expect("tag m2");
 
-    let scrapbook = list_scrapbook_messages(&conn, &tag.id, None, None, 10)
-        .expect("list scrapbook");
+    let scrapbook = list_scrapbook_messages(&conn, &tag.id, None, ScrapbookOrder::TaggedAt, None, None, 10)
+        .expect("list scrapbook")
+        .items;
 
     // Results ordered by tagged_at DESC, so m2 comes first
     // Discontinuity is detected when processing each message against the previous one
@@ -269,8 +394,9 @@ fn list_scrapbook_messages_no_discontinuity_for_adjacent() {
     set_message_tags(&conn, "m2", &[tag.id.clone()]).expect("tag m2");
     set_message_tags(&conn, "m3", &[tag.id.clone()]).expect("tag m3");
 
-    let scrapbook = list_scrapbook_messages(&conn, &tag.id, None, None, 10)
-        .expect("list scrapbook");
+    let scrapbook = list_scrapbook_messages(&conn, &tag.id, None, ScrapbookOrder::TaggedAt, None, None, 10)
+        .expect("list scrapbook")
+        .items;
 
     assert_eq!(scrapbook.len(), 2);
     assert_eq!(scrapbook[0].message.id, "m3");
@@ -289,13 +415,13 @@ fn list_scrapbook_messages_paginates_with_tagged_at() {
     set_message_tags(&conn, "m3", &[tag.id.clone()]).expect("tag m3");
 
     // Get first page (limit 2)
-    let page1 = list_scrapbook_messages(&conn, &tag.id, None, None, 2)
+    let page1 = list_scrapbook_messages(&conn, &tag.id, None, ScrapbookOrder::TaggedAt, None, None, 2)
         .expect("page 1");
-    assert_eq!(page1.len(), 2);
-    assert_eq!(page1[0].message.id, "m3"); // Newest by tagged_at
-    assert_eq!(page1[1].message.id, "m2");
+    assert_eq!(page1.items.len(), 2);
+    assert_eq!(page1.items[0].message.id, "m3"); // Newest by tagged_at
+    assert_eq!(page1.items[1].message.id, "m2");
 
-    // Get next page using last message's tagged_at
+    // The cursor points at the last row's tagged_at
     let tagged_at = conn
         .query_row(
             "SELECT tagged_at FROM message_tags WHERE message_id = ?1 AND tag_id = ?2",
@@ -303,9 +429,580 @@ fn list_scrapbook_messages_paginates_with_tagged_at() {
             |row| row.get::<_, i64>(0),
         )
         .unwrap();
+    assert_eq!(page1.next_before_ts, Some(tagged_at));
+    assert_eq!(page1.next_before_id.as_deref(), Some("m2"));
+
+    let page2 = list_scrapbook_messages(
+        &conn,
+        &tag.id,
+        None,
+        ScrapbookOrder::TaggedAt,
+        page1.next_before_ts,
+        page1.next_before_id.as_deref(),
+        2,
+    )
+    .expect("page 2");
+    assert_eq!(page2.items.len(), 1);
+    assert_eq!(page2.items[0].message.id, "m1");
+    assert_eq!(page2.next_before_ts, None, "last page carries no cursor");
+    assert_eq!(page2.next_before_id, None);
+}
 
-    let page2 = list_scrapbook_messages(&conn, &tag.id, Some(tagged_at), Some("m2"), 2)
-        .expect("page 2");
+#[test]
+fn list_scrapbook_messages_orders_and_paginates_by_message_time() {
+    let conn = setup_db();
+    seed_test_data(&conn);
+
+    let tag = create_tag(&conn, "Test", "#ff0000").expect("create tag");
+    // Tag out of timeline order so tagged_at and sort_ts disagree
+    set_message_tags(&conn, "m1", std::slice::from_ref(&tag.id)).expect("tag m1");
+    set_message_tags(&conn, "m3", std::slice::from_ref(&tag.id)).expect("tag m3");
+    set_message_tags(&conn, "m2", std::slice::from_ref(&tag.id)).expect("tag m2");
+
+    let page1 = list_scrapbook_messages(&conn, &tag.id, None, ScrapbookOrder::MessageTime, None, None, 2)
+        .expect("page 1");
+    assert_eq!(page1.items.len(), 2);
+    assert_eq!(page1.items[0].message.id, "m3"); // Newest by sort_ts
+    assert_eq!(page1.items[1].message.id, "m2");
+    assert!(!page1.items[1].is_discontinuous, "m2 directly precedes m3");
+
+    // Cursor is the last message's sort_ts (its sent_at here)
+    assert_eq!(page1.next_before_ts, Some(5));
+    assert_eq!(page1.next_before_id.as_deref(), Some("m2"));
+    let page2 = list_scrapbook_messages(
+        &conn,
+        &tag.id,
+        None,
+        ScrapbookOrder::MessageTime,
+        page1.next_before_ts,
+        page1.next_before_id.as_deref(),
+        2,
+    )
+    .expect("page 2")
+    .items;
+    assert_eq!(page2.len(), 1);
+    assert_eq!(page2[0].message.id, "m1");
+
+    let all = list_scrapbook_messages(&conn, &tag.id, None, ScrapbookOrder::MessageTime, None, None, 10)
+        .expect("all")
+        .items;
+    let ids: Vec<&str> = all.iter().map(|m| m.message.id.as_str()).collect();
+    assert_eq!(ids, vec!["m3", "m2", "m1"]);
+    assert!(all[2].is_discontinuous, "m_between sits between m1 and m2");
+}
+
+#[test]
+fn list_scrapbook_messages_filters_by_thread() {
+    let conn = setup_db();
+    seed_test_data(&conn);
+    conn.execute(
+        "INSERT INTO threads (id, name, last_message_at) VALUES (?1, ?2, ?3);",
+        rusqlite::params!["t2", "Other Thread", 4_i64],
+    )
+    .unwrap();
+    conn.execute(
+        "INSERT INTO messages (id, thread_id, sender_id, sent_at, received_at, type, body, is_outgoing, is_view_once, dedupe_key) \
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, 0, 0, ?8);",
+        rusqlite::params!["o1", "t2", "r1", 4_i64, 4_i64, "text", "Other thread", "d_o1"],
+    )
+    .unwrap();
+
+    let tag = create_tag(&conn, "Funny", "#ff0000").expect("create tag");
+    add_tag_to_messages(&conn, &tag.id, &["m1".to_string(), "o1".to_string(), "m2".to_string()]).expect("tag");
+
+    let all = list_scrapbook_messages(&conn, &tag.id, None, ScrapbookOrder::MessageTime, None, None, 10)
+        .expect("all")
+        .items;
+    assert_eq!(all.len(), 3);
+
+    let t1 = list_scrapbook_messages(&conn, &tag.id, Some("t1"), ScrapbookOrder::MessageTime, None, None, 10)
+        .expect("t1 only")
+        .items;
+    let ids: Vec<&str> = t1.iter().map(|m| m.message.id.as_str()).collect();
+    assert_eq!(ids, vec!["m2", "m1"]);
+    assert!(t1[1].is_discontinuous, "m_between still separates m1 from m2");
+
+    let page2 = list_scrapbook_messages(&conn, &tag.id, Some("t1"), ScrapbookOrder::MessageTime, Some(5), Some("m2"), 10)
+        .expect("t1 page 2")
+        .items;
     assert_eq!(page2.len(), 1);
     assert_eq!(page2[0].message.id, "m1");
 }
+
+#[test]
+fn interleaved_tag_adds_both_persist() {
+    let conn = setup_db();
+    seed_test_data(&conn);
+    let tag1 = create_tag(&conn, "Love", "#ff0000").expect("create tag1");
+    let tag2 = create_tag(&conn, "Funny", "#00ff00").expect("create tag2");
+
+    // Two surfaces each add their own tag to the same message.
+    add_message_tag(&conn, "m1", &tag1.id).expect("add tag1");
+    add_message_tag(&conn, "m1", &tag2.id).expect("add tag2");
+
+    let tags = get_message_tags(&conn, "m1").expect("get tags");
+    assert_eq!(tags.len(), 2);
+
+    remove_message_tag(&conn, "m1", &tag1.id).expect("remove tag1");
+    let tags = get_message_tags(&conn, "m1").expect("get tags");
+    assert_eq!(tags.len(), 1);
+    assert_eq!(tags[0].id, tag2.id);
+}
+
+#[test]
+fn re_adding_tag_keeps_tagged_at() {
+    let conn = setup_db();
+    seed_test_data(&conn);
+    let tag = create_tag(&conn, "Love", "#ff0000").expect("create tag");
+
+    add_message_tag(&conn, "m1", &tag.id).expect("first add");
+    let tagged_at = |conn: &Connection| -> i64 {
+        conn.query_row(
+            "SELECT tagged_at FROM message_tags WHERE message_id = 'm1' AND tag_id = ?1;",
+            rusqlite::params![tag.id],
+            |row| row.get(0),
+        )
+        .unwrap()
+    };
+    let first = tagged_at(&conn);
+    std::thread::sleep(std::time::Duration::from_millis(2));
+    add_message_tag(&conn, "m1", &tag.id).expect("second add");
+    assert_eq!(tagged_at(&conn), first);
+}
+
+#[test]
+fn bulk_tagging_reports_changed_messages() {
+    let conn = setup_db();
+    seed_test_data(&conn);
+    let tag = create_tag(&conn, "Trip", "#0000ff").expect("create tag");
+    add_message_tag(&conn, "m2", &tag.id).expect("pre-tag m2");
+
+    let selection: Vec<String> = ["m1", "m2", "m3", "m_between"].iter().map(|id| id.to_string()).collect();
+    let added = add_tag_to_messages(&conn, &tag.id, &selection).expect("bulk add");
+    assert_eq!(added, 3);
+    for id in &selection {
+        assert_eq!(get_message_tags(&conn, id).expect("tags").len(), 1);
+    }
+
+    let removed = remove_tag_from_messages(&conn, &tag.id, &selection[..2]).expect("bulk remove");
+    assert_eq!(removed, 2);
+    let again = remove_tag_from_messages(&conn, &tag.id, &selection[..2]).expect("bulk remove again");
+    assert_eq!(again, 0);
+    assert!(get_message_tags(&conn, "m1").expect("tags").is_empty());
+    assert_eq!(get_message_tags(&conn, "m3").expect("tags").len(), 1);
+}
+
+#[test]
+fn bulk_tagging_handles_large_selections() {
+    let conn = setup_db();
+    conn.execute(
+        "INSERT INTO threads (id, name, last_message_at) VALUES ('t1', 'Big', 0);",
+        [],
+    )
+    .unwrap();
+    let mut ids = Vec::new();
+    for idx in 0..1000 {
+        let id = format!("bulk{}", idx);
+        conn.execute(
+            "INSERT INTO messages (id, thread_id, sender_id, sent_at, received_at, type, body, is_outgoing, is_view_once, dedupe_key) \
+             VALUES (?1, 't1', 'r1', ?2, ?2, 'text', 'x', 0, 0, ?1);",
+            rusqlite::params![id, idx],
+        )
+        .unwrap();
+        ids.push(id);
+    }
+    let tag = create_tag(&conn, "All", "#000000").expect("create tag");
+
+    assert_eq!(add_tag_to_messages(&conn, &tag.id, &ids).expect("bulk add"), 1000);
+    assert_eq!(remove_tag_from_messages(&conn, &tag.id, &ids).expect("bulk remove"), 1000);
+}
+
+#[test]
+fn merge_tags_collapses_rows_and_keeps_earliest_tagged_at() {
+    let conn = setup_db();
+    seed_test_data(&conn);
+    let trip = create_tag(&conn, "trip", "#ff0000").expect("create trip");
+    let travel = create_tag(&conn, "travel", "#00ff00").expect("create travel");
+
+    // m1 carries both tags, tagged "trip" first; m2 only "trip"; m3 only "travel".
+    conn.execute(
+        "INSERT INTO message_tags (message_id, tag_id, tagged_at) VALUES ('m1', ?1, 100), ('m2', ?1, 200);",
+        rusqlite::params![trip.id],
+    )
+    .unwrap();
+    conn.execute(
+        "INSERT INTO message_tags (message_id, tag_id, tagged_at) VALUES ('m1', ?1, 300), ('m3', ?1, 400);",
+        rusqlite::params![travel.id],
+    )
+    .unwrap();
+
+    merge_tags(&conn, &trip.id, &travel.id).expect("merge");
+
+    let rows: Vec<(String, String, i64)> = {
+        let mut stmt = conn
+            .prepare("SELECT message_id, tag_id, tagged_at FROM message_tags ORDER BY message_id;")
+            .unwrap();
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+    };
+    assert_eq!(
+        rows,
+        vec![
+            ("m1".to_string(), travel.id.clone(), 100),
+            ("m2".to_string(), travel.id.clone(), 200),
+            ("m3".to_string(), travel.id.clone(), 400),
+        ]
+    );
+    let tags = list_tags(&conn).expect("list tags");
+    assert_eq!(tags.len(), 1);
+    assert_eq!(tags[0].id, travel.id);
+
+    assert!(merge_tags(&conn, &travel.id, &travel.id).is_err());
+}
+
+#[test]
+fn set_attachment_tags_replaces_and_reads_back_in_bulk() {
+    let conn = setup_db();
+    seed_test_data(&conn);
+    seed_attachments(&conn);
+    let tag1 = create_tag(&conn, "Tag 1", "#ff0000").expect("tag 1");
+    let tag2 = create_tag(&conn, "Tag 2", "#00ff00").expect("tag 2");
+
+    set_attachment_tags(&conn, "a1", &[tag1.id.clone(), tag2.id.clone()]).expect("set both");
+    set_attachment_tags(&conn, "a1", std::slice::from_ref(&tag2.id)).expect("keep tag 2");
+
+    let bulk = get_attachment_tags_bulk(&conn, &["a1".to_string(), "a2".to_string()]).expect("bulk");
+    assert_eq!(bulk.len(), 2);
+    assert_eq!(bulk[0].attachment_id, "a1");
+    let ids: Vec<&str> = bulk[0].tags.iter().map(|t| t.id.as_str()).collect();
+    assert_eq!(ids, vec![tag2.id.as_str()]);
+    assert!(bulk[1].tags.is_empty());
+
+    set_attachment_tags(&conn, "a1", &[]).expect("clear");
+    assert!(get_attachment_tags_bulk(&conn, &["a1".to_string()]).expect("bulk")[0].tags.is_empty());
+}
+
+#[test]
+fn list_tagged_media_orders_by_tagged_at() {
+    let conn = setup_db();
+    seed_test_data(&conn);
+    seed_attachments(&conn);
+    let tag = create_tag(&conn, "Photos", "#ff0000").expect("create tag");
+
+    set_attachment_tags(&conn, "a2", std::slice::from_ref(&tag.id)).expect("tag a2");
+    std::thread::sleep(std::time::Duration::from_millis(2));
+    set_attachment_tags(&conn, "a1", std::slice::from_ref(&tag.id)).expect("tag a1");
+
+    let media = list_tagged_media(&conn, &tag.id, 10, 0).expect("tagged media");
+    let ids: Vec<&str> = media.iter().map(|m| m.id.as_str()).collect();
+    assert_eq!(ids, vec!["a1", "a2"]);
+    assert_eq!(media[0].thread_id, "t1");
+    assert_eq!(media[0].message_id, "m1");
+
+    let page2 = list_tagged_media(&conn, &tag.id, 1, 1).expect("second page");
+    assert_eq!(page2.len(), 1);
+    assert_eq!(page2[0].id, "a2");
+}
+
+#[test]
+fn delete_tag_cascades_to_message_and_attachment_tags() {
+    let conn = setup_db();
+    conn.execute_batch("PRAGMA foreign_keys = ON;").unwrap();
+    seed_test_data(&conn);
+    seed_attachments(&conn);
+    let tag = create_tag(&conn, "Doomed", "#ff0000").expect("create tag");
+    add_message_tag(&conn, "m1", &tag.id).expect("tag message");
+    set_attachment_tags(&conn, "a1", std::slice::from_ref(&tag.id)).expect("tag attachment");
+
+    delete_tag(&conn, &tag.id).expect("delete");
+
+    let count = |table: &str| -> i64 {
+        conn.query_row(&format!("SELECT COUNT(1) FROM {};", table), [], |row| row.get(0))
+            .unwrap()
+    };
+    assert_eq!(count("message_tags"), 0);
+    assert_eq!(count("attachment_tags"), 0);
+}
+
+#[test]
+fn merge_tags_moves_attachment_tags() {
+    let conn = setup_db();
+    seed_test_data(&conn);
+    seed_attachments(&conn);
+    let trip = create_tag(&conn, "trip", "#ff0000").expect("create trip");
+    let travel = create_tag(&conn, "travel", "#00ff00").expect("create travel");
+    set_attachment_tags(&conn, "a1", &[trip.id.clone(), travel.id.clone()]).expect("tag a1");
+    set_attachment_tags(&conn, "a2", std::slice::from_ref(&trip.id)).expect("tag a2");
+
+    merge_tags(&conn, &trip.id, &travel.id).expect("merge");
+
+    let media = list_tagged_media(&conn, &travel.id, 10, 0).expect("tagged media");
+    assert_eq!(media.len(), 2);
+    assert!(list_tagged_media(&conn, &trip.id, 10, 0).expect("source").is_empty());
+}
+
+#[test]
+fn tag_stats_aggregates_tagged_messages() {
+    let conn = setup_db();
+    seed_test_data(&conn);
+    seed_attachments(&conn);
+    conn.execute(
+        "INSERT INTO threads (id, name, last_message_at) VALUES ('t2', 'Other Thread', 4);",
+        [],
+    )
+    .unwrap();
+    conn.execute(
+        "INSERT INTO messages (id, thread_id, sender_id, sent_at, received_at, type, body, is_outgoing, is_view_once, dedupe_key) \
+         VALUES ('o1', 't2', 'r1', 4, 4, 'text', 'Other thread', 0, 0, 'd_o1');",
+        [],
+    )
+    .unwrap();
+    let tag = create_tag(&conn, "Stats", "#ff0000").expect("create tag");
+
+    let empty = tag_stats(&conn, &tag.id).expect("empty stats");
+    assert_eq!(empty.message_count, 0);
+    assert_eq!(empty.first_tagged_at, None);
+    assert_eq!(empty.last_message_ts, None);
+
+    conn.execute(
+        "INSERT INTO message_tags (message_id, tag_id, tagged_at) VALUES ('m1', ?1, 300), ('m2', ?1, 100), ('o1', ?1, 200);",
+        rusqlite::params![tag.id],
+    )
+    .unwrap();
+    let stats = tag_stats(&conn, &tag.id).expect("stats");
+    assert_eq!(stats.message_count, 3);
+    assert_eq!(stats.thread_count, 2);
+    assert_eq!((stats.first_tagged_at, stats.last_tagged_at), (Some(100), Some(300)));
+    assert_eq!((stats.first_message_ts, stats.last_message_ts), (Some(1), Some(5)));
+    assert_eq!(stats.attachment_count, 1, "only m1 has an attachment");
+
+    assert!(tag_stats(&conn, "tag:missing").is_err());
+}
+
+#[test]
+fn scrapbook_context_flags_tagged_neighbours_within_thread() {
+    let conn = setup_db();
+    seed_test_data(&conn);
+    conn.execute(
+        "INSERT INTO threads (id, name, last_message_at) VALUES (?1, ?2, ?3);",
+        rusqlite::params!["t2", "Other Thread", 4_i64],
+    )
+    .unwrap();
+    conn.execute(
+        "INSERT INTO messages (id, thread_id, sender_id, sent_at, received_at, type, body, is_outgoing, is_view_once, dedupe_key) \
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, 0, 0, ?8);",
+        rusqlite::params!["o1", "t2", "r1", 4_i64, 4_i64, "text", "Other thread", "d_o1"],
+    )
+    .unwrap();
+
+    let tag = create_tag(&conn, "Funny", "#ff0000").expect("create tag");
+    add_tag_to_messages(&conn, &tag.id, &["m2".to_string(), "m3".to_string(), "o1".to_string()]).expect("tag");
+
+    // o1 sits between m_between and m2 in time but belongs to another thread.
+    let context = scrapbook_context(&conn, &tag.id, "m2", 5, 5).expect("context");
+    let ids: Vec<&str> = context.iter().map(|m| m.message.id.as_str()).collect();
+    assert_eq!(ids, vec!["m1", "m_between", "m2", "m3"]);
+    let tagged: Vec<bool> = context.iter().map(|m| m.is_tagged).collect();
+    assert_eq!(tagged, vec![false, false, true, true]);
+
+    let narrow = scrapbook_context(&conn, &tag.id, "m2", 1, 0).expect("narrow context");
+    let ids: Vec<&str> = narrow.iter().map(|m| m.message.id.as_str()).collect();
+    assert_eq!(ids, vec!["m_between", "m2"]);
+
+    let other = scrapbook_context(&conn, &tag.id, "o1", 2, 2).expect("other thread");
+    assert_eq!(other.len(), 1);
+    assert!(other[0].is_tagged);
+}
+
+#[test]
+fn list_scrapbook_messages_pages_through_large_threads() {
+    let conn = setup_db();
+    conn.execute(
+        "INSERT INTO threads (id, name, last_message_at) VALUES ('big', 'Big Thread', 0);",
+        [],
+    )
+    .unwrap();
+    let mut ids = Vec::new();
+    for idx in 0..4000_i64 {
+        let id = format!("m{:05}", idx);
+        conn.execute(
+            "INSERT INTO messages (id, thread_id, sender_id, sent_at, received_at, type, body, is_outgoing, is_view_once, dedupe_key) \
+             VALUES (?1, 'big', 'r1', ?2, ?2, 'text', 'x', 0, 0, ?1);",
+            rusqlite::params![id, idx + 1],
+        )
+        .unwrap();
+        ids.push(id);
+    }
+    // Every other message in the first half (gaps), then a contiguous run in the second half.
+    let mut tagged: Vec<String> = ids[..2000].iter().step_by(2).cloned().collect();
+    tagged.extend(ids[2000..3000].iter().cloned());
+    let tag = create_tag(&conn, "Bench", "#123456").expect("create tag");
+    add_tag_to_messages(&conn, &tag.id, &tagged).expect("bulk tag");
+
+    // Bulk tagging shares one tagged_at, so pages continue on message id.
+    let mut all = Vec::new();
+    let (mut before_ts, mut before_id): (Option<i64>, Option<String>) = (None, None);
+    loop {
+        let page = list_scrapbook_messages(&conn, &tag.id, None, ScrapbookOrder::TaggedAt, before_ts, before_id.as_deref(), 50)
+            .expect("page");
+        all.extend(page.items);
+        if page.next_before_ts.is_none() {
+            break;
+        }
+        before_ts = page.next_before_ts;
+        before_id = page.next_before_id;
+    }
+    assert_eq!(all.len(), 2000);
+    // Results run newest id first: the contiguous run is adjacent, the strided half is not.
+    // The first row of each page has no predecessor and is never discontinuous.
+    for (idx, msg) in all.iter().enumerate() {
+        let expected = idx % 50 != 0 && idx >= 1000;
+        assert_eq!(msg.is_discontinuous, expected, "row {} ({})", idx, msg.message.id);
+    }
+
+    // Each row's successor is one seek into the thread's timeline, not a scan of it.
+    let plan = next_in_thread_query_plan_for_tests(&conn).expect("plan");
+    assert!(
+        plan.iter().any(|line| line.starts_with("SEARCH n USING") && line.contains("idx_messages_thread_sort")),
+        "{:?}",
+        plan
+    );
+    assert!(!plan.iter().any(|line| line.starts_with("SCAN n") || line.contains("TEMP B-TREE")), "{:?}", plan);
+}
+
+#[test]
+fn tags_json_round_trips_through_a_reimport() {
+    let source = setup_db();
+    seed_test_data(&source);
+    seed_attachments(&source);
+    let tag = create_tag(&source, "Trip", "#5f88b3").expect("create tag");
+    add_message_tag(&source, "m1", &tag.id).expect("tag m1");
+    add_message_tag(&source, "m3", &tag.id).expect("tag m3");
+    set_attachment_tags(&source, "a1", std::slice::from_ref(&tag.id)).expect("tag a1");
+    set_attachment_tags(&source, "a2", std::slice::from_ref(&tag.id)).expect("tag a2");
+    let json = export_tags_json(&source).expect("export");
+
+    // A fresh import assigns new message ids and no longer has m3.
+    let target = setup_db();
+    seed_test_data(&target);
+    target.execute("UPDATE messages SET id = 'new_' || id;", []).unwrap();
+    target.execute("DELETE FROM messages WHERE id = 'new_m3';", []).unwrap();
+    target
+        .execute(
+            "INSERT INTO attachments (id, message_id, sha256, mime, kind) VALUES ('b1', 'new_m1', 'a1', 'image/jpeg', 'image');",
+            [],
+        )
+        .unwrap();
+
+    let summary = import_tags_json(&target, &json).expect("import");
+    assert_eq!(summary.tags_created, 1);
+    assert_eq!(summary.message_tags_applied, 1);
+    assert_eq!(summary.message_tags_skipped, 1);
+    assert_eq!(summary.attachment_tags_applied, 1);
+    assert_eq!(summary.attachment_tags_skipped, 1);
+
+    let tags = list_tags(&target).expect("list");
+    assert_eq!(tags.len(), 1);
+    assert_eq!((tags[0].id.as_str(), tags[0].name.as_str()), (tag.id.as_str(), "Trip"));
+    assert_eq!(get_message_tags(&target, "new_m1").expect("tags").len(), 1);
+    let attachment_tags = get_attachment_tags_bulk(&target, &["b1".to_string()]).expect("bulk");
+    assert_eq!(attachment_tags[0].tags.len(), 1);
+
+    let again = import_tags_json(&target, &json).expect("reimport");
+    assert_eq!((again.tags_matched, again.tags_created), (1, 0));
+    assert_eq!(again.message_tags_applied, 0);
+}
+
+#[test]
+fn import_tags_json_maps_onto_existing_tag_names() {
+    let source = setup_db();
+    seed_test_data(&source);
+    let tag = create_tag(&source, "Trip", "#5f88b3").expect("create tag");
+    add_message_tag(&source, "m2", &tag.id).expect("tag m2");
+    let json = export_tags_json(&source).expect("export");
+
+    let target = setup_db();
+    seed_test_data(&target);
+    let existing = create_tag(&target, "Trip", "#c3684a").expect("existing tag");
+    let summary = import_tags_json(&target, &json).expect("import");
+
+    assert_eq!(summary.tags_matched, 1);
+    assert_eq!(list_tags(&target).expect("list").len(), 1);
+    assert_eq!(get_message_tags(&target, "m2").expect("tags")[0].id, existing.id);
+    let future = r#"{"version": 99, "tags": [], "message_tags": [], "attachment_tags": []}"#;
+    assert!(import_tags_json(&target, future).is_err());
+}
+
+#[test]
+fn get_or_create_tag_matches_names_loosely() {
+    let conn = setup_db();
+    let created = get_or_create_tag(&conn, "Vacation", "#5f8f6b").expect("create");
+    let found = get_or_create_tag(&conn, "vacation ", "#c3684a").expect("lookup");
+
+    assert_eq!(found.id, created.id);
+    assert_eq!(found.color, "#5f8f6b");
+    assert_eq!(list_tags(&conn).expect("list").len(), 1);
+
+    let trimmed = get_or_create_tag(&conn, "  Road trip ", "#c3684a").expect("create trimmed");
+    assert_eq!(trimmed.name, "Road trip");
+    assert!(get_or_create_tag(&conn, "   ", "#c3684a").is_err());
+}
+
+#[test]
+fn tagged_at_is_reported_with_message_tags() {
+    let conn = setup_db();
+    seed_test_data(&conn);
+    let trip = create_tag(&conn, "Trip", "#5f88b3").expect("trip");
+    let food = create_tag(&conn, "Food", "#c3684a").expect("food");
+    add_message_tag(&conn, "m1", &trip.id).expect("tag m1");
+    add_message_tag(&conn, "m1", &food.id).expect("tag m1 food");
+    conn.execute("UPDATE message_tags SET tagged_at = 42 WHERE tag_id = ?1;", [&trip.id]).unwrap();
+
+    let bulk = get_message_tags_bulk(&conn, &["m1".to_string(), "m2".to_string()]).expect("bulk");
+    assert_eq!(bulk[0].tags.len(), 2);
+    assert_eq!(bulk[0].tags[0].tag.id, trip.id);
+    assert_eq!(bulk[0].tags[0].tagged_at, 42);
+    assert!(bulk[1].tags.is_empty());
+
+    let scrapbook = list_scrapbook_messages(&conn, &trip.id, None, ScrapbookOrder::TaggedAt, None, None, 10)
+        .expect("list scrapbook")
+        .items;
+    let tags: Vec<(&str, i64)> = scrapbook[0].tags.iter().map(|t| (t.tag.name.as_str(), t.tagged_at)).collect();
+    assert_eq!(tags[0], ("Trip", 42));
+    assert_eq!(tags[1].0, "Food");
+}
+
+#[test]
+fn delete_tag_checked_refuses_heavily_used_tags() {
+    let conn = setup_db();
+    seed_test_data(&conn);
+    let tag = create_tag(&conn, "Busy", "#ff0000").expect("create");
+    let ids: Vec<String> = ["m1", "m2", "m3"].iter().map(|id| id.to_string()).collect();
+    add_tag_to_messages(&conn, &tag.id, &ids).expect("bulk tag");
+
+    match delete_tag_checked(&conn, &tag.id, 2, false) {
+        Err(CoreError::InvalidArgument(msg)) => assert!(msg.contains("3 items"), "{}", msg),
+        other => panic!("expected refusal, got {:?}", other),
+    }
+    assert_eq!(list_tags(&conn).expect("list").len(), 1);
+
+    assert_eq!(delete_tag_checked(&conn, &tag.id, 2, true).expect("forced"), 3);
+    assert!(list_tags(&conn).expect("list").is_empty());
+
+    let quiet = create_tag(&conn, "Quiet", "#00ff00").expect("create");
+    assert_eq!(delete_tag_checked(&conn, &quiet.id, 2, false).expect("under threshold"), 0);
+
+    // Attachments count towards the threshold too.
+    seed_attachments(&conn);
+    let media = create_tag(&conn, "Media", "#0000ff").expect("create");
+    set_attachment_tags(&conn, "a1", std::slice::from_ref(&media.id)).expect("tag a1");
+    set_attachment_tags(&conn, "a2", std::slice::from_ref(&media.id)).expect("tag a2");
+    set_message_tags(&conn, "m1", std::slice::from_ref(&media.id)).expect("tag m1");
+    match delete_tag_checked(&conn, &media.id, 2, false) {
+        Err(CoreError::InvalidArgument(msg)) => assert!(msg.contains("3 items"), "{}", msg),
+        other => panic!("expected refusal, got {:?}", other),
+    }
+    assert_eq!(delete_tag(&conn, &media.id).expect("delete"), 3);
+}